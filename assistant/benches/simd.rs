@@ -0,0 +1,130 @@
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+#[path = "../src/simd.rs"]
+mod simd;
+
+/// Embeddings compared with the query, about as many examples as a large config has.
+const EXAMPLES: usize = 1000;
+/// The size of the embeddings of the default embedding model.
+const DIMENSIONS: usize = 384;
+/// One second of audio.
+const SAMPLES: usize = 48000;
+const ITERATIONS: usize = 100;
+
+/// Cosine similarity between embeddings that aren't normalized, as intent recognition used to
+/// compare them.
+fn cosine_similarity_scalar(a: &[f32], b: &[f32]) -> f32 {
+    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
+    let magnitude_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let magnitude_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot_product / (magnitude_a * magnitude_b)
+}
+
+/// The conversion of cpal used before [simd::i16_to_f32].
+fn i16_to_f32_scalar(input: &[i16], output: &mut Vec<f32>) {
+    use cpal::Sample;
+
+    output.extend(input.iter().map(|&s| f32::from_sample(s)));
+}
+
+/// The average time of one iteration of `f`.
+fn time(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        f();
+    }
+    start.elapsed() / ITERATIONS as u32
+}
+
+/// Compare the vectorized implementations with the scalar ones they replaced, with `cargo bench
+/// --bench simd`.
+fn main() {
+    // A simple linear congruential generator, so runs are comparable
+    let mut state = 0x2545_f491_u32;
+    let mut next = move || {
+        state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        state
+    };
+    let target: Vec<f32> = (0..DIMENSIONS)
+        .map(|_| next() as f32 / u32::MAX as f32 - 0.5)
+        .collect();
+    let embeddings: Vec<Vec<f32>> = (0..EXAMPLES)
+        .map(|_| {
+            (0..DIMENSIONS)
+                .map(|_| next() as f32 / u32::MAX as f32 - 0.5)
+                .collect()
+        })
+        .collect();
+    let samples: Vec<i16> = (0..SAMPLES).map(|_| (next() >> 16) as i16).collect();
+
+    let normalized: Vec<Vec<f32>> = embeddings
+        .iter()
+        .map(|embedding| {
+            let mut embedding = embedding.clone();
+            simd::normalize(&mut embedding);
+            embedding
+        })
+        .collect();
+    let mut normalized_target = target.clone();
+    simd::normalize(&mut normalized_target);
+    let normalized_max_error = embeddings
+        .iter()
+        .zip(&normalized)
+        .map(|(embedding, normalized)| {
+            (cosine_similarity_scalar(embedding, &target)
+                - simd::dot(normalized, &normalized_target))
+            .abs()
+        })
+        .fold(0., f32::max);
+    let time_conversion = |convert: fn(&[i16], &mut Vec<f32>)| {
+        let mut output = Vec::with_capacity(SAMPLES);
+        time(|| {
+            output.clear();
+            convert(black_box(&samples), &mut output);
+            black_box(&output);
+        })
+    };
+
+    let cosine_scalar = time(|| {
+        for embedding in &embeddings {
+            black_box(cosine_similarity_scalar(
+                black_box(embedding),
+                black_box(&target),
+            ));
+        }
+    });
+    // Including normalizing the query, as intent recognition does
+    let cosine_normalized = time(|| {
+        let mut query = black_box(&target).clone();
+        simd::normalize(&mut query);
+        for embedding in &normalized {
+            black_box(simd::dot(black_box(embedding), &query));
+        }
+    });
+    let conversion_scalar = time_conversion(i16_to_f32_scalar);
+    let conversion_simd = time_conversion(simd::i16_to_f32);
+    let speedup = |before: Duration, after: Duration| before.as_secs_f64() / after.as_secs_f64();
+
+    println!(
+        "{:<18}  {:>12}  {:>12}  {:>8}",
+        "", "Scalar", "SIMD", "Speedup"
+    );
+    println!(
+        "{:<18}  {:>12.1?}  {:>12.1?}  {:>7.2}x  (normalized, max error {:.1e})",
+        format!("Cosine ({})", EXAMPLES),
+        cosine_scalar,
+        cosine_normalized,
+        speedup(cosine_scalar, cosine_normalized),
+        normalized_max_error
+    );
+    println!(
+        "{:<18}  {:>12.1?}  {:>12.1?}  {:>7.2}x",
+        "i16 to f32 (1s)",
+        conversion_scalar,
+        conversion_simd,
+        speedup(conversion_scalar, conversion_simd)
+    );
+}
@@ -0,0 +1,407 @@
+use chrono::{DateTime, Local, NaiveDate};
+use serde::Deserialize;
+use std::time::Duration;
+
+use crate::{
+    http::HttpClient,
+    http_cache::HttpCache,
+    permissions::Capability,
+    skills::{IntentSpec, Skill, SkillContext},
+    weather::{capitalize, WeatherError, WeatherLocation},
+    AssistantQuery,
+};
+
+/// A source of air quality data. [OpenMeteoAirQuality] is used by default, other services can
+/// implement this trait and be given to [AirQualitySkill::with_provider].
+pub trait AirQualityProvider {
+    fn current(&self, location: &WeatherLocation) -> Result<AirQuality, WeatherError>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AirQuality {
+    /// The index on the scale of the [AirQualityIndex].
+    pub index: f64,
+    /// Particulate matter under 2.5 micrometers, in μg/m³.
+    pub pm2_5: Option<f64>,
+    /// Particulate matter under 10 micrometers, in μg/m³.
+    pub pm10: Option<f64>,
+    /// The pollen in the air, in grains/m³, of the kinds with data for the location.
+    pub pollen: Vec<(Pollen, f64)>,
+}
+
+/// The scale of [AirQuality::index].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AirQualityIndex {
+    /// From 0 to over 100, by the European Environment Agency.
+    #[default]
+    European,
+    /// From 0 to 500, by the United States Environmental Protection Agency.
+    UnitedStates,
+}
+
+impl AirQualityIndex {
+    /// How the air is at the index, in the categories of the scale.
+    pub fn category(&self, index: f64) -> &'static str {
+        let limits: &[(f64, &str)] = match self {
+            AirQualityIndex::European => &[
+                (20., "good"),
+                (40., "fair"),
+                (60., "moderate"),
+                (80., "poor"),
+                (100., "very poor"),
+                (f64::INFINITY, "extremely poor"),
+            ],
+            AirQualityIndex::UnitedStates => &[
+                (50., "good"),
+                (100., "moderate"),
+                (150., "unhealthy for sensitive groups"),
+                (200., "unhealthy"),
+                (300., "very unhealthy"),
+                (f64::INFINITY, "hazardous"),
+            ],
+        };
+        limits
+            .iter()
+            .find(|(limit, _)| index <= *limit)
+            .map_or("unknown", |(_, category)| category)
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            AirQualityIndex::European => "European",
+            AirQualityIndex::UnitedStates => "US",
+        }
+    }
+}
+
+/// The kinds of pollen of the forecasts of the Copernicus Atmosphere Monitoring Service, only
+/// available in Europe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pollen {
+    Alder,
+    Birch,
+    Grass,
+    Mugwort,
+    Olive,
+    Ragweed,
+}
+
+impl Pollen {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pollen::Alder => "alder",
+            Pollen::Birch => "birch",
+            Pollen::Grass => "grass",
+            Pollen::Mugwort => "mugwort",
+            Pollen::Olive => "olive",
+            Pollen::Ragweed => "ragweed",
+        }
+    }
+}
+
+/// How much pollen there is, with the limits of most pollen services: up to 10 grains/m³ is low
+/// and from 50 it is high.
+fn pollen_level(count: f64) -> &'static str {
+    if count < 10. {
+        "low"
+    } else if count < 50. {
+        "moderate"
+    } else {
+        "high"
+    }
+}
+
+/// The free air quality API of Open-Meteo, which needs no API key.
+pub struct OpenMeteoAirQuality {
+    index: AirQualityIndex,
+    client: HttpClient,
+    cache: Option<(HttpCache, Duration)>,
+}
+
+impl OpenMeteoAirQuality {
+    pub fn new(index: AirQualityIndex) -> Self {
+        Self {
+            index,
+            client: HttpClient::default(),
+            cache: None,
+        }
+    }
+
+    /// Make the requests with a shared client, see [crate::AssistantConfig::http_client].
+    pub fn set_client(&mut self, client: HttpClient) {
+        self.client = client;
+    }
+
+    /// Keep the responses in the cache for `ttl`. Every request is made by default.
+    pub fn set_cache(&mut self, cache: HttpCache, ttl: Duration) {
+        self.cache = Some((cache, ttl));
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoAirQualityResponse {
+    current: OpenMeteoAirQualityCurrent,
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoAirQualityCurrent {
+    european_aqi: Option<f64>,
+    us_aqi: Option<f64>,
+    pm2_5: Option<f64>,
+    pm10: Option<f64>,
+    alder_pollen: Option<f64>,
+    birch_pollen: Option<f64>,
+    grass_pollen: Option<f64>,
+    mugwort_pollen: Option<f64>,
+    olive_pollen: Option<f64>,
+    ragweed_pollen: Option<f64>,
+}
+
+impl AirQualityProvider for OpenMeteoAirQuality {
+    fn current(&self, location: &WeatherLocation) -> Result<AirQuality, WeatherError> {
+        let request = self
+            .client
+            .get("https://air-quality-api.open-meteo.com/v1/air-quality")
+            .query("latitude", &location.latitude.to_string())
+            .query("longitude", &location.longitude.to_string())
+            .query(
+                "current",
+                "european_aqi,us_aqi,pm2_5,pm10,alder_pollen,birch_pollen,grass_pollen,\
+                 mugwort_pollen,olive_pollen,ragweed_pollen",
+            )
+            .query("timezone", "auto");
+        let fetch =
+            || -> Result<String, WeatherError> { Ok(request.clone().call()?.into_string()?) };
+        let body = match &self.cache {
+            Some((cache, ttl)) => cache.get_or_fetch(&request.url()?, *ttl, fetch)?,
+            None => fetch()?,
+        };
+        let current: OpenMeteoAirQualityResponse =
+            serde_json::from_str(&body).map_err(std::io::Error::from)?;
+        let current = current.current;
+        let index = match self.index {
+            AirQualityIndex::European => current.european_aqi,
+            AirQualityIndex::UnitedStates => current.us_aqi,
+        };
+        let pollen = [
+            (Pollen::Alder, current.alder_pollen),
+            (Pollen::Birch, current.birch_pollen),
+            (Pollen::Grass, current.grass_pollen),
+            (Pollen::Mugwort, current.mugwort_pollen),
+            (Pollen::Olive, current.olive_pollen),
+            (Pollen::Ragweed, current.ragweed_pollen),
+        ];
+        Ok(AirQuality {
+            index: index.ok_or(WeatherError::MissingData)?,
+            pm2_5: current.pm2_5,
+            pm10: current.pm10,
+            pollen: pollen
+                .into_iter()
+                .filter_map(|(kind, count)| Some((kind, count?)))
+                .collect(),
+        })
+    }
+}
+
+fn describe_air(index: AirQualityIndex, air: &AirQuality) -> String {
+    format!(
+        "the air quality is {}, with a {} index of {:.0}",
+        index.category(air.index),
+        index.name(),
+        air.index
+    )
+}
+
+/// The kinds of pollen with at least `at_least` grains/m³, most first, e.g. "birch pollen is
+/// high and grass pollen is moderate". `None` if there are none.
+fn describe_pollen(air: &AirQuality, at_least: f64) -> Option<String> {
+    let mut pollen: Vec<_> = air
+        .pollen
+        .iter()
+        .filter(|(_, count)| *count >= at_least)
+        .collect();
+    pollen.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    let parts: Vec<String> = pollen
+        .iter()
+        .map(|(kind, count)| format!("{} pollen is {}", kind.name(), pollen_level(*count)))
+        .collect();
+    match parts.as_slice() {
+        [] => None,
+        [only] => Some(only.clone()),
+        [rest @ .., last] => Some(format!("{} and {}", rest.join(", "), last)),
+    }
+}
+
+const AIR_QUALITY_INTENT: &str = "air quality";
+const POLLEN_INTENT: &str = "pollen";
+
+/// A [Skill] answering questions about the air quality and the pollen at the location of the
+/// [crate::weather::WeatherSkill], or the one of the speaker. See [AirQualityAlert] to be warned
+/// when the air gets bad. Enabled with the `weather` feature.
+pub struct AirQualitySkill {
+    provider: Box<dyn AirQualityProvider>,
+    location: WeatherLocation,
+    index: AirQualityIndex,
+}
+
+impl AirQualitySkill {
+    /// Get the air quality from [OpenMeteoAirQuality].
+    pub fn new(location: WeatherLocation, index: AirQualityIndex) -> Self {
+        Self::with_provider(OpenMeteoAirQuality::new(index), location, index)
+    }
+
+    /// Get the air quality from another service. The provider has to return the index on the
+    /// scale of `index`.
+    pub fn with_provider(
+        provider: impl AirQualityProvider + 'static,
+        location: WeatherLocation,
+        index: AirQualityIndex,
+    ) -> Self {
+        Self {
+            provider: Box::new(provider),
+            location,
+            index,
+        }
+    }
+}
+
+impl Skill for AirQualitySkill {
+    fn name(&self) -> &str {
+        "air quality"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak, Capability::Network]
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        vec![
+            IntentSpec::new(
+                AIR_QUALITY_INTENT,
+                examples(&[
+                    "what's the air quality",
+                    "what's the air quality like",
+                    "how is the air outside",
+                    "is the air clean",
+                ]),
+            ),
+            IntentSpec::new(
+                POLLEN_INTENT,
+                examples(&[
+                    "what's the pollen count",
+                    "is there a lot of pollen",
+                    "how bad is the pollen today",
+                ]),
+            ),
+        ]
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        let location = ctx
+            .speaker_preferences()
+            .and_then(|preferences| preferences.weather_location.as_ref())
+            .unwrap_or(&self.location);
+        let air = match self.provider.current(location) {
+            Ok(air) => air,
+            Err(e) => {
+                warn!("Failed to get the air quality: {:?}", e);
+                _ = ctx.speak("Sorry, I couldn't get the air quality right now.");
+                return;
+            }
+        };
+        let response = match query.intent {
+            Some(AIR_QUALITY_INTENT) => {
+                let pollen = describe_pollen(&air, 10.)
+                    .map(|pollen| format!(" {}.", capitalize(&pollen)))
+                    .unwrap_or_default();
+                format!("{}.{}", capitalize(&describe_air(self.index, &air)), pollen)
+            }
+            Some(POLLEN_INTENT) if air.pollen.is_empty() => {
+                "I don't have pollen data for this area.".to_string()
+            }
+            Some(POLLEN_INTENT) => match describe_pollen(&air, 1.) {
+                Some(pollen) => format!("{}.", capitalize(&pollen)),
+                None => "There's hardly any pollen right now.".to_string(),
+            },
+            _ => return,
+        };
+        _ = ctx.speak(response);
+    }
+
+    #[cfg(feature = "offline")]
+    fn needs_network(&self) -> bool {
+        true
+    }
+}
+
+/// Warns once a day when the air quality index or the pollen reach a threshold: the warnings
+/// are returned by [AirQualityAlert::check], to announce with
+/// [crate::remote::RemoteHandle::spawn_check].
+pub struct AirQualityAlert {
+    provider: Box<dyn AirQualityProvider + Send>,
+    location: WeatherLocation,
+    index: AirQualityIndex,
+    index_threshold: Option<f64>,
+    pollen_threshold: Option<f64>,
+    last_alert: Option<NaiveDate>,
+}
+
+impl AirQualityAlert {
+    /// Without thresholds, see [AirQualityAlert::set_index_threshold] and
+    /// [AirQualityAlert::set_pollen_threshold].
+    pub fn new(
+        provider: impl AirQualityProvider + Send + 'static,
+        location: WeatherLocation,
+        index: AirQualityIndex,
+    ) -> Self {
+        Self {
+            provider: Box::new(provider),
+            location,
+            index,
+            index_threshold: None,
+            pollen_threshold: None,
+            last_alert: None,
+        }
+    }
+
+    /// Warn when the index is at least `threshold`, e.g. 60 for poor air on the European scale.
+    pub fn set_index_threshold(&mut self, threshold: Option<f64>) {
+        self.index_threshold = threshold;
+    }
+
+    /// Warn when there are at least `threshold` grains/m³ of a kind of pollen.
+    pub fn set_pollen_threshold(&mut self, threshold: Option<f64>) {
+        self.pollen_threshold = threshold;
+    }
+
+    /// The warning to announce, if a threshold is reached and there was no warning yet today.
+    pub fn check(&mut self, now: DateTime<Local>) -> Option<String> {
+        if self.last_alert == Some(now.date_naive()) {
+            return None;
+        }
+        let air = self
+            .provider
+            .current(&self.location)
+            .map_err(|e| warn!("Failed to check the air quality: {:?}", e))
+            .ok()?;
+        let mut warnings = Vec::new();
+        if self
+            .index_threshold
+            .is_some_and(|threshold| air.index >= threshold)
+        {
+            warnings.push(describe_air(self.index, &air));
+        }
+        if let Some(pollen) = self
+            .pollen_threshold
+            .and_then(|threshold| describe_pollen(&air, threshold))
+        {
+            warnings.push(pollen);
+        }
+        if warnings.is_empty() {
+            return None;
+        }
+        self.last_alert = Some(now.date_naive());
+        Some(format!("Heads up, {}.", warnings.join(", and ")))
+    }
+}
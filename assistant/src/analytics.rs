@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+
+use crate::{
+    intents::{IntentRecognizer, IntentRecognizerError},
+    simd,
+};
+
+/// Embedder turns text into a normalized embedding, so that texts can be compared by meaning. The
+/// [IntentRecognizer] uses its embedding model, like for its examples.
+pub trait Embedder {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, IntentRecognizerError>;
+}
+
+impl<T> Embedder for IntentRecognizer<T> {
+    fn embed(&self, text: &str) -> Result<Vec<f32>, IntentRecognizerError> {
+        IntentRecognizer::embed(self, text)
+    }
+}
+
+/// Transcripts with about the same meaning, see [cluster_transcripts].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TranscriptCluster {
+    /// All the transcripts of the cluster, repeated ones included, closest to its center first.
+    pub transcripts: Vec<String>,
+}
+
+impl TranscriptCluster {
+    /// The transcript that represents the cluster best.
+    pub fn representative(&self) -> &str {
+        &self.transcripts[0]
+    }
+}
+
+struct Cluster {
+    sum: Vec<f32>,
+    center: Vec<f32>,
+    members: Vec<(usize, Vec<f32>)>,
+}
+
+/// Group the transcripts by meaning, e.g. those that weren't understood to find out which intents
+/// or examples are missing. A transcript joins the cluster whose center it is the most similar to
+/// if that is at least `similarity`, from 0 to 1, otherwise it starts a new one. The biggest
+/// clusters come first.
+pub fn cluster_transcripts(
+    embedder: &dyn Embedder,
+    transcripts: &[String],
+    similarity: f32,
+) -> Result<Vec<TranscriptCluster>, IntentRecognizerError> {
+    // Repeated transcripts are only embedded once
+    let mut embeddings: HashMap<&str, Vec<f32>> = HashMap::new();
+    let mut clusters: Vec<Cluster> = Vec::new();
+    for (index, transcript) in transcripts.iter().enumerate() {
+        let embedding = match embeddings.get(transcript.as_str()) {
+            Some(embedding) => embedding.clone(),
+            None => {
+                let embedding = embedder.embed(transcript)?;
+                embeddings.insert(transcript, embedding.clone());
+                embedding
+            }
+        };
+        let closest = clusters
+            .iter_mut()
+            .map(|cluster| (simd::dot(&cluster.center, &embedding), cluster))
+            .filter(|(score, _)| *score >= similarity)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b));
+        match closest {
+            Some((_, cluster)) => {
+                for (sum, value) in cluster.sum.iter_mut().zip(&embedding) {
+                    *sum += value;
+                }
+                cluster.center.clone_from(&cluster.sum);
+                simd::normalize(&mut cluster.center);
+                cluster.members.push((index, embedding));
+            }
+            None => clusters.push(Cluster {
+                sum: embedding.clone(),
+                center: embedding.clone(),
+                members: vec![(index, embedding)],
+            }),
+        }
+    }
+
+    let mut clusters: Vec<TranscriptCluster> = clusters
+        .into_iter()
+        .map(|cluster| {
+            let mut members: Vec<(f32, usize)> = cluster
+                .members
+                .iter()
+                .map(|(index, embedding)| (simd::dot(&cluster.center, embedding), *index))
+                .collect();
+            members.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+            TranscriptCluster {
+                transcripts: members
+                    .into_iter()
+                    .map(|(_, index)| transcripts[index].clone())
+                    .collect(),
+            }
+        })
+        .collect();
+    clusters.sort_by_key(|cluster| std::cmp::Reverse(cluster.transcripts.len()));
+    Ok(clusters)
+}
@@ -0,0 +1,230 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    events::AssistantEvent,
+    metrics::Metric,
+    slots::SlotValues,
+    speech_queue::SpeechControl,
+    tts::{self, TtsError},
+    AskOptions, Assistant, AssistantListenError, AssistantListenSuccessfulWakewordError,
+    AssistantQuery, QueryFailure, STARTING_UP_RESPONSE,
+};
+
+impl<T> Assistant<T> {
+    /// Like [Assistant::listen], but waits for the wakeword and the sentence without blocking the
+    /// thread. Enabled with the `tokio` feature. The TTS backend can't be sent between threads,
+    /// so the async methods have to run on a local task, e.g. in a `tokio::task::LocalSet`.
+    pub async fn listen_async(&mut self) -> Result<AssistantQuery<'_, T>, AssistantListenError> {
+        loop {
+            let detection = loop {
+                if self.stopped {
+                    return Err(AssistantListenError::Stopped);
+                }
+                let detection = tokio::time::timeout(
+                    Duration::from_millis(100),
+                    self.wakeword_listener.listen_async(),
+                )
+                .await;
+                match detection {
+                    Ok(Ok(_)) if self.muted => (),
+                    Ok(Ok(detection)) if !self.guest_mode.accepts_wakeword(&detection.name) => (),
+                    Ok(Ok(detection)) if !self.confirm_presence(&detection.name) => (),
+                    Ok(Ok(detection)) => break detection,
+                    Ok(Err(e)) => {
+                        let error = AssistantListenError::from(e);
+                        self.events.emit(AssistantEvent::Error(error.to_string()));
+                        return Err(error);
+                    }
+                    Err(_) => {
+                        self.announce_ready();
+                        self.emit_shadow_divergences();
+                        self.announce_due_items();
+                        self.end_guest_mode_when_due();
+                        self.resume_listening_when_due();
+                        self.supervise_audio_input();
+                        #[cfg(feature = "sync")]
+                        self.reload_synced();
+                        #[cfg(feature = "offline")]
+                        self.watch_connectivity();
+                        self.poll_audio_cues();
+                        self.run_automation();
+                        _ = self.continue_speaking_long();
+                        if let Some(text) = self.run_remote_commands() {
+                            let mut failure = QueryFailure::default();
+                            match self.match_text(String::new(), text, &mut failure) {
+                                Ok(Some(query)) => return Ok(self.resolve(query)),
+                                Ok(None) => (),
+                                Err(e) => {
+                                    return Err(AssistantListenError::ProcessError(
+                                        Box::new(failure),
+                                        e,
+                                    ))
+                                }
+                            }
+                        }
+                    }
+                }
+            };
+            let detected = Instant::now();
+            let wakeword = detection.name.clone();
+            let wakeword_at = detection.timestamp;
+            self.record_wakeword(&wakeword);
+            self.events
+                .emit(AssistantEvent::WakewordDetected(detection));
+            let mut failure = QueryFailure {
+                wakeword: wakeword.clone(),
+                detected_at: Some(self.clock.now()),
+                wakeword_at,
+                ..QueryFailure::default()
+            };
+
+            match tts::is_speaking(self.tts.as_ref()) {
+                Err(_) => {
+                    return Err(AssistantListenError::ProcessError(
+                        Box::new(failure),
+                        AssistantListenSuccessfulWakewordError::SpeechRecognitionError,
+                    ))
+                }
+                // Long texts can be interrupted to pause or skip them
+                Ok(true) if self.speech_queue.is_active() => {
+                    _ = self.control_speech(SpeechControl::Pause);
+                }
+                Ok(true) if self.barge_in => _ = tts::stop(self.tts.as_mut()),
+                Ok(true) => {
+                    _ = self.finish_speaking_async().await;
+                    continue;
+                }
+                Ok(false) => (),
+            }
+
+            if let Some(response) = self.wakeword_responses.get(&wakeword) {
+                // Wait for the response to finish so it isn't picked up by the recognizer
+                _ = crate::tts_cache::speak(
+                    self.tts.as_mut(),
+                    self.utterances.as_ref(),
+                    &self.normalizer,
+                    self.tts_cache.as_ref(),
+                    response.clone(),
+                );
+                _ = self.finish_speaking_async().await;
+            }
+
+            if !self.wakewords_listen.contains(&wakeword) {
+                return Ok(AssistantQuery {
+                    context: self.wakeword_contexts.get(&wakeword).cloned(),
+                    wakeword,
+                    intent: None,
+                    text: None,
+                    score: None,
+                    slots: SlotValues::new(),
+                    location: self.location.clone(),
+                    wakeword_at,
+                    speech_at: None,
+                });
+            }
+
+            self.query_speech_recognizer = self.wakeword_speech_recognizers.get(&wakeword).cloned();
+            self.wait_until_ready_async().await;
+            self.enter_context(&wakeword);
+            self.metrics
+                .record(Metric::WakewordToListen, detected.elapsed());
+            let options = self.query_options();
+            let text = match self.recognize_text_async(&options, &mut failure).await {
+                Ok(text) => text,
+                Err(e) => return Err(AssistantListenError::ProcessError(Box::new(failure), e)),
+            };
+
+            if self.speech_queue.is_active() {
+                if let Some(control) = SpeechControl::from_text(&text) {
+                    _ = self.control_speech(control);
+                    continue;
+                }
+            }
+
+            // Queries handled by a skill aren't returned
+            let matched = self.match_text(wakeword, text, &mut failure);
+            self.metrics.record(Metric::Query, detected.elapsed());
+            match matched {
+                Ok(Some(query)) => return Ok(self.resolve(query)),
+                Ok(None) => continue,
+                Err(e) => return Err(AssistantListenError::ProcessError(Box::new(failure), e)),
+            }
+        }
+    }
+
+    /// Like [Assistant::ask], without blocking the thread.
+    pub async fn ask_async(
+        &mut self,
+        question: impl Into<String>,
+        options: AskOptions,
+    ) -> Result<String, AssistantListenSuccessfulWakewordError> {
+        // Wait for the question to finish so it isn't picked up by the recognizer
+        crate::tts_cache::speak(
+            self.tts.as_mut(),
+            self.utterances.as_ref(),
+            &self.normalizer,
+            self.tts_cache.as_ref(),
+            question,
+        )
+        .map_err(|_| AssistantListenSuccessfulWakewordError::SpeechRecognitionError)?;
+        _ = self.finish_speaking_async().await;
+        self.recognize_text_async(&options, &mut QueryFailure::default())
+            .await
+    }
+
+    /// Speak the text and wait until it has been spoken, without blocking the thread.
+    pub async fn speak_async(&mut self, text: impl Into<String>) -> Result<(), TtsError> {
+        self.speak(text)?;
+        self.finish_speaking_async().await
+    }
+
+    /// Like [Assistant::finish_speaking], without blocking the thread.
+    pub async fn finish_speaking_async(&self) -> Result<(), TtsError> {
+        while tts::is_speaking(self.tts.as_ref())? {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        Ok(())
+    }
+
+    /// Like [Assistant::wait_until_ready], without blocking the thread.
+    async fn wait_until_ready_async(&mut self) {
+        if self.is_ready() {
+            return;
+        }
+        _ = crate::tts_cache::speak(
+            self.tts.as_mut(),
+            self.utterances.as_ref(),
+            &self.normalizer,
+            self.tts_cache.as_ref(),
+            STARTING_UP_RESPONSE,
+        );
+        _ = self.finish_speaking_async().await;
+        while !self.is_ready() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        self.announce_ready();
+    }
+
+    async fn recognize_text_async(
+        &mut self,
+        options: &AskOptions,
+        failure: &mut QueryFailure,
+    ) -> Result<String, AssistantListenSuccessfulWakewordError> {
+        let mut retries = 0;
+        loop {
+            self.events.emit(AssistantEvent::RecognitionStarted);
+            let started = Instant::now();
+            let result = match self.sentence_recognizer(options) {
+                Ok(recognizer) => recognizer.recognize_with_audio_async().await,
+                Err(e) => Err(e),
+            };
+            self.metrics
+                .record(Metric::SpeechRecognition, started.elapsed());
+            if let Some(text) = self.handle_recognition_result(result, &mut retries, failure)? {
+                return Ok(text);
+            }
+            // Wait for the prompt to finish so it isn't picked up by the recognizer
+            _ = self.finish_speaking_async().await;
+        }
+    }
+}
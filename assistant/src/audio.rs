@@ -0,0 +1,768 @@
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    BuildStreamError, Sample, SampleRate, SizedSample,
+};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, TryLockError,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use thiserror::Error;
+
+use crate::{ring::RingBuffer, scheduling::ThreadScheduling, simd};
+
+/// The format of the frames given to audio consumers. Samples are always `f32` and interleaved
+/// when there is more than one channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+}
+
+/// AudioInputConfig selects the input device and configuration shared by the wakeword listener
+/// and speech-to-text. It can be created by calling [AudioInputConfig::build] and the capture
+/// stream is started with [AudioInput::start].
+pub struct AudioInputConfig {
+    input_device: cpal::Device,
+    selection: DeviceSelection,
+    input_config: cpal::SupportedStreamConfig,
+    scheduling: ThreadScheduling,
+}
+
+/// How the input device is found again after it was disconnected.
+#[derive(Clone)]
+enum DeviceSelection {
+    Default,
+    Name(String),
+}
+
+/// How long the stream can go without audio before it is considered failed, see
+/// [AudioInput::supervise].
+const STALL_TIMEOUT: Duration = Duration::from_secs(3);
+/// How often reconnecting to a failed input device is tried.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Which input device to capture audio from.
+pub enum InputDevice {
+    /// The default input device of the system.
+    Default,
+    /// The input device with the given name, see [input_device_names].
+    Name(String),
+    Device(cpal::Device),
+}
+
+/// Names of the available input devices, to be used with [InputDevice::Name].
+pub fn input_device_names() -> Result<Vec<String>, AudioInputBuildError> {
+    Ok(cpal::default_host()
+        .input_devices()?
+        .filter_map(|device| device.name().ok())
+        .collect())
+}
+
+#[derive(Error, Debug)]
+pub enum AudioInputBuildError {
+    #[error("No input device available")]
+    NoInputDevice,
+    #[error("Failed to list input devices")]
+    ListInputDevices(#[from] cpal::DevicesError),
+    #[error("Input device {0} not found")]
+    InputDeviceNotFound(String),
+    #[error("No default input config available")]
+    NoDefaultInputConfig(#[from] cpal::DefaultStreamConfigError),
+    #[error("Failed to list input configs")]
+    ListInputConfigs(#[from] cpal::SupportedStreamConfigsError),
+    #[error("Failed to get a supported input config")]
+    GetSupportedInputConfig,
+}
+
+#[derive(Error, Debug)]
+pub enum AudioInputStartError {
+    #[error("Failed to init input stream")]
+    InitInputStream(#[from] BuildStreamError),
+    #[error("Failed to play stream")]
+    PlayStream(#[from] cpal::PlayStreamError),
+}
+
+#[derive(Error, Debug)]
+pub enum AudioInputReconnectError {
+    #[error("Failed to find the input device")]
+    Device(#[from] AudioInputBuildError),
+    #[error("The input device doesn't support the format of the stream anymore")]
+    FormatUnavailable,
+    #[error("Failed to restart the stream")]
+    Start(#[from] AudioInputStartError),
+}
+
+#[derive(Error, Debug)]
+pub enum AudioInputPauseError {
+    #[error("Failed to pause stream")]
+    PauseStream(#[from] cpal::PauseStreamError),
+    #[error("Failed to play stream")]
+    PlayStream(#[from] cpal::PlayStreamError),
+}
+
+impl AudioInputConfig {
+    /// Pick the default input device, see [AudioInputConfig::build_with_device].
+    pub fn build() -> Result<Self, AudioInputBuildError> {
+        Self::build_with_device(InputDevice::Default)
+    }
+
+    /// Pick the input device and its configuration. I16 mono at 16 kHz is preferred, since that's
+    /// what the STT recognizer works with and doesn't need resampling, otherwise any configuration
+    /// with a supported sample format is used.
+    pub fn build_with_device(device: InputDevice) -> Result<Self, AudioInputBuildError> {
+        let selection = match &device {
+            InputDevice::Default => DeviceSelection::Default,
+            InputDevice::Name(name) => DeviceSelection::Name(name.clone()),
+            InputDevice::Device(device) => device
+                .name()
+                .map(DeviceSelection::Name)
+                .unwrap_or(DeviceSelection::Default),
+        };
+        let input_device = match device {
+            InputDevice::Device(device) => device,
+            _ => selection.find()?,
+        };
+
+        let default_input_config = input_device.default_input_config()?;
+
+        let input_config = if is_preferred_config(
+            &default_input_config.sample_format(),
+            default_input_config.channels(),
+            default_input_config.sample_rate().0,
+        ) {
+            default_input_config
+        } else {
+            let supported: Vec<_> = input_device.supported_input_configs()?.collect();
+            supported
+                .iter()
+                .find(|sc| {
+                    is_preferred_config(&sc.sample_format(), sc.channels(), 16000)
+                        && sc.min_sample_rate().0 <= 16000
+                        && 16000 <= sc.max_sample_rate().0
+                })
+                .map(|sc| sc.with_sample_rate(SampleRate(16000)))
+                .or_else(|| {
+                    // look for any compatible configuration
+                    supported
+                        .into_iter()
+                        .find(|sc| is_compatible_format(&sc.sample_format()))
+                        .map(|sc| try_get_config_with_sample_rate(sc, 16000))
+                })
+                .ok_or(AudioInputBuildError::GetSupportedInputConfig)?
+        };
+
+        Ok(AudioInputConfig {
+            input_device,
+            selection,
+            input_config,
+            scheduling: ThreadScheduling::default(),
+        })
+    }
+
+    /// Schedule the thread running the audio callback, which is applied when it first runs.
+    /// Settings that can't be applied, e.g. without permission, are skipped with a warning.
+    pub fn set_scheduling(&mut self, scheduling: ThreadScheduling) {
+        self.scheduling = scheduling;
+    }
+
+    pub fn format(&self) -> AudioFormat {
+        AudioFormat {
+            sample_rate: self.input_config.sample_rate().0,
+            channels: self.input_config.channels(),
+        }
+    }
+}
+
+impl DeviceSelection {
+    fn find(&self) -> Result<cpal::Device, AudioInputBuildError> {
+        let host = cpal::default_host();
+        match self {
+            DeviceSelection::Default => host
+                .default_input_device()
+                .ok_or(AudioInputBuildError::NoInputDevice),
+            DeviceSelection::Name(name) => host
+                .input_devices()?
+                .find(|device| device.name().is_ok_and(|n| n == *name))
+                .ok_or(AudioInputBuildError::InputDeviceNotFound(name.clone())),
+        }
+    }
+}
+
+/// Receives the blocks of samples of an [AudioSource], on the thread producing them, with where
+/// the block starts in the stream.
+pub type Consumer = Box<dyn FnMut(&[f32], AudioTimestamp) + Send>;
+
+/// Identifies a consumer registered with [AudioSource::subscribe].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ConsumerId(usize);
+
+/// Where something was heard in the stream of an [AudioSource], to align it with recorded audio.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AudioTimestamp {
+    /// The index of the frame, counting every frame the source gave to its consumers.
+    pub frame: u64,
+    /// When the block with the frame was given to the consumers, shortly after it was captured.
+    pub time: SystemTime,
+}
+
+struct Consumers {
+    next_id: usize,
+    consumers: HashMap<usize, Consumer>,
+    channels: usize,
+    // The frames given to the consumers so far
+    frames: u64,
+}
+
+impl Consumers {
+    fn new(format: AudioFormat) -> Self {
+        Self {
+            next_id: 0,
+            consumers: HashMap::new(),
+            channels: format.channels.max(1).into(),
+            frames: 0,
+        }
+    }
+
+    fn add(&mut self, consumer: Consumer) -> ConsumerId {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.consumers.insert(id, consumer);
+        ConsumerId(id)
+    }
+
+    fn remove(&mut self, id: ConsumerId) {
+        self.consumers.remove(&id.0);
+    }
+
+    fn feed(&mut self, block: &[f32]) {
+        let start = AudioTimestamp {
+            frame: self.frames,
+            time: SystemTime::now(),
+        };
+        for consumer in self.consumers.values_mut() {
+            consumer(block, start);
+        }
+        self.frames += (block.len() / self.channels) as u64;
+    }
+}
+
+/// Where the wakeword listener and speech recognition get their audio from: an [AudioInput]
+/// capturing a device, or a [MemoryAudioSource] given the samples by the application, e.g. from
+/// a recording in a test.
+pub trait AudioSource {
+    /// The format of the blocks given to the consumers.
+    fn format(&self) -> AudioFormat;
+
+    /// Register a consumer that will receive every block of samples until it is removed with
+    /// [AudioSource::unsubscribe]. Its timestamp is the first frame of the block, so that what a
+    /// consumer hears can be aligned with what the other consumers of the source heard.
+    fn subscribe(&self, consumer: Consumer) -> ConsumerId;
+
+    fn unsubscribe(&self, id: ConsumerId);
+}
+
+/// How much audio the stream keeps for the consumers while one is being added or removed, see
+/// [BlockFeeder].
+const BACKLOG: Duration = Duration::from_millis(250);
+
+/// Gives the blocks of a stream to the consumers without allocating or waiting for a consumer
+/// being added or removed, either of which could make the stream miss its deadlines. Blocks that
+/// come while the consumers are locked are kept and given to them with the next one.
+struct BlockFeeder {
+    consumers: Arc<Mutex<Consumers>>,
+    // The block converted to f32, large enough for the blocks of any usual device
+    buffer: Vec<f32>,
+    backlog: RingBuffer<f32>,
+}
+
+impl BlockFeeder {
+    fn new(format: AudioFormat, consumers: Arc<Mutex<Consumers>>) -> Self {
+        let frames = (BACKLOG.as_secs_f64() * format.sample_rate as f64) as usize;
+        let samples = frames * usize::from(format.channels.max(1));
+        Self {
+            consumers,
+            buffer: Vec::with_capacity(samples),
+            backlog: RingBuffer::new(samples),
+        }
+    }
+
+    fn feed<S>(&mut self, data: &[S], convert: fn(&[S], &mut Vec<f32>)) {
+        self.buffer.clear();
+        convert(data, &mut self.buffer);
+        let mut consumers = match self.consumers.try_lock() {
+            Ok(consumers) => consumers,
+            Err(TryLockError::WouldBlock) => {
+                // The backlog holds whole frames, so its two parts can be fed separately
+                self.backlog.push_overwrite(&self.buffer);
+                return;
+            }
+            Err(TryLockError::Poisoned(_)) => panic!("A consumer of the audio panicked"),
+        };
+        let (first, second) = self.backlog.as_slices();
+        for part in [first, second] {
+            if !part.is_empty() {
+                consumers.feed(part);
+            }
+        }
+        self.backlog.clear();
+        consumers.feed(&self.buffer);
+    }
+}
+
+/// What happened to the capture stream, see [AudioInput::supervise].
+#[derive(Clone, Debug, PartialEq)]
+pub enum AudioInputStatus {
+    /// The stream failed, e.g. because the microphone was unplugged, for the given reason.
+    Disconnected(String),
+    /// The stream was started again after failing.
+    Reconnected,
+}
+
+/// What the callbacks of a stream tell about its health.
+#[derive(Default)]
+struct StreamHealth {
+    /// The last error that broke the stream.
+    error: Mutex<Option<String>>,
+    /// Blocks of samples received so far.
+    blocks: AtomicUsize,
+}
+
+/// AudioInput owns the single capture stream of the assistant and passes every block of samples
+/// to the registered consumers, in the format returned by [AudioInput::format]. Consumers run on
+/// the audio thread, so they should not block or allocate, and hand the audio over to another
+/// thread to process it.
+pub struct AudioInput {
+    format: AudioFormat,
+    consumers: Arc<Mutex<Consumers>>,
+    stream: cpal::Stream,
+    selection: DeviceSelection,
+    sample_format: cpal::SampleFormat,
+    scheduling: ThreadScheduling,
+    health: Arc<StreamHealth>,
+    paused: bool,
+    // The blocks received at the last check and when they changed
+    watchdog: (usize, Instant),
+    // When to try reconnecting, while the stream is failed
+    retry_at: Option<Instant>,
+}
+
+impl AudioInput {
+    pub fn start(config: AudioInputConfig) -> Result<Self, AudioInputStartError> {
+        let format = config.format();
+        let consumers = Arc::new(Mutex::new(Consumers::new(format)));
+        let health = Arc::new(StreamHealth::default());
+        let sample_format = config.input_config.sample_format();
+        let stream = build_stream(
+            &config.input_device,
+            format,
+            sample_format,
+            consumers.clone(),
+            config.scheduling.clone(),
+            health.clone(),
+        )?;
+        stream.play()?;
+
+        Ok(AudioInput {
+            format,
+            consumers,
+            stream,
+            selection: config.selection,
+            sample_format,
+            scheduling: config.scheduling,
+            health,
+            paused: false,
+            watchdog: (0, Instant::now()),
+            retry_at: None,
+        })
+    }
+
+    pub fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    /// Register a consumer that will receive every block of captured samples until it is
+    /// removed with [AudioInput::unsubscribe].
+    pub fn subscribe(
+        &self,
+        consumer: impl FnMut(&[f32], AudioTimestamp) + Send + 'static,
+    ) -> ConsumerId {
+        self.consumers.lock().unwrap().add(Box::new(consumer))
+    }
+
+    pub fn unsubscribe(&self, id: ConsumerId) {
+        self.consumers.lock().unwrap().remove(id);
+    }
+
+    /// Stop capturing, e.g. for privacy. The consumers get no audio until
+    /// [AudioInput::resume].
+    pub fn pause(&mut self) -> Result<(), AudioInputPauseError> {
+        self.stream.pause()?;
+        self.paused = true;
+        Ok(())
+    }
+
+    pub fn resume(&mut self) -> Result<(), AudioInputPauseError> {
+        self.stream.play()?;
+        self.paused = false;
+        self.watchdog = (self.health.blocks.load(Ordering::Relaxed), Instant::now());
+        Ok(())
+    }
+
+    /// Check that the stream still works, and try to reconnect to the input device every few
+    /// seconds once it failed, e.g. after a USB microphone was unplugged and plugged in again.
+    /// The stream is considered failed after an error or when no audio arrived for a few
+    /// seconds. Meant to be called regularly, it returns what changed since the last call.
+    pub fn supervise(&mut self) -> Option<AudioInputStatus> {
+        let now = Instant::now();
+        let Some(retry_at) = self.retry_at else {
+            let reason = self.health.error.lock().unwrap().take();
+            let reason = reason.or_else(|| self.stalled(now))?;
+            self.retry_at = Some(now);
+            return Some(AudioInputStatus::Disconnected(reason));
+        };
+        if now < retry_at {
+            return None;
+        }
+        match self.reconnect() {
+            Ok(()) => Some(AudioInputStatus::Reconnected),
+            Err(_) => {
+                self.retry_at = Some(now + RECONNECT_INTERVAL);
+                None
+            }
+        }
+    }
+
+    /// Find the input device again and restart the stream, with the same format so that the
+    /// consumers keep working. A paused stream stays paused.
+    pub fn reconnect(&mut self) -> Result<(), AudioInputReconnectError> {
+        let device = self.selection.find()?;
+        let supported = device
+            .supported_input_configs()
+            .map_err(AudioInputBuildError::from)?
+            .any(|sc| {
+                sc.sample_format() == self.sample_format
+                    && sc.channels() == self.format.channels
+                    && sc.min_sample_rate().0 <= self.format.sample_rate
+                    && self.format.sample_rate <= sc.max_sample_rate().0
+            });
+        if !supported {
+            return Err(AudioInputReconnectError::FormatUnavailable);
+        }
+        let health = Arc::new(StreamHealth::default());
+        let stream = build_stream(
+            &device,
+            self.format,
+            self.sample_format,
+            self.consumers.clone(),
+            self.scheduling.clone(),
+            health.clone(),
+        )
+        .map_err(AudioInputStartError::from)?;
+        if !self.paused {
+            stream.play().map_err(AudioInputStartError::from)?;
+        }
+        self.stream = stream;
+        self.health = health;
+        self.watchdog = (0, Instant::now());
+        self.retry_at = None;
+        Ok(())
+    }
+
+    /// The reason the stream failed if no audio arrived for [STALL_TIMEOUT].
+    fn stalled(&mut self, now: Instant) -> Option<String> {
+        let blocks = self.health.blocks.load(Ordering::Relaxed);
+        if self.paused || blocks != self.watchdog.0 {
+            self.watchdog = (blocks, now);
+            return None;
+        }
+        (now.duration_since(self.watchdog.1) > STALL_TIMEOUT)
+            .then(|| format!("No audio for {} seconds", STALL_TIMEOUT.as_secs()))
+    }
+}
+
+impl AudioSource for AudioInput {
+    fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    fn subscribe(&self, consumer: Consumer) -> ConsumerId {
+        self.consumers.lock().unwrap().add(consumer)
+    }
+
+    fn unsubscribe(&self, id: ConsumerId) {
+        AudioInput::unsubscribe(self, id);
+    }
+}
+
+/// An [AudioSource] given its samples with [MemoryAudioSource::push], e.g. from a recording read
+/// with [read_wav], to test wakeword detection and speech recognition without a microphone. The
+/// consumers run on the thread pushing the samples, so speech recognition has to be started on
+/// another thread first.
+pub struct MemoryAudioSource {
+    format: AudioFormat,
+    consumers: Arc<Mutex<Consumers>>,
+    feeder: Mutex<BlockFeeder>,
+}
+
+/// How many frames [MemoryAudioSource::push] gives the consumers at once, like a device would.
+const MEMORY_BLOCK_FRAMES: usize = 1024;
+
+impl MemoryAudioSource {
+    pub fn new(format: AudioFormat) -> Self {
+        let consumers = Arc::new(Mutex::new(Consumers::new(format)));
+        Self {
+            format,
+            feeder: Mutex::new(BlockFeeder::new(format, consumers.clone())),
+            consumers,
+        }
+    }
+
+    /// Give interleaved samples in the format of the source to the consumers, in blocks, the
+    /// same way an [AudioInput] does.
+    pub fn push(&self, samples: &[f32]) {
+        let block = MEMORY_BLOCK_FRAMES * self.format.channels as usize;
+        let mut feeder = self.feeder.lock().unwrap();
+        for chunk in samples.chunks(block) {
+            feeder.feed(chunk, convert_samples);
+        }
+    }
+
+    /// Give the consumers silence, e.g. so that speech recognition notices the end of a sentence.
+    pub fn push_silence(&self, duration: Duration) {
+        let frames = (duration.as_secs_f64() * self.format.sample_rate as f64) as usize;
+        self.push(&vec![0.0; frames * self.format.channels as usize]);
+    }
+}
+
+impl AudioSource for MemoryAudioSource {
+    fn format(&self) -> AudioFormat {
+        self.format
+    }
+
+    fn subscribe(&self, consumer: Consumer) -> ConsumerId {
+        self.consumers.lock().unwrap().add(consumer)
+    }
+
+    fn unsubscribe(&self, id: ConsumerId) {
+        self.consumers.lock().unwrap().remove(id);
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ReadWavError {
+    #[error("Failed to read WAV file")]
+    Read(#[from] hound::Error),
+    #[error("The only supported sample formats are i16, i32 and f32")]
+    UnsupportedSampleFormat,
+}
+
+/// The format and interleaved samples of a WAV file, e.g. to give to a [MemoryAudioSource].
+pub fn read_wav(path: impl AsRef<Path>) -> Result<(AudioFormat, Vec<f32>), ReadWavError> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    let format = AudioFormat {
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+    };
+    let samples = match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Int, 16) => reader
+            .samples::<i16>()
+            .map(|s| s.map(f32::from_sample))
+            .collect::<Result<_, _>>()?,
+        (hound::SampleFormat::Int, 32) => reader
+            .samples::<i32>()
+            .map(|s| s.map(f32::from_sample))
+            .collect::<Result<_, _>>()?,
+        (hound::SampleFormat::Float, 32) => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        _ => return Err(ReadWavError::UnsupportedSampleFormat),
+    };
+    Ok((format, samples))
+}
+
+/// Resampler converts interleaved audio to mono at another sample rate, one block at a time.
+/// Channels are averaged. When downsampling, every output sample is the average of the input
+/// samples it covers, which filters out most of the frequencies that would otherwise alias. Only
+/// the samples still needed for the next output sample are kept between blocks, without
+/// buffering them.
+pub(crate) struct Resampler {
+    channels: usize,
+    /// Input samples per output sample.
+    step: f64,
+    /// Position of the next output sample, from `previous` when upsampling and from the start of
+    /// the window being averaged when downsampling.
+    position: f64,
+    /// The last input sample, to interpolate from when upsampling.
+    previous: Option<f32>,
+    /// Sum of the input samples of the window being averaged, and how many there are.
+    sum: f32,
+    count: usize,
+}
+
+impl Resampler {
+    pub(crate) fn new(format: AudioFormat, sample_rate: u32) -> Self {
+        Self {
+            channels: format.channels.max(1) as usize,
+            step: format.sample_rate as f64 / sample_rate as f64,
+            position: 0.,
+            previous: None,
+            sum: 0.,
+            count: 0,
+        }
+    }
+
+    /// Give the resampled block to `output`, one sample at a time.
+    pub(crate) fn process(&mut self, interleaved: &[f32], mut output: impl FnMut(f32)) {
+        let mono = interleaved
+            .chunks(self.channels)
+            .map(|frame| frame.iter().sum::<f32>() / frame.len() as f32);
+
+        if self.step == 1. {
+            mono.for_each(output);
+        } else if self.step < 1. {
+            // Upsampling, interpolate between neighbouring samples
+            for sample in mono {
+                let Some(previous) = self.previous.replace(sample) else {
+                    continue;
+                };
+                while self.position < 1. {
+                    let fraction = self.position as f32;
+                    output(previous * (1. - fraction) + sample * fraction);
+                    self.position += self.step;
+                }
+                self.position -= 1.;
+            }
+        } else {
+            for sample in mono {
+                self.sum += sample;
+                self.count += 1;
+                // The window ends at the first sample after the position of the next one
+                let end = (self.position + self.step) as usize;
+                if self.count >= end {
+                    output(self.sum / self.count as f32);
+                    self.position += self.step - self.count as f64;
+                    self.sum = 0.;
+                    self.count = 0;
+                }
+            }
+        }
+    }
+}
+
+fn is_compatible_format(format: &cpal::SampleFormat) -> bool {
+    matches!(
+        format,
+        cpal::SampleFormat::I16 | cpal::SampleFormat::I32 | cpal::SampleFormat::F32
+    )
+}
+
+fn is_preferred_config(format: &cpal::SampleFormat, channels: u16, sample_rate: u32) -> bool {
+    *format == cpal::SampleFormat::I16 && channels == 1 && sample_rate == 16000
+}
+
+fn try_get_config_with_sample_rate(
+    sc: cpal::SupportedStreamConfigRange,
+    preferred_sample_rate: u32,
+) -> cpal::SupportedStreamConfig {
+    if sc.min_sample_rate().0 <= preferred_sample_rate
+        && preferred_sample_rate <= sc.max_sample_rate().0
+    {
+        sc.with_sample_rate(SampleRate(preferred_sample_rate))
+    } else {
+        sc.with_max_sample_rate()
+    }
+}
+
+/// Convert samples to `f32` and append them to the output.
+fn convert_samples<S: SizedSample>(input: &[S], output: &mut Vec<f32>)
+where
+    f32: cpal::FromSample<S>,
+{
+    output.extend(input.iter().map(|&s| f32::from_sample(s)));
+}
+
+/// Build a stream of the device in the format, not started yet.
+fn build_stream(
+    device: &cpal::Device,
+    format: AudioFormat,
+    sample_format: cpal::SampleFormat,
+    consumers: Arc<Mutex<Consumers>>,
+    scheduling: ThreadScheduling,
+    health: Arc<StreamHealth>,
+) -> Result<cpal::Stream, BuildStreamError> {
+    let stream_config = cpal::StreamConfig {
+        channels: format.channels,
+        sample_rate: SampleRate(format.sample_rate),
+        buffer_size: cpal::BufferSize::Default,
+    };
+    match sample_format {
+        cpal::SampleFormat::I16 => init_input_stream::<i16>(
+            device,
+            &stream_config,
+            consumers,
+            scheduling,
+            health,
+            simd::i16_to_f32,
+        ),
+        cpal::SampleFormat::I32 => init_input_stream::<i32>(
+            device,
+            &stream_config,
+            consumers,
+            scheduling,
+            health,
+            convert_samples,
+        ),
+        cpal::SampleFormat::F32 => init_input_stream::<f32>(
+            device,
+            &stream_config,
+            consumers,
+            scheduling,
+            health,
+            convert_samples,
+        ),
+        _ => panic!("The only supported sample formats are i16, i32 and f32. This should never happen, because we already checked for this in AudioInputConfig::build."),
+    }
+}
+
+/// Start a stream of samples of type `S`, converted with `convert` before being given to the
+/// consumers.
+fn init_input_stream<S: SizedSample + 'static>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    consumers: Arc<Mutex<Consumers>>,
+    scheduling: ThreadScheduling,
+    health: Arc<StreamHealth>,
+    convert: fn(&[S], &mut Vec<f32>),
+) -> Result<cpal::Stream, BuildStreamError> {
+    let error_health = health.clone();
+    let error_callback = move |err| {
+        warn!("an error occurred on stream: {}", err);
+        // Other errors, like overruns, don't stop the stream. If they did, the watchdog of
+        // AudioInput::supervise notices that no audio arrives.
+        if let cpal::StreamError::DeviceNotAvailable = err {
+            *error_health.error.lock().unwrap() = Some(err.to_string());
+        }
+    };
+
+    let mut feeder = BlockFeeder::new(
+        AudioFormat {
+            sample_rate: config.sample_rate.0,
+            channels: config.channels,
+        },
+        consumers,
+    );
+    let mut scheduled = false;
+    let data_callback = move |data: &[S], _: &_| {
+        if !scheduled {
+            scheduling.apply_or_warn("audio");
+            scheduled = true;
+        }
+        health.blocks.fetch_add(1, Ordering::Relaxed);
+        feeder.feed(data, convert);
+    };
+    device.build_input_stream(config, data_callback, error_callback, None)
+}
@@ -0,0 +1,277 @@
+use chrono::NaiveTime;
+use std::collections::{HashMap, VecDeque};
+
+#[cfg(feature = "home")]
+use crate::{home::MqttPublisher, http::HttpClient};
+use crate::{schedule::ScheduledItem, skills::IntentTarget};
+
+/// How many rules can trigger each other through [Action::SetState] before the chain is stopped,
+/// so rules that keep changing each other's state don't loop forever.
+const MAX_CHAIN_LENGTH: usize = 8;
+
+/// What makes a [Rule] run.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trigger<T> {
+    /// An intent added with [crate::AssistantConfig::add_intent] matched a query.
+    Intent(T),
+    /// An intent of a skill matched a query, by the name of the intent.
+    SkillIntent(String),
+    /// A timer, alarm or reminder is due, only the ones with this label if set.
+    ScheduledItemDue(Option<String>),
+    /// A message was published to an MQTT topic, given to the assistant with
+    /// [crate::remote::RemoteCommand::MqttMessage]. `+` and `#` match topic levels like in MQTT
+    /// subscriptions.
+    MqttMessage(String),
+    /// A value set with [crate::Assistant::set_state] changed.
+    StateChanged(String),
+    /// A cue with this name was heard, see [crate::cues::AudioCueSource].
+    AudioCue(String),
+}
+
+/// Has to hold for a [Rule] to run.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    /// The local time is in the range, which wraps around midnight if `from` is after `to`.
+    TimeRange { from: NaiveTime, to: NaiveTime },
+    /// Do not disturb is on or off, see [crate::Assistant::set_do_not_disturb].
+    DoNotDisturb(bool),
+    /// A value set with [crate::Assistant::set_state] is equal to this one.
+    State { key: String, value: String },
+}
+
+/// What a [Rule] does, in order. Speech waits for the assistant to finish what it is saying.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Speak(String),
+    /// Match the text as if the user said it, e.g. to run a skill, like
+    /// [crate::remote::RemoteCommand::Query].
+    Query(String),
+    SetDoNotDisturb(bool),
+    SetState {
+        key: String,
+        value: String,
+    },
+    /// Needs a publisher, see [crate::AssistantConfig::set_automation_mqtt].
+    #[cfg(feature = "home")]
+    PublishMqtt {
+        topic: String,
+        payload: String,
+        retain: bool,
+    },
+    #[cfg(feature = "home")]
+    Http {
+        method: String,
+        url: String,
+        body: Option<String>,
+    },
+}
+
+/// Runs the actions when the trigger fires and all conditions hold, see
+/// [crate::AssistantConfig::add_rule].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule<T> {
+    pub name: String,
+    pub trigger: Trigger<T>,
+    pub conditions: Vec<Condition>,
+    pub actions: Vec<Action>,
+}
+
+/// What happened, for the triggers of the rules.
+pub(crate) enum Fired<'a, T> {
+    Intent(&'a IntentTarget<T>),
+    ScheduledItemDue(&'a ScheduledItem),
+    MqttMessage(&'a str),
+    StateChanged(&'a str),
+    AudioCue(&'a str),
+}
+
+/// The rules of the assistant, the state they check and the actions waiting to run.
+pub(crate) struct Automation<T> {
+    rules: Vec<Rule<T>>,
+    eq: fn(&T, &T) -> bool,
+    state: HashMap<String, String>,
+    do_not_disturb: bool,
+    // With the number of rules that led to them
+    pending: VecDeque<(Action, usize)>,
+    #[cfg(feature = "home")]
+    mqtt: Option<MqttPublisher>,
+    #[cfg(feature = "home")]
+    http: HttpClient,
+}
+
+impl<T> Automation<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            rules: Vec::new(),
+            eq: |_, _| false,
+            state: HashMap::new(),
+            do_not_disturb: false,
+            pending: VecDeque::new(),
+            #[cfg(feature = "home")]
+            mqtt: None,
+            #[cfg(feature = "home")]
+            http: HttpClient::default(),
+        }
+    }
+
+    pub(crate) fn add_rule(&mut self, rule: Rule<T>)
+    where
+        T: PartialEq,
+    {
+        self.eq = T::eq;
+        self.rules.push(rule);
+    }
+
+    #[cfg(feature = "home")]
+    pub(crate) fn set_mqtt(&mut self, publisher: MqttPublisher) {
+        self.mqtt = Some(publisher);
+    }
+
+    #[cfg(feature = "home")]
+    pub(crate) fn set_http_client(&mut self, client: HttpClient) {
+        self.http = client;
+    }
+
+    /// Queue the actions of the rules triggered by what happened, returning their names.
+    /// `chain` is the number of rules that led to it.
+    pub(crate) fn fire(
+        &mut self,
+        fired: Fired<'_, T>,
+        now: NaiveTime,
+        chain: usize,
+    ) -> Vec<String> {
+        if self.rules.is_empty() {
+            return Vec::new();
+        }
+        if chain >= MAX_CHAIN_LENGTH {
+            warn!("Stopped a chain of {} automation rules", chain);
+            return Vec::new();
+        }
+        let triggered: Vec<&Rule<T>> = self
+            .rules
+            .iter()
+            .filter(|rule| self.matches(&rule.trigger, &fired))
+            .filter(|rule| rule.conditions.iter().all(|c| self.holds(c, now)))
+            .collect();
+        let names = triggered.iter().map(|rule| rule.name.clone()).collect();
+        let actions: Vec<Action> = triggered
+            .into_iter()
+            .flat_map(|rule| rule.actions.iter().cloned())
+            .collect();
+        self.pending
+            .extend(actions.into_iter().map(|action| (action, chain + 1)));
+        names
+    }
+
+    fn matches(&self, trigger: &Trigger<T>, fired: &Fired<'_, T>) -> bool {
+        match (trigger, fired) {
+            (Trigger::Intent(intent), Fired::Intent(IntentTarget::App(matched))) => {
+                (self.eq)(intent, matched)
+            }
+            (Trigger::SkillIntent(name), Fired::Intent(IntentTarget::Skill { intent, .. })) => {
+                name == intent
+            }
+            (Trigger::ScheduledItemDue(label), Fired::ScheduledItemDue(item)) => {
+                label.as_ref().is_none_or(|label| *label == item.label)
+            }
+            (Trigger::MqttMessage(filter), Fired::MqttMessage(topic)) => {
+                topic_matches(filter, topic)
+            }
+            (Trigger::StateChanged(key), Fired::StateChanged(changed)) => key == changed,
+            (Trigger::AudioCue(name), Fired::AudioCue(heard)) => name == heard,
+            _ => false,
+        }
+    }
+
+    fn holds(&self, condition: &Condition, now: NaiveTime) -> bool {
+        match condition {
+            Condition::TimeRange { from, to } if from <= to => *from <= now && now < *to,
+            Condition::TimeRange { from, to } => *from <= now || now < *to,
+            Condition::DoNotDisturb(on) => self.do_not_disturb == *on,
+            Condition::State { key, value } => self.state(key) == Some(value.as_str()),
+        }
+    }
+
+    /// Queue actions that weren't triggered by a rule, e.g. announcements.
+    pub(crate) fn queue(&mut self, actions: Vec<Action>) {
+        self.pending
+            .extend(actions.into_iter().map(|action| (action, 1)));
+    }
+
+    pub(crate) fn next_action(&mut self) -> Option<(Action, usize)> {
+        self.pending.pop_front()
+    }
+
+    pub(crate) fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub(crate) fn state(&self, key: &str) -> Option<&str> {
+        self.state.get(key).map(String::as_str)
+    }
+
+    /// Returns whether the value changed.
+    pub(crate) fn set_state(&mut self, key: &str, value: &str) -> bool {
+        if self.state(key) == Some(value) {
+            return false;
+        }
+        self.state.insert(key.to_string(), value.to_string());
+        true
+    }
+
+    pub(crate) fn do_not_disturb(&self) -> bool {
+        self.do_not_disturb
+    }
+
+    /// Returns whether it changed.
+    pub(crate) fn set_do_not_disturb(&mut self, on: bool) -> bool {
+        std::mem::replace(&mut self.do_not_disturb, on) != on
+    }
+
+    /// Run an action reaching out to the network. Failures are logged, since there is nobody to
+    /// report them to.
+    #[cfg(feature = "home")]
+    pub(crate) fn run_network_action(&mut self, action: &Action) {
+        match action {
+            Action::PublishMqtt {
+                topic,
+                payload,
+                retain,
+            } => {
+                let Some(mqtt) = &mut self.mqtt else {
+                    warn!("Can't publish to {} without an MQTT publisher", topic);
+                    return;
+                };
+                if let Err(e) = mqtt.publish(topic, payload.as_bytes(), *retain) {
+                    warn!("Failed to publish to {}: {:?}", topic, e);
+                }
+            }
+            Action::Http { method, url, body } => {
+                let request = self.http.request(method, url);
+                let result = match body {
+                    Some(body) => request.send_string(body),
+                    None => request.call(),
+                };
+                if let Err(e) = result {
+                    warn!("Failed to {} {}: {}", method, url, e);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Whether an MQTT topic filter, which can contain `+` for one level and `#` for the remaining
+/// levels, matches the topic.
+fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut topic_levels = topic.split('/');
+    for level in filter.split('/') {
+        match (level, topic_levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => (),
+            (level, Some(topic_level)) if level == topic_level => (),
+            _ => return false,
+        }
+    }
+    topic_levels.next().is_none()
+}
@@ -0,0 +1,55 @@
+use std::{
+    panic,
+    sync::{Mutex, OnceLock},
+    thread::{self, JoinHandle},
+};
+
+/// A value loaded on another thread, e.g. a model, so that starting the assistant doesn't wait
+/// for it. Getting the value waits for the thread if it isn't done yet, and panics if it did.
+pub(crate) struct Background<V> {
+    value: OnceLock<V>,
+    loading: Mutex<Option<JoinHandle<V>>>,
+}
+
+impl<V> Background<V> {
+    pub(crate) fn ready(value: V) -> Self {
+        Self {
+            value: OnceLock::from(value),
+            loading: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn spawn(load: impl FnOnce() -> V + Send + 'static) -> Self
+    where
+        V: Send + 'static,
+    {
+        Self {
+            value: OnceLock::new(),
+            loading: Mutex::new(Some(thread::spawn(load))),
+        }
+    }
+
+    /// Whether getting the value doesn't wait.
+    pub(crate) fn is_ready(&self) -> bool {
+        self.value.get().is_some()
+            || self
+                .loading
+                .lock()
+                .unwrap()
+                .as_ref()
+                .is_some_and(JoinHandle::is_finished)
+    }
+
+    pub(crate) fn get(&self) -> &V {
+        self.value.get_or_init(|| {
+            let loading = self.loading.lock().unwrap().take();
+            let loading = loading.expect("Only loaded once");
+            loading.join().unwrap_or_else(|e| panic::resume_unwind(e))
+        })
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut V {
+        self.get();
+        self.value.get_mut().expect("Loaded by get")
+    }
+}
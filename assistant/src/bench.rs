@@ -0,0 +1,365 @@
+#[cfg(feature = "rustpotter")]
+use rustpotter::{Rustpotter, Sample, SampleFormat};
+#[cfg(feature = "rustpotter")]
+use std::fs;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tts::Tts;
+use vosk::{DecodingState, Model, Recognizer};
+
+use crate::tts::TtsError;
+#[cfg(feature = "rustpotter")]
+use crate::wakeword::{detector_config, DetectorSettings};
+
+#[derive(Error, Debug)]
+pub enum BenchError {
+    #[error("Failed to read recording")]
+    ReadRecording(#[from] hound::Error),
+    #[error("Unsupported recording sample format, only i16, i32 and f32 are supported")]
+    UnsupportedSampleFormat,
+    #[error("Recording is empty")]
+    EmptyRecording,
+    #[error("Failed to create Rustpotter")]
+    CreateRustpotter(String),
+    #[error("Failed to add wakeword: {0}")]
+    AddWakeword(String),
+    #[error("Failed to create recognizer")]
+    CreateRecognizer,
+    #[error("Failed to decode audio")]
+    DecodeAudio,
+    #[error("TTS error")]
+    Tts(#[from] TtsError),
+    #[error("The TTS backend didn't start speaking")]
+    TtsNoAudio,
+}
+
+/// WakewordBenchReport contains the results of [bench_wakeword].
+#[cfg(feature = "rustpotter")]
+#[derive(Debug)]
+pub struct WakewordBenchReport {
+    /// Duration of the audio that went through the detector.
+    pub audio_duration: Duration,
+    /// Wall clock time spent processing the audio.
+    pub processing_time: Duration,
+    /// CPU time spent by the benchmarking thread.
+    pub cpu_time: Duration,
+    /// Number of frames given to the detector.
+    pub frames: usize,
+    /// The slowest frame.
+    pub max_frame_time: Duration,
+    /// Names of the detected wakewords with the position in the audio they were detected at.
+    pub detections: Vec<(Duration, String)>,
+    /// Peak resident memory of the process in KiB, if it could be determined.
+    pub peak_memory_kib: Option<u64>,
+}
+
+#[cfg(feature = "rustpotter")]
+impl WakewordBenchReport {
+    /// Processing time divided by audio duration. Values below 1 mean the detector keeps up with
+    /// live audio on this machine.
+    pub fn real_time_factor(&self) -> f64 {
+        self.processing_time.as_secs_f64() / self.audio_duration.as_secs_f64()
+    }
+
+    pub fn cpu_time_per_frame(&self) -> Duration {
+        self.cpu_time / self.frames.max(1) as u32
+    }
+}
+
+/// Run the wakeword detector on a WAV recording as fast as possible, looping the recording until
+/// `duration` of audio has been processed. Wakewords are given as `(name, path)` pairs of
+/// Rustpotter wakeword files. The detector is configured like the one of the assistant with the
+/// same [DetectorSettings].
+#[cfg(feature = "rustpotter")]
+pub fn bench_wakeword(
+    wakewords: &[(&str, &str)],
+    settings: &DetectorSettings,
+    recording: &str,
+    duration: Duration,
+) -> Result<WakewordBenchReport, BenchError> {
+    let mut reader = hound::WavReader::open(recording)?;
+    let spec = reader.spec();
+    let samples_per_second = spec.sample_rate as usize * spec.channels as usize;
+    let total_samples = (duration.as_secs_f64() * samples_per_second as f64) as usize;
+
+    match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Int, 16) => run_bench(
+            &mut new_detector(wakewords, settings, &spec, SampleFormat::I16)?,
+            reader.samples::<i16>().collect::<Result<_, _>>()?,
+            total_samples,
+            samples_per_second,
+        ),
+        (hound::SampleFormat::Int, 32) => run_bench(
+            &mut new_detector(wakewords, settings, &spec, SampleFormat::I32)?,
+            reader.samples::<i32>().collect::<Result<_, _>>()?,
+            total_samples,
+            samples_per_second,
+        ),
+        (hound::SampleFormat::Float, 32) => run_bench(
+            &mut new_detector(wakewords, settings, &spec, SampleFormat::F32)?,
+            reader.samples::<f32>().collect::<Result<_, _>>()?,
+            total_samples,
+            samples_per_second,
+        ),
+        _ => Err(BenchError::UnsupportedSampleFormat),
+    }
+}
+
+#[cfg(feature = "rustpotter")]
+fn new_detector(
+    wakewords: &[(&str, &str)],
+    settings: &DetectorSettings,
+    spec: &hound::WavSpec,
+    sample_format: SampleFormat,
+) -> Result<Rustpotter, BenchError> {
+    let config = detector_config(
+        spec.sample_rate as usize,
+        spec.channels,
+        sample_format,
+        settings,
+    );
+    let mut rustpotter = Rustpotter::new(&config).map_err(BenchError::CreateRustpotter)?;
+    for (name, path) in wakewords {
+        rustpotter
+            .add_wakeword_from_file(name, path)
+            .map_err(BenchError::AddWakeword)?;
+    }
+    Ok(rustpotter)
+}
+
+#[cfg(feature = "rustpotter")]
+fn run_bench<S: Sample>(
+    rustpotter: &mut Rustpotter,
+    recording: Vec<S>,
+    total_samples: usize,
+    samples_per_second: usize,
+) -> Result<WakewordBenchReport, BenchError> {
+    if recording.is_empty() {
+        return Err(BenchError::EmptyRecording);
+    }
+
+    let samples_per_frame = rustpotter.get_samples_per_frame();
+    let mut looped = recording.iter().copied().cycle();
+    let mut frame = Vec::with_capacity(samples_per_frame);
+    let mut processed = 0;
+    let mut frames = 0;
+    let mut max_frame_time = Duration::ZERO;
+    let mut detections = Vec::new();
+
+    let cpu_start = thread_cpu_time();
+    let start = Instant::now();
+    while processed + samples_per_frame <= total_samples {
+        frame.clear();
+        frame.extend(looped.by_ref().take(samples_per_frame));
+
+        let frame_start = Instant::now();
+        let detection = rustpotter.process_samples(frame.clone());
+        max_frame_time = max_frame_time.max(frame_start.elapsed());
+
+        processed += samples_per_frame;
+        frames += 1;
+        if let Some(detection) = detection {
+            detections.push((
+                Duration::from_secs_f64(processed as f64 / samples_per_second as f64),
+                detection.name,
+            ));
+        }
+    }
+    let processing_time = start.elapsed();
+    let cpu_time = thread_cpu_time().saturating_sub(cpu_start);
+
+    Ok(WakewordBenchReport {
+        audio_duration: Duration::from_secs_f64(processed as f64 / samples_per_second as f64),
+        processing_time,
+        cpu_time,
+        frames,
+        max_frame_time,
+        detections,
+        peak_memory_kib: peak_memory_kib(),
+    })
+}
+
+/// SttBenchReport contains the results of [bench_stt].
+#[derive(Debug)]
+pub struct SttBenchReport {
+    /// Duration of the recording.
+    pub audio_duration: Duration,
+    /// Wall clock time spent recognizing the recording.
+    pub processing_time: Duration,
+    /// Everything the recognizer transcribed.
+    pub transcript: String,
+    /// Number of words in the reference text.
+    pub reference_words: usize,
+    /// Minimum number of word substitutions, deletions and insertions needed to turn the
+    /// transcript into the reference.
+    pub word_errors: usize,
+}
+
+impl SttBenchReport {
+    /// Word error rate of the transcript against the reference text.
+    pub fn word_error_rate(&self) -> f64 {
+        self.word_errors as f64 / self.reference_words.max(1) as f64
+    }
+
+    /// Processing time divided by audio duration.
+    pub fn real_time_factor(&self) -> f64 {
+        self.processing_time.as_secs_f64() / self.audio_duration.as_secs_f64()
+    }
+}
+
+/// Transcribe a 16 bit WAV recording with the given model and compare the result with the
+/// expected text. Multi-channel recordings are mixed down to mono.
+pub fn bench_stt(
+    model: &Model,
+    recording: &str,
+    reference: &str,
+) -> Result<SttBenchReport, BenchError> {
+    let mut reader = hound::WavReader::open(recording)?;
+    let spec = reader.spec();
+    if spec.sample_format != hound::SampleFormat::Int || spec.bits_per_sample != 16 {
+        return Err(BenchError::UnsupportedSampleFormat);
+    }
+
+    let channels = spec.channels as usize;
+    let samples = reader.samples::<i16>().collect::<Result<Vec<_>, _>>()?;
+    let samples: Vec<i16> = samples
+        .chunks(channels)
+        .map(|frame| (frame.iter().map(|&s| s as i32).sum::<i32>() / frame.len() as i32) as i16)
+        .collect();
+    if samples.is_empty() {
+        return Err(BenchError::EmptyRecording);
+    }
+
+    let mut recognizer =
+        Recognizer::new(model, spec.sample_rate as f32).ok_or(BenchError::CreateRecognizer)?;
+    let mut sentences = Vec::new();
+
+    let start = Instant::now();
+    // Feed the recognizer 100 ms at a time, like a live stream would
+    for chunk in samples.chunks(spec.sample_rate as usize / 10) {
+        if let DecodingState::Finalized = recognizer
+            .accept_waveform(chunk)
+            .map_err(|_| BenchError::DecodeAudio)?
+        {
+            if let Some(result) = recognizer.result().single() {
+                sentences.push(result.text.to_string());
+            }
+        }
+    }
+    if let Some(result) = recognizer.final_result().single() {
+        sentences.push(result.text.to_string());
+    }
+    let processing_time = start.elapsed();
+
+    let transcript = sentences
+        .iter()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let reference_words = normalize_words(reference);
+
+    Ok(SttBenchReport {
+        audio_duration: Duration::from_secs_f64(samples.len() as f64 / spec.sample_rate as f64),
+        processing_time,
+        word_errors: word_edit_distance(&reference_words, &normalize_words(&transcript)),
+        reference_words: reference_words.len(),
+        transcript,
+    })
+}
+
+/// How long the TTS backend took to speak a phrase, see [bench_tts].
+#[derive(Debug)]
+pub struct TtsLatency {
+    pub phrase: String,
+    /// Time from the speak call until the backend reported speaking.
+    pub time_to_first_audio: Duration,
+    /// Time from the speak call until the backend finished speaking.
+    pub total_time: Duration,
+}
+
+/// Speak every phrase `repeats` times and measure the latency of the TTS backend. The phrases
+/// are spoken as given, without normalization, so that different backends get the same text.
+pub fn bench_tts(
+    tts: &mut Tts,
+    phrases: &[&str],
+    repeats: usize,
+) -> Result<Vec<TtsLatency>, BenchError> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(1);
+    const START_TIMEOUT: Duration = Duration::from_secs(10);
+
+    let mut results = Vec::with_capacity(phrases.len() * repeats);
+    for phrase in phrases {
+        for _ in 0..repeats {
+            let start = Instant::now();
+            tts.speak(*phrase, true)?;
+            while !tts.is_speaking()? {
+                if start.elapsed() > START_TIMEOUT {
+                    return Err(BenchError::TtsNoAudio);
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            let time_to_first_audio = start.elapsed();
+            while tts.is_speaking()? {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            results.push(TtsLatency {
+                phrase: phrase.to_string(),
+                time_to_first_audio,
+                total_time: start.elapsed(),
+            });
+        }
+    }
+    Ok(results)
+}
+
+fn normalize_words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric() || *c == '\'')
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+fn word_edit_distance(reference: &[String], hypothesis: &[String]) -> usize {
+    let mut previous: Vec<usize> = (0..=hypothesis.len()).collect();
+    for (i, reference_word) in reference.iter().enumerate() {
+        let mut current = vec![i + 1; hypothesis.len() + 1];
+        for (j, hypothesis_word) in hypothesis.iter().enumerate() {
+            let substitution = previous[j] + usize::from(reference_word != hypothesis_word);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[hypothesis.len()]
+}
+
+#[cfg(feature = "rustpotter")]
+fn thread_cpu_time() -> Duration {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `time` is a valid timespec for clock_gettime to write to.
+    if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) } != 0 {
+        return Duration::ZERO;
+    }
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+#[cfg(feature = "rustpotter")]
+fn peak_memory_kib() -> Option<u64> {
+    fs::read_to_string("/proc/self/status")
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()
+}
@@ -0,0 +1,334 @@
+use chrono::{Datelike, NaiveDate};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+use crate::{
+    clock::describe_day,
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{Slot, SlotKind, SlotValue},
+    AssistantQuery,
+};
+
+#[derive(Error, Debug)]
+pub enum DatesError {
+    #[error("Failed to read the dates")]
+    Read(#[from] std::io::Error),
+    #[error("Invalid date on line {0}")]
+    Invalid(usize),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Occasion {
+    Birthday,
+    Anniversary,
+}
+
+/// A birthday or an anniversary, celebrated every year.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportantDate {
+    /// Who it is of, like "Ada" or "Sam and Alex".
+    pub name: String,
+    pub occasion: Occasion,
+    pub month: u32,
+    pub day: u32,
+    /// The year it started, if known, to say how many years it is.
+    pub year: Option<i32>,
+}
+
+impl ImportantDate {
+    /// The next day it is celebrated, `today` included. February 29 is celebrated on February
+    /// 28 in the other years.
+    fn next(&self, today: NaiveDate) -> Option<NaiveDate> {
+        let on = |year: i32| {
+            NaiveDate::from_ymd_opt(year, self.month, self.day)
+                .or_else(|| NaiveDate::from_ymd_opt(year, self.month, self.day - 1))
+        };
+        on(today.year())
+            .filter(|date| *date >= today)
+            .or_else(|| on(today.year() + 1))
+    }
+
+    /// What is said of it, e.g. "Ada turns 36 tomorrow." or "Sam and Alex's anniversary is on
+    /// Friday."
+    fn describe(&self, date: NaiveDate, today: NaiveDate) -> String {
+        let day = describe_day(date, today);
+        let years = self
+            .year
+            .map(|year| date.year() - year)
+            .filter(|years| *years > 0);
+        match (self.occasion, years) {
+            (Occasion::Birthday, Some(years)) => format!("{} turns {} {}.", self.name, years, day),
+            (Occasion::Birthday, None) => format!("{}'s birthday is {}.", self.name, day),
+            (Occasion::Anniversary, Some(years)) => {
+                format!("{}'s {} anniversary is {}.", self.name, ordinal(years), day)
+            }
+            (Occasion::Anniversary, None) => format!("{}'s anniversary is {}.", self.name, day),
+        }
+    }
+}
+
+fn ordinal(number: i32) -> String {
+    let suffix = match (number % 10, number % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    };
+    format!("{}{}", number, suffix)
+}
+
+/// A date like `1990-05-03`, `05-03` or `--05-03`, without the year if it isn't given.
+fn parse_date(text: &str) -> Option<(Option<i32>, u32, u32)> {
+    let digits: String = text
+        .trim_start_matches('-')
+        .chars()
+        .filter(|c| *c != '-')
+        .collect();
+    let (year, month_day) = match digits.len() {
+        8 => (Some(digits.get(..4)?.parse().ok()?), digits.get(4..)?),
+        4 => (None, digits.as_str()),
+        _ => return None,
+    };
+    let month = month_day.get(..2)?.parse().ok()?;
+    let day = month_day.get(2..)?.parse().ok()?;
+    // Checked on a leap year, so February 29 is valid
+    NaiveDate::from_ymd_opt(2000, month, day)?;
+    Some((year, month, day))
+}
+
+/// The dates of a text file, one per line like `1990-05-03 Ada` or `05-03 Ada` without the
+/// year, and `anniversary 2015-06-20 Sam and Alex` for anniversaries. Empty lines and lines
+/// starting with `#` are skipped.
+pub fn parse_dates(text: &str) -> Result<Vec<ImportantDate>, DatesError> {
+    let mut dates = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (occasion, line) = match line.strip_prefix("anniversary ") {
+            Some(line) => (Occasion::Anniversary, line.trim_start()),
+            None => (Occasion::Birthday, line),
+        };
+        let (date, name) = line.split_once(' ').ok_or(DatesError::Invalid(index + 1))?;
+        let (year, month, day) = parse_date(date).ok_or(DatesError::Invalid(index + 1))?;
+        dates.push(ImportantDate {
+            name: name.trim().to_string(),
+            occasion,
+            month,
+            day,
+            year,
+        });
+    }
+    Ok(dates)
+}
+
+/// The birthdays and anniversaries of the contacts of a vCard file, e.g. exported from a phone
+/// or a CardDAV server, from their `BDAY` and `ANNIVERSARY`. Contacts without are skipped.
+pub fn parse_vcards(text: &str) -> Vec<ImportantDate> {
+    // Long lines are folded onto the next ones, starting with a space
+    let text = text
+        .replace("\r\n", "\n")
+        .replace("\n ", "")
+        .replace("\n\t", "");
+    let mut dates = Vec::new();
+    let mut name = None;
+    let mut contact = Vec::new();
+    for line in text.lines() {
+        let Some((property, value)) = line.split_once(':') else {
+            continue;
+        };
+        // Without its parameters, like BDAY;VALUE=date
+        let property = property.split(';').next().unwrap_or_default();
+        match property.to_ascii_uppercase().as_str() {
+            "BEGIN" => {
+                name = None;
+                contact.clear();
+            }
+            "FN" => name = Some(value.trim().replace("\\,", ",")),
+            "BDAY" => contact.push((Occasion::Birthday, value.to_string())),
+            "ANNIVERSARY" => contact.push((Occasion::Anniversary, value.to_string())),
+            "END" => {
+                let Some(name) = name.take() else { continue };
+                for (occasion, value) in contact.drain(..) {
+                    // Without its time, like 19900503T000000
+                    let date = value.split('T').next().unwrap_or_default();
+                    match parse_date(date.trim()) {
+                        Some((year, month, day)) => dates.push(ImportantDate {
+                            name: name.clone(),
+                            occasion,
+                            month,
+                            day,
+                            year,
+                        }),
+                        None => warn!("Ignoring the invalid date {} of {}", value, name),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+    dates
+}
+
+/// The dates of the file, a vCard file if its extension is `.vcf`, see [parse_vcards], or a
+/// text file, see [parse_dates].
+pub fn load_dates(path: &Path) -> Result<Vec<ImportantDate>, DatesError> {
+    let text = fs::read_to_string(path)?;
+    let is_vcard = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("vcf"));
+    if is_vcard {
+        Ok(parse_vcards(&text))
+    } else {
+        parse_dates(&text)
+    }
+}
+
+/// What is celebrated today, e.g. for a daily briefing, `None` if nothing is.
+pub fn celebrated_today(dates: &[ImportantDate], today: NaiveDate) -> Option<String> {
+    let today_dates: Vec<String> = dates
+        .iter()
+        .filter(|date| date.next(today) == Some(today))
+        .map(|date| date.describe(today, today))
+        .collect();
+    (!today_dates.is_empty()).then(|| today_dates.join(" "))
+}
+
+const UPCOMING_INTENT: &str = "upcoming birthdays";
+const DATE_INTENT: &str = "birthday of";
+/// At most this many dates are said of the upcoming ones.
+const MAX_UPCOMING: usize = 3;
+
+/// A [Skill] answering "whose birthday is coming up" with the next birthdays and anniversaries,
+/// and "when is Ada's birthday". The dates are read from a file at every question, so it can be
+/// edited while the assistant runs, see [load_dates]. See [celebrated_today] for the dates of
+/// the day in a briefing.
+pub struct BirthdaySkill {
+    path: PathBuf,
+    days: i64,
+}
+
+impl BirthdaySkill {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            days: 30,
+        }
+    }
+
+    /// How many days ahead upcoming dates are said. 30 by default.
+    pub fn set_days(&mut self, days: u32) {
+        self.days = days.into();
+    }
+
+    fn upcoming(&self, dates: &[ImportantDate], today: NaiveDate) -> String {
+        let mut upcoming: Vec<(NaiveDate, &ImportantDate)> = dates
+            .iter()
+            .filter_map(|date| Some((date.next(today)?, date)))
+            .filter(|(next, _)| (*next - today).num_days() < self.days)
+            .collect();
+        upcoming.sort_by_key(|(next, _)| *next);
+        if upcoming.is_empty() {
+            return format!(
+                "There are no birthdays or anniversaries in the next {} days.",
+                self.days
+            );
+        }
+        upcoming
+            .iter()
+            .take(MAX_UPCOMING)
+            .map(|(next, date)| date.describe(*next, today))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// How a name is said before "birthday", like "Ada's".
+fn possessive(name: &str) -> String {
+    format!("{}'s", name)
+}
+
+impl Skill for BirthdaySkill {
+    fn name(&self) -> &str {
+        "birthdays"
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        let mut names: Vec<String> = match load_dates(&self.path) {
+            Ok(dates) => dates.iter().map(|date| possessive(&date.name)).collect(),
+            Err(e) => {
+                warn!("Failed to read the birthdays: {}", e);
+                Vec::new()
+            }
+        };
+        names.sort();
+        names.dedup();
+        let mut intents = vec![IntentSpec::new(
+            UPCOMING_INTENT,
+            examples(&[
+                "whose birthday is coming up",
+                "whose birthday is today",
+                "are there any birthdays coming up",
+                "are there any anniversaries coming up",
+            ]),
+        )];
+        if !names.is_empty() {
+            intents.push(IntentSpec::with_slots(
+                DATE_INTENT,
+                examples(&["when is {person} birthday", "when is {person} anniversary"]),
+                vec![Slot::new("person", SlotKind::Entity(names))],
+            ));
+        }
+        intents
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        let dates = match load_dates(&self.path) {
+            Ok(dates) => dates,
+            Err(e) => {
+                warn!("Failed to read the birthdays: {}", e);
+                _ = ctx.speak("Sorry, I couldn't read the birthdays.");
+                return;
+            }
+        };
+        let today = ctx.clock().local_now().date_naive();
+        let response = match query.intent {
+            Some(UPCOMING_INTENT) => self.upcoming(&dates, today),
+            Some(DATE_INTENT) => {
+                let Some(SlotValue::Entity(person)) = query.slots.get("person") else {
+                    _ = ctx.speak("Whose birthday?");
+                    return;
+                };
+                let occasion = match &query.text {
+                    Some(text) if text.contains("anniversary") => Occasion::Anniversary,
+                    _ => Occasion::Birthday,
+                };
+                let date = dates
+                    .iter()
+                    .find(|date| possessive(&date.name) == *person && date.occasion == occasion);
+                match date.and_then(|date| Some((date.next(today)?, date))) {
+                    Some((next, date)) => date.describe(next, today),
+                    None => format!(
+                        "I don't know when {} is.",
+                        describe_occasion(person, occasion)
+                    ),
+                }
+            }
+            _ => return,
+        };
+        _ = ctx.speak(response);
+    }
+}
+
+fn describe_occasion(person: &str, occasion: Occasion) -> String {
+    match occasion {
+        Occasion::Birthday => format!("{} birthday", person),
+        Occasion::Anniversary => format!("{} anniversary", person),
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, UdpSocket},
+    time::Duration,
+};
+use thiserror::Error;
+
+use crate::{
+    permissions::Capability,
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{Slot, SlotKind, SlotValue},
+    AssistantQuery,
+};
+
+#[derive(Error, Debug)]
+pub enum BridgeError {
+    #[error("Failed to reach the bridge")]
+    Io(#[from] io::Error),
+    #[error("The Modbus device answered with exception code {0}")]
+    ModbusException(u8),
+    #[error("Invalid address {0}")]
+    InvalidAddress(String),
+}
+
+/// A home automation bus that switches and dims devices by address, see [KnxRouting] and
+/// [ModbusTcp].
+pub trait Bridge: Send {
+    fn switch(&mut self, address: u16, on: bool) -> Result<(), BridgeError>;
+
+    /// Set the level of a dimmable device, from 0 to 100 percent.
+    fn dim(&mut self, address: u16, percent: u8) -> Result<(), BridgeError>;
+}
+
+/// The multicast address and port KNX IP routers listen on.
+pub const KNX_ROUTING_ADDRESS: &str = "224.0.23.12:3671";
+
+/// Writes group values to a KNX installation through KNXnet/IP routing, which needs no
+/// connection: the addresses are group addresses, like `1/2/3`, see [parse_group_address].
+/// Switching writes a 1-bit value (DPT 1.001) and dimming a scaled 1-byte value (DPT 5.001).
+pub struct KnxRouting {
+    address: String,
+    source: u16,
+    socket: Option<UdpSocket>,
+}
+
+impl KnxRouting {
+    /// `address` is where the telegrams are sent, [KNX_ROUTING_ADDRESS] for the routers of the
+    /// network, and `source` the individual address they are sent from, see
+    /// [parse_individual_address].
+    pub fn new(address: impl Into<String>, source: u16) -> Self {
+        Self {
+            address: address.into(),
+            source,
+            socket: None,
+        }
+    }
+
+    /// Send an A_GroupValue_Write with the APCI and data after the TPCI byte: values of up to 6
+    /// bits are packed into the APCI byte, bigger ones follow it.
+    fn write_group_value(&mut self, group: u16, apdu: &[u8]) -> Result<(), BridgeError> {
+        // cEMI L_Data.ind without additional info, standard frame, group destination, hop count 6
+        let mut cemi = vec![0x29, 0, 0xbc, 0xe0];
+        cemi.extend_from_slice(&self.source.to_be_bytes());
+        cemi.extend_from_slice(&group.to_be_bytes());
+        cemi.extend_from_slice(&[apdu.len() as u8, 0]);
+        cemi.extend_from_slice(apdu);
+        // KNXnet/IP header of a ROUTING_INDICATION
+        let mut packet = vec![0x06, 0x10, 0x05, 0x30];
+        packet.extend_from_slice(&(6 + cemi.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&cemi);
+
+        let socket = match &self.socket {
+            Some(socket) => socket,
+            None => self.socket.insert(UdpSocket::bind("0.0.0.0:0")?),
+        };
+        socket.send_to(&packet, &self.address)?;
+        Ok(())
+    }
+}
+
+impl Bridge for KnxRouting {
+    fn switch(&mut self, address: u16, on: bool) -> Result<(), BridgeError> {
+        self.write_group_value(address, &[0x80 | u8::from(on)])
+    }
+
+    fn dim(&mut self, address: u16, percent: u8) -> Result<(), BridgeError> {
+        let value = (f32::from(percent.min(100)) * 2.55).round() as u8;
+        self.write_group_value(address, &[0x80, value])
+    }
+}
+
+/// Parse a KNX group address in the three level notation, like `1/2/3`, into its 16 bits.
+pub fn parse_group_address(address: &str) -> Result<u16, BridgeError> {
+    let invalid = || BridgeError::InvalidAddress(address.to_string());
+    let parts: Vec<u16> = address
+        .split('/')
+        .map(|part| part.trim().parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    match parts[..] {
+        [main, middle, sub] if main < 32 && middle < 8 && sub < 256 => {
+            Ok(main << 11 | middle << 8 | sub)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Parse a KNX individual address, like `1.1.250`, into its 16 bits.
+pub fn parse_individual_address(address: &str) -> Result<u16, BridgeError> {
+    let invalid = || BridgeError::InvalidAddress(address.to_string());
+    let parts: Vec<u16> = address
+        .split('.')
+        .map(|part| part.trim().parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    match parts[..] {
+        [area, line, device] if area < 16 && line < 16 && device < 256 => {
+            Ok(area << 12 | line << 8 | device)
+        }
+        _ => Err(invalid()),
+    }
+}
+
+/// Writes to a Modbus TCP device, like a PLC or a relay board: switching writes a coil and
+/// dimming writes the percentage to a holding register. Connects on the first write and
+/// reconnects when the connection was lost.
+pub struct ModbusTcp {
+    address: String,
+    unit: u8,
+    transaction: u16,
+    stream: Option<TcpStream>,
+}
+
+impl ModbusTcp {
+    /// `address` is the host and port of the device, like `192.168.1.20:502`, and `unit` the
+    /// unit identifier it answers to, usually 1.
+    pub fn new(address: impl Into<String>, unit: u8) -> Self {
+        Self {
+            address: address.into(),
+            unit,
+            transaction: 0,
+            stream: None,
+        }
+    }
+
+    fn write(&mut self, function: u8, address: u16, value: u16) -> Result<(), BridgeError> {
+        self.transaction = self.transaction.wrapping_add(1);
+        let mut request = Vec::with_capacity(12);
+        request.extend_from_slice(&self.transaction.to_be_bytes());
+        // Protocol 0, then the length of the unit and the PDU
+        request.extend_from_slice(&[0, 0, 0, 6, self.unit, function]);
+        request.extend_from_slice(&address.to_be_bytes());
+        request.extend_from_slice(&value.to_be_bytes());
+
+        // A connection the device closed is only noticed when using it, so retry once
+        for attempt in 0..2 {
+            match self.exchange(&request) {
+                Ok(response) => {
+                    return match response[..] {
+                        [code, exception, ..] if code == function | 0x80 => {
+                            Err(BridgeError::ModbusException(exception))
+                        }
+                        _ => Ok(()),
+                    }
+                }
+                Err(e) if attempt == 1 => return Err(e.into()),
+                Err(_) => self.stream = None,
+            }
+        }
+        unreachable!("The second attempt always returns")
+    }
+
+    /// Send the request and return the PDU of the response.
+    fn exchange(&mut self, request: &[u8]) -> io::Result<Vec<u8>> {
+        let stream = match &mut self.stream {
+            Some(stream) => stream,
+            None => {
+                let stream = TcpStream::connect(&self.address)?;
+                stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+                self.stream.insert(stream)
+            }
+        };
+        stream.write_all(request)?;
+        let mut header = [0; 7];
+        stream.read_exact(&mut header)?;
+        let length = usize::from(u16::from_be_bytes([header[4], header[5]]));
+        let mut pdu = vec![0; length.saturating_sub(1)];
+        stream.read_exact(&mut pdu)?;
+        Ok(pdu)
+    }
+}
+
+impl Bridge for ModbusTcp {
+    fn switch(&mut self, address: u16, on: bool) -> Result<(), BridgeError> {
+        // Write Single Coil
+        self.write(0x05, address, if on { 0xff00 } else { 0 })
+    }
+
+    fn dim(&mut self, address: u16, percent: u8) -> Result<(), BridgeError> {
+        // Write Single Register
+        self.write(0x06, address, u16::from(percent.min(100)))
+    }
+}
+
+/// A device of a [BridgeSkill], by the name it is called by.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BridgeDevice {
+    pub name: String,
+    /// Where to switch the device on and off.
+    pub switch: u16,
+    /// Where to set its level, `None` if it can't be dimmed.
+    pub dim: Option<u16>,
+}
+
+const TURN_ON_INTENT: &str = "turn on device";
+const TURN_OFF_INTENT: &str = "turn off device";
+const DIM_INTENT: &str = "dim device";
+
+/// A [Skill] to turn the devices of a KNX or Modbus installation on and off and dim them, with
+/// their names in the examples of its intents, e.g. "turn on the kitchen light" or "dim the
+/// living room to forty percent". Enabled with the `bridge` feature.
+pub struct BridgeSkill {
+    bridge: Box<dyn Bridge>,
+    devices: Vec<BridgeDevice>,
+}
+
+impl BridgeSkill {
+    pub fn new(bridge: impl Bridge + 'static, devices: Vec<BridgeDevice>) -> Self {
+        Self {
+            bridge: Box::new(bridge),
+            devices,
+        }
+    }
+
+    fn device(&self, query: &AssistantQuery<'_, str>) -> Option<&BridgeDevice> {
+        let Some(SlotValue::Entity(name)) = query.slots.get("device") else {
+            return None;
+        };
+        self.devices.iter().find(|device| device.name == *name)
+    }
+}
+
+impl Skill for BridgeSkill {
+    fn name(&self) -> &str {
+        "bridge"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak, Capability::Network]
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        if self.devices.is_empty() {
+            return Vec::new();
+        }
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        let names = |dimmable: bool| {
+            self.devices
+                .iter()
+                .filter(|device| !dimmable || device.dim.is_some())
+                .map(|device| device.name.clone())
+                .collect()
+        };
+        let device = Slot::new("device", SlotKind::Entity(names(false)));
+        let mut intents = vec![
+            IntentSpec::with_slots(
+                TURN_ON_INTENT,
+                examples(&[
+                    "turn on the {device}",
+                    "switch on the {device}",
+                    "turn the {device} on",
+                ]),
+                vec![device.clone()],
+            ),
+            IntentSpec::with_slots(
+                TURN_OFF_INTENT,
+                examples(&[
+                    "turn off the {device}",
+                    "switch off the {device}",
+                    "turn the {device} off",
+                ]),
+                vec![device],
+            ),
+        ];
+        let dimmable: Vec<String> = names(true);
+        if !dimmable.is_empty() {
+            intents.push(IntentSpec::with_slots(
+                DIM_INTENT,
+                examples(&[
+                    "dim the {device} to {level} percent",
+                    "set the {device} to {level} percent",
+                    "turn the {device} to {level} percent",
+                ]),
+                vec![
+                    Slot::new("device", SlotKind::Entity(dimmable)),
+                    Slot::new("level", SlotKind::Number),
+                ],
+            ));
+        }
+        intents
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        let Some(device) = self.device(query).cloned() else {
+            _ = ctx.speak("Which device?");
+            return;
+        };
+        let result = match query.intent {
+            Some(intent @ (TURN_ON_INTENT | TURN_OFF_INTENT)) => {
+                let on = intent == TURN_ON_INTENT;
+                self.bridge.switch(device.switch, on).map(|_| {
+                    format!(
+                        "Turned {} the {}.",
+                        if on { "on" } else { "off" },
+                        device.name
+                    )
+                })
+            }
+            Some(DIM_INTENT) => match (device.dim, query.slots.get("level")) {
+                (Some(address), Some(SlotValue::Number(level))) => {
+                    let percent = level.clamp(0., 100.).round() as u8;
+                    self.bridge
+                        .dim(address, percent)
+                        .map(|_| format!("The {} is at {} percent.", device.name, percent))
+                }
+                (Some(_), _) => Ok("To what level?".to_string()),
+                (None, _) => Ok(format!("The {} can't be dimmed.", device.name)),
+            },
+            _ => return,
+        };
+        let response = result.unwrap_or_else(|e| {
+            warn!("Failed to control {}: {}", device.name, e);
+            format!("Sorry, I couldn't reach the {}.", device.name)
+        });
+        _ = ctx.speak(response);
+    }
+}
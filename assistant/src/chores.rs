@@ -0,0 +1,358 @@
+use chrono::{DateTime, Days, Local, NaiveDate, NaiveTime};
+
+use crate::{
+    clock::describe_day,
+    permissions::Capability,
+    schedule::{Repeat, Schedule, ScheduleKind},
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{Slot, SlotKind, SlotValue},
+    storage::{MemoryStorage, Storage, StorageError},
+    AssistantQuery,
+};
+
+/// The [Storage] namespace of when each chore was last done, by its name.
+const CHORES_NAMESPACE: &str = "chores";
+
+/// A chore done every few days, like watering the plants.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chore {
+    /// What to do, like "water the plants", said in the reminders.
+    pub name: String,
+    /// How it is said once done, like "watered the plants" for "I watered the plants".
+    pub done: String,
+    /// Every how many days it is done.
+    pub days: u32,
+    /// When it is reminded of on the days it is due.
+    pub time: NaiveTime,
+    /// Who take turns doing it, in order. Nobody in particular without any.
+    pub people: Vec<String>,
+}
+
+impl Chore {
+    /// The day it is due, `None` if it was never done.
+    fn due(&self, state: &ChoreState) -> Option<NaiveDate> {
+        let last_done = state.last_done?.date_naive();
+        Some(
+            last_done
+                .checked_add_days(Days::new(self.days.into()))
+                .unwrap_or(last_done),
+        )
+    }
+
+    /// Whose turn it is, if it rotates.
+    fn turn(&self, state: &ChoreState) -> Option<&str> {
+        self.people
+            .get(state.turn % self.people.len().max(1))
+            .map(String::as_str)
+    }
+
+    /// The label of the reminder, said as "This is your reminder to ...".
+    fn label(&self, state: &ChoreState) -> String {
+        match self.turn(state) {
+            Some(person) => format!("{}, it's {}'s turn", self.name, person),
+            None => self.name.clone(),
+        }
+    }
+
+    /// When to remind of it next: at the time of the reminder on the day it is due, or the next
+    /// time of the reminder if it is already due.
+    fn next_reminder(&self, state: &ChoreState, now: DateTime<Local>) -> Option<DateTime<Local>> {
+        let today = now.date_naive();
+        let day = self.due(state).filter(|due| *due > today).unwrap_or(today);
+        let at = |day: NaiveDate| day.and_time(self.time).and_local_timezone(Local).earliest();
+        match at(day)? {
+            reminder if reminder > now => Some(reminder),
+            _ => at(day.checked_add_days(Days::new(1))?),
+        }
+    }
+}
+
+/// What is kept in the storage of each chore.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct ChoreState {
+    last_done: Option<DateTime<Local>>,
+    /// The id of its reminder in the [Schedule].
+    reminder: Option<u64>,
+    /// Who does it next, among the people of the chore.
+    turn: usize,
+}
+
+impl ChoreState {
+    fn load(storage: &dyn Storage, chore: &Chore) -> Result<Self, StorageError> {
+        let Some(value) = storage.get(CHORES_NAMESPACE, &chore.name)? else {
+            return Ok(Self::default());
+        };
+        let value = String::from_utf8_lossy(&value);
+        match Self::decode(&value) {
+            Some(state) => Ok(state),
+            None => {
+                warn!("Ignoring the invalid state of the chore {}", chore.name);
+                Ok(Self::default())
+            }
+        }
+    }
+
+    fn save(&self, storage: &dyn Storage, chore: &Chore) -> Result<(), StorageError> {
+        storage.set(CHORES_NAMESPACE, &chore.name, self.encode().as_bytes())
+    }
+
+    fn encode(&self) -> String {
+        format!(
+            "{}\t{}\t{}",
+            self.last_done
+                .map(|last_done| last_done.to_rfc3339())
+                .unwrap_or_default(),
+            self.reminder
+                .map(|reminder| reminder.to_string())
+                .unwrap_or_default(),
+            self.turn
+        )
+    }
+
+    fn decode(value: &str) -> Option<Self> {
+        let mut fields = value.splitn(3, '\t');
+        let last_done = match fields.next()? {
+            "" => None,
+            last_done => Some(
+                DateTime::parse_from_rfc3339(last_done)
+                    .ok()?
+                    .with_timezone(&Local),
+            ),
+        };
+        let reminder = match fields.next()? {
+            "" => None,
+            reminder => Some(reminder.parse().ok()?),
+        };
+        Some(Self {
+            last_done,
+            reminder,
+            turn: fields.next()?.parse().ok()?,
+        })
+    }
+
+    /// Whether its reminder is still in the schedule. The ids of cancelled items can be reused,
+    /// so the label is compared too.
+    fn has_reminder(&self, schedule: &Schedule, chore: &Chore) -> bool {
+        self.reminder.is_some_and(|id| {
+            schedule.items().iter().any(|item| {
+                item.id == id
+                    && item.kind == ScheduleKind::Reminder
+                    && item.label.starts_with(&chore.name)
+            })
+        })
+    }
+
+    /// Replace its reminder with one for the next time it is due. Repeated every day until the
+    /// chore is done.
+    fn reschedule(
+        &mut self,
+        schedule: &mut Schedule,
+        chore: &Chore,
+        now: DateTime<Local>,
+    ) -> Result<(), StorageError> {
+        if self.has_reminder(schedule, chore) {
+            if let Some(id) = self.reminder {
+                schedule.cancel(id)?;
+            }
+        }
+        self.reminder = match chore.next_reminder(self, now) {
+            Some(due) => Some(schedule.add(
+                ScheduleKind::Reminder,
+                due,
+                Some(Repeat::Daily),
+                chore.label(self),
+            )?),
+            None => None,
+        };
+        Ok(())
+    }
+}
+
+/// Schedule the reminders of the chores that have none, e.g. when the assistant starts, and
+/// cancel the ones of chores that were removed. Chores that were never done are reminded of at
+/// their next time. The [ChoreSkill] reschedules them once they are done.
+pub fn schedule_reminders(
+    chores: &[Chore],
+    schedule: &mut Schedule,
+    storage: &dyn Storage,
+    now: DateTime<Local>,
+) -> Result<(), StorageError> {
+    for name in storage.keys(CHORES_NAMESPACE)? {
+        if chores.iter().any(|chore| chore.name == name) {
+            continue;
+        }
+        let value = storage.get(CHORES_NAMESPACE, &name)?.unwrap_or_default();
+        if let Some(id) = ChoreState::decode(&String::from_utf8_lossy(&value))
+            .and_then(|state| state.reminder)
+            .filter(|id| {
+                schedule
+                    .items()
+                    .iter()
+                    .any(|item| item.id == *id && item.label.starts_with(&name))
+            })
+        {
+            schedule.cancel(id)?;
+        }
+        storage.remove(CHORES_NAMESPACE, &name)?;
+    }
+    for chore in chores {
+        let mut state = ChoreState::load(storage, chore)?;
+        if !state.has_reminder(schedule, chore) {
+            state.reschedule(schedule, chore, now)?;
+            state.save(storage, chore)?;
+        }
+    }
+    Ok(())
+}
+
+const DONE_INTENT: &str = "chore done";
+const DUE_INTENT: &str = "chores due";
+
+/// A [Skill] keeping track of recurring chores: "I watered the plants" marks one as done and
+/// reminds of it again once it is due, with the [Schedule] of the assistant, and "what chores
+/// are due" lists the ones to do. The reminders of chores done by several people say whose turn
+/// it is, the one after who marked it as done if they were recognized. See [schedule_reminders]
+/// to remind of them from the start.
+pub struct ChoreSkill {
+    chores: Vec<Chore>,
+    // Where the chores are kept without the storage of the assistant
+    memory: MemoryStorage,
+}
+
+impl ChoreSkill {
+    pub fn new(chores: Vec<Chore>) -> Self {
+        Self {
+            chores,
+            memory: MemoryStorage::new(),
+        }
+    }
+
+    fn done(
+        &self,
+        ctx: &mut SkillContext,
+        chore: &Chore,
+        now: DateTime<Local>,
+    ) -> Result<String, StorageError> {
+        let mut state = ChoreState::load(ctx.storage().unwrap_or(&self.memory), chore)?;
+        state.last_done = Some(now);
+        let speaker = ctx
+            .speaker()
+            .and_then(|speaker| chore.people.iter().position(|person| person == speaker));
+        state.turn = match speaker {
+            Some(done_by) => done_by + 1,
+            None => state.turn + 1,
+        } % chore.people.len().max(1);
+        state.reschedule(ctx.schedule(), chore, now)?;
+        state.save(ctx.storage().unwrap_or(&self.memory), chore)?;
+
+        let mut response = match chore.due(&state) {
+            Some(due) => format!(
+                "Thanks! I'll remind you to {} again {}.",
+                chore.name,
+                describe_day(due, now.date_naive())
+            ),
+            None => "Thanks!".to_string(),
+        };
+        if let Some(person) = chore.turn(&state) {
+            response.push_str(&format!(" It's {}'s turn next.", person));
+        }
+        Ok(response)
+    }
+
+    fn due(&self, ctx: &SkillContext, today: NaiveDate) -> Result<String, StorageError> {
+        let storage = ctx.storage().unwrap_or(&self.memory);
+        let mut due = Vec::new();
+        let mut next: Option<(NaiveDate, &Chore)> = None;
+        for chore in &self.chores {
+            let state = ChoreState::load(storage, chore)?;
+            match chore.due(&state) {
+                Some(day) if day > today => {
+                    if next.is_none_or(|(next, _)| day < next) {
+                        next = Some((day, chore));
+                    }
+                }
+                day => {
+                    let mut text = format!("It's time to {}", chore.name);
+                    if let Some(day) = day.filter(|day| *day < today) {
+                        text.push_str(&format!(", it was due {}", describe_day(day, today)));
+                    }
+                    text.push('.');
+                    if let Some(person) = chore.turn(&state) {
+                        text.push_str(&format!(" It's {}'s turn.", person));
+                    }
+                    due.push(text);
+                }
+            }
+        }
+        Ok(match next {
+            _ if !due.is_empty() => due.join(" "),
+            Some((day, chore)) => format!(
+                "No chores are due. Next is to {} {}.",
+                chore.name,
+                describe_day(day, today)
+            ),
+            None => "You don't have any chores.".to_string(),
+        })
+    }
+}
+
+impl Skill for ChoreSkill {
+    fn name(&self) -> &str {
+        "chores"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak, Capability::Storage]
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        if self.chores.is_empty() {
+            return Vec::new();
+        }
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        let done = Slot::new(
+            "chore",
+            SlotKind::Entity(self.chores.iter().map(|chore| chore.done.clone()).collect()),
+        );
+        vec![
+            IntentSpec::with_slots(
+                DONE_INTENT,
+                examples(&["i {chore}", "i just {chore}", "i already {chore}"]),
+                vec![done],
+            ),
+            IntentSpec::new(
+                DUE_INTENT,
+                examples(&[
+                    "what chores are due",
+                    "what chores do i have to do",
+                    "what needs to be done around the house",
+                ]),
+            ),
+        ]
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        let now = ctx.clock().local_now();
+        let response = match query.intent {
+            Some(DONE_INTENT) => {
+                let chore = match query.slots.get("chore") {
+                    Some(SlotValue::Entity(done)) => {
+                        self.chores.iter().find(|chore| chore.done == *done)
+                    }
+                    _ => None,
+                };
+                match chore {
+                    Some(chore) => self.done(ctx, chore, now),
+                    None => Ok("Which chore did you do?".to_string()),
+                }
+            }
+            Some(DUE_INTENT) => self.due(ctx, now.date_naive()),
+            _ => return,
+        };
+        let response = response.unwrap_or_else(|e| {
+            warn!("Failed to update the chores: {}", e);
+            "Sorry, I couldn't save that.".to_string()
+        });
+        _ = ctx.speak(response);
+    }
+}
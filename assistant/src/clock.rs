@@ -0,0 +1,82 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Datelike, Local, NaiveDate};
+
+/// Clock is the source of time for everything in the assistant that measures timeouts or tells
+/// the time. The default is [SystemClock]; tests can use [ManualClock] to move time forward
+/// without waiting.
+pub trait Clock: Send + Sync {
+    /// Monotonic time, used for timeouts.
+    fn now(&self) -> Instant;
+
+    /// Wall clock time in the local timezone.
+    fn local_now(&self) -> DateTime<Local>;
+}
+
+/// SystemClock reads the time from the operating system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn local_now(&self) -> DateTime<Local> {
+        Local::now()
+    }
+}
+
+/// ManualClock only advances when [ManualClock::advance] is called. Both the monotonic and the
+/// wall clock time move together.
+pub struct ManualClock {
+    start_instant: Instant,
+    start_time: DateTime<Local>,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    /// Create a clock that reads `start_time` until it is advanced.
+    pub fn new(start_time: DateTime<Local>) -> Self {
+        Self {
+            start_instant: Instant::now(),
+            start_time,
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.start_instant + self.elapsed()
+    }
+
+    fn local_now(&self) -> DateTime<Local> {
+        // Adding to a DateTime<Local> goes through UTC, so DST transitions are handled correctly
+        self.start_time
+            + chrono::Duration::from_std(self.elapsed()).expect("Elapsed time out of range")
+    }
+}
+
+/// The date as it would be said on `today`, e.g. "yesterday", "on Friday" or "on May 3".
+pub(crate) fn describe_day(date: NaiveDate, today: NaiveDate) -> String {
+    match (date - today).num_days() {
+        0 => "today".to_string(),
+        1 => "tomorrow".to_string(),
+        -1 => "yesterday".to_string(),
+        -6..=6 => format!("on {}", date.format("%A")),
+        _ if date.year() == today.year() => format!("on {}", date.format("%B %-d")),
+        _ => format!("on {}", date.format("%B %-d, %Y")),
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// What is said by skills that need the internet and have no offline fallback, see
+/// [crate::skills::Skill::handle_offline].
+pub const OFFLINE_RESPONSE: &str = "I can't reach the internet right now.";
+
+/// How the assistant checks that it is online, see
+/// [crate::AssistantConfig::set_connectivity_monitor]. Enabled with the `offline` feature.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnectivityConfig {
+    hosts: Vec<String>,
+    interval: Duration,
+    timeout: Duration,
+}
+
+impl Default for ConnectivityConfig {
+    fn default() -> Self {
+        Self {
+            hosts: vec!["1.1.1.1:53".to_string(), "8.8.8.8:53".to_string()],
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(3),
+        }
+    }
+}
+
+impl ConnectivityConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The `host:port` addresses connected to with TCP, the DNS servers of Cloudflare and Google
+    /// by default. The assistant is online if any of them answers.
+    pub fn set_hosts(&mut self, hosts: Vec<String>) {
+        self.hosts = hosts;
+    }
+
+    /// How often to check, every 30 seconds by default.
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+
+    /// How long to wait for a host to answer, 3 seconds by default.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn check(&self) -> bool {
+        self.hosts.iter().any(|host| {
+            // Resolving a name needs the DNS server to answer, so it counts as a check too
+            let Ok(addresses) = host.to_socket_addrs() else {
+                return false;
+            };
+            addresses
+                .into_iter()
+                .any(|address| TcpStream::connect_timeout(&address, self.timeout).is_ok())
+        })
+    }
+}
+
+/// Whether the assistant is online, updated by a background thread.
+#[derive(Clone, Debug)]
+pub struct Connectivity(Arc<AtomicBool>);
+
+impl Connectivity {
+    /// Check connectivity every interval on a thread. Until the first check finishes the
+    /// assistant is considered online.
+    pub fn spawn(config: ConnectivityConfig) -> Self {
+        let online = Arc::new(AtomicBool::new(true));
+        let updated = online.clone();
+        thread::spawn(move || loop {
+            updated.store(config.check(), Ordering::Relaxed);
+            thread::sleep(config.interval);
+        });
+        Self(online)
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
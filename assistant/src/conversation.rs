@@ -0,0 +1,274 @@
+use std::time::Duration;
+#[cfg(feature = "sync")]
+use std::time::SystemTime;
+
+#[cfg(feature = "sync")]
+use crate::sync::{SyncError, SyncKey};
+use crate::{
+    events::AssistantEvent, stt::RecognitionResult, tts::TtsError, tts_cache, AskOptions,
+    Assistant, AssistantListenSuccessfulWakewordError, AssistantQuery, QueryFailure,
+};
+
+/// How long to wait for a follow-up by default, see [Conversation::set_follow_up_window].
+pub const DEFAULT_FOLLOW_UP_WINDOW: Duration = Duration::from_secs(5);
+
+/// Sealed dialogs older than this are refused, so they can't be replayed later.
+#[cfg(feature = "sync")]
+const MAX_HANDOFF_AGE: Duration = Duration::from_secs(60);
+
+/// Where the dialog is: the last query, what the assistant answered and what its skill still
+/// waits for, so it can go on on another assistant, see [Assistant::resume_dialog].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DialogContext {
+    pub query: Option<String>,
+    /// What was said since the query, joined.
+    pub response: Option<String>,
+    /// The name of the skill that handled the query, `None` for intents of the application.
+    pub skill: Option<String>,
+    /// What the skill left for its next query, see [crate::skills::SkillContext::set_pending].
+    pub pending: Option<String>,
+}
+
+impl DialogContext {
+    pub fn is_empty(&self) -> bool {
+        self.query.is_none() && self.response.is_none()
+    }
+
+    /// Add to what was said since the query.
+    pub(crate) fn respond(&mut self, text: &str) {
+        match &mut self.response {
+            Some(response) => {
+                response.push(' ');
+                response.push_str(text);
+            }
+            None => self.response = Some(text.to_string()),
+        }
+    }
+
+    /// One field per line, like `query what's the weather`, with newlines escaped.
+    #[cfg(feature = "sync")]
+    fn to_text(&self) -> String {
+        let fields = [
+            ("query", &self.query),
+            ("response", &self.response),
+            ("skill", &self.skill),
+            ("pending", &self.pending),
+        ];
+        fields
+            .iter()
+            .filter_map(|(name, value)| {
+                let value = value.as_ref()?.replace('\\', "\\\\").replace('\n', "\\n");
+                Some(format!("{} {}\n", name, value))
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "sync")]
+    fn from_text(text: &str) -> Self {
+        let mut context = Self::default();
+        for line in text.lines() {
+            let (name, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = Some(unescape_line(value));
+            match name {
+                "query" => context.query = value,
+                "response" => context.response = value,
+                "skill" => context.skill = value,
+                "pending" => context.pending = value,
+                _ => (),
+            }
+        }
+        context
+    }
+
+    /// Encrypt the dialog for the assistants syncing with the same key, as hexadecimal text to
+    /// send to one of them, like to the `/handoff` endpoint of raspberry. Opened with
+    /// [DialogContext::open] within a minute.
+    #[cfg(feature = "sync")]
+    pub fn seal(&self, key: &SyncKey, now: SystemTime) -> Result<String, SyncError> {
+        let sent = now
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let sealed = key.seal(format!("{}\n{}", sent, self.to_text()).as_bytes())?;
+        Ok(sealed.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    /// `None` if the dialog wasn't sealed with this key, was changed or is too old.
+    #[cfg(feature = "sync")]
+    pub fn open(key: &SyncKey, sealed: &str, now: SystemTime) -> Option<Self> {
+        let sealed = sealed.trim();
+        let bytes = (0..sealed.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(sealed.get(i..i + 2)?, 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+        let text = String::from_utf8(key.open(&bytes)?).ok()?;
+        let (sent, text) = text.split_once('\n')?;
+        let sent = SystemTime::UNIX_EPOCH + Duration::from_secs(sent.parse().ok()?);
+        // The clocks of the assistants may be a little apart, either way
+        let age = match now.duration_since(sent) {
+            Ok(age) => age,
+            Err(e) => e.duration(),
+        };
+        (age <= MAX_HANDOFF_AGE).then(|| Self::from_text(text))
+    }
+}
+
+#[cfg(feature = "sync")]
+fn unescape_line(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => (),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Conversation keeps listening after the assistant responds, so the user can ask a follow-up
+/// like "and tomorrow?" without saying the wakeword again. It is created by
+/// [Assistant::conversation], usually after [Assistant::listen] returned a query.
+pub struct Conversation<'a, T> {
+    assistant: &'a mut Assistant<T>,
+    follow_up_window: Duration,
+}
+
+impl<T> Assistant<T> {
+    pub fn conversation(&mut self) -> Conversation<'_, T> {
+        Conversation {
+            assistant: self,
+            follow_up_window: DEFAULT_FOLLOW_UP_WINDOW,
+        }
+    }
+
+    /// The last query and what was answered, e.g. to hand the dialog off to another assistant.
+    pub fn dialog_context(&self) -> &DialogContext {
+        &self.dialog
+    }
+
+    /// Go on with the dialog of another assistant, like after "continue in the bedroom": say its
+    /// last response again, then listen for a query without a wakeword the next time
+    /// [Assistant::listen] waits for one. The skill of the dialog gets what it left pending.
+    pub fn resume_dialog(&mut self, context: DialogContext) {
+        if let Some(response) = &context.response {
+            _ = tts_cache::speak(
+                self.tts.as_mut(),
+                self.utterances.as_ref(),
+                &self.normalizer,
+                self.tts_cache.as_ref(),
+                response.clone(),
+            );
+        }
+        self.dialog = context;
+        self.dialog_resumed = true;
+    }
+
+    /// The text of the query following [Assistant::resume_dialog], `None` if the user didn't say
+    /// one within the follow-up window or no dialog was resumed.
+    pub(crate) fn listen_resumed_dialog(&mut self) -> Option<String> {
+        if !std::mem::take(&mut self.dialog_resumed) {
+            return None;
+        }
+        _ = self.finish_speaking();
+        self.query_speech_recognizer = None;
+        self.wait_until_ready();
+        let options = AskOptions {
+            timeout: Some(DEFAULT_FOLLOW_UP_WINDOW),
+            ..self.query_options()
+        };
+        self.recognize_text(&options, &mut QueryFailure::default())
+            .ok()
+    }
+}
+
+impl<T> Conversation<'_, T> {
+    /// Set how long the user has to start speaking a follow-up after a response.
+    pub fn set_follow_up_window(&mut self, window: Duration) {
+        self.follow_up_window = window;
+    }
+
+    /// Speak the prompt and recognize the answer once, without reprompting.
+    pub fn ask(
+        &mut self,
+        prompt: impl Into<String>,
+    ) -> Result<RecognitionResult, AssistantListenSuccessfulWakewordError> {
+        self.speak_and_wait(prompt)
+            .map_err(|_| AssistantListenSuccessfulWakewordError::SpeechRecognitionError)?;
+        self.recognize(&AskOptions::default())
+    }
+
+    /// Speak the response and listen for a follow-up query. Returns `None` if the user didn't
+    /// start speaking within the follow-up window, which ends the conversation. The query has an
+    /// empty wakeword.
+    pub fn respond(
+        &mut self,
+        response: impl Into<String>,
+    ) -> Result<Option<AssistantQuery<'_, T>>, AssistantListenSuccessfulWakewordError> {
+        // Failing to respond shouldn't end the conversation
+        _ = self.speak_and_wait(response);
+        self.follow_up()
+    }
+
+    /// Listen for a follow-up query without speaking first. Follow-ups handled by a
+    /// [crate::skills::Skill] aren't returned, the conversation goes on with the next one.
+    pub fn follow_up(
+        &mut self,
+    ) -> Result<Option<AssistantQuery<'_, T>>, AssistantListenSuccessfulWakewordError> {
+        let options = AskOptions {
+            timeout: Some(self.follow_up_window),
+            ..AskOptions::default()
+        };
+        loop {
+            let text = match self.recognize(&options)? {
+                RecognitionResult::Final(sentence) => sentence.text,
+                RecognitionResult::Failed => {
+                    return Err(AssistantListenSuccessfulWakewordError::SpeechRecognitionError)
+                }
+                RecognitionResult::Cancelled => return Ok(None),
+            };
+            let query =
+                self.assistant
+                    .match_text(String::new(), text, &mut QueryFailure::default())?;
+            if let Some(query) = query {
+                return Ok(Some(self.assistant.resolve(query)));
+            }
+        }
+    }
+
+    fn speak_and_wait(&mut self, text: impl Into<String>) -> Result<(), TtsError> {
+        let text = text.into();
+        self.assistant.dialog.respond(&text);
+        // Wait for the text to finish so it isn't picked up by the recognizer
+        tts_cache::speak(
+            self.assistant.tts.as_mut(),
+            self.assistant.utterances.as_ref(),
+            &self.assistant.normalizer,
+            self.assistant.tts_cache.as_ref(),
+            text,
+        )?;
+        self.assistant.finish_speaking()
+    }
+
+    fn recognize(
+        &mut self,
+        options: &AskOptions,
+    ) -> Result<RecognitionResult, AssistantListenSuccessfulWakewordError> {
+        self.assistant
+            .events
+            .emit(AssistantEvent::RecognitionStarted);
+        let result = self.assistant.sentence_recognizer(options)?.recognize()?;
+        let text = match &result {
+            RecognitionResult::Final(sentence) => Some(sentence.text.clone()),
+            _ => None,
+        };
+        self.assistant
+            .events
+            .emit(AssistantEvent::RecognitionFinished(text));
+        Ok(result)
+    }
+}
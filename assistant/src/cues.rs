@@ -0,0 +1,102 @@
+use thiserror::Error;
+
+use crate::{
+    audio::{
+        AudioInput, AudioInputBuildError, AudioInputConfig, AudioInputStartError, AudioInputStatus,
+        InputDevice,
+    },
+    wakeword::{
+        WakewordConfig, WakewordConfigBuildError, WakewordConfigStartError, WakewordDetection,
+        WakewordListener,
+    },
+};
+
+/// Another capture source with its own wakeword detector, e.g. a loopback of the audio of the
+/// TV, whose wakewords are audio cues like the jingle of an ad break. Cues don't start queries,
+/// they are [crate::events::AssistantEvent::AudioCue] events and trigger the automation rules
+/// with [crate::automation::Trigger::AudioCue]. See [crate::AssistantConfig::add_audio_cue_source].
+pub struct AudioCueSource {
+    name: String,
+    audio: AudioInputConfig,
+    wakewords: WakewordConfig,
+}
+
+#[derive(Error, Debug)]
+pub enum AudioCueSourceBuildError {
+    #[error("Failed to configure the audio input of the cues")]
+    AudioInput(#[from] AudioInputBuildError),
+    #[error("Failed to build the wakeword config of the cues")]
+    WakewordConfig(#[from] WakewordConfigBuildError),
+}
+
+#[derive(Error, Debug)]
+pub enum AudioCueSourceStartError {
+    #[error("Failed to start the audio input of the cues")]
+    AudioInput(#[from] AudioInputStartError),
+    #[error("Failed to start listening for cues")]
+    Wakewords(#[from] WakewordConfigStartError),
+}
+
+impl AudioCueSource {
+    /// `name` tells the sources apart in the events, and `device` is usually a line-in or a
+    /// loopback device, see [crate::audio::input_device_names].
+    pub fn new(
+        name: impl Into<String>,
+        device: InputDevice,
+    ) -> Result<Self, AudioCueSourceBuildError> {
+        let audio = AudioInputConfig::build_with_device(device)?;
+        let wakewords = WakewordConfig::build(audio.format())?;
+        Ok(Self {
+            name: name.into(),
+            audio,
+            wakewords,
+        })
+    }
+
+    /// The detector of the cues, to add them with [WakewordConfig::add_wakeword_from_file].
+    pub fn wakeword_config_mut(&mut self) -> &mut WakewordConfig {
+        &mut self.wakewords
+    }
+
+    pub(crate) fn start(self) -> Result<RunningAudioCueSource, AudioCueSourceStartError> {
+        let input = AudioInput::start(self.audio)?;
+        let listener = self.wakewords.start(&input)?;
+        Ok(RunningAudioCueSource {
+            name: self.name,
+            input,
+            listener,
+        })
+    }
+}
+
+pub(crate) struct RunningAudioCueSource {
+    name: String,
+    input: AudioInput,
+    listener: WakewordListener,
+}
+
+impl RunningAudioCueSource {
+    pub(crate) fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The cues detected since the last call, after reconnecting to the source if it failed.
+    pub(crate) fn poll(&mut self) -> Vec<WakewordDetection> {
+        match self.input.supervise() {
+            Some(AudioInputStatus::Disconnected(reason)) => warn!(
+                "Lost the audio of the cues of {} ({}), reconnecting...",
+                self.name, reason
+            ),
+            Some(AudioInputStatus::Reconnected) => {
+                info!("Reconnected to the audio of the cues of {}.", self.name)
+            }
+            None => (),
+        }
+        self.listener.try_iter().collect()
+    }
+
+    pub(crate) fn stop(&mut self) {
+        _ = self.input.pause();
+        self.listener.drain();
+    }
+}
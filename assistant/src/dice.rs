@@ -0,0 +1,134 @@
+use crate::{
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{Slot, SlotKind, SlotValue},
+    AssistantQuery,
+};
+
+const ROLL_INTENT: &str = "roll dice";
+const FLIP_INTENT: &str = "flip a coin";
+const CHOOSE_INTENT: &str = "choose between";
+/// At most this many dice are rolled at once, so the answer stays short.
+const MAX_DICE: u64 = 20;
+const MAX_SIDES: u64 = 1000;
+
+/// A [Skill] rolling dice, like "roll two six sided dice", flipping a coin and picking one of
+/// the choices of "pick between pizza or pasta", with the [crate::random::Random] of the
+/// assistant. A small example of intents with [Slot]s of numbers and free text.
+#[derive(Default)]
+pub struct DiceSkill;
+
+/// The whole number of the slot, `default` if it wasn't said and `None` if it isn't one.
+fn whole_number(query: &AssistantQuery<'_, str>, slot: &str, default: u64) -> Option<u64> {
+    match query.slots.get(slot) {
+        Some(SlotValue::Number(number)) if number.fract() == 0. && *number >= 1. => {
+            Some(*number as u64)
+        }
+        Some(_) => None,
+        None => Some(default),
+    }
+}
+
+/// The choices of a list, like "pizza or pasta or salad". Split on "and" too, unless "or" is
+/// said, so "mac and cheese or pizza" is two choices, and on commas of typed queries.
+fn choices(text: &str) -> Vec<&str> {
+    let separator = if text.split_whitespace().any(|word| word == "or") {
+        " or "
+    } else {
+        " and "
+    };
+    text.split(separator)
+        .flat_map(|choice| choice.split(','))
+        .map(str::trim)
+        .filter(|choice| !choice.is_empty())
+        .collect()
+}
+
+fn roll(ctx: &SkillContext, query: &AssistantQuery<'_, str>) -> String {
+    let (Some(count), Some(sides)) = (
+        whole_number(query, "count", 1),
+        whole_number(query, "sides", 6),
+    ) else {
+        return "I can only roll whole numbers of dice.".to_string();
+    };
+    if count > MAX_DICE {
+        return format!("I can roll up to {} dice at once.", MAX_DICE);
+    }
+    if !(2..=MAX_SIDES).contains(&sides) {
+        return format!("Dice have 2 to {} sides.", MAX_SIDES);
+    }
+    let rolls: Vec<u64> = (0..count).map(|_| ctx.random().below(sides) + 1).collect();
+    match rolls.as_slice() {
+        [only] => format!("You rolled a {}.", only),
+        [rest @ .., last] => format!(
+            "You rolled {} and {}, for a total of {}.",
+            rest.iter()
+                .map(u64::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            last,
+            rolls.iter().sum::<u64>()
+        ),
+        [] => unreachable!("At least one die is rolled"),
+    }
+}
+
+impl Skill for DiceSkill {
+    fn name(&self) -> &str {
+        "dice"
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        vec![
+            IntentSpec::with_slots(
+                ROLL_INTENT,
+                examples(&[
+                    "roll a die",
+                    "roll the dice",
+                    "roll {count} dice",
+                    "roll a {sides} sided die",
+                    "roll {count} {sides} sided dice",
+                ]),
+                vec![
+                    Slot::new("count", SlotKind::Number),
+                    Slot::new("sides", SlotKind::Number),
+                ],
+            ),
+            IntentSpec::new(
+                FLIP_INTENT,
+                examples(&["flip a coin", "toss a coin", "heads or tails"]),
+            ),
+            IntentSpec::with_slots(
+                CHOOSE_INTENT,
+                examples(&[
+                    "pick between {choices}",
+                    "choose between {choices}",
+                    "help me decide between {choices}",
+                ]),
+                vec![Slot::new("choices", SlotKind::FreeText)],
+            ),
+        ]
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        let response = match query.intent {
+            Some(ROLL_INTENT) => roll(ctx, query),
+            Some(FLIP_INTENT) => match ctx.random().below(2) {
+                0 => "It's heads.".to_string(),
+                _ => "It's tails.".to_string(),
+            },
+            Some(CHOOSE_INTENT) => {
+                let choices = match query.slots.get("choices") {
+                    Some(SlotValue::Text(text)) => choices(text),
+                    _ => Vec::new(),
+                };
+                match ctx.random().choose(&choices) {
+                    Some(choice) if choices.len() > 1 => format!("I pick {}.", choice),
+                    _ => "What should I choose between?".to_string(),
+                }
+            }
+            _ => return,
+        };
+        _ = ctx.speak(response);
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use crate::{
+    audio::AudioInputStatus, schedule::ScheduledItem, shadow::ShadowDivergence, sounds::Earcons,
+    wakeword::WakewordDetection,
+};
+
+/// Events emitted while the assistant processes a query, received with
+/// [crate::Assistant::events]. They let other parts of an application follow what the assistant
+/// is doing, e.g. to light up a LED while it listens, without driving [crate::Assistant::listen]
+/// themselves. Since the TTS backend can't be sent between threads, the assistant should be
+/// built and run on its own thread and the receiver passed to the rest of the application.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AssistantEvent {
+    /// A wakeword was detected, with its score to tune the sensitivity of the detector.
+    WakewordDetected(WakewordDetection),
+    /// Speech recognition started, including when the user is reprompted.
+    RecognitionStarted,
+    /// Speech recognition finished with the transcript, or `None` if it failed or timed out.
+    RecognitionFinished(Option<String>),
+    /// An intent matched the transcript.
+    IntentMatched { text: String, score: f32 },
+    /// Processing the query failed, with the error message.
+    Error(String),
+    /// A shadow configuration disagreed with the active one.
+    ShadowDivergence(ShadowDivergence),
+    /// A timer, alarm or reminder is due, right before it is announced.
+    ScheduledItemDue(ScheduledItem),
+    /// The assistant was muted or unmuted, see [crate::Assistant::set_muted].
+    MutedChanged(bool),
+    /// Capturing audio was paused or resumed, see [crate::Assistant::pause_listening].
+    ListeningChanged(bool),
+    /// The assistant was shut down, see [crate::Assistant::shutdown].
+    Stopped,
+    /// The microphone was disconnected or reconnected, see [crate::audio::AudioInput::supervise].
+    AudioInput(AudioInputStatus),
+    /// See [crate::Assistant::set_do_not_disturb].
+    DoNotDisturbChanged(bool),
+    /// A value set with [crate::Assistant::set_state] changed.
+    StateChanged { key: String, value: String },
+    /// Guest mode started or ended, see [crate::Assistant::start_guest_mode].
+    GuestModeChanged(bool),
+    /// The actions of the automation rule with this name are about to run.
+    RuleTriggered(String),
+    /// A cue was heard by the audio cue source with this name, see
+    /// [crate::cues::AudioCueSource].
+    AudioCue {
+        source: String,
+        cue: WakewordDetection,
+    },
+    /// The assistant went online or offline, see
+    /// [crate::AssistantConfig::set_connectivity_monitor].
+    #[cfg(feature = "offline")]
+    ConnectivityChanged(bool),
+    /// [crate::AssistantConfig::start] got further, see [crate::AssistantConfig::events].
+    Startup(StartupProgress),
+}
+
+/// The stages of [crate::AssistantConfig::start]. The speech recognition model is already loaded
+/// by [crate::AssistantConfig::build].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StartupProgress {
+    /// The intent recognition model is loaded and `embedded` of the `total` intents were
+    /// embedded. Examples embedded on a previous start are read from the storage, if set.
+    IntentsEmbedded { embedded: usize, total: usize },
+    /// The microphone and the wakeword detectors are running.
+    AudioReady,
+    /// The assistant is ready to listen.
+    Ready,
+}
+
+/// The receivers of [AssistantEvent]s, and the earcons played on them. Receivers that were
+/// dropped are removed on the next event.
+pub(crate) struct EventSenders {
+    senders: Vec<Sender<AssistantEvent>>,
+    pub(crate) earcons: Earcons,
+}
+
+impl EventSenders {
+    pub(crate) fn new(earcons: Earcons) -> Self {
+        Self {
+            senders: Vec::new(),
+            earcons,
+        }
+    }
+
+    pub(crate) fn subscribe(&mut self) -> Receiver<AssistantEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.senders.push(tx);
+        rx
+    }
+
+    pub(crate) fn emit(&mut self, event: AssistantEvent) {
+        #[cfg(feature = "tracing")]
+        tracing::debug!(?event, "assistant event");
+        self.earcons.play_for(&event);
+        self.senders.retain(|tx| tx.send(event.clone()).is_ok());
+    }
+}
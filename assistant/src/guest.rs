@@ -0,0 +1,73 @@
+use chrono::{DateTime, Local};
+
+use crate::storage::{Storage, StorageError};
+
+/// The [Storage] namespace of the end of guest mode, so it survives a restart.
+const GUEST_MODE_NAMESPACE: &str = "guest_mode";
+
+/// What changes while guests use the assistant, e.g. a house sitter, see
+/// [crate::AssistantConfig::set_guest_mode].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GuestModeConfig {
+    /// The wakeword that only works in guest mode, while the others only work outside of it.
+    /// All wakewords keep working if `None`.
+    pub wakeword: Option<String>,
+    /// The intent groups guests can use, see [crate::AssistantConfig::set_intent_group]. Intents
+    /// in other groups or in none can't be used.
+    pub allowed_groups: Vec<String>,
+    /// Said when guest mode starts, e.g. "Guest mode is on. Say 'hey house' to talk to me."
+    pub start_announcement: Option<String>,
+    /// Said when guest mode ends, including when its time is up.
+    pub end_announcement: Option<String>,
+}
+
+/// The guest mode configuration and until when it is on.
+pub(crate) struct GuestMode {
+    pub(crate) config: GuestModeConfig,
+    until: Option<DateTime<Local>>,
+}
+
+impl GuestMode {
+    /// Load the end of guest mode from the storage, if it was on when the assistant stopped.
+    pub(crate) fn load(
+        config: GuestModeConfig,
+        storage: Option<&dyn Storage>,
+    ) -> Result<Self, StorageError> {
+        let until = match storage {
+            Some(storage) => storage
+                .get(GUEST_MODE_NAMESPACE, "until")?
+                .and_then(|value| String::from_utf8(value).ok())
+                .and_then(|value| DateTime::parse_from_rfc3339(&value).ok())
+                .map(|until| until.with_timezone(&Local)),
+            None => None,
+        };
+        Ok(Self { config, until })
+    }
+
+    pub(crate) fn until(&self) -> Option<DateTime<Local>> {
+        self.until
+    }
+
+    pub(crate) fn set_until(
+        &mut self,
+        until: Option<DateTime<Local>>,
+        storage: Option<&dyn Storage>,
+    ) -> Result<(), StorageError> {
+        self.until = until;
+        match (storage, until) {
+            (Some(storage), Some(until)) => {
+                storage.set(GUEST_MODE_NAMESPACE, "until", until.to_rfc3339().as_bytes())
+            }
+            (Some(storage), None) => storage.remove(GUEST_MODE_NAMESPACE, "until"),
+            (None, _) => Ok(()),
+        }
+    }
+
+    /// Whether a detected wakeword should be answered.
+    pub(crate) fn accepts_wakeword(&self, wakeword: &str) -> bool {
+        match &self.config.wakeword {
+            Some(guest_wakeword) => (guest_wakeword == wakeword) == self.until.is_some(),
+            None => true,
+        }
+    }
+}
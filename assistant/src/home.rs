@@ -0,0 +1,424 @@
+use serde_json::{json, Map, Value};
+use std::{
+    io::{self, Read, Write},
+    net::TcpStream,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+use crate::{
+    http::{HttpClient, HttpError},
+    slots::SlotValue,
+    AssistantQuery,
+};
+
+#[derive(Error, Debug)]
+pub enum HomeError {
+    #[error("Home Assistant request failed")]
+    Request(#[from] HttpError),
+    #[error("Failed to read the Home Assistant response")]
+    Response(#[source] io::Error),
+    #[error("MQTT connection failed")]
+    Mqtt(#[source] io::Error),
+    #[error("The MQTT broker refused the connection with code {0}")]
+    MqttRefused(u8),
+}
+
+/// A Home Assistant server, to run its intents or give it sentences the assistant doesn't
+/// understand. Enabled with the `home` feature.
+pub struct HomeAssistant {
+    url: String,
+    token: String,
+    language: Option<String>,
+    client: HttpClient,
+}
+
+impl HomeAssistant {
+    /// `url` is the address of the server, like `http://homeassistant.local:8123`, and `token`
+    /// a long-lived access token created in the profile of a Home Assistant user.
+    pub fn new(url: impl Into<String>, token: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            token: token.into(),
+            language: None,
+            client: HttpClient::default(),
+        }
+    }
+
+    /// Make the requests with a shared client, see [crate::AssistantConfig::http_client].
+    pub fn set_client(&mut self, client: HttpClient) {
+        self.client = client;
+    }
+
+    /// The language of the sentences given to [HomeAssistant::converse], the default language of
+    /// the server otherwise.
+    pub fn set_language(&mut self, language: Option<&str>) {
+        self.language = language.map(str::to_string);
+    }
+
+    /// Run an intent, like `HassTurnOn`, through the intent API, which has to be enabled with
+    /// `intent:` in the configuration of the server. Returns what Home Assistant would say.
+    pub fn handle_intent(
+        &self,
+        name: &str,
+        data: Map<String, Value>,
+    ) -> Result<Option<String>, HomeError> {
+        let response = self.post("/api/intent/handle", json!({ "name": name, "data": data }))?;
+        Ok(speech(&response))
+    }
+
+    /// Let the conversation agent of Home Assistant handle the sentence, e.g. "turn on the kitchen
+    /// lights", and return its answer.
+    pub fn converse(&self, text: &str) -> Result<Option<String>, HomeError> {
+        let mut body = json!({ "text": text });
+        if let Some(language) = &self.language {
+            body["language"] = json!(language);
+        }
+        let response = self.post("/api/conversation/process", body)?;
+        Ok(response.get("response").and_then(speech))
+    }
+
+    fn post(&self, path: &str, body: Value) -> Result<Value, HomeError> {
+        self.client
+            .post(&format!("{}{}", self.url, path))
+            .set("Authorization", &format!("Bearer {}", self.token))
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string())?
+            .into_json()
+            .map_err(HomeError::Response)
+    }
+}
+
+fn speech(response: &Value) -> Option<String> {
+    response
+        .pointer("/speech/plain/speech")
+        .and_then(Value::as_str)
+        .filter(|speech| !speech.is_empty())
+        .map(str::to_string)
+}
+
+/// The slots of the query as intent data for Home Assistant. The location is given as `area`,
+/// and durations in seconds.
+pub fn intent_data<T: ?Sized>(query: &AssistantQuery<'_, T>) -> Map<String, Value> {
+    let mut data: Map<String, Value> = query
+        .slots
+        .iter()
+        .map(|(name, value)| (name.clone(), slot_json(value)))
+        .collect();
+    if let Some(location) = &query.location {
+        data.entry("area")
+            .or_insert_with(|| Value::String(location.clone()));
+    }
+    data
+}
+
+/// The query as JSON, e.g. to publish it with [MqttPublisher]:
+/// `{"intent": ..., "text": ..., "score": ..., "location": ..., "slots": {...}}`.
+pub fn query_json(intent: &str, query: &AssistantQuery<'_, impl ?Sized>) -> Value {
+    let slots: Map<String, Value> = query
+        .slots
+        .iter()
+        .map(|(name, value)| (name.clone(), slot_json(value)))
+        .collect();
+    json!({
+        "intent": intent,
+        "wakeword": query.wakeword,
+        "text": query.text,
+        "score": query.score,
+        "location": query.location,
+        "slots": slots,
+    })
+}
+
+fn slot_json(value: &SlotValue) -> Value {
+    match value {
+        SlotValue::Duration(duration) => json!(duration.as_secs_f64()),
+        SlotValue::Number(number) => json!(number),
+        SlotValue::Entity(text) | SlotValue::Text(text) => json!(text),
+    }
+}
+
+/// The keep alive sent to the broker. It closes connections it received nothing from for one and
+/// a half times as long.
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+/// How long a connection is idle before a PINGREQ is sent, and how long the PINGRESP is waited for.
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Publishes messages to an MQTT broker, with MQTT 3.1.1 at QoS 0. Connects on the first message
+/// and reconnects when the connection was lost.
+pub struct MqttPublisher {
+    address: String,
+    client_id: String,
+    credentials: Option<(String, String)>,
+    stream: Option<TcpStream>,
+    last_sent: Instant,
+}
+
+impl MqttPublisher {
+    /// `address` is the host and port of the broker, like `localhost:1883`.
+    pub fn new(address: impl Into<String>, client_id: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            client_id: client_id.into(),
+            credentials: None,
+            stream: None,
+            last_sent: Instant::now(),
+        }
+    }
+
+    pub fn set_credentials(&mut self, username: &str, password: &str) {
+        self.credentials = Some((username.to_string(), password.to_string()));
+        self.stream = None;
+    }
+
+    pub fn publish(&mut self, topic: &str, payload: &[u8], retain: bool) -> Result<(), HomeError> {
+        let mut packet = Vec::new();
+        write_string(&mut packet, topic);
+        packet.extend_from_slice(payload);
+        let packet = with_header(0x30 | retain as u8, &packet);
+
+        // The broker closed the connection if it was idle for longer than the keep alive
+        if self.last_sent.elapsed() >= KEEP_ALIVE {
+            self.stream = None;
+        }
+        // A connection the broker closed is only noticed when writing, so retry once
+        for attempt in 0..2 {
+            let stream = match &mut self.stream {
+                Some(stream) => stream,
+                None => self.stream.insert(self.connect()?),
+            };
+            match stream.write_all(&packet) {
+                Ok(()) => {
+                    self.last_sent = Instant::now();
+                    return Ok(());
+                }
+                Err(e) if attempt == 1 => return Err(HomeError::Mqtt(e)),
+                Err(_) => self.stream = None,
+            }
+        }
+        unreachable!("The second attempt always returns")
+    }
+
+    fn connect(&self) -> Result<TcpStream, HomeError> {
+        connect(&self.address, &self.client_id, self.credentials.as_ref())
+    }
+}
+
+/// Receives the messages published to MQTT topics, with MQTT 3.1.1 at QoS 0, e.g. for
+/// [crate::automation::Trigger::MqttMessage]. Pings the broker to keep the connection alive, and
+/// fails to get the next message once the broker stopped answering, to reconnect.
+pub struct MqttSubscriber {
+    stream: TcpStream,
+    last_sent: Instant,
+    /// When the PINGREQ still waiting for its PINGRESP was sent.
+    ping_sent: Option<Instant>,
+}
+
+impl MqttSubscriber {
+    /// Connect to the broker and subscribe to the topic filters. The client id has to be another
+    /// one than the one of an [MqttPublisher] connected at the same time.
+    pub fn connect(
+        address: &str,
+        client_id: &str,
+        credentials: Option<(&str, &str)>,
+        topics: &[String],
+    ) -> Result<Self, HomeError> {
+        let credentials =
+            credentials.map(|(user, password)| (user.to_string(), password.to_string()));
+        let mut stream = connect(address, client_id, credentials.as_ref())?;
+
+        // Packet identifier 1, then every filter at QoS 0
+        let mut packet = vec![0, 1];
+        for topic in topics {
+            write_string(&mut packet, topic);
+            packet.push(0);
+        }
+        stream
+            .write_all(&with_header(0x82, &packet))
+            .map_err(HomeError::Mqtt)?;
+        let (packet_type, _) = read_packet(&mut stream)?;
+        if packet_type & 0xf0 != 0x90 {
+            return Err(HomeError::Mqtt(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Expected SUBACK",
+            )));
+        }
+        // Messages can take any time to arrive, so the timeout is only to ping the broker
+        stream
+            .set_read_timeout(Some(PING_INTERVAL))
+            .map_err(HomeError::Mqtt)?;
+        Ok(Self {
+            stream,
+            last_sent: Instant::now(),
+            ping_sent: None,
+        })
+    }
+
+    /// Wait for the next message, returning its topic and payload. Fails when the connection was
+    /// lost, including when the broker didn't answer a PINGREQ in time.
+    pub fn next_message(&mut self) -> Result<(String, Vec<u8>), HomeError> {
+        loop {
+            self.keep_alive()?;
+            let mut byte = [0];
+            match self.stream.read(&mut byte) {
+                Ok(0) => {
+                    return Err(HomeError::Mqtt(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "The broker closed the connection",
+                    )))
+                }
+                Ok(_) => (),
+                // Timed out, to ping the broker
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(HomeError::Mqtt(e)),
+            }
+            let (packet_type, body) = read_packet_body(&mut self.stream, byte[0])?;
+            if packet_type & 0xf0 == 0xd0 {
+                self.ping_sent = None;
+            }
+            // Only PUBLISH packets, all at QoS 0 since that's what was subscribed with
+            if packet_type & 0xf0 != 0x30 || body.len() < 2 {
+                continue;
+            }
+            let length = u16::from_be_bytes([body[0], body[1]]) as usize;
+            let Some(topic) = body.get(2..2 + length) else {
+                continue;
+            };
+            let topic = String::from_utf8_lossy(topic).into_owned();
+            return Ok((topic, body[2 + length..].to_vec()));
+        }
+    }
+
+    /// Send a PINGREQ when nothing was sent for a while, even if messages keep arriving, since
+    /// the broker only counts what it receives.
+    fn keep_alive(&mut self) -> Result<(), HomeError> {
+        match self.ping_sent {
+            Some(sent) if sent.elapsed() >= PING_INTERVAL => Err(HomeError::Mqtt(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "The broker didn't answer the PINGREQ",
+            ))),
+            Some(_) => Ok(()),
+            None if self.last_sent.elapsed() >= PING_INTERVAL => {
+                self.stream.write_all(&[0xc0, 0]).map_err(HomeError::Mqtt)?;
+                self.last_sent = Instant::now();
+                self.ping_sent = Some(self.last_sent);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for MqttSubscriber {
+    fn drop(&mut self) {
+        // DISCONNECT
+        _ = self.stream.write_all(&[0xe0, 0]);
+    }
+}
+
+fn connect(
+    address: &str,
+    client_id: &str,
+    credentials: Option<&(String, String)>,
+) -> Result<TcpStream, HomeError> {
+    let mut stream = TcpStream::connect(address).map_err(HomeError::Mqtt)?;
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .map_err(HomeError::Mqtt)?;
+
+    let mut packet = Vec::new();
+    write_string(&mut packet, "MQTT");
+    // Protocol level 4 is MQTT 3.1.1
+    packet.push(4);
+    let mut flags = 0x02; // clean session
+    if credentials.is_some() {
+        flags |= 0xc0;
+    }
+    packet.push(flags);
+    packet.extend_from_slice(&(KEEP_ALIVE.as_secs() as u16).to_be_bytes());
+    write_string(&mut packet, client_id);
+    if let Some((username, password)) = credentials {
+        write_string(&mut packet, username);
+        write_string(&mut packet, password);
+    }
+    stream
+        .write_all(&with_header(0x10, &packet))
+        .map_err(HomeError::Mqtt)?;
+
+    let mut connack = [0; 4];
+    stream.read_exact(&mut connack).map_err(HomeError::Mqtt)?;
+    if connack[0] != 0x20 {
+        return Err(HomeError::Mqtt(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Expected CONNACK",
+        )));
+    }
+    if connack[3] != 0 {
+        return Err(HomeError::MqttRefused(connack[3]));
+    }
+    Ok(stream)
+}
+
+/// Read a packet, returning its first byte and the rest after the remaining length.
+fn read_packet(stream: &mut TcpStream) -> Result<(u8, Vec<u8>), HomeError> {
+    let mut byte = [0];
+    stream.read_exact(&mut byte).map_err(HomeError::Mqtt)?;
+    read_packet_body(stream, byte[0])
+}
+
+/// Read the rest of a packet once its first byte was read.
+fn read_packet_body(stream: &mut TcpStream, packet_type: u8) -> Result<(u8, Vec<u8>), HomeError> {
+    let mut byte = [0];
+    let mut length = 0;
+    for shift in (0..28).step_by(7) {
+        stream.read_exact(&mut byte).map_err(HomeError::Mqtt)?;
+        length |= ((byte[0] & 0x7f) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+    }
+    let mut body = vec![0; length];
+    stream.read_exact(&mut body).map_err(HomeError::Mqtt)?;
+    Ok((packet_type, body))
+}
+
+impl Drop for MqttPublisher {
+    fn drop(&mut self) {
+        if let Some(stream) = &mut self.stream {
+            // DISCONNECT
+            _ = stream.write_all(&[0xe0, 0]);
+        }
+    }
+}
+
+fn write_string(packet: &mut Vec<u8>, text: &str) {
+    packet.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    packet.extend_from_slice(text.as_bytes());
+}
+
+/// Prefix the packet with its type and remaining length.
+fn with_header(packet_type: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![packet_type];
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
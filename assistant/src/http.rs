@@ -0,0 +1,252 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// How many requests can be made to a host in a period of time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RateLimit {
+    pub requests: usize,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    pub fn per_minute(requests: usize) -> Self {
+        Self {
+            requests,
+            per: Duration::from_secs(60),
+        }
+    }
+}
+
+/// How the requests of a [HttpClient] are made. By default they time out after 10 seconds, failed
+/// ones are retried twice with a backoff starting at half a second, and 30 requests a minute can
+/// be made to each host.
+#[derive(Clone, Debug)]
+pub struct HttpClientConfig {
+    timeout: Duration,
+    max_retries: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    rate_limit: Option<RateLimit>,
+    host_rate_limits: HashMap<String, Option<RateLimit>>,
+}
+
+impl Default for HttpClientConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10),
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(4),
+            rate_limit: Some(RateLimit::per_minute(30)),
+            host_rate_limits: HashMap::new(),
+        }
+    }
+}
+
+impl HttpClientConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The longest a request can take, including connecting. Requests can only ask for a shorter
+    /// one.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// How many times a failed request is retried. The backoff starts at `initial_backoff` and
+    /// doubles after every attempt, up to `max_backoff`.
+    pub fn set_retries(
+        &mut self,
+        max_retries: u32,
+        initial_backoff: Duration,
+        max_backoff: Duration,
+    ) {
+        self.max_retries = max_retries;
+        self.initial_backoff = initial_backoff;
+        self.max_backoff = max_backoff;
+    }
+
+    /// The rate limit of every host, `None` for no limit.
+    pub fn set_rate_limit(&mut self, limit: Option<RateLimit>) {
+        self.rate_limit = limit;
+    }
+
+    /// The rate limit of one host, like "api.open-meteo.com", replacing the one of every host.
+    pub fn set_host_rate_limit(&mut self, host: impl Into<String>, limit: Option<RateLimit>) {
+        self.host_rate_limits.insert(host.into(), limit);
+    }
+
+    pub fn build(self) -> HttpClient {
+        HttpClient {
+            inner: Arc::new(ClientState {
+                agent: ureq::AgentBuilder::new().timeout(self.timeout).build(),
+                config: self,
+                requests: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum HttpError {
+    #[error("Too many requests to {0}")]
+    RateLimited(String),
+    #[error("HTTP request failed")]
+    Request(#[source] Box<ureq::Error>),
+}
+
+impl From<ureq::Error> for HttpError {
+    fn from(e: ureq::Error) -> Self {
+        HttpError::Request(Box::new(e))
+    }
+}
+
+/// The HTTP client shared by the skills, see [crate::AssistantConfig::http_client]. It enforces
+/// the timeouts, retries and rate limits of its [HttpClientConfig] for all of them, so that one
+/// skill can't flood a service or block the assistant for long. Clones share the same limits.
+#[derive(Clone)]
+pub struct HttpClient {
+    inner: Arc<ClientState>,
+}
+
+struct ClientState {
+    agent: ureq::Agent,
+    config: HttpClientConfig,
+    // When the last requests to each host were made, within their rate limit
+    requests: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        HttpClientConfig::default().build()
+    }
+}
+
+impl HttpClient {
+    pub fn get(&self, url: &str) -> HttpRequest {
+        self.request("GET", url)
+    }
+
+    pub fn post(&self, url: &str) -> HttpRequest {
+        self.request("POST", url)
+    }
+
+    pub fn request(&self, method: &str, url: &str) -> HttpRequest {
+        HttpRequest {
+            client: self.clone(),
+            request: self.inner.agent.request(method, url),
+        }
+    }
+
+    /// Count a request to the host, unless it would go over its rate limit.
+    fn acquire(&self, host: &str) -> Result<(), HttpError> {
+        let config = &self.inner.config;
+        let limit = match config.host_rate_limits.get(host) {
+            Some(limit) => *limit,
+            None => config.rate_limit,
+        };
+        let Some(limit) = limit else {
+            return Ok(());
+        };
+        let now = Instant::now();
+        let mut requests = self.inner.requests.lock().unwrap();
+        let made = requests.entry(host.to_string()).or_default();
+        while made
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= limit.per)
+        {
+            made.pop_front();
+        }
+        if made.len() >= limit.requests {
+            return Err(HttpError::RateLimited(host.to_string()));
+        }
+        made.push_back(now);
+        Ok(())
+    }
+}
+
+/// A request of a [HttpClient], made with [HttpRequest::call] or [HttpRequest::send_string].
+#[derive(Clone)]
+pub struct HttpRequest {
+    client: HttpClient,
+    request: ureq::Request,
+}
+
+impl HttpRequest {
+    pub fn query(self, param: &str, value: &str) -> Self {
+        Self {
+            request: self.request.query(param, value),
+            ..self
+        }
+    }
+
+    pub fn set(self, header: &str, value: &str) -> Self {
+        Self {
+            request: self.request.set(header, value),
+            ..self
+        }
+    }
+
+    /// A shorter timeout than the one of the client.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        let timeout = timeout.min(self.client.inner.config.timeout);
+        Self {
+            request: self.request.timeout(timeout),
+            ..self
+        }
+    }
+
+    /// The URL with the query, e.g. as the key of a [crate::http_cache::HttpCache].
+    pub fn url(&self) -> Result<String, HttpError> {
+        Ok(self.request.request_url()?.as_url().to_string())
+    }
+
+    pub fn call(self) -> Result<ureq::Response, HttpError> {
+        self.send(|request| request.call().map_err(Box::new))
+    }
+
+    pub fn send_string(self, body: &str) -> Result<ureq::Response, HttpError> {
+        self.send(|request| request.send_string(body).map_err(Box::new))
+    }
+
+    /// Make the request, retrying it after connection errors and responses saying the server is
+    /// busy. Only requests that can safely be made twice are retried, so a POST turning on a light
+    /// doesn't turn it on again.
+    fn send(
+        self,
+        send: impl Fn(ureq::Request) -> Result<ureq::Response, Box<ureq::Error>>,
+    ) -> Result<ureq::Response, HttpError> {
+        let host = self.request.request_url()?.host().to_string();
+        let config = &self.client.inner.config;
+        let idempotent = matches!(
+            self.request.method(),
+            "GET" | "HEAD" | "PUT" | "DELETE" | "OPTIONS"
+        );
+        let mut backoff = config.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            self.client.acquire(&host)?;
+            match send(self.request.clone()) {
+                Err(e) if idempotent && attempt < config.max_retries && is_retryable(&e) => {
+                    thread::sleep(backoff);
+                    backoff = (backoff * 2).min(config.max_backoff);
+                    attempt += 1;
+                }
+                result => return result.map_err(HttpError::Request),
+            }
+        }
+    }
+}
+
+fn is_retryable(error: &ureq::Error) -> bool {
+    match error {
+        ureq::Error::Transport(_) => true,
+        ureq::Error::Status(status, _) => matches!(status, 429 | 502 | 503 | 504),
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Responses of HTTP requests kept for a while, so that asking the same thing twice in a few
+/// minutes doesn't make the request again. Clones share the same responses, see
+/// [crate::Assistant::http_cache]. Each skill chooses how long its responses are kept.
+#[derive(Clone, Default)]
+pub struct HttpCache {
+    state: Arc<Mutex<CacheState>>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<String, CacheEntry>,
+    hits: u64,
+    misses: u64,
+}
+
+struct CacheEntry {
+    body: String,
+    fetched: Instant,
+    ttl: Duration,
+}
+
+impl CacheEntry {
+    fn is_fresh(&self, now: Instant) -> bool {
+        now.duration_since(self.fetched) < self.ttl
+    }
+}
+
+/// What is in a [HttpCache], see [HttpCache::stats].
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpCacheStats {
+    /// Requests answered from the cache.
+    pub hits: u64,
+    /// Requests that had to be made.
+    pub misses: u64,
+    pub entries: Vec<HttpCacheEntry>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpCacheEntry {
+    /// The key of the response, usually the URL of the request.
+    pub key: String,
+    /// How long ago the response was fetched.
+    pub age: Duration,
+    /// How long the response is kept.
+    pub ttl: Duration,
+}
+
+impl HttpCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The response for `key` if it was fetched less than `ttl` ago, otherwise the one `fetch`
+    /// returns, which is kept for `ttl`. Nothing is kept with a `ttl` of zero, and failed
+    /// requests aren't kept either.
+    pub fn get_or_fetch<E>(
+        &self,
+        key: &str,
+        ttl: Duration,
+        fetch: impl FnOnce() -> Result<String, E>,
+    ) -> Result<String, E> {
+        if ttl.is_zero() {
+            return fetch();
+        }
+        let now = Instant::now();
+        {
+            let mut state = self.state.lock().unwrap();
+            state.entries.retain(|_, entry| entry.is_fresh(now));
+            if let Some(body) = state.entries.get(key).map(|entry| entry.body.clone()) {
+                state.hits += 1;
+                return Ok(body);
+            }
+            state.misses += 1;
+        }
+        // Not locked while fetching, so other requests don't wait for this one
+        let body = fetch()?;
+        self.state.lock().unwrap().entries.insert(
+            key.to_string(),
+            CacheEntry {
+                body: body.clone(),
+                fetched: Instant::now(),
+                ttl,
+            },
+        );
+        Ok(body)
+    }
+
+    /// The hits and misses so far and the responses that are still fresh.
+    pub fn stats(&self) -> HttpCacheStats {
+        let now = Instant::now();
+        let state = self.state.lock().unwrap();
+        let mut entries: Vec<HttpCacheEntry> = state
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.is_fresh(now))
+            .map(|(key, entry)| HttpCacheEntry {
+                key: key.clone(),
+                age: now.duration_since(entry.fetched),
+                ttl: entry.ttl,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.key.cmp(&b.key));
+        HttpCacheStats {
+            hits: state.hits,
+            misses: state.misses,
+            entries,
+        }
+    }
+
+    /// Forget all responses, e.g. after changing the location of the weather.
+    pub fn clear(&self) {
+        self.state.lock().unwrap().entries.clear();
+    }
+}
@@ -0,0 +1,357 @@
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::{
+    http::{HttpClient, HttpError},
+    permissions::Capability,
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{Slot, SlotKind, SlotValue},
+    AssistantQuery,
+};
+
+#[derive(Error, Debug)]
+pub enum HueError {
+    #[error("Hue bridge request failed")]
+    Request(#[from] HttpError),
+    #[error("Failed to read the Hue bridge response")]
+    Response(#[from] std::io::Error),
+    #[error("The link button of the Hue bridge wasn't pressed")]
+    LinkButtonNotPressed,
+    #[error("The Hue bridge refused the request: {0}")]
+    Refused(String),
+}
+
+/// The error type of the bridge when pairing before the link button was pressed.
+const LINK_BUTTON_ERROR: u64 = 101;
+
+/// Ask the bridge at the address, like `192.168.1.20`, for the key of an application, e.g. for a
+/// pairing command. Fails with [HueError::LinkButtonNotPressed] until the link button of the
+/// bridge was pressed, within 30 seconds before. `device` names the application in the app.
+pub fn pair(client: &HttpClient, address: &str, device: &str) -> Result<String, HueError> {
+    let response: Value = client
+        .post(&format!("http://{}/api", address))
+        .set("Content-Type", "application/json")
+        .send_string(&json!({ "devicetype": device }).to_string())?
+        .into_json()?;
+    check(&response)?;
+    response
+        .pointer("/0/success/username")
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| HueError::Refused("No key in the response".to_string()))
+}
+
+/// The errors of the bridge come as an array of `{"error": {...}}`, even for GET requests.
+fn check(response: &Value) -> Result<(), HueError> {
+    let error = response
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find_map(|item| item.get("error"));
+    match error {
+        Some(error) if error["type"].as_u64() == Some(LINK_BUTTON_ERROR) => {
+            Err(HueError::LinkButtonNotPressed)
+        }
+        Some(error) => Err(HueError::Refused(
+            error["description"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+        )),
+        None => Ok(()),
+    }
+}
+
+/// A room or zone of the bridge, with the lights turned on and off together.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HueRoom {
+    pub id: String,
+    pub name: String,
+}
+
+/// A scene of the bridge, setting the lights of its room to saved colors and brightness.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HueScene {
+    pub id: String,
+    pub name: String,
+    /// The id of its [HueRoom], `None` for scenes of lights in several rooms.
+    pub room: Option<String>,
+}
+
+/// A Philips Hue bridge on the local network, with the key of [pair].
+pub struct HueBridge {
+    address: String,
+    key: String,
+    client: HttpClient,
+}
+
+/// The group of the bridge with all the lights.
+const ALL_LIGHTS: &str = "0";
+
+impl HueBridge {
+    pub fn new(address: impl Into<String>, key: impl Into<String>) -> Self {
+        Self {
+            address: address.into(),
+            key: key.into(),
+            client: HttpClient::default(),
+        }
+    }
+
+    /// Make the requests with a shared client, see [crate::AssistantConfig::http_client].
+    pub fn set_client(&mut self, client: HttpClient) {
+        self.client = client;
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("http://{}/api/{}/{}", self.address, self.key, path)
+    }
+
+    fn get(&self, path: &str) -> Result<Value, HueError> {
+        let response = self.client.get(&self.url(path)).call()?.into_json()?;
+        check(&response)?;
+        Ok(response)
+    }
+
+    pub fn rooms(&self) -> Result<Vec<HueRoom>, HueError> {
+        let groups = self.get("groups")?;
+        Ok(groups
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter(|(_, group)| matches!(group["type"].as_str(), Some("Room" | "Zone")))
+            .filter_map(|(id, group)| {
+                Some(HueRoom {
+                    id: id.clone(),
+                    name: group["name"].as_str()?.to_string(),
+                })
+            })
+            .collect())
+    }
+
+    pub fn scenes(&self) -> Result<Vec<HueScene>, HueError> {
+        let scenes = self.get("scenes")?;
+        Ok(scenes
+            .as_object()
+            .into_iter()
+            .flatten()
+            .filter_map(|(id, scene)| {
+                Some(HueScene {
+                    id: id.clone(),
+                    name: scene["name"].as_str()?.to_string(),
+                    room: scene["group"].as_str().map(str::to_string),
+                })
+            })
+            .collect())
+    }
+
+    /// Change the state of the lights of a room, or all of them without one, like
+    /// `{"on": true}`.
+    pub fn set_lights(&self, room: Option<&str>, state: Value) -> Result<(), HueError> {
+        let path = format!("groups/{}/action", room.unwrap_or(ALL_LIGHTS));
+        let response = self
+            .client
+            .request("PUT", &self.url(&path))
+            .set("Content-Type", "application/json")
+            .send_string(&state.to_string())?
+            .into_json()?;
+        check(&response)
+    }
+}
+
+const ON_INTENT: &str = "lights on";
+const OFF_INTENT: &str = "lights off";
+const DIM_INTENT: &str = "dim lights";
+const SCENE_INTENT: &str = "lights scene";
+
+/// A [Skill] controlling the lights of a Philips Hue bridge through its local API: "turn on the
+/// lights in the kitchen", "turn off the lights", "dim the bedroom lights to 30 percent" and "set
+/// the relax scene in the living room". The rooms and scenes are those of the bridge when the
+/// skill is created, so they are in the examples of its intents and in the vocabulary of speech
+/// recognition. Without a room, all the lights are changed.
+pub struct HueSkill {
+    bridge: HueBridge,
+    rooms: Vec<HueRoom>,
+    scenes: Vec<HueScene>,
+}
+
+impl HueSkill {
+    /// Load the rooms and scenes of the bridge. Without them, only all the lights together can be
+    /// controlled.
+    pub fn new(bridge: HueBridge) -> Self {
+        let rooms = bridge.rooms().unwrap_or_else(|e| {
+            warn!("Failed to get the rooms of the Hue bridge: {}", e);
+            Vec::new()
+        });
+        let scenes = bridge.scenes().unwrap_or_else(|e| {
+            warn!("Failed to get the scenes of the Hue bridge: {}", e);
+            Vec::new()
+        });
+        Self {
+            bridge,
+            rooms,
+            scenes,
+        }
+    }
+
+    fn room(&self, query: &AssistantQuery<'_, str>) -> Option<&HueRoom> {
+        match query.slots.get("room") {
+            Some(SlotValue::Entity(name)) => self.rooms.iter().find(|room| room.name == *name),
+            _ => None,
+        }
+    }
+
+    /// How the lights of the room are called in the responses.
+    fn lights(room: Option<&HueRoom>) -> String {
+        match room {
+            Some(room) => format!("the lights in the {}", room.name),
+            None => "the lights".to_string(),
+        }
+    }
+
+    fn switch(&self, query: &AssistantQuery<'_, str>, on: bool) -> Result<String, HueError> {
+        let room = self.room(query);
+        let id = room.map(|room| room.id.as_str());
+        self.bridge.set_lights(id, json!({ "on": on }))?;
+        Ok(format!(
+            "Turned {} {}.",
+            if on { "on" } else { "off" },
+            Self::lights(room)
+        ))
+    }
+
+    fn dim(&self, query: &AssistantQuery<'_, str>) -> Result<String, HueError> {
+        let Some(SlotValue::Number(level)) = query.slots.get("level") else {
+            return Ok("To what level?".to_string());
+        };
+        let room = self.room(query);
+        let percent = level.clamp(0., 100.).round();
+        // The brightness of the bridge goes from 1 to 254
+        let brightness = (percent / 100. * 253.).round() as u8 + 1;
+        let id = room.map(|room| room.id.as_str());
+        self.bridge
+            .set_lights(id, json!({ "on": true, "bri": brightness }))?;
+        Ok(format!(
+            "Set {} to {} percent.",
+            Self::lights(room),
+            percent
+        ))
+    }
+
+    /// Set the scene said, the one of the room if several rooms have a scene with that name.
+    fn set_scene(&self, query: &AssistantQuery<'_, str>) -> Result<String, HueError> {
+        let Some(SlotValue::Entity(name)) = query.slots.get("scene") else {
+            return Ok("Which scene?".to_string());
+        };
+        let room = self.room(query);
+        let mut scenes = self.scenes.iter().filter(|scene| scene.name == *name);
+        let scene = match room {
+            Some(room) => scenes.find(|scene| scene.room.as_ref() == Some(&room.id)),
+            None => scenes.next(),
+        };
+        let Some(scene) = scene else {
+            return Ok(format!(
+                "There's no {} scene for {}.",
+                name,
+                Self::lights(room)
+            ));
+        };
+        self.bridge
+            .set_lights(scene.room.as_deref(), json!({ "scene": scene.id }))?;
+        Ok(format!("Set the {} scene.", scene.name))
+    }
+}
+
+/// The names without duplicates, e.g. of scenes in several rooms.
+fn unique_names<'a>(names: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut names: Vec<String> = names.cloned().collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+impl Skill for HueSkill {
+    fn name(&self) -> &str {
+        "hue"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak, Capability::Network]
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        let rooms = unique_names(self.rooms.iter().map(|room| &room.name));
+        let scenes = unique_names(self.scenes.iter().map(|scene| &scene.name));
+        // The examples with a room, and its slot, only once the bridge gave some
+        let with_rooms = |mut all: Vec<String>, in_room: &[&str], mut slots: Vec<Slot>| {
+            if !rooms.is_empty() {
+                all.extend(in_room.iter().map(|e| e.to_string()));
+                slots.push(Slot::new("room", SlotKind::Entity(rooms.clone())));
+            }
+            (all, slots)
+        };
+        let intent = |name: &str, (examples, slots): (Vec<String>, Vec<Slot>)| {
+            IntentSpec::with_slots(name, examples, slots)
+        };
+        let mut intents = vec![
+            intent(
+                ON_INTENT,
+                with_rooms(
+                    examples(&["turn on the lights", "switch the lights on"]),
+                    &[
+                        "turn on the lights in the {room}",
+                        "turn on the {room} lights",
+                    ],
+                    Vec::new(),
+                ),
+            ),
+            intent(
+                OFF_INTENT,
+                with_rooms(
+                    examples(&["turn off the lights", "turn off all the lights"]),
+                    &[
+                        "turn off the lights in the {room}",
+                        "turn off the {room} lights",
+                    ],
+                    Vec::new(),
+                ),
+            ),
+            intent(
+                DIM_INTENT,
+                with_rooms(
+                    examples(&["dim the lights to {level} percent"]),
+                    &[
+                        "dim the {room} lights to {level} percent",
+                        "set the lights in the {room} to {level} percent",
+                    ],
+                    vec![Slot::new("level", SlotKind::Number)],
+                ),
+            ),
+        ];
+        if !scenes.is_empty() {
+            intents.push(intent(
+                SCENE_INTENT,
+                with_rooms(
+                    examples(&["set the {scene} scene", "turn on the {scene} scene"]),
+                    &["set the {scene} scene in the {room}"],
+                    vec![Slot::new("scene", SlotKind::Entity(scenes))],
+                ),
+            ));
+        }
+        intents
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        let response = match query.intent {
+            Some(intent @ (ON_INTENT | OFF_INTENT)) => self.switch(query, intent == ON_INTENT),
+            Some(DIM_INTENT) => self.dim(query),
+            Some(SCENE_INTENT) => self.set_scene(query),
+            _ => return,
+        };
+        let response = response.unwrap_or_else(|e| {
+            warn!("Failed to control the Hue lights: {}", e);
+            "Sorry, I couldn't reach the lights.".to_string()
+        });
+        _ = ctx.speak(response);
+    }
+}
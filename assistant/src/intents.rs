@@ -1,30 +1,231 @@
-use std::{fs::read, io};
+use std::{collections::HashSet, fs::read, io, sync::Arc, time::Instant};
 
 pub use fastembed::{
     InitOptions, InitOptionsUserDefined, TextEmbedding, TokenizerFiles, UserDefinedEmbeddingModel,
 };
+pub use regex::Regex;
 use thiserror::Error;
 
+use crate::{
+    metrics::{Metric, Metrics},
+    simd,
+    slots::{self, Slot, SlotValues, Template},
+    storage::Storage,
+};
+
+const EMBEDDINGS_NAMESPACE: &str = "embeddings";
+
 pub struct IntentsConfig<T> {
     intents: Vec<Intent<T>>,
-    model: EmbeddingModelSource,
+    model: Option<EmbeddingModelSource>,
+    threshold: f32,
+    group: Option<String>,
+    language: Option<String>,
+    context: Option<String>,
+    intent_threshold: Option<f32>,
+    fallback: Option<Fallback>,
+    scoring: Scoring,
+    negative_examples: Vec<String>,
+    cache: Option<Arc<dyn Storage>>,
+}
+
+/// How the similarities of a text to the examples of an intent make the score of the intent.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Scoring {
+    /// The similarity of the closest example.
+    #[default]
+    Closest,
+    /// The similarity to the mean of the examples, which is less thrown off by a single example
+    /// that is close to the sentences of another intent.
+    Centroid,
+    /// The mean similarity of the `k` closest examples, or of all of them if there are fewer.
+    TopKMean(usize),
+}
+
+/// Matches an intent without embeddings, for commands that have to work even when the text isn't
+/// close enough to the examples or embedding fails, like "stop". See
+/// [IntentsConfig::set_intent_fallback].
+#[derive(Clone, Debug, Default)]
+pub struct Fallback {
+    /// Words or phrases that have to appear in the text as whole words, ignoring case.
+    pub keywords: Vec<String>,
+    pub patterns: Vec<Regex>,
+}
+
+impl Fallback {
+    fn matches(&self, text: &str) -> bool {
+        let text_words = words(text);
+        self.keywords.iter().any(|keyword| {
+            let keyword = words(keyword);
+            !keyword.is_empty()
+                && text_words
+                    .windows(keyword.len())
+                    .any(|window| window == keyword)
+        }) || self.patterns.iter().any(|pattern| pattern.is_match(text))
+    }
+}
+
+/// The lowercase words of the text, without punctuation.
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
 }
 
 struct Intent<T> {
     id: T,
     examples: Vec<String>,
+    templates: Vec<Template>,
+    slots: Vec<Slot>,
+    group: Option<String>,
+    language: Option<String>,
+    context: Option<String>,
+    threshold: Option<f32>,
+    fallback: Option<Fallback>,
 }
 
 impl<T> IntentsConfig<T> {
     pub fn new(model: EmbeddingModelSource) -> Self {
+        Self::with_model(Some(model))
+    }
+
+    /// Without a model no intents can be added, and the recognizer built from it matches none.
+    pub(crate) fn without_model() -> Self {
+        Self::with_model(None)
+    }
+
+    fn with_model(model: Option<EmbeddingModelSource>) -> Self {
         Self {
             intents: Vec::new(),
             model,
+            threshold: 0.5,
+            group: None,
+            language: None,
+            context: None,
+            intent_threshold: None,
+            fallback: None,
+            scoring: Scoring::default(),
+            negative_examples: Vec::new(),
+            cache: None,
         }
     }
 
+    /// Keep the embeddings of the examples in the storage, so that [IntentRecognizer::build]
+    /// only embeds the examples that are new or changed since the last build with the same model.
+    pub fn set_embedding_cache(&mut self, storage: Arc<dyn Storage>) {
+        self.cache = Some(storage);
+    }
+
+    /// Put the intents added after this call in a group, e.g. "smart home", until another group
+    /// or `None` is set. Groups can be disabled with [IntentRecognizer::disable_group].
+    pub fn set_group(&mut self, group: Option<&str>) {
+        self.group = group.map(str::to_string);
+    }
+
+    /// Put the intents added after this call in a language, e.g. "it", until another language or
+    /// `None` is set. They are only recognized while it is active, see
+    /// [IntentRecognizer::set_language]. Intents without a language are recognized in all of them.
+    pub fn set_language(&mut self, language: Option<&str>) {
+        self.language = language.map(str::to_string);
+    }
+
+    /// Put the intents added after this call in a context, e.g. "music", until another context or
+    /// `None` is set. Only the intents of the current context are recognized, see
+    /// [IntentRecognizer::set_context], so intents without one aren't recognized in any context.
+    pub fn set_context(&mut self, context: Option<&str>) {
+        self.context = context.map(str::to_string);
+    }
+
+    /// Set the lowest similarity score for an intent to be recognized, 0.5 by default. Texts
+    /// closer to no intent result in [IntentRecognizerError::ScoreTooLow].
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Set how intents are scored, [Scoring::Closest] by default. Thresholds are compared with
+    /// these scores, so they may need to be lowered for the other strategies.
+    pub fn set_scoring(&mut self, scoring: Scoring) {
+        self.scoring = scoring;
+    }
+
+    /// Add sentences that shouldn't match any intent, e.g. "never mind" or what the speech
+    /// recognizer makes of background noise. Texts closer to one of them than to any intent
+    /// aren't recognized, as with [IntentRecognizerError::ScoreTooLow].
+    pub fn add_negative_examples(&mut self, examples: Vec<String>) {
+        self.negative_examples.extend(examples);
+    }
+
+    /// Give the intents added after this call their own threshold instead of the one of
+    /// [IntentsConfig::set_threshold], until another threshold or `None` is set. Useful for
+    /// intents whose examples are close to other sentences, which need a higher one.
+    pub fn set_intent_threshold(&mut self, threshold: Option<f32>) {
+        self.intent_threshold = threshold;
+    }
+
+    /// Give the intents added after this call a fallback, until another fallback or `None` is
+    /// set. When embedding fails or no intent scores high enough, the first enabled intent whose
+    /// fallback matches the text is recognized instead, with a score of 1.
+    pub fn set_intent_fallback(&mut self, fallback: Option<Fallback>) {
+        self.fallback = fallback;
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.intents.is_empty()
+    }
+
+    pub(crate) fn has_model(&self) -> bool {
+        self.model.is_some()
+    }
+
     pub fn add_intent(&mut self, id: T, examples: Vec<String>) {
-        self.intents.push(Intent { id, examples });
+        self.intents.push(Intent {
+            id,
+            examples,
+            templates: Vec::new(),
+            slots: Vec::new(),
+            group: self.group.clone(),
+            language: self.language.clone(),
+            context: self.context.clone(),
+            threshold: self.intent_threshold,
+            fallback: self.fallback.clone(),
+        });
+    }
+
+    /// Add an intent whose examples are templates containing slot placeholders, e.g.
+    /// `"set a timer for {duration}"` with a slot named `duration`. The slot values are extracted
+    /// from the recognized text by [IntentRecognizer::recognize_with_slots].
+    pub fn add_intent_with_slots(&mut self, id: T, templates: Vec<String>, slots: Vec<Slot>) {
+        let mut intent = Intent::with_slots(id, templates, slots, self.group.clone());
+        intent.language = self.language.clone();
+        intent.context = self.context.clone();
+        intent.threshold = self.intent_threshold;
+        intent.fallback = self.fallback.clone();
+        self.intents.push(intent);
+    }
+}
+
+impl<T> Intent<T> {
+    fn with_slots(id: T, templates: Vec<String>, slots: Vec<Slot>, group: Option<String>) -> Self {
+        let templates: Vec<Template> = templates
+            .iter()
+            .map(|template| Template::parse(template, &slots))
+            .collect();
+        let examples = templates
+            .iter()
+            .flat_map(|template| template.examples(&slots))
+            .collect();
+        Self {
+            id,
+            examples,
+            templates,
+            slots,
+            group,
+            language: None,
+            context: None,
+            threshold: None,
+            fallback: None,
+        }
     }
 }
 
@@ -33,14 +234,190 @@ pub enum EmbeddingModelSource {
     Local(UserDefinedEmbeddingModel, InitOptionsUserDefined),
 }
 
+impl EmbeddingModelSource {
+    /// Identifies the model in the embedding cache.
+    fn cache_hash(&self) -> u64 {
+        match self {
+            EmbeddingModelSource::Online(options) => {
+                fnv1a(format!("{:?}", options.model_name).as_bytes())
+            }
+            EmbeddingModelSource::Local(model, _) => fnv1a(&model.onnx_file),
+        }
+    }
+}
+
+/// Normalized embeddings of examples, see [IntentsConfig::set_embedding_cache]. Failing to read
+/// or write the cache is logged and the examples are embedded instead.
+struct EmbeddingCache {
+    storage: Arc<dyn Storage>,
+    model: u64,
+}
+
+impl EmbeddingCache {
+    fn key(&self, text: &str) -> String {
+        format!("{:016x}{:016x}", self.model, fnv1a(text.as_bytes()))
+    }
+
+    fn get(&self, text: &str) -> Option<Vec<f32>> {
+        match self.storage.get(EMBEDDINGS_NAMESPACE, &self.key(text)) {
+            Ok(Some(bytes)) if bytes.len() % 4 == 0 => Some(
+                bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                    .collect(),
+            ),
+            Ok(_) => None,
+            Err(e) => {
+                warn!("Failed to read cached embedding of \"{}\": {}", text, e);
+                None
+            }
+        }
+    }
+
+    fn set(&self, text: &str, embedding: &[f32]) {
+        let bytes: Vec<u8> = embedding.iter().flat_map(|x| x.to_le_bytes()).collect();
+        if let Err(e) = self
+            .storage
+            .set(EMBEDDINGS_NAMESPACE, &self.key(text), &bytes)
+        {
+            warn!("Failed to cache embedding of \"{}\": {}", text, e);
+        }
+    }
+}
+
+/// 64-bit FNV-1a, which unlike the hasher of the standard library doesn't change between Rust
+/// releases.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The normalized embeddings of the texts, taken from the cache when possible.
+fn embed_examples(
+    model: &TextEmbedding,
+    cache: Option<&EmbeddingCache>,
+    texts: &[String],
+) -> Result<Vec<Vec<f32>>, fastembed::Error> {
+    let mut embeddings: Vec<Option<Vec<f32>>> = texts
+        .iter()
+        .map(|text| cache.and_then(|cache| cache.get(text)))
+        .collect();
+    let missing: Vec<&String> = texts
+        .iter()
+        .zip(&embeddings)
+        .filter(|(_, embedding)| embedding.is_none())
+        .map(|(text, _)| text)
+        .collect();
+    if !missing.is_empty() {
+        #[cfg(feature = "tracing")]
+        let started = std::time::Instant::now();
+        let mut new = model.embed(missing.clone(), None)?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            embedded = missing.len(),
+            cached = texts.len() - missing.len(),
+            millis = started.elapsed().as_millis() as u64,
+            "embedded examples"
+        );
+        for (text, embedding) in missing.iter().zip(&mut new) {
+            simd::normalize(embedding);
+            if let Some(cache) = cache {
+                cache.set(text, embedding);
+            }
+        }
+        let mut new = new.into_iter();
+        for embedding in embeddings.iter_mut().filter(|e| e.is_none()) {
+            *embedding = new.next();
+        }
+    }
+    Ok(embeddings.into_iter().flatten().collect())
+}
+
 struct ProcessedIntent<T> {
     id: T,
+    group: Option<String>,
+    language: Option<String>,
+    context: Option<String>,
+    threshold: Option<f32>,
+    fallback: Option<Fallback>,
+    example_texts: Vec<String>,
+    /// Normalized embeddings of the examples, so that their dot product with a normalized
+    /// embedding is the cosine similarity.
     examples: Vec<Vec<f32>>,
+    /// The normalized mean of the examples, for [Scoring::Centroid].
+    centroid: Vec<f32>,
+    templates: Vec<Template>,
+    slots: Vec<Slot>,
+}
+
+/// IntentMatch is the result of [IntentRecognizer::recognize_with_slots].
+pub struct IntentMatch<'a, T> {
+    pub intent: &'a T,
+    pub score: f32,
+    /// Values of the slots found in the text. Empty if the intent has no slots or the text
+    /// doesn't follow any of its templates.
+    pub slots: SlotValues,
+}
+
+/// An intent ranked by [IntentRecognizer::recognize_top_n].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RankedIntent<'a, T> {
+    pub intent: &'a T,
+    /// The similarity of the closest example of the intent.
+    pub score: f32,
+    /// Whether the score reaches the threshold of the intent.
+    pub above_threshold: bool,
+}
+
+/// An intent close to a text, see [IntentRecognizer::candidates].
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntentCandidate {
+    /// The example of the intent closest to the text.
+    pub example: String,
+    pub score: f32,
+}
+
+/// Why a text is recognized as an intent or not, see [IntentRecognizer::explain].
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntentExplanation<'a, T> {
+    /// The intents closest to the text, best first.
+    pub candidates: Vec<ExplainedIntent<'a, T>>,
+    /// The similarity of the closest negative example, which the best intent has to beat.
+    pub negative_score: Option<f32>,
+    /// Whether the best candidate is recognized, leaving fallbacks aside.
+    pub recognized: bool,
+}
+
+/// An intent close to a text, see [IntentExplanation].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExplainedIntent<'a, T> {
+    pub intent: &'a T,
+    /// The example of the intent closest to the text.
+    pub example: String,
+    /// The similarity of the example.
+    pub example_score: f32,
+    /// The score of the intent, the similarity of the example unless another [Scoring] is used.
+    pub score: f32,
+    /// The lowest score for the intent to be recognized.
+    pub threshold: f32,
+    /// How much higher the score is than the one of the next intent, `None` if there is none.
+    pub gap: Option<f32>,
 }
 
 pub struct IntentRecognizer<T> {
     intents: Vec<ProcessedIntent<T>>,
-    model: TextEmbedding,
+    model: Option<TextEmbedding>,
+    cache: Option<EmbeddingCache>,
+    scoring: Scoring,
+    /// Normalized like the examples of the intents.
+    negative_examples: Vec<Vec<f32>>,
+    threshold: f32,
+    disabled_groups: HashSet<String>,
+    allowed_groups: Option<HashSet<String>>,
+    language: Option<String>,
+    context: Option<String>,
+    metrics: Option<Metrics>,
 }
 
 #[derive(Error, Debug)]
@@ -49,6 +426,10 @@ pub enum IntentRecognizerBuildError {
     TextEmbeddingError(#[from] fastembed::Error),
     #[error("No intents provided")]
     NoIntentsProvided,
+    #[error("No embedding model provided for the intents")]
+    NoEmbeddingModel,
+    #[error("Failed to embed the intents")]
+    EmbeddingError(#[from] IntentRecognizerError),
 }
 
 #[derive(Error, Debug)]
@@ -57,70 +438,467 @@ pub enum IntentRecognizerError {
     TextEmbeddingError(#[from] fastembed::Error),
     #[error("Failed to recognize intent, matched none with a high enough score")]
     ScoreTooLow,
+    #[error("No embedding model to recognize intents with")]
+    NoEmbeddingModel,
 }
 
 impl<T> IntentRecognizer<T> {
     pub fn build(config: IntentsConfig<T>) -> Result<Self, IntentRecognizerBuildError> {
-        if config.intents.is_empty() {
+        Self::build_with_progress(config, |_, _| ())
+    }
+
+    /// Same as [IntentRecognizer::build], calling `progress` with how many of the intents were
+    /// embedded and how many there are, starting with none once the model is loaded.
+    pub fn build_with_progress(
+        config: IntentsConfig<T>,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<Self, IntentRecognizerBuildError> {
+        if config.intents.is_empty() && config.model.is_some() {
             return Err(IntentRecognizerBuildError::NoIntentsProvided);
         }
 
+        let cache = config
+            .cache
+            .zip(config.model.as_ref().map(EmbeddingModelSource::cache_hash))
+            .map(|(storage, model)| EmbeddingCache { storage, model });
         let model = match config.model {
-            EmbeddingModelSource::Online(config) => TextEmbedding::try_new(config),
-            EmbeddingModelSource::Local(model, config) => {
-                TextEmbedding::try_new_from_user_defined(model, config)
+            Some(EmbeddingModelSource::Online(config)) => Some(TextEmbedding::try_new(config)?),
+            Some(EmbeddingModelSource::Local(model, config)) => {
+                Some(TextEmbedding::try_new_from_user_defined(model, config)?)
             }
-        }?;
-
-        Ok(Self {
-            intents: config
-                .intents
-                .into_iter()
-                .map(|intent| {
-                    model
-                        .embed(intent.examples, None)
-                        .map(|examples| ProcessedIntent {
-                            id: intent.id,
-                            examples,
-                        })
-                })
-                .collect::<Result<_, _>>()?,
+            None if config.intents.is_empty() => None,
+            None => return Err(IntentRecognizerBuildError::NoEmbeddingModel),
+        };
+
+        let negative_examples = match &model {
+            Some(model) => embed_examples(model, cache.as_ref(), &config.negative_examples)?,
+            None => Vec::new(),
+        };
+        let mut recognizer = Self {
+            intents: Vec::with_capacity(config.intents.len()),
             model,
+            cache,
+            scoring: config.scoring,
+            negative_examples,
+            threshold: config.threshold,
+            disabled_groups: HashSet::new(),
+            allowed_groups: None,
+            language: None,
+            context: None,
+            metrics: None,
+        };
+        let total = config.intents.len();
+        progress(0, total);
+        for (embedded, intent) in config.intents.into_iter().enumerate() {
+            recognizer.push(intent)?;
+            progress(embedded + 1, total);
+        }
+        Ok(recognizer)
+    }
+
+    fn push(&mut self, intent: Intent<T>) -> Result<(), IntentRecognizerError> {
+        let model = self
+            .model
+            .as_ref()
+            .ok_or(IntentRecognizerError::NoEmbeddingModel)?;
+        let examples = embed_examples(model, self.cache.as_ref(), &intent.examples)?;
+        let mut centroid = vec![0.0; examples.first().map_or(0, Vec::len)];
+        for example in &examples {
+            centroid.iter_mut().zip(example).for_each(|(c, x)| *c += x);
+        }
+        simd::normalize(&mut centroid);
+        self.intents.push(ProcessedIntent {
+            id: intent.id,
+            group: intent.group,
+            language: intent.language,
+            context: intent.context,
+            threshold: intent.threshold,
+            fallback: intent.fallback,
+            example_texts: intent.examples,
+            examples,
+            centroid,
+            templates: intent.templates,
+            slots: intent.slots,
+        });
+        Ok(())
+    }
+
+    /// Change the lowest score for an intent to be recognized, see [IntentsConfig::set_threshold].
+    /// Intents with their own threshold keep it.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// Add an intent after building, e.g. for a phrase the user taught the assistant. Its
+    /// examples are embedded straight away.
+    pub fn add_intent(
+        &mut self,
+        id: T,
+        examples: Vec<String>,
+    ) -> Result<(), IntentRecognizerError> {
+        self.add_intent_in_group(id, examples, Vec::new(), None)
+    }
+
+    /// Like [IntentRecognizer::add_intent], see [IntentsConfig::add_intent_with_slots].
+    pub fn add_intent_with_slots(
+        &mut self,
+        id: T,
+        templates: Vec<String>,
+        slots: Vec<Slot>,
+    ) -> Result<(), IntentRecognizerError> {
+        self.add_intent_in_group(id, templates, slots, None)
+    }
+
+    /// Add an intent in a group, see [IntentsConfig::set_group]. Without slots, the templates are
+    /// the examples.
+    pub fn add_intent_in_group(
+        &mut self,
+        id: T,
+        templates: Vec<String>,
+        slots: Vec<Slot>,
+        group: Option<&str>,
+    ) -> Result<(), IntentRecognizerError> {
+        let group = group.map(str::to_string);
+        let intent = if slots.is_empty() {
+            Intent {
+                id,
+                examples: templates,
+                templates: Vec::new(),
+                slots,
+                group,
+                language: None,
+                context: None,
+                threshold: None,
+                fallback: None,
+            }
+        } else {
+            Intent::with_slots(id, templates, slots, group)
+        };
+        self.push(intent)
+    }
+
+    /// Stop recognizing the intents with this id. Returns whether there were any.
+    pub fn remove_intent(&mut self, id: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.remove_intents_where(|intent| intent == id)
+    }
+
+    pub(crate) fn remove_intents_where(&mut self, mut remove: impl FnMut(&T) -> bool) -> bool {
+        let count = self.intents.len();
+        self.intents.retain(|intent| !remove(&intent.id));
+        self.intents.len() != count
+    }
+
+    /// Stop recognizing the intents of a group, see [IntentsConfig::set_group].
+    pub fn disable_group(&mut self, group: &str) {
+        self.disabled_groups.insert(group.to_string());
+    }
+
+    pub fn enable_group(&mut self, group: &str) {
+        self.disabled_groups.remove(group);
+    }
+
+    pub fn is_group_enabled(&self, group: &str) -> bool {
+        !self.disabled_groups.contains(group)
+    }
+
+    /// The groups that were disabled with [IntentRecognizer::disable_group].
+    pub fn disabled_groups(&self) -> impl Iterator<Item = &str> {
+        self.disabled_groups.iter().map(String::as_str)
+    }
+
+    /// Only recognize the enabled intents of these groups, e.g. for guests, or all enabled
+    /// intents with `None`. Intents without a group aren't in any of them.
+    pub fn set_allowed_groups(&mut self, groups: Option<&[String]>) {
+        self.allowed_groups = groups.map(|groups| groups.iter().cloned().collect());
+    }
+
+    /// Only recognize the intents of this language and the ones without a language, see
+    /// [IntentsConfig::set_language]. All intents are recognized with `None`.
+    pub fn set_language(&mut self, language: Option<&str>) {
+        self.language = language.map(str::to_string);
+    }
+
+    /// Only recognize the intents of this context, see [IntentsConfig::set_context]. `None`, the
+    /// default, recognizes the intents without a context.
+    pub fn set_context(&mut self, context: Option<&str>) {
+        self.context = context.map(str::to_string);
+    }
+
+    /// Record how long embedding texts takes, see [Metric::Embedding].
+    pub(crate) fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = Some(metrics);
+    }
+
+    /// The intents that can currently be recognized, with their index.
+    fn enabled_intents(&self) -> impl Iterator<Item = (usize, &ProcessedIntent<T>)> {
+        self.intents.iter().enumerate().filter(|(_, intent)| {
+            let group = intent.group.as_ref();
+            group.is_none_or(|group| !self.disabled_groups.contains(group))
+                && self
+                    .allowed_groups
+                    .as_ref()
+                    .is_none_or(|allowed| group.is_some_and(|group| allowed.contains(group)))
+                && self.language.as_ref().is_none_or(|language| {
+                    intent.language.as_ref().is_none_or(|own| own == language)
+                })
+                && intent.context == self.context
         })
     }
 
     pub fn recognize(&self, text: &str) -> Result<&T, IntentRecognizerError> {
-        let target = self
+        self.recognize_with_score(text).map(|(intent, _)| intent)
+    }
+
+    /// Same as [IntentRecognizer::recognize], but also returns the cosine similarity between the
+    /// text and the closest example of the matched intent.
+    pub fn recognize_with_score(&self, text: &str) -> Result<(&T, f32), IntentRecognizerError> {
+        self.closest(text)
+            .map(|(index, score)| (&self.intents[index].id, score))
+    }
+
+    /// Same as [IntentRecognizer::recognize_with_score], but also extracts the values of the
+    /// matched intent's slots.
+    pub fn recognize_with_slots(
+        &self,
+        text: &str,
+    ) -> Result<IntentMatch<'_, T>, IntentRecognizerError> {
+        let (index, score, slots) = self.recognize_index(text)?;
+        Ok(IntentMatch {
+            intent: &self.intents[index].id,
+            score,
+            slots,
+        })
+    }
+
+    /// Same as [IntentRecognizer::recognize_with_slots], but returns the index of the intent, to
+    /// be looked up with [IntentRecognizer::intent], so the result doesn't borrow the recognizer.
+    pub(crate) fn recognize_index(
+        &self,
+        text: &str,
+    ) -> Result<(usize, f32, SlotValues), IntentRecognizerError> {
+        let (index, score) = self.closest(text)?;
+        let intent = &self.intents[index];
+        let slots = intent
+            .templates
+            .iter()
+            .find_map(|template| template.extract(text, &intent.slots))
+            .unwrap_or_default();
+        Ok((index, score, slots))
+    }
+
+    pub(crate) fn intent(&self, index: usize) -> &T {
+        &self.intents[index].id
+    }
+
+    /// The words of the enabled intents, sorted, for a grammar limiting speech recognition to
+    /// sentences made of them, see [crate::stt::SessionOptions::grammar]. Free text slots can't be
+    /// recognized with it.
+    pub fn grammar(&self) -> Vec<String> {
+        let mut words: Vec<String> = self
+            .enabled_intents()
+            .flat_map(|(_, intent)| {
+                if intent.templates.is_empty() {
+                    intent
+                        .example_texts
+                        .iter()
+                        .flat_map(|example| slots::normalize(example))
+                        .collect::<Vec<_>>()
+                } else {
+                    intent
+                        .templates
+                        .iter()
+                        .flat_map(|template| template.vocabulary(&intent.slots))
+                        .collect()
+                }
+            })
+            .collect();
+        words.sort();
+        words.dedup();
+        words
+    }
+
+    /// The `count` intents closest to the text, best first, even if none of them is close enough
+    /// to be recognized. Useful to log why a text wasn't understood.
+    pub fn candidates(
+        &self,
+        text: &str,
+        count: usize,
+    ) -> Result<Vec<IntentCandidate>, IntentRecognizerError> {
+        Ok(self
+            .rank(text)?
+            .into_iter()
+            .take(count)
+            .map(|(index, example, score)| IntentCandidate {
+                example: self.intents[index].example_texts[example].clone(),
+                score,
+            })
+            .collect())
+    }
+
+    /// The `count` intents closest to the text, best first, with their scores, e.g. to ask "did
+    /// you mean..." when the best ones are close to each other.
+    pub fn recognize_top_n(
+        &self,
+        text: &str,
+        count: usize,
+    ) -> Result<Vec<RankedIntent<'_, T>>, IntentRecognizerError> {
+        Ok(self
+            .rank(text)?
+            .into_iter()
+            .take(count)
+            .map(|(index, _, score)| RankedIntent {
+                intent: &self.intents[index].id,
+                score,
+                above_threshold: score >= self.threshold_of(index),
+            })
+            .collect())
+    }
+
+    /// The `count` intents closest to the text, best first, with the example each of them matched
+    /// best and how far ahead of the next intent it is. A small gap after the best intent means
+    /// that another one has examples too close to it.
+    pub fn explain(
+        &self,
+        text: &str,
+        count: usize,
+    ) -> Result<IntentExplanation<'_, T>, IntentRecognizerError> {
+        let target = self.embed(text)?;
+        let negative_score = self
+            .negative_examples
+            .iter()
+            .map(|e| simd::dot(e, &target))
+            .reduce(f32::max);
+        let ranked = self.rank_embedding(&target);
+        let recognized = ranked.first().is_some_and(|&(index, _, score)| {
+            score >= self.threshold_of(index) && negative_score.is_none_or(|n| score > n)
+        });
+        let candidates = ranked
+            .iter()
+            .enumerate()
+            .take(count)
+            .map(|(position, &(index, closest, score))| {
+                let intent = &self.intents[index];
+                ExplainedIntent {
+                    intent: &intent.id,
+                    example: intent.example_texts[closest].clone(),
+                    example_score: simd::dot(&intent.examples[closest], &target),
+                    score,
+                    threshold: self.threshold_of(index),
+                    gap: ranked.get(position + 1).map(|next| score - next.2),
+                }
+            })
+            .collect();
+        Ok(IntentExplanation {
+            candidates,
+            negative_score,
+            recognized,
+        })
+    }
+
+    /// The enabled intents with their index, the index of their closest example and their score,
+    /// best first.
+    fn rank(&self, text: &str) -> Result<Vec<(usize, usize, f32)>, IntentRecognizerError> {
+        let target = self.embed(text)?;
+        Ok(self.rank_embedding(&target))
+    }
+
+    fn rank_embedding(&self, target: &[f32]) -> Vec<(usize, usize, f32)> {
+        let mut ranked: Vec<(usize, usize, f32)> = self
+            .enabled_intents()
+            .filter_map(|(index, intent)| {
+                let mut similarities: Vec<(usize, f32)> = intent
+                    .examples
+                    .iter()
+                    .map(|e| simd::dot(e, target))
+                    .enumerate()
+                    .collect();
+                similarities.sort_by(|a, b| b.1.total_cmp(&a.1));
+                let &(closest, similarity) = similarities.first()?;
+                let score = match self.scoring {
+                    Scoring::Closest => similarity,
+                    Scoring::Centroid => simd::dot(&intent.centroid, target),
+                    Scoring::TopKMean(k) => {
+                        let top = &similarities[..k.clamp(1, similarities.len())];
+                        top.iter().map(|(_, s)| s).sum::<f32>() / top.len() as f32
+                    }
+                };
+                Some((index, closest, score))
+            })
+            .collect();
+        ranked.sort_by(|a, b| b.2.total_cmp(&a.2));
+        ranked
+    }
+
+    /// The lowest score for the intent to be recognized.
+    fn threshold_of(&self, index: usize) -> f32 {
+        self.intents[index].threshold.unwrap_or(self.threshold)
+    }
+
+    /// Embed the text, normalized like the examples.
+    pub(crate) fn embed(&self, text: &str) -> Result<Vec<f32>, IntentRecognizerError> {
+        let started = Instant::now();
+        let mut embedding = self
             .model
+            .as_ref()
+            .ok_or(IntentRecognizerError::NoEmbeddingModel)?
             .embed(vec![text], None)?
             .into_iter()
             .next()
             .unwrap();
-
-        let (intent, score) = find_closest(&self.intents, target);
-
-        if score < 0.5 {
-            return Err(IntentRecognizerError::ScoreTooLow);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            text,
+            micros = started.elapsed().as_micros() as u64,
+            "embedded text"
+        );
+        if let Some(metrics) = &self.metrics {
+            metrics.record(Metric::Embedding, started.elapsed());
         }
+        simd::normalize(&mut embedding);
+        Ok(embedding)
+    }
 
-        Ok(intent)
+    /// The index of the closest enabled intent, with its score. It is only recognized if the score
+    /// reaches the threshold of that intent, even if the lower one of another intent is reached.
+    /// Fallbacks are tried if embedding fails or no intent is recognized.
+    fn closest(&self, text: &str) -> Result<(usize, f32), IntentRecognizerError> {
+        self.closest_embedding(text).or_else(|e| {
+            self.enabled_intents()
+                .find(|(_, intent)| intent.fallback.as_ref().is_some_and(|f| f.matches(text)))
+                .map(|(index, _)| (index, 1.0))
+                .ok_or(e)
+        })
     }
-}
 
-fn compute_cosine_distance(a: &[f32], b: &[f32]) -> f32 {
-    let dot_product: f32 = a.iter().zip(b.iter()).map(|(x, y)| x * y).sum();
-    let magnitude_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
-    let magnitude_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
-    dot_product / (magnitude_a * magnitude_b)
-}
+    fn closest_embedding(&self, text: &str) -> Result<(usize, f32), IntentRecognizerError> {
+        let target = self.embed(text)?;
+        let negative = self
+            .negative_examples
+            .iter()
+            .map(|e| simd::dot(e, &target))
+            .fold(f32::NEG_INFINITY, f32::max);
 
-fn find_closest<T>(intents: &[ProcessedIntent<T>], target: Vec<f32>) -> (&T, f32) {
-    intents
-        .into_iter()
-        .flat_map(|ProcessedIntent { id, examples }| examples.iter().map(move |e| (id, e)))
-        .map(|(n, e)| (n, compute_cosine_distance(e, &target)))
-        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Less))
-        .unwrap()
+        let ranked = self.rank_embedding(&target);
+        #[cfg(feature = "tracing")]
+        for &(index, closest, score) in ranked.iter().take(3) {
+            tracing::debug!(
+                example = %self.intents[index].example_texts[closest],
+                score,
+                threshold = self.threshold_of(index),
+                negative,
+                "intent score"
+            );
+        }
+        match ranked.first() {
+            Some(&(index, _, score)) if score >= self.threshold_of(index) && score > negative => {
+                Ok((index, score))
+            }
+            _ => Err(IntentRecognizerError::ScoreTooLow),
+        }
+    }
 }
 
 pub struct EmbeddingModelFilePaths<'a> {
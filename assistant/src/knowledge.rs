@@ -0,0 +1,390 @@
+use serde::Deserialize;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::{
+    http::{HttpClient, HttpError},
+    http_cache::HttpCache,
+    permissions::Capability,
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{Slot, SlotKind, SlotValue},
+    AssistantQuery,
+};
+
+#[derive(Error, Debug)]
+pub enum KnowledgeError {
+    #[error("Knowledge request failed")]
+    Request(#[from] HttpError),
+    #[error("Failed to read the knowledge response")]
+    Response(#[from] std::io::Error),
+}
+
+/// Where a [KnowledgeSkill] looks subjects up, like [Wikipedia] or a [Kiwix] server with an
+/// offline copy of it.
+pub trait KnowledgeSource {
+    /// The first paragraph of the article about the subject as plain text, `None` if there is
+    /// none.
+    fn lookup(&self, subject: &str) -> Result<Option<String>, KnowledgeError>;
+
+    /// Whether the source works without the internet, so it is asked first and while the
+    /// assistant is offline.
+    fn is_local(&self) -> bool {
+        false
+    }
+}
+
+/// The REST API of Wikipedia, finding the article with a title search first.
+pub struct Wikipedia {
+    language: String,
+    client: HttpClient,
+    cache: Option<(HttpCache, Duration)>,
+}
+
+impl Wikipedia {
+    /// `language` is the code of the Wikipedia, like `en`.
+    pub fn new(language: impl Into<String>) -> Self {
+        Self {
+            language: language.into(),
+            client: HttpClient::default(),
+            cache: None,
+        }
+    }
+
+    /// Make the requests with a shared client, see [crate::AssistantConfig::http_client].
+    pub fn set_client(&mut self, client: HttpClient) {
+        self.client = client;
+    }
+
+    /// Keep the responses in the cache for `ttl`. Every request is made by default.
+    pub fn set_cache(&mut self, cache: HttpCache, ttl: Duration) {
+        self.cache = Some((cache, ttl));
+    }
+
+    /// The body of the response, `None` if there is nothing at the URL.
+    fn get(&self, url: &str, query: &[(&str, &str)]) -> Result<Option<String>, KnowledgeError> {
+        let mut request = self.client.get(url);
+        for (name, value) in query {
+            request = request.query(name, value);
+        }
+        fetch(request, self.cache.as_ref())
+    }
+}
+
+#[derive(Deserialize)]
+struct WikipediaSearch {
+    pages: Vec<WikipediaPage>,
+}
+
+#[derive(Deserialize)]
+struct WikipediaPage {
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct WikipediaSummary {
+    #[serde(rename = "type")]
+    kind: String,
+    extract: String,
+}
+
+impl KnowledgeSource for Wikipedia {
+    fn lookup(&self, subject: &str) -> Result<Option<String>, KnowledgeError> {
+        let base = format!("https://{}.wikipedia.org", self.language);
+        let Some(search) = self.get(
+            &format!("{}/w/rest.php/v1/search/title", base),
+            &[("q", subject), ("limit", "1")],
+        )?
+        else {
+            return Ok(None);
+        };
+        let search: WikipediaSearch = parse_json(&search)?;
+        let Some(page) = search.pages.first() else {
+            return Ok(None);
+        };
+        let url = format!(
+            "{}/api/rest_v1/page/summary/{}",
+            base,
+            encode_path_segment(&page.key)
+        );
+        let Some(summary) = self.get(&url, &[])? else {
+            return Ok(None);
+        };
+        let summary: WikipediaSummary = parse_json(&summary)?;
+        // A list of articles the subject could be isn't worth reading out
+        Ok(
+            (summary.kind != "disambiguation" && !summary.extract.is_empty())
+                .then_some(summary.extract),
+        )
+    }
+}
+
+/// A kiwix-serve server with a ZIM archive of Wikipedia, or another wiki, so questions are
+/// answered without the internet. The article is the first result of its full text search.
+pub struct Kiwix {
+    url: String,
+    book: String,
+    client: HttpClient,
+}
+
+impl Kiwix {
+    /// `url` is the address of the server, like `http://localhost:8080`, and `book` the name of
+    /// the archive in its library, like `wikipedia_en_all_nopic`.
+    pub fn new(url: impl Into<String>, book: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            book: book.into(),
+            client: HttpClient::default(),
+        }
+    }
+
+    /// Make the requests with a shared client, see [crate::AssistantConfig::http_client].
+    pub fn set_client(&mut self, client: HttpClient) {
+        self.client = client;
+    }
+}
+
+impl KnowledgeSource for Kiwix {
+    fn lookup(&self, subject: &str) -> Result<Option<String>, KnowledgeError> {
+        let search = self
+            .client
+            .get(&format!("{}/search", self.url))
+            .query("books.name", &self.book)
+            .query("pattern", subject);
+        let Some(results) = fetch(search, None)? else {
+            return Ok(None);
+        };
+        let Some(path) = results
+            .split("href=\"")
+            .skip(1)
+            .filter_map(|link| link.split('"').next())
+            .find(|link| link.starts_with("/content/"))
+        else {
+            return Ok(None);
+        };
+        let article = self
+            .client
+            .get(&format!("{}{}", self.url, decode_entities(path)));
+        Ok(fetch(article, None)?.and_then(|html| first_paragraph(&html)))
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+}
+
+/// Make the request through the cache, `None` if the server has nothing at the URL.
+fn fetch(
+    request: crate::http::HttpRequest,
+    cache: Option<&(HttpCache, Duration)>,
+) -> Result<Option<String>, KnowledgeError> {
+    let fetch = || -> Result<String, KnowledgeError> { Ok(request.clone().call()?.into_string()?) };
+    let body = match cache {
+        Some((cache, ttl)) => cache.get_or_fetch(&request.url()?, *ttl, fetch),
+        None => fetch(),
+    };
+    match body {
+        Ok(body) => Ok(Some(body)),
+        Err(KnowledgeError::Request(HttpError::Request(e)))
+            if matches!(*e, ureq::Error::Status(404, _)) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn parse_json<'a, T: Deserialize<'a>>(body: &'a str) -> Result<T, KnowledgeError> {
+    Ok(serde_json::from_str(body).map_err(std::io::Error::from)?)
+}
+
+/// Percent-encode the characters that can't be in a segment of a URL path.
+fn encode_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// The text of the first paragraph of an HTML page that has some, without its tags.
+fn first_paragraph(html: &str) -> Option<String> {
+    html.split("<p").skip(1).find_map(|paragraph| {
+        // Not another tag starting with p, like <pre>
+        let paragraph = match paragraph.strip_prefix('>') {
+            Some(paragraph) => paragraph,
+            None => paragraph.strip_prefix(' ')?.split_once('>')?.1,
+        };
+        let paragraph = paragraph.split("</p>").next()?;
+        let mut text = String::new();
+        let mut in_tag = false;
+        for c in paragraph.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' => in_tag = false,
+                c if !in_tag => text.push(c),
+                _ => (),
+            }
+        }
+        let text = decode_entities(text.trim());
+        (text.split_whitespace().count() >= 5).then_some(text)
+    })
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// The text shortened for speech: without what is in parentheses or brackets, like dates,
+/// pronunciations and references, and with as many of its first sentences as fit in
+/// `max_length` characters, but at least the first one.
+pub fn summarize(text: &str, max_length: usize) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut depth = 0usize;
+    for c in text.chars() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            c if depth == 0 => plain.push(c),
+            _ => (),
+        }
+    }
+    let plain = plain.split_whitespace().collect::<Vec<_>>().join(" ");
+    let plain = plain.replace(" ,", ",").replace(" .", ".");
+
+    let mut summary = String::new();
+    for sentence in sentences(&plain) {
+        if !summary.is_empty() && summary.len() + sentence.len() + 1 > max_length {
+            break;
+        }
+        if !summary.is_empty() {
+            summary.push(' ');
+        }
+        summary.push_str(sentence);
+    }
+    summary
+}
+
+/// The sentences of the text, ending where a period, question or exclamation mark is followed
+/// by a word starting with a capital letter.
+fn sentences(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let end = rest
+            .char_indices()
+            .find(|&(i, c)| {
+                matches!(c, '.' | '?' | '!')
+                    && rest[i + 1..].starts_with(' ')
+                    && rest[i + 2..].starts_with(char::is_uppercase)
+            })
+            .map_or(rest.len(), |(i, _)| i + 1);
+        let sentence = &rest[..end];
+        rest = rest[end..].trim_start();
+        Some(sentence)
+    })
+}
+
+const LOOKUP_INTENT: &str = "look up subject";
+
+/// A [Skill] answering questions like "who is Ada Lovelace" with the start of the article about
+/// the subject, from the first of its [KnowledgeSource]s that has one, local ones first.
+/// Enabled with the `knowledge` feature.
+pub struct KnowledgeSkill {
+    sources: Vec<Box<dyn KnowledgeSource>>,
+    max_length: usize,
+}
+
+impl KnowledgeSkill {
+    pub fn new(sources: Vec<Box<dyn KnowledgeSource>>) -> Self {
+        let mut sources = sources;
+        // Stable, so the order is kept among the local and the other sources
+        sources.sort_by_key(|source| !source.is_local());
+        Self {
+            sources,
+            max_length: 300,
+        }
+    }
+
+    /// How many characters of the article are said at most, see [summarize]. 300 by default.
+    pub fn set_max_length(&mut self, max_length: usize) {
+        self.max_length = max_length;
+    }
+
+    fn answer(&self, query: &AssistantQuery<'_, str>, online: bool) -> String {
+        let Some(SlotValue::Text(subject)) = query.slots.get("subject") else {
+            return "Who or what should I look up?".to_string();
+        };
+        let mut failed = false;
+        for source in self.sources.iter().filter(|s| online || s.is_local()) {
+            match source.lookup(subject) {
+                Ok(Some(text)) => return summarize(&text, self.max_length),
+                Ok(None) => (),
+                Err(e) => {
+                    warn!("Failed to look up {}: {}", subject, e);
+                    failed = true;
+                }
+            }
+        }
+        if failed {
+            "Sorry, I couldn't look that up right now.".to_string()
+        } else {
+            format!("I don't know anything about {}.", subject)
+        }
+    }
+}
+
+impl Skill for KnowledgeSkill {
+    fn name(&self) -> &str {
+        "knowledge"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak, Capability::Network]
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        vec![IntentSpec::with_slots(
+            LOOKUP_INTENT,
+            examples(&[
+                "who is {subject}",
+                "who was {subject}",
+                "tell me about {subject}",
+                "what do you know about {subject}",
+            ]),
+            vec![Slot::new("subject", SlotKind::FreeText)],
+        )]
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        let response = self.answer(query, true);
+        _ = ctx.speak(response);
+    }
+
+    #[cfg(feature = "offline")]
+    fn needs_network(&self) -> bool {
+        self.sources.iter().any(|source| !source.is_local())
+    }
+
+    /// Only from the local sources.
+    #[cfg(feature = "offline")]
+    fn handle_offline(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        let response = if self.sources.iter().any(|source| source.is_local()) {
+            self.answer(query, false)
+        } else {
+            crate::connectivity::OFFLINE_RESPONSE.to_string()
+        };
+        _ = ctx.speak(response);
+    }
+}
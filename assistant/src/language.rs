@@ -0,0 +1,111 @@
+use ::tts::{Tts, Voice};
+use std::collections::HashMap;
+use thiserror::Error;
+
+use crate::{
+    stt::SpeechRecognizer,
+    tts::{set_voice, TtsConfigError, VoiceSelection},
+};
+
+/// A language the assistant can switch to, with its own speech recognizer and TTS voice, see
+/// [crate::AssistantConfig::add_language]. Its intents are added after
+/// [crate::AssistantConfig::set_intent_language].
+pub struct Language {
+    speech_recognizer: Box<dyn SpeechRecognizer>,
+    voice: Option<VoiceSelection>,
+}
+
+#[derive(Error, Debug)]
+pub enum LanguageError {
+    #[error("No language named {0}")]
+    UnknownLanguage(String),
+    #[error("Failed to switch to the voice of the language")]
+    Voice(#[from] TtsConfigError),
+}
+
+impl Language {
+    /// A language recognized by the speech recognizer, e.g. a [crate::stt::VoskRecognizer] with
+    /// a model of the language.
+    pub fn new(speech_recognizer: impl SpeechRecognizer + 'static) -> Self {
+        Self {
+            speech_recognizer: Box::new(speech_recognizer),
+            voice: None,
+        }
+    }
+
+    /// The voice answering in the language, e.g. [VoiceSelection::Language]. The current voice is
+    /// kept if it isn't set.
+    pub fn set_voice(&mut self, voice: Option<VoiceSelection>) {
+        self.voice = voice;
+    }
+}
+
+/// The languages of the assistant and the one it is speaking.
+pub(crate) struct Languages {
+    languages: HashMap<String, Language>,
+    active: Option<String>,
+    // The voice before the voice of a language replaced it
+    default_voice: Option<Voice>,
+}
+
+impl Languages {
+    pub(crate) fn new(languages: HashMap<String, Language>) -> Self {
+        Self {
+            languages,
+            active: None,
+            default_voice: None,
+        }
+    }
+
+    pub(crate) fn active(&self) -> Option<&str> {
+        self.active.as_deref()
+    }
+
+    pub(crate) fn names(&self) -> impl Iterator<Item = &str> {
+        self.languages.keys().map(String::as_str)
+    }
+
+    /// The speech recognizer of the active language, if one is active.
+    pub(crate) fn speech_recognizer(&self) -> Option<&dyn SpeechRecognizer> {
+        let language = self.languages.get(self.active.as_deref()?)?;
+        Some(language.speech_recognizer.as_ref())
+    }
+
+    pub(crate) fn is_ready(&self) -> bool {
+        self.languages
+            .values()
+            .all(|language| language.speech_recognizer.is_ready())
+    }
+
+    /// Make the language active and switch to its voice, or back to the default one with `None`.
+    /// The voice is left alone without a TTS backend.
+    pub(crate) fn switch(
+        &mut self,
+        language: Option<&str>,
+        tts: Option<&mut Tts>,
+    ) -> Result<(), LanguageError> {
+        let voice = match language {
+            Some(name) => match self.languages.get(name) {
+                Some(language) => language.voice.as_ref(),
+                None => return Err(LanguageError::UnknownLanguage(name.to_string())),
+            },
+            None => None,
+        };
+        match (voice, tts) {
+            (_, None) => (),
+            (Some(selection), Some(tts)) => {
+                if self.default_voice.is_none() {
+                    self.default_voice = tts.voice().map_err(TtsConfigError::from)?;
+                }
+                set_voice(tts, selection)?;
+            }
+            (None, Some(tts)) => {
+                if let Some(voice) = self.default_voice.take() {
+                    tts.set_voice(&voice).map_err(TtsConfigError::from)?;
+                }
+            }
+        }
+        self.active = language.map(str::to_string);
+        Ok(())
+    }
+}
@@ -1,110 +1,1180 @@
-use std::{collections::HashSet, sync::mpsc::RecvError};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{Receiver, RecvError, RecvTimeoutError},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
 use ::tts::Tts;
+use audio::{
+    AudioInput, AudioInputBuildError, AudioInputConfig, AudioInputPauseError, AudioInputStartError,
+    AudioInputStatus, AudioTimestamp, InputDevice,
+};
+use automation::{Action, Automation, Fired, Rule};
+use background::Background;
+use clock::{Clock, SystemClock};
+use conversation::DialogContext;
+use cues::{AudioCueSource, AudioCueSourceStartError, RunningAudioCueSource};
+use events::{AssistantEvent, EventSenders, StartupProgress};
+use guest::{GuestMode, GuestModeConfig};
+use http_cache::HttpCache;
 use intents::{
-    EmbeddingModelSource, IntentRecognizer, IntentRecognizerBuildError, IntentRecognizerError,
-    IntentsConfig,
+    EmbeddingModelSource, Fallback, IntentCandidate, IntentRecognizer, IntentRecognizerBuildError,
+    IntentRecognizerError, IntentsConfig, RankedIntent, Scoring,
 };
+use language::{Language, LanguageError, Languages};
+use metrics::{Metric, Metrics, MetricsSnapshot};
+use middleware::{ConfirmCommands, DispatchedIntent, Flow, Middleware};
+use normalize::Normalizer;
+use permissions::{Capability, Permissions};
+use power::{PowerMode, PowerStats};
+use presence::PresenceSensor;
+use profile::SettingsProfile;
+use random::{Random, SystemRandom};
+use recording::{Recorder, RecordingConfig, RecordingError};
+use remote::{RemoteCommand, RemoteCommands, RemoteHandle};
+use schedule::Schedule;
+use scheduling::{SchedulingConfig, ThreadScheduling};
+use session::SessionError;
+use shadow::ShadowIntents;
+use skills::{Acknowledgement, IntentTarget, Skill, SkillContext};
+use slots::{Slot, SlotValue, SlotValues};
+use sounds::{Earcon, Earcons, SoundError};
+use speakers::{spoken_name, SpeakerError, SpeakerIdentifier, SpeakerPreferences, Speakers};
+use speech_queue::{SpeechControl, SpeechQueue};
+use storage::{Storage, StorageError};
 use stt::{
-    load_stt_model, RecognitionError, RecognitionResult, STTConfig, STTConfigError,
-    STTSentenceRecognizer,
+    load_stt_model, BackgroundVoskRecognizer, EndpointConfig, RecognitionError, RecognitionResult,
+    RecognizedAudio, STTConfig, STTSentenceRecognizer, Sentence, SpeechRecognizer, VoskRecognizer,
 };
 use thiserror::Error;
-use tts::{tts_speak, TtsError};
-use vosk::Model;
+use tts::{
+    tts_speak, tts_speak_utterance, TtsConfig, TtsConfigError, TtsError, UtteranceId, Utterances,
+};
+use tts_cache::{TtsCache, TtsCacheConfig};
 use wakeword::{
     WakewordConfig, WakewordConfigAddError, WakewordConfigBuildError, WakewordConfigStartError,
+    WakewordEngine,
 };
 
+/// Log a warning with tracing, e.g. about a failure that doesn't fail the query. The library
+/// doesn't write to stdout or stderr, so warnings are dropped without the `tracing` feature.
+macro_rules! warn {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::warn!($($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        let _ = format_args!($($arg)+);
+    }};
+}
+
+/// Like `warn!`, for what the assistant is doing, e.g. that it reconnected to the audio input.
+macro_rules! info {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::info!($($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        let _ = format_args!($($arg)+);
+    }};
+}
+
+#[cfg(feature = "weather")]
+pub mod air_quality;
+pub mod analytics;
+#[cfg(feature = "tokio")]
+mod asynchronous;
+pub mod audio;
+pub mod automation;
+mod background;
+pub mod bench;
+pub mod birthdays;
+#[cfg(feature = "bridge")]
+pub mod bridge;
+pub mod chores;
+pub mod clock;
+#[cfg(feature = "offline")]
+pub mod connectivity;
+pub mod conversation;
+pub mod cues;
+pub mod dice;
+pub mod events;
+pub mod guest;
+#[cfg(feature = "home")]
+pub mod home;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod http_cache;
+#[cfg(feature = "hue")]
+pub mod hue;
 pub mod intents;
+#[cfg(feature = "knowledge")]
+pub mod knowledge;
+pub mod language;
+pub mod metrics;
+pub mod middleware;
+pub mod normalize;
+#[cfg(feature = "packages")]
+pub mod packages;
+pub mod permissions;
+pub mod phonetic;
+pub mod power;
+pub mod presence;
+pub mod profile;
+pub mod random;
+pub mod recording;
+pub mod remote;
+mod ring;
+pub mod schedule;
+pub mod scheduling;
+pub mod session;
+pub mod shadow;
+mod simd;
+pub mod skills;
+pub mod slots;
+#[cfg(feature = "sonos")]
+pub mod sonos;
+pub mod sounds;
+pub mod speakers;
+pub mod speech_queue;
+#[cfg(feature = "sports")]
+pub mod sports;
+pub mod storage;
 pub mod stt;
+#[cfg(feature = "sync")]
+pub mod sync;
+#[cfg(feature = "transit")]
+pub mod transit;
 pub mod tts;
+pub mod tts_cache;
 pub mod wakeword;
+#[cfg(feature = "weather")]
+pub mod weather;
+#[cfg(feature = "whisper")]
+pub mod whisper;
 
 pub struct AssistantConfig<T> {
+    audio_input_config: AudioInputConfig,
     wakeword_config: WakewordConfig,
-    stt_model: Model,
-    stt_config: STTConfig,
-    tts: Tts,
-    intents_config: IntentsConfig<T>,
+    speech_recognizer: Option<Box<dyn SpeechRecognizer>>,
+    speech_recognizers: HashMap<String, Box<dyn SpeechRecognizer>>,
+    wakeword_speech_recognizers: HashMap<String, String>,
+    wakeword_contexts: HashMap<String, String>,
+    languages: HashMap<String, Language>,
+    language: Option<String>,
+    tts: Option<Tts>,
+    normalizer: Normalizer,
+    intents_config: IntentsConfig<IntentTarget<T>>,
+    spawn_intent_recognizer: Option<SpawnIntentRecognizer<IntentTarget<T>>>,
+    skills: Vec<Box<dyn Skill>>,
+    middlewares: Vec<Box<dyn Middleware<T>>>,
+    permissions: Permissions,
+    shadow_intents: Option<ShadowIntents<IntentsConfig<T>, T>>,
     wakewords_listen: HashSet<String>,
+    wakeword_responses: HashMap<String, String>,
+    acknowledgements: HashMap<String, Acknowledgement>,
+    profile: SettingsProfile,
+    clock: Arc<dyn Clock>,
+    random: Arc<dyn Random>,
+    reprompt_on_failure: Option<RepromptPolicy>,
+    reprompt_on_timeout: Option<RepromptPolicy>,
+    location: Option<String>,
+    not_understood_response: Option<String>,
+    greeting: Option<String>,
+    farewell: Option<String>,
+    barge_in: bool,
+    query_alternatives: u16,
+    storage: Option<Arc<dyn Storage>>,
+    http_cache: HttpCache,
+    #[cfg(feature = "http")]
+    http_client: http::HttpClient,
+    earcons: HashMap<Earcon, PathBuf>,
+    earcon_volume: f32,
+    inference_scheduling: ThreadScheduling,
+    automation: Automation<T>,
+    #[cfg(feature = "sync")]
+    sync: Option<sync::SyncConfig>,
+    #[cfg(feature = "offline")]
+    connectivity: Option<connectivity::ConnectivityConfig>,
+    guest_mode: GuestModeConfig,
+    speaker_identifier: Option<Box<dyn SpeakerIdentifier>>,
+    speaker_preferences: HashMap<String, SpeakerPreferences>,
+    audio_cue_sources: Vec<AudioCueSource>,
+    recording: Option<RecordingConfig>,
+    session_archive: Option<PathBuf>,
+    presence_sensor: Option<Box<dyn PresenceSensor>>,
+    tts_cache: Option<TtsCacheConfig>,
+    events: EventSenders,
+}
+
+/// Name of the slot that overrides the location of the assistant in [AssistantQuery::location],
+/// e.g. `"turn on the lights in the {room}"`.
+pub const ROOM_SLOT: &str = "room";
+
+/// What speakers repeat to enroll their voice with [Assistant::enroll_speaker].
+const ENROLLMENT_PHRASES: [&str; 4] = [
+    "The quick brown fox jumps over the lazy dog",
+    "What's the weather like tomorrow",
+    "Set a timer for ten minutes",
+    "Turn on the lights in the kitchen",
+];
+
+/// Said to queries before the models loaded in the background are ready, see
+/// [AssistantConfig::build_in_background].
+const STARTING_UP_RESPONSE: &str = "One moment, I'm still starting up.";
+
+/// How many times the user says a wakeword taught with [Assistant::train_wakeword].
+#[cfg(feature = "rustpotter")]
+const WAKEWORD_TRAINING_SAMPLES: usize = 5;
+
+/// The [Storage] namespace of the intent groups disabled with [Assistant::disable_intent_group].
+const DISABLED_GROUPS_NAMESPACE: &str = "disabled_intent_groups";
+
+/// The kinds of speech recognition failures that can be retried with a [RepromptPolicy].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RecognitionFailure {
+    /// The recognizer couldn't make sense of the audio.
+    Failed,
+    /// The user didn't finish a sentence before the timeout.
+    Timeout,
+}
+
+/// RepromptPolicy makes [Assistant::listen] speak `prompt` and listen again, up to `max_retries`
+/// times, instead of returning an error straight away.
+#[derive(Clone, Debug)]
+pub struct RepromptPolicy {
+    pub prompt: String,
+    pub max_retries: usize,
 }
 
 #[derive(Error, Debug)]
 pub enum AssistantConfigBuildError {
+    #[error("Failed to configure audio input")]
+    AudioInputError(#[from] AudioInputBuildError),
     #[error("Failed to build wakeword config")]
     WakewordConfigError(#[from] WakewordConfigBuildError),
     #[error("Failed to load STT model")]
     STTModelError,
-    #[error("Failed to build STT config")]
-    STTConfigError(#[from] STTConfigError),
     #[error("Failed to get TTS")]
     TtsError(#[from] TtsError),
+    #[error("Failed to configure TTS")]
+    TtsConfigError(#[from] TtsConfigError),
 }
 
 #[derive(Error, Debug)]
 pub enum AssistantStartError {
+    #[error("Failed to start audio input")]
+    AudioInputStartError(#[from] AudioInputStartError),
     #[error("Failed to build intent recognizer")]
     IntentRecognizerBuildError(#[from] IntentRecognizerBuildError),
     #[error("Failed to start wakeword listener")]
     WakewordListenerStartError(#[from] WakewordConfigStartError),
+    #[error("Failed to load state from storage")]
+    StorageError(#[from] StorageError),
+    #[error("Failed to register utterance callbacks")]
+    TtsError(#[from] TtsError),
+    #[error("Failed to start an audio cue source")]
+    AudioCueSourceStartError(#[from] AudioCueSourceStartError),
+    #[error("Failed to set up recording")]
+    RecordingError(#[from] RecordingError),
+    #[error("Failed to create the session archive")]
+    SessionError(#[from] SessionError),
+    #[error("No speech recognizer named {0}")]
+    UnknownSpeechRecognizer(String),
+    #[error("Failed to switch to the language")]
+    LanguageError(#[from] LanguageError),
+    #[cfg(feature = "sync")]
+    #[error("Sync needs storage")]
+    SyncWithoutStorage,
+}
+
+/// AssistantConfigBuilder picks the components an [AssistantConfig] is built with, e.g. for a
+/// device that forwards the recognized intents elsewhere and never speaks. Components that are
+/// left out can be set afterwards on the [AssistantConfig], like with
+/// [AssistantConfig::set_speech_recognizer].
+pub struct AssistantConfigBuilder {
+    input_device: InputDevice,
+    stt_model_path: Option<String>,
+    embedding_model: Option<EmbeddingModelSource>,
+    tts: Option<TtsConfig>,
+}
+
+impl AssistantConfigBuilder {
+    /// The default input device and TTS backend, without a speech recognizer or intents.
+    pub fn new() -> Self {
+        Self {
+            input_device: InputDevice::Default,
+            stt_model_path: None,
+            embedding_model: None,
+            tts: Some(TtsConfig::new()),
+        }
+    }
+
+    pub fn set_input_device(&mut self, input_device: InputDevice) {
+        self.input_device = input_device;
+    }
+
+    /// The Vosk model recognizing the queries. Without a speech recognizer, listening for a query
+    /// fails with [RecognitionError::NoSpeechRecognizer], so only wakewords that don't listen
+    /// for one are of use.
+    pub fn set_stt_model(&mut self, path: Option<&str>) {
+        self.stt_model_path = path.map(str::to_string);
+    }
+
+    /// The model embedding the intents. Without one no intents can be added, and the queries
+    /// are returned with their text and no intent.
+    pub fn set_embedding_model(&mut self, model: Option<EmbeddingModelSource>) {
+        self.embedding_model = model;
+    }
+
+    /// The TTS backend, the default one of the platform by default. Without one, the assistant
+    /// doesn't say its responses, and [Assistant::speak] fails with
+    /// [TtsError::UnsupportedFeature].
+    pub fn set_tts(&mut self, tts: Option<TtsConfig>) {
+        self.tts = tts;
+    }
+
+    pub fn build<T>(self) -> Result<AssistantConfig<T>, AssistantConfigBuildError> {
+        let speech_recognizer = match &self.stt_model_path {
+            Some(path) => {
+                let stt_model = load_stt_model(path.as_str())
+                    .map_err(|_| AssistantConfigBuildError::STTModelError)?;
+                Some(Box::new(VoskRecognizer::new(stt_model, STTConfig::new()))
+                    as Box<dyn SpeechRecognizer>)
+            }
+            None => None,
+        };
+        AssistantConfig::build_with_recognizer(self, speech_recognizer)
+    }
+
+    /// Like [AssistantConfigBuilder::build], but the Vosk model is loaded and the intents are
+    /// embedded in the background, see [AssistantConfig::build_in_background].
+    pub fn build_in_background<T: Send + 'static>(
+        self,
+    ) -> Result<AssistantConfig<T>, AssistantConfigBuildError> {
+        let speech_recognizer = self.stt_model_path.clone().map(|path| {
+            Box::new(BackgroundVoskRecognizer::load(path, STTConfig::new()))
+                as Box<dyn SpeechRecognizer>
+        });
+        let mut config = AssistantConfig::build_with_recognizer(self, speech_recognizer)?;
+        config.spawn_intent_recognizer = Some(spawn_intent_recognizer);
+        Ok(config)
+    }
+}
+
+impl Default for AssistantConfigBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T> AssistantConfig<T> {
+    /// Build the configuration using the default input device.
     pub fn build(
         stt_model_path: impl Into<String>,
         embedding_model: EmbeddingModelSource,
     ) -> Result<Self, AssistantConfigBuildError> {
-        let wakeword_config = WakewordConfig::build()?;
-        let stt_model =
-            load_stt_model(stt_model_path).map_err(|_| AssistantConfigBuildError::STTModelError)?;
-        let stt_config = STTConfig::build()?;
-        let tts = tts::get_tts()?;
-        let intents_config = IntentsConfig::new(embedding_model);
+        Self::build_with_input_device(stt_model_path, embedding_model, InputDevice::Default)
+    }
+
+    pub fn build_with_input_device(
+        stt_model_path: impl Into<String>,
+        embedding_model: EmbeddingModelSource,
+        input_device: InputDevice,
+    ) -> Result<Self, AssistantConfigBuildError> {
+        Self::builder_with(stt_model_path.into(), embedding_model, input_device).build()
+    }
+
+    /// The builder of [AssistantConfig::build_with_input_device].
+    fn builder_with(
+        stt_model_path: String,
+        embedding_model: EmbeddingModelSource,
+        input_device: InputDevice,
+    ) -> AssistantConfigBuilder {
+        let mut builder = AssistantConfigBuilder::new();
+        builder.set_input_device(input_device);
+        builder.set_stt_model(Some(&stt_model_path));
+        builder.set_embedding_model(Some(embedding_model));
+        builder
+    }
+
+    fn build_with_recognizer(
+        builder: AssistantConfigBuilder,
+        speech_recognizer: Option<Box<dyn SpeechRecognizer>>,
+    ) -> Result<Self, AssistantConfigBuildError> {
+        let audio_input_config = AudioInputConfig::build_with_device(builder.input_device)?;
+        let wakeword_config = WakewordConfig::build(audio_input_config.format())?;
+        let tts = builder.tts.map(|config| config.build()).transpose()?;
+        let intents_config = match builder.embedding_model {
+            Some(model) => IntentsConfig::new(model),
+            None => IntentsConfig::without_model(),
+        };
 
         Ok(Self {
+            audio_input_config,
             wakeword_config,
-            stt_model,
-            stt_config,
+            speech_recognizer,
+            speech_recognizers: HashMap::new(),
+            wakeword_speech_recognizers: HashMap::new(),
+            wakeword_contexts: HashMap::new(),
+            languages: HashMap::new(),
+            language: None,
             tts,
+            normalizer: Normalizer::default(),
             intents_config,
+            spawn_intent_recognizer: None,
+            skills: Vec::new(),
+            middlewares: vec![Box::new(ConfirmCommands)],
+            permissions: Permissions::new(),
+            shadow_intents: None,
             wakewords_listen: HashSet::new(),
+            wakeword_responses: HashMap::new(),
+            acknowledgements: HashMap::new(),
+            profile: SettingsProfile::default(),
+            clock: Arc::new(SystemClock),
+            random: Arc::new(SystemRandom::new()),
+            reprompt_on_failure: None,
+            reprompt_on_timeout: None,
+            location: None,
+            not_understood_response: None,
+            greeting: None,
+            farewell: None,
+            barge_in: false,
+            query_alternatives: 0,
+            storage: None,
+            http_cache: HttpCache::new(),
+            #[cfg(feature = "http")]
+            http_client: http::HttpClient::default(),
+            earcons: HashMap::new(),
+            earcon_volume: 1.,
+            inference_scheduling: ThreadScheduling::default(),
+            automation: Automation::new(),
+            #[cfg(feature = "sync")]
+            sync: None,
+            #[cfg(feature = "offline")]
+            connectivity: None,
+            guest_mode: GuestModeConfig::default(),
+            speaker_identifier: None,
+            speaker_preferences: HashMap::new(),
+            audio_cue_sources: Vec::new(),
+            recording: None,
+            session_archive: None,
+            presence_sensor: None,
+            tts_cache: None,
+            events: EventSenders::new(Earcons::new(HashMap::new(), 1., 1.)),
         })
     }
 
+    /// Receive the [AssistantEvent]s from the start, including the progress of
+    /// [AssistantConfig::start] as [AssistantEvent::Startup], e.g. to show it on a screen or a
+    /// LED. The receiver keeps receiving the events of the started assistant, like the ones of
+    /// [Assistant::events].
+    pub fn events(&mut self) -> Receiver<AssistantEvent> {
+        self.events.subscribe()
+    }
+
+    /// Enable or disable automatic reprompting for a kind of recognition failure. Disabled by
+    /// default for all of them.
+    pub fn set_reprompt_policy(
+        &mut self,
+        failure: RecognitionFailure,
+        policy: Option<RepromptPolicy>,
+    ) {
+        match failure {
+            RecognitionFailure::Failed => self.reprompt_on_failure = policy,
+            RecognitionFailure::Timeout => self.reprompt_on_timeout = policy,
+        }
+    }
+
+    /// Set the room this assistant is in, e.g. "kitchen". Queries that don't name a room refer to
+    /// this one.
+    pub fn set_location(&mut self, location: impl Into<String>) {
+        self.location = Some(location.into());
+    }
+
+    /// Speak `response` when no intent matches what the user said, before
+    /// [AssistantListenSuccessfulWakewordError::NotUnderstood] is returned. `{text}` is replaced
+    /// with the transcript, e.g. "I heard '{text}' but I don't know how to do that.". Nothing is
+    /// spoken by default.
+    pub fn set_not_understood_response(&mut self, response: Option<&str>) {
+        self.not_understood_response = response.map(str::to_string);
+    }
+
+    /// Speak `greeting` once the assistant is ready to listen, e.g. "Raspberry is online.", after
+    /// the [Earcon::Ready] earcon. Nothing is spoken by default.
+    pub fn set_greeting(&mut self, greeting: Option<&str>) {
+        self.greeting = greeting.map(str::to_string);
+    }
+
+    /// Speak `farewell` on [Assistant::shutdown] and wait for it before stopping, before the
+    /// [Earcon::Stopped] earcon. Nothing is spoken by default.
+    pub fn set_farewell(&mut self, farewell: Option<&str>) {
+        self.farewell = farewell.map(str::to_string);
+    }
+
+    /// Let the wakeword interrupt what the assistant is saying and start listening straight away.
+    /// Disabled by default, which makes [Assistant::listen] wait for the TTS backend to finish and
+    /// ignore the wakeword. Long texts from [Assistant::speak_long] can always be interrupted.
+    pub fn set_barge_in(&mut self, barge_in: bool) {
+        self.barge_in = barge_in;
+    }
+
+    /// Decode up to `count` alternative transcriptions of queries and keep the one whose intent
+    /// scores highest, which helps with short commands that speech-to-text mishears. 0, the
+    /// default, only decodes the most likely one.
+    pub fn set_query_alternatives(&mut self, count: u16) {
+        self.query_alternatives = count;
+    }
+
+    /// Synthesize short phrases once and play them from memory afterwards, which is faster than
+    /// going through the TTS backend every time. The wakeword responses are synthesized when the
+    /// assistant starts. Disabled by default.
+    pub fn set_tts_cache(&mut self, cache: Option<TtsCacheConfig>) {
+        self.tts_cache = cache;
+    }
+
+    /// Play a WAV or OGG Vorbis file on an event, or nothing if `path` is `None`. No earcons are
+    /// played by default.
+    pub fn set_earcon(&mut self, earcon: Earcon, path: Option<impl Into<PathBuf>>) {
+        match path {
+            Some(path) => _ = self.earcons.insert(earcon, path.into()),
+            None => _ = self.earcons.remove(&earcon),
+        }
+    }
+
+    /// Set the gain of the earcons, e.g. 0.5 to play them at half their amplitude. 1 by default,
+    /// playing them as they are.
+    pub fn set_earcon_volume(&mut self, volume: f32) {
+        self.earcon_volume = volume;
+    }
+
+    /// Replace the Vosk recognizer loaded by [AssistantConfig::build], e.g. with one using
+    /// another backend or a [VoskRecognizer] with a custom [STTConfig]. The timeout is part of
+    /// the settings profile, see [AssistantConfig::set_profile].
+    pub fn set_speech_recognizer(&mut self, recognizer: impl SpeechRecognizer + 'static) {
+        self.speech_recognizer = Some(Box::new(recognizer));
+    }
+
+    /// Register another speech recognizer under a name, e.g. a Vosk model of another language.
+    /// It recognizes the queries of the wakewords bound to it with
+    /// [AssistantConfig::set_wakeword_speech_recognizer] and of the speakers preferring it, see
+    /// [SpeakerPreferences::speech_recognizer].
+    pub fn add_speech_recognizer(
+        &mut self,
+        name: impl Into<String>,
+        recognizer: impl SpeechRecognizer + 'static,
+    ) {
+        self.speech_recognizers
+            .insert(name.into(), Box::new(recognizer));
+    }
+
+    /// Only recognize the intents of a context after the wakeword, e.g. "music" for a music
+    /// persona, see [AssistantConfig::set_intent_context]. Wakewords without a context recognize
+    /// the intents without one. The context is reported in [AssistantQuery::context].
+    pub fn set_wakeword_context(&mut self, wakeword: &str, context: Option<&str>) {
+        match context {
+            Some(context) => {
+                _ = self
+                    .wakeword_contexts
+                    .insert(wakeword.to_string(), context.to_string())
+            }
+            None => _ = self.wakeword_contexts.remove(wakeword),
+        }
+    }
+
+    /// Register a language the assistant can be switched to with [Assistant::set_language], e.g.
+    /// "it" for a bilingual household. Its intents are added after
+    /// [AssistantConfig::set_intent_language].
+    pub fn add_language(&mut self, name: impl Into<String>, language: Language) {
+        self.languages.insert(name.into(), language);
+    }
+
+    /// The language the assistant starts in, see [Assistant::set_language]. `None` by default.
+    pub fn set_language(&mut self, language: Option<&str>) {
+        self.language = language.map(str::to_string);
+    }
+
+    /// Recognize the queries after a wakeword with the speech recognizer registered under the
+    /// name, e.g. the Italian model for an Italian wakeword. `None` uses the preference of the
+    /// speaker or the default recognizer again.
+    pub fn set_wakeword_speech_recognizer(&mut self, wakeword: &str, recognizer: Option<&str>) {
+        match recognizer {
+            Some(recognizer) => {
+                _ = self
+                    .wakeword_speech_recognizers
+                    .insert(wakeword.to_string(), recognizer.to_string())
+            }
+            None => _ = self.wakeword_speech_recognizers.remove(wakeword),
+        }
+    }
+
+    /// Run another wakeword engine in shadow mode, see [WakewordConfig::set_shadow_engine].
+    /// Divergences are emitted as [AssistantEvent::ShadowDivergence].
+    pub fn set_shadow_wakeword_engine(&mut self, engine: impl WakewordEngine + 'static) {
+        self.wakeword_config.set_shadow_engine(engine);
+    }
+
+    /// Set the priority and cores of the audio callback and of the thread running intent
+    /// recognition, so that inference doesn't make the audio stutter. Nothing is changed by
+    /// default, and settings that can't be applied are skipped with a warning.
+    pub fn set_scheduling(&mut self, config: SchedulingConfig) {
+        self.audio_input_config.set_scheduling(config.audio);
+        self.inference_scheduling = config.inference;
+    }
+
+    /// Select how the wakeword engine is run, e.g. [PowerMode::LowPower] on battery powered
+    /// builds. See [WakewordConfig::set_power_mode].
+    pub fn set_power_mode(&mut self, mode: PowerMode) {
+        self.wakeword_config.set_power_mode(mode);
+    }
+
+    /// Recognize every transcript with a second set of intents too, e.g. with another threshold
+    /// or embedding model, without acting on it. Transcripts for which it matches another intent
+    /// are emitted as [AssistantEvent::ShadowDivergence].
+    pub fn set_shadow_intents(&mut self, config: IntentsConfig<T>)
+    where
+        T: PartialEq + std::fmt::Debug,
+    {
+        self.shadow_intents = Some(ShadowIntents::new(config));
+    }
+
+    /// Configure guest mode, started with [Assistant::start_guest_mode]. Without a wakeword or
+    /// allowed groups, guest mode only makes the announcements.
+    pub fn set_guest_mode(&mut self, config: GuestModeConfig) {
+        self.guest_mode = config;
+    }
+
+    /// Tell who is speaking from the audio of each query, so their preferences are used, see
+    /// [AssistantConfig::set_speaker_preferences].
+    pub fn set_speaker_identifier(&mut self, identifier: impl SpeakerIdentifier + 'static) {
+        self.speaker_identifier = Some(Box::new(identifier));
+    }
+
+    /// Set the preferences of a speaker profile, used for the queries the speaker identifier
+    /// attributes to it.
+    pub fn set_speaker_preferences(&mut self, speaker: &str, preferences: SpeakerPreferences) {
+        self.speaker_preferences
+            .insert(speaker.to_string(), preferences);
+    }
+
+    /// Add an automation rule, run while the assistant listens.
+    pub fn add_rule(&mut self, rule: Rule<T>)
+    where
+        T: PartialEq,
+    {
+        self.automation.add_rule(rule);
+    }
+
+    /// Listen for audio cues on another capture source, e.g. to mute the TV during ad breaks.
+    /// They are emitted as events and trigger automation rules while the assistant listens.
+    pub fn add_audio_cue_source(&mut self, source: AudioCueSource) {
+        self.audio_cue_sources.push(source);
+    }
+
+    /// Sync some of the storage with other assistants in the background, see [sync::SyncConfig].
+    /// Needs storage to be set. Enabled with the `sync` feature.
+    #[cfg(feature = "sync")]
+    pub fn set_sync(&mut self, config: sync::SyncConfig) {
+        self.sync = Some(config);
+    }
+
+    /// Check in the background whether the assistant is online, so that skills needing the
+    /// internet answer with their offline fallback instead of failing, see
+    /// [skills::Skill::handle_offline]. Without it the assistant is always considered online.
+    /// Enabled with the `offline` feature.
+    #[cfg(feature = "offline")]
+    pub fn set_connectivity_monitor(&mut self, config: connectivity::ConnectivityConfig) {
+        self.connectivity = Some(config);
+    }
+
+    /// Only go on after a wakeword when the sensor says someone is present, e.g. a camera, to
+    /// ignore the wakewords said by the TV in an empty room. Wakewords are always accepted by
+    /// default.
+    pub fn set_presence_sensor(&mut self, sensor: impl PresenceSensor + 'static) {
+        self.presence_sensor = Some(Box::new(sensor));
+    }
+
+    /// Save the audio leading up to each wakeword detection and the audio of each speech
+    /// recognition session as WAV files, to hear what the assistant heard when it misbehaves.
+    /// Off by default.
+    pub fn set_recording(&mut self, config: Option<RecordingConfig>) {
+        self.recording = config;
+    }
+
+    /// Write all the audio heard by the assistant and what it made of it, the wakewords,
+    /// transcripts and intents, into an archive at the path, to replay it against another
+    /// configuration with [session::read_session]. The file is overwritten. Off by default.
+    pub fn set_session_archive(&mut self, path: Option<PathBuf>) {
+        self.session_archive = path;
+    }
+
+    /// Publish the messages of [Action::PublishMqtt]. Enabled with the `home` feature.
+    #[cfg(feature = "home")]
+    pub fn set_automation_mqtt(&mut self, publisher: home::MqttPublisher) {
+        self.automation.set_mqtt(publisher);
+    }
+
+    /// Replace the wakeword engine, which is Rustpotter by default. Has to be called before
+    /// adding wakewords. The engine is given audio in [AssistantConfig::audio_format].
+    pub fn set_wakeword_engine(&mut self, engine: impl WakewordEngine + 'static) {
+        self.wakeword_config.set_engine(engine);
+        self.wakewords_listen.clear();
+        self.wakeword_responses.clear();
+    }
+
+    /// Tune the Rustpotter wakeword detector, see [WakewordConfig::set_detector_settings]. Has to
+    /// be called before adding wakewords.
+    #[cfg(feature = "rustpotter")]
+    pub fn set_wakeword_detector(
+        &mut self,
+        settings: &wakeword::DetectorSettings,
+    ) -> Result<(), WakewordConfigBuildError> {
+        self.wakeword_config.set_detector_settings(settings)?;
+        self.wakewords_listen.clear();
+        self.wakeword_responses.clear();
+        Ok(())
+    }
+
+    /// The format of the captured audio.
+    pub fn audio_format(&self) -> audio::AudioFormat {
+        self.audio_input_config.format()
+    }
+
+    /// Change how text is rewritten before it is spoken, e.g. to add pronunciations or use
+    /// another locale.
+    pub fn normalizer_mut(&mut self) -> &mut Normalizer {
+        &mut self.normalizer
+    }
+
+    /// Replace the clock used for timeouts and by [Assistant::clock].
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Replace the source of randomness used by the skills, e.g. with the `SeededRandom` of the
+    /// `deterministic` feature to make their choices reproducible.
+    pub fn set_random(&mut self, random: Arc<dyn Random>) {
+        self.random = random;
+    }
+
+    /// Replace the TTS backend created by [AssistantConfig::build] with the platform defaults.
+    pub fn set_tts_config(&mut self, config: &TtsConfig) -> Result<(), TtsConfigError> {
+        self.tts = Some(config.build()?);
+        Ok(())
+    }
+
+    /// Persist state, like disabled intent groups and the embeddings of the intent examples, in
+    /// the storage. Nothing is persisted by default.
+    pub fn set_storage(&mut self, storage: Arc<dyn Storage>) {
+        self.storage = Some(storage);
+    }
+
+    /// The cache of HTTP responses shared by the skills, to give to the skills added with
+    /// [AssistantConfig::add_skill] that make requests.
+    pub fn http_cache(&self) -> HttpCache {
+        self.http_cache.clone()
+    }
+
+    /// Enforce other timeouts, retries and rate limits than the default ones of
+    /// [http::HttpClientConfig] for the requests of the skills and automation rules. Enabled
+    /// with the `weather`, `home` and `sync` features.
+    #[cfg(feature = "http")]
+    pub fn set_http_client(&mut self, client: http::HttpClient) {
+        self.http_client = client;
+    }
+
+    /// The HTTP client shared by the skills, to give to the skills added with
+    /// [AssistantConfig::add_skill] that make requests.
+    #[cfg(feature = "http")]
+    pub fn http_client(&self) -> http::HttpClient {
+        self.http_client.clone()
+    }
+
+    /// Set the settings profile used once the assistant is started. The TTS settings are applied
+    /// immediately.
+    pub fn set_profile(&mut self, profile: SettingsProfile) -> Result<(), TtsError> {
+        if let Some(tts) = &mut self.tts {
+            profile.apply_tts(tts)?;
+        }
+        self.profile = profile;
+        Ok(())
+    }
+
+    /// Add a wakeword from a Rustpotter wakeword file. If `listen` is true, speech recognition
+    /// starts after the wakeword is detected. The `response`, e.g. "Yes?", is spoken as soon as
+    /// the wakeword is detected, before listening.
     pub fn add_wakeword_from_file(
         &mut self,
         wakeword: &str,
         file: &str,
         listen: bool,
+        response: Option<&str>,
     ) -> Result<(), WakewordConfigAddError> {
         self.wakeword_config
             .add_wakeword_from_file(wakeword, file)?;
+        self.set_wakeword_behavior(wakeword, listen, response);
+        Ok(())
+    }
+
+    /// Like [AssistantConfig::add_wakeword_from_file], with the contents of the file, e.g.
+    /// embedded with `include_bytes!` so it doesn't have to be shipped next to the application.
+    pub fn add_wakeword_from_bytes(
+        &mut self,
+        wakeword: &str,
+        bytes: &[u8],
+        listen: bool,
+        response: Option<&str>,
+    ) -> Result<(), WakewordConfigAddError> {
+        self.wakeword_config
+            .add_wakeword_from_bytes(wakeword, bytes)?;
+        self.set_wakeword_behavior(wakeword, listen, response);
+        Ok(())
+    }
+
+    fn set_wakeword_behavior(&mut self, wakeword: &str, listen: bool, response: Option<&str>) {
         if listen {
             self.wakewords_listen.insert(wakeword.to_string());
         }
-        Ok(())
+        if let Some(response) = response {
+            self.wakeword_responses
+                .insert(wakeword.to_string(), response.to_string());
+        }
+    }
+
+    /// See [IntentsConfig::set_group].
+    pub fn set_intent_group(&mut self, group: Option<&str>) {
+        self.intents_config.set_group(group);
+    }
+
+    /// See [IntentsConfig::set_context].
+    pub fn set_intent_context(&mut self, context: Option<&str>) {
+        self.intents_config.set_context(context);
+    }
+
+    /// See [IntentsConfig::set_language].
+    pub fn set_intent_language(&mut self, language: Option<&str>) {
+        self.intents_config.set_language(language);
+    }
+
+    /// See [IntentsConfig::set_threshold].
+    pub fn set_intent_threshold(&mut self, threshold: f32) {
+        self.intents_config.set_threshold(threshold);
+    }
+
+    /// See [IntentsConfig::set_scoring].
+    pub fn set_intent_scoring(&mut self, scoring: Scoring) {
+        self.intents_config.set_scoring(scoring);
+    }
+
+    /// See [IntentsConfig::add_negative_examples].
+    pub fn add_negative_examples(&mut self, examples: Vec<String>) {
+        self.intents_config.add_negative_examples(examples);
+    }
+
+    /// The threshold of the intents and skills added after this call, see
+    /// [IntentsConfig::set_intent_threshold].
+    pub fn set_threshold_of_next_intents(&mut self, threshold: Option<f32>) {
+        self.intents_config.set_intent_threshold(threshold);
+    }
+
+    /// The fallback of the intents and skills added after this call, see
+    /// [IntentsConfig::set_intent_fallback].
+    pub fn set_fallback_of_next_intents(&mut self, fallback: Option<Fallback>) {
+        self.intents_config.set_intent_fallback(fallback);
     }
 
     pub fn add_intent(&mut self, id: T, examples: Vec<String>) {
-        self.intents_config.add_intent(id, examples);
+        self.intents_config
+            .add_intent(IntentTarget::App(id), examples);
+    }
+
+    /// See [IntentsConfig::add_intent_with_slots].
+    pub fn add_intent_with_slots(&mut self, id: T, templates: Vec<String>, slots: Vec<Slot>) {
+        self.intents_config
+            .add_intent_with_slots(IntentTarget::App(id), templates, slots);
+    }
+
+    /// Register a skill and add its intents, in the current intent group. Queries for them are
+    /// handled by the skill instead of being returned by [Assistant::listen].
+    pub fn add_skill(&mut self, skill: impl Skill + 'static) {
+        let index = self.skills.len();
+        for spec in skill.intents() {
+            let target = IntentTarget::Skill {
+                skill: index,
+                intent: spec.name,
+            };
+            if spec.slots.is_empty() {
+                self.intents_config.add_intent(target, spec.examples);
+            } else {
+                self.intents_config
+                    .add_intent_with_slots(target, spec.examples, spec.slots);
+            }
+        }
+        self.skills.push(Box::new(skill));
+    }
+
+    /// Say something while a skill handles a query, for slow skills like the weather, unless it
+    /// answers quickly. `name` is the name of an intent of a skill, or of a skill for all of its
+    /// intents. No acknowledgement is said if `acknowledgement` is `None`.
+    pub fn set_acknowledgement(&mut self, name: &str, acknowledgement: Option<Acknowledgement>) {
+        match acknowledgement {
+            Some(acknowledgement) => {
+                self.acknowledgements
+                    .insert(name.to_string(), acknowledgement);
+            }
+            None => {
+                self.acknowledgements.remove(name);
+            }
+        }
+    }
+
+    /// Add a middleware around the dispatch of recognized intents, after the ones added before.
+    pub fn add_middleware(&mut self, middleware: impl Middleware<T> + 'static) {
+        self.middlewares.push(Box::new(middleware));
+    }
+
+    /// The capabilities denied to skills, by the name of the skill. Skills can do everything
+    /// they declared with [Skill::capabilities] by default.
+    pub fn set_permissions(&mut self, permissions: Permissions) {
+        self.permissions = permissions;
+    }
+
+    /// Deny a capability to the skill with the name, see [Skill::name].
+    pub fn deny_capability(&mut self, skill: &str, capability: Capability) {
+        self.permissions.deny(skill, capability);
     }
 
     pub fn start(self) -> Result<Assistant<T>, AssistantStartError> {
-        let intent_recognizer = IntentRecognizer::build(self.intents_config)?;
-        let wakeword_listener = self.wakeword_config.start()?;
+        self.inference_scheduling.apply_or_warn("inference");
+        // Everything has to write through the synced storage for changes to be synced
+        #[cfg(feature = "sync")]
+        let (storage, sync) = match (self.storage, self.sync) {
+            (Some(storage), Some(config)) => {
+                let storage = Arc::new(sync::SyncedStorage::new(storage, &config.namespaces));
+                (
+                    Some(storage.clone() as Arc<dyn Storage>),
+                    Some((storage, config)),
+                )
+            }
+            (None, Some(_)) => return Err(AssistantStartError::SyncWithoutStorage),
+            (storage, None) => (storage, None),
+        };
+        #[cfg(not(feature = "sync"))]
+        let storage = self.storage;
+        if let Some(name) = self
+            .wakeword_speech_recognizers
+            .values()
+            .find(|name| !self.speech_recognizers.contains_key(*name))
+        {
+            return Err(AssistantStartError::UnknownSpeechRecognizer(name.clone()));
+        }
+        let mut intents_config = self.intents_config;
+        let mut shadow_intents = self.shadow_intents;
+        if let Some(storage) = &storage {
+            intents_config.set_embedding_cache(storage.clone());
+            if let Some(shadow_intents) = &mut shadow_intents {
+                shadow_intents.set_embedding_cache(storage.clone());
+            }
+        }
+        let mut events = self.events;
+        events.earcons = Earcons::new(self.earcons, self.earcon_volume, self.profile.earcon_volume);
+        let guest_mode = GuestMode::load(self.guest_mode, storage.as_deref())?;
+        let metrics = Metrics::new();
+        let setup = RecognizerSetup {
+            disabled_groups: match &storage {
+                Some(storage) => storage.keys(DISABLED_GROUPS_NAMESPACE)?,
+                None => Vec::new(),
+            },
+            allowed_groups: guest_mode
+                .until()
+                .map(|_| guest_mode.config.allowed_groups.clone()),
+            language: self.language.clone(),
+            metrics: metrics.clone(),
+        };
+        let intent_recognizer = match self.spawn_intent_recognizer {
+            Some(_) if intents_config.is_empty() && intents_config.has_model() => {
+                return Err(IntentRecognizerBuildError::NoIntentsProvided.into())
+            }
+            Some(spawn) => spawn(intents_config, setup),
+            None => Background::ready(setup.build(intents_config, |embedded, total| {
+                events.emit(AssistantEvent::Startup(StartupProgress::IntentsEmbedded {
+                    embedded,
+                    total,
+                }))
+            })?),
+        };
+        let mut languages = Languages::new(self.languages);
+        let mut tts = self.tts;
+        languages.switch(self.language.as_deref(), tts.as_mut())?;
+        let shadow_intents = shadow_intents.map(ShadowIntents::build).transpose()?;
+        let schedule = Schedule::load(storage.clone())?;
+        let utterances = tts
+            .as_ref()
+            .map(|tts| Utterances::register(tts, metrics.clone()))
+            .transpose()?
+            .flatten();
+        let audio_input = AudioInput::start(self.audio_input_config)?;
+        let wakeword_listener = self.wakeword_config.start(&audio_input)?;
+        let recorder = self
+            .recording
+            .map(|config| Recorder::start(config, &audio_input))
+            .transpose()?;
+        if let Some(path) = &self.session_archive {
+            session::record_session(path, &audio_input, events.subscribe())?;
+        }
+        let tts_cache = self.tts_cache.map(|mut config| {
+            for response in self.wakeword_responses.values() {
+                config.add_phrase(response.clone());
+            }
+            for phrase in self
+                .acknowledgements
+                .values()
+                .filter_map(Acknowledgement::phrase)
+            {
+                config.add_phrase(phrase);
+            }
+            TtsCache::new(config, &self.normalizer)
+        });
+        let audio_cues = self
+            .audio_cue_sources
+            .into_iter()
+            .map(AudioCueSource::start)
+            .collect::<Result<_, _>>()?;
+        events.emit(AssistantEvent::Startup(StartupProgress::AudioReady));
+        #[allow(unused_mut)]
+        let mut automation = self.automation;
+        #[cfg(feature = "home")]
+        automation.set_http_client(self.http_client.clone());
 
-        Ok(Assistant {
-            stt_model: self.stt_model,
-            stt_config: self.stt_config,
-            tts: self.tts,
+        let mut assistant = Assistant {
+            audio_input,
+            speech_recognizer: self.speech_recognizer,
+            speech_recognizers: self.speech_recognizers,
+            wakeword_speech_recognizers: self.wakeword_speech_recognizers,
+            wakeword_contexts: self.wakeword_contexts,
+            query_speech_recognizer: None,
+            languages,
+            tts,
+            utterances,
+            normalizer: self.normalizer,
+            speech_queue: SpeechQueue::default(),
             intent_recognizer,
+            shadow_intents,
+            skills: self.skills,
+            middlewares: self.middlewares,
+            permissions: self.permissions,
             wakeword_listener,
             wakewords_listen: self.wakewords_listen,
-        })
+            wakeword_responses: self.wakeword_responses,
+            acknowledgements: self.acknowledgements,
+            profile: self.profile,
+            clock: self.clock,
+            random: self.random,
+            reprompt_on_failure: self.reprompt_on_failure,
+            reprompt_on_timeout: self.reprompt_on_timeout,
+            location: self.location,
+            not_understood_response: self.not_understood_response,
+            greeting: self.greeting,
+            farewell: self.farewell,
+            barge_in: self.barge_in,
+            query_alternatives: self.query_alternatives,
+            intent_grammar: false,
+            storage,
+            http_cache: self.http_cache,
+            #[cfg(feature = "http")]
+            http_client: self.http_client,
+            metrics,
+            schedule,
+            remote_commands: RemoteCommands::new(),
+            muted: false,
+            listening_paused: false,
+            listening_paused_until: None,
+            paused_do_not_disturb: false,
+            stopped: false,
+            automation,
+            guest_mode,
+            #[cfg(feature = "sync")]
+            dialog_key: sync.as_ref().map(|(_, config)| config.key.clone()),
+            #[cfg(feature = "sync")]
+            synced: sync.map(|(storage, config)| sync::spawn(storage, config)),
+            dialog: DialogContext::default(),
+            dialog_resumed: false,
+            #[cfg(feature = "offline")]
+            connectivity: self.connectivity.map(connectivity::Connectivity::spawn),
+            #[cfg(feature = "offline")]
+            was_online: true,
+            speakers: Speakers::new(self.speaker_identifier, self.speaker_preferences),
+            audio_cues,
+            recorder,
+            presence_sensor: self.presence_sensor,
+            tts_cache,
+            ready: false,
+            events,
+        };
+        assistant.announce_ready();
+        Ok(assistant)
+    }
+}
+
+impl<T: Send + 'static> AssistantConfig<T> {
+    /// Like [AssistantConfig::build_with_input_device], but the Vosk model is loaded and the
+    /// intents are embedded on other threads, so that [AssistantConfig::start] returns as soon as
+    /// the wakewords are listened for. Queries are asked to wait until they are loaded, and go on
+    /// once they are, see [Assistant::is_ready]. The intents embedded in the background aren't
+    /// reported as [StartupProgress::IntentsEmbedded], and failing to embed them panics when the
+    /// intent recognizer is first needed, since the assistant is already started.
+    pub fn build_in_background(
+        stt_model_path: impl Into<String>,
+        embedding_model: EmbeddingModelSource,
+        input_device: InputDevice,
+    ) -> Result<Self, AssistantConfigBuildError> {
+        Self::builder_with(stt_model_path.into(), embedding_model, input_device)
+            .build_in_background()
+    }
+}
+
+type SpawnIntentRecognizer<T> =
+    fn(IntentsConfig<T>, RecognizerSetup) -> Background<IntentRecognizer<T>>;
+
+/// What is set on the intent recognizer once it is built, see [AssistantConfig::start].
+struct RecognizerSetup {
+    disabled_groups: Vec<String>,
+    allowed_groups: Option<Vec<String>>,
+    language: Option<String>,
+    metrics: Metrics,
+}
+
+impl RecognizerSetup {
+    fn build<T>(
+        self,
+        config: IntentsConfig<T>,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<IntentRecognizer<T>, IntentRecognizerBuildError> {
+        let mut recognizer = IntentRecognizer::build_with_progress(config, progress)?;
+        recognizer.set_metrics(self.metrics);
+        for group in &self.disabled_groups {
+            recognizer.disable_group(group);
+        }
+        recognizer.set_allowed_groups(self.allowed_groups.as_deref());
+        recognizer.set_language(self.language.as_deref());
+        Ok(recognizer)
     }
 }
 
+/// Build the intent recognizer on another thread, see [AssistantConfig::build_in_background].
+fn spawn_intent_recognizer<T: Send + 'static>(
+    config: IntentsConfig<T>,
+    setup: RecognizerSetup,
+) -> Background<IntentRecognizer<T>> {
+    Background::spawn(move || {
+        setup
+            .build(config, |_, _| ())
+            .expect("Failed to build the intent recognizer in the background")
+    })
+}
+
 #[derive(Error, Debug)]
 pub enum AssistantListenSuccessfulWakewordError {
     #[error("Error while initializing speech recognition")]
@@ -115,6 +1185,27 @@ pub enum AssistantListenSuccessfulWakewordError {
     SpeechRecognitionTimeout,
     #[error("Failed to recognize intent")]
     IntentRecognizerError(#[from] IntentRecognizerError),
+    /// No intent matched the transcript well enough, see
+    /// [AssistantConfig::set_not_understood_response].
+    #[error("Failed to understand \"{0}\"")]
+    NotUnderstood(String),
+}
+
+#[derive(Error, Debug)]
+pub enum EnrollSpeakerError {
+    #[error("Failed to hear the speaker")]
+    Recognition(#[from] AssistantListenSuccessfulWakewordError),
+    #[error("Failed to learn the voice")]
+    Speaker(#[from] SpeakerError),
+}
+
+#[cfg(feature = "rustpotter")]
+#[derive(Error, Debug)]
+pub enum TrainWakewordError {
+    #[error("Failed to hear the wakeword")]
+    Recognition(#[from] AssistantListenSuccessfulWakewordError),
+    #[error("Failed to train the wakeword")]
+    Train(#[from] wakeword::trainer::WakewordTrainError),
 }
 
 #[derive(Error, Debug)]
@@ -122,90 +1213,1853 @@ pub enum AssistantListenError {
     #[error("Failed to receive wakeword")]
     WakewordRecvError(#[from] RecvError),
     #[error("Something went wrong while processing data after wakeword detection")]
-    ProcessError(String, AssistantListenSuccessfulWakewordError),
+    ProcessError(Box<QueryFailure>, AssistantListenSuccessfulWakewordError),
+    #[error("The assistant was shut down")]
+    Stopped,
+}
+
+/// What was produced before processing a query failed, to log the failure or recover from it.
+#[derive(Clone, Debug, Default)]
+pub struct QueryFailure {
+    pub wakeword: String,
+    /// When the wakeword was detected, by the clock of the assistant.
+    pub detected_at: Option<Instant>,
+    /// See [AssistantQuery::wakeword_at].
+    pub wakeword_at: Option<AudioTimestamp>,
+    /// The audio given to speech-to-text in the last attempt, mono at [stt::STT_SAMPLE_RATE].
+    /// Empty if recognition didn't run.
+    pub audio: Vec<i16>,
+    /// Where [QueryFailure::audio] starts in the stream of the microphone.
+    pub speech_at: Option<AudioTimestamp>,
+    /// The recognized sentence, if recognition succeeded.
+    pub transcript: Option<String>,
+    /// The intents closest to the transcript, best first, if none matched.
+    pub candidates: Vec<IntentCandidate>,
 }
 
 pub struct Assistant<T> {
-    stt_model: Model,
-    stt_config: STTConfig,
-    tts: Tts,
-    intent_recognizer: IntentRecognizer<T>,
+    audio_input: AudioInput,
+    speech_recognizer: Option<Box<dyn SpeechRecognizer>>,
+    speech_recognizers: HashMap<String, Box<dyn SpeechRecognizer>>,
+    wakeword_speech_recognizers: HashMap<String, String>,
+    wakeword_contexts: HashMap<String, String>,
+    /// The speech recognizer of the wakeword of the current query, by name.
+    query_speech_recognizer: Option<String>,
+    languages: Languages,
+    tts: Option<Tts>,
+    utterances: Option<Utterances>,
+    normalizer: Normalizer,
+    speech_queue: SpeechQueue,
+    intent_recognizer: Background<IntentRecognizer<IntentTarget<T>>>,
+    skills: Vec<Box<dyn Skill>>,
+    middlewares: Vec<Box<dyn Middleware<T>>>,
+    permissions: Permissions,
+    shadow_intents: Option<ShadowIntents<IntentRecognizer<T>, T>>,
     wakeword_listener: wakeword::WakewordListener,
     wakewords_listen: HashSet<String>,
+    wakeword_responses: HashMap<String, String>,
+    acknowledgements: HashMap<String, Acknowledgement>,
+    profile: SettingsProfile,
+    clock: Arc<dyn Clock>,
+    random: Arc<dyn Random>,
+    reprompt_on_failure: Option<RepromptPolicy>,
+    reprompt_on_timeout: Option<RepromptPolicy>,
+    location: Option<String>,
+    not_understood_response: Option<String>,
+    greeting: Option<String>,
+    farewell: Option<String>,
+    barge_in: bool,
+    query_alternatives: u16,
+    intent_grammar: bool,
+    storage: Option<Arc<dyn Storage>>,
+    http_cache: HttpCache,
+    #[cfg(feature = "http")]
+    http_client: http::HttpClient,
+    metrics: Metrics,
+    schedule: Schedule,
+    remote_commands: RemoteCommands,
+    muted: bool,
+    listening_paused: bool,
+    listening_paused_until: Option<chrono::DateTime<chrono::Local>>,
+    // Whether do not disturb was turned on by Assistant::pause_listening_for
+    paused_do_not_disturb: bool,
+    stopped: bool,
+    automation: Automation<T>,
+    guest_mode: GuestMode,
+    /// Set by the sync thread when it changed the storage.
+    #[cfg(feature = "sync")]
+    synced: Option<Arc<std::sync::atomic::AtomicBool>>,
+    /// Opens the dialogs handed off by the assistants syncing with it.
+    #[cfg(feature = "sync")]
+    dialog_key: Option<sync::SyncKey>,
+    dialog: DialogContext,
+    /// Listen for a query without a wakeword, after a dialog was resumed.
+    dialog_resumed: bool,
+    #[cfg(feature = "offline")]
+    connectivity: Option<connectivity::Connectivity>,
+    /// Whether the assistant was online at the last tick, to emit changes.
+    #[cfg(feature = "offline")]
+    was_online: bool,
+    speakers: Speakers,
+    audio_cues: Vec<RunningAudioCueSource>,
+    recorder: Option<Recorder>,
+    presence_sensor: Option<Box<dyn PresenceSensor>>,
+    tts_cache: Option<TtsCache>,
+    /// Whether [StartupProgress::Ready] was emitted.
+    ready: bool,
+    events: EventSenders,
 }
 
 impl<T> Assistant<T> {
-    pub fn listen(&self) -> Result<AssistantQuery<T>, AssistantListenError> {
-        let wakeword = self.wakeword_listener.listen()?;
-        match self.tts.is_speaking() {
-            Err(_) => {
-                return Err(AssistantListenError::ProcessError(
-                    wakeword,
-                    AssistantListenSuccessfulWakewordError::SpeechRecognitionError,
-                ))
-            }
-            Ok(true) => {
-                return {
+    pub fn listen(&mut self) -> Result<AssistantQuery<'_, T>, AssistantListenError> {
+        loop {
+            let detection = loop {
+                if self.stopped {
+                    return Err(AssistantListenError::Stopped);
+                }
+                match self
+                    .wakeword_listener
+                    .listen_timeout(Duration::from_millis(100))
+                {
+                    Ok(_) if self.muted => (),
+                    Ok(detection) if !self.guest_mode.accepts_wakeword(&detection.name) => (),
+                    Ok(detection) if !self.confirm_presence(&detection.name) => (),
+                    Ok(detection) => break detection,
+                    Err(RecvTimeoutError::Timeout) => {
+                        self.announce_ready();
+                        self.emit_shadow_divergences();
+                        self.announce_due_items();
+                        self.end_guest_mode_when_due();
+                        self.resume_listening_when_due();
+                        self.supervise_audio_input();
+                        #[cfg(feature = "sync")]
+                        self.reload_synced();
+                        #[cfg(feature = "offline")]
+                        self.watch_connectivity();
+                        self.poll_audio_cues();
+                        self.run_automation();
+                        _ = self.continue_speaking_long();
+                        let text = self
+                            .run_remote_commands()
+                            .or_else(|| self.listen_resumed_dialog());
+                        if let Some(text) = text {
+                            let mut failure = QueryFailure::default();
+                            match self.match_text(String::new(), text, &mut failure) {
+                                Ok(Some(query)) => return Ok(self.resolve(query)),
+                                Ok(None) => (),
+                                Err(e) => {
+                                    return Err(AssistantListenError::ProcessError(
+                                        Box::new(failure),
+                                        e,
+                                    ))
+                                }
+                            }
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => {
+                        let error = AssistantListenError::from(RecvError);
+                        self.events.emit(AssistantEvent::Error(error.to_string()));
+                        return Err(error);
+                    }
+                }
+            };
+            let detected = self.clock.now();
+            let wakeword = detection.name.clone();
+            let wakeword_at = detection.timestamp;
+            self.record_wakeword(&wakeword);
+            self.events
+                .emit(AssistantEvent::WakewordDetected(detection));
+            let mut failure = QueryFailure {
+                wakeword: wakeword.clone(),
+                detected_at: Some(detected),
+                wakeword_at,
+                ..QueryFailure::default()
+            };
+            match tts::is_speaking(self.tts.as_ref()) {
+                Err(_) => {
+                    return Err(AssistantListenError::ProcessError(
+                        Box::new(failure),
+                        AssistantListenSuccessfulWakewordError::SpeechRecognitionError,
+                    ))
+                }
+                // Long texts can be interrupted to pause or skip them
+                Ok(true) if self.speech_queue.is_active() => {
+                    _ = self.control_speech(SpeechControl::Pause);
+                }
+                Ok(true) if self.barge_in => _ = tts::stop(self.tts.as_mut()),
+                Ok(true) => {
                     _ = self.finish_speaking();
-                    self.listen()
+                    continue;
                 }
+                Ok(false) => (),
             }
-            Ok(false) => (),
-        }
-
-        if !self.wakewords_listen.contains(&wakeword) {
-            return Ok(AssistantQuery {
-                wakeword,
-                intent: None,
-            });
-        }
 
-        let recognizer = STTSentenceRecognizer::new(&self.stt_model, &self.stt_config);
-
-        let result = recognizer
-            .recognize()
-            .map_err(|e| AssistantListenError::ProcessError(wakeword.clone(), e.into()))?;
-
-        let text = match result {
-            RecognitionResult::Final(text) => text,
-            RecognitionResult::Failed => {
-                return Err(AssistantListenError::ProcessError(
-                    wakeword,
-                    AssistantListenSuccessfulWakewordError::SpeechRecognitionError,
-                ))
+            if let Some(response) = self.wakeword_responses.get(&wakeword) {
+                // Wait for the response to finish so it isn't picked up by the recognizer
+                _ = tts_cache::speak(
+                    self.tts.as_mut(),
+                    self.utterances.as_ref(),
+                    &self.normalizer,
+                    self.tts_cache.as_ref(),
+                    response.clone(),
+                );
+                _ = self.finish_speaking();
             }
-            RecognitionResult::Cancelled => {
-                return Err(AssistantListenError::ProcessError(
+
+            if !self.wakewords_listen.contains(&wakeword) {
+                return Ok(AssistantQuery {
+                    context: self.wakeword_contexts.get(&wakeword).cloned(),
                     wakeword,
-                    AssistantListenSuccessfulWakewordError::SpeechRecognitionTimeout,
-                ))
+                    intent: None,
+                    text: None,
+                    score: None,
+                    slots: SlotValues::new(),
+                    location: self.location.clone(),
+                    wakeword_at,
+                    speech_at: None,
+                });
             }
-        };
 
-        let intent = self
-            .intent_recognizer
-            .recognize(&text)
-            .map_err(|e| AssistantListenError::ProcessError(wakeword.clone(), e.into()))?;
+            self.query_speech_recognizer = self.wakeword_speech_recognizers.get(&wakeword).cloned();
+            self.wait_until_ready();
+            self.enter_context(&wakeword);
+            self.metrics.record(
+                Metric::WakewordToListen,
+                self.clock.now().saturating_duration_since(detected),
+            );
+            let options = self.query_options();
+            let text = match self.recognize_text(&options, &mut failure) {
+                Ok(text) => text,
+                Err(e) => return Err(AssistantListenError::ProcessError(Box::new(failure), e)),
+            };
 
-        Ok(AssistantQuery {
-            wakeword,
-            intent: Some(intent),
-        })
+            if self.speech_queue.is_active() {
+                if let Some(control) = SpeechControl::from_text(&text) {
+                    _ = self.control_speech(control);
+                    continue;
+                }
+            }
+
+            // Queries handled by a skill aren't returned
+            let matched = self.match_text(wakeword, text, &mut failure);
+            self.metrics.record(
+                Metric::Query,
+                self.clock.now().saturating_duration_since(detected),
+            );
+            match matched {
+                Ok(Some(query)) => return Ok(self.resolve(query)),
+                Ok(None) => continue,
+                Err(e) => return Err(AssistantListenError::ProcessError(Box::new(failure), e)),
+            }
+        }
+    }
+
+    /// Run a single query without waiting for a wakeword, for callers that have their own
+    /// trigger, like a button. The returned query has an empty wakeword. Saying a [SpeechControl]
+    /// command while a long text is being read applies it and returns a query without an intent.
+    pub fn query_once(
+        &mut self,
+    ) -> Result<AssistantQuery<'_, T>, AssistantListenSuccessfulWakewordError> {
+        let long_text_active = self.speech_queue.is_active();
+        if long_text_active {
+            _ = self.control_speech(SpeechControl::Pause);
+        } else {
+            _ = self.finish_speaking();
+        }
+
+        self.query_speech_recognizer = None;
+        self.wait_until_ready();
+        let mut failure = QueryFailure::default();
+        let options = self.query_options();
+        let text = self.recognize_text(&options, &mut failure)?;
+
+        if long_text_active {
+            if let Some(control) = SpeechControl::from_text(&text) {
+                _ = self.control_speech(control);
+                return Ok(AssistantQuery {
+                    wakeword: String::new(),
+                    intent: None,
+                    text: Some(text),
+                    score: None,
+                    slots: SlotValues::new(),
+                    location: self.location.clone(),
+                    context: None,
+                    wakeword_at: None,
+                    speech_at: failure.speech_at,
+                });
+            }
+        }
+
+        self.process_text(&text)
+    }
+
+    /// Handle a text as if it was said after a wakeword, e.g. typed in a chat bridge or given by a
+    /// test, skipping the wakeword and speech recognition. Intents of skills are handled by their
+    /// skill as usual. Like [Assistant::query_once], the returned query has an empty wakeword, and
+    /// no intent if a skill handled it.
+    pub fn process_text(
+        &mut self,
+        text: &str,
+    ) -> Result<AssistantQuery<'_, T>, AssistantListenSuccessfulWakewordError> {
+        let mut failure = QueryFailure::default();
+        match self.match_text(String::new(), text.to_string(), &mut failure)? {
+            Some(query) => Ok(self.resolve(query)),
+            // Handled by a skill
+            None => Ok(AssistantQuery {
+                wakeword: String::new(),
+                intent: None,
+                text: Some(text.to_string()),
+                score: None,
+                slots: SlotValues::new(),
+                location: self.location.clone(),
+                context: None,
+                wakeword_at: None,
+                speech_at: None,
+            }),
+        }
+    }
+
+    /// Ask the user a question and return the answer, e.g. from a skill that needs more details.
+    /// The options tune recognition to the expected answer, see [AskOptions::yes_no] and
+    /// [AskOptions::dictation].
+    pub fn ask(
+        &mut self,
+        question: impl Into<String>,
+        options: AskOptions,
+    ) -> Result<String, AssistantListenSuccessfulWakewordError> {
+        // Wait for the question to finish so it isn't picked up by the recognizer
+        tts_cache::speak(
+            self.tts.as_mut(),
+            self.utterances.as_ref(),
+            &self.normalizer,
+            self.tts_cache.as_ref(),
+            question,
+        )
+        .map_err(|_| AssistantListenSuccessfulWakewordError::SpeechRecognitionError)?;
+        _ = self.finish_speaking();
+        self.recognize_text(&options, &mut QueryFailure::default())
+    }
+
+    /// Learn the voice of a speaker by asking them to repeat a few sentences, e.g. when they say
+    /// "learn my voice". Asks for their name if it isn't given, and returns it. Needs a speaker
+    /// identifier that can learn voices, see [AssistantConfig::set_speaker_identifier].
+    pub fn enroll_speaker(&mut self, name: Option<&str>) -> Result<String, EnrollSpeakerError> {
+        self.speakers.identifier()?;
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => spoken_name(&self.ask("What's your name?", AskOptions::short_answer())?),
+        };
+        let mut recordings = Vec::with_capacity(ENROLLMENT_PHRASES.len());
+        for (i, phrase) in ENROLLMENT_PHRASES.iter().enumerate() {
+            let prompt = match i {
+                0 => format!("Please repeat after me. {}.", phrase),
+                _ => format!("{}.", phrase),
+            };
+            tts_cache::speak(
+                self.tts.as_mut(),
+                self.utterances.as_ref(),
+                &self.normalizer,
+                self.tts_cache.as_ref(),
+                prompt,
+            )
+            .map_err(|_| AssistantListenSuccessfulWakewordError::SpeechRecognitionError)?;
+            _ = self.finish_speaking();
+            let mut failure = QueryFailure::default();
+            self.recognize_text(&AskOptions::default(), &mut failure)?;
+            recordings.push(failure.audio);
+        }
+        self.speakers.identifier()?.enroll(&name, &recordings)?;
+        _ = tts_cache::speak(
+            self.tts.as_mut(),
+            self.utterances.as_ref(),
+            &self.normalizer,
+            self.tts_cache.as_ref(),
+            format!("Thanks, {}. I'll recognize your voice from now on.", name),
+        );
+        Ok(name)
+    }
+
+    /// Teach the assistant a new wakeword by asking the user to say it a few times, and save it
+    /// as a Rustpotter file at `path`. The wakeword can be added when building the next
+    /// assistant, since the listener of this one is already running.
+    #[cfg(feature = "rustpotter")]
+    pub fn train_wakeword(&mut self, name: &str, path: &str) -> Result<(), TrainWakewordError> {
+        let mut trainer = wakeword::trainer::WakewordTrainer::new(name);
+        while trainer.sample_count() < WAKEWORD_TRAINING_SAMPLES {
+            let prompt = match trainer.sample_count() {
+                0 => format!("Please say {} after me, once each time I ask.", name),
+                _ => "Again.".to_string(),
+            };
+            tts_cache::speak(
+                self.tts.as_mut(),
+                self.utterances.as_ref(),
+                &self.normalizer,
+                self.tts_cache.as_ref(),
+                prompt,
+            )
+            .map_err(|_| AssistantListenSuccessfulWakewordError::SpeechRecognitionError)?;
+            _ = self.finish_speaking();
+            let mut failure = QueryFailure::default();
+            self.recognize_text(&AskOptions::short_answer(), &mut failure)?;
+            // Recordings without speech are asked for again
+            _ = trainer.add_sample(&failure.audio);
+        }
+        trainer.build()?.save(path)?;
+        Ok(())
+    }
+
+    /// The names of the speakers whose voice was learned.
+    pub fn speaker_profiles(&mut self) -> Result<Vec<String>, SpeakerError> {
+        self.speakers.identifier()?.profiles()
+    }
+
+    /// Forget the voice of a speaker. Returns whether it was learned.
+    pub fn remove_speaker_profile(&mut self, name: &str) -> Result<bool, SpeakerError> {
+        let removed = self.speakers.identifier()?.remove_profile(name)?;
+        // The voice and groups of the speaker no longer apply
+        if self.speakers.current() == Some(name) {
+            self.identify_speaker(&[]);
+        }
+        Ok(removed)
+    }
+
+    /// Limit speech recognition of the next queries to the words of the enabled intents, which is
+    /// much more accurate for small vocabularies. Words outside of it are recognized as `[unk]`,
+    /// so turn it off before queries with free text, like notes. Off by default.
+    pub fn set_intent_grammar(&mut self, enabled: bool) {
+        self.intent_grammar = enabled;
+    }
+
+    /// The recognition options of queries, see [Assistant::set_intent_grammar].
+    fn query_options(&self) -> AskOptions {
+        let alternatives = (self.query_alternatives > 0).then_some(self.query_alternatives);
+        if !self.intent_grammar {
+            return AskOptions {
+                alternatives,
+                ..AskOptions::default()
+            };
+        }
+        let mut grammar = self.intent_recognizer.get().grammar();
+        if self.speech_queue.is_active() {
+            grammar.extend(SpeechControl::vocabulary().map(str::to_string));
+        }
+        AskOptions {
+            grammar: Some(grammar),
+            alternatives,
+            ..AskOptions::default()
+        }
+    }
+
+    /// Recognize a sentence, reprompting as configured with [AssistantConfig::set_reprompt_policy].
+    /// Options that aren't set use the values of the settings profile. The audio of the last
+    /// attempt is recorded in `failure`.
+    fn recognize_text(
+        &mut self,
+        options: &AskOptions,
+        failure: &mut QueryFailure,
+    ) -> Result<String, AssistantListenSuccessfulWakewordError> {
+        let mut retries = 0;
+        loop {
+            self.events.emit(AssistantEvent::RecognitionStarted);
+            let started = self.clock.now();
+            let result = self
+                .sentence_recognizer(options)
+                .and_then(|recognizer| recognizer.recognize_with_audio());
+            self.metrics.record(
+                Metric::SpeechRecognition,
+                self.clock.now().saturating_duration_since(started),
+            );
+            if let Some(text) = self.handle_recognition_result(result, &mut retries, failure)? {
+                return Ok(text);
+            }
+            // Wait for the prompt to finish so it isn't picked up by the recognizer
+            _ = self.finish_speaking();
+        }
     }
 
+    fn sentence_recognizer(
+        &self,
+        options: &AskOptions,
+    ) -> Result<STTSentenceRecognizer<'_>, RecognitionError> {
+        let speech_recognizer = self
+            .active_speech_recognizer()
+            .ok_or(RecognitionError::NoSpeechRecognizer)?;
+        let mut recognizer = STTSentenceRecognizer::new(speech_recognizer, &self.audio_input);
+        recognizer.set_timeout(options.timeout.unwrap_or(self.profile.stt_timeout));
+        recognizer.set_endpoint_config(
+            options
+                .endpoint
+                .clone()
+                .unwrap_or_else(|| self.profile.endpoint.clone()),
+        );
+        recognizer.set_grammar(options.grammar.clone());
+        recognizer.set_max_alternatives(options.alternatives);
+        recognizer.set_clock(self.clock.clone());
+        Ok(recognizer)
+    }
+
+    /// The speech recognizer of the current query: the one of its wakeword, else the one the
+    /// speaker of the last query prefers, else the one of the active language, else the default
+    /// one. Names that aren't registered are skipped.
+    fn active_speech_recognizer(&self) -> Option<&dyn SpeechRecognizer> {
+        self.query_speech_recognizer
+            .as_ref()
+            .or_else(|| self.speakers.preferences()?.speech_recognizer.as_ref())
+            .and_then(|name| self.speech_recognizers.get(name))
+            .map(|recognizer| recognizer.as_ref())
+            .or_else(|| self.languages.speech_recognizer())
+            .or(self.speech_recognizer.as_deref())
+    }
+
+    /// Recognize the intents of the context of the wakeword, see
+    /// [AssistantConfig::set_wakeword_context]. Returns the context.
+    fn enter_context(&mut self, wakeword: &str) -> Option<String> {
+        let context = self.wakeword_contexts.get(wakeword).cloned();
+        self.intent_recognizer
+            .get_mut()
+            .set_context(context.as_deref());
+        context
+    }
+
+    /// Switch to a language registered with [AssistantConfig::add_language], e.g. when the user
+    /// says "speak Italian": its speech recognizer, voice and intents are used from the next
+    /// query. `None` goes back to the default recognizer and voice, with all intents.
+    pub fn set_language(&mut self, language: Option<&str>) -> Result<(), LanguageError> {
+        self.languages.switch(language, self.tts.as_mut())?;
+        // The cached phrases were synthesized with the voice of the previous language
+        if let Some(cache) = &self.tts_cache {
+            cache.clear();
+        }
+        self.intent_recognizer.get_mut().set_language(language);
+        Ok(())
+    }
+
+    /// The active language, see [Assistant::set_language].
+    pub fn language(&self) -> Option<&str> {
+        self.languages.active()
+    }
+
+    /// The names of the languages registered with [AssistantConfig::add_language].
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.languages.names()
+    }
+
+    /// Return the recognized text, or start speaking the reprompt and return `None` if the
+    /// recognition should be retried.
+    fn handle_recognition_result(
+        &mut self,
+        result: Result<(RecognitionResult, RecognizedAudio), RecognitionError>,
+        retries: &mut usize,
+        failure: &mut QueryFailure,
+    ) -> Result<Option<String>, AssistantListenSuccessfulWakewordError> {
+        let result = match result {
+            Ok((result, audio)) => {
+                self.record_query(&audio.samples);
+                failure.audio = audio.samples;
+                failure.speech_at = audio.start;
+                result
+            }
+            Err(e) => {
+                self.events.emit(AssistantEvent::RecognitionFinished(None));
+                self.events.emit(AssistantEvent::Error(e.to_string()));
+                return Err(e.into());
+            }
+        };
+        let (policy, error) = match result {
+            RecognitionResult::Final(sentence) => {
+                let text = self.best_alternative(sentence);
+                self.events
+                    .emit(AssistantEvent::RecognitionFinished(Some(text.clone())));
+                return Ok(Some(text));
+            }
+            RecognitionResult::Failed => (
+                &self.reprompt_on_failure,
+                AssistantListenSuccessfulWakewordError::SpeechRecognitionError,
+            ),
+            RecognitionResult::Cancelled => (
+                &self.reprompt_on_timeout,
+                AssistantListenSuccessfulWakewordError::SpeechRecognitionTimeout,
+            ),
+        };
+        self.events.emit(AssistantEvent::RecognitionFinished(None));
+
+        match policy {
+            Some(policy) if *retries < policy.max_retries => {
+                *retries += 1;
+                _ = tts_cache::speak(
+                    self.tts.as_mut(),
+                    self.utterances.as_ref(),
+                    &self.normalizer,
+                    self.tts_cache.as_ref(),
+                    policy.prompt.clone(),
+                );
+                Ok(None)
+            }
+            _ => {
+                self.events.emit(AssistantEvent::Error(error.to_string()));
+                Err(error)
+            }
+        }
+    }
+
+    /// The alternative transcription whose intent scores highest, or the most likely one if none
+    /// matches an intent. See [AskOptions::alternatives].
+    fn best_alternative(&self, sentence: Sentence) -> String {
+        if sentence.alternatives.len() < 2 {
+            return sentence.text;
+        }
+        let mut best: Option<(&str, f32)> = None;
+        for alternative in &sentence.alternatives {
+            if let Ok((_, score, _)) = self
+                .intent_recognizer
+                .get()
+                .recognize_index(&alternative.text)
+            {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(text = %alternative.text, score, "scored alternative");
+                // The first alternative is the most likely, so it wins ties
+                if best.is_none_or(|(_, best_score)| score > best_score) {
+                    best = Some((&alternative.text, score));
+                }
+            }
+        }
+        match best {
+            Some((text, _)) => text.to_string(),
+            None => sentence.text,
+        }
+    }
+
+    /// Recognize the intent of the text, recording the candidates in `failure` if none matches.
+    /// Returns `None` if the intent belongs to a [Skill], which then handled the query.
+    fn match_text(
+        &mut self,
+        wakeword: String,
+        text: String,
+        failure: &mut QueryFailure,
+    ) -> Result<Option<MatchedQuery>, AssistantListenSuccessfulWakewordError> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("match_text", %wakeword, %text).entered();
+        failure.transcript = Some(text.clone());
+        self.identify_speaker(&failure.audio);
+        let context = self.enter_context(&wakeword);
+        let (index, score, slots) = match self.intent_recognizer.get().recognize_index(&text) {
+            Ok(intent_match) => intent_match,
+            Err(IntentRecognizerError::ScoreTooLow) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!("no intent scored high enough");
+                if let Some(shadow) = &self.shadow_intents {
+                    if let Some(divergence) = shadow.compare(&text, None) {
+                        self.events
+                            .emit(AssistantEvent::ShadowDivergence(divergence));
+                    }
+                }
+                failure.candidates = self
+                    .intent_recognizer
+                    .get()
+                    .candidates(&text, 3)
+                    .unwrap_or_default();
+                if let Some(response) = &self.not_understood_response {
+                    _ = tts_cache::speak(
+                        self.tts.as_mut(),
+                        self.utterances.as_ref(),
+                        &self.normalizer,
+                        self.tts_cache.as_ref(),
+                        response.replace("{text}", &text),
+                    );
+                }
+                let error = AssistantListenSuccessfulWakewordError::NotUnderstood(text);
+                self.events.emit(AssistantEvent::Error(error.to_string()));
+                return Err(error);
+            }
+            // Without intents, the application gets the text, e.g. to forward it
+            Err(IntentRecognizerError::NoEmbeddingModel) => {
+                self.dialog = DialogContext {
+                    query: Some(text.clone()),
+                    ..DialogContext::default()
+                };
+                return Ok(Some(MatchedQuery {
+                    wakeword,
+                    intent: None,
+                    text,
+                    score: None,
+                    slots: SlotValues::new(),
+                    location: self.location.clone(),
+                    context,
+                    wakeword_at: failure.wakeword_at,
+                    speech_at: failure.speech_at,
+                }));
+            }
+            Err(e) => {
+                self.events.emit(AssistantEvent::Error(e.to_string()));
+                return Err(e.into());
+            }
+        };
+        self.events.emit(AssistantEvent::IntentMatched {
+            text: text.clone(),
+            score,
+        });
+        let triggered = self.automation.fire(
+            Fired::Intent(self.intent_recognizer.get().intent(index)),
+            self.clock.local_now().time(),
+            0,
+        );
+        self.emit_triggered(triggered);
+        // Shadow intents only cover the intents of the application
+        if let (Some(shadow), IntentTarget::App(active)) = (
+            &self.shadow_intents,
+            self.intent_recognizer.get().intent(index),
+        ) {
+            if let Some(divergence) = shadow.compare(&text, Some(active)) {
+                self.events
+                    .emit(AssistantEvent::ShadowDivergence(divergence));
+            }
+        }
+
+        let location = match slots.get(ROOM_SLOT) {
+            Some(SlotValue::Entity(room) | SlotValue::Text(room)) => Some(room.clone()),
+            _ => self.location.clone(),
+        };
+
+        let target = self.intent_recognizer.get().intent(index);
+        let dispatched = match target {
+            IntentTarget::App(intent) => DispatchedIntent::App(intent),
+            IntentTarget::Skill { intent, .. } => DispatchedIntent::Skill(intent.as_str()),
+        };
+        let query = AssistantQuery {
+            wakeword,
+            intent: Some(&dispatched),
+            text: Some(text),
+            score: Some(score),
+            slots,
+            location,
+            context,
+            wakeword_at: failure.wakeword_at,
+            speech_at: failure.speech_at,
+        };
+        let mut ctx = SkillContext {
+            tts: self.tts.as_mut(),
+            utterances: self.utterances.as_ref(),
+            normalizer: &self.normalizer,
+            tts_cache: self.tts_cache.as_ref(),
+            clock: self.clock.as_ref(),
+            random: self.random.as_ref(),
+            storage: self.storage.as_deref(),
+            http_cache: &self.http_cache,
+            #[cfg(feature = "http")]
+            http_client: &self.http_client,
+            schedule: &mut self.schedule,
+            speakers: &self.speakers,
+            profile: &self.profile,
+            #[cfg(feature = "offline")]
+            online: self.connectivity.as_ref().is_none_or(|c| c.is_online()),
+            granted: Capability::ALL.into_iter().collect(),
+            acknowledgement: None,
+            dialog: &self.dialog,
+            said: Vec::new(),
+            pending: None,
+        };
+        let mut passed = 0;
+        for middleware in &mut self.middlewares {
+            if middleware.before(&mut ctx, &query) == Flow::Stop {
+                break;
+            }
+            passed += 1;
+        }
+        let stopped = passed < self.middlewares.len();
+
+        let mut handled_by = None;
+        if let (false, IntentTarget::Skill { skill, intent }) = (stopped, target) {
+            let skill_query = AssistantQuery {
+                wakeword: query.wakeword.clone(),
+                intent: Some(intent.as_str()),
+                text: query.text.clone(),
+                score: query.score,
+                slots: query.slots.clone(),
+                location: query.location.clone(),
+                context: query.context.clone(),
+                wakeword_at: query.wakeword_at,
+                speech_at: query.speech_at,
+            };
+            let skill = &mut self.skills[*skill];
+            if self.dialog.skill.as_deref() == Some(skill.name()) {
+                ctx.pending = self.dialog.pending.clone();
+            }
+            handled_by = Some(skill.name().to_string());
+            let all = std::mem::replace(
+                &mut ctx.granted,
+                self.permissions
+                    .granted(skill.name(), &skill.capabilities()),
+            );
+            let acknowledgement = self
+                .acknowledgements
+                .get(intent)
+                .or_else(|| self.acknowledgements.get(skill.name()));
+            if let Some(acknowledgement) = acknowledgement {
+                let text = skill_query.text.as_deref().unwrap_or_default();
+                if let Err(e) = acknowledgement.start(&mut ctx, text, &skill_query.slots) {
+                    warn!("Failed to acknowledge the query: {}", e);
+                }
+            }
+            #[cfg(feature = "offline")]
+            if skill.needs_network() && !ctx.is_online() {
+                skill.handle_offline(&mut ctx, &skill_query);
+            } else {
+                skill.handle(&mut ctx, &skill_query);
+            }
+            #[cfg(not(feature = "offline"))]
+            skill.handle(&mut ctx, &skill_query);
+            ctx.finish_acknowledgement();
+            ctx.granted = all;
+        }
+        for middleware in self.middlewares[..passed].iter_mut().rev() {
+            middleware.after(&mut ctx, &query);
+        }
+        let pending = ctx.pending.take();
+        let said = std::mem::take(&mut ctx.said);
+        self.dialog = DialogContext {
+            query: query.text.clone(),
+            response: (!said.is_empty()).then(|| said.join(" ")),
+            pending: pending.filter(|_| handled_by.is_some()),
+            skill: handled_by,
+        };
+        if stopped || matches!(target, IntentTarget::Skill { .. }) {
+            return Ok(None);
+        }
+
+        Ok(Some(MatchedQuery {
+            wakeword: query.wakeword,
+            intent: Some(index),
+            text: query.text.unwrap_or_default(),
+            score: Some(score),
+            slots: query.slots,
+            location: query.location,
+            context: query.context,
+            wakeword_at: query.wakeword_at,
+            speech_at: query.speech_at,
+        }))
+    }
+
+    /// Look up the intent of a query returned by [Assistant::match_text].
+    fn resolve(&self, query: MatchedQuery) -> AssistantQuery<'_, T> {
+        let intent = query
+            .intent
+            .map(|index| match self.intent_recognizer.get().intent(index) {
+                IntentTarget::App(intent) => intent,
+                IntentTarget::Skill { .. } => {
+                    unreachable!("Skill queries are handled by the skill")
+                }
+            });
+        AssistantQuery {
+            wakeword: query.wakeword,
+            intent,
+            text: Some(query.text),
+            score: query.score,
+            slots: query.slots,
+            location: query.location,
+            context: query.context,
+            wakeword_at: query.wakeword_at,
+            speech_at: query.speech_at,
+        }
+    }
+
+    /// Stop recognizing the intents of a group, e.g. when the user says "disable smart home
+    /// commands". The group stays disabled after a restart if storage is set with
+    /// [AssistantConfig::set_storage].
+    pub fn disable_intent_group(&mut self, group: &str) -> Result<(), StorageError> {
+        self.intent_recognizer.get_mut().disable_group(group);
+        match &self.storage {
+            Some(storage) => storage.set(DISABLED_GROUPS_NAMESPACE, group, &[]),
+            None => Ok(()),
+        }
+    }
+
+    pub fn enable_intent_group(&mut self, group: &str) -> Result<(), StorageError> {
+        self.intent_recognizer.get_mut().enable_group(group);
+        match &self.storage {
+            Some(storage) => storage.remove(DISABLED_GROUPS_NAMESPACE, group),
+            None => Ok(()),
+        }
+    }
+
+    pub fn is_intent_group_enabled(&self, group: &str) -> bool {
+        self.intent_recognizer.get().is_group_enabled(group)
+    }
+
+    /// The `count` intents of the application closest to the text, best first, see
+    /// [IntentRecognizer::recognize_top_n]. The intents of skills are left out.
+    pub fn recognize_top_n(
+        &self,
+        text: &str,
+        count: usize,
+    ) -> Result<Vec<RankedIntent<'_, T>>, IntentRecognizerError> {
+        Ok(self
+            .intent_recognizer
+            .get()
+            .recognize_top_n(text, usize::MAX)?
+            .into_iter()
+            .filter_map(|ranked| match ranked.intent {
+                IntentTarget::App(intent) => Some(RankedIntent {
+                    intent,
+                    score: ranked.score,
+                    above_threshold: ranked.above_threshold,
+                }),
+                IntentTarget::Skill { .. } => None,
+            })
+            .take(count)
+            .collect())
+    }
+
+    /// Add an intent while running, e.g. for a phrase the user taught the assistant. Its examples
+    /// are embedded straight away. Intents added while running aren't in any group.
+    pub fn add_intent(
+        &mut self,
+        id: T,
+        examples: Vec<String>,
+    ) -> Result<(), IntentRecognizerError> {
+        self.intent_recognizer
+            .get_mut()
+            .add_intent(IntentTarget::App(id), examples)
+    }
+
+    /// See [AssistantConfig::add_intent_with_slots] and [Assistant::add_intent].
+    pub fn add_intent_with_slots(
+        &mut self,
+        id: T,
+        templates: Vec<String>,
+        slots: Vec<Slot>,
+    ) -> Result<(), IntentRecognizerError> {
+        self.intent_recognizer.get_mut().add_intent_with_slots(
+            IntentTarget::App(id),
+            templates,
+            slots,
+        )
+    }
+
+    /// Stop recognizing the intents added with this id. Returns whether there were any.
+    pub fn remove_intent(&mut self, id: &T) -> bool
+    where
+        T: PartialEq,
+    {
+        self.intent_recognizer.get_mut().remove_intents_where(
+            |target| matches!(target, IntentTarget::App(intent) if intent == id),
+        )
+    }
+
+    /// Register a skill while running, e.g. one loaded from a plugin, see
+    /// [AssistantConfig::add_skill].
+    pub fn add_skill(&mut self, skill: impl Skill + 'static) -> Result<(), IntentRecognizerError> {
+        let index = self.skills.len();
+        for spec in skill.intents() {
+            let target = IntentTarget::Skill {
+                skill: index,
+                intent: spec.name,
+            };
+            if let Err(e) = self.intent_recognizer.get_mut().add_intent_with_slots(
+                target,
+                spec.examples,
+                spec.slots,
+            ) {
+                // The skill isn't registered, so none of its intents may stay
+                self.intent_recognizer.get_mut().remove_intents_where(
+                    |target| matches!(target, IntentTarget::Skill { skill, .. } if *skill == index),
+                );
+                return Err(e);
+            }
+        }
+        self.skills.push(Box::new(skill));
+        Ok(())
+    }
+
+    /// Change the lowest score for an intent to be recognized while running, e.g. to tune it, see
+    /// [AssistantConfig::set_intent_threshold].
+    pub fn set_intent_threshold(&mut self, threshold: f32) {
+        self.intent_recognizer.get_mut().set_threshold(threshold);
+    }
+
+    /// Change the settings of the wakeword detector while running, see
+    /// [AssistantConfig::set_wakeword_detector]. The wakewords are kept.
+    #[cfg(feature = "rustpotter")]
+    pub fn set_wakeword_detector(&self, settings: wakeword::DetectorSettings) {
+        self.wakeword_listener.set_detector_settings(settings);
+    }
+
+    /// How much of the captured audio the wakeword engine processed, see
+    /// [AssistantConfig::set_power_mode].
+    pub fn power_stats(&self) -> PowerStats {
+        self.wakeword_listener.power_stats()
+    }
+
+    /// Receive the [AssistantEvent]s of every following query, from any thread.
+    pub fn events(&mut self) -> Receiver<AssistantEvent> {
+        self.events.subscribe()
+    }
+
+    /// Start playing a WAV or OGG Vorbis file without waiting for it to finish, mixed with
+    /// anything else being played.
+    pub fn play_sound(&mut self, path: impl AsRef<Path>) -> Result<(), SoundError> {
+        self.events.earcons.play(path.as_ref())
+    }
+
+    /// Change the gain of the earcons, see [AssistantConfig::set_earcon_volume]. Sounds played
+    /// with [Assistant::play_sound] are played as they are.
+    pub fn set_earcon_volume(&mut self, volume: f32) {
+        self.events.earcons.set_volume(volume);
+    }
+
+    /// Fails with [TtsError::UnsupportedFeature] without a TTS backend, see
+    /// [AssistantConfigBuilder::set_tts].
     pub fn speak(&mut self, text: impl Into<String>) -> Result<(), TtsError> {
-        tts_speak(&mut self.tts, text)
+        let text = text.into();
+        self.dialog.respond(&text);
+        tts_cache::speak(
+            Some(tts::backend(&mut self.tts)?),
+            self.utterances.as_ref(),
+            &self.normalizer,
+            self.tts_cache.as_ref(),
+            text,
+        )
+    }
+
+    /// Like [Assistant::speak], but returns the id of the utterance, which can be waited for with
+    /// [Assistant::wait_for]. `None` if the backend doesn't identify utterances.
+    pub fn speak_utterance(
+        &mut self,
+        text: impl Into<String>,
+    ) -> Result<Option<UtteranceId>, TtsError> {
+        if let Some(utterances) = &self.utterances {
+            utterances.requested();
+        }
+        let id = tts_speak_utterance(tts::backend(&mut self.tts)?, &self.normalizer, text)?;
+        if let (Some(utterances), Some(id)) = (&self.utterances, id) {
+            utterances.track(id, None);
+        }
+        Ok(id)
+    }
+
+    /// Speak the text and block until it was spoken or interrupted.
+    pub fn speak_blocking(&mut self, text: impl Into<String>) -> Result<(), TtsError> {
+        match self.speak_utterance(text)? {
+            Some(id) => self.wait_for(id),
+            None => self.finish_speaking(),
+        }
+    }
+
+    /// Speak the text and call `callback` once it was spoken or interrupted, from the thread of
+    /// the backend. Backends without utterance callbacks block until the text was spoken instead.
+    pub fn speak_with_callback(
+        &mut self,
+        text: impl Into<String>,
+        callback: impl FnOnce() + Send + 'static,
+    ) -> Result<Option<UtteranceId>, TtsError> {
+        if let Some(utterances) = &self.utterances {
+            utterances.requested();
+        }
+        let id = tts_speak_utterance(tts::backend(&mut self.tts)?, &self.normalizer, text)?;
+        match (&self.utterances, id) {
+            (Some(utterances), Some(id)) => utterances.track(id, Some(Box::new(callback))),
+            _ => {
+                self.finish_speaking()?;
+                callback();
+            }
+        }
+        Ok(id)
+    }
+
+    /// Block until the utterance was spoken or interrupted. Backends without utterance callbacks
+    /// wait until nothing is being spoken instead.
+    pub fn wait_for(&self, id: UtteranceId) -> Result<(), TtsError> {
+        match &self.utterances {
+            Some(utterances) => {
+                utterances.wait_for(id);
+                Ok(())
+            }
+            None => self.finish_speaking(),
+        }
+    }
+
+    /// Read out a long text, like a news article, one chunk at a time. Reading continues while
+    /// [Assistant::listen] waits for a wakeword and can be controlled by saying a
+    /// [SpeechControl] command after the wakeword, or with [Assistant::control_speech].
+    pub fn speak_long(&mut self, text: &str) -> Result<(), TtsError> {
+        tts::stop(self.tts.as_mut())?;
+        self.speech_queue.replace(text);
+        self.continue_speaking_long()
+    }
+
+    pub fn control_speech(&mut self, control: SpeechControl) -> Result<(), TtsError> {
+        match control {
+            SpeechControl::Pause => {
+                tts::stop(self.tts.as_mut())?;
+                self.speech_queue.pause();
+            }
+            SpeechControl::Resume => self.speech_queue.resume(),
+            SpeechControl::Skip => {
+                tts::stop(self.tts.as_mut())?;
+                self.speech_queue.skip();
+            }
+            SpeechControl::Stop => {
+                tts::stop(self.tts.as_mut())?;
+                self.speech_queue.clear();
+            }
+        }
+        self.continue_speaking_long()
+    }
+
+    fn emit_shadow_divergences(&mut self) {
+        for divergence in self.wakeword_listener.shadow_divergences() {
+            self.events
+                .emit(AssistantEvent::ShadowDivergence(divergence));
+        }
+    }
+
+    /// Run the commands sent with [Assistant::remote] and return the first text query, if any.
+    /// The commands after it are run on the next call.
+    fn run_remote_commands(&mut self) -> Option<String> {
+        while let Some(command) = self.remote_commands.try_next() {
+            match command {
+                RemoteCommand::Query(text) => return Some(text),
+                RemoteCommand::Speak(text) => {
+                    _ = tts_cache::speak(
+                        self.tts.as_mut(),
+                        self.utterances.as_ref(),
+                        &self.normalizer,
+                        self.tts_cache.as_ref(),
+                        text,
+                    )
+                }
+                RemoteCommand::SetMuted(muted) => self.set_muted(muted),
+                RemoteCommand::SetListening(listening) => {
+                    let result = match listening {
+                        true => self.resume_listening(),
+                        false => self.pause_listening(),
+                    };
+                    if let Err(e) = result {
+                        self.events.emit(AssistantEvent::Error(e.to_string()));
+                    }
+                }
+                RemoteCommand::Shutdown => {
+                    self.shutdown();
+                    return None;
+                }
+                RemoteCommand::SetIntentThreshold(threshold) => {
+                    self.set_intent_threshold(threshold)
+                }
+                #[cfg(feature = "rustpotter")]
+                RemoteCommand::SetWakewordDetector(settings) => {
+                    self.set_wakeword_detector(settings)
+                }
+                RemoteCommand::Announce(mut actions) => {
+                    if self.automation.do_not_disturb() {
+                        actions.retain(|action| !matches!(action, Action::Speak(_)));
+                    }
+                    self.automation.queue(actions);
+                }
+                #[cfg(feature = "sync")]
+                RemoteCommand::ResumeDialog(sealed) => {
+                    let context = self.dialog_key.as_ref().and_then(|key| {
+                        DialogContext::open(key, &sealed, std::time::SystemTime::now())
+                    });
+                    match context {
+                        Some(context) => self.resume_dialog(context),
+                        None => {
+                            warn!("Refusing a dialog that wasn't sealed with the sync key");
+                            self.events.emit(AssistantEvent::Error(
+                                "Refused a handed off dialog".to_string(),
+                            ));
+                        }
+                    }
+                }
+                RemoteCommand::MqttMessage { topic, .. } => {
+                    let triggered = self.automation.fire(
+                        Fired::MqttMessage(&topic),
+                        self.clock.local_now().time(),
+                        0,
+                    );
+                    self.emit_triggered(triggered);
+                }
+            }
+        }
+        None
+    }
+
+    /// Emit and announce the scheduled items that are due.
+    fn announce_due_items(&mut self) {
+        let now = self.clock.local_now();
+        for item in self.schedule.take_due(now) {
+            let announcement = item.announcement();
+            let triggered = self
+                .automation
+                .fire(Fired::ScheduledItemDue(&item), now.time(), 0);
+            self.emit_triggered(triggered);
+            self.events.emit(AssistantEvent::ScheduledItemDue(item));
+            _ = tts_cache::speak(
+                self.tts.as_mut(),
+                self.utterances.as_ref(),
+                &self.normalizer,
+                self.tts_cache.as_ref(),
+                announcement,
+            );
+        }
+    }
+
+    fn emit_triggered(&mut self, rules: Vec<String>) {
+        for rule in rules {
+            self.events.emit(AssistantEvent::RuleTriggered(rule));
+        }
+    }
+
+    /// Run the actions of the triggered automation rules. Speech waits for the assistant to be
+    /// quiet, so it doesn't cut off the response to the query that triggered the rule.
+    fn run_automation(&mut self) {
+        while self.automation.has_pending() {
+            if tts::is_speaking(self.tts.as_ref()).unwrap_or(false) {
+                return;
+            }
+            let Some((action, chain)) = self.automation.next_action() else {
+                return;
+            };
+            match action {
+                Action::Speak(text) => {
+                    _ = tts_cache::speak(
+                        self.tts.as_mut(),
+                        self.utterances.as_ref(),
+                        &self.normalizer,
+                        self.tts_cache.as_ref(),
+                        text,
+                    )
+                }
+                Action::Query(text) => self.remote_commands.push(RemoteCommand::Query(text)),
+                Action::SetDoNotDisturb(on) => self.set_do_not_disturb(on),
+                Action::SetState { key, value } => self.change_state(&key, &value, chain),
+                #[cfg(feature = "home")]
+                action => self.automation.run_network_action(&action),
+            }
+        }
+    }
+
+    fn resume_listening_when_due(&mut self) {
+        if self
+            .listening_paused_until
+            .is_some_and(|until| until <= self.clock.local_now())
+        {
+            if let Err(e) = self.resume_listening() {
+                warn!("Failed to resume listening: {}", e);
+            }
+        }
+    }
+
+    fn end_guest_mode_when_due(&mut self) {
+        if self
+            .guest_mode
+            .until()
+            .is_some_and(|until| until <= self.clock.local_now())
+        {
+            if let Err(e) = self.end_guest_mode() {
+                warn!("Failed to save the end of guest mode: {}", e);
+            }
+        }
+    }
+
+    /// Emit the audio cues that were heard and run the rules they trigger.
+    fn poll_audio_cues(&mut self) {
+        for source in &mut self.audio_cues {
+            for cue in source.poll() {
+                let triggered = self.automation.fire(
+                    Fired::AudioCue(&cue.name),
+                    self.clock.local_now().time(),
+                    0,
+                );
+                for name in triggered {
+                    self.events.emit(AssistantEvent::RuleTriggered(name));
+                }
+                self.events.emit(AssistantEvent::AudioCue {
+                    source: source.name().to_string(),
+                    cue,
+                });
+            }
+        }
+    }
+
+    /// Whether the models loaded in the background are ready, see
+    /// [AssistantConfig::build_in_background]. Always true otherwise.
+    pub fn is_ready(&self) -> bool {
+        self.intent_recognizer.is_ready()
+            && self
+                .speech_recognizer
+                .as_ref()
+                .is_none_or(|recognizer| recognizer.is_ready())
+            && self
+                .speech_recognizers
+                .values()
+                .all(|recognizer| recognizer.is_ready())
+            && self.languages.is_ready()
+    }
+
+    /// Emit [StartupProgress::Ready] once the models loaded in the background are ready, and
+    /// speak the greeting, see [AssistantConfig::set_greeting].
+    fn announce_ready(&mut self) {
+        if !self.ready && self.is_ready() {
+            self.ready = true;
+            self.events
+                .emit(AssistantEvent::Startup(StartupProgress::Ready));
+            if let Some(greeting) = &self.greeting {
+                _ = tts_cache::speak(
+                    self.tts.as_mut(),
+                    self.utterances.as_ref(),
+                    &self.normalizer,
+                    self.tts_cache.as_ref(),
+                    greeting,
+                );
+            }
+        }
+    }
+
+    /// Ask the user to wait if the models loaded in the background aren't ready, and wait for
+    /// them before going on with the query.
+    fn wait_until_ready(&mut self) {
+        if self.is_ready() {
+            return;
+        }
+        _ = tts_cache::speak(
+            self.tts.as_mut(),
+            self.utterances.as_ref(),
+            &self.normalizer,
+            self.tts_cache.as_ref(),
+            STARTING_UP_RESPONSE,
+        );
+        _ = self.finish_speaking();
+        while !self.is_ready() {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        self.announce_ready();
+    }
+
+    /// Whether someone is present to have said the wakeword, see
+    /// [AssistantConfig::set_presence_sensor].
+    fn confirm_presence(&mut self, wakeword: &str) -> bool {
+        let present = self
+            .presence_sensor
+            .as_mut()
+            .is_none_or(|sensor| sensor.is_present());
+        if !present {
+            info!("Ignored the wakeword {}, nobody is present", wakeword);
+        }
+        present
+    }
+
+    /// Save the audio of a detected wakeword, see [AssistantConfig::set_recording].
+    fn record_wakeword(&self, wakeword: &str) {
+        if let Some(recorder) = &self.recorder {
+            if let Err(e) = recorder.save_wakeword(wakeword, self.clock.local_now()) {
+                warn!("Failed to record the wakeword {}: {}", wakeword, e);
+            }
+        }
+    }
+
+    /// Save the audio of a speech recognition session, see [AssistantConfig::set_recording].
+    fn record_query(&self, audio: &[i16]) {
+        if let Some(recorder) = &self.recorder {
+            if let Err(e) = recorder.save_query(audio, self.clock.local_now()) {
+                warn!("Failed to record the query: {}", e);
+            }
+        }
+    }
+
+    /// Reconnect to the microphone when its stream failed, wakewords aren't heard until then.
+    fn supervise_audio_input(&mut self) {
+        let Some(status) = self.audio_input.supervise() else {
+            return;
+        };
+        match &status {
+            AudioInputStatus::Disconnected(reason) => {
+                warn!("Lost the audio input ({}), reconnecting...", reason)
+            }
+            AudioInputStatus::Reconnected => {
+                info!("Reconnected to the audio input.");
+                // Detections of the old stream may be cut off
+                self.wakeword_listener.drain();
+            }
+        }
+        self.events.emit(AssistantEvent::AudioInput(status));
+    }
+
+    /// Emit [AssistantEvent::ConnectivityChanged] when the connectivity monitor noticed a change.
+    #[cfg(feature = "offline")]
+    fn watch_connectivity(&mut self) {
+        let online = self.is_online();
+        if online != self.was_online {
+            self.was_online = online;
+            self.events
+                .emit(AssistantEvent::ConnectivityChanged(online));
+        }
+    }
+
+    /// Whether the assistant can reach the internet, see
+    /// [AssistantConfig::set_connectivity_monitor]. Enabled with the `offline` feature.
+    #[cfg(feature = "offline")]
+    pub fn is_online(&self) -> bool {
+        self.connectivity.as_ref().is_none_or(|c| c.is_online())
+    }
+
+    /// Reload the schedule and the disabled intent groups after the sync thread changed them.
+    #[cfg(feature = "sync")]
+    fn reload_synced(&mut self) {
+        let changed = self
+            .synced
+            .as_ref()
+            .is_some_and(|changed| changed.swap(false, std::sync::atomic::Ordering::Relaxed));
+        if !changed {
+            return;
+        }
+        match Schedule::load(self.storage.clone()) {
+            Ok(schedule) => self.schedule = schedule,
+            Err(e) => warn!("Failed to reload the schedule: {}", e),
+        }
+        let Some(storage) = &self.storage else {
+            return;
+        };
+        match storage.keys(DISABLED_GROUPS_NAMESPACE) {
+            Ok(disabled) => {
+                let enabled: Vec<String> = self
+                    .intent_recognizer
+                    .get()
+                    .disabled_groups()
+                    .filter(|group| !disabled.iter().any(|d| d == group))
+                    .map(str::to_string)
+                    .collect();
+                for group in enabled {
+                    self.intent_recognizer.get_mut().enable_group(&group);
+                }
+                for group in &disabled {
+                    self.intent_recognizer.get_mut().disable_group(group);
+                }
+            }
+            Err(e) => warn!("Failed to reload the disabled intent groups: {}", e),
+        }
+    }
+
+    /// Give the next chunk of a long text to the TTS backend once the previous one is done.
+    fn continue_speaking_long(&mut self) -> Result<(), TtsError> {
+        if !self.speech_queue.is_active() || tts::is_speaking(self.tts.as_ref())? {
+            return Ok(());
+        }
+        if let Some(chunk) = self.speech_queue.next_chunk() {
+            tts_speak(tts::backend(&mut self.tts)?, &self.normalizer, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Spell out text using the NATO phonetic alphabet, e.g. to read out a password.
+    pub fn speak_phonetic(&mut self, text: &str) -> Result<(), TtsError> {
+        tts_speak(
+            tts::backend(&mut self.tts)?,
+            &self.normalizer,
+            phonetic::spell_nato(text),
+        )
+    }
+
+    /// Switch to another settings profile, e.g. when the user asks for accessibility mode by voice.
+    pub fn set_profile(&mut self, profile: SettingsProfile) -> Result<(), TtsError> {
+        if let Some(tts) = &mut self.tts {
+            profile.apply_tts(tts)?;
+        }
+        self.events
+            .earcons
+            .set_profile_volume(profile.earcon_volume);
+        self.profile = profile;
+        Ok(())
+    }
+
+    pub fn profile(&self) -> &SettingsProfile {
+        &self.profile
+    }
+
+    /// The room set with [AssistantConfig::set_location].
+    pub fn location(&self) -> Option<&str> {
+        self.location.as_deref()
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    pub fn random(&self) -> &dyn Random {
+        self.random.as_ref()
+    }
+
+    /// The cache of HTTP responses shared by the skills, e.g. to show what is in it.
+    pub fn http_cache(&self) -> HttpCache {
+        self.http_cache.clone()
+    }
+
+    /// The latencies of the queries so far, see [Metric].
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// The metrics of the assistant, to read them from another thread, e.g. for an exporter.
+    pub fn metrics_handle(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    #[cfg(feature = "http")]
+    pub fn http_client(&self) -> http::HttpClient {
+        self.http_client.clone()
+    }
+
+    /// A handle to control the assistant from other threads, e.g. from a network API. Commands
+    /// run while [Assistant::listen] waits for a wakeword.
+    pub fn remote(&self) -> RemoteHandle {
+        self.remote_commands.handle()
+    }
+
+    /// Ignore detected wakewords, e.g. for privacy. Text queries sent with [Assistant::remote]
+    /// and scheduled items still work.
+    pub fn set_muted(&mut self, muted: bool) {
+        if self.muted != muted {
+            self.muted = muted;
+            self.events.emit(AssistantEvent::MutedChanged(muted));
+        }
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Stop capturing audio, e.g. for a privacy switch, so that wakewords can't be detected.
+    /// Unlike [Assistant::set_muted], the microphone is closed. Scheduled items and remote
+    /// commands still work while [Assistant::listen] waits.
+    pub fn pause_listening(&mut self) -> Result<(), AudioInputPauseError> {
+        if self.listening_paused {
+            return Ok(());
+        }
+        self.audio_input.pause()?;
+        // Wakewords detected right before pausing shouldn't start a query on resume
+        self.wakeword_listener.drain();
+        self.listening_paused = true;
+        self.events.emit(AssistantEvent::ListeningChanged(false));
+        Ok(())
+    }
+
+    /// Pause listening for a while, e.g. for "stop listening for two hours" during a party, with
+    /// do not disturb on meanwhile. Listening resumes by itself at the returned time, or before
+    /// with [Assistant::resume_listening], e.g. from a button or a remote command since the
+    /// wakeword can't be heard.
+    pub fn pause_listening_for(
+        &mut self,
+        duration: Duration,
+    ) -> Result<chrono::DateTime<chrono::Local>, AudioInputPauseError> {
+        let duration = duration.min(Duration::from_secs(10 * 365 * 24 * 60 * 60));
+        let until = schedule::after(self.clock.local_now(), duration)
+            .expect("Clamped to a representable duration");
+        self.pause_listening()?;
+        self.listening_paused_until = Some(until);
+        if !self.is_do_not_disturb() {
+            self.set_do_not_disturb(true);
+            self.paused_do_not_disturb = true;
+        }
+        Ok(until)
+    }
+
+    /// Resume listening, also before the end of [Assistant::pause_listening_for], which turns
+    /// off the do not disturb it turned on.
+    pub fn resume_listening(&mut self) -> Result<(), AudioInputPauseError> {
+        if !self.listening_paused {
+            return Ok(());
+        }
+        self.audio_input.resume()?;
+        self.listening_paused = false;
+        self.listening_paused_until = None;
+        if std::mem::take(&mut self.paused_do_not_disturb) {
+            self.set_do_not_disturb(false);
+        }
+        self.events.emit(AssistantEvent::ListeningChanged(true));
+        Ok(())
+    }
+
+    pub fn is_listening_paused(&self) -> bool {
+        self.listening_paused
+    }
+
+    /// When listening resumes after [Assistant::pause_listening_for].
+    pub fn listening_paused_until(&self) -> Option<chrono::DateTime<chrono::Local>> {
+        self.listening_paused_until
+    }
+
+    /// Stop speaking and capturing audio, and drop the pending wakewords and remote commands.
+    /// [Assistant::listen] returns [AssistantListenError::Stopped] from then on, so the
+    /// assistant can be dropped once it returns.
+    pub fn shutdown(&mut self) {
+        if self.stopped {
+            return;
+        }
+        self.stopped = true;
+        if let Some(farewell) = &self.farewell {
+            _ = tts::stop(self.tts.as_mut());
+            _ = tts_cache::speak(
+                self.tts.as_mut(),
+                self.utterances.as_ref(),
+                &self.normalizer,
+                self.tts_cache.as_ref(),
+                farewell,
+            );
+            _ = self.finish_speaking();
+        }
+        _ = tts::stop(self.tts.as_mut());
+        _ = self.audio_input.pause();
+        self.wakeword_listener.drain();
+        self.audio_cues
+            .iter_mut()
+            .for_each(RunningAudioCueSource::stop);
+        while self.remote_commands.try_next().is_some() {}
+        self.events.emit(AssistantEvent::Stopped);
+    }
+
+    /// Set a value automation rules can check and be triggered by, e.g. `"mode"` to `"night"`.
+    pub fn set_state(&mut self, key: &str, value: &str) {
+        self.change_state(key, value, 0);
+    }
+
+    fn change_state(&mut self, key: &str, value: &str, chain: usize) {
+        if self.automation.set_state(key, value) {
+            self.events.emit(AssistantEvent::StateChanged {
+                key: key.to_string(),
+                value: value.to_string(),
+            });
+            let triggered = self.automation.fire(
+                Fired::StateChanged(key),
+                self.clock.local_now().time(),
+                chain,
+            );
+            self.emit_triggered(triggered);
+        }
+    }
+
+    /// A value set with [Assistant::set_state].
+    pub fn state(&self, key: &str) -> Option<&str> {
+        self.automation.state(key)
+    }
+
+    /// Let guests use the assistant for a while, see [AssistantConfig::set_guest_mode]. Starting it
+    /// while it is on changes when it ends. It ends after at most 10 years.
+    pub fn start_guest_mode(&mut self, duration: Duration) -> Result<(), StorageError> {
+        let now = self.clock.local_now();
+        let duration = duration.min(Duration::from_secs(10 * 365 * 24 * 60 * 60));
+        let until = schedule::after(now, duration).expect("Clamped to a representable duration");
+        let was_on = self.is_guest_mode();
+        self.guest_mode
+            .set_until(Some(until), self.storage.as_deref())?;
+        if !was_on {
+            self.update_allowed_groups();
+            self.events.emit(AssistantEvent::GuestModeChanged(true));
+            if let Some(announcement) = self.guest_mode.config.start_announcement.clone() {
+                _ = tts_cache::speak(
+                    self.tts.as_mut(),
+                    self.utterances.as_ref(),
+                    &self.normalizer,
+                    self.tts_cache.as_ref(),
+                    announcement,
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// End guest mode before its time is up.
+    pub fn end_guest_mode(&mut self) -> Result<(), StorageError> {
+        if !self.is_guest_mode() {
+            return Ok(());
+        }
+        self.guest_mode.set_until(None, self.storage.as_deref())?;
+        self.update_allowed_groups();
+        self.events.emit(AssistantEvent::GuestModeChanged(false));
+        if let Some(announcement) = self.guest_mode.config.end_announcement.clone() {
+            _ = tts_cache::speak(
+                self.tts.as_mut(),
+                self.utterances.as_ref(),
+                &self.normalizer,
+                self.tts_cache.as_ref(),
+                announcement,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn is_guest_mode(&self) -> bool {
+        self.guest_mode.until().is_some()
+    }
+
+    /// When guest mode ends, if it is on.
+    pub fn guest_mode_until(&self) -> Option<chrono::DateTime<chrono::Local>> {
+        self.guest_mode.until()
+    }
+
+    /// Limit the intents to the groups allowed in guest mode and for the current speaker, only
+    /// the ones allowed by both if both are set.
+    fn update_allowed_groups(&mut self) {
+        let guest = self
+            .is_guest_mode()
+            .then(|| self.guest_mode.config.allowed_groups.clone());
+        let speaker = self
+            .speakers
+            .preferences()
+            .and_then(|preferences| preferences.allowed_groups.clone());
+        let allowed = match (guest, speaker) {
+            (Some(guest), Some(speaker)) => Some(
+                guest
+                    .into_iter()
+                    .filter(|group| speaker.contains(group))
+                    .collect::<Vec<_>>(),
+            ),
+            (guest, speaker) => guest.or(speaker),
+        };
+        self.intent_recognizer
+            .get_mut()
+            .set_allowed_groups(allowed.as_deref());
+    }
+
+    /// Identify who said the query and switch to their preferences, which stay in effect until
+    /// the next query. Queries without audio, like remote ones, have no speaker.
+    fn identify_speaker(&mut self, audio: &[i16]) {
+        if !self.speakers.identify(audio) {
+            return;
+        }
+        if let Some(tts) = &mut self.tts {
+            if let Err(e) = self.speakers.apply_voice(tts) {
+                warn!("Failed to switch to the voice of the speaker: {}", e);
+            }
+        }
+        self.update_allowed_groups();
+    }
+
+    /// The speaker profile of the last query, if the speaker identifier recognized them, see
+    /// [AssistantConfig::set_speaker_identifier].
+    pub fn speaker(&self) -> Option<&str> {
+        self.speakers.current()
+    }
+
+    /// The preferences of the speaker of the last query.
+    pub fn speaker_preferences(&self) -> Option<&SpeakerPreferences> {
+        self.speakers.preferences()
+    }
+
+    /// Change the preferences of a speaker profile, e.g. after enrolling a new voice. They are
+    /// used from the next query of the speaker.
+    pub fn set_speaker_preferences(&mut self, speaker: &str, preferences: SpeakerPreferences) {
+        self.speakers.set_preferences(speaker, preferences);
+    }
+
+    /// Do not disturb only changes which automation rules run, see
+    /// [automation::Condition::DoNotDisturb].
+    pub fn set_do_not_disturb(&mut self, on: bool) {
+        if self.automation.set_do_not_disturb(on) {
+            self.events.emit(AssistantEvent::DoNotDisturbChanged(on));
+        }
+    }
+
+    pub fn is_do_not_disturb(&self) -> bool {
+        self.automation.do_not_disturb()
+    }
+
+    /// The timers, alarms and reminders, which are announced while the assistant listens.
+    pub fn schedule(&self) -> &Schedule {
+        &self.schedule
+    }
+
+    pub fn schedule_mut(&mut self) -> &mut Schedule {
+        &mut self.schedule
     }
 
     pub fn finish_speaking(&self) -> Result<(), TtsError> {
-        while self.tts.is_speaking()? {
+        while tts::is_speaking(self.tts.as_ref())? {
             std::thread::sleep(std::time::Duration::from_millis(100));
         }
         Ok(())
     }
 }
 
-pub struct AssistantQuery<'a, T> {
+/// Recognition hints for [Assistant::ask], for when the expected answer is known.
+#[derive(Clone, Debug, Default)]
+pub struct AskOptions {
+    /// Endpointing to use instead of the one of the settings profile.
+    pub endpoint: Option<EndpointConfig>,
+    /// Time to start speaking, instead of the one of the settings profile.
+    pub timeout: Option<Duration>,
+    /// Phrases the answer is limited to, see [stt::SessionOptions::grammar].
+    pub grammar: Option<Vec<String>>,
+    /// Alternative transcriptions to decode, keeping the one whose intent scores highest. See
+    /// [AssistantConfig::set_query_alternatives].
+    pub alternatives: Option<u16>,
+}
+
+impl AskOptions {
+    /// A short answer, ending soon after the user stops speaking.
+    pub fn short_answer() -> Self {
+        Self {
+            endpoint: Some(EndpointConfig {
+                max_silence: Duration::from_millis(500),
+                max_utterance: Duration::from_secs(5),
+                ..EndpointConfig::default()
+            }),
+            ..Self::default()
+        }
+    }
+
+    /// A yes or no answer.
+    pub fn yes_no() -> Self {
+        Self {
+            grammar: Some(vec!["yes".to_string(), "no".to_string()]),
+            ..Self::short_answer()
+        }
+    }
+
+    /// A long answer like a note, allowing pauses to think.
+    pub fn dictation() -> Self {
+        Self {
+            endpoint: Some(EndpointConfig {
+                max_silence: Duration::from_secs(3),
+                max_utterance: Duration::from_secs(120),
+                ..EndpointConfig::default()
+            }),
+            ..Self::default()
+        }
+    }
+}
+
+pub struct AssistantQuery<'a, T: ?Sized> {
+    /// The detected wakeword, empty for queries from [Assistant::query_once].
     pub wakeword: String,
     pub intent: Option<&'a T>,
+    /// The sentence recognized by speech-to-text, if the wakeword listens for one.
+    pub text: Option<String>,
+    /// Similarity score of the matched intent, see [IntentRecognizer::recognize_with_score].
+    pub score: Option<f32>,
+    /// Slot values extracted from the text, see [IntentsConfig::add_intent_with_slots].
+    pub slots: SlotValues,
+    /// The room the query refers to: the [ROOM_SLOT] value if one was said, otherwise the
+    /// location of the assistant.
+    pub location: Option<String>,
+    /// The intent context of the wakeword, which the intent was recognized in, see
+    /// [AssistantConfig::set_wakeword_context].
+    pub context: Option<String>,
+    /// Where the wakeword was detected in the stream of the microphone, e.g. to align the query
+    /// with a recording of it. See [wakeword::WakewordDetection::timestamp].
+    pub wakeword_at: Option<AudioTimestamp>,
+    /// Where the audio given to speech-to-text starts in the stream of the microphone.
+    pub speech_at: Option<AudioTimestamp>,
+}
+
+/// A query that matched an intent of the application, with the intent as an index in the
+/// recognizer so that it doesn't borrow the assistant, see [Assistant::resolve]. Without intents,
+/// see [AssistantConfigBuilder::set_embedding_model], it only has the text.
+struct MatchedQuery {
+    wakeword: String,
+    intent: Option<usize>,
+    text: String,
+    score: Option<f32>,
+    slots: SlotValues,
+    location: Option<String>,
+    context: Option<String>,
+    wakeword_at: Option<AudioTimestamp>,
+    speech_at: Option<AudioTimestamp>,
 }
@@ -0,0 +1,179 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+/// How many of the latest samples of a metric the percentiles are computed from.
+const RECENT_SAMPLES: usize = 256;
+
+/// What is timed by [Metrics].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    /// From detecting the wakeword to listening for the query, including the wakeword response.
+    WakewordToListen,
+    /// Recognizing a sentence, from listening to the final transcript, including the time the
+    /// user speaks.
+    SpeechRecognition,
+    /// Embedding a text for intent recognition.
+    Embedding,
+    /// From detecting the wakeword to the query being handled by a skill or returned.
+    Query,
+    /// From giving a text to the TTS backend to it starting to say it. Only recorded with
+    /// backends that have utterance callbacks.
+    TtsFirstAudio,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 5] = [
+        Metric::WakewordToListen,
+        Metric::SpeechRecognition,
+        Metric::Embedding,
+        Metric::Query,
+        Metric::TtsFirstAudio,
+    ];
+
+    /// The name of the metric in snake case, e.g. for exporters.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::WakewordToListen => "wakeword_to_listen",
+            Metric::SpeechRecognition => "speech_recognition",
+            Metric::Embedding => "embedding",
+            Metric::Query => "query",
+            Metric::TtsFirstAudio => "tts_first_audio",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// The latencies of the assistant, to find where the time of a query goes on slow devices. Clones
+/// share the same samples, see [crate::Assistant::metrics_handle].
+#[derive(Clone, Default)]
+pub struct Metrics {
+    state: Arc<Mutex<[Samples; 5]>>,
+}
+
+#[derive(Default)]
+struct Samples {
+    count: u64,
+    total: Duration,
+    min: Option<Duration>,
+    max: Duration,
+    recent: VecDeque<Duration>,
+}
+
+/// The latencies recorded so far, see [Metrics::snapshot].
+#[derive(Clone, Debug, PartialEq)]
+pub struct MetricsSnapshot {
+    pub wakeword_to_listen: LatencyStats,
+    pub speech_recognition: LatencyStats,
+    pub embedding: LatencyStats,
+    pub query: LatencyStats,
+    pub tts_first_audio: LatencyStats,
+}
+
+/// The samples of a [Metric]. All durations are zero while there are none.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LatencyStats {
+    pub count: u64,
+    pub total: Duration,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    /// The median of the latest samples.
+    pub p50: Duration,
+    /// The 95th percentile of the latest samples.
+    pub p95: Duration,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, metric: Metric, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let samples = &mut state[metric.index()];
+        samples.count += 1;
+        samples.total += duration;
+        samples.min = Some(samples.min.map_or(duration, |min| min.min(duration)));
+        samples.max = samples.max.max(duration);
+        if samples.recent.len() == RECENT_SAMPLES {
+            samples.recent.pop_front();
+        }
+        samples.recent.push_back(duration);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let state = self.state.lock().unwrap();
+        let stats = |metric: Metric| state[metric.index()].stats();
+        MetricsSnapshot {
+            wakeword_to_listen: stats(Metric::WakewordToListen),
+            speech_recognition: stats(Metric::SpeechRecognition),
+            embedding: stats(Metric::Embedding),
+            query: stats(Metric::Query),
+            tts_first_audio: stats(Metric::TtsFirstAudio),
+        }
+    }
+
+    /// Forget all samples, e.g. before measuring a change.
+    pub fn reset(&self) {
+        *self.state.lock().unwrap() = Default::default();
+    }
+}
+
+impl Samples {
+    fn stats(&self) -> LatencyStats {
+        if self.count == 0 {
+            return LatencyStats::default();
+        }
+        let mut recent: Vec<Duration> = self.recent.iter().copied().collect();
+        recent.sort();
+        let percentile = |p: f64| recent[((recent.len() - 1) as f64 * p).round() as usize];
+        LatencyStats {
+            count: self.count,
+            total: self.total,
+            min: self.min.unwrap_or_default(),
+            max: self.max,
+            mean: self.total.div_f64(self.count as f64),
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    pub fn get(&self, metric: Metric) -> &LatencyStats {
+        match metric {
+            Metric::WakewordToListen => &self.wakeword_to_listen,
+            Metric::SpeechRecognition => &self.speech_recognition,
+            Metric::Embedding => &self.embedding,
+            Metric::Query => &self.query,
+            Metric::TtsFirstAudio => &self.tts_first_audio,
+        }
+    }
+
+    /// The metrics in the Prometheus text format, as summaries named like
+    /// `assistant_query_seconds`.
+    #[cfg(feature = "prometheus")]
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::new();
+        for metric in Metric::ALL {
+            let stats = self.get(metric);
+            let name = format!("assistant_{}_seconds", metric.name());
+            text += &format!("# TYPE {} summary\n", name);
+            text += &format!("{}{{quantile=\"0.5\"}} {}\n", name, stats.p50.as_secs_f64());
+            text += &format!(
+                "{}{{quantile=\"0.95\"}} {}\n",
+                name,
+                stats.p95.as_secs_f64()
+            );
+            text += &format!("{}_sum {}\n", name, stats.total.as_secs_f64());
+            text += &format!("{}_count {}\n", name, stats.count);
+        }
+        text
+    }
+}
@@ -0,0 +1,51 @@
+use crate::{skills::SkillContext, AssistantQuery};
+
+/// A Middleware runs around the dispatch of every recognized intent, for concerns shared by all
+/// of them like logging, permissions or rate limiting. Middlewares are added with
+/// [crate::AssistantConfig::add_middleware] and run in the order they were added, after
+/// [ConfirmCommands].
+pub trait Middleware<T> {
+    /// Called before the query is handled by its skill or returned by [crate::Assistant::listen].
+    /// Returning [Flow::Stop] drops the query, e.g. after saying why it isn't allowed.
+    fn before(&mut self, _ctx: &mut SkillContext, _query: &DispatchedQuery<'_, T>) -> Flow {
+        Flow::Continue
+    }
+
+    /// Called after a skill handled the query, or before a query of the application is returned.
+    /// Runs in the reverse order, and only for the middlewares whose [Middleware::before] ran.
+    fn after(&mut self, _ctx: &mut SkillContext, _query: &DispatchedQuery<'_, T>) {}
+}
+
+/// Whether a query goes on after [Middleware::before].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Stop,
+}
+
+/// The intent of a query going through the middlewares.
+#[derive(Debug, PartialEq)]
+pub enum DispatchedIntent<'a, T> {
+    /// An intent of the application, returned by [crate::Assistant::listen].
+    App(&'a T),
+    /// An intent of a [crate::skills::Skill], by the name of its
+    /// [crate::skills::IntentSpec].
+    Skill(&'a str),
+}
+
+pub type DispatchedQuery<'a, T> = AssistantQuery<'a, DispatchedIntent<'a, T>>;
+
+/// Repeats the recognized sentence back to the user while
+/// [crate::profile::SettingsProfile::confirm_commands] is on. Always the first middleware.
+pub struct ConfirmCommands;
+
+impl<T> Middleware<T> for ConfirmCommands {
+    fn before(&mut self, ctx: &mut SkillContext, query: &DispatchedQuery<'_, T>) -> Flow {
+        if let (true, Some(text)) = (ctx.profile().confirm_commands, &query.text) {
+            // Failing to confirm shouldn't prevent the command from running
+            _ = ctx.speak(format!("You said: {}.", text));
+            _ = ctx.finish_speaking();
+        }
+        Flow::Continue
+    }
+}
@@ -0,0 +1,469 @@
+use std::collections::HashMap;
+
+/// Normalizer rewrites text before it is given to the TTS backend, so that numbers, units,
+/// abbreviations, URLs and email addresses are read out the way a person would say them.
+/// Custom pronunciations can be added to the lexicon with [Normalizer::add_pronunciation].
+#[derive(Clone, Debug)]
+pub struct Normalizer {
+    words: Words,
+    lexicon: HashMap<String, String>,
+    units: HashMap<String, (String, String)>,
+}
+
+/// The words used to read out numbers and symbols in a language.
+#[derive(Clone, Debug)]
+struct Words {
+    ones: [&'static str; 20],
+    tens: [&'static str; 10],
+    hundred: &'static str,
+    scales: [&'static str; 4],
+    minus: &'static str,
+    point: &'static str,
+    percent: &'static str,
+    at: &'static str,
+    dot: &'static str,
+    slash: &'static str,
+    contextual_abbreviations: &'static [ContextualAbbreviation],
+}
+
+/// An abbreviation read depending on the word after it, e.g. "No. 5" but "the answer is no.".
+#[derive(Clone, Copy, Debug)]
+struct ContextualAbbreviation {
+    abbreviation: &'static str,
+    before: Before,
+    spoken: &'static str,
+    /// How it is read before other words, or at the end of a sentence. As written without.
+    otherwise: Option<&'static str>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Before {
+    Number,
+    /// A word starting with a capital letter
+    Name,
+}
+
+const ENGLISH: Words = Words {
+    ones: [
+        "zero",
+        "one",
+        "two",
+        "three",
+        "four",
+        "five",
+        "six",
+        "seven",
+        "eight",
+        "nine",
+        "ten",
+        "eleven",
+        "twelve",
+        "thirteen",
+        "fourteen",
+        "fifteen",
+        "sixteen",
+        "seventeen",
+        "eighteen",
+        "nineteen",
+    ],
+    tens: [
+        "", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+    ],
+    hundred: "hundred",
+    scales: ["", "thousand", "million", "billion"],
+    minus: "minus",
+    point: "point",
+    percent: "percent",
+    at: "at",
+    dot: "dot",
+    slash: "slash",
+    contextual_abbreviations: &[
+        ContextualAbbreviation {
+            abbreviation: "no.",
+            before: Before::Number,
+            spoken: "number",
+            otherwise: None,
+        },
+        ContextualAbbreviation {
+            abbreviation: "st.",
+            before: Before::Name,
+            spoken: "saint",
+            otherwise: Some("street"),
+        },
+    ],
+};
+
+const ENGLISH_ABBREVIATIONS: &[(&str, &str)] = &[
+    ("dr.", "doctor"),
+    ("mr.", "mister"),
+    ("mrs.", "missus"),
+    ("ms.", "miz"),
+    ("ave.", "avenue"),
+    ("e.g.", "for example"),
+    ("i.e.", "that is"),
+    ("etc.", "et cetera"),
+    ("vs.", "versus"),
+    ("approx.", "approximately"),
+];
+
+const ENGLISH_UNITS: &[(&str, &str, &str)] = &[
+    ("km", "kilometer", "kilometers"),
+    ("m", "meter", "meters"),
+    ("cm", "centimeter", "centimeters"),
+    ("mm", "millimeter", "millimeters"),
+    ("kg", "kilogram", "kilograms"),
+    ("g", "gram", "grams"),
+    ("l", "liter", "liters"),
+    ("ml", "milliliter", "milliliters"),
+    ("km/h", "kilometer per hour", "kilometers per hour"),
+    ("mph", "mile per hour", "miles per hour"),
+    ("h", "hour", "hours"),
+    ("min", "minute", "minutes"),
+    ("s", "second", "seconds"),
+    ("ms", "millisecond", "milliseconds"),
+    ("°c", "degree Celsius", "degrees Celsius"),
+    ("°f", "degree Fahrenheit", "degrees Fahrenheit"),
+    ("°", "degree", "degrees"),
+    ("kb", "kilobyte", "kilobytes"),
+    ("mb", "megabyte", "megabytes"),
+    ("gb", "gigabyte", "gigabytes"),
+];
+
+impl Normalizer {
+    /// Normalizer for English text.
+    pub fn english() -> Self {
+        Self {
+            words: ENGLISH,
+            lexicon: ENGLISH_ABBREVIATIONS
+                .iter()
+                .map(|(word, spoken)| (word.to_string(), spoken.to_string()))
+                .collect(),
+            units: ENGLISH_UNITS
+                .iter()
+                .map(|(unit, singular, plural)| {
+                    (unit.to_string(), (singular.to_string(), plural.to_string()))
+                })
+                .collect(),
+        }
+    }
+
+    /// Normalizer for a locale such as "en" or "en-US". Returns `None` if the language isn't
+    /// supported.
+    pub fn for_locale(locale: &str) -> Option<Self> {
+        match locale.split(['-', '_']).next()? {
+            "en" => Some(Self::english()),
+            _ => None,
+        }
+    }
+
+    /// Read `word` as `spoken`, e.g. a name the TTS backend gets wrong. Matching ignores case.
+    pub fn add_pronunciation(&mut self, word: &str, spoken: impl Into<String>) {
+        self.lexicon.insert(word.to_lowercase(), spoken.into());
+    }
+
+    /// Read `unit` after a number as `singular` or `plural`, e.g. `("kWh", "kilowatt hour",
+    /// "kilowatt hours")`. Matching ignores case.
+    pub fn add_unit(&mut self, unit: &str, singular: impl Into<String>, plural: impl Into<String>) {
+        self.units
+            .insert(unit.to_lowercase(), (singular.into(), plural.into()));
+    }
+
+    pub fn normalize(&self, text: &str) -> String {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let mut output = Vec::with_capacity(tokens.len());
+        let mut i = 0;
+        while i < tokens.len() {
+            let (word, trailing) = split_trailing_punctuation(tokens[i]);
+            // A unit in the next token belongs to this number, as in "3.14 km"
+            let next_unit = tokens.get(i + 1).and_then(|next| {
+                let (unit, trailing) = split_trailing_punctuation(next);
+                let (unit, trailing) = match unit.strip_suffix('.') {
+                    Some(unit) => (unit, &next[unit.len()..]),
+                    None => (unit, trailing),
+                };
+                self.units.get(&unit.to_lowercase()).map(|u| (u, trailing))
+            });
+
+            if let (Some(number), Some((unit, unit_trailing))) = (parse_number(word), next_unit) {
+                output.push(format!(
+                    "{} {}{}",
+                    self.number(word),
+                    if number == 1. { &unit.0 } else { &unit.1 },
+                    unit_trailing
+                ));
+                i += 2;
+                continue;
+            }
+
+            if let Some(spoken) = self.contextual_abbreviation(word, tokens.get(i + 1)) {
+                output.push(format!("{}{}", spoken, trailing));
+                i += 1;
+                continue;
+            }
+
+            output.push(format!("{}{}", self.word(word), trailing));
+            i += 1;
+        }
+        output.join(" ")
+    }
+
+    /// How the word is read if it is one of the [ContextualAbbreviation]s, unless the lexicon
+    /// has it.
+    fn contextual_abbreviation(&self, word: &str, next: Option<&&str>) -> Option<&'static str> {
+        let word = word.to_lowercase();
+        if self.lexicon.contains_key(&word) {
+            return None;
+        }
+        let abbreviation = self
+            .words
+            .contextual_abbreviations
+            .iter()
+            .find(|abbreviation| abbreviation.abbreviation == word)?;
+        let applies = next.is_some_and(|next| match abbreviation.before {
+            Before::Number => parse_number(split_trailing_punctuation(next).0).is_some(),
+            Before::Name => next.starts_with(char::is_uppercase),
+        });
+        if applies {
+            Some(abbreviation.spoken)
+        } else {
+            abbreviation.otherwise
+        }
+    }
+
+    fn word(&self, word: &str) -> String {
+        if let Some(spoken) = self.lexicon.get(&word.to_lowercase()) {
+            return spoken.clone();
+        }
+        if parse_number(word).is_some() {
+            return self.number(word);
+        }
+        if let Some(number) = word.strip_suffix('%').filter(|n| parse_number(n).is_some()) {
+            return format!("{} {}", self.number(number), self.words.percent);
+        }
+        if let Some(ordinal) = self.ordinal(word) {
+            return ordinal;
+        }
+        // A number directly followed by a unit, as in "5km"
+        if let Some(split) = word.find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ',')) {
+            let (number, unit) = word.split_at(split);
+            if let (Some(value), Some(unit)) =
+                (parse_number(number), self.units.get(&unit.to_lowercase()))
+            {
+                let unit = if value == 1. { &unit.0 } else { &unit.1 };
+                return format!("{} {}", self.number(number), unit);
+            }
+        }
+        if let Some(rest) = word.strip_suffix('.').filter(|rest| !rest.is_empty()) {
+            // The end of a sentence, as in "It's 5km."
+            let spoken = self.word(rest);
+            if spoken != rest {
+                return spoken + ".";
+            }
+        }
+        if word.contains('@') || word.contains("://") || word.starts_with("www.") {
+            return self.address(word);
+        }
+        word.to_string()
+    }
+
+    /// Read out a number as written, e.g. "-1,250.75".
+    fn number(&self, text: &str) -> String {
+        let (negative, text) = match text.strip_prefix('-') {
+            Some(text) => (true, text),
+            None => (false, text),
+        };
+        let digits: String = text.chars().filter(|c| *c != ',').collect();
+        let (integer, fraction) = match digits.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (digits.as_str(), None),
+        };
+
+        let mut words = Vec::new();
+        if negative {
+            words.push(self.words.minus.to_string());
+        }
+        match integer.parse::<u64>() {
+            Ok(value) if value < 1000u64.pow(self.words.scales.len() as u32) => {
+                words.push(self.integer(value))
+            }
+            // Too long to read as a number, read the digits instead
+            _ => words.extend(self.digits(integer)),
+        }
+        if let Some(fraction) = fraction.filter(|f| !f.is_empty()) {
+            words.push(self.words.point.to_string());
+            words.extend(self.digits(fraction));
+        }
+        words.join(" ")
+    }
+
+    fn digits(&self, digits: &str) -> Vec<String> {
+        digits
+            .chars()
+            .filter_map(|c| c.to_digit(10))
+            .map(|d| self.words.ones[d as usize].to_string())
+            .collect()
+    }
+
+    fn integer(&self, value: u64) -> String {
+        if value == 0 {
+            return self.words.ones[0].to_string();
+        }
+        let mut parts = Vec::new();
+        let mut remaining = value;
+        for scale in self.words.scales {
+            let group = remaining % 1000;
+            remaining /= 1000;
+            if group > 0 {
+                let mut group = self.below_thousand(group);
+                if !scale.is_empty() {
+                    group = format!("{} {}", group, scale);
+                }
+                parts.push(group);
+            }
+        }
+        parts.reverse();
+        parts.join(" ")
+    }
+
+    fn below_thousand(&self, value: u64) -> String {
+        let mut words = Vec::new();
+        if value >= 100 {
+            words.push(self.words.ones[(value / 100) as usize].to_string());
+            words.push(self.words.hundred.to_string());
+        }
+        let rest = value % 100;
+        if rest >= 20 {
+            let tens = self.words.tens[(rest / 10) as usize];
+            match rest % 10 {
+                0 => words.push(tens.to_string()),
+                ones => words.push(format!("{} {}", tens, self.words.ones[ones as usize])),
+            }
+        } else if rest > 0 || words.is_empty() {
+            words.push(self.words.ones[rest as usize].to_string());
+        }
+        words.join(" ")
+    }
+
+    /// English ordinals like "1st" or "22nd".
+    fn ordinal(&self, word: &str) -> Option<String> {
+        let lower = word.to_lowercase();
+        let number = ["st", "nd", "rd", "th"]
+            .iter()
+            .find_map(|suffix| lower.strip_suffix(suffix))?;
+        let value: u64 = number.parse().ok()?;
+        let cardinal = self.integer(value);
+        let (init, last) = match cardinal.rsplit_once(' ') {
+            Some((init, last)) => (format!("{} ", init), last),
+            None => (String::new(), cardinal.as_str()),
+        };
+        let last = match last {
+            "one" => "first".to_string(),
+            "two" => "second".to_string(),
+            "three" => "third".to_string(),
+            "five" => "fifth".to_string(),
+            "eight" => "eighth".to_string(),
+            "nine" => "ninth".to_string(),
+            "twelve" => "twelfth".to_string(),
+            last if last.ends_with('y') => format!("{}ieth", last.trim_end_matches('y')),
+            last => format!("{}th", last),
+        };
+        Some(init + &last)
+    }
+
+    /// Read out a URL or an email address, e.g. "example dot com slash help".
+    fn address(&self, address: &str) -> String {
+        let address = address
+            .split_once("://")
+            .map_or(address, |(_, rest)| rest)
+            .trim_start_matches("www.")
+            .trim_end_matches('/');
+        let mut words = Vec::new();
+        let mut current = String::new();
+        for c in address.chars() {
+            let symbol = match c {
+                '@' => self.words.at,
+                '.' => self.words.dot,
+                '/' => self.words.slash,
+                c => {
+                    current.push(c);
+                    continue;
+                }
+            };
+            if !current.is_empty() {
+                words.push(std::mem::take(&mut current));
+            }
+            words.push(symbol.to_string());
+        }
+        if !current.is_empty() {
+            words.push(current);
+        }
+        words.join(" ")
+    }
+}
+
+impl Default for Normalizer {
+    fn default() -> Self {
+        Self::english()
+    }
+}
+
+/// Split off punctuation that ends a sentence or clause, so "5," is read as a number. A final
+/// "." is kept when it could be part of an abbreviation and removed later if it isn't.
+fn split_trailing_punctuation(word: &str) -> (&str, &str) {
+    let trimmed = word.trim_end_matches([',', ';', ':', '!', '?', ')', '"']);
+    let trimmed = match trimmed.strip_suffix('.') {
+        Some(rest) if parse_number(rest).is_some() || rest.ends_with('%') => rest,
+        _ => trimmed,
+    };
+    word.split_at(trimmed.len())
+}
+
+/// Parse a written number like "42", "-3.5" or "1,000".
+fn parse_number(text: &str) -> Option<f64> {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    if !digits.starts_with(|c: char| c.is_ascii_digit())
+        || !digits
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '.' || c == ',')
+    {
+        return None;
+    }
+    text.replace(',', "").parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_no_as_number_only_before_one() {
+        let normalizer = Normalizer::english();
+        assert_eq!(
+            normalizer.normalize("The answer is no."),
+            "The answer is no."
+        );
+        assert_eq!(normalizer.normalize("no. I won't"), "no. I won't");
+        assert_eq!(normalizer.normalize("Track No. 5"), "Track number five");
+    }
+
+    #[test]
+    fn reads_st_as_saint_before_a_name() {
+        let normalizer = Normalizer::english();
+        assert_eq!(normalizer.normalize("St. Patrick"), "saint Patrick");
+        assert_eq!(
+            normalizer.normalize("It's on Main St."),
+            "It's on Main street"
+        );
+        assert_eq!(
+            normalizer.normalize("Main St. and 5th"),
+            "Main street and fifth"
+        );
+    }
+
+    #[test]
+    fn keeps_the_pronunciations_of_the_lexicon() {
+        let mut normalizer = Normalizer::english();
+        normalizer.add_pronunciation("St.", "state");
+        assert_eq!(normalizer.normalize("St. Patrick"), "state Patrick");
+    }
+}
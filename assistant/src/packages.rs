@@ -0,0 +1,519 @@
+use chrono::{DateTime, Local, NaiveDate};
+use serde::Deserialize;
+use std::sync::Arc;
+use thiserror::Error;
+
+use crate::{
+    clock::describe_day,
+    http::{HttpClient, HttpError},
+    permissions::Capability,
+    skills::{IntentSpec, Skill, SkillContext},
+    storage::{Storage, StorageError},
+    AssistantQuery,
+};
+
+/// The [Storage] namespace of the packages added with [add_package].
+const PACKAGES_NAMESPACE: &str = "packages";
+/// The [Storage] namespace of what was last announced of each package.
+const ANNOUNCED_NAMESPACE: &str = "package announcements";
+
+#[derive(Error, Debug)]
+pub enum TrackingError {
+    #[error("Tracking request failed")]
+    Request(#[from] HttpError),
+    #[error("Failed to read the tracking response")]
+    Response(#[from] std::io::Error),
+}
+
+/// A tracked package.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Package {
+    pub number: String,
+    /// The carrier, like "ups" or "dhl", if the tracking service can't detect it.
+    pub carrier: Option<String>,
+    /// What is in it, like "headphones", to say instead of the number.
+    pub name: Option<String>,
+}
+
+impl Package {
+    /// How the package is called at the start of responses, e.g. "The package with your
+    /// headphones" or "The package ending in 4784".
+    fn description(&self) -> String {
+        match &self.name {
+            Some(name) => format!("The package with your {}", name),
+            None => {
+                let start = self.number.len().saturating_sub(4);
+                let end = self.number.get(start..).unwrap_or(&self.number);
+                format!("The package ending in {}", end)
+            }
+        }
+    }
+
+    fn encode(&self) -> String {
+        format!(
+            "{}\t{}",
+            self.carrier.as_deref().unwrap_or_default(),
+            self.name.as_deref().unwrap_or_default()
+        )
+    }
+
+    fn decode(number: &str, value: &str) -> Option<Self> {
+        let (carrier, name) = value.split_once('\t')?;
+        let field = |field: &str| (!field.is_empty()).then(|| field.to_string());
+        Some(Self {
+            number: number.to_string(),
+            carrier: field(carrier),
+            name: field(name),
+        })
+    }
+}
+
+/// Track a package from now on, e.g. from the HTTP server of the assistant, in addition to the
+/// ones given to the [PackageSkill] and [DeliveryAnnouncer].
+pub fn add_package(storage: &dyn Storage, package: &Package) -> Result<(), StorageError> {
+    storage.set(
+        PACKAGES_NAMESPACE,
+        &package.number,
+        package.encode().as_bytes(),
+    )
+}
+
+/// Stop tracking a package added with [add_package].
+pub fn remove_package(storage: &dyn Storage, number: &str) -> Result<(), StorageError> {
+    storage.remove(PACKAGES_NAMESPACE, number)?;
+    storage.remove(ANNOUNCED_NAMESPACE, number)
+}
+
+/// The packages added with [add_package].
+pub fn stored_packages(storage: &dyn Storage) -> Result<Vec<Package>, StorageError> {
+    let mut packages = Vec::new();
+    for number in storage.keys(PACKAGES_NAMESPACE)? {
+        let value = storage
+            .get(PACKAGES_NAMESPACE, &number)?
+            .unwrap_or_default();
+        match Package::decode(&number, &String::from_utf8_lossy(&value)) {
+            Some(package) => packages.push(package),
+            None => warn!("Ignoring the invalid stored package {}", number),
+        }
+    }
+    Ok(packages)
+}
+
+/// The packages given with the ones in the storage, without duplicates.
+fn all_packages(packages: &[Package], storage: Option<&dyn Storage>) -> Vec<Package> {
+    let mut all = packages.to_vec();
+    let stored = storage.map_or(Ok(Vec::new()), stored_packages);
+    for package in stored.unwrap_or_else(|e| {
+        warn!("Failed to load the stored packages: {}", e);
+        Vec::new()
+    }) {
+        if !all.iter().any(|known| known.number == package.number) {
+            all.push(package);
+        }
+    }
+    all
+}
+
+/// Where a package is, in the categories of most tracking services.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryState {
+    /// The carrier doesn't have it yet.
+    Pending,
+    InTransit,
+    OutForDelivery,
+    /// It can be picked up at a parcel shop or locker.
+    AvailableForPickup,
+    Delivered,
+    /// Delivery failed or there is a problem with the package.
+    Exception,
+    Unknown,
+}
+
+impl DeliveryState {
+    fn description(&self) -> &'static str {
+        match self {
+            DeliveryState::Pending => "waiting for the carrier",
+            DeliveryState::InTransit => "on its way",
+            DeliveryState::OutForDelivery => "out for delivery",
+            DeliveryState::AvailableForPickup => "ready for pickup",
+            DeliveryState::Delivered => "delivered",
+            DeliveryState::Exception => "held up",
+            DeliveryState::Unknown => "somewhere I can't tell",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tracking {
+    pub state: DeliveryState,
+    /// When it is expected, while it isn't delivered.
+    pub expected: Option<NaiveDate>,
+    pub delivered: Option<NaiveDate>,
+    /// The last update of the carrier, like "Departed facility, Leipzig".
+    pub last_update: Option<String>,
+}
+
+/// A package tracking service. [AfterShip] is the one included, other services can implement
+/// this trait and be given to [PackageSkill::new].
+pub trait TrackingProvider {
+    fn track(&self, package: &Package) -> Result<Tracking, TrackingError>;
+}
+
+/// The tracking API of AfterShip, which detects the carrier of most tracking numbers. Packages
+/// it doesn't know yet are added to the account, and are pending until it has tracked them.
+pub struct AfterShip {
+    api_key: String,
+    client: HttpClient,
+}
+
+const AFTERSHIP_URL: &str = "https://api.aftership.com/v4/trackings";
+
+impl AfterShip {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            client: HttpClient::default(),
+        }
+    }
+
+    /// Make the requests with a shared client, see [crate::AssistantConfig::http_client].
+    pub fn set_client(&mut self, client: HttpClient) {
+        self.client = client;
+    }
+
+    fn create(&self, package: &Package) -> Result<AfterShipTracking, TrackingError> {
+        let mut tracking = serde_json::Map::new();
+        tracking.insert("tracking_number".into(), package.number.clone().into());
+        if let Some(carrier) = &package.carrier {
+            tracking.insert("slug".into(), carrier.clone().into());
+        }
+        if let Some(name) = &package.name {
+            tracking.insert("title".into(), name.clone().into());
+        }
+        let body = serde_json::json!({ "tracking": tracking });
+        let response = self
+            .client
+            .post(AFTERSHIP_URL)
+            .set("aftership-api-key", &self.api_key)
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string())?
+            .into_string()?;
+        let response: AfterShipResponse<AfterShipCreated> =
+            serde_json::from_str(&response).map_err(std::io::Error::from)?;
+        Ok(response.data.tracking)
+    }
+}
+
+#[derive(Deserialize)]
+struct AfterShipResponse<T> {
+    data: T,
+}
+
+#[derive(Deserialize)]
+struct AfterShipList {
+    trackings: Vec<AfterShipTracking>,
+}
+
+#[derive(Deserialize)]
+struct AfterShipCreated {
+    tracking: AfterShipTracking,
+}
+
+#[derive(Deserialize)]
+struct AfterShipTracking {
+    slug: Option<String>,
+    tag: Option<String>,
+    expected_delivery: Option<String>,
+    shipment_delivery_date: Option<String>,
+    #[serde(default)]
+    checkpoints: Vec<AfterShipCheckpoint>,
+}
+
+#[derive(Deserialize)]
+struct AfterShipCheckpoint {
+    message: Option<String>,
+    location: Option<String>,
+}
+
+/// The date of a date or a date and time of AfterShip, which are in the time zone of the
+/// carrier.
+fn parse_date(date: &Option<String>) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date.as_deref()?.get(..10)?, "%Y-%m-%d").ok()
+}
+
+impl AfterShipTracking {
+    fn to_tracking(&self) -> Tracking {
+        let state = match self.tag.as_deref() {
+            Some("Pending" | "InfoReceived") => DeliveryState::Pending,
+            Some("InTransit") => DeliveryState::InTransit,
+            Some("OutForDelivery") => DeliveryState::OutForDelivery,
+            Some("AvailableForPickup") => DeliveryState::AvailableForPickup,
+            Some("Delivered") => DeliveryState::Delivered,
+            Some("AttemptFail" | "Exception" | "Expired") => DeliveryState::Exception,
+            _ => DeliveryState::Unknown,
+        };
+        // The last checkpoint is the latest
+        let last_update = self.checkpoints.last().and_then(|checkpoint| {
+            let message = checkpoint.message.as_deref()?.trim_end_matches('.');
+            Some(match checkpoint.location.as_deref() {
+                Some(location) if !location.is_empty() => format!("{}, {}", message, location),
+                _ => message.to_string(),
+            })
+        });
+        Tracking {
+            state,
+            expected: parse_date(&self.expected_delivery),
+            delivered: parse_date(&self.shipment_delivery_date),
+            last_update,
+        }
+    }
+}
+
+impl TrackingProvider for AfterShip {
+    fn track(&self, package: &Package) -> Result<Tracking, TrackingError> {
+        let response = self
+            .client
+            .get(AFTERSHIP_URL)
+            .query("tracking_numbers", &package.number)
+            .set("aftership-api-key", &self.api_key)
+            .call()?
+            .into_string()?;
+        let response: AfterShipResponse<AfterShipList> =
+            serde_json::from_str(&response).map_err(std::io::Error::from)?;
+        let tracking = response.data.trackings.into_iter().find(|tracking| {
+            match (&package.carrier, &tracking.slug) {
+                (Some(carrier), Some(slug)) => carrier.eq_ignore_ascii_case(slug),
+                _ => true,
+            }
+        });
+        match tracking {
+            Some(tracking) => Ok(tracking.to_tracking()),
+            None => Ok(self.create(package)?.to_tracking()),
+        }
+    }
+}
+
+/// Delivered packages are mentioned for this many days.
+const RECENTLY_DELIVERED_DAYS: i64 = 3;
+
+fn describe_tracking(package: &Package, tracking: &Tracking, today: NaiveDate) -> Option<String> {
+    let name = package.description();
+    Some(
+        match (tracking.state, tracking.delivered, tracking.expected) {
+            (DeliveryState::Delivered, Some(delivered), _) => {
+                if (today - delivered).num_days() > RECENTLY_DELIVERED_DAYS {
+                    return None;
+                }
+                format!("{} was delivered {}.", name, describe_day(delivered, today))
+            }
+            (DeliveryState::Delivered, None, _) => format!("{} was delivered.", name),
+            (DeliveryState::InTransit, _, Some(expected)) if expected >= today => format!(
+                "{} is on its way and should arrive {}.",
+                name,
+                describe_day(expected, today)
+            ),
+            (state, _, _) => {
+                let update = tracking
+                    .last_update
+                    .as_ref()
+                    // Otherwise the state already says where it is
+                    .filter(|_| {
+                        matches!(
+                            state,
+                            DeliveryState::InTransit
+                                | DeliveryState::Exception
+                                | DeliveryState::Unknown
+                        )
+                    })
+                    .map(|update| format!(" The last update was: {}.", update))
+                    .unwrap_or_default();
+                format!("{} is {}.{}", name, state.description(), update)
+            }
+        },
+    )
+}
+
+const PACKAGES_INTENT: &str = "where is my package";
+/// At most this many packages are described in a response.
+const MAX_DESCRIBED_PACKAGES: usize = 3;
+
+/// A [Skill] answering "where is my package" with the status of the tracked packages: the ones
+/// it is given and the ones added to the storage with [add_package]. See [DeliveryAnnouncer] to
+/// be told when a package is out for delivery. Enabled with the `packages` feature.
+pub struct PackageSkill {
+    provider: Box<dyn TrackingProvider>,
+    packages: Vec<Package>,
+}
+
+impl PackageSkill {
+    pub fn new(provider: impl TrackingProvider + 'static, packages: Vec<Package>) -> Self {
+        Self {
+            provider: Box::new(provider),
+            packages,
+        }
+    }
+}
+
+impl Skill for PackageSkill {
+    fn name(&self) -> &str {
+        "packages"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak, Capability::Network, Capability::Storage]
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        vec![IntentSpec::new(
+            PACKAGES_INTENT,
+            examples(&[
+                "where is my package",
+                "where are my packages",
+                "when will my package arrive",
+                "is my package coming today",
+            ]),
+        )]
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        if query.intent != Some(PACKAGES_INTENT) {
+            return;
+        }
+        let packages = all_packages(&self.packages, ctx.storage());
+        if packages.is_empty() {
+            _ = ctx.speak("I'm not tracking any packages.");
+            return;
+        }
+        let today = ctx.clock().local_now().date_naive();
+        let mut failed = false;
+        let descriptions: Vec<String> = packages
+            .iter()
+            .filter_map(|package| match self.provider.track(package) {
+                Ok(tracking) => describe_tracking(package, &tracking, today),
+                Err(e) => {
+                    warn!("Failed to track the package {}: {}", package.number, e);
+                    failed = true;
+                    None
+                }
+            })
+            .take(MAX_DESCRIBED_PACKAGES)
+            .collect();
+        let response = match (descriptions.is_empty(), failed) {
+            (true, true) => "Sorry, I couldn't track your packages right now.".to_string(),
+            (true, false) => "All your packages have been delivered.".to_string(),
+            (false, _) => descriptions.join(" "),
+        };
+        _ = ctx.speak(response);
+    }
+
+    #[cfg(feature = "offline")]
+    fn needs_network(&self) -> bool {
+        true
+    }
+}
+
+/// Tells when a package is out for delivery, expected today or delivered, once for each of
+/// them: the announcements are returned by [DeliveryAnnouncer::check], to make every so often
+/// with [crate::remote::RemoteHandle::spawn_check]. What was announced is kept in the storage,
+/// so it isn't repeated after a restart.
+pub struct DeliveryAnnouncer {
+    provider: Box<dyn TrackingProvider + Send>,
+    packages: Vec<Package>,
+    storage: Option<Arc<dyn Storage>>,
+    // What was announced of each package, without storage
+    announced: Vec<(String, String)>,
+}
+
+impl DeliveryAnnouncer {
+    pub fn new(
+        provider: impl TrackingProvider + Send + 'static,
+        packages: Vec<Package>,
+        storage: Option<Arc<dyn Storage>>,
+    ) -> Self {
+        Self {
+            provider: Box::new(provider),
+            packages,
+            storage,
+            announced: Vec::new(),
+        }
+    }
+
+    /// The announcements of what changed since the last check.
+    pub fn check(&mut self, now: DateTime<Local>) -> Option<String> {
+        let today = now.date_naive();
+        let mut announcements = Vec::new();
+        for package in all_packages(&self.packages, self.storage.as_deref()) {
+            let tracking = match self.provider.track(&package) {
+                Ok(tracking) => tracking,
+                Err(e) => {
+                    warn!("Failed to track the package {}: {}", package.number, e);
+                    continue;
+                }
+            };
+            let name = package.description();
+            let (key, announcement) = match tracking.state {
+                DeliveryState::Delivered => {
+                    // Packages that were delivered before they were tracked aren't news
+                    if tracking
+                        .delivered
+                        .is_some_and(|delivered| delivered < today)
+                    {
+                        continue;
+                    }
+                    ("delivered".to_string(), format!("{} was delivered.", name))
+                }
+                DeliveryState::OutForDelivery => (
+                    format!("out {}", today),
+                    format!("{} is out for delivery.", name),
+                ),
+                DeliveryState::AvailableForPickup => (
+                    "pickup".to_string(),
+                    format!("{} is ready for pickup.", name),
+                ),
+                _ if tracking.expected == Some(today) => (
+                    format!("expected {}", today),
+                    format!("{} should arrive today.", name),
+                ),
+                _ => continue,
+            };
+            if self.last_announced(&package.number).as_deref() == Some(key.as_str()) {
+                continue;
+            }
+            self.set_announced(&package.number, key);
+            announcements.push(announcement);
+        }
+        (!announcements.is_empty()).then(|| announcements.join(" "))
+    }
+
+    fn last_announced(&self, number: &str) -> Option<String> {
+        match &self.storage {
+            Some(storage) => match storage.get(ANNOUNCED_NAMESPACE, number) {
+                Ok(value) => value.map(|value| String::from_utf8_lossy(&value).into_owned()),
+                Err(e) => {
+                    warn!("Failed to load the announcements of {}: {}", number, e);
+                    None
+                }
+            },
+            None => self
+                .announced
+                .iter()
+                .find(|(announced, _)| announced == number)
+                .map(|(_, key)| key.clone()),
+        }
+    }
+
+    fn set_announced(&mut self, number: &str, key: String) {
+        match &self.storage {
+            Some(storage) => {
+                if let Err(e) = storage.set(ANNOUNCED_NAMESPACE, number, key.as_bytes()) {
+                    warn!("Failed to save the announcements of {}: {}", number, e);
+                }
+            }
+            None => {
+                self.announced.retain(|(announced, _)| announced != number);
+                self.announced.push((number.to_string(), key));
+            }
+        }
+    }
+}
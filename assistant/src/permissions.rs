@@ -0,0 +1,81 @@
+use std::collections::{HashMap, HashSet};
+
+/// What a [crate::skills::Skill] can do through its [crate::skills::SkillContext], declared with
+/// [crate::skills::Skill::capabilities]. Native skills can still do anything Rust can, so this
+/// mostly matters for skills that only reach the outside through the context, like scripted
+/// ones.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Record audio from the microphone.
+    RecordAudio,
+    /// Reach the internet, see `SkillContext::http_client` of the `http` feature.
+    Network,
+    /// Talk to the user, see [crate::skills::SkillContext::speak].
+    Speak,
+    /// Run commands on the system.
+    SystemCommands,
+    /// Read and write the storage of the assistant, see [crate::skills::SkillContext::storage].
+    Storage,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 5] = [
+        Capability::RecordAudio,
+        Capability::Network,
+        Capability::Speak,
+        Capability::SystemCommands,
+        Capability::Storage,
+    ];
+
+    /// The name of the capability in kebab case, e.g. for configuration files.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::RecordAudio => "record-audio",
+            Capability::Network => "network",
+            Capability::Speak => "speak",
+            Capability::SystemCommands => "system-commands",
+            Capability::Storage => "storage",
+        }
+    }
+}
+
+/// The capabilities the user denied to skills, by the name of the skill, see
+/// [crate::AssistantConfig::set_permissions]. Everything a skill declares is allowed by default.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Permissions {
+    denied: HashMap<String, HashSet<Capability>>,
+}
+
+impl Permissions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn deny(&mut self, skill: &str, capability: Capability) {
+        self.denied
+            .entry(skill.to_string())
+            .or_default()
+            .insert(capability);
+    }
+
+    pub fn allow(&mut self, skill: &str, capability: Capability) {
+        if let Some(denied) = self.denied.get_mut(skill) {
+            denied.remove(&capability);
+        }
+    }
+
+    pub fn is_denied(&self, skill: &str, capability: Capability) -> bool {
+        self.denied
+            .get(skill)
+            .is_some_and(|denied| denied.contains(&capability))
+    }
+
+    /// The capabilities a skill gets: the ones it declared and that weren't denied to it.
+    pub fn granted(&self, skill: &str, declared: &[Capability]) -> HashSet<Capability> {
+        declared
+            .iter()
+            .copied()
+            .filter(|capability| !self.is_denied(skill, *capability))
+            .collect()
+    }
+}
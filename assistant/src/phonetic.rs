@@ -0,0 +1,223 @@
+use cpal::{
+    traits::{DeviceTrait, HostTrait, StreamTrait},
+    FromSample, SizedSample,
+};
+use std::{sync::mpsc, time::Duration};
+use thiserror::Error;
+
+const NATO_ALPHABET: [&str; 26] = [
+    "Alfa", "Bravo", "Charlie", "Delta", "Echo", "Foxtrot", "Golf", "Hotel", "India", "Juliett",
+    "Kilo", "Lima", "Mike", "November", "Oscar", "Papa", "Quebec", "Romeo", "Sierra", "Tango",
+    "Uniform", "Victor", "Whiskey", "X-ray", "Yankee", "Zulu",
+];
+
+const DIGITS: [&str; 10] = [
+    "Zero", "One", "Two", "Three", "Four", "Five", "Six", "Seven", "Eight", "Nine",
+];
+
+const MORSE_LETTERS: [&str; 26] = [
+    ".-", "-...", "-.-.", "-..", ".", "..-.", "--.", "....", "..", ".---", "-.-", ".-..", "--",
+    "-.", "---", ".--.", "--.-", ".-.", "...", "-", "..-", "...-", ".--", "-..-", "-.--", "--..",
+];
+
+const MORSE_DIGITS: [&str; 10] = [
+    "-----", ".----", "..---", "...--", "....-", ".....", "-....", "--...", "---..", "----.",
+];
+
+fn spell_char(c: char) -> Option<String> {
+    if c.is_ascii_alphabetic() {
+        let word = NATO_ALPHABET[(c.to_ascii_lowercase() as u8 - b'a') as usize];
+        if c.is_ascii_uppercase() {
+            Some(format!("capital {}", word))
+        } else {
+            Some(word.to_string())
+        }
+    } else if let Some(digit) = c.to_digit(10) {
+        Some(DIGITS[digit as usize].to_string())
+    } else {
+        let name = match c {
+            '.' => "dot",
+            ',' => "comma",
+            '-' => "dash",
+            '_' => "underscore",
+            '@' => "at",
+            '/' => "slash",
+            ':' => "colon",
+            '!' => "exclamation mark",
+            '?' => "question mark",
+            '#' => "hash",
+            '&' => "ampersand",
+            '+' => "plus",
+            '=' => "equals",
+            '*' => "asterisk",
+            _ => return None,
+        };
+        Some(name.to_string())
+    }
+}
+
+/// Convert text into a sentence spelling it out with the NATO phonetic alphabet. Letters are
+/// separated by commas and words by full stops, so that the TTS backend pauses between them.
+/// Characters that have no spoken name are skipped.
+pub fn spell_nato(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter_map(spell_char)
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(". Space. ")
+}
+
+/// Encode text as Morse code, using `.` and `-` for the symbols, a single space between letters
+/// and ` / ` between words. Characters without a Morse representation are skipped.
+pub fn encode_morse(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter_map(|c| {
+                    if c.is_ascii_alphabetic() {
+                        Some(MORSE_LETTERS[(c.to_ascii_lowercase() as u8 - b'a') as usize])
+                    } else {
+                        c.to_digit(10).map(|digit| MORSE_DIGITS[digit as usize])
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" / ")
+}
+
+/// MorseConfig controls how Morse code is played by [play_morse].
+pub struct MorseConfig {
+    /// Frequency of the tone in Hz.
+    pub frequency: f32,
+    /// Length of a dot. Dashes are three units long, the gap between letters is three units and
+    /// the gap between words is seven units.
+    pub unit: Duration,
+    /// Volume of the tone, from 0 to 1.
+    pub volume: f32,
+}
+
+impl Default for MorseConfig {
+    fn default() -> Self {
+        Self {
+            frequency: 600.,
+            unit: Duration::from_millis(80),
+            volume: 0.5,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum MorsePlayError {
+    #[error("No output device available")]
+    NoOutputDevice,
+    #[error("No default output config available")]
+    NoDefaultOutputConfig(#[from] cpal::DefaultStreamConfigError),
+    #[error("Unsupported output sample format")]
+    UnsupportedSampleFormat,
+    #[error("Failed to build output stream")]
+    BuildOutputStream(#[from] cpal::BuildStreamError),
+    #[error("Failed to play stream")]
+    PlayStream(#[from] cpal::PlayStreamError),
+    #[error("Output stream stopped before playback finished")]
+    StreamStopped,
+}
+
+/// Play text as Morse code tones on the default output device. This function will block until
+/// playback has finished.
+pub fn play_morse(text: &str, config: &MorseConfig) -> Result<(), MorsePlayError> {
+    let host = cpal::default_host();
+    let device = host
+        .default_output_device()
+        .ok_or(MorsePlayError::NoOutputDevice)?;
+    let output_config = device.default_output_config()?;
+    let stream_config: cpal::StreamConfig = output_config.config();
+
+    let samples = render_morse(
+        &encode_morse(text),
+        config,
+        stream_config.sample_rate.0 as f32,
+    );
+
+    let (tx, rx) = mpsc::channel();
+    let stream = match output_config.sample_format() {
+        cpal::SampleFormat::I16 => init_output_stream::<i16>(&device, &stream_config, samples, tx)?,
+        cpal::SampleFormat::I32 => init_output_stream::<i32>(&device, &stream_config, samples, tx)?,
+        cpal::SampleFormat::F32 => init_output_stream::<f32>(&device, &stream_config, samples, tx)?,
+        _ => return Err(MorsePlayError::UnsupportedSampleFormat),
+    };
+    stream.play()?;
+
+    rx.recv().map_err(|_| MorsePlayError::StreamStopped)?;
+    drop(stream);
+    Ok(())
+}
+
+fn render_morse(code: &str, config: &MorseConfig, sample_rate: f32) -> Vec<f32> {
+    let unit_samples = (config.unit.as_secs_f32() * sample_rate) as usize;
+    let mut samples = Vec::new();
+    let mut push = |units: usize, tone: bool| {
+        let start = samples.len();
+        samples.extend((0..units * unit_samples).map(|i| {
+            if tone {
+                let t = (start + i) as f32 / sample_rate;
+                (t * config.frequency * std::f32::consts::TAU).sin() * config.volume
+            } else {
+                0.
+            }
+        }));
+    };
+
+    for symbol in code.chars() {
+        match symbol {
+            '.' => {
+                push(1, true);
+                push(1, false);
+            }
+            '-' => {
+                push(3, true);
+                push(1, false);
+            }
+            // One unit of silence already follows every symbol, so a letter gap adds two more
+            // and the " / " word gap adds six.
+            ' ' => push(2, false),
+            '/' => push(2, false),
+            _ => (),
+        }
+    }
+    samples
+}
+
+fn init_output_stream<S: SizedSample + FromSample<f32>>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    samples: Vec<f32>,
+    tx: mpsc::Sender<()>,
+) -> Result<cpal::Stream, cpal::BuildStreamError> {
+    let error_callback = move |err| {
+        warn!("an error occurred on stream: {}", err);
+    };
+
+    let channels = config.channels as usize;
+    let mut position = 0;
+    let mut finished = false;
+    let data_callback = move |data: &mut [S], _: &_| {
+        for frame in data.chunks_mut(channels) {
+            let value = samples.get(position).copied().unwrap_or(0.);
+            position += 1;
+            frame.fill(S::from_sample(value));
+        }
+        if position >= samples.len() && !finished {
+            finished = true;
+            _ = tx.send(());
+        }
+    };
+    device.build_output_stream(config, data_callback, error_callback, None)
+}
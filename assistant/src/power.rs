@@ -0,0 +1,152 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{audio::AudioFormat, ring::RingBuffer};
+
+/// How the wakeword engine is run, see [crate::wakeword::WakewordConfig::set_power_mode].
+#[derive(Clone, Debug, PartialEq, Default)]
+pub enum PowerMode {
+    /// Give all audio to the wakeword engine.
+    #[default]
+    AlwaysOn,
+    /// Only run the wakeword engine while there is sound, which saves most of its CPU time in a
+    /// quiet room, e.g. on battery powered builds.
+    LowPower(EnergyGateConfig),
+}
+
+/// Configuration of the energy detector used in [PowerMode::LowPower].
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnergyGateConfig {
+    /// RMS level, from 0 to 1, above which audio is considered sound.
+    pub threshold: f32,
+    /// How long the engine keeps running after the last sound.
+    pub hangover: Duration,
+    /// Audio kept while the engine isn't running and given to it when sound is detected, so the
+    /// start of the wakeword isn't lost.
+    pub pre_roll: Duration,
+}
+
+impl Default for EnergyGateConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 0.01,
+            hangover: Duration::from_secs(2),
+            pre_roll: Duration::from_secs(1),
+        }
+    }
+}
+
+/// How much of the audio was given to the wakeword engine, see
+/// [crate::wakeword::WakewordListener::power_stats].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PowerStats {
+    /// Blocks of audio captured.
+    pub blocks: u64,
+    /// Blocks of audio given to the wakeword engine, including pre-roll.
+    pub processed_blocks: u64,
+}
+
+impl PowerStats {
+    /// The fraction of the audio processed by the wakeword engine, from 0 to 1.
+    pub fn processed_fraction(&self) -> f64 {
+        if self.blocks == 0 {
+            return 0.;
+        }
+        self.processed_blocks as f64 / self.blocks as f64
+    }
+}
+
+/// Counters behind [PowerStats], shared with the thread of the wakeword engine.
+#[derive(Default)]
+pub(crate) struct PowerCounters {
+    blocks: AtomicU64,
+    processed_blocks: AtomicU64,
+}
+
+impl PowerCounters {
+    pub(crate) fn stats(&self) -> PowerStats {
+        PowerStats {
+            blocks: self.blocks.load(Ordering::Relaxed),
+            processed_blocks: self.processed_blocks.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Passes audio on only while there is sound, as configured by a [PowerMode].
+pub(crate) struct EnergyGate {
+    config: Option<EnergyGateConfig>,
+    hangover_samples: usize,
+    samples_since_sound: usize,
+    pre_roll: RingBuffer<f32>,
+    counters: Arc<PowerCounters>,
+}
+
+impl EnergyGate {
+    pub(crate) fn new(mode: PowerMode, format: AudioFormat, counters: Arc<PowerCounters>) -> Self {
+        let samples_per_second = format.sample_rate as f64 * format.channels.max(1) as f64;
+        let config = match mode {
+            PowerMode::AlwaysOn => None,
+            PowerMode::LowPower(config) => Some(config),
+        };
+        let samples = |duration: Option<Duration>| {
+            (duration.unwrap_or_default().as_secs_f64() * samples_per_second) as usize
+        };
+        let hangover_samples = samples(config.as_ref().map(|c| c.hangover));
+        Self {
+            hangover_samples,
+            samples_since_sound: hangover_samples + 1,
+            pre_roll: RingBuffer::new(samples(config.as_ref().map(|c| c.pre_roll))),
+            config,
+            counters,
+        }
+    }
+
+    /// Give the block to `process` if the gate is open, preceded by the pre-roll if it just
+    /// opened.
+    pub(crate) fn process(&mut self, data: &[f32], mut process: impl FnMut(&[f32])) {
+        self.counters.blocks.fetch_add(1, Ordering::Relaxed);
+        let Some(config) = &self.config else {
+            self.counters
+                .processed_blocks
+                .fetch_add(1, Ordering::Relaxed);
+            return process(data);
+        };
+
+        if rms(data) >= config.threshold {
+            self.samples_since_sound = 0;
+        } else {
+            self.samples_since_sound = self.samples_since_sound.saturating_add(data.len());
+        }
+
+        if self.samples_since_sound > self.hangover_samples {
+            self.pre_roll.push_overwrite(data);
+            return;
+        }
+
+        // The pre-roll is counted in blocks of the size of this one
+        let blocks = self.pre_roll.len().div_ceil(data.len().max(1)) as u64 + 1;
+        let (first, second) = self.pre_roll.as_slices();
+        for part in [first, second] {
+            if !part.is_empty() {
+                process(part);
+            }
+        }
+        self.pre_roll.clear();
+        process(data);
+        self.counters
+            .processed_blocks
+            .fetch_add(blocks, Ordering::Relaxed);
+    }
+}
+
+fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.;
+    }
+    (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+}
@@ -0,0 +1,56 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// PresenceSensor tells whether someone is in the room, e.g. from a camera person detector, so
+/// that wakewords detected without anyone there, like from the TV, are ignored. See
+/// [crate::AssistantConfig::set_presence_sensor].
+pub trait PresenceSensor {
+    /// Called for every wakeword detection. Detections are ignored when it returns `false`.
+    fn is_present(&mut self) -> bool;
+}
+
+/// A [PresenceSensor] set from elsewhere, e.g. by a thread receiving the MQTT messages of a person
+/// detector. Whoever was seen counts as present for a while, since detectors miss people who
+/// stand still or look away. Clones share the same state.
+#[derive(Clone, Debug)]
+pub struct PresenceSignal {
+    hold: Duration,
+    state: Arc<Mutex<PresenceState>>,
+}
+
+#[derive(Debug, Default)]
+struct PresenceState {
+    present: bool,
+    last_seen: Option<Instant>,
+}
+
+impl PresenceSignal {
+    /// Nobody is present until [PresenceSignal::set_present] is called. Someone seen stays
+    /// present for `hold` after they are gone.
+    pub fn new(hold: Duration) -> Self {
+        Self {
+            hold,
+            state: Arc::default(),
+        }
+    }
+
+    pub fn set_present(&self, present: bool) {
+        let mut state = self.state.lock().unwrap();
+        if state.present || present {
+            state.last_seen = Some(Instant::now());
+        }
+        state.present = present;
+    }
+}
+
+impl PresenceSensor for PresenceSignal {
+    fn is_present(&mut self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.present
+            || state
+                .last_seen
+                .is_some_and(|last_seen| last_seen.elapsed() <= self.hold)
+    }
+}
@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use ::tts::Tts;
+
+use crate::{
+    stt::EndpointConfig,
+    tts::{self, TtsError},
+};
+
+/// SettingsProfile groups the interaction settings that are applied across the whole assistant,
+/// so that switching between e.g. the standard and the accessibility behaviour is a single call
+/// to [crate::AssistantConfig::set_profile] or [crate::Assistant::set_profile].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SettingsProfile {
+    /// Speech rate relative to the TTS backend, from -1 (slowest) to 1 (fastest), 0 being the
+    /// backend's normal rate.
+    pub speech_rate: f32,
+    /// Repeat the recognized sentence back to the user before returning the query.
+    pub confirm_commands: bool,
+    /// How long to wait for the user to start speaking before giving up.
+    pub stt_timeout: Duration,
+    /// How the end of a sentence is detected.
+    pub endpoint: EndpointConfig,
+    /// Gain of the earcons, multiplied with the one set with
+    /// [crate::AssistantConfig::set_earcon_volume].
+    pub earcon_volume: f32,
+}
+
+impl SettingsProfile {
+    /// The default behaviour of the assistant.
+    pub fn standard() -> Self {
+        Self {
+            speech_rate: 0.,
+            confirm_commands: false,
+            stt_timeout: Duration::from_secs(20),
+            endpoint: EndpointConfig::default(),
+            earcon_volume: 1.,
+        }
+    }
+
+    /// Slower speech, louder earcons, every recognized command is repeated back and the user is
+    /// given more time to speak and to pause between words.
+    pub fn accessibility() -> Self {
+        Self {
+            speech_rate: -0.4,
+            confirm_commands: true,
+            stt_timeout: Duration::from_secs(40),
+            endpoint: EndpointConfig {
+                max_silence: Duration::from_millis(2000),
+                max_utterance: Duration::from_secs(30),
+                ..EndpointConfig::default()
+            },
+            earcon_volume: 1.5,
+        }
+    }
+
+    pub(crate) fn apply_tts(&self, tts: &mut Tts) -> Result<(), TtsError> {
+        tts::set_rate(tts, self.speech_rate)
+    }
+}
+
+impl Default for SettingsProfile {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
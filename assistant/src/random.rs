@@ -0,0 +1,86 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Random is the source of randomness of the assistant, e.g. to pick one of the variants of a
+/// response. The default is [SystemRandom]; with the `deterministic` feature, `SeededRandom`
+/// makes every choice reproducible from a seed, so that together with a
+/// [crate::clock::ManualClock] tests can replay exactly what the assistant did.
+pub trait Random: Send + Sync {
+    /// A uniformly distributed number.
+    fn next_u64(&self) -> u64;
+}
+
+impl dyn Random + '_ {
+    /// A number from 0 to `n`, excluded, or 0 if `n` is 0.
+    pub fn below(&self, n: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(n)) >> 64) as u64
+    }
+
+    /// One of the items, each as likely as the others, or `None` if there are none.
+    pub fn choose<'a, T>(&self, items: &'a [T]) -> Option<&'a T> {
+        items.get(self.below(items.len() as u64) as usize)
+    }
+}
+
+/// SystemRandom is seeded differently every time the assistant starts, from the keys the
+/// standard library gets from the operating system for its hash maps. It isn't meant for
+/// cryptography.
+pub struct SystemRandom(SplitMix);
+
+impl SystemRandom {
+    pub fn new() -> Self {
+        Self(SplitMix(AtomicU64::new(
+            RandomState::new().build_hasher().finish(),
+        )))
+    }
+}
+
+impl Default for SystemRandom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Random for SystemRandom {
+    fn next_u64(&self) -> u64 {
+        self.0.next()
+    }
+}
+
+/// SeededRandom makes the same choices every time it is created with the same seed. Enabled
+/// with the `deterministic` feature.
+#[cfg(feature = "deterministic")]
+pub struct SeededRandom(SplitMix);
+
+#[cfg(feature = "deterministic")]
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        Self(SplitMix(AtomicU64::new(seed)))
+    }
+}
+
+#[cfg(feature = "deterministic")]
+impl Random for SeededRandom {
+    fn next_u64(&self) -> u64 {
+        self.0.next()
+    }
+}
+
+/// The SplitMix64 generator, which can be shared between threads without a lock.
+struct SplitMix(AtomicU64);
+
+impl SplitMix {
+    fn next(&self) -> u64 {
+        const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+        let mut z = self
+            .0
+            .fetch_add(GAMMA, Ordering::Relaxed)
+            .wrapping_add(GAMMA);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
@@ -0,0 +1,190 @@
+use chrono::{DateTime, Local};
+use hound::{SampleFormat, WavSpec, WavWriter};
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+
+use crate::{
+    audio::{AudioFormat, AudioSource},
+    ring::RingBuffer,
+    stt::STT_SAMPLE_RATE,
+};
+
+/// Where and for how long the audio heard by the assistant is kept, to find out why a wakeword
+/// or a query was misheard, see [crate::AssistantConfig::set_recording].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordingConfig {
+    directory: PathBuf,
+    wakeword_window: Duration,
+    max_recordings: Option<usize>,
+    max_age: Option<Duration>,
+}
+
+#[derive(Error, Debug)]
+pub enum RecordingError {
+    #[error("Failed to access the recordings directory")]
+    Io(#[from] io::Error),
+    #[error("Failed to write the recording")]
+    Wav(#[from] hound::Error),
+}
+
+impl RecordingConfig {
+    /// Save the recordings as WAV files in the directory, which is created if it doesn't exist.
+    /// Old recordings are deleted from it, so it shouldn't contain other WAV files.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+            wakeword_window: Duration::from_secs(3),
+            max_recordings: Some(100),
+            max_age: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+        }
+    }
+
+    /// How much of the audio leading up to a wakeword detection is saved, 3 seconds by default.
+    pub fn set_wakeword_window(&mut self, window: Duration) {
+        self.wakeword_window = window;
+    }
+
+    /// How many recordings are kept, the oldest are deleted first. 100 by default, `None` keeps
+    /// all of them.
+    pub fn set_max_recordings(&mut self, max_recordings: Option<usize>) {
+        self.max_recordings = max_recordings;
+    }
+
+    /// How long recordings are kept, a week by default. `None` keeps them until there are too
+    /// many.
+    pub fn set_max_age(&mut self, max_age: Option<Duration>) {
+        self.max_age = max_age;
+    }
+}
+
+/// Saves the audio around wakeword detections and of speech recognition sessions.
+pub(crate) struct Recorder {
+    config: RecordingConfig,
+    format: AudioFormat,
+    window: Arc<Mutex<RingBuffer<f32>>>,
+}
+
+impl Recorder {
+    pub(crate) fn start(
+        config: RecordingConfig,
+        input: &dyn AudioSource,
+    ) -> Result<Self, RecordingError> {
+        fs::create_dir_all(&config.directory)?;
+        let format = input.format();
+        let samples = config.wakeword_window.as_secs_f64()
+            * format.sample_rate as f64
+            * format.channels as f64;
+        let window = Arc::new(Mutex::new(RingBuffer::new(samples as usize)));
+        let captured = window.clone();
+        input.subscribe(Box::new(move |data, _| {
+            captured.lock().unwrap().push_overwrite(data)
+        }));
+        Ok(Self {
+            config,
+            format,
+            window,
+        })
+    }
+
+    /// Save the audio of the wakeword window, named after the time and the wakeword.
+    pub(crate) fn save_wakeword(
+        &self,
+        wakeword: &str,
+        now: DateTime<Local>,
+    ) -> Result<PathBuf, RecordingError> {
+        let samples: Vec<f32> = {
+            let window = self.window.lock().unwrap();
+            let (first, second) = window.as_slices();
+            first.iter().chain(second).copied().collect()
+        };
+        let path = self.path(now, &format!("wakeword-{}", file_name_part(wakeword)));
+        let spec = WavSpec {
+            channels: self.format.channels,
+            sample_rate: self.format.sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let mut writer = WavWriter::create(&path, spec)?;
+        for sample in samples {
+            writer.write_sample(sample)?;
+        }
+        writer.finalize()?;
+        self.prune()?;
+        Ok(path)
+    }
+
+    /// Save the audio given to speech-to-text in a session, mono at [STT_SAMPLE_RATE].
+    pub(crate) fn save_query(
+        &self,
+        audio: &[i16],
+        now: DateTime<Local>,
+    ) -> Result<PathBuf, RecordingError> {
+        let path = self.path(now, "query");
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: STT_SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec)?;
+        for &sample in audio {
+            writer.write_sample(sample)?;
+        }
+        writer.finalize()?;
+        self.prune()?;
+        Ok(path)
+    }
+
+    fn path(&self, now: DateTime<Local>, kind: &str) -> PathBuf {
+        self.config.directory.join(format!(
+            "{}-{}.wav",
+            now.format("%Y-%m-%d_%H-%M-%S%.3f"),
+            kind
+        ))
+    }
+
+    /// Delete the recordings that are too old or too many.
+    fn prune(&self) -> Result<(), RecordingError> {
+        let mut recordings = Vec::new();
+        for entry in fs::read_dir(&self.config.directory)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|extension| extension == "wav") {
+                recordings.push(path);
+            }
+        }
+        // The names start with the time, so they sort from oldest to newest
+        recordings.sort();
+        if let Some(max_age) = self.config.max_age {
+            let now = SystemTime::now();
+            let mut kept = Vec::with_capacity(recordings.len());
+            for path in recordings {
+                let modified = fs::metadata(&path)?.modified()?;
+                if now.duration_since(modified).unwrap_or_default() > max_age {
+                    fs::remove_file(&path)?;
+                } else {
+                    kept.push(path);
+                }
+            }
+            recordings = kept;
+        }
+        if let Some(max_recordings) = self.config.max_recordings {
+            let excess = recordings.len().saturating_sub(max_recordings);
+            for path in &recordings[..excess] {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The name of a wakeword without the characters that can't be in file names.
+fn file_name_part(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect()
+}
@@ -0,0 +1,105 @@
+use std::{
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+use thiserror::Error;
+
+/// A command for the assistant from another thread, e.g. a network API, see
+/// [crate::Assistant::remote].
+#[derive(Clone, Debug, PartialEq)]
+pub enum RemoteCommand {
+    /// Match the text as if the user said it. The query is returned by
+    /// [crate::Assistant::listen] with an empty wakeword, or handled by its skill.
+    Query(String),
+    Speak(String),
+    /// Ignore the wakewords while muted, see [crate::Assistant::set_muted].
+    SetMuted(bool),
+    /// Stop or start capturing audio, see [crate::Assistant::pause_listening].
+    SetListening(bool),
+    /// Shut the assistant down, see [crate::Assistant::shutdown].
+    Shutdown,
+    /// See [crate::Assistant::set_intent_threshold].
+    SetIntentThreshold(f32),
+    /// See [crate::Assistant::set_wakeword_detector].
+    #[cfg(feature = "rustpotter")]
+    SetWakewordDetector(crate::wakeword::DetectorSettings),
+    /// Run the actions like those of an automation rule, e.g. for an announcement at a set time.
+    /// Like them, speech waits for the assistant to be quiet. Speech is skipped in do not disturb.
+    Announce(Vec<crate::automation::Action>),
+    /// A [crate::conversation::DialogContext] sealed by another assistant, to go on with its
+    /// dialog, see [crate::Assistant::resume_dialog]. Refused unless it was sealed with the key of
+    /// [crate::AssistantConfig::set_sync].
+    #[cfg(feature = "sync")]
+    ResumeDialog(String),
+    /// A message published to an MQTT topic, for [crate::automation::Trigger::MqttMessage].
+    MqttMessage {
+        topic: String,
+        payload: Vec<u8>,
+    },
+}
+
+#[derive(Error, Debug)]
+#[error("The assistant was stopped")]
+pub struct RemoteError;
+
+/// Sends [RemoteCommand]s to the assistant, which runs them while it waits for a wakeword. Can be
+/// cloned and sent to other threads.
+#[derive(Clone)]
+pub struct RemoteHandle {
+    tx: Sender<RemoteCommand>,
+}
+
+impl RemoteHandle {
+    pub fn send(&self, command: RemoteCommand) -> Result<(), RemoteError> {
+        self.tx.send(command).map_err(|_| RemoteError)
+    }
+
+    /// Call `check` on another thread every `interval`, starting now, and announce what it
+    /// returns like [RemoteCommand::Announce], e.g. for the alerts of a skill. The thread stops
+    /// when an announcement finds the assistant stopped.
+    pub fn spawn_check(
+        &self,
+        interval: Duration,
+        mut check: impl FnMut() -> Option<String> + Send + 'static,
+    ) {
+        let remote = self.clone();
+        thread::spawn(move || loop {
+            if let Some(text) = check() {
+                let actions = vec![crate::automation::Action::Speak(text)];
+                if remote.send(RemoteCommand::Announce(actions)).is_err() {
+                    return;
+                }
+            }
+            thread::sleep(interval);
+        });
+    }
+}
+
+pub(crate) struct RemoteCommands {
+    tx: Sender<RemoteCommand>,
+    rx: Receiver<RemoteCommand>,
+}
+
+impl RemoteCommands {
+    pub(crate) fn new() -> Self {
+        let (tx, rx) = mpsc::channel();
+        Self { tx, rx }
+    }
+
+    pub(crate) fn handle(&self) -> RemoteHandle {
+        RemoteHandle {
+            tx: self.tx.clone(),
+        }
+    }
+
+    /// Queue a command from the assistant itself.
+    pub(crate) fn push(&self, command: RemoteCommand) {
+        // The receiver lives as long as the sender
+        _ = self.tx.send(command);
+    }
+
+    pub(crate) fn try_next(&self) -> Option<RemoteCommand> {
+        self.rx.try_recv().ok()
+    }
+}
@@ -0,0 +1,80 @@
+/// A fixed-size FIFO of samples for the audio callbacks, which never allocates or moves the samples
+/// it holds after being created.
+pub(crate) struct RingBuffer<T> {
+    data: Box<[T]>,
+    start: usize,
+    len: usize,
+}
+
+impl<T: Copy + Default> RingBuffer<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            data: vec![T::default(); capacity].into_boxed_slice(),
+            start: 0,
+            len: 0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+
+    /// Append as many samples as fit and return how many were appended.
+    pub(crate) fn push(&mut self, samples: &[T]) -> usize {
+        let count = samples.len().min(self.data.len() - self.len);
+        for &sample in &samples[..count] {
+            let index = (self.start + self.len) % self.data.len();
+            self.data[index] = sample;
+            self.len += 1;
+        }
+        count
+    }
+
+    /// Append the samples, dropping the oldest ones to make room.
+    pub(crate) fn push_overwrite(&mut self, samples: &[T]) {
+        let capacity = self.data.len();
+        if capacity == 0 {
+            return;
+        }
+        let samples = &samples[samples.len().saturating_sub(capacity)..];
+        let excess = (self.len + samples.len()).saturating_sub(capacity);
+        self.start = (self.start + excess) % capacity;
+        self.len -= excess;
+        self.push(samples);
+    }
+
+    /// Move the oldest `output.len()` samples to the output. Returns false, leaving the buffer
+    /// as it is, if there aren't enough.
+    #[cfg(feature = "rustpotter")]
+    pub(crate) fn pop_into(&mut self, output: &mut [T]) -> bool {
+        if output.len() > self.len {
+            return false;
+        }
+        let count = output.len();
+        let (first, second) = self.as_slices();
+        let split = first.len().min(count);
+        output[..split].copy_from_slice(&first[..split]);
+        output[split..].copy_from_slice(&second[..count - split]);
+        self.start = (self.start + count) % self.data.len().max(1);
+        self.len -= count;
+        true
+    }
+
+    /// The samples from oldest to newest, in two parts since they can wrap around.
+    pub(crate) fn as_slices(&self) -> (&[T], &[T]) {
+        let end = self.start + self.len;
+        if end <= self.data.len() {
+            (&self.data[self.start..end], &[])
+        } else {
+            (
+                &self.data[self.start..],
+                &self.data[..end - self.data.len()],
+            )
+        }
+    }
+}
@@ -0,0 +1,596 @@
+use chrono::{DateTime, Days, Local, NaiveTime, TimeDelta, Utc};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+use crate::{
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{parse_number, Slot, SlotKind, SlotValue},
+    storage::{Storage, StorageError},
+    AssistantQuery,
+};
+
+/// The [Storage] namespace of the scheduled items.
+const SCHEDULE_NAMESPACE: &str = "schedule";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScheduleKind {
+    Timer,
+    Alarm,
+    Reminder,
+}
+
+impl ScheduleKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScheduleKind::Timer => "timer",
+            ScheduleKind::Alarm => "alarm",
+            ScheduleKind::Reminder => "reminder",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [
+            ScheduleKind::Timer,
+            ScheduleKind::Alarm,
+            ScheduleKind::Reminder,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == name)
+    }
+}
+
+/// How often a [ScheduledItem] fires again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Repeat {
+    Daily,
+    Weekly,
+}
+
+impl Repeat {
+    fn name(&self) -> &'static str {
+        match self {
+            Repeat::Daily => "daily",
+            Repeat::Weekly => "weekly",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [Repeat::Daily, Repeat::Weekly]
+            .into_iter()
+            .find(|repeat| repeat.name() == name)
+    }
+
+    /// The next time after `time`, at the same local time of day.
+    fn next(&self, time: DateTime<Local>) -> DateTime<Local> {
+        let days = match self {
+            Repeat::Daily => 1,
+            Repeat::Weekly => 7,
+        };
+        time.checked_add_days(Days::new(days))
+            .unwrap_or(time + TimeDelta::days(days as i64))
+    }
+}
+
+/// A timer, alarm or reminder, announced by the assistant when it is due.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScheduledItem {
+    pub id: u64,
+    pub kind: ScheduleKind,
+    /// When the item fires next.
+    pub due: DateTime<Local>,
+    pub repeat: Option<Repeat>,
+    /// What the item is for: the duration of a timer or the text of a reminder.
+    pub label: String,
+}
+
+impl ScheduledItem {
+    /// What the assistant says when the item is due.
+    pub fn announcement(&self) -> String {
+        match self.kind {
+            ScheduleKind::Timer => format!("Your timer for {} is done.", self.label),
+            ScheduleKind::Alarm => format!("It's {}. This is your alarm.", time_of_day(self.due)),
+            ScheduleKind::Reminder => format!("This is your reminder to {}.", self.label),
+        }
+    }
+
+    /// A short description, used when listing the schedule.
+    pub fn describe(&self) -> String {
+        let repeat = match self.repeat {
+            Some(Repeat::Daily) => " every day",
+            Some(Repeat::Weekly) => " every week",
+            None => "",
+        };
+        match self.kind {
+            ScheduleKind::Timer => format!(
+                "A timer for {}, done at {}",
+                self.label,
+                time_of_day(self.due)
+            ),
+            ScheduleKind::Alarm => format!("An alarm at {}{repeat}", time_of_day(self.due)),
+            ScheduleKind::Reminder => format!(
+                "A reminder to {} at {}{repeat}",
+                self.label,
+                time_of_day(self.due)
+            ),
+        }
+    }
+
+    /// The item as an iCalendar event, see [Schedule::to_ics].
+    fn ics_event(&self, stamp: &str) -> String {
+        let summary = match self.kind {
+            ScheduleKind::Timer => format!("Timer for {}", self.label),
+            ScheduleKind::Alarm => "Alarm".to_string(),
+            ScheduleKind::Reminder => format!("Reminder: {}", self.label),
+        };
+        let mut lines = vec![
+            "BEGIN:VEVENT".to_string(),
+            format!("UID:{}@raspberry", self.id),
+            format!("DTSTAMP:{stamp}"),
+            format!("DTSTART:{}", ics_time(self.due)),
+            format!("SUMMARY:{}", ics_escape(&summary)),
+        ];
+        if let Some(repeat) = self.repeat {
+            let frequency = match repeat {
+                Repeat::Daily => "DAILY",
+                Repeat::Weekly => "WEEKLY",
+            };
+            lines.push(format!("RRULE:FREQ={frequency}"));
+        }
+        lines.extend([
+            "BEGIN:VALARM".to_string(),
+            "ACTION:DISPLAY".to_string(),
+            "TRIGGER:PT0S".to_string(),
+            format!("DESCRIPTION:{}", ics_escape(&summary)),
+            "END:VALARM".to_string(),
+            "END:VEVENT".to_string(),
+        ]);
+        lines.iter().map(|line| ics_fold(line)).collect()
+    }
+
+    fn encode(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.kind.name(),
+            self.due.to_rfc3339(),
+            self.repeat.map_or("", |repeat| repeat.name()),
+            self.label
+        )
+    }
+
+    fn decode(id: u64, value: &str) -> Option<Self> {
+        let mut fields = value.splitn(4, '\t');
+        let kind = ScheduleKind::from_name(fields.next()?)?;
+        let due = DateTime::parse_from_rfc3339(fields.next()?)
+            .ok()?
+            .with_timezone(&Local);
+        let repeat = match fields.next()? {
+            "" => None,
+            name => Some(Repeat::from_name(name)?),
+        };
+        Some(Self {
+            id,
+            kind,
+            due,
+            repeat,
+            label: fields.next()?.to_string(),
+        })
+    }
+}
+
+/// The timers, alarms and reminders of the assistant, kept in the storage of the assistant so
+/// they survive restarts. Items that became due while the assistant wasn't running are announced
+/// once it starts listening. See [crate::Assistant::schedule_mut].
+pub struct Schedule {
+    storage: Option<Arc<dyn Storage>>,
+    items: BTreeMap<u64, ScheduledItem>,
+    next_id: u64,
+}
+
+impl Schedule {
+    /// Load the schedule from the storage. The assistant loads its own when it starts, this is
+    /// for reading the schedule elsewhere, e.g. on another thread.
+    pub fn load(storage: Option<Arc<dyn Storage>>) -> Result<Self, StorageError> {
+        let mut items = BTreeMap::new();
+        if let Some(storage) = &storage {
+            for key in storage.keys(SCHEDULE_NAMESPACE)? {
+                let Ok(id) = key.parse() else { continue };
+                let Some(value) = storage.get(SCHEDULE_NAMESPACE, &key)? else {
+                    continue;
+                };
+                match ScheduledItem::decode(id, &String::from_utf8_lossy(&value)) {
+                    Some(item) => _ = items.insert(id, item),
+                    None => warn!("Skipping invalid scheduled item {key}"),
+                }
+            }
+        }
+        let next_id = items.keys().next_back().map_or(0, |id| id + 1);
+        Ok(Self {
+            storage,
+            items,
+            next_id,
+        })
+    }
+
+    /// Schedule an item and return its id.
+    pub fn add(
+        &mut self,
+        kind: ScheduleKind,
+        due: DateTime<Local>,
+        repeat: Option<Repeat>,
+        label: impl Into<String>,
+    ) -> Result<u64, StorageError> {
+        let item = ScheduledItem {
+            id: self.next_id,
+            kind,
+            due,
+            repeat,
+            label: label.into(),
+        };
+        self.save(&item)?;
+        self.next_id += 1;
+        self.items.insert(item.id, item);
+        Ok(self.next_id - 1)
+    }
+
+    /// Remove the item, returning it if it existed.
+    pub fn cancel(&mut self, id: u64) -> Result<Option<ScheduledItem>, StorageError> {
+        if let Some(storage) = &self.storage {
+            storage.remove(SCHEDULE_NAMESPACE, &key(id))?;
+        }
+        Ok(self.items.remove(&id))
+    }
+
+    /// The scheduled items, the next one to fire first.
+    pub fn items(&self) -> Vec<&ScheduledItem> {
+        let mut items: Vec<_> = self.items.values().collect();
+        items.sort_by_key(|item| item.due);
+        items
+    }
+
+    /// The next item of the kind to fire.
+    pub fn next_of_kind(&self, kind: ScheduleKind) -> Option<&ScheduledItem> {
+        self.items
+            .values()
+            .filter(|item| item.kind == kind)
+            .min_by_key(|item| item.due)
+    }
+
+    /// Remove the items due at `now` and return them. Repeating items are moved to their next
+    /// time after `now` instead, so an alarm missed while the assistant was off fires only once.
+    pub(crate) fn take_due(&mut self, now: DateTime<Local>) -> Vec<ScheduledItem> {
+        let due: Vec<u64> = self
+            .items
+            .values()
+            .filter(|item| item.due <= now)
+            .map(|item| item.id)
+            .collect();
+        let mut fired = Vec::new();
+        for id in due {
+            let Some(mut item) = self.items.remove(&id) else {
+                continue;
+            };
+            fired.push(item.clone());
+            let result = match item.repeat {
+                Some(repeat) => {
+                    while item.due <= now {
+                        item.due = repeat.next(item.due);
+                    }
+                    let result = self.save(&item);
+                    self.items.insert(id, item);
+                    result
+                }
+                None => self.storage.as_ref().map_or(Ok(()), |storage| {
+                    storage.remove(SCHEDULE_NAMESPACE, &key(id))
+                }),
+            };
+            if let Err(e) = result {
+                warn!("Failed to update scheduled item {id}: {e}");
+            }
+        }
+        fired
+    }
+
+    /// The scheduled items as an iCalendar feed, so calendars and phones can show them.
+    /// `now` is the time the feed is created at.
+    pub fn to_ics(&self, now: DateTime<Local>) -> String {
+        let stamp = ics_time(now);
+        let mut ics = String::from(
+            "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nPRODID:-//raspberry//assistant//EN\r\n",
+        );
+        for item in self.items() {
+            ics.push_str(&item.ics_event(&stamp));
+        }
+        ics.push_str("END:VCALENDAR\r\n");
+        ics
+    }
+
+    fn save(&self, item: &ScheduledItem) -> Result<(), StorageError> {
+        match &self.storage {
+            Some(storage) => {
+                storage.set(SCHEDULE_NAMESPACE, &key(item.id), item.encode().as_bytes())
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Padded so that the keys sort like the ids.
+fn key(id: u64) -> String {
+    format!("{id:020}")
+}
+
+fn ics_time(time: DateTime<Local>) -> String {
+    time.with_timezone(&Utc)
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+/// End the content line with CRLF, folding it so that no line is longer than 75 bytes.
+fn ics_fold(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len() + 2);
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
+
+fn time_of_day(time: DateTime<Local>) -> String {
+    time.format("%-H:%M").to_string()
+}
+
+const TIMER_INTENT: &str = "set timer";
+const ALARM_INTENT: &str = "set alarm";
+const DAILY_ALARM_INTENT: &str = "set daily alarm";
+const REMINDER_INTENT: &str = "set reminder";
+const CANCEL_INTENT: &str = "cancel scheduled";
+const LIST_INTENT: &str = "list scheduled";
+
+/// A [Skill] to set, cancel and list timers, alarms and reminders by voice, kept in the
+/// [Schedule] of the assistant.
+#[derive(Default)]
+pub struct ScheduleSkill;
+
+impl Skill for ScheduleSkill {
+    fn name(&self) -> &str {
+        "schedule"
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        let duration = Slot::new("duration", SlotKind::Duration);
+        let time = Slot::new("time", SlotKind::FreeText);
+        let kinds = [
+            ScheduleKind::Timer,
+            ScheduleKind::Alarm,
+            ScheduleKind::Reminder,
+        ];
+        let kind = Slot::new(
+            "kind",
+            SlotKind::Entity(kinds.iter().map(|kind| kind.name().to_string()).collect()),
+        );
+        vec![
+            IntentSpec::with_slots(
+                TIMER_INTENT,
+                examples(&["set a timer for {duration}", "start a timer for {duration}"]),
+                vec![duration.clone()],
+            ),
+            IntentSpec::with_slots(
+                ALARM_INTENT,
+                examples(&["set an alarm for {time}", "wake me up at {time}"]),
+                vec![time.clone()],
+            ),
+            IntentSpec::with_slots(
+                DAILY_ALARM_INTENT,
+                examples(&[
+                    "set a daily alarm for {time}",
+                    "wake me up every day at {time}",
+                ]),
+                vec![time],
+            ),
+            IntentSpec::with_slots(
+                REMINDER_INTENT,
+                examples(&[
+                    "remind me to {text} in {duration}",
+                    "in {duration} remind me to {text}",
+                ]),
+                vec![Slot::new("text", SlotKind::FreeText), duration],
+            ),
+            IntentSpec::with_slots(
+                CANCEL_INTENT,
+                examples(&["cancel the {kind}", "stop the {kind}", "delete my {kind}"]),
+                vec![kind],
+            ),
+            IntentSpec::new(
+                LIST_INTENT,
+                examples(&[
+                    "what timers do i have",
+                    "list my alarms",
+                    "what reminders do i have",
+                ]),
+            ),
+        ]
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        let now = ctx.clock().local_now();
+        let response = match query.intent {
+            Some(TIMER_INTENT) => match query.slots.get("duration") {
+                Some(SlotValue::Duration(duration)) => match after(now, *duration) {
+                    Some(due) => {
+                        let label = describe_duration(*duration);
+                        ctx.schedule()
+                            .add(ScheduleKind::Timer, due, None, label.clone())
+                            .map(|_| format!("Timer set for {label}."))
+                    }
+                    None => Ok("That's too long for a timer.".to_string()),
+                },
+                _ => Ok("For how long?".to_string()),
+            },
+            Some(intent @ (ALARM_INTENT | DAILY_ALARM_INTENT)) => {
+                let repeat = (intent == DAILY_ALARM_INTENT).then_some(Repeat::Daily);
+                match query.slots.get("time") {
+                    Some(SlotValue::Text(text)) => match next_time_of_day(text, now) {
+                        Some(due) => ctx
+                            .schedule()
+                            .add(ScheduleKind::Alarm, due, repeat, "")
+                            .map(|_| format!("Alarm set for {}.", time_of_day(due))),
+                        None => Ok(format!("I don't know what time {text} is.")),
+                    },
+                    _ => Ok("For what time?".to_string()),
+                }
+            }
+            Some(REMINDER_INTENT) => match (query.slots.get("text"), query.slots.get("duration")) {
+                (Some(SlotValue::Text(text)), Some(SlotValue::Duration(duration))) => {
+                    match after(now, *duration) {
+                        Some(due) => ctx
+                            .schedule()
+                            .add(ScheduleKind::Reminder, due, None, text.clone())
+                            .map(|_| {
+                                format!("I'll remind you in {}.", describe_duration(*duration))
+                            }),
+                        None => Ok("That's too far away for a reminder.".to_string()),
+                    }
+                }
+                _ => Ok("What should I remind you of, and when?".to_string()),
+            },
+            Some(CANCEL_INTENT) => {
+                let kind = match query.slots.get("kind") {
+                    Some(SlotValue::Entity(name)) => ScheduleKind::from_name(name),
+                    _ => None,
+                };
+                let next = kind.and_then(|kind| ctx.schedule().next_of_kind(kind).cloned());
+                match (kind, next) {
+                    (_, Some(item)) => ctx
+                        .schedule()
+                        .cancel(item.id)
+                        .map(|_| format!("Cancelled: {}.", item.describe())),
+                    (Some(kind), None) => Ok(format!("There is no {} to cancel.", kind.name())),
+                    (None, None) => Ok("What should I cancel?".to_string()),
+                }
+            }
+            Some(LIST_INTENT) => {
+                let items = ctx.schedule().items();
+                Ok(if items.is_empty() {
+                    "You have nothing scheduled.".to_string()
+                } else {
+                    items
+                        .iter()
+                        .map(|item| format!("{}.", item.describe()))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                })
+            }
+            _ => return,
+        };
+        let response = response.unwrap_or_else(|e| {
+            warn!("Failed to update the schedule: {e}");
+            "Sorry, I couldn't save that.".to_string()
+        });
+        _ = ctx.speak(response);
+    }
+}
+
+pub(crate) fn after(now: DateTime<Local>, duration: Duration) -> Option<DateTime<Local>> {
+    now.checked_add_signed(TimeDelta::from_std(duration).ok()?)
+}
+
+/// Describe a duration the way it would be said, e.g. "1 hour and 30 minutes".
+fn describe_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let parts: Vec<String> = [
+        (seconds / 3600, "hour"),
+        (seconds / 60 % 60, "minute"),
+        (seconds % 60, "second"),
+    ]
+    .into_iter()
+    .filter(|(value, _)| *value > 0)
+    .map(|(value, unit)| match value {
+        1 => format!("1 {unit}"),
+        _ => format!("{value} {unit}s"),
+    })
+    .collect();
+    match parts.as_slice() {
+        [] => "0 seconds".to_string(),
+        [only] => only.clone(),
+        [rest @ .., last] => format!("{} and {last}", rest.join(", ")),
+    }
+}
+
+/// The next time the spoken time of day comes after `now`, e.g. "seven thirty", "six a m" or
+/// "7:45 pm". Without a period, the earliest of the morning and evening times is used.
+fn next_time_of_day(text: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let mut words: Vec<String> = text
+        .split_whitespace()
+        .flat_map(|word| word.split(':'))
+        .map(|word| word.trim_matches('.').to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect();
+
+    let mut period = None;
+    for (suffix, is_pm) in [
+        (&["a", "m"][..], false),
+        (&["am"], false),
+        (&["in", "the", "morning"], false),
+        (&["p", "m"], true),
+        (&["pm"], true),
+        (&["in", "the", "afternoon"], true),
+        (&["in", "the", "evening"], true),
+        (&["at", "night"], true),
+        (&["o'clock"], false),
+    ] {
+        if words.ends_with(&suffix.iter().map(|w| w.to_string()).collect::<Vec<_>>()) {
+            words.truncate(words.len() - suffix.len());
+            if suffix != ["o'clock"] {
+                period = Some(is_pm);
+            }
+        }
+    }
+
+    let (hour, minutes) = words.split_first()?;
+    let hour = parse_number(std::slice::from_ref(hour))?;
+    let minute = match minutes {
+        [] => 0.,
+        minutes => parse_number(minutes)?,
+    };
+    if hour.fract() != 0. || minute.fract() != 0. || !(0. ..60.).contains(&minute) {
+        return None;
+    }
+    let (hour, minute) = (hour as u32, minute as u32);
+    let hours = match period {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(false) => vec![hour % 12],
+        Some(true) => vec![hour % 12 + 12],
+        None if (1..=12).contains(&hour) => vec![hour % 12, hour % 12 + 12],
+        None => vec![hour],
+    };
+
+    hours
+        .into_iter()
+        .filter_map(|hour| NaiveTime::from_hms_opt(hour, minute, 0))
+        .filter_map(|time| {
+            let today = now
+                .date_naive()
+                .and_time(time)
+                .and_local_timezone(Local)
+                .earliest()?;
+            if today > now {
+                Some(today)
+            } else {
+                today.checked_add_days(Days::new(1))
+            }
+        })
+        .min()
+}
@@ -0,0 +1,120 @@
+use std::io;
+use thiserror::Error;
+
+/// The real-time priority and the cores of a thread. The default leaves the thread as it is.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ThreadScheduling {
+    /// `SCHED_FIFO` priority, from 1 to 99. Needs `CAP_SYS_NICE` or an `rtprio` limit.
+    pub realtime_priority: Option<i32>,
+    /// The cores the thread may run on, all of them if empty.
+    pub cores: Vec<usize>,
+}
+
+/// How the threads of the assistant are scheduled, see
+/// [crate::AssistantConfig::set_scheduling].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SchedulingConfig {
+    /// The audio callback, which feeds the wakeword engine and speech recognition.
+    pub audio: ThreadScheduling,
+    /// The thread starting the assistant, which computes the intent embeddings and should be the
+    /// one calling [crate::Assistant::listen].
+    pub inference: ThreadScheduling,
+}
+
+impl SchedulingConfig {
+    /// The audio callback at real-time priority on the first core, inference on the others.
+    pub fn dedicated_audio_core() -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self {
+            audio: ThreadScheduling {
+                realtime_priority: Some(80),
+                cores: vec![0],
+            },
+            inference: ThreadScheduling {
+                realtime_priority: None,
+                cores: (1..cores).collect(),
+            },
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SchedulingError {
+    #[error("Failed to set the real-time priority")]
+    Priority(#[source] io::Error),
+    #[error("Failed to set the CPU affinity")]
+    Affinity(#[source] io::Error),
+    #[error("Thread scheduling is only supported on Linux")]
+    Unsupported,
+}
+
+impl ThreadScheduling {
+    /// Apply to the calling thread.
+    pub fn apply(&self) -> Result<(), SchedulingError> {
+        if let Some(priority) = self.realtime_priority {
+            set_realtime_priority(priority)?;
+        }
+        if !self.cores.is_empty() {
+            set_affinity(&self.cores)?;
+        }
+        Ok(())
+    }
+
+    /// Apply as much as possible to the calling thread, logging what failed, e.g. because the
+    /// process isn't allowed to use real-time priorities.
+    pub(crate) fn apply_or_warn(&self, thread: &str) {
+        if let Some(priority) = self.realtime_priority {
+            if let Err(e) = set_realtime_priority(priority) {
+                warn!("Running the {thread} thread without real-time priority: {e:?}");
+            }
+        }
+        if !self.cores.is_empty() {
+            if let Err(e) = set_affinity(&self.cores) {
+                warn!("Running the {thread} thread on all cores: {e:?}");
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_realtime_priority(priority: i32) -> Result<(), SchedulingError> {
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    let result =
+        unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) };
+    if result != 0 {
+        return Err(SchedulingError::Priority(io::Error::from_raw_os_error(
+            result,
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn set_affinity(cores: &[usize]) -> Result<(), SchedulingError> {
+    let mut set = unsafe { std::mem::zeroed::<libc::cpu_set_t>() };
+    for &core in cores {
+        if core >= libc::CPU_SETSIZE as usize {
+            return Err(SchedulingError::Affinity(io::Error::from(
+                io::ErrorKind::InvalidInput,
+            )));
+        }
+        unsafe { libc::CPU_SET(core, &mut set) };
+    }
+    // 0 is the calling thread
+    if unsafe { libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) } != 0 {
+        return Err(SchedulingError::Affinity(io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_realtime_priority(_priority: i32) -> Result<(), SchedulingError> {
+    Err(SchedulingError::Unsupported)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_affinity(_cores: &[usize]) -> Result<(), SchedulingError> {
+    Err(SchedulingError::Unsupported)
+}
@@ -0,0 +1,336 @@
+use cpal::Sample;
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+use thiserror::Error;
+
+use crate::{
+    audio::{AudioFormat, AudioSource, AudioTimestamp},
+    events::AssistantEvent,
+};
+
+/// The first bytes of a session archive, ending with the version of the format.
+const MAGIC: &[u8; 8] = b"RSPSESS1";
+
+const AUDIO_RECORD: u8 = 0;
+const EVENT_RECORD: u8 = 1;
+
+/// How many blocks of audio can wait for the thread writing the archive. Blocks are dropped, and
+/// replayed as silence, if it falls further behind.
+const SESSION_QUEUED_BLOCKS: usize = 256;
+/// Blocks allocated up front for the audio thread to fill, like for speech recognition.
+const SESSION_PREALLOCATED_BLOCKS: usize = 16;
+const SESSION_BLOCK_CAPACITY: usize = 8192;
+
+#[derive(Error, Debug)]
+pub enum SessionError {
+    #[error("Failed to access the session archive")]
+    Io(#[from] io::Error),
+    #[error("Not a session archive")]
+    NotAnArchive,
+    #[error("The session archive is corrupted")]
+    Corrupted,
+}
+
+/// What a session archive keeps of an [AssistantEvent]: the results of the pipeline, to compare
+/// a replay of the audio with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SessionEvent {
+    WakewordDetected {
+        name: String,
+        score: f32,
+    },
+    /// The transcript, or `None` if speech recognition failed or timed out.
+    RecognitionFinished(Option<String>),
+    IntentMatched {
+        text: String,
+        score: f32,
+    },
+    Error(String),
+}
+
+impl SessionEvent {
+    /// The part of the event kept in archives, `None` for events that aren't results of the
+    /// pipeline.
+    pub fn from_event(event: &AssistantEvent) -> Option<Self> {
+        Some(match event {
+            AssistantEvent::WakewordDetected(detection) => Self::WakewordDetected {
+                name: detection.name.clone(),
+                score: detection.score,
+            },
+            AssistantEvent::RecognitionFinished(text) => Self::RecognitionFinished(text.clone()),
+            AssistantEvent::IntentMatched { text, score } => Self::IntentMatched {
+                text: text.clone(),
+                score: *score,
+            },
+            AssistantEvent::Error(message) => Self::Error(message.clone()),
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for SessionEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WakewordDetected { name, score } => {
+                write!(f, "wakeword {} (score {:.3})", name, score)
+            }
+            Self::RecognitionFinished(Some(text)) => write!(f, "heard \"{}\"", text),
+            Self::RecognitionFinished(None) => write!(f, "heard nothing"),
+            Self::IntentMatched { text, score } => {
+                write!(f, "matched \"{}\" (score {:.3})", text, score)
+            }
+            Self::Error(message) => write!(f, "error: {}", message),
+        }
+    }
+}
+
+/// A [SessionEvent] with how many frames of audio were heard before it, or where the wakeword was
+/// for a detection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedEvent {
+    pub frame: u64,
+    pub event: SessionEvent,
+}
+
+/// A session archive read with [read_session].
+#[derive(Clone, Debug)]
+pub struct Session {
+    pub format: AudioFormat,
+    /// Interleaved samples in the format of the input. Blocks the recorder missed are silence.
+    pub samples: Vec<f32>,
+    pub events: Vec<RecordedEvent>,
+}
+
+enum Record {
+    Audio(AudioTimestamp, Vec<i16>),
+    Event(u64, SessionEvent),
+}
+
+/// Write all the audio of the input and the [SessionEvent]s of the events into a new archive at
+/// `path`, as the assistant runs, see [crate::AssistantConfig::set_session_archive]. The archive
+/// is written by the returned thread until both the input and the sender of the events are
+/// dropped. Events are flushed as they come, so an archive cut off by a crash can still be read.
+pub fn record_session(
+    path: &Path,
+    input: &dyn AudioSource,
+    events: Receiver<AssistantEvent>,
+) -> Result<JoinHandle<()>, SessionError> {
+    let format = input.format();
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(MAGIC)?;
+    writer.write_all(&format.sample_rate.to_le_bytes())?;
+    writer.write_all(&format.channels.to_le_bytes())?;
+    writer.flush()?;
+
+    let (tx, rx) = mpsc::sync_channel(SESSION_QUEUED_BLOCKS);
+    let (free, free_rx) = mpsc::sync_channel(SESSION_QUEUED_BLOCKS);
+    for _ in 0..SESSION_PREALLOCATED_BLOCKS {
+        _ = free.try_send(Vec::with_capacity(SESSION_BLOCK_CAPACITY));
+    }
+    // Events are placed after the audio heard when they are received, or where the wakeword was
+    let heard = Arc::new(AtomicU64::new(0));
+    let audio = tx.clone();
+    let audio_heard = heard.clone();
+    let channels = usize::from(format.channels.max(1));
+    input.subscribe(Box::new(move |data, start| {
+        // Only allocates if all the blocks are queued
+        let mut block: Vec<i16> = free_rx.try_recv().unwrap_or_default();
+        block.clear();
+        block.extend(data.iter().map(|&sample| i16::from_sample(sample)));
+        audio_heard.store(
+            start.frame + (data.len() / channels) as u64,
+            Ordering::Relaxed,
+        );
+        _ = audio.try_send(Record::Audio(start, block));
+    }));
+    thread::spawn(move || {
+        for event in events {
+            let frame = match &event {
+                AssistantEvent::WakewordDetected(detection) => detection.timestamp.map(|t| t.frame),
+                _ => None,
+            };
+            let Some(event) = SessionEvent::from_event(&event) else {
+                continue;
+            };
+            let frame = frame.unwrap_or_else(|| heard.load(Ordering::Relaxed));
+            if tx.send(Record::Event(frame, event)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let path = path.to_path_buf();
+    Ok(thread::spawn(move || {
+        if let Err(e) = write_records(&mut writer, rx, free) {
+            warn!(
+                "Stopped writing the session archive {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }))
+}
+
+fn write_records(
+    writer: &mut BufWriter<File>,
+    records: Receiver<Record>,
+    free: SyncSender<Vec<i16>>,
+) -> io::Result<()> {
+    for record in records {
+        match record {
+            Record::Audio(start, block) => {
+                writer.write_all(&[AUDIO_RECORD])?;
+                writer.write_all(&start.frame.to_le_bytes())?;
+                writer.write_all(&(block.len() as u32).to_le_bytes())?;
+                for sample in &block {
+                    writer.write_all(&sample.to_le_bytes())?;
+                }
+                _ = free.try_send(block);
+            }
+            Record::Event(frame, event) => {
+                writer.write_all(&[EVENT_RECORD])?;
+                writer.write_all(&frame.to_le_bytes())?;
+                write_event(writer, &event)?;
+                writer.flush()?;
+            }
+        }
+    }
+    writer.flush()
+}
+
+fn write_event(writer: &mut impl Write, event: &SessionEvent) -> io::Result<()> {
+    match event {
+        SessionEvent::WakewordDetected { name, score } => {
+            writer.write_all(&[0])?;
+            write_str(writer, name)?;
+            writer.write_all(&score.to_le_bytes())
+        }
+        SessionEvent::RecognitionFinished(text) => {
+            writer.write_all(&[1, u8::from(text.is_some())])?;
+            write_str(writer, text.as_deref().unwrap_or_default())
+        }
+        SessionEvent::IntentMatched { text, score } => {
+            writer.write_all(&[2])?;
+            write_str(writer, text)?;
+            writer.write_all(&score.to_le_bytes())
+        }
+        SessionEvent::Error(message) => {
+            writer.write_all(&[3])?;
+            write_str(writer, message)
+        }
+    }
+}
+
+fn write_str(writer: &mut impl Write, text: &str) -> io::Result<()> {
+    writer.write_all(&(text.len() as u32).to_le_bytes())?;
+    writer.write_all(text.as_bytes())
+}
+
+/// Read an archive written with [crate::AssistantConfig::set_session_archive], e.g. to replay
+/// its audio. A record cut off at the end, by a crash or because the assistant is still writing
+/// it, is ignored.
+pub fn read_session(path: impl AsRef<Path>) -> Result<Session, SessionError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut magic = [0; 8];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|_| SessionError::NotAnArchive)?;
+    if magic != *MAGIC {
+        return Err(SessionError::NotAnArchive);
+    }
+    let format = AudioFormat {
+        sample_rate: u32::from_le_bytes(read_array(&mut reader)?),
+        channels: u16::from_le_bytes(read_array(&mut reader)?),
+    };
+    let mut session = Session {
+        format,
+        samples: Vec::new(),
+        events: Vec::new(),
+    };
+    loop {
+        match read_record(&mut reader, &mut session) {
+            Ok(true) => (),
+            Ok(false) => return Ok(session),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(session),
+            Err(e) if e.kind() == ErrorKind::InvalidData => return Err(SessionError::Corrupted),
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
+
+/// Read the next record into the session, `false` at the end of the archive.
+fn read_record(reader: &mut impl Read, session: &mut Session) -> io::Result<bool> {
+    let mut tag = [0];
+    if reader.read(&mut tag)? == 0 {
+        return Ok(false);
+    }
+    let frame = u64::from_le_bytes(read_array(reader)?);
+    match tag[0] {
+        AUDIO_RECORD => {
+            let len = u32::from_le_bytes(read_array(reader)?) as usize;
+            let mut bytes = vec![0; len * 2];
+            reader.read_exact(&mut bytes)?;
+            // Missed blocks are replayed as silence, so the events stay in place
+            let channels = usize::from(session.format.channels.max(1));
+            let start = frame as usize * channels;
+            if start > session.samples.len() {
+                session.samples.resize(start, 0.);
+            }
+            session.samples.extend(
+                bytes
+                    .chunks_exact(2)
+                    .map(|b| f32::from_sample(i16::from_le_bytes([b[0], b[1]]))),
+            );
+        }
+        EVENT_RECORD => {
+            let event = read_event(reader)?;
+            session.events.push(RecordedEvent { frame, event });
+        }
+        _ => return Err(ErrorKind::InvalidData.into()),
+    }
+    Ok(true)
+}
+
+fn read_event(reader: &mut impl Read) -> io::Result<SessionEvent> {
+    let [kind] = read_array(reader)?;
+    Ok(match kind {
+        0 => SessionEvent::WakewordDetected {
+            name: read_str(reader)?,
+            score: f32::from_le_bytes(read_array(reader)?),
+        },
+        1 => {
+            let [present] = read_array(reader)?;
+            let text = read_str(reader)?;
+            SessionEvent::RecognitionFinished((present != 0).then_some(text))
+        }
+        2 => SessionEvent::IntentMatched {
+            text: read_str(reader)?,
+            score: f32::from_le_bytes(read_array(reader)?),
+        },
+        3 => SessionEvent::Error(read_str(reader)?),
+        _ => return Err(ErrorKind::InvalidData.into()),
+    })
+}
+
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_str(reader: &mut impl Read) -> io::Result<String> {
+    let len = u32::from_le_bytes(read_array(reader)?) as usize;
+    let mut bytes = vec![0; len];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes).map_err(|_| ErrorKind::InvalidData.into())
+}
@@ -0,0 +1,137 @@
+use std::{
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    intents::{IntentRecognizer, IntentRecognizerBuildError, IntentRecognizerError, IntentsConfig},
+    storage::Storage,
+};
+
+/// How far apart the detections of the active and the shadow wakeword engine can be to count as
+/// the same detection.
+const WAKEWORD_MATCH_WINDOW: Duration = Duration::from_secs(1);
+
+/// A decision on which a shadow configuration disagreed with the active one. Shadow
+/// configurations run on the same audio and text as the active ones without affecting the
+/// assistant, so that a new threshold or model can be validated before switching to it. See
+/// [crate::AssistantConfig::set_shadow_wakeword_engine] and
+/// [crate::AssistantConfig::set_shadow_intents].
+#[derive(Clone, Debug, PartialEq)]
+pub enum ShadowDivergence {
+    /// Only one of the engines detected the wakeword.
+    Wakeword {
+        wakeword: String,
+        detected_by_shadow: bool,
+    },
+    /// The recognizers matched different intents for the text, `None` being no match.
+    Intent {
+        text: String,
+        active: Option<String>,
+        shadow: Option<String>,
+    },
+}
+
+/// Pairs up the detections of the active and the shadow wakeword engine.
+#[derive(Default)]
+pub(crate) struct WakewordComparator {
+    // Detections not matched yet, with whether they're from the shadow engine
+    pending: Vec<(String, bool, Instant)>,
+}
+
+impl WakewordComparator {
+    pub(crate) fn compare(
+        &mut self,
+        active: Vec<String>,
+        shadow: Vec<String>,
+        now: Instant,
+    ) -> Vec<ShadowDivergence> {
+        let detections = active
+            .into_iter()
+            .map(|w| (w, false))
+            .chain(shadow.into_iter().map(|w| (w, true)));
+        for (wakeword, detected_by_shadow) in detections {
+            let matching = self
+                .pending
+                .iter()
+                .position(|(w, shadow, _)| *w == wakeword && *shadow != detected_by_shadow);
+            match matching {
+                Some(index) => _ = self.pending.remove(index),
+                None => self.pending.push((wakeword, detected_by_shadow, now)),
+            }
+        }
+
+        let mut divergences = Vec::new();
+        self.pending.retain(|(wakeword, detected_by_shadow, at)| {
+            let expired = now.duration_since(*at) > WAKEWORD_MATCH_WINDOW;
+            if expired {
+                divergences.push(ShadowDivergence::Wakeword {
+                    wakeword: wakeword.clone(),
+                    detected_by_shadow: *detected_by_shadow,
+                });
+            }
+            !expired
+        });
+        divergences
+    }
+}
+
+/// A second set of intents run on every transcript, `I` being the [IntentsConfig] before the
+/// assistant is started and the [IntentRecognizer] after.
+pub(crate) struct ShadowIntents<I, T> {
+    intents: I,
+    eq: fn(&T, &T) -> bool,
+    describe: fn(&T) -> String,
+}
+
+impl<T: PartialEq + Debug> ShadowIntents<IntentsConfig<T>, T> {
+    pub(crate) fn new(config: IntentsConfig<T>) -> Self {
+        Self {
+            intents: config,
+            eq: T::eq,
+            describe: |intent| format!("{:?}", intent),
+        }
+    }
+}
+
+impl<T> ShadowIntents<IntentsConfig<T>, T> {
+    pub(crate) fn set_embedding_cache(&mut self, storage: Arc<dyn Storage>) {
+        self.intents.set_embedding_cache(storage);
+    }
+
+    pub(crate) fn build(
+        self,
+    ) -> Result<ShadowIntents<IntentRecognizer<T>, T>, IntentRecognizerBuildError> {
+        Ok(ShadowIntents {
+            intents: IntentRecognizer::build(self.intents)?,
+            eq: self.eq,
+            describe: self.describe,
+        })
+    }
+}
+
+impl<T> ShadowIntents<IntentRecognizer<T>, T> {
+    /// Compare the intent matched by the active recognizer with the one of the shadow
+    /// recognizer. Texts the shadow recognizer fails to embed are ignored.
+    pub(crate) fn compare(&self, text: &str, active: Option<&T>) -> Option<ShadowDivergence> {
+        let shadow = match self.intents.recognize(text) {
+            Ok(intent) => Some(intent),
+            Err(IntentRecognizerError::ScoreTooLow) => None,
+            Err(
+                IntentRecognizerError::TextEmbeddingError(_)
+                | IntentRecognizerError::NoEmbeddingModel,
+            ) => return None,
+        };
+        let agree = match (active, shadow) {
+            (Some(active), Some(shadow)) => (self.eq)(active, shadow),
+            (None, None) => true,
+            _ => false,
+        };
+        (!agree).then(|| ShadowDivergence::Intent {
+            text: text.to_string(),
+            active: active.map(self.describe),
+            shadow: shadow.map(self.describe),
+        })
+    }
+}
@@ -0,0 +1,95 @@
+/// Accumulators of the portable implementation, enough for the compiler to vectorize the loops.
+const LANES: usize = 8;
+
+/// Scale of [i16_to_f32], the same as the conversion of cpal.
+const I16_SCALE: f32 = 1. / 32768.;
+
+/// Dot product of two vectors of the same length, which is their cosine similarity if both are
+/// normalized with [normalize]. Uses NEON on aarch64 when the CPU has it.
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        // SAFETY: NEON was detected at runtime
+        return unsafe { neon::dot(a, b) };
+    }
+
+    let mut sum = [0.; LANES];
+    let chunks_a = a.chunks_exact(LANES);
+    let chunks_b = b.chunks_exact(LANES);
+    let (rest_a, rest_b) = (chunks_a.remainder(), chunks_b.remainder());
+    for (x, y) in chunks_a.zip(chunks_b) {
+        for i in 0..LANES {
+            sum[i] += x[i] * y[i];
+        }
+    }
+    sum.iter().sum::<f32>() + rest_a.iter().zip(rest_b).map(|(x, y)| x * y).sum::<f32>()
+}
+
+/// Scale the vector to a magnitude of 1. Zero vectors are left as they are.
+pub(crate) fn normalize(vector: &mut [f32]) {
+    let magnitude = dot(vector, vector).sqrt();
+    if magnitude > 0. {
+        vector.iter_mut().for_each(|x| *x /= magnitude);
+    }
+}
+
+/// Convert 16 bit samples to `f32` and append them to the output. Uses NEON on aarch64 when the
+/// CPU has it.
+pub(crate) fn i16_to_f32(input: &[i16], output: &mut Vec<f32>) {
+    let start = output.len();
+    output.resize(start + input.len(), 0.);
+    let output = &mut output[start..];
+
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        // SAFETY: NEON was detected at runtime and the output is as long as the input
+        unsafe { neon::i16_to_f32(input, output) };
+        return;
+    }
+
+    for (output, input) in output.iter_mut().zip(input) {
+        *output = *input as f32 * I16_SCALE;
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod neon {
+    use std::arch::aarch64::*;
+
+    use super::I16_SCALE;
+
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn dot(a: &[f32], b: &[f32]) -> f32 {
+        let len = a.len().min(b.len());
+        let chunks = len / 4;
+        let mut sum = vdupq_n_f32(0.);
+        for i in 0..chunks {
+            let x = vld1q_f32(a.as_ptr().add(i * 4));
+            let y = vld1q_f32(b.as_ptr().add(i * 4));
+            sum = vfmaq_f32(sum, x, y);
+        }
+
+        let mut sum = vaddvq_f32(sum);
+        for i in chunks * 4..len {
+            sum += a[i] * b[i];
+        }
+        sum
+    }
+
+    /// The output has to be at least as long as the input.
+    #[target_feature(enable = "neon")]
+    pub(super) unsafe fn i16_to_f32(input: &[i16], output: &mut [f32]) {
+        let chunks = input.len() / 8;
+        let scale = vdupq_n_f32(I16_SCALE);
+        for i in 0..chunks {
+            let samples = vld1q_s16(input.as_ptr().add(i * 8));
+            let low = vcvtq_f32_s32(vmovl_s16(vget_low_s16(samples)));
+            let high = vcvtq_f32_s32(vmovl_high_s16(samples));
+            vst1q_f32(output.as_mut_ptr().add(i * 8), vmulq_f32(low, scale));
+            vst1q_f32(output.as_mut_ptr().add(i * 8 + 4), vmulq_f32(high, scale));
+        }
+        for i in chunks * 8..input.len() {
+            output[i] = input[i] as f32 * I16_SCALE;
+        }
+    }
+}
@@ -0,0 +1,293 @@
+use ::tts::Tts;
+use std::{collections::HashSet, time::Duration};
+
+use crate::{
+    clock::Clock,
+    conversation::DialogContext,
+    http_cache::HttpCache,
+    normalize::Normalizer,
+    permissions::Capability,
+    profile::SettingsProfile,
+    random::Random,
+    schedule::Schedule,
+    slots::{Slot, SlotValue, SlotValues},
+    speakers::{SpeakerPreferences, Speakers},
+    storage::Storage,
+    tts::{self, TtsError, Utterances},
+    tts_cache::{self, DelayedPhrase, TtsCache},
+    AssistantQuery,
+};
+
+/// A Skill handles a set of intents on its own, e.g. timers or the weather, so that features can
+/// be shared between assistants instead of living in one big match on the query. Skills are
+/// registered with [crate::AssistantConfig::add_skill] and their queries are handled by
+/// [crate::Assistant::listen] without being returned.
+pub trait Skill {
+    /// The name of the skill, which [crate::permissions::Permissions] are given by. The name of
+    /// its type by default.
+    fn name(&self) -> &str {
+        let name = std::any::type_name::<Self>();
+        let name = name.split_once('<').map_or(name, |(name, _)| name);
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
+    /// What the skill needs to do through the [SkillContext]. Only [Capability::Speak] by default.
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak]
+    }
+
+    /// The intents of the skill. Called once, when the skill is added.
+    fn intents(&self) -> Vec<IntentSpec>;
+
+    /// Handle a query for one of the intents. The intent of the query is the name of the
+    /// [IntentSpec].
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>);
+
+    /// Whether the skill needs the internet, so its queries are given to
+    /// [Skill::handle_offline] while the assistant is offline. Enabled with the `offline`
+    /// feature.
+    #[cfg(feature = "offline")]
+    fn needs_network(&self) -> bool {
+        false
+    }
+
+    /// Handle a query while the assistant is offline, e.g. with the last known answer. Says
+    /// [crate::connectivity::OFFLINE_RESPONSE] by default.
+    #[cfg(feature = "offline")]
+    fn handle_offline(&mut self, ctx: &mut SkillContext, _query: &AssistantQuery<'_, str>) {
+        _ = ctx.speak(crate::connectivity::OFFLINE_RESPONSE);
+    }
+}
+
+/// An intent of a [Skill].
+#[derive(Clone, Debug)]
+pub struct IntentSpec {
+    pub name: String,
+    /// Example sentences, or templates if there are slots, see
+    /// [crate::intents::IntentsConfig::add_intent_with_slots].
+    pub examples: Vec<String>,
+    pub slots: Vec<Slot>,
+}
+
+impl IntentSpec {
+    pub fn new(name: impl Into<String>, examples: Vec<String>) -> Self {
+        Self {
+            name: name.into(),
+            examples,
+            slots: Vec::new(),
+        }
+    }
+
+    pub fn with_slots(name: impl Into<String>, templates: Vec<String>, slots: Vec<Slot>) -> Self {
+        Self {
+            name: name.into(),
+            examples: templates,
+            slots,
+        }
+    }
+}
+
+/// What to say while a slow skill handles a query, like "Checking the forecast.", see
+/// [crate::AssistantConfig::set_acknowledgement].
+#[derive(Clone, Debug)]
+pub struct Acknowledgement {
+    template: String,
+    delay: Duration,
+}
+
+impl Acknowledgement {
+    /// `{text}` in the template is replaced by what the user said and `{<slot>}` by the value of a
+    /// slot. Said if the skill hasn't said anything after 500ms.
+    pub fn new(template: impl Into<String>) -> Self {
+        Self {
+            template: template.into(),
+            delay: Duration::from_millis(500),
+        }
+    }
+
+    /// How long the skill has to answer before the acknowledgement is said. It is only delayed
+    /// if it is in the TTS cache, see [crate::AssistantConfig::set_tts_cache], otherwise it is said
+    /// right away.
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay = delay;
+    }
+
+    /// The template, if it doesn't depend on the query and can be cached ahead of time.
+    pub(crate) fn phrase(&self) -> Option<&str> {
+        (!self.template.contains('{')).then_some(self.template.as_str())
+    }
+
+    /// Start saying the acknowledgement of a query, unless the skill answers within the delay.
+    pub(crate) fn start(
+        &self,
+        ctx: &mut SkillContext,
+        text: &str,
+        slots: &SlotValues,
+    ) -> Result<(), TtsError> {
+        let mut phrase = self.template.replace("{text}", text);
+        for (name, value) in slots {
+            let value = match value {
+                SlotValue::Entity(value) | SlotValue::Text(value) => value.clone(),
+                SlotValue::Number(number) => number.to_string(),
+                SlotValue::Duration(_) => continue,
+            };
+            phrase = phrase.replace(&format!("{{{}}}", name), &value);
+        }
+        ctx.acknowledgement = tts_cache::speak_later(
+            ctx.tts.as_deref_mut(),
+            ctx.utterances,
+            ctx.normalizer,
+            ctx.tts_cache,
+            phrase,
+            self.delay,
+        )?;
+        Ok(())
+    }
+}
+
+/// The parts of the assistant a [Skill] can use while handling a query.
+pub struct SkillContext<'a> {
+    pub(crate) tts: Option<&'a mut Tts>,
+    pub(crate) utterances: Option<&'a Utterances>,
+    pub(crate) normalizer: &'a Normalizer,
+    pub(crate) tts_cache: Option<&'a TtsCache>,
+    pub(crate) clock: &'a dyn Clock,
+    pub(crate) random: &'a dyn Random,
+    pub(crate) storage: Option<&'a dyn Storage>,
+    pub(crate) http_cache: &'a HttpCache,
+    #[cfg(feature = "http")]
+    pub(crate) http_client: &'a crate::http::HttpClient,
+    // What the skill being run is allowed to do, everything for middlewares
+    pub(crate) granted: HashSet<Capability>,
+    pub(crate) schedule: &'a mut Schedule,
+    pub(crate) speakers: &'a Speakers,
+    pub(crate) profile: &'a SettingsProfile,
+    #[cfg(feature = "offline")]
+    pub(crate) online: bool,
+    // Waiting for the skill to answer, see Acknowledgement
+    pub(crate) acknowledgement: Option<DelayedPhrase>,
+    // The previous query, and what is said and left pending for this one
+    pub(crate) dialog: &'a DialogContext,
+    pub(crate) said: Vec<String>,
+    pub(crate) pending: Option<String>,
+}
+
+impl SkillContext<'_> {
+    /// Say something, unless [Capability::Speak] was denied.
+    pub fn speak(&mut self, text: impl Into<String>) -> Result<(), TtsError> {
+        if !self.can(Capability::Speak) {
+            warn!("Not speaking, the skill isn't allowed to");
+            return Ok(());
+        }
+        self.finish_acknowledgement();
+        let text = text.into();
+        self.said.push(text.clone());
+        tts_cache::speak(
+            self.tts.as_deref_mut(),
+            self.utterances,
+            self.normalizer,
+            self.tts_cache,
+            text,
+        )
+    }
+
+    /// Cancel the acknowledgement if it wasn't said yet, or let it finish before answering.
+    pub(crate) fn finish_acknowledgement(&mut self) {
+        if let Some(acknowledgement) = self.acknowledgement.take() {
+            acknowledgement.finish();
+        }
+    }
+
+    /// Wait until everything said so far was spoken, e.g. before listening.
+    pub fn finish_speaking(&self) -> Result<(), TtsError> {
+        while tts::is_speaking(self.tts.as_deref())? {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+        Ok(())
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock
+    }
+
+    /// The source of randomness of the assistant, see [crate::AssistantConfig::set_random].
+    pub fn random(&self) -> &dyn Random {
+        self.random
+    }
+
+    /// The storage of the assistant, see [crate::AssistantConfig::set_storage]. `None` without
+    /// [Capability::Storage].
+    pub fn storage(&self) -> Option<&dyn Storage> {
+        self.storage.filter(|_| self.can(Capability::Storage))
+    }
+
+    /// The cache of HTTP responses shared by the skills, see [crate::http_cache::HttpCache].
+    pub fn http_cache(&self) -> &HttpCache {
+        self.http_cache
+    }
+
+    /// The HTTP client shared by the skills, see [crate::AssistantConfig::set_http_client].
+    /// `None` without [Capability::Network].
+    #[cfg(feature = "http")]
+    pub fn http_client(&self) -> Option<&crate::http::HttpClient> {
+        self.can(Capability::Network).then_some(self.http_client)
+    }
+
+    /// The timers, alarms and reminders of the assistant.
+    pub fn schedule(&mut self) -> &mut Schedule {
+        self.schedule
+    }
+
+    /// The previous query and what was answered, or the dialog resumed from another assistant,
+    /// see [crate::Assistant::resume_dialog].
+    pub fn dialog(&self) -> &DialogContext {
+        self.dialog
+    }
+
+    /// What this skill left with [SkillContext::set_pending] when it handled the previous query,
+    /// e.g. the question it asked. Kept when the dialog is handed off to another assistant.
+    pub fn pending(&self) -> Option<&str> {
+        self.pending.as_deref()
+    }
+
+    /// Leave something for the next query, if it is handled by this skill too, see
+    /// [SkillContext::pending].
+    pub fn set_pending(&mut self, state: Option<String>) {
+        self.pending = state;
+    }
+
+    /// The profile of who asked, if a [crate::speakers::SpeakerIdentifier] recognized them.
+    pub fn speaker(&self) -> Option<&str> {
+        self.speakers.current()
+    }
+
+    /// Whether the assistant can reach the internet, see
+    /// [crate::AssistantConfig::set_connectivity_monitor]. Never for a skill without
+    /// [Capability::Network].
+    #[cfg(feature = "offline")]
+    pub fn is_online(&self) -> bool {
+        self.online && self.can(Capability::Network)
+    }
+
+    /// The preferences of who asked, see [crate::AssistantConfig::set_speaker_preferences].
+    pub fn speaker_preferences(&self) -> Option<&SpeakerPreferences> {
+        self.speakers.preferences()
+    }
+
+    /// The settings profile of the assistant, see [crate::Assistant::set_profile].
+    pub fn profile(&self) -> &SettingsProfile {
+        self.profile
+    }
+
+    /// Whether the skill being run was granted the capability, see
+    /// [crate::AssistantConfig::set_permissions].
+    pub fn can(&self, capability: Capability) -> bool {
+        self.granted.contains(&capability)
+    }
+}
+
+/// The id of an intent in the recognizer: one added by the application, or one of a skill.
+pub(crate) enum IntentTarget<T> {
+    App(T),
+    Skill { skill: usize, intent: String },
+}
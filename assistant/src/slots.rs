@@ -0,0 +1,459 @@
+use std::{collections::HashMap, time::Duration};
+
+/// A slot is a named placeholder in an intent example template, such as `{duration}` in
+/// `"set a timer for {duration}"`.
+#[derive(Clone, Debug)]
+pub struct Slot {
+    pub name: String,
+    pub kind: SlotKind,
+}
+
+impl Slot {
+    pub fn new(name: impl Into<String>, kind: SlotKind) -> Self {
+        Self {
+            name: name.into(),
+            kind,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum SlotKind {
+    /// A spoken duration, like "one hour and thirty minutes".
+    Duration,
+    /// A spoken number, like "twenty five" or "two and a half".
+    Number,
+    /// One of a fixed list of names, like the rooms of a house.
+    Entity(Vec<String>),
+    /// Any text.
+    FreeText,
+}
+
+impl SlotKind {
+    /// Values substituted into templates to produce sentences for the embedding model.
+    fn sample_values(&self) -> Vec<String> {
+        match self {
+            SlotKind::Duration => vec!["five minutes".to_string(), "one hour".to_string()],
+            SlotKind::Number => vec!["three".to_string(), "twenty".to_string()],
+            SlotKind::Entity(values) => values.clone(),
+            SlotKind::FreeText => vec!["something".to_string()],
+        }
+    }
+
+    /// The words values of the slot can be made of, for a grammar. Empty for free text, which
+    /// can't be limited.
+    fn vocabulary(&self) -> Vec<String> {
+        let numbers = SMALL_NUMBERS.iter().map(|(word, _)| *word);
+        let words: Vec<&str> = match self {
+            SlotKind::Duration => numbers
+                .chain(["and", "a", "an", "half", "quarter"])
+                .chain(["second", "seconds", "minute", "minutes", "hour", "hours"])
+                .chain(["day", "days"])
+                .collect(),
+            SlotKind::Number => numbers
+                .chain([
+                    "hundred", "thousand", "point", "and", "a", "an", "half", "quarter",
+                ])
+                .collect(),
+            SlotKind::Entity(values) => {
+                return values.iter().flat_map(|value| normalize(value)).collect()
+            }
+            SlotKind::FreeText => Vec::new(),
+        };
+        words.into_iter().map(str::to_string).collect()
+    }
+
+    fn parse(&self, words: &[String]) -> Option<SlotValue> {
+        match self {
+            SlotKind::Duration => parse_duration(words).map(SlotValue::Duration),
+            SlotKind::Number => parse_number(words).map(SlotValue::Number),
+            SlotKind::Entity(values) => {
+                let text = words.join(" ");
+                values
+                    .iter()
+                    .find(|value| normalize(value).join(" ") == text)
+                    .map(|value| SlotValue::Entity(value.clone()))
+            }
+            SlotKind::FreeText => Some(SlotValue::Text(words.join(" "))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SlotValue {
+    Duration(Duration),
+    Number(f64),
+    /// The matching entry of the [SlotKind::Entity] list, as it was declared.
+    Entity(String),
+    Text(String),
+}
+
+pub type SlotValues = HashMap<String, SlotValue>;
+
+enum TemplateToken {
+    Word(String),
+    Slot(usize),
+}
+
+/// A parsed example template. Placeholders refer to slots by name; a placeholder without a
+/// matching slot is treated as a literal word.
+pub(crate) struct Template {
+    tokens: Vec<TemplateToken>,
+}
+
+impl Template {
+    pub(crate) fn parse(template: &str, slots: &[Slot]) -> Self {
+        let tokens = template
+            .split_whitespace()
+            .flat_map(|word| {
+                let slot = word
+                    .strip_prefix('{')
+                    .and_then(|w| w.strip_suffix('}'))
+                    .and_then(|name| slots.iter().position(|slot| slot.name == name));
+                match slot {
+                    Some(index) => vec![TemplateToken::Slot(index)],
+                    None => normalize(word)
+                        .into_iter()
+                        .map(TemplateToken::Word)
+                        .collect(),
+                }
+            })
+            .collect();
+        Self { tokens }
+    }
+
+    /// The words of the template and of the values of its slots.
+    pub(crate) fn vocabulary(&self, slots: &[Slot]) -> Vec<String> {
+        self.tokens
+            .iter()
+            .flat_map(|token| match token {
+                TemplateToken::Word(word) => normalize(word),
+                TemplateToken::Slot(index) => slots[*index].kind.vocabulary(),
+            })
+            .collect()
+    }
+
+    /// Sentences used to embed this template, with every slot replaced by sample values.
+    pub(crate) fn examples(&self, slots: &[Slot]) -> Vec<String> {
+        let samples: Vec<Vec<String>> = slots.iter().map(|s| s.kind.sample_values()).collect();
+        let count = self
+            .tokens
+            .iter()
+            .filter_map(|token| match token {
+                TemplateToken::Slot(index) => Some(samples[*index].len()),
+                TemplateToken::Word(_) => None,
+            })
+            .max()
+            .unwrap_or(1);
+
+        (0..count)
+            .map(|i| {
+                self.tokens
+                    .iter()
+                    .filter_map(|token| match token {
+                        TemplateToken::Word(word) => Some(word.clone()),
+                        TemplateToken::Slot(index) => {
+                            let values = &samples[*index];
+                            values.get(i % values.len().max(1)).cloned()
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect()
+    }
+
+    /// Try to align the template with the text and parse the slot values. The template may
+    /// match anywhere in the text, so words the user says before or after it are ignored.
+    pub(crate) fn extract(&self, text: &str, slots: &[Slot]) -> Option<SlotValues> {
+        let words = normalize(text);
+        (0..=words.len()).find_map(|start| {
+            let mut values = SlotValues::new();
+            self.match_from(0, &words[start..], slots, &mut values)
+                .then_some(values)
+        })
+    }
+
+    fn match_from(
+        &self,
+        token: usize,
+        words: &[String],
+        slots: &[Slot],
+        values: &mut SlotValues,
+    ) -> bool {
+        match self.tokens.get(token) {
+            None => true,
+            Some(TemplateToken::Word(word)) => {
+                words.first() == Some(word)
+                    && self.match_from(token + 1, &words[1..], slots, values)
+            }
+            Some(TemplateToken::Slot(index)) => {
+                let slot = &slots[*index];
+                // Longest first, so that trailing slots take the rest of the sentence
+                (1..=words.len()).rev().any(|len| {
+                    let Some(value) = slot.kind.parse(&words[..len]) else {
+                        return false;
+                    };
+                    if self.match_from(token + 1, &words[len..], slots, values) {
+                        values.insert(slot.name.clone(), value);
+                        true
+                    } else {
+                        false
+                    }
+                })
+            }
+        }
+    }
+}
+
+/// Lowercase words with punctuation removed, the same format Vosk produces.
+pub(crate) fn normalize(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|word| {
+            word.chars()
+                .filter(|c| c.is_alphanumeric() || *c == '\'' || *c == '.')
+                .flat_map(char::to_lowercase)
+                .collect::<String>()
+                .trim_end_matches('.')
+                .to_string()
+        })
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+const SMALL_NUMBERS: [(&str, u32); 29] = [
+    ("zero", 0),
+    ("oh", 0),
+    ("one", 1),
+    ("two", 2),
+    ("three", 3),
+    ("four", 4),
+    ("five", 5),
+    ("six", 6),
+    ("seven", 7),
+    ("eight", 8),
+    ("nine", 9),
+    ("ten", 10),
+    ("eleven", 11),
+    ("twelve", 12),
+    ("thirteen", 13),
+    ("fourteen", 14),
+    ("fifteen", 15),
+    ("sixteen", 16),
+    ("seventeen", 17),
+    ("eighteen", 18),
+    ("nineteen", 19),
+    ("twenty", 20),
+    ("thirty", 30),
+    ("forty", 40),
+    ("fifty", 50),
+    ("sixty", 60),
+    ("seventy", 70),
+    ("eighty", 80),
+    ("ninety", 90),
+];
+
+/// The value of a number word or of digits, like "42" or "2.5". Other text that Rust parses as
+/// a number, like "inf" or "1e30", isn't one.
+fn small_number(word: &str) -> Option<f64> {
+    match SMALL_NUMBERS.iter().find(|(name, _)| *name == word) {
+        Some((_, value)) => Some(*value as f64),
+        None => {
+            let (whole, fraction) = word.split_once('.').unwrap_or((word, "0"));
+            let is_digits =
+                |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+            (is_digits(whole) && is_digits(fraction))
+                .then(|| word.parse().ok())
+                .flatten()
+        }
+    }
+}
+
+/// A ten like "twenty", or the "oh" of "seven oh five", which a digit word can follow.
+fn is_tens(word: &str) -> bool {
+    word == "oh"
+        || SMALL_NUMBERS
+            .iter()
+            .any(|(name, value)| *name == word && *value >= 20)
+}
+
+/// What the last word of a number was, to tell which words can follow it.
+#[derive(Clone, Copy, PartialEq)]
+enum NumberWord {
+    Tens,
+    /// A number below a hundred that nothing can be added to, like "five" or "42".
+    Units,
+}
+
+/// Parse a number spoken in English, e.g. "three hundred and twelve", "two point five",
+/// "a half" or "42". All words have to be part of the number.
+pub fn parse_number(words: &[String]) -> Option<f64> {
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    match words.as_slice() {
+        [] => return None,
+        ["a" | "an"] => return Some(1.),
+        ["half"] | ["a" | "one", "half"] => return Some(0.5),
+        ["a" | "one", "quarter"] => return Some(0.25),
+        _ => (),
+    }
+
+    let mut total = 0.;
+    let mut current = 0.;
+    let mut seen_number = false;
+    // Reset by "hundred" and "thousand", after which a number can follow again
+    let mut last = None;
+    let mut iter = words.iter().enumerate();
+    while let Some((i, word)) = iter.next() {
+        match *word {
+            "and" if seen_number => {
+                // "two and a half"
+                if words[i + 1..] == ["a", "half"] {
+                    return Some(total + current + 0.5);
+                }
+            }
+            "a" | "an" if i == 0 => {
+                current = 1.;
+                seen_number = true;
+                last = Some(NumberWord::Units);
+            }
+            "hundred" => {
+                current = current.max(1.) * 100.;
+                last = None;
+            }
+            "thousand" => {
+                total += current.max(1.) * 1000.;
+                current = 0.;
+                last = None;
+            }
+            "point" if seen_number => {
+                let mut scale = 0.1;
+                for (_, word) in iter.by_ref() {
+                    let digit = small_number(word).filter(|d| *d < 10.)?;
+                    current += digit * scale;
+                    scale /= 10.;
+                }
+            }
+            word => {
+                let value = small_number(word)?;
+                let tens = is_tens(word);
+                // Only "twenty five", not "one two" or "twenty twenty"
+                let digit_word = value < 10. && SMALL_NUMBERS.iter().any(|(name, _)| *name == word);
+                match last {
+                    Some(NumberWord::Units) => return None,
+                    Some(NumberWord::Tens) if tens || !digit_word => return None,
+                    _ => (),
+                }
+                current += value;
+                seen_number = true;
+                last = Some(if tens {
+                    NumberWord::Tens
+                } else {
+                    NumberWord::Units
+                });
+            }
+        }
+    }
+
+    seen_number.then_some(total + current)
+}
+
+fn unit_seconds(word: &str) -> Option<f64> {
+    match word {
+        "second" | "seconds" | "sec" | "secs" => Some(1.),
+        "minute" | "minutes" | "min" | "mins" => Some(60.),
+        "hour" | "hours" => Some(3600.),
+        "day" | "days" => Some(86400.),
+        _ => None,
+    }
+}
+
+/// Parse a duration spoken in English, e.g. "five minutes", "half an hour", "one hour and thirty
+/// minutes" or "a minute and a half". All words have to be part of the duration.
+pub fn parse_duration(words: &[String]) -> Option<Duration> {
+    let mut seconds = 0.;
+    let mut last_unit = None;
+    let mut segment: Vec<String> = Vec::new();
+
+    for word in words {
+        if let Some(unit) = unit_seconds(word) {
+            let mut amount: &[String] = &segment;
+            if amount.first().map(String::as_str) == Some("and") {
+                amount = &amount[1..];
+            }
+            // "half an hour"
+            let value = if amount.len() == 2 && amount[0] == "half" {
+                matches!(amount[1].as_str(), "a" | "an").then_some(0.5)?
+            } else {
+                parse_number(amount)?
+            };
+            seconds += value * unit;
+            last_unit = Some(unit);
+            segment.clear();
+        } else {
+            segment.push(word.clone());
+        }
+    }
+
+    let unit = last_unit?;
+    match segment
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => (),
+        ["and", "a", "half"] => seconds += unit / 2.,
+        _ => return None,
+    }
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(text: &str) -> Vec<String> {
+        normalize(text)
+    }
+
+    #[test]
+    fn parses_numbers() {
+        assert_eq!(parse_number(&words("twenty five")), Some(25.));
+        assert_eq!(parse_number(&words("three hundred and twelve")), Some(312.));
+        assert_eq!(parse_number(&words("two point five")), Some(2.5));
+        assert_eq!(parse_number(&words("oh five")), Some(5.));
+        assert_eq!(parse_number(&words("42")), Some(42.));
+    }
+
+    #[test]
+    fn rejects_numbers_said_one_after_the_other() {
+        assert_eq!(parse_number(&words("one two")), None);
+        assert_eq!(parse_number(&words("twenty twenty")), None);
+        assert_eq!(parse_number(&words("twenty fifteen")), None);
+        assert_eq!(parse_number(&words("twenty five six")), None);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(
+            parse_duration(&words("one hour and thirty minutes")),
+            Some(Duration::from_secs(5400))
+        );
+        assert_eq!(
+            parse_duration(&words("2.5 minutes")),
+            Some(Duration::from_secs(150))
+        );
+    }
+
+    #[test]
+    fn rejects_durations_that_are_not_digits_or_too_long() {
+        for text in [
+            "inf seconds",
+            "nan seconds",
+            "1e30 seconds",
+            "99999999999999999999 days",
+        ] {
+            assert_eq!(parse_duration(&words(text)), None, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+use crate::{
+    http::{HttpClient, HttpError},
+    permissions::Capability,
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{Slot, SlotKind, SlotValue},
+    AssistantQuery,
+};
+
+#[derive(Error, Debug)]
+pub enum SonosError {
+    #[error("Sonos request failed")]
+    Request(#[from] HttpError),
+    #[error("Failed to read the Sonos response")]
+    Response(#[from] std::io::Error),
+    #[error("The Sonos response has no {0}")]
+    Missing(&'static str),
+}
+
+const AV_TRANSPORT: &str = "AVTransport";
+const RENDERING_CONTROL: &str = "RenderingControl";
+/// How often the zone is asked whether an announcement is over.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// Announcements are cut off after this, in case the zone never stops.
+const MAX_ANNOUNCEMENT: Duration = Duration::from_secs(120);
+/// How long an announcement may take to start playing.
+const MAX_START: Duration = Duration::from_secs(5);
+
+/// What a zone was playing, to go back to it after an announcement, see [SonosZone::snapshot].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ZoneSnapshot {
+    /// Like `x-rincon-queue:RINCON_...#0` for the queue, or `x-rincon:RINCON_...` when the zone
+    /// is grouped with another one.
+    pub uri: String,
+    pub metadata: String,
+    /// The track of the queue and the position in it, like `0:01:23`.
+    pub track: String,
+    pub position: String,
+    pub volume: u8,
+    pub playing: bool,
+}
+
+/// A Sonos zone controlled through the UPnP services of one of its players, at its address like
+/// "192.168.1.30".
+#[derive(Clone)]
+pub struct SonosZone {
+    name: String,
+    address: String,
+    announcement_volume: Option<u8>,
+    client: HttpClient,
+}
+
+impl SonosZone {
+    /// A zone called by its name, like "kitchen" for "pause the kitchen Sonos".
+    pub fn new(name: impl Into<String>, address: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            address: address.into(),
+            announcement_volume: None,
+            client: HttpClient::default(),
+        }
+    }
+
+    /// Make the requests with a shared client, see [crate::AssistantConfig::http_client].
+    pub fn set_client(&mut self, client: HttpClient) {
+        self.client = client;
+    }
+
+    /// The volume of the announcements, from 0 to 100. The one of the zone if not set.
+    pub fn set_announcement_volume(&mut self, volume: Option<u8>) {
+        self.announcement_volume = volume;
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Call an action of a UPnP service of the player, returning the SOAP response.
+    fn call(
+        &self,
+        service: &str,
+        action: &str,
+        arguments: &[(&str, &str)],
+    ) -> Result<String, SonosError> {
+        let arguments: String = arguments
+            .iter()
+            .map(|(name, value)| format!("<{0}>{1}</{0}>", name, escape(value)))
+            .collect();
+        let body = format!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+             <s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" \
+             s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body>\
+             <u:{1} xmlns:u=\"urn:schemas-upnp-org:service:{0}:1\">\
+             <InstanceID>0</InstanceID>{2}</u:{1}></s:Body></s:Envelope>",
+            service, action, arguments
+        );
+        let url = format!(
+            "http://{}:1400/MediaRenderer/{}/Control",
+            self.address, service
+        );
+        let soap_action = format!("\"urn:schemas-upnp-org:service:{}:1#{}\"", service, action);
+        Ok(self
+            .client
+            .post(&url)
+            .set("Content-Type", "text/xml; charset=\"utf-8\"")
+            .set("SOAPACTION", &soap_action)
+            .send_string(&body)?
+            .into_string()?)
+    }
+
+    pub fn play(&self) -> Result<(), SonosError> {
+        self.call(AV_TRANSPORT, "Play", &[("Speed", "1")])
+            .map(|_| ())
+    }
+
+    pub fn pause(&self) -> Result<(), SonosError> {
+        self.call(AV_TRANSPORT, "Pause", &[]).map(|_| ())
+    }
+
+    pub fn next(&self) -> Result<(), SonosError> {
+        self.call(AV_TRANSPORT, "Next", &[]).map(|_| ())
+    }
+
+    /// Whether the zone is playing, and not paused, stopped or grouped with a paused zone.
+    pub fn is_playing(&self) -> Result<bool, SonosError> {
+        Ok(self.transport_state()? == "PLAYING")
+    }
+
+    /// Like "PLAYING", "PAUSED_PLAYBACK", "STOPPED" or "TRANSITIONING".
+    fn transport_state(&self) -> Result<String, SonosError> {
+        let response = self.call(AV_TRANSPORT, "GetTransportInfo", &[])?;
+        value(&response, "CurrentTransportState").ok_or(SonosError::Missing("transport state"))
+    }
+
+    pub fn volume(&self) -> Result<u8, SonosError> {
+        let response = self.call(RENDERING_CONTROL, "GetVolume", &[("Channel", "Master")])?;
+        value(&response, "CurrentVolume")
+            .and_then(|volume| volume.parse().ok())
+            .ok_or(SonosError::Missing("volume"))
+    }
+
+    /// Set the volume, from 0 to 100.
+    pub fn set_volume(&self, volume: u8) -> Result<(), SonosError> {
+        let volume = volume.min(100).to_string();
+        self.call(
+            RENDERING_CONTROL,
+            "SetVolume",
+            &[("Channel", "Master"), ("DesiredVolume", &volume)],
+        )
+        .map(|_| ())
+    }
+
+    /// Play the audio at the URL next, without starting it, e.g. a WAV file on a local server.
+    fn set_uri(&self, uri: &str, metadata: &str) -> Result<(), SonosError> {
+        self.call(
+            AV_TRANSPORT,
+            "SetAVTransportURI",
+            &[("CurrentURI", uri), ("CurrentURIMetaData", metadata)],
+        )
+        .map(|_| ())
+    }
+
+    fn seek(&self, unit: &str, target: &str) -> Result<(), SonosError> {
+        self.call(AV_TRANSPORT, "Seek", &[("Unit", unit), ("Target", target)])
+            .map(|_| ())
+    }
+
+    /// What the zone is playing, to [SonosZone::restore] it later.
+    pub fn snapshot(&self) -> Result<ZoneSnapshot, SonosError> {
+        let media = self.call(AV_TRANSPORT, "GetMediaInfo", &[])?;
+        let position = self.call(AV_TRANSPORT, "GetPositionInfo", &[])?;
+        Ok(ZoneSnapshot {
+            uri: value(&media, "CurrentURI").ok_or(SonosError::Missing("URI"))?,
+            metadata: value(&media, "CurrentURIMetaData").unwrap_or_default(),
+            track: value(&position, "Track").unwrap_or_default(),
+            position: value(&position, "RelTime").unwrap_or_default(),
+            volume: self.volume()?,
+            playing: self.is_playing()?,
+        })
+    }
+
+    /// Go back to what the zone was playing, at the same track and position of the queue, and
+    /// into the group it was in.
+    pub fn restore(&self, snapshot: &ZoneSnapshot) -> Result<(), SonosError> {
+        if !snapshot.uri.is_empty() {
+            self.set_uri(&snapshot.uri, &snapshot.metadata)?;
+            // Streams like radio stations can't seek
+            if snapshot.uri.starts_with("x-rincon-queue:") {
+                self.seek("TRACK_NR", &snapshot.track)?;
+                self.seek("REL_TIME", &snapshot.position)?;
+            }
+        }
+        self.set_volume(snapshot.volume)?;
+        if snapshot.playing {
+            self.play()?;
+        }
+        Ok(())
+    }
+
+    /// Play the audio at the URL, like a WAV of what the assistant says, over what the zone is
+    /// playing, then go back to it. Returns once the announcement is over.
+    pub fn announce(&self, url: &str) -> Result<(), SonosError> {
+        let snapshot = self.snapshot()?;
+        let announced = self.play_until_stopped(url);
+        let restored = self.restore(&snapshot);
+        announced.and(restored)
+    }
+
+    fn play_until_stopped(&self, url: &str) -> Result<(), SonosError> {
+        self.set_uri(url, "")?;
+        if let Some(volume) = self.announcement_volume {
+            self.set_volume(volume)?;
+        }
+        self.play()?;
+        let started = Instant::now();
+        let mut playing = false;
+        while started.elapsed() < MAX_ANNOUNCEMENT {
+            thread::sleep(POLL_INTERVAL);
+            match self.transport_state()?.as_str() {
+                "STOPPED" if playing || started.elapsed() > MAX_START => return Ok(()),
+                "PLAYING" | "TRANSITIONING" => playing = true,
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The text of the first element with that name in the XML, unescaped.
+fn value(xml: &str, name: &str) -> Option<String> {
+    if xml.contains(&format!("<{}/>", name)) {
+        return Some(String::new());
+    }
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", name))?;
+    Some(unescape(&xml[start..end]))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+const PAUSE_INTENT: &str = "pause sonos";
+const RESUME_INTENT: &str = "resume sonos";
+const NEXT_INTENT: &str = "next sonos track";
+const VOLUME_INTENT: &str = "sonos volume";
+
+/// A [Skill] controlling the playback of Sonos zones: "pause the kitchen Sonos", "resume the
+/// music", "skip this song in the living room" and "set the bedroom Sonos volume to 20". Without
+/// a zone, all the zones playing are paused and all the paused ones resumed. See
+/// [SonosZone::announce] to say something in a zone.
+pub struct SonosSkill {
+    zones: Vec<SonosZone>,
+}
+
+impl SonosSkill {
+    pub fn new(zones: Vec<SonosZone>) -> Self {
+        Self { zones }
+    }
+
+    /// The zone said, or all of them.
+    fn zones(&self, query: &AssistantQuery<'_, str>) -> Vec<&SonosZone> {
+        match query.slots.get("zone") {
+            Some(SlotValue::Entity(name)) => self
+                .zones
+                .iter()
+                .filter(|zone| zone.name == *name)
+                .collect(),
+            _ => self.zones.iter().collect(),
+        }
+    }
+
+    /// How the zones are called in the responses.
+    fn music(query: &AssistantQuery<'_, str>) -> String {
+        match query.slots.get("zone") {
+            Some(SlotValue::Entity(name)) => format!("the music in the {}", name),
+            _ => "the music".to_string(),
+        }
+    }
+
+    fn pause(&self, query: &AssistantQuery<'_, str>) -> Result<String, SonosError> {
+        let named = query.slots.contains_key("zone");
+        for zone in self.zones(query) {
+            if named || zone.is_playing()? {
+                zone.pause()?;
+            }
+        }
+        Ok(format!("Paused {}.", Self::music(query)))
+    }
+
+    fn resume(&self, query: &AssistantQuery<'_, str>) -> Result<String, SonosError> {
+        let named = query.slots.contains_key("zone");
+        for zone in self.zones(query) {
+            if named || zone.transport_state()? == "PAUSED_PLAYBACK" {
+                zone.play()?;
+            }
+        }
+        Ok(format!("Resumed {}.", Self::music(query)))
+    }
+
+    fn next(&self, query: &AssistantQuery<'_, str>) -> Result<String, SonosError> {
+        let named = query.slots.contains_key("zone");
+        for zone in self.zones(query) {
+            if named || zone.is_playing()? {
+                zone.next()?;
+            }
+        }
+        Ok("Skipped.".to_string())
+    }
+
+    fn set_volume(&self, query: &AssistantQuery<'_, str>) -> Result<String, SonosError> {
+        let Some(SlotValue::Number(level)) = query.slots.get("level") else {
+            return Ok("To what volume?".to_string());
+        };
+        let volume = level.clamp(0., 100.).round() as u8;
+        for zone in self.zones(query) {
+            zone.set_volume(volume)?;
+        }
+        Ok(format!(
+            "Set the volume of {} to {}.",
+            Self::music(query),
+            volume
+        ))
+    }
+}
+
+impl Skill for SonosSkill {
+    fn name(&self) -> &str {
+        "sonos"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak, Capability::Network]
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        let names: Vec<String> = self.zones.iter().map(|zone| zone.name.clone()).collect();
+        // The examples with a zone, and its slot, only if there are zones
+        let intent = |name: &str, all: &[&str], in_zone: &[&str], mut slots: Vec<Slot>| {
+            let mut examples: Vec<String> = all.iter().map(|e| e.to_string()).collect();
+            if !names.is_empty() {
+                examples.extend(in_zone.iter().map(|e| e.to_string()));
+                slots.push(Slot::new("zone", SlotKind::Entity(names.clone())));
+            }
+            IntentSpec::with_slots(name, examples, slots)
+        };
+        vec![
+            intent(
+                PAUSE_INTENT,
+                &["pause the music", "pause the sonos"],
+                &["pause the {zone} sonos", "pause the music in the {zone}"],
+                Vec::new(),
+            ),
+            intent(
+                RESUME_INTENT,
+                &["resume the music", "resume the sonos"],
+                &["resume the {zone} sonos", "resume the music in the {zone}"],
+                Vec::new(),
+            ),
+            intent(
+                NEXT_INTENT,
+                &["skip this song", "play the next song"],
+                &[
+                    "skip this song in the {zone}",
+                    "next song on the {zone} sonos",
+                ],
+                Vec::new(),
+            ),
+            intent(
+                VOLUME_INTENT,
+                &["set the volume of the music to {level}"],
+                &[
+                    "set the {zone} sonos volume to {level}",
+                    "set the volume in the {zone} to {level}",
+                ],
+                vec![Slot::new("level", SlotKind::Number)],
+            ),
+        ]
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        let response = match query.intent {
+            Some(PAUSE_INTENT) => self.pause(query),
+            Some(RESUME_INTENT) => self.resume(query),
+            Some(NEXT_INTENT) => self.next(query),
+            Some(VOLUME_INTENT) => self.set_volume(query),
+            _ => return,
+        };
+        let response = response.unwrap_or_else(|e| {
+            warn!("Failed to control the Sonos: {}", e);
+            "Sorry, I couldn't reach the Sonos.".to_string()
+        });
+        _ = ctx.speak(response);
+    }
+}
@@ -0,0 +1,161 @@
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, Cursor},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+
+use crate::events::{AssistantEvent, StartupProgress};
+
+/// Short sounds played on events so the user knows what the assistant is doing, e.g. a chime
+/// when the wakeword is detected. See [crate::AssistantConfig::set_earcon].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Earcon {
+    WakewordDetected,
+    /// Speech recognition started, including when the user is reprompted.
+    ListeningStarted,
+    IntentMatched,
+    /// Processing a query failed.
+    Error,
+    /// A timer, alarm or reminder is due.
+    ScheduledItemDue,
+    /// The assistant started and is ready to listen.
+    Ready,
+    /// The assistant was shut down. Played without waiting for it, so the application should
+    /// wait a moment before exiting.
+    Stopped,
+}
+
+impl Earcon {
+    /// The earcon played for an event, if any.
+    fn for_event(event: &AssistantEvent) -> Option<Self> {
+        match event {
+            AssistantEvent::WakewordDetected(_) => Some(Earcon::WakewordDetected),
+            AssistantEvent::RecognitionStarted => Some(Earcon::ListeningStarted),
+            AssistantEvent::IntentMatched { .. } => Some(Earcon::IntentMatched),
+            AssistantEvent::Error(_) => Some(Earcon::Error),
+            AssistantEvent::ScheduledItemDue(_) => Some(Earcon::ScheduledItemDue),
+            AssistantEvent::Startup(StartupProgress::Ready) => Some(Earcon::Ready),
+            AssistantEvent::Stopped => Some(Earcon::Stopped),
+            AssistantEvent::RecognitionFinished(_)
+            | AssistantEvent::ShadowDivergence(_)
+            | AssistantEvent::MutedChanged(_)
+            | AssistantEvent::ListeningChanged(_)
+            | AssistantEvent::AudioInput(_)
+            | AssistantEvent::DoNotDisturbChanged(_)
+            | AssistantEvent::StateChanged { .. }
+            | AssistantEvent::GuestModeChanged(_)
+            | AssistantEvent::RuleTriggered(_)
+            | AssistantEvent::AudioCue { .. }
+            | AssistantEvent::Startup(_) => None,
+            #[cfg(feature = "offline")]
+            AssistantEvent::ConnectivityChanged(_) => None,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SoundError {
+    #[error("Failed to open sound file")]
+    Open(#[from] std::io::Error),
+    #[error("Failed to decode sound file")]
+    Decode(#[from] rodio::decoder::DecoderError),
+    #[error("Failed to open audio output")]
+    Output(#[from] rodio::StreamError),
+    #[error("Failed to play sound")]
+    Play(#[from] rodio::PlayError),
+}
+
+/// The earcons set with [crate::AssistantConfig::set_earcon], played on the default output
+/// device. The device is opened on the first sound.
+pub(crate) struct Earcons {
+    paths: HashMap<Earcon, PathBuf>,
+    volume: f32,
+    profile_volume: f32,
+    player: Option<SoundPlayer>,
+}
+
+impl Earcons {
+    pub(crate) fn new(paths: HashMap<Earcon, PathBuf>, volume: f32, profile_volume: f32) -> Self {
+        Self {
+            paths,
+            volume,
+            profile_volume,
+            player: None,
+        }
+    }
+
+    /// The gain applied to the earcons, 1 playing them as they are.
+    pub(crate) fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+
+    /// The [crate::profile::SettingsProfile::earcon_volume] of the settings profile.
+    pub(crate) fn set_profile_volume(&mut self, volume: f32) {
+        self.profile_volume = volume;
+    }
+
+    /// Play the earcon of the event, if one is set. Errors are logged, since a missing sound
+    /// shouldn't fail the query.
+    pub(crate) fn play_for(&mut self, event: &AssistantEvent) {
+        let Some(path) = Earcon::for_event(event).and_then(|e| self.paths.get(&e)) else {
+            return;
+        };
+        let path = path.clone();
+        let volume = self.volume * self.profile_volume;
+        if let Err(e) = self.player().and_then(|player| player.play(&path, volume)) {
+            warn!("Failed to play earcon {}: {}", path.display(), e);
+        }
+    }
+
+    /// Play any sound file as it is, without the gain of the earcons.
+    pub(crate) fn play(&mut self, path: &Path) -> Result<(), SoundError> {
+        self.player()?.play(path, 1.)
+    }
+
+    fn player(&mut self) -> Result<&mut SoundPlayer, SoundError> {
+        let player = match self.player.take() {
+            Some(player) => player,
+            None => SoundPlayer::new()?,
+        };
+        Ok(self.player.insert(player))
+    }
+}
+
+/// Plays sound files on the default output device, mixed with each other.
+pub(crate) struct SoundPlayer {
+    // The output stops when the stream is dropped
+    _stream: OutputStream,
+    handle: OutputStreamHandle,
+}
+
+impl SoundPlayer {
+    pub(crate) fn new() -> Result<Self, SoundError> {
+        let (stream, handle) = OutputStream::try_default()?;
+        Ok(Self {
+            _stream: stream,
+            handle,
+        })
+    }
+
+    /// Start playing a WAV or OGG Vorbis file without waiting for it to finish, with its samples
+    /// multiplied by `volume`.
+    fn play(&self, path: &Path, volume: f32) -> Result<(), SoundError> {
+        let source = Decoder::new(BufReader::new(File::open(path)?))?;
+        self.handle
+            .play_raw(source.convert_samples().amplify(volume))?;
+        Ok(())
+    }
+
+    /// Play a WAV or OGG Vorbis file in memory and wait for it to finish.
+    pub(crate) fn play_and_wait(&self, sound: Arc<[u8]>) -> Result<(), SoundError> {
+        let source = Decoder::new(Cursor::new(sound))?;
+        let sink = Sink::try_new(&self.handle)?;
+        sink.append(source);
+        sink.sleep_until_end();
+        Ok(())
+    }
+}
@@ -0,0 +1,291 @@
+use std::{collections::HashMap, sync::Arc};
+use thiserror::Error;
+use tts::{Tts, Voice};
+use vosk::{Model, Recognizer, SpeakerModel};
+
+#[cfg(feature = "weather")]
+use crate::weather::WeatherLocation;
+use crate::{
+    simd,
+    storage::{Storage, StorageError},
+    stt::STT_SAMPLE_RATE,
+    tts::{set_voice, TtsConfigError, VoiceSelection},
+};
+
+/// The [Storage] namespace of the voices of [SpeakerProfiles].
+const SPEAKER_PROFILES_NAMESPACE: &str = "speaker_profiles";
+
+/// Tells who is speaking from their voice, e.g. by comparing a speaker embedding with the ones
+/// of enrolled profiles. See [crate::AssistantConfig::set_speaker_identifier].
+pub trait SpeakerIdentifier {
+    /// The name of the profile of the speaker of the audio, mono at [crate::stt::STT_SAMPLE_RATE],
+    /// or `None` if it is nobody known.
+    fn identify(&mut self, audio: &[i16]) -> Option<String>;
+
+    /// Learn the voice of a speaker from recordings of them, replacing the profile with the same
+    /// name. See [crate::Assistant::enroll_speaker].
+    fn enroll(&mut self, _name: &str, _recordings: &[Vec<i16>]) -> Result<(), SpeakerError> {
+        Err(SpeakerError::Unsupported)
+    }
+
+    /// The names of the enrolled profiles.
+    fn profiles(&self) -> Result<Vec<String>, SpeakerError> {
+        Ok(Vec::new())
+    }
+
+    /// Forget the voice of a speaker. Returns whether there was a profile with that name.
+    fn remove_profile(&mut self, _name: &str) -> Result<bool, SpeakerError> {
+        Ok(false)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SpeakerError {
+    #[error("No speaker identifier is set")]
+    NoIdentifier,
+    #[error("The speaker identifier can't learn voices")]
+    Unsupported,
+    #[error("Failed to find a voice in the recordings")]
+    NoVoice,
+    #[error("Failed to access the speaker profiles")]
+    Storage(#[from] StorageError),
+}
+
+/// The voices of the enrolled speakers as normalized speaker embeddings, kept in a [Storage].
+pub struct SpeakerProfiles {
+    storage: Arc<dyn Storage>,
+}
+
+impl SpeakerProfiles {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self { storage }
+    }
+
+    pub fn names(&self) -> Result<Vec<String>, StorageError> {
+        self.storage.keys(SPEAKER_PROFILES_NAMESPACE)
+    }
+
+    /// The embeddings of all profiles, with their name.
+    pub fn all(&self) -> Result<Vec<(String, Vec<f32>)>, StorageError> {
+        let mut profiles = Vec::new();
+        for name in self.names()? {
+            if let Some(value) = self.storage.get(SPEAKER_PROFILES_NAMESPACE, &name)? {
+                let embedding = value
+                    .chunks_exact(4)
+                    .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+                    .collect();
+                profiles.push((name, embedding));
+            }
+        }
+        Ok(profiles)
+    }
+
+    pub fn set(&self, name: &str, embedding: &[f32]) -> Result<(), StorageError> {
+        let value: Vec<u8> = embedding.iter().flat_map(|x| x.to_le_bytes()).collect();
+        self.storage.set(SPEAKER_PROFILES_NAMESPACE, name, &value)
+    }
+
+    /// Returns whether there was a profile with that name.
+    pub fn remove(&self, name: &str) -> Result<bool, StorageError> {
+        if self
+            .storage
+            .get(SPEAKER_PROFILES_NAMESPACE, name)?
+            .is_none()
+        {
+            return Ok(false);
+        }
+        self.storage.remove(SPEAKER_PROFILES_NAMESPACE, name)?;
+        Ok(true)
+    }
+}
+
+#[derive(Error, Debug)]
+#[error("Failed to load speaker model")]
+pub struct SpeakerModelLoadFail;
+
+/// Identifies speakers with the speaker vectors of a Vosk speaker model, like
+/// vosk-model-spk-0.4, compared with the ones of [SpeakerProfiles].
+pub struct VoskSpeakerIdentifier {
+    // Vosk only computes speaker vectors while recognizing speech
+    model: Model,
+    speaker_model: SpeakerModel,
+    profiles: SpeakerProfiles,
+    threshold: f32,
+}
+
+impl VoskSpeakerIdentifier {
+    /// `model` can be any Vosk speech model, a small one saves memory since only its features are
+    /// used.
+    pub fn new(
+        model: Model,
+        speaker_model_path: impl Into<String>,
+        profiles: SpeakerProfiles,
+    ) -> Result<Self, SpeakerModelLoadFail> {
+        Ok(Self {
+            model,
+            speaker_model: SpeakerModel::new(speaker_model_path).ok_or(SpeakerModelLoadFail)?,
+            profiles,
+            threshold: 0.6,
+        })
+    }
+
+    /// Set the lowest cosine similarity to a profile for the speaker to be recognized, 0.6 by
+    /// default.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// The normalized speaker vector of the audio, `None` if there is no speech in it.
+    fn embed(&self, audio: &[i16]) -> Option<Vec<f32>> {
+        let mut recognizer =
+            Recognizer::new_with_speaker(&self.model, STT_SAMPLE_RATE as f32, &self.speaker_model)?;
+        recognizer.accept_waveform(audio).ok()?;
+        let mut embedding = recognizer.final_result().single()?.speaker_info?.vector;
+        simd::normalize(&mut embedding);
+        Some(embedding)
+    }
+}
+
+impl SpeakerIdentifier for VoskSpeakerIdentifier {
+    fn identify(&mut self, audio: &[i16]) -> Option<String> {
+        let embedding = self.embed(audio)?;
+        let profiles = self
+            .profiles
+            .all()
+            .inspect_err(|e| warn!("Failed to load the speaker profiles: {}", e))
+            .ok()?;
+        profiles
+            .into_iter()
+            .map(|(name, profile)| (name, simd::dot(&profile, &embedding)))
+            .filter(|(_, similarity)| *similarity >= self.threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(name, _)| name)
+    }
+
+    /// The profile is the mean of the speaker vectors of the recordings.
+    fn enroll(&mut self, name: &str, recordings: &[Vec<i16>]) -> Result<(), SpeakerError> {
+        let embeddings: Vec<Vec<f32>> = recordings
+            .iter()
+            .filter_map(|audio| self.embed(audio))
+            .collect();
+        let mut profile = vec![0.0; embeddings.first().ok_or(SpeakerError::NoVoice)?.len()];
+        for embedding in &embeddings {
+            profile.iter_mut().zip(embedding).for_each(|(p, x)| *p += x);
+        }
+        simd::normalize(&mut profile);
+        Ok(self.profiles.set(name, &profile)?)
+    }
+
+    fn profiles(&self) -> Result<Vec<String>, SpeakerError> {
+        Ok(self.profiles.names()?)
+    }
+
+    fn remove_profile(&mut self, name: &str) -> Result<bool, SpeakerError> {
+        Ok(self.profiles.remove(name)?)
+    }
+}
+
+/// The preferences of a speaker, used for their queries. See
+/// [crate::AssistantConfig::set_speaker_preferences].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SpeakerPreferences {
+    /// The language the speaker prefers, like "it", for skills and applications that can answer
+    /// in it.
+    pub language: Option<String>,
+    /// The TTS voice answering the speaker.
+    pub voice: Option<VoiceSelection>,
+    /// Where weather questions that don't name a place are about.
+    #[cfg(feature = "weather")]
+    pub weather_location: Option<WeatherLocation>,
+    /// The only intent groups the speaker can use, e.g. for children, see
+    /// [crate::AssistantConfig::set_intent_group]. All of them if `None`.
+    pub allowed_groups: Option<Vec<String>>,
+    /// The speech recognizer of the queries of the speaker, by the name it was registered with
+    /// [crate::AssistantConfig::add_speech_recognizer], unless the wakeword has its own. Since
+    /// the speaker is identified from a query, it applies from their next query.
+    pub speech_recognizer: Option<String>,
+}
+
+/// The speaker identifier, the preferences of the known speakers and who spoke last.
+pub(crate) struct Speakers {
+    identifier: Option<Box<dyn SpeakerIdentifier>>,
+    preferences: HashMap<String, SpeakerPreferences>,
+    current: Option<String>,
+    // The voice before a speaker's voice replaced it
+    default_voice: Option<Voice>,
+}
+
+impl Speakers {
+    pub(crate) fn new(
+        identifier: Option<Box<dyn SpeakerIdentifier>>,
+        preferences: HashMap<String, SpeakerPreferences>,
+    ) -> Self {
+        Self {
+            identifier,
+            preferences,
+            current: None,
+            default_voice: None,
+        }
+    }
+
+    /// Identify the speaker of the audio if there is an identifier. Returns whether it is another
+    /// speaker than the last one.
+    pub(crate) fn identify(&mut self, audio: &[i16]) -> bool {
+        let speaker = match &mut self.identifier {
+            Some(identifier) if !audio.is_empty() => identifier.identify(audio),
+            _ => None,
+        };
+        self.set_current(speaker)
+    }
+
+    /// Returns whether it changed.
+    pub(crate) fn set_current(&mut self, speaker: Option<String>) -> bool {
+        std::mem::replace(&mut self.current, speaker) != self.current
+    }
+
+    pub(crate) fn current(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    pub(crate) fn preferences(&self) -> Option<&SpeakerPreferences> {
+        self.preferences.get(self.current.as_deref()?)
+    }
+
+    pub(crate) fn set_preferences(&mut self, speaker: &str, preferences: SpeakerPreferences) {
+        self.preferences.insert(speaker.to_string(), preferences);
+    }
+
+    pub(crate) fn identifier(&mut self) -> Result<&mut dyn SpeakerIdentifier, SpeakerError> {
+        match &mut self.identifier {
+            Some(identifier) => Ok(identifier.as_mut()),
+            None => Err(SpeakerError::NoIdentifier),
+        }
+    }
+
+    /// Switch to the voice of the current speaker, or back to the default one.
+    pub(crate) fn apply_voice(&mut self, tts: &mut Tts) -> Result<(), TtsConfigError> {
+        match self.preferences().and_then(|p| p.voice.clone()) {
+            Some(selection) => {
+                if self.default_voice.is_none() {
+                    self.default_voice = tts.voice()?;
+                }
+                set_voice(tts, &selection)
+            }
+            None => match self.default_voice.take() {
+                Some(voice) => Ok(tts.set_voice(&voice)?),
+                None => Ok(()),
+            },
+        }
+    }
+}
+
+/// The name in an answer to "What's your name?", without the words around it.
+pub(crate) fn spoken_name(answer: &str) -> String {
+    let answer = answer.trim();
+    ["my name is ", "i'm ", "i am ", "it's ", "call me "]
+        .iter()
+        .find_map(|prefix| answer.strip_prefix(prefix))
+        .unwrap_or(answer)
+        .trim()
+        .to_string()
+}
@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+
+/// Longest chunk given to the TTS backend at once.
+const MAX_CHUNK_LEN: usize = 300;
+
+/// Commands that control a long text being read out with [crate::Assistant::speak_long]. They
+/// are recognized by [crate::Assistant::listen] before intent recognition while a long text is
+/// being spoken.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpeechControl {
+    Pause,
+    Resume,
+    /// Skip to the next sentence.
+    Skip,
+    Stop,
+}
+
+/// What the user can say for each [SpeechControl].
+const SPEECH_CONTROL_PHRASES: [(&str, SpeechControl); 15] = [
+    ("pause", SpeechControl::Pause),
+    ("wait", SpeechControl::Pause),
+    ("hold on", SpeechControl::Pause),
+    ("resume", SpeechControl::Resume),
+    ("continue", SpeechControl::Resume),
+    ("go on", SpeechControl::Resume),
+    ("keep going", SpeechControl::Resume),
+    ("skip", SpeechControl::Skip),
+    ("skip ahead", SpeechControl::Skip),
+    ("next", SpeechControl::Skip),
+    ("skip this", SpeechControl::Skip),
+    ("stop", SpeechControl::Stop),
+    ("stop reading", SpeechControl::Stop),
+    ("that's enough", SpeechControl::Stop),
+    ("cancel", SpeechControl::Stop),
+];
+
+impl SpeechControl {
+    pub fn from_text(text: &str) -> Option<Self> {
+        let text = text
+            .trim()
+            .trim_end_matches(['.', '!'])
+            .to_lowercase()
+            .replace("please", "");
+        SPEECH_CONTROL_PHRASES
+            .iter()
+            .find(|(phrase, _)| *phrase == text.trim())
+            .map(|(_, control)| *control)
+    }
+
+    /// The words of all commands, for a grammar, see [crate::Assistant::set_intent_grammar].
+    pub(crate) fn vocabulary() -> impl Iterator<Item = &'static str> {
+        SPEECH_CONTROL_PHRASES
+            .iter()
+            .flat_map(|(phrase, _)| phrase.split(' '))
+            .chain(["please"])
+    }
+}
+
+/// SpeechQueue holds the chunks of a long text. Only one chunk is given to the TTS backend at a
+/// time, so that reading can be paused and the backend's text length limits aren't hit.
+#[derive(Default)]
+pub(crate) struct SpeechQueue {
+    chunks: VecDeque<String>,
+    current: Option<String>,
+    paused: bool,
+}
+
+impl SpeechQueue {
+    pub(crate) fn replace(&mut self, text: &str) {
+        self.chunks = split_chunks(text).into();
+        self.current = None;
+        self.paused = false;
+    }
+
+    /// Whether a long text is being read, even if it's paused.
+    pub(crate) fn is_active(&self) -> bool {
+        self.current.is_some() || !self.chunks.is_empty()
+    }
+
+    /// The chunk to speak next, if the previous one is done and the queue isn't paused.
+    pub(crate) fn next_chunk(&mut self) -> Option<&str> {
+        if self.paused {
+            return None;
+        }
+        self.current = self.chunks.pop_front();
+        self.current.as_deref()
+    }
+
+    /// Pause, reading the interrupted chunk again on resume.
+    pub(crate) fn pause(&mut self) {
+        if let Some(current) = self.current.take() {
+            self.chunks.push_front(current);
+        }
+        self.paused = true;
+    }
+
+    pub(crate) fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub(crate) fn skip(&mut self) {
+        if self.paused {
+            self.chunks.pop_front();
+        }
+        self.current = None;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Split text at sentence boundaries, joining short sentences and splitting sentences longer
+/// than [MAX_CHUNK_LEN] at word boundaries.
+fn split_chunks(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut chunk = String::new();
+    for sentence in split_sentences(text) {
+        for part in split_long(sentence) {
+            if !chunk.is_empty() && chunk.len() + part.len() + 1 > MAX_CHUNK_LEN {
+                chunks.push(std::mem::take(&mut chunk));
+            }
+            if !chunk.is_empty() {
+                chunk.push(' ');
+            }
+            chunk.push_str(part);
+        }
+    }
+    if !chunk.is_empty() {
+        chunks.push(chunk);
+    }
+    chunks
+}
+
+fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_boundary = matches!(c, '.' | '!' | '?' | '\n')
+            && chars.peek().is_none_or(|(_, next)| next.is_whitespace());
+        if at_boundary {
+            let end = i + c.len_utf8();
+            sentences.push(text[start..end].trim());
+            start = end;
+        }
+    }
+    sentences.push(text[start..].trim());
+    sentences.retain(|s| !s.is_empty());
+    sentences
+}
+
+fn split_long(sentence: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = sentence;
+    while rest.len() > MAX_CHUNK_LEN {
+        let mut limit = MAX_CHUNK_LEN;
+        while !rest.is_char_boundary(limit) {
+            limit -= 1;
+        }
+        let split = rest[..limit]
+            .rfind(", ")
+            .map(|i| i + 1)
+            .or_else(|| rest[..limit].rfind(' '))
+            .unwrap_or(limit);
+        parts.push(rest[..split].trim());
+        rest = rest[split..].trim_start();
+    }
+    parts.push(rest);
+    parts
+}
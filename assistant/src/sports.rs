@@ -0,0 +1,364 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use serde::Deserialize;
+use std::{collections::HashSet, time::Duration};
+use thiserror::Error;
+
+use crate::{
+    clock::describe_day,
+    http::{HttpClient, HttpError},
+    http_cache::HttpCache,
+    permissions::Capability,
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{Slot, SlotKind, SlotValue},
+    AssistantQuery,
+};
+
+#[derive(Error, Debug)]
+pub enum SportsError {
+    #[error("Sports request failed")]
+    Request(#[from] HttpError),
+    #[error("Failed to read the sports response")]
+    Response(#[from] std::io::Error),
+}
+
+/// A source of the matches of teams. [TheSportsDb] is the one included, other services can
+/// implement this trait and be given to [SportsSkill::new].
+pub trait SportsProvider {
+    /// The last match of the team that was played.
+    fn last_match(&self, team: &Team) -> Result<Option<Match>, SportsError>;
+
+    /// The next match of the team that hasn't started yet.
+    fn next_match(&self, team: &Team) -> Result<Option<Match>, SportsError>;
+}
+
+/// A followed team, by the name it is called by.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Team {
+    pub name: String,
+    /// The id of the team at the provider.
+    pub id: String,
+}
+
+/// A match, from the side of the team it was asked for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Match {
+    pub opponent: String,
+    pub home: bool,
+    pub date: NaiveDate,
+    /// When it starts, if the time is known.
+    pub start: Option<DateTime<Local>>,
+    /// The goals or points of the team and of the opponent, once played.
+    pub score: Option<(u32, u32)>,
+}
+
+/// The free JSON API of TheSportsDB, for most sports. Teams are its ids, found with
+/// `https://www.thesportsdb.com/api/v1/json/<key>/searchteams.php?t=<team name>`.
+pub struct TheSportsDb {
+    key: String,
+    client: HttpClient,
+    cache: Option<(HttpCache, Duration)>,
+}
+
+impl TheSportsDb {
+    /// `key` is the API key, the free one is `123`.
+    pub fn new(key: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            client: HttpClient::default(),
+            cache: None,
+        }
+    }
+
+    /// Make the requests with a shared client, see [crate::AssistantConfig::http_client].
+    pub fn set_client(&mut self, client: HttpClient) {
+        self.client = client;
+    }
+
+    /// Keep the responses in the cache for `ttl`. Every request is made by default.
+    pub fn set_cache(&mut self, cache: HttpCache, ttl: Duration) {
+        self.cache = Some((cache, ttl));
+    }
+
+    fn events(&self, endpoint: &str, team: &Team) -> Result<Vec<Match>, SportsError> {
+        let request = self
+            .client
+            .get(&format!(
+                "https://www.thesportsdb.com/api/v1/json/{}/{}",
+                self.key, endpoint
+            ))
+            .query("id", &team.id);
+        let fetch =
+            || -> Result<String, SportsError> { Ok(request.clone().call()?.into_string()?) };
+        let body = match &self.cache {
+            Some((cache, ttl)) => cache.get_or_fetch(&request.url()?, *ttl, fetch)?,
+            None => fetch()?,
+        };
+        let events: SportsDbEvents = serde_json::from_str(&body).map_err(std::io::Error::from)?;
+        Ok(events
+            .results
+            .or(events.events)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|event| event.to_match(&team.id))
+            .collect())
+    }
+}
+
+/// The last events are in `results` and the next ones in `events`, both null without any.
+#[derive(Deserialize)]
+struct SportsDbEvents {
+    results: Option<Vec<SportsDbEvent>>,
+    events: Option<Vec<SportsDbEvent>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SportsDbEvent {
+    id_home_team: Option<String>,
+    str_home_team: Option<String>,
+    str_away_team: Option<String>,
+    int_home_score: Option<String>,
+    int_away_score: Option<String>,
+    date_event: Option<NaiveDate>,
+    /// In UTC, without the offset.
+    str_timestamp: Option<String>,
+}
+
+impl SportsDbEvent {
+    fn to_match(&self, team: &str) -> Option<Match> {
+        let home = self.id_home_team.as_deref() == Some(team);
+        let opponent = if home {
+            &self.str_away_team
+        } else {
+            &self.str_home_team
+        };
+        let score = |score: &Option<String>| score.as_deref()?.trim().parse().ok();
+        let score = score(&self.int_home_score).zip(score(&self.int_away_score));
+        let start = self
+            .str_timestamp
+            .as_deref()
+            .and_then(|timestamp| {
+                NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%dT%H:%M:%S").ok()
+            })
+            .map(|start| Utc.from_utc_datetime(&start).with_timezone(&Local));
+        Some(Match {
+            opponent: opponent.clone()?,
+            home,
+            date: start.map(|start| start.date_naive()).or(self.date_event)?,
+            start,
+            score: score.map(|(home_score, away_score)| {
+                if home {
+                    (home_score, away_score)
+                } else {
+                    (away_score, home_score)
+                }
+            }),
+        })
+    }
+}
+
+impl SportsProvider for TheSportsDb {
+    fn last_match(&self, team: &Team) -> Result<Option<Match>, SportsError> {
+        let matches = self.events("eventslast.php", team)?;
+        Ok(matches
+            .into_iter()
+            .filter(|played| played.score.is_some())
+            .max_by_key(|played| played.date))
+    }
+
+    fn next_match(&self, team: &Team) -> Result<Option<Match>, SportsError> {
+        let matches = self.events("eventsnext.php", team)?;
+        Ok(matches.into_iter().min_by_key(|next| next.date))
+    }
+}
+
+fn describe_result(team: &str, played: &Match, today: NaiveDate) -> String {
+    let day = describe_day(played.date, today);
+    match played.score {
+        Some((ours, theirs)) if ours > theirs => format!(
+            "Yes, {} beat {} {} to {} {}.",
+            team, played.opponent, ours, theirs, day
+        ),
+        Some((ours, theirs)) if ours < theirs => format!(
+            "No, {} lost {} to {} against {} {}.",
+            team, ours, theirs, played.opponent, day
+        ),
+        Some((ours, _)) => format!(
+            "{} drew {} all with {} {}.",
+            team, ours, played.opponent, day
+        ),
+        None => format!("{} played {} {}.", team, played.opponent, day),
+    }
+}
+
+fn describe_next(team: &str, next: &Match, today: NaiveDate) -> String {
+    let place = if next.home {
+        format!("at home against {}", next.opponent)
+    } else {
+        format!("away at {}", next.opponent)
+    };
+    let time = next
+        .start
+        .map(|start| format!(" at {}", start.format("%-H:%M")))
+        .unwrap_or_default();
+    format!(
+        "{} play {} {}{}.",
+        team,
+        place,
+        describe_day(next.date, today),
+        time
+    )
+}
+
+const RESULT_INTENT: &str = "team result";
+const NEXT_MATCH_INTENT: &str = "next match";
+
+/// A [Skill] answering "did Arsenal win" with the last result of a followed team, and "when do
+/// Arsenal play next" with its next match. See [MatchDayReminder] to be reminded on the day of
+/// a match. Enabled with the `sports` feature.
+pub struct SportsSkill {
+    provider: Box<dyn SportsProvider>,
+    teams: Vec<Team>,
+}
+
+impl SportsSkill {
+    pub fn new(provider: impl SportsProvider + 'static, teams: Vec<Team>) -> Self {
+        Self {
+            provider: Box::new(provider),
+            teams,
+        }
+    }
+}
+
+impl Skill for SportsSkill {
+    fn name(&self) -> &str {
+        "sports"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak, Capability::Network]
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        if self.teams.is_empty() {
+            return Vec::new();
+        }
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        let team = Slot::new(
+            "team",
+            SlotKind::Entity(self.teams.iter().map(|team| team.name.clone()).collect()),
+        );
+        vec![
+            IntentSpec::with_slots(
+                RESULT_INTENT,
+                examples(&[
+                    "did {team} win",
+                    "how did {team} do",
+                    "what was the score of the {team} game",
+                ]),
+                vec![team.clone()],
+            ),
+            IntentSpec::with_slots(
+                NEXT_MATCH_INTENT,
+                examples(&[
+                    "when do {team} play next",
+                    "when is the next {team} game",
+                    "who do {team} play next",
+                ]),
+                vec![team],
+            ),
+        ]
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        let team = match query.slots.get("team") {
+            Some(SlotValue::Entity(name)) => self.teams.iter().find(|team| team.name == *name),
+            _ => None,
+        };
+        let Some(team) = team else {
+            _ = ctx.speak("Which team?");
+            return;
+        };
+        let today = ctx.clock().local_now().date_naive();
+        let response = match query.intent {
+            Some(RESULT_INTENT) => self.provider.last_match(team).map(|played| match played {
+                Some(played) => describe_result(&team.name, &played, today),
+                None => format!("I don't know of a recent match of {}.", team.name),
+            }),
+            Some(NEXT_MATCH_INTENT) => self.provider.next_match(team).map(|next| match next {
+                Some(next) => describe_next(&team.name, &next, today),
+                None => format!("I don't know when {} play next.", team.name),
+            }),
+            _ => return,
+        };
+        let response = response.unwrap_or_else(|e| {
+            warn!("Failed to get the matches of {}: {}", team.name, e);
+            "Sorry, I couldn't get the scores right now.".to_string()
+        });
+        _ = ctx.speak(response);
+    }
+
+    #[cfg(feature = "offline")]
+    fn needs_network(&self) -> bool {
+        true
+    }
+}
+
+/// Reminds of the matches of the teams on the day they are played, once the time of the
+/// reminder has come: the reminder is returned by [MatchDayReminder::check], to announce with
+/// [crate::remote::RemoteHandle::spawn_check].
+pub struct MatchDayReminder {
+    provider: Box<dyn SportsProvider + Send>,
+    teams: Vec<Team>,
+    time: NaiveTime,
+    checked: Option<NaiveDate>,
+    reminded: HashSet<(String, NaiveDate)>,
+}
+
+impl MatchDayReminder {
+    pub fn new(
+        provider: impl SportsProvider + Send + 'static,
+        teams: Vec<Team>,
+        time: NaiveTime,
+    ) -> Self {
+        Self {
+            provider: Box::new(provider),
+            teams,
+            time,
+            checked: None,
+            reminded: HashSet::new(),
+        }
+    }
+
+    /// The reminder of today's matches, the first time it is called after the time of the
+    /// reminder. Teams whose matches couldn't be checked are tried again on the next call.
+    pub fn check(&mut self, now: DateTime<Local>) -> Option<String> {
+        let today = now.date_naive();
+        if now.time() < self.time || self.checked == Some(today) {
+            return None;
+        }
+        let mut failed = false;
+        let mut reminders = Vec::new();
+        for team in &self.teams {
+            if self.reminded.contains(&(team.id.clone(), today)) {
+                continue;
+            }
+            match self.provider.next_match(team) {
+                Ok(Some(next)) if next.date == today => {
+                    self.reminded.insert((team.id.clone(), today));
+                    reminders.push(describe_next(&team.name, &next, today));
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    warn!("Failed to check the matches of {}: {}", team.name, e);
+                    failed = true;
+                }
+            }
+        }
+        if !failed {
+            self.checked = Some(today);
+            self.reminded.retain(|(_, date)| *date == today);
+        }
+        (!reminders.is_empty()).then(|| format!("It's match day! {}", reminders.join(" ")))
+    }
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+use thiserror::Error;
+
+/// Storage persists the data of the assistant, like caches and schedules, as values under keys
+/// grouped in namespaces. [MemoryStorage] keeps everything in memory and [SqliteStorage], enabled
+/// with the `sqlite` feature, in a database file. Other backends, e.g. a database shared by
+/// several assistants, can implement this trait.
+pub trait Storage: Send + Sync {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError>;
+
+    /// Store the value, replacing any previous value of the key.
+    fn set(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), StorageError>;
+
+    /// Remove the key. Removing a key that doesn't exist isn't an error.
+    fn remove(&self, namespace: &str, key: &str) -> Result<(), StorageError>;
+
+    /// The keys in the namespace, sorted.
+    fn keys(&self, namespace: &str) -> Result<Vec<String>, StorageError>;
+}
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite error")]
+    Sqlite(#[from] rusqlite::Error),
+    /// An error of a custom [Storage] backend.
+    #[error("Storage backend error: {0}")]
+    Backend(String),
+}
+
+/// MemoryStorage keeps the data until it is dropped.
+#[derive(Default)]
+pub struct MemoryStorage {
+    namespaces: Mutex<HashMap<String, BTreeMap<String, Vec<u8>>>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        Ok(self
+            .namespaces
+            .lock()
+            .unwrap()
+            .get(namespace)
+            .and_then(|values| values.get(key).cloned()))
+    }
+
+    fn set(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        self.namespaces
+            .lock()
+            .unwrap()
+            .entry(namespace.to_string())
+            .or_default()
+            .insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> Result<(), StorageError> {
+        if let Some(values) = self.namespaces.lock().unwrap().get_mut(namespace) {
+            values.remove(key);
+        }
+        Ok(())
+    }
+
+    fn keys(&self, namespace: &str) -> Result<Vec<String>, StorageError> {
+        Ok(self
+            .namespaces
+            .lock()
+            .unwrap()
+            .get(namespace)
+            .map(|values| values.keys().cloned().collect())
+            .unwrap_or_default())
+    }
+}
+
+/// SqliteStorage keeps the data in a single table of an SQLite database. Enabled with the
+/// `sqlite` feature.
+#[cfg(feature = "sqlite")]
+pub struct SqliteStorage {
+    connection: Mutex<rusqlite::Connection>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStorage {
+    /// Open the database file, creating it if it doesn't exist.
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, StorageError> {
+        Self::from_connection(rusqlite::Connection::open(path)?)
+    }
+
+    /// Use an open connection, e.g. to an in-memory database.
+    pub fn from_connection(connection: rusqlite::Connection) -> Result<Self, StorageError> {
+        connection.execute(
+            "CREATE TABLE IF NOT EXISTS storage (
+                namespace TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value BLOB NOT NULL,
+                PRIMARY KEY (namespace, key)
+            )",
+            (),
+        )?;
+        Ok(Self {
+            connection: Mutex::new(connection),
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Storage for SqliteStorage {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        use rusqlite::OptionalExtension;
+
+        Ok(self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM storage WHERE namespace = ?1 AND key = ?2",
+                (namespace, key),
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn set(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        self.connection.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO storage (namespace, key, value) VALUES (?1, ?2, ?3)",
+            (namespace, key, value),
+        )?;
+        Ok(())
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> Result<(), StorageError> {
+        self.connection.lock().unwrap().execute(
+            "DELETE FROM storage WHERE namespace = ?1 AND key = ?2",
+            (namespace, key),
+        )?;
+        Ok(())
+    }
+
+    fn keys(&self, namespace: &str) -> Result<Vec<String>, StorageError> {
+        let connection = self.connection.lock().unwrap();
+        let mut statement =
+            connection.prepare("SELECT key FROM storage WHERE namespace = ?1 ORDER BY key")?;
+        let keys = statement
+            .query_map([namespace], |row| row.get(0))?
+            .collect::<Result<_, _>>()?;
+        Ok(keys)
+    }
+}
@@ -1,67 +1,172 @@
-use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    SampleRate, Stream,
+use cpal::Sample;
+use std::{
+    sync::{mpsc, Arc},
+    time::{Duration, Instant},
 };
-use std::{sync::mpsc, time::Instant};
 use thiserror::Error;
-use vosk::{DecodingState, Model, Recognizer};
+#[cfg(feature = "tokio")]
+use tokio::sync::Notify;
+use vosk::{CompleteResult, DecodingState, Model, Recognizer};
 
-/// STTConfig can be used to configure the speech-to-text recognizer. It can be created by calling
-/// [STTConfig::build]. The recognizer can be used by calling [STTSentenceRecognizer::recognize].
-pub struct STTConfig {
-    stream_config: cpal::StreamConfig,
-    input_device: cpal::Device,
+use crate::{
+    audio::{AudioSource, AudioTimestamp, ConsumerId, Resampler},
+    background::Background,
+    clock::{Clock, SystemClock},
+    ring::RingBuffer,
+};
+
+/// A speech-to-text backend. [VoskRecognizer] is used by default, other backends can be given to
+/// [crate::AssistantConfig::set_speech_recognizer].
+pub trait SpeechRecognizer {
+    /// Start recognizing a new sentence. Backends may ignore options they don't support.
+    fn start_session(
+        &self,
+        options: &SessionOptions,
+    ) -> Result<Box<dyn RecognitionSession + '_>, RecognitionError>;
+
+    /// Whether sessions start without waiting for the backend to load, see
+    /// [crate::Assistant::is_ready].
+    fn is_ready(&self) -> bool {
+        true
+    }
 }
 
-#[derive(Error, Debug)]
-pub enum STTConfigError {
-    #[error("Failed to get default input device")]
-    FailedGetDefaultInputDevice,
-    #[error("Failed to get default input config")]
-    FailedGetDefaultInputConfig(#[from] cpal::DefaultStreamConfigError),
-    #[error("Failed to list input configs")]
-    FailedListInputConfigs(#[from] cpal::SupportedStreamConfigsError),
-    #[error("Failed to get a supported input config")]
-    FailedGetSupportedInputConfig,
+/// Hints for a single recognition, see [STTSentenceRecognizer::set_grammar].
+#[derive(Clone, Debug, Default)]
+pub struct SessionOptions {
+    /// Phrases the user is expected to say. Anything else is recognized as "[unk]".
+    pub grammar: Option<Vec<String>>,
+    /// Alternative sentences to decode instead of the ones set in the config of the backend, see
+    /// [Sentence::alternatives].
+    pub max_alternatives: Option<u16>,
+}
+
+/// The state of a single sentence being recognized, created by
+/// [SpeechRecognizer::start_session]. Audio is mono 16 bit PCM at [STT_SAMPLE_RATE], whatever
+/// the format of the [AudioSource]. The end of the sentence is detected by
+/// [STTSentenceRecognizer], so backends that can't detect it themselves can keep returning
+/// [SessionState::Running].
+pub trait RecognitionSession {
+    fn accept_waveform(&mut self, samples: &[i16]) -> SessionState;
+
+    /// What has been recognized so far.
+    fn partial_result(&mut self) -> String {
+        String::new()
+    }
+
+    /// Recognize everything given to the session so far, once the user stopped speaking.
+    fn final_result(&mut self) -> Option<Sentence>;
+}
+
+pub enum SessionState {
+    Running,
+    Finalized(Sentence),
+    Failed,
+}
+
+/// Sample rate of the audio given to a [RecognitionSession]. Captured audio is resampled to it.
+pub const STT_SAMPLE_RATE: u32 = 16000;
+
+/// How many blocks of audio can wait for the thread reading a [RecognitionStream]. Blocks are
+/// dropped if it falls further behind.
+const STT_QUEUED_BLOCKS: usize = 256;
+/// Blocks allocated up front for the audio thread to fill, so it doesn't allocate while the
+/// reader keeps up.
+const STT_PREALLOCATED_BLOCKS: usize = 16;
+const STT_BLOCK_CAPACITY: usize = 4096;
+
+/// STTConfig can be used to configure the Vosk speech-to-text recognizer. The timeout is set on
+/// the recognizer, see [STTSentenceRecognizer::set_timeout].
+#[derive(Clone, Debug, Default)]
+pub struct STTConfig {
+    max_alternatives: u16,
+    words: bool,
+    partial_words: bool,
 }
 
 impl STTConfig {
-    pub fn build() -> Result<Self, STTConfigError> {
-        let host = cpal::default_host();
-        let input_device = host
-            .default_input_device()
-            .ok_or(STTConfigError::FailedGetDefaultInputDevice)?;
-
-        let default_input_config = input_device.default_input_config()?;
-
-        let input_config = if default_input_config.sample_format() == cpal::SampleFormat::I16
-            && default_input_config.channels() == 1
-        {
-            default_input_config
-        } else {
-            // look for any compatible configuration
-            input_device
-                .supported_input_configs()?
-                .find(|sc| {
-                    sc.sample_format() == cpal::SampleFormat::I16
-                        && sc.channels() == 1
-                        && sc.min_sample_rate().0 <= 16000
-                        && 16000 <= sc.max_sample_rate().0
-                })
-                .map(|sc| sc.with_sample_rate(SampleRate(16000)))
-                .ok_or(STTConfigError::FailedGetSupportedInputConfig)?
-        };
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        let stream_config = cpal::StreamConfig {
-            channels: input_config.channels(),
-            sample_rate: input_config.sample_rate(),
-            buffer_size: cpal::BufferSize::Default,
-        };
+    /// Decode up to `max_alternatives` alternative sentences, see [Sentence::alternatives]. 0, the
+    /// default, only decodes the most likely one.
+    pub fn set_max_alternatives(&mut self, max_alternatives: u16) {
+        self.max_alternatives = max_alternatives;
+    }
 
-        Ok(STTConfig {
-            stream_config,
-            input_device,
-        })
+    /// Include word timestamps in the results, see [Sentence::words].
+    pub fn set_words(&mut self, words: bool) {
+        self.words = words;
+    }
+
+    /// Include word timestamps in partial results.
+    pub fn set_partial_words(&mut self, partial_words: bool) {
+        self.partial_words = partial_words;
+    }
+}
+
+/// The default [SpeechRecognizer], using a Vosk model.
+pub struct VoskRecognizer {
+    model: Model,
+    config: STTConfig,
+}
+
+impl VoskRecognizer {
+    pub fn new(model: Model, config: STTConfig) -> Self {
+        Self { model, config }
+    }
+
+    pub fn config_mut(&mut self) -> &mut STTConfig {
+        &mut self.config
+    }
+}
+
+impl SpeechRecognizer for VoskRecognizer {
+    fn start_session(
+        &self,
+        options: &SessionOptions,
+    ) -> Result<Box<dyn RecognitionSession + '_>, RecognitionError> {
+        let mut recognizer = match &options.grammar {
+            Some(grammar) => {
+                let mut grammar = grammar.clone();
+                grammar.push("[unk]".to_string());
+                Recognizer::new_with_grammar(&self.model, STT_SAMPLE_RATE as f32, &grammar)
+            }
+            None => Recognizer::new(&self.model, STT_SAMPLE_RATE as f32),
+        }
+        .ok_or(RecognitionError::FailedCreateRecognizer)?;
+        recognizer.set_max_alternatives(
+            options
+                .max_alternatives
+                .unwrap_or(self.config.max_alternatives),
+        );
+        recognizer.set_words(self.config.words);
+        recognizer.set_partial_words(self.config.partial_words);
+        Ok(Box::new(VoskSession(recognizer)))
+    }
+}
+
+struct VoskSession(Recognizer);
+
+impl RecognitionSession for VoskSession {
+    fn accept_waveform(&mut self, samples: &[i16]) -> SessionState {
+        match self.0.accept_waveform(samples) {
+            Ok(DecodingState::Running) => SessionState::Running,
+            Ok(DecodingState::Finalized) => match Sentence::from_result(self.0.result()) {
+                Some(sentence) => SessionState::Finalized(sentence),
+                None => SessionState::Failed,
+            },
+            Ok(DecodingState::Failed) | Err(_) => SessionState::Failed,
+        }
+    }
+
+    fn partial_result(&mut self) -> String {
+        self.0.partial_result().partial.to_string()
+    }
+
+    fn final_result(&mut self) -> Option<Sentence> {
+        Sentence::from_result(self.0.final_result())
     }
 }
 
@@ -76,85 +181,563 @@ pub fn load_stt_model(path: impl Into<String>) -> Result<Model, STTLoadModelFail
     Model::new(path).ok_or(STTLoadModelFail)
 }
 
+/// A [VoskRecognizer] whose model is loaded on another thread, see
+/// [crate::AssistantConfig::build_in_background]. Sessions wait for the model, and fail if it
+/// couldn't be loaded.
+pub(crate) struct BackgroundVoskRecognizer {
+    recognizer: Background<Option<VoskRecognizer>>,
+}
+
+impl BackgroundVoskRecognizer {
+    pub(crate) fn load(path: String, config: STTConfig) -> Self {
+        let recognizer = Background::spawn(move || match load_stt_model(path) {
+            Ok(model) => Some(VoskRecognizer::new(model, config)),
+            Err(e) => {
+                warn!("{}", e);
+                None
+            }
+        });
+        Self { recognizer }
+    }
+}
+
+impl SpeechRecognizer for BackgroundVoskRecognizer {
+    fn start_session(
+        &self,
+        options: &SessionOptions,
+    ) -> Result<Box<dyn RecognitionSession + '_>, RecognitionError> {
+        match self.recognizer.get() {
+            Some(recognizer) => recognizer.start_session(options),
+            None => Err(RecognitionError::FailedCreateRecognizer),
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        self.recognizer.is_ready()
+    }
+}
+
 #[derive(Debug)]
 pub enum RecognitionResult {
-    Final(String),
+    Final(Sentence),
     Failed,
     Cancelled,
 }
 
+/// The audio given to the backend in a recognition, see
+/// [STTSentenceRecognizer::recognize_with_audio].
+#[derive(Clone, Debug, Default)]
+pub struct RecognizedAudio {
+    /// Mono at [STT_SAMPLE_RATE].
+    pub samples: Vec<i16>,
+    /// Where the first sample is in the stream of the audio source, if speech was heard.
+    pub start: Option<AudioTimestamp>,
+}
+
+/// A recognized sentence.
+#[derive(Clone, Debug, Default)]
+pub struct Sentence {
+    /// The most likely transcription.
+    pub text: String,
+    /// Alternative transcriptions, most likely first. Empty unless
+    /// [STTConfig::set_max_alternatives] is used.
+    pub alternatives: Vec<Alternative>,
+    /// Timestamps of the words in [Sentence::text]. Empty unless [STTConfig::set_words] is used.
+    pub words: Vec<WordTiming>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Alternative {
+    pub text: String,
+    pub confidence: f32,
+}
+
+/// Start and end of a word in seconds, relative to the start of recognition.
+#[derive(Clone, Debug)]
+pub struct WordTiming {
+    pub word: String,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Sentence {
+    fn from_result(result: CompleteResult) -> Option<Self> {
+        match result {
+            CompleteResult::Single(single) => Some(Sentence {
+                text: single.text.to_string(),
+                alternatives: Vec::new(),
+                words: single
+                    .result
+                    .iter()
+                    .map(|w| WordTiming {
+                        word: w.word.to_string(),
+                        start: w.start,
+                        end: w.end,
+                    })
+                    .collect(),
+            }),
+            CompleteResult::Multiple(multiple) => {
+                let best = multiple.alternatives.first()?;
+                Some(Sentence {
+                    text: best.text.to_string(),
+                    words: best
+                        .result
+                        .iter()
+                        .map(|w| WordTiming {
+                            word: w.word.to_string(),
+                            start: w.start,
+                            end: w.end,
+                        })
+                        .collect(),
+                    alternatives: multiple
+                        .alternatives
+                        .iter()
+                        .map(|a| Alternative {
+                            text: a.text.to_string(),
+                            confidence: a.confidence,
+                        })
+                        .collect(),
+                })
+            }
+        }
+    }
+}
+
+/// EndpointConfig controls how [STTSentenceRecognizer] decides that the user finished speaking.
+/// Speech is detected from the loudness of the audio, so that recognition stops shortly after the
+/// user goes quiet instead of waiting for the backend to finalize the sentence.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EndpointConfig {
+    /// RMS level, from 0 to 1, above which audio is considered speech.
+    pub speech_threshold: f32,
+    /// How long the user has to be quiet after speaking for the sentence to end.
+    pub max_silence: Duration,
+    /// The longest a sentence can be, measured from the start of speech.
+    pub max_utterance: Duration,
+    /// Audio kept from before speech was detected and given to the recognizer, so the start of
+    /// the first word isn't lost.
+    pub pre_roll: Duration,
+}
+
+impl Default for EndpointConfig {
+    fn default() -> Self {
+        Self {
+            speech_threshold: 0.02,
+            max_silence: Duration::from_millis(800),
+            max_utterance: Duration::from_secs(15),
+            pre_roll: Duration::from_millis(300),
+        }
+    }
+}
+
+/// Updates sent by [RecognitionStream] while a sentence is recognized.
+#[derive(Debug)]
+pub enum RecognitionUpdate {
+    /// What has been recognized so far. Can change as the user keeps speaking.
+    Partial(String),
+    /// The final result, always the last update.
+    Done(RecognitionResult),
+}
+
 #[derive(Error, Debug)]
 pub enum RecognitionError {
     #[error("Failed to create recognizer")]
     FailedCreateRecognizer,
     #[error("Failed to receive recognition result")]
     FailedReceiveResult,
-    #[error("Failed to play stream")]
-    FailedPlayStream(#[from] cpal::PlayStreamError),
+    #[error("No speech recognizer configured")]
+    NoSpeechRecognizer,
 }
 
 /// STTSentenceRecognizer is used to recognize a sentence from the microphone. It can be created by
 /// calling [STTSentenceRecognizer::new]. The sentence can be recognized by calling
 /// [STTSentenceRecognizer::recognize], which will block until the sentence is recognized. Timeout
-/// is set to 20 seconds.
+/// is set to 20 seconds by default and can be changed with [STTSentenceRecognizer::set_timeout].
+/// The end of the sentence is detected as configured with
+/// [STTSentenceRecognizer::set_endpoint_config].
 pub struct STTSentenceRecognizer<'a> {
-    model: &'a Model,
-    config: &'a STTConfig,
+    recognizer: &'a dyn SpeechRecognizer,
+    input: &'a dyn AudioSource,
+    timeout: Duration,
+    endpoint: EndpointConfig,
+    options: SessionOptions,
+    clock: Arc<dyn Clock>,
 }
 
 impl<'a> STTSentenceRecognizer<'a> {
-    pub fn new(model: &'a Model, config: &'a STTConfig) -> Self {
-        STTSentenceRecognizer { model, config }
+    pub fn new(recognizer: &'a dyn SpeechRecognizer, input: &'a dyn AudioSource) -> Self {
+        STTSentenceRecognizer {
+            recognizer,
+            input,
+            timeout: Duration::from_secs(20),
+            endpoint: EndpointConfig::default(),
+            options: SessionOptions::default(),
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Only recognize the given phrases, e.g. "yes" and "no", see [SessionOptions::grammar].
+    pub fn set_grammar(&mut self, grammar: Option<Vec<String>>) {
+        self.options.grammar = grammar;
+    }
+
+    /// Decode up to this many alternative sentences, see [SessionOptions::max_alternatives].
+    pub fn set_max_alternatives(&mut self, max_alternatives: Option<u16>) {
+        self.options.max_alternatives = max_alternatives;
+    }
+
+    pub fn set_endpoint_config(&mut self, endpoint: EndpointConfig) {
+        self.endpoint = endpoint;
+    }
+
+    /// Set the clock used to measure the timeout.
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Set how long to wait for the user to start speaking before returning
+    /// [RecognitionResult::Cancelled].
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
     }
 
     pub fn recognize(self) -> Result<RecognitionResult, RecognitionError> {
-        let recognizer =
-            Recognizer::new(self.model, 16000.).ok_or(RecognitionError::FailedCreateRecognizer)?;
-
-        let (tx, rx) = mpsc::channel();
-        let stream = init_stream(
-            &self.config.input_device,
-            &self.config.stream_config,
-            tx,
-            recognizer,
-        );
-        stream.play()?;
-
-        let result = rx
-            .recv()
-            .map_err(|_| RecognitionError::FailedReceiveResult)?;
-        drop(stream);
-        Ok(result)
-    }
-}
-
-fn init_stream(
-    device: &cpal::Device,
-    config: &cpal::StreamConfig,
-    tx: mpsc::Sender<RecognitionResult>,
-    mut recognizer: Recognizer,
-) -> Stream {
-    let start_time = Instant::now();
-
-    let error_callback = move |err| {
-        eprintln!("an error occurred on stream: {}", err);
-    };
-
-    let data_callback = move |data: &[i16], _: &_| match recognizer.accept_waveform(data).unwrap() {
-        DecodingState::Finalized => {
-            tx.send(RecognitionResult::Final(
-                recognizer.result().single().unwrap().text.to_string(),
-            ))
-            .unwrap();
+        self.start(false, false)?
+            .find_map(|update| match update {
+                RecognitionUpdate::Done(result) => Some(result),
+                RecognitionUpdate::Partial(_) => None,
+            })
+            .ok_or(RecognitionError::FailedReceiveResult)
+    }
+
+    /// Like [STTSentenceRecognizer::recognize], but also returns the audio given to the backend,
+    /// mono at [STT_SAMPLE_RATE], e.g. to find out why recognition failed.
+    pub fn recognize_with_audio(
+        self,
+    ) -> Result<(RecognitionResult, RecognizedAudio), RecognitionError> {
+        let mut stream = self.start(false, true)?;
+        let result = stream
+            .by_ref()
+            .find_map(|update| match update {
+                RecognitionUpdate::Done(result) => Some(result),
+                RecognitionUpdate::Partial(_) => None,
+            })
+            .ok_or(RecognitionError::FailedReceiveResult)?;
+        Ok((result, stream.take_audio()))
+    }
+
+    /// Like [STTSentenceRecognizer::recognize], but returns an iterator of partial results while
+    /// decoding is running, e.g. to show live captions. The iterator ends after
+    /// [RecognitionUpdate::Done].
+    pub fn recognize_streaming(self) -> Result<RecognitionStream<'a>, RecognitionError> {
+        self.start(true, false)
+    }
+
+    /// Like [STTSentenceRecognizer::recognize], but waits for audio without blocking the thread.
+    /// Decoding still runs on the calling thread. Enabled with the `tokio` feature.
+    #[cfg(feature = "tokio")]
+    pub async fn recognize_async(self) -> Result<RecognitionResult, RecognitionError> {
+        self.recognize_async_inner(false)
+            .await
+            .map(|(result, _)| result)
+    }
+
+    /// Like [STTSentenceRecognizer::recognize_with_audio], without blocking the thread. Enabled
+    /// with the `tokio` feature.
+    #[cfg(feature = "tokio")]
+    pub async fn recognize_with_audio_async(
+        self,
+    ) -> Result<(RecognitionResult, RecognizedAudio), RecognitionError> {
+        self.recognize_async_inner(true).await
+    }
+
+    #[cfg(feature = "tokio")]
+    async fn recognize_async_inner(
+        self,
+        record: bool,
+    ) -> Result<(RecognitionResult, RecognizedAudio), RecognitionError> {
+        let mut stream = self.start(false, record)?;
+        while let Some(update) = stream.next_async().await {
+            if let RecognitionUpdate::Done(result) = update {
+                return Ok((result, stream.take_audio()));
+            }
         }
-        DecodingState::Failed => tx.send(RecognitionResult::Failed).unwrap(),
-        DecodingState::Running => {
-            if start_time.elapsed().as_secs() > 20 {
-                tx.send(RecognitionResult::Cancelled).unwrap();
+        Err(RecognitionError::FailedReceiveResult)
+    }
+
+    fn start(
+        self,
+        partials: bool,
+        record: bool,
+    ) -> Result<RecognitionStream<'a>, RecognitionError> {
+        let session = self.recognizer.start_session(&self.options)?;
+        let pre_roll_samples =
+            (self.endpoint.pre_roll.as_secs_f64() * STT_SAMPLE_RATE as f64) as usize;
+
+        // Decoding happens on the thread reading the stream rather than the audio thread, so
+        // slow backends don't hold up the other consumers. Blocks are sent back once processed and
+        // reused, and both channels are bounded so sending doesn't allocate either.
+        let (tx, rx) = mpsc::sync_channel(STT_QUEUED_BLOCKS);
+        let (free, free_rx) = mpsc::sync_channel(STT_QUEUED_BLOCKS);
+        for _ in 0..STT_PREALLOCATED_BLOCKS {
+            _ = free.try_send(Vec::with_capacity(STT_BLOCK_CAPACITY));
+        }
+        #[cfg(feature = "tokio")]
+        let notify = Arc::new(Notify::new());
+        #[cfg(feature = "tokio")]
+        let notify_samples = notify.clone();
+        let mut resampler = Resampler::new(self.input.format(), STT_SAMPLE_RATE);
+        let consumer = self.input.subscribe(Box::new(move |data, start| {
+            // Only allocates if all the blocks are queued
+            let mut block: Vec<i16> = free_rx.try_recv().unwrap_or_default();
+            block.clear();
+            resampler.process(data, |sample| block.push(i16::from_sample(sample)));
+            _ = tx.try_send((start, block));
+            #[cfg(feature = "tokio")]
+            notify_samples.notify_one();
+        }));
+
+        Ok(RecognitionStream {
+            input: self.input,
+            consumer,
+            rx,
+            free,
+            #[cfg(feature = "tokio")]
+            notify,
+            session,
+            partials,
+            timeout: self.timeout,
+            endpoint: self.endpoint,
+            start_time: self.clock.now(),
+            clock: self.clock,
+            pre_roll: RingBuffer::new(pre_roll_samples),
+            speech: None,
+            partial: String::new(),
+            recording: record.then(Vec::new),
+            audio_start: None,
+            finished: false,
+        })
+    }
+}
+
+/// Iterator over the updates of a running recognition, created by
+/// [STTSentenceRecognizer::recognize_streaming]. Recognition stops when it is dropped.
+pub struct RecognitionStream<'a> {
+    input: &'a dyn AudioSource,
+    consumer: ConsumerId,
+    // With where the blocks start in the stream of the source
+    rx: mpsc::Receiver<(AudioTimestamp, Vec<i16>)>,
+    free: mpsc::SyncSender<Vec<i16>>,
+    #[cfg(feature = "tokio")]
+    notify: Arc<Notify>,
+    session: Box<dyn RecognitionSession + 'a>,
+    partials: bool,
+    timeout: Duration,
+    endpoint: EndpointConfig,
+    start_time: Instant,
+    clock: Arc<dyn Clock>,
+    pre_roll: RingBuffer<i16>,
+    // When speech started and when it was last heard
+    speech: Option<(Instant, Instant)>,
+    partial: String,
+    // The audio given to the session, if it's being recorded
+    recording: Option<Vec<i16>>,
+    audio_start: Option<AudioTimestamp>,
+    finished: bool,
+}
+
+impl RecognitionStream<'_> {
+    /// Where the audio given to the backend starts in the stream of the audio source, once
+    /// speech was heard. It includes the [EndpointConfig::pre_roll].
+    pub fn audio_start(&self) -> Option<AudioTimestamp> {
+        self.audio_start
+    }
+
+    /// Like [Iterator::next], but only processes the audio that already arrived and returns
+    /// `None` instead of waiting for more, e.g. to push the audio of a
+    /// [crate::audio::MemoryAudioSource] and recognize it on the same thread.
+    pub fn try_next(&mut self) -> Option<RecognitionUpdate> {
+        while !self.finished {
+            let Ok((start, samples)) = self.rx.try_recv() else {
+                return None;
+            };
+            let update = self.process(start, &samples);
+            _ = self.free.try_send(samples);
+            if let Some(update) = update {
+                self.finished = matches!(update, RecognitionUpdate::Done(_));
+                return Some(update);
+            }
+        }
+        None
+    }
+
+    fn take_audio(&mut self) -> RecognizedAudio {
+        RecognizedAudio {
+            samples: self.recording.take().unwrap_or_default(),
+            start: self.audio_start,
+        }
+    }
+
+    /// Like [Iterator::next], but waits for audio without blocking the thread. Enabled with the
+    /// `tokio` feature.
+    #[cfg(feature = "tokio")]
+    pub async fn next_async(&mut self) -> Option<RecognitionUpdate> {
+        while !self.finished {
+            let (start, samples) = match self.rx.try_recv() {
+                Ok(block) => block,
+                Err(mpsc::TryRecvError::Empty) => {
+                    self.notify.notified().await;
+                    continue;
+                }
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.finished = true;
+                    break;
+                }
+            };
+            let update = self.process(start, &samples);
+            _ = self.free.try_send(samples);
+            if let Some(update) = update {
+                self.finished = matches!(update, RecognitionUpdate::Done(_));
+                return Some(update);
+            }
+        }
+        None
+    }
+
+    fn process(&mut self, start: AudioTimestamp, samples: &[i16]) -> Option<RecognitionUpdate> {
+        let now = self.clock.now();
+        let is_speech = rms(samples) >= self.endpoint.speech_threshold;
+
+        let (speech_start, last_speech) = match &mut self.speech {
+            Some((start, last)) => {
+                if is_speech {
+                    *last = now;
+                }
+                (*start, *last)
+            }
+            None if is_speech => {
+                // The audio starts with the pre-roll, before this block
+                let pre_roll = self.pre_roll.len() as u64;
+                let sample_rate = u64::from(self.input.format().sample_rate);
+                self.audio_start = Some(AudioTimestamp {
+                    frame: start
+                        .frame
+                        .saturating_sub(pre_roll * sample_rate / u64::from(STT_SAMPLE_RATE)),
+                    time: start
+                        .time
+                        .checked_sub(Duration::from_secs_f64(
+                            pre_roll as f64 / f64::from(STT_SAMPLE_RATE),
+                        ))
+                        .unwrap_or(start.time),
+                });
+                // Give the recognizer what was said right before speech was detected
+                let (first, second) = self.pre_roll.as_slices();
+                for part in [first, second] {
+                    if part.is_empty() {
+                        continue;
+                    }
+                    self.session.accept_waveform(part);
+                    if let Some(recording) = &mut self.recording {
+                        recording.extend(part);
+                    }
+                }
+                self.pre_roll.clear();
+                #[cfg(feature = "tracing")]
+                tracing::debug!(
+                    after_millis = now.duration_since(self.start_time).as_millis() as u64,
+                    "speech started"
+                );
+                *self.speech.insert((now, now))
             }
+            None => {
+                if now.duration_since(self.start_time) > self.timeout {
+                    return Some(RecognitionUpdate::Done(RecognitionResult::Cancelled));
+                }
+                self.pre_roll.push_overwrite(samples);
+                return None;
+            }
+        };
+
+        if let Some(recording) = &mut self.recording {
+            recording.extend(samples);
         }
-    };
-    device
-        .build_input_stream::<i16, _, _>(&config, data_callback, error_callback, None)
-        .expect("Failed to build input stream")
+        let result = match self.session.accept_waveform(samples) {
+            SessionState::Finalized(sentence) => RecognitionResult::Final(sentence),
+            SessionState::Failed => RecognitionResult::Failed,
+            SessionState::Running => {
+                if now.duration_since(last_speech) < self.endpoint.max_silence
+                    && now.duration_since(speech_start) < self.endpoint.max_utterance
+                {
+                    // Partial transcripts are also computed to be traced
+                    #[cfg(feature = "tracing")]
+                    let partials = self.partials || tracing::enabled!(tracing::Level::TRACE);
+                    #[cfg(not(feature = "tracing"))]
+                    let partials = self.partials;
+                    if partials {
+                        let partial = self.session.partial_result();
+                        if partial != self.partial {
+                            #[cfg(feature = "tracing")]
+                            tracing::trace!(partial = %partial, "partial transcript");
+                            self.partial = partial;
+                            if self.partials {
+                                return Some(RecognitionUpdate::Partial(self.partial.clone()));
+                            }
+                        }
+                    }
+                    return None;
+                }
+                match self.session.final_result() {
+                    Some(sentence) if !sentence.text.is_empty() => {
+                        RecognitionResult::Final(sentence)
+                    }
+                    _ => RecognitionResult::Failed,
+                }
+            }
+        };
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            ?result,
+            speech_millis = now.duration_since(speech_start).as_millis() as u64,
+            "recognition done"
+        );
+        Some(RecognitionUpdate::Done(result))
+    }
+}
+
+impl Iterator for RecognitionStream<'_> {
+    type Item = RecognitionUpdate;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.finished {
+            let Ok((start, samples)) = self.rx.recv() else {
+                self.finished = true;
+                break;
+            };
+            let update = self.process(start, &samples);
+            _ = self.free.try_send(samples);
+            if let Some(update) = update {
+                self.finished = matches!(update, RecognitionUpdate::Done(_));
+                return Some(update);
+            }
+        }
+        None
+    }
+}
+
+impl Drop for RecognitionStream<'_> {
+    fn drop(&mut self) {
+        self.input.unsubscribe(self.consumer);
+    }
+}
+
+pub(crate) fn rms(samples: &[i16]) -> f32 {
+    if samples.is_empty() {
+        return 0.;
+    }
+    let sum: f32 = samples
+        .iter()
+        .map(|&s| f32::from_sample(s))
+        .map(|s| s * s)
+        .sum();
+    (sum / samples.len() as f32).sqrt()
 }
@@ -0,0 +1,463 @@
+use ring::{
+    aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN},
+    pbkdf2,
+    rand::{SecureRandom, SystemRandom},
+};
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Read},
+    num::NonZeroU32,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+
+use crate::storage::{Storage, StorageError};
+
+/// The [Storage] namespace of when each synced key last changed.
+const SYNC_NAMESPACE: &str = "sync";
+/// Starts the encrypted snapshots, so files of other programs aren't mistaken for them.
+const MAGIC: &[u8] = b"RSYNC1";
+const KEY_SALT: &[u8] = b"raspberry-sync";
+const KEY_ITERATIONS: u32 = 100_000;
+
+#[derive(Error, Debug)]
+pub enum SyncError {
+    #[error("Failed to access the storage")]
+    Storage(#[from] StorageError),
+    #[error("Sync request failed")]
+    Request(#[source] Box<ureq::Error>),
+    #[error("Failed to access the sync folder")]
+    Io(#[from] io::Error),
+    #[error("Failed to encrypt the snapshot")]
+    Encrypt,
+}
+
+/// Keeps the data of several assistants in sync, e.g. the reminders of two homes, see
+/// [crate::AssistantConfig::set_sync]. Each assistant uploads a snapshot of the synced namespaces
+/// encrypted with the key, and takes the values of the others that changed after its own.
+/// Items added on two assistants between syncs can get the same key, in which case the newer one
+/// wins.
+pub struct SyncConfig {
+    pub(crate) backend: Box<dyn SyncBackend>,
+    pub(crate) key: SyncKey,
+    pub(crate) namespaces: Vec<String>,
+    pub(crate) interval: Duration,
+}
+
+impl SyncConfig {
+    /// Sync the timers, alarms and reminders, the disabled intent groups and the learned voices
+    /// every 5 minutes.
+    pub fn new(backend: impl SyncBackend + 'static, key: SyncKey) -> Self {
+        Self {
+            backend: Box::new(backend),
+            key,
+            namespaces: ["schedule", "disabled_intent_groups", "speaker_profiles"]
+                .map(str::to_string)
+                .to_vec(),
+            interval: Duration::from_secs(5 * 60),
+        }
+    }
+
+    /// Also sync a namespace of the storage, e.g. one of a skill.
+    pub fn add_namespace(&mut self, namespace: &str) {
+        self.namespaces.push(namespace.to_string());
+    }
+
+    pub fn set_interval(&mut self, interval: Duration) {
+        self.interval = interval;
+    }
+}
+
+/// Where the snapshots of the assistants are exchanged. The data is encrypted before it is given
+/// to the backend.
+pub trait SyncBackend: Send {
+    /// The snapshots of all assistants, which can include the own one.
+    fn download(&mut self) -> Result<Vec<Vec<u8>>, SyncError>;
+
+    fn upload(&mut self, snapshot: &[u8]) -> Result<(), SyncError>;
+}
+
+/// Keeps one snapshot, with all the data, at a URL that answers GET and PUT requests. Assistants
+/// merge the snapshot there with their own data before replacing it.
+pub struct HttpSync {
+    url: String,
+    token: Option<String>,
+}
+
+impl HttpSync {
+    /// The token is sent as a bearer token, if any.
+    pub fn new(url: impl Into<String>, token: Option<String>) -> Self {
+        Self {
+            url: url.into(),
+            token,
+        }
+    }
+
+    fn request(&self, method: &str) -> ureq::Request {
+        let request = ureq::request(method, &self.url).timeout(Duration::from_secs(30));
+        match &self.token {
+            Some(token) => request.set("Authorization", &format!("Bearer {}", token)),
+            None => request,
+        }
+    }
+}
+
+impl SyncBackend for HttpSync {
+    fn download(&mut self) -> Result<Vec<Vec<u8>>, SyncError> {
+        let response = match self.request("GET").call() {
+            Ok(response) => response,
+            // Nobody uploaded a snapshot yet
+            Err(ureq::Error::Status(404, _)) => return Ok(Vec::new()),
+            Err(e) => return Err(SyncError::Request(Box::new(e))),
+        };
+        let mut snapshot = Vec::new();
+        response.into_reader().read_to_end(&mut snapshot)?;
+        Ok(vec![snapshot])
+    }
+
+    fn upload(&mut self, snapshot: &[u8]) -> Result<(), SyncError> {
+        self.request("PUT")
+            .send_bytes(snapshot)
+            .map_err(|e| SyncError::Request(Box::new(e)))?;
+        Ok(())
+    }
+}
+
+/// Keeps the snapshot of each assistant in its own file of a folder synced by another program,
+/// like Syncthing, so they never write the same file.
+pub struct FolderSync {
+    folder: PathBuf,
+    instance: String,
+}
+
+impl FolderSync {
+    /// The instance names the file of this assistant and has to be different on each one.
+    pub fn new(folder: impl Into<PathBuf>, instance: impl Into<String>) -> Self {
+        Self {
+            folder: folder.into(),
+            instance: instance.into(),
+        }
+    }
+}
+
+impl SyncBackend for FolderSync {
+    fn download(&mut self) -> Result<Vec<Vec<u8>>, SyncError> {
+        let mut snapshots = Vec::new();
+        for entry in fs::read_dir(&self.folder)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "sync")
+            {
+                snapshots.push(fs::read(path)?);
+            }
+        }
+        Ok(snapshots)
+    }
+
+    fn upload(&mut self, snapshot: &[u8]) -> Result<(), SyncError> {
+        // Renamed into place so the other program never syncs half a file
+        let path = self.folder.join(format!("{}.sync", self.instance));
+        let temporary = self.folder.join(format!(".{}.sync.tmp", self.instance));
+        fs::write(&temporary, snapshot)?;
+        fs::rename(temporary, path)?;
+        Ok(())
+    }
+}
+
+/// The key encrypting the snapshots with ChaCha20-Poly1305. All assistants syncing together need
+/// the same one.
+#[derive(Clone)]
+pub struct SyncKey([u8; 32]);
+
+impl SyncKey {
+    /// Derive the key from a passphrase with PBKDF2.
+    pub fn from_passphrase(passphrase: &str) -> Self {
+        let mut key = [0; 32];
+        pbkdf2::derive(
+            pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(KEY_ITERATIONS).unwrap(),
+            KEY_SALT,
+            passphrase.as_bytes(),
+            &mut key,
+        );
+        Self(key)
+    }
+
+    pub fn from_bytes(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+
+    fn aead_key(&self) -> LessSafeKey {
+        LessSafeKey::new(UnboundKey::new(&CHACHA20_POLY1305, &self.0).unwrap())
+    }
+
+    pub(crate) fn seal(&self, data: &[u8]) -> Result<Vec<u8>, SyncError> {
+        let mut nonce = [0; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| SyncError::Encrypt)?;
+        let mut sealed = data.to_vec();
+        self.aead_key()
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(MAGIC),
+                &mut sealed,
+            )
+            .map_err(|_| SyncError::Encrypt)?;
+        Ok([MAGIC, &nonce, &sealed].concat())
+    }
+
+    /// `None` if the snapshot wasn't sealed with this key or was changed.
+    pub(crate) fn open(&self, snapshot: &[u8]) -> Option<Vec<u8>> {
+        let snapshot = snapshot.strip_prefix(MAGIC)?;
+        if snapshot.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, sealed) = snapshot.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let mut data = sealed.to_vec();
+        let opened = self
+            .aead_key()
+            .open_in_place(nonce, Aad::from(MAGIC), &mut data)
+            .ok()?;
+        Some(opened.to_vec())
+    }
+}
+
+/// A value of a synced namespace, or its removal, with when it changed.
+struct Entry {
+    namespace: String,
+    key: String,
+    /// Milliseconds since the Unix epoch, 0 for values that were set before sync was enabled.
+    changed: u64,
+    value: Option<Vec<u8>>,
+}
+
+/// Records when the keys of the synced namespaces change, so the newest value can be kept when
+/// syncing.
+pub(crate) struct SyncedStorage {
+    inner: Arc<dyn Storage>,
+    namespaces: HashSet<String>,
+}
+
+impl SyncedStorage {
+    pub(crate) fn new(inner: Arc<dyn Storage>, namespaces: &[String]) -> Self {
+        Self {
+            inner,
+            namespaces: namespaces.iter().cloned().collect(),
+        }
+    }
+
+    fn record(&self, namespace: &str, key: &str, present: bool) -> Result<(), StorageError> {
+        if !self.namespaces.contains(namespace) {
+            return Ok(());
+        }
+        let changed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        self.write_record(namespace, key, changed, present)
+    }
+
+    fn write_record(
+        &self,
+        namespace: &str,
+        key: &str,
+        changed: u64,
+        present: bool,
+    ) -> Result<(), StorageError> {
+        let mut record = changed.to_le_bytes().to_vec();
+        record.push(present as u8);
+        self.inner
+            .set(SYNC_NAMESPACE, &format!("{}/{}", namespace, key), &record)
+    }
+
+    /// When the key last changed and whether it is set, `None` if it didn't change since sync was
+    /// enabled.
+    fn read_record(&self, namespace: &str, key: &str) -> Result<Option<(u64, bool)>, StorageError> {
+        let record = self
+            .inner
+            .get(SYNC_NAMESPACE, &format!("{}/{}", namespace, key))?;
+        Ok(match record.as_deref() {
+            Some([changed @ .., present]) if changed.len() == 8 => Some((
+                u64::from_le_bytes(changed.try_into().unwrap()),
+                *present != 0,
+            )),
+            _ => None,
+        })
+    }
+
+    /// The values of the synced namespaces, and the removed keys.
+    fn entries(&self) -> Result<Vec<Entry>, StorageError> {
+        let mut entries = Vec::new();
+        for namespace in &self.namespaces {
+            for key in self.inner.keys(namespace)? {
+                let changed = self
+                    .read_record(namespace, &key)?
+                    .map_or(0, |(changed, _)| changed);
+                entries.push(Entry {
+                    namespace: namespace.clone(),
+                    value: self.inner.get(namespace, &key)?,
+                    key,
+                    changed,
+                });
+            }
+        }
+        for record in self.inner.keys(SYNC_NAMESPACE)? {
+            let Some((namespace, key)) = record.split_once('/') else {
+                continue;
+            };
+            if !self.namespaces.contains(namespace) {
+                continue;
+            }
+            if let Some((changed, false)) = self.read_record(namespace, key)? {
+                entries.push(Entry {
+                    namespace: namespace.to_string(),
+                    key: key.to_string(),
+                    changed,
+                    value: None,
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Take the values of the other assistants that changed after the own ones, then upload the
+    /// result. Returns whether any value changed here. Snapshots that can't be opened with the
+    /// key are skipped.
+    pub(crate) fn sync(
+        &self,
+        backend: &mut dyn SyncBackend,
+        key: &SyncKey,
+    ) -> Result<bool, SyncError> {
+        let mut changed = false;
+        for snapshot in backend.download()? {
+            let Some(entries) = key.open(&snapshot).and_then(|data| decode(&data)) else {
+                warn!("Skipping a sync snapshot that couldn't be decrypted");
+                continue;
+            };
+            for entry in entries {
+                if !self.namespaces.contains(&entry.namespace) {
+                    continue;
+                }
+                let newer = match self.read_record(&entry.namespace, &entry.key)? {
+                    Some((local, _)) => entry.changed > local,
+                    // Values set before sync was enabled are older than all others
+                    None => match self.inner.get(&entry.namespace, &entry.key)? {
+                        Some(_) => entry.changed > 0,
+                        None => entry.value.is_some(),
+                    },
+                };
+                if !newer {
+                    continue;
+                }
+                match &entry.value {
+                    Some(value) => self.inner.set(&entry.namespace, &entry.key, value)?,
+                    None => self.inner.remove(&entry.namespace, &entry.key)?,
+                }
+                self.write_record(
+                    &entry.namespace,
+                    &entry.key,
+                    entry.changed,
+                    entry.value.is_some(),
+                )?;
+                changed = true;
+            }
+        }
+        backend.upload(&key.seal(&encode(&self.entries()?))?)?;
+        Ok(changed)
+    }
+}
+
+impl Storage for SyncedStorage {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, StorageError> {
+        self.inner.get(namespace, key)
+    }
+
+    fn set(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), StorageError> {
+        self.inner.set(namespace, key, value)?;
+        self.record(namespace, key, true)
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> Result<(), StorageError> {
+        self.inner.remove(namespace, key)?;
+        self.record(namespace, key, false)
+    }
+
+    fn keys(&self, namespace: &str) -> Result<Vec<String>, StorageError> {
+        self.inner.keys(namespace)
+    }
+}
+
+/// Sync in the background every interval, starting now. The flag is set when a value changed.
+pub(crate) fn spawn(storage: Arc<SyncedStorage>, config: SyncConfig) -> Arc<AtomicBool> {
+    let changed = Arc::new(AtomicBool::new(false));
+    let flag = changed.clone();
+    let mut backend = config.backend;
+    thread::spawn(move || loop {
+        match storage.sync(backend.as_mut(), &config.key) {
+            Ok(true) => flag.store(true, Ordering::Relaxed),
+            Ok(false) => (),
+            Err(e) => warn!("Failed to sync: {}", e),
+        }
+        thread::sleep(config.interval);
+    });
+    changed
+}
+
+/// Lengths are u32 and timestamps u64, in little endian. The value is prefixed by whether it is
+/// set.
+fn encode(entries: &[Entry]) -> Vec<u8> {
+    fn push_bytes(data: &mut Vec<u8>, bytes: &[u8]) {
+        data.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        data.extend_from_slice(bytes);
+    }
+
+    let mut data = Vec::new();
+    for entry in entries {
+        push_bytes(&mut data, entry.namespace.as_bytes());
+        push_bytes(&mut data, entry.key.as_bytes());
+        data.extend_from_slice(&entry.changed.to_le_bytes());
+        data.push(entry.value.is_some() as u8);
+        push_bytes(&mut data, entry.value.as_deref().unwrap_or_default());
+    }
+    data
+}
+
+fn decode(mut data: &[u8]) -> Option<Vec<Entry>> {
+    fn take<'a>(data: &mut &'a [u8], length: usize) -> Option<&'a [u8]> {
+        if data.len() < length {
+            return None;
+        }
+        let (taken, rest) = data.split_at(length);
+        *data = rest;
+        Some(taken)
+    }
+    fn take_bytes<'a>(data: &mut &'a [u8]) -> Option<&'a [u8]> {
+        let length = u32::from_le_bytes(take(data, 4)?.try_into().ok()?);
+        take(data, length as usize)
+    }
+
+    let mut entries = Vec::new();
+    while !data.is_empty() {
+        let namespace = String::from_utf8(take_bytes(&mut data)?.to_vec()).ok()?;
+        let key = String::from_utf8(take_bytes(&mut data)?.to_vec()).ok()?;
+        let changed = u64::from_le_bytes(take(&mut data, 8)?.try_into().ok()?);
+        let present = take(&mut data, 1)?[0] != 0;
+        let value = take_bytes(&mut data)?;
+        entries.push(Entry {
+            namespace,
+            key,
+            changed,
+            value: present.then(|| value.to_vec()),
+        });
+    }
+    Some(entries)
+}
@@ -0,0 +1,293 @@
+use chrono::{DateTime, FixedOffset, Local};
+use serde::Deserialize;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::{
+    http::{HttpClient, HttpError},
+    http_cache::HttpCache,
+    permissions::Capability,
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{Slot, SlotKind, SlotValue},
+    AssistantQuery,
+};
+
+#[derive(Error, Debug)]
+pub enum TransitError {
+    #[error("Departures request failed")]
+    Request(#[from] HttpError),
+    #[error("Failed to read the departures response")]
+    Response(#[from] std::io::Error),
+}
+
+/// A source of the departures at a stop. [TransportRest] is the one included, other APIs can
+/// implement this trait and be given to [TransitSkill::new].
+pub trait TransitProvider {
+    /// The departures from the stop with the id in the next `within`, in any order.
+    fn departures(&self, stop: &str, within: Duration) -> Result<Vec<Departure>, TransitError>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Departure {
+    /// The name of the line, like "S5" or "Bus 42".
+    pub line: String,
+    /// Where the trip ends.
+    pub direction: String,
+    pub planned: DateTime<Local>,
+    /// When it is expected to leave with the real-time data, if the API has it.
+    pub expected: Option<DateTime<Local>>,
+    pub cancelled: bool,
+}
+
+impl Departure {
+    /// When it leaves, the expected time if known.
+    pub fn when(&self) -> DateTime<Local> {
+        self.expected.unwrap_or(self.planned)
+    }
+}
+
+/// The transport.rest APIs, in the Friendly Public Transport Format, which include real-time
+/// delays, like <https://v6.db.transport.rest> for Germany or <https://v1.vbb.transport.rest> for
+/// Berlin. Stops are the ids of the API, found with its `/locations?query=` endpoint.
+pub struct TransportRest {
+    url: String,
+    client: HttpClient,
+    cache: Option<(HttpCache, Duration)>,
+}
+
+impl TransportRest {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            client: HttpClient::default(),
+            cache: None,
+        }
+    }
+
+    /// Make the requests with a shared client, see [crate::AssistantConfig::http_client].
+    pub fn set_client(&mut self, client: HttpClient) {
+        self.client = client;
+    }
+
+    /// Keep the responses in the cache for `ttl`, which should be short for the delays to stay
+    /// right. Every request is made by default.
+    pub fn set_cache(&mut self, cache: HttpCache, ttl: Duration) {
+        self.cache = Some((cache, ttl));
+    }
+}
+
+/// Newer versions wrap the departures in an object, older ones return them directly.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FptfResponse {
+    Wrapped { departures: Vec<FptfDeparture> },
+    List(Vec<FptfDeparture>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct FptfDeparture {
+    when: Option<DateTime<FixedOffset>>,
+    planned_when: Option<DateTime<FixedOffset>>,
+    direction: Option<String>,
+    line: Option<FptfLine>,
+    #[serde(default)]
+    cancelled: bool,
+}
+
+#[derive(Deserialize)]
+struct FptfLine {
+    name: Option<String>,
+}
+
+impl TransitProvider for TransportRest {
+    fn departures(&self, stop: &str, within: Duration) -> Result<Vec<Departure>, TransitError> {
+        let request = self
+            .client
+            .get(&format!("{}/stops/{}/departures", self.url, stop))
+            .query("duration", &within.as_secs().div_ceil(60).to_string());
+        let fetch =
+            || -> Result<String, TransitError> { Ok(request.clone().call()?.into_string()?) };
+        let body = match &self.cache {
+            Some((cache, ttl)) => cache.get_or_fetch(&request.url()?, *ttl, fetch)?,
+            None => fetch()?,
+        };
+        let departures = match serde_json::from_str(&body).map_err(std::io::Error::from)? {
+            FptfResponse::Wrapped { departures } | FptfResponse::List(departures) => departures,
+        };
+        Ok(departures
+            .into_iter()
+            .filter_map(|departure| {
+                // Cancelled departures only have the planned time
+                let planned = departure.planned_when.or(departure.when)?;
+                Some(Departure {
+                    line: departure
+                        .line
+                        .and_then(|line| line.name)
+                        .unwrap_or_default(),
+                    direction: departure.direction.unwrap_or_default(),
+                    planned: planned.with_timezone(&Local),
+                    expected: departure.when.map(|when| when.with_timezone(&Local)),
+                    cancelled: departure.cancelled,
+                })
+            })
+            .collect())
+    }
+}
+
+/// Where a [TransitSkill] is asked to go, e.g. "downtown": the departures from a stop, of a line
+/// or towards a direction if set.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TransitRoute {
+    /// The name the route is called by.
+    pub name: String,
+    /// The id of the stop in the API of the provider.
+    pub stop: String,
+    /// Only departures of the line with this name, ignoring case.
+    pub line: Option<String>,
+    /// Only departures whose direction contains this, ignoring case.
+    pub direction: Option<String>,
+}
+
+impl TransitRoute {
+    fn matches(&self, departure: &Departure) -> bool {
+        let line = self
+            .line
+            .as_ref()
+            .is_none_or(|line| departure.line.eq_ignore_ascii_case(line));
+        let direction = self.direction.as_ref().is_none_or(|direction| {
+            departure
+                .direction
+                .to_lowercase()
+                .contains(&direction.to_lowercase())
+        });
+        line && direction
+    }
+}
+
+const NEXT_DEPARTURE_INTENT: &str = "next departure";
+/// How far ahead departures are looked for.
+const LOOKAHEAD: Duration = Duration::from_secs(60 * 60);
+
+/// A [Skill] answering "when is the next bus to downtown" with the next departures of the
+/// route, and their delays. The names of the routes are the vocabulary of its slot, so they are
+/// also in the grammar of speech recognition. Without a route in the question, the first one is
+/// used. Enabled with the `transit` feature.
+pub struct TransitSkill {
+    provider: Box<dyn TransitProvider>,
+    routes: Vec<TransitRoute>,
+}
+
+impl TransitSkill {
+    pub fn new(provider: impl TransitProvider + 'static, routes: Vec<TransitRoute>) -> Self {
+        Self {
+            provider: Box::new(provider),
+            routes,
+        }
+    }
+
+    fn answer(&self, route: &TransitRoute, now: DateTime<Local>) -> String {
+        let mut departures = match self.provider.departures(&route.stop, LOOKAHEAD) {
+            Ok(departures) => departures,
+            Err(e) => {
+                warn!("Failed to get the departures to {}: {}", route.name, e);
+                return "Sorry, I couldn't get the departures right now.".to_string();
+            }
+        };
+        departures.retain(|departure| {
+            !departure.cancelled && departure.when() >= now && route.matches(departure)
+        });
+        departures.sort_by_key(Departure::when);
+        let Some(next) = departures.first() else {
+            return format!(
+                "There are no departures to {} in the next hour.",
+                route.name
+            );
+        };
+        let mut response = format!(
+            "The next {} to {} leaves {}",
+            if next.line.is_empty() {
+                "departure"
+            } else {
+                &next.line
+            },
+            route.name,
+            describe_wait(next.when(), now)
+        );
+        let delay = (next.when() - next.planned).num_minutes();
+        if delay > 0 {
+            response.push_str(&format!(
+                ", {} late",
+                describe_minutes(delay.unsigned_abs())
+            ));
+        }
+        if let Some(then) = departures.get(1) {
+            response.push_str(&format!(", then {}", describe_wait(then.when(), now)));
+        }
+        response.push('.');
+        response
+    }
+}
+
+fn describe_wait(when: DateTime<Local>, now: DateTime<Local>) -> String {
+    match (when - now).num_minutes() {
+        0 => "now".to_string(),
+        minutes => format!("in {}", describe_minutes(minutes.unsigned_abs())),
+    }
+}
+
+fn describe_minutes(minutes: u64) -> String {
+    match minutes {
+        1 => "1 minute".to_string(),
+        minutes => format!("{} minutes", minutes),
+    }
+}
+
+impl Skill for TransitSkill {
+    fn name(&self) -> &str {
+        "transit"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak, Capability::Network]
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        if self.routes.is_empty() {
+            return Vec::new();
+        }
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        let routes = self.routes.iter().map(|route| route.name.clone()).collect();
+        vec![IntentSpec::with_slots(
+            NEXT_DEPARTURE_INTENT,
+            examples(&[
+                "when is the next bus",
+                "when is the next bus to {route}",
+                "when does the next train to {route} leave",
+                "when is the next departure to {route}",
+                "when do i have to leave for {route}",
+            ]),
+            vec![Slot::new("route", SlotKind::Entity(routes))],
+        )]
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        if query.intent != Some(NEXT_DEPARTURE_INTENT) {
+            return;
+        }
+        let route = match query.slots.get("route") {
+            Some(SlotValue::Entity(name)) => self.routes.iter().find(|route| route.name == *name),
+            _ => self.routes.first(),
+        };
+        let response = match route {
+            Some(route) => self.answer(route, ctx.clock().local_now()),
+            None => "Where do you want to go?".to_string(),
+        };
+        _ = ctx.speak(response);
+    }
+
+    #[cfg(feature = "offline")]
+    fn needs_network(&self) -> bool {
+        true
+    }
+}
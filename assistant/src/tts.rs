@@ -1,15 +1,307 @@
-use tts::{Backends, Tts};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Condvar, Mutex},
+    time::Instant,
+};
+use thiserror::Error;
+use tts::Tts;
 
-pub use tts::Error as TtsError;
+use crate::{
+    metrics::{Metric, Metrics},
+    normalize::Normalizer,
+};
 
+pub use tts::{Backends, Error as TtsError, UtteranceId, Voice};
+
+/// How many utterances that ended before being tracked are remembered.
+const ENDED_EARLY_CAPACITY: usize = 16;
+
+/// TtsConfig selects and configures the TTS backend, see [crate::AssistantConfig::set_tts_config].
+/// Rate, pitch and volume go from -1 (lowest) to 1 (highest), 0 being the backend's normal value.
+#[derive(Clone, Debug, Default)]
+pub struct TtsConfig {
+    backend: Option<Backends>,
+    voice: Option<VoiceSelection>,
+    rate: f32,
+    pitch: f32,
+    volume: f32,
+}
+
+/// How [TtsConfig] picks a voice among the ones of the backend.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VoiceSelection {
+    /// The voice with this name, ignoring case.
+    Name(String),
+    /// The first voice for a language, e.g. "en" or "en-GB".
+    Language(String),
+}
+
+#[derive(Error, Debug)]
+pub enum TtsConfigError {
+    #[error("Failed to configure TTS")]
+    Tts(#[from] TtsError),
+    #[error("No voice matching {0:?}")]
+    VoiceNotFound(VoiceSelection),
+}
+
+impl TtsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use this backend instead of the default one of the platform.
+    pub fn set_backend(&mut self, backend: Backends) {
+        self.backend = Some(backend);
+    }
+
+    pub fn set_voice(&mut self, voice: VoiceSelection) {
+        self.voice = Some(voice);
+    }
+
+    /// Set the speech rate. The rate of the settings profile replaces it once a profile is set.
+    pub fn set_rate(&mut self, rate: f32) {
+        self.rate = rate;
+    }
+
+    pub fn set_pitch(&mut self, pitch: f32) {
+        self.pitch = pitch;
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+    }
+
+    /// Create the backend and apply the configuration. Settings the backend doesn't support are
+    /// skipped.
+    pub fn build(&self) -> Result<Tts, TtsConfigError> {
+        let mut tts = match self.backend {
+            Some(backend) => Tts::new(backend)?,
+            None => Tts::default()?,
+        };
+        let features = tts.supported_features();
+
+        if let Some(selection) = &self.voice {
+            set_voice(&mut tts, selection)?;
+        }
+        if features.rate {
+            set_rate(&mut tts, self.rate)?;
+        }
+        if features.pitch {
+            let pitch = relative_value(
+                self.pitch,
+                tts.min_pitch(),
+                tts.normal_pitch(),
+                tts.max_pitch(),
+            );
+            tts.set_pitch(pitch)?;
+        }
+        if features.volume {
+            let volume = relative_value(
+                self.volume,
+                tts.min_volume(),
+                tts.normal_volume(),
+                tts.max_volume(),
+            );
+            tts.set_volume(volume)?;
+        }
+        Ok(tts)
+    }
+}
+
+impl VoiceSelection {
+    fn matches(&self, voice: &Voice) -> bool {
+        match self {
+            VoiceSelection::Name(name) => voice.name().eq_ignore_ascii_case(name),
+            VoiceSelection::Language(language) => {
+                let tag = voice.language();
+                if language.contains(['-', '_']) {
+                    tag.as_str()
+                        .replace('_', "-")
+                        .eq_ignore_ascii_case(&language.replace('_', "-"))
+                } else {
+                    tag.primary_language().eq_ignore_ascii_case(language)
+                }
+            }
+        }
+    }
+}
+
+/// The default backend of the platform with its normal settings.
 pub fn get_tts() -> Result<Tts, TtsError> {
-    let mut tts = Tts::new(Backends::SpeechDispatcher)?;
-    // let voices = tts.voices()?;
-    // tts.set_voice(&voices[0])?;
-    tts.set_rate(0.0)?;
+    let mut tts = Tts::default()?;
+    if tts.supported_features().rate {
+        set_rate(&mut tts, 0.)?;
+    }
     Ok(tts)
 }
 
-pub fn tts_speak(tts: &mut Tts, text: impl Into<String>) -> Result<(), TtsError> {
-    tts.speak(text, true).map(|_| ())
+/// The backend, or [TtsError::UnsupportedFeature] for assistants built without one, see
+/// [crate::AssistantConfigBuilder::set_tts].
+pub(crate) fn backend(tts: &mut Option<Tts>) -> Result<&mut Tts, TtsError> {
+    tts.as_mut().ok_or(TtsError::UnsupportedFeature)
+}
+
+/// Whether the backend is speaking. Assistants without one never are.
+pub(crate) fn is_speaking(tts: Option<&Tts>) -> Result<bool, TtsError> {
+    tts.map_or(Ok(false), Tts::is_speaking)
+}
+
+/// Interrupt what the backend is saying, if there is one.
+pub(crate) fn stop(tts: Option<&mut Tts>) -> Result<(), TtsError> {
+    match tts {
+        Some(tts) => tts.stop().map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+/// Set the rate of the backend on the scale of [TtsConfig::set_rate].
+pub(crate) fn set_rate(tts: &mut Tts, rate: f32) -> Result<(), TtsError> {
+    let rate = relative_value(rate, tts.min_rate(), tts.normal_rate(), tts.max_rate());
+    tts.set_rate(rate)?;
+    Ok(())
+}
+
+/// Switch to the first voice of the backend matching the selection.
+pub(crate) fn set_voice(tts: &mut Tts, selection: &VoiceSelection) -> Result<(), TtsConfigError> {
+    let voices = if tts.supported_features().voice {
+        tts.voices()?
+    } else {
+        Vec::new()
+    };
+    let voice = voices
+        .iter()
+        .find(|voice| selection.matches(voice))
+        .ok_or_else(|| TtsConfigError::VoiceNotFound(selection.clone()))?;
+    tts.set_voice(voice)?;
+    Ok(())
+}
+
+/// Map a value from -1 to 1 onto the range of a backend setting.
+fn relative_value(value: f32, min: f32, normal: f32, max: f32) -> f32 {
+    if value < 0. {
+        normal + (normal - min) * value.max(-1.)
+    } else {
+        normal + (max - normal) * value.min(1.)
+    }
+}
+
+/// Speak the text after normalizing it, interrupting anything that is being spoken.
+pub fn tts_speak(
+    tts: &mut Tts,
+    normalizer: &Normalizer,
+    text: impl Into<String>,
+) -> Result<(), TtsError> {
+    tts_speak_utterance(tts, normalizer, text).map(|_| ())
+}
+
+/// Like [tts_speak], but returns the id of the utterance if the backend has one.
+pub fn tts_speak_utterance(
+    tts: &mut Tts,
+    normalizer: &Normalizer,
+    text: impl Into<String>,
+) -> Result<Option<UtteranceId>, TtsError> {
+    tts.speak(normalizer.normalize(&text.into()), true)
+}
+
+type UtteranceCallback = Box<dyn FnOnce() + Send>;
+
+/// Keeps track of the utterances being spoken with the utterance callbacks of the backend, so they
+/// can be waited for without polling, and records how long they take to start, see
+/// [Metric::TtsFirstAudio].
+#[derive(Clone, Default)]
+pub(crate) struct Utterances {
+    state: Arc<(Mutex<UtterancesState>, Condvar)>,
+    metrics: Metrics,
+}
+
+#[derive(Default)]
+struct UtterancesState {
+    speaking: HashMap<UtteranceId, Vec<UtteranceCallback>>,
+    // Utterances that ended before their id was returned by the backend
+    ended_early: VecDeque<UtteranceId>,
+    // When the last text was given to the backend, until it starts saying it
+    requested_at: Option<Instant>,
+}
+
+impl Utterances {
+    /// Register the callbacks on the backend. Returns `None` if it doesn't support them.
+    pub(crate) fn register(tts: &Tts, metrics: Metrics) -> Result<Option<Self>, TtsError> {
+        if !tts.supported_features().utterance_callbacks {
+            return Ok(None);
+        }
+        let utterances = Self {
+            metrics,
+            ..Self::default()
+        };
+        let begun = utterances.clone();
+        tts.on_utterance_begin(Some(Box::new(move |_| begun.begun())))?;
+        let ended = utterances.clone();
+        tts.on_utterance_end(Some(Box::new(move |id| ended.ended(id))))?;
+        let stopped = utterances.clone();
+        tts.on_utterance_stop(Some(Box::new(move |id| stopped.ended(id))))?;
+        Ok(Some(utterances))
+    }
+
+    /// Note that a text is given to the backend, to time until it starts saying it. A text given
+    /// before the previous one started replaces it.
+    pub(crate) fn requested(&self) {
+        let (state, _) = &*self.state;
+        state.lock().unwrap().requested_at = Some(Instant::now());
+    }
+
+    /// Track an utterance until it ends or is interrupted, then call the callback.
+    pub(crate) fn track(&self, id: UtteranceId, callback: Option<UtteranceCallback>) {
+        let (state, _) = &*self.state;
+        let mut state = state.lock().unwrap();
+        if let Some(index) = state.ended_early.iter().position(|ended| *ended == id) {
+            state.ended_early.remove(index);
+            drop(state);
+            if let Some(callback) = callback {
+                callback();
+            }
+            return;
+        }
+        state.speaking.entry(id).or_default().extend(callback);
+    }
+
+    /// Block until the utterance ended. Returns immediately for utterances that aren't tracked.
+    pub(crate) fn wait_for(&self, id: UtteranceId) {
+        let (state, ended) = &*self.state;
+        let state = state.lock().unwrap();
+        drop(
+            ended
+                .wait_while(state, |state| state.speaking.contains_key(&id))
+                .unwrap(),
+        );
+    }
+
+    fn begun(&self) {
+        let (state, _) = &*self.state;
+        let requested_at = state.lock().unwrap().requested_at.take();
+        if let Some(requested_at) = requested_at {
+            self.metrics
+                .record(Metric::TtsFirstAudio, requested_at.elapsed());
+        }
+    }
+
+    fn ended(&self, id: UtteranceId) {
+        let (state, ended) = &*self.state;
+        let callbacks = {
+            let mut state = state.lock().unwrap();
+            match state.speaking.remove(&id) {
+                Some(callbacks) => callbacks,
+                None => {
+                    if state.ended_early.len() == ENDED_EARLY_CAPACITY {
+                        state.ended_early.pop_front();
+                    }
+                    state.ended_early.push_back(id);
+                    Vec::new()
+                }
+            }
+        };
+        ended.notify_all();
+        for callback in callbacks {
+            callback();
+        }
+    }
 }
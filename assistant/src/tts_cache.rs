@@ -0,0 +1,259 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use ::tts::Tts;
+
+use crate::{
+    normalize::Normalizer,
+    sounds::{SoundError, SoundPlayer},
+    tts::{TtsError, Utterances},
+};
+
+/// Turns text into audio, for the cache of [TtsCacheConfig] to play instead of the TTS backend.
+/// It should sound like the voice of the backend, e.g. espeak-ng for speech-dispatcher's default
+/// module.
+pub trait Synthesizer: Send + Sync {
+    /// A WAV or OGG Vorbis file of the text being spoken.
+    fn synthesize(&self, text: &str) -> io::Result<Vec<u8>>;
+}
+
+/// Which phrases are synthesized ahead of time so that speaking them again is instant, see
+/// [crate::AssistantConfig::set_tts_cache].
+pub struct TtsCacheConfig {
+    synthesizer: Arc<dyn Synthesizer>,
+    phrases: Vec<String>,
+    capacity: usize,
+    max_length: usize,
+}
+
+impl TtsCacheConfig {
+    pub fn new(synthesizer: impl Synthesizer + 'static) -> Self {
+        Self {
+            synthesizer: Arc::new(synthesizer),
+            phrases: Vec::new(),
+            capacity: 64,
+            max_length: 80,
+        }
+    }
+
+    /// Synthesize the phrase when the assistant starts, e.g. a wakeword response, instead of the
+    /// first time it is spoken. Declared phrases are never dropped from the cache.
+    pub fn add_phrase(&mut self, phrase: impl Into<String>) {
+        self.phrases.push(phrase.into());
+    }
+
+    /// How many phrases spoken by the assistant are kept besides the declared ones, 64 by
+    /// default. The oldest are dropped first.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+    }
+
+    /// The longest phrase in characters that is cached when it is spoken, 80 by default. Cached
+    /// phrases are played to the end before speaking returns, so they should be short.
+    pub fn set_max_length(&mut self, max_length: usize) {
+        self.max_length = max_length;
+    }
+}
+
+/// The audio of phrases spoken before, played instead of going through the TTS backend again.
+/// Phrases are synthesized in the background the first time they are spoken.
+pub(crate) struct TtsCache {
+    synthesizer: Arc<dyn Synthesizer>,
+    state: Arc<Mutex<CacheState>>,
+    capacity: usize,
+    max_length: usize,
+    // Opened on the first cached phrase
+    player: Mutex<Option<SoundPlayer>>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    audio: HashMap<String, Arc<[u8]>>,
+    declared: HashSet<String>,
+    // The phrases that weren't declared, oldest first
+    spoken: VecDeque<String>,
+    // Being synthesized
+    pending: HashSet<String>,
+}
+
+impl TtsCache {
+    /// Start synthesizing the declared phrases in the background.
+    pub(crate) fn new(config: TtsCacheConfig, normalizer: &Normalizer) -> Self {
+        let phrases: Vec<String> = config
+            .phrases
+            .iter()
+            .map(|phrase| normalizer.normalize(phrase))
+            .collect();
+        let state = CacheState {
+            declared: phrases.iter().cloned().collect(),
+            ..CacheState::default()
+        };
+        let cache = Self {
+            synthesizer: config.synthesizer,
+            state: Arc::new(Mutex::new(state)),
+            capacity: config.capacity,
+            max_length: config.max_length,
+            player: Mutex::new(None),
+        };
+        let synthesizer = cache.synthesizer.clone();
+        let state = cache.state.clone();
+        thread::spawn(move || {
+            for phrase in phrases {
+                synthesize(synthesizer.as_ref(), &state, phrase, None);
+            }
+        });
+        cache
+    }
+
+    /// The audio of the phrase if it was synthesized. Otherwise it is synthesized in the
+    /// background for the next time, if it is short enough.
+    fn get(&self, phrase: &str) -> Option<Arc<[u8]>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(audio) = state.audio.get(phrase) {
+            return Some(audio.clone());
+        }
+        let cacheable = phrase.chars().count() <= self.max_length
+            && (self.capacity > 0 || state.declared.contains(phrase));
+        if cacheable && state.pending.insert(phrase.to_string()) {
+            let synthesizer = self.synthesizer.clone();
+            let shared = self.state.clone();
+            let phrase = phrase.to_string();
+            let capacity = self.capacity;
+            thread::spawn(move || {
+                synthesize(synthesizer.as_ref(), &shared, phrase, Some(capacity))
+            });
+        }
+        None
+    }
+
+    fn play(&self, audio: Arc<[u8]>) -> Result<(), SoundError> {
+        let mut player = self.player.lock().unwrap();
+        match player.as_ref() {
+            Some(player) => player.play_and_wait(audio),
+            None => player.insert(SoundPlayer::new()?).play_and_wait(audio),
+        }
+    }
+
+    /// Play the phrase on another thread once the delay is over, if it is in the cache.
+    fn play_later(&self, phrase: &str, delay: Duration) -> Option<DelayedPhrase> {
+        let audio = self.get(phrase)?;
+        let (cancel, cancelled) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            if cancelled.recv_timeout(delay) != Err(RecvTimeoutError::Timeout) {
+                return;
+            }
+            // The player of the cache can't leave the thread of the assistant
+            if let Err(e) = SoundPlayer::new().and_then(|player| player.play_and_wait(audio)) {
+                warn!("Failed to play the cached phrase: {}", e);
+            }
+        });
+        Some(DelayedPhrase { cancel, thread })
+    }
+
+    /// Drop the phrases that weren't declared, e.g. because the voice changed.
+    pub(crate) fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        let CacheState { audio, spoken, .. } = &mut *state;
+        for phrase in spoken.drain(..) {
+            audio.remove(&phrase);
+        }
+    }
+}
+
+/// Like [crate::tts::tts_speak], but plays the phrase from the cache if it is in it and waits for
+/// it to finish. Nothing is said by assistants without a TTS backend. Phrases said by the backend
+/// are timed with the `utterances`.
+pub(crate) fn speak(
+    tts: Option<&mut Tts>,
+    utterances: Option<&Utterances>,
+    normalizer: &Normalizer,
+    cache: Option<&TtsCache>,
+    text: impl Into<String>,
+) -> Result<(), TtsError> {
+    let Some(tts) = tts else {
+        return Ok(());
+    };
+    let text = normalizer.normalize(&text.into());
+    if let Some((cache, audio)) = cache.and_then(|cache| Some((cache, cache.get(&text)?))) {
+        // Interrupt what is being said, like the backend does
+        tts.stop()?;
+        match cache.play(audio) {
+            Ok(()) => return Ok(()),
+            Err(e) => warn!("Failed to play the cached phrase \"{}\": {}", text, e),
+        }
+    }
+    if let Some(utterances) = utterances {
+        utterances.requested();
+    }
+    tts.speak(text, true).map(|_| ())
+}
+
+/// A cached phrase played after a delay unless it is cancelled first, see [speak_later].
+pub(crate) struct DelayedPhrase {
+    cancel: mpsc::Sender<()>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl DelayedPhrase {
+    /// Cancel the phrase if it wasn't played yet, otherwise wait for it to finish.
+    pub(crate) fn finish(self) {
+        _ = self.cancel.send(());
+        _ = self.thread.join();
+    }
+}
+
+/// Like [speak], but the phrase is only played if [DelayedPhrase::finish] wasn't called before
+/// the delay is over. Only cached phrases can be played while the assistant is busy, others are
+/// said right away.
+pub(crate) fn speak_later(
+    tts: Option<&mut Tts>,
+    utterances: Option<&Utterances>,
+    normalizer: &Normalizer,
+    cache: Option<&TtsCache>,
+    text: impl Into<String>,
+    delay: Duration,
+) -> Result<Option<DelayedPhrase>, TtsError> {
+    let text = text.into();
+    let normalized = normalizer.normalize(&text);
+    if let Some(delayed) = cache.and_then(|cache| cache.play_later(&normalized, delay)) {
+        return Ok(Some(delayed));
+    }
+    speak(tts, utterances, normalizer, cache, text).map(|_| None)
+}
+
+/// Synthesize a phrase into the cache. Phrases that weren't declared are dropped once there are
+/// more than `capacity` of them.
+fn synthesize(
+    synthesizer: &dyn Synthesizer,
+    state: &Mutex<CacheState>,
+    phrase: String,
+    capacity: Option<usize>,
+) {
+    let audio = synthesizer.synthesize(&phrase);
+    let mut state = state.lock().unwrap();
+    state.pending.remove(&phrase);
+    let audio = match audio {
+        Ok(audio) => audio,
+        Err(e) => {
+            warn!("Failed to synthesize \"{}\": {}", phrase, e);
+            return;
+        }
+    };
+    if let (Some(capacity), false) = (capacity, state.declared.contains(&phrase)) {
+        state.spoken.push_back(phrase.clone());
+        while state.spoken.len() > capacity {
+            if let Some(oldest) = state.spoken.pop_front() {
+                state.audio.remove(&oldest);
+            }
+        }
+    }
+    state.audio.insert(phrase, audio.into());
+}
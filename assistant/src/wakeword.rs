@@ -1,123 +1,338 @@
-use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    BuildStreamError, SampleRate, SizedSample,
+#[cfg(feature = "rustpotter")]
+use rustpotter::{Rustpotter, RustpotterConfig, SampleFormat};
+use std::{
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
 };
-use rustpotter::{Rustpotter, RustpotterConfig, Sample, SampleFormat, ScoreMode};
-use std::sync::mpsc;
 use thiserror::Error;
+#[cfg(feature = "tokio")]
+use tokio::sync::Notify;
+
+#[cfg(feature = "rustpotter")]
+use crate::ring::RingBuffer;
+use crate::{
+    audio::{AudioFormat, AudioSource, AudioTimestamp},
+    power::{EnergyGate, PowerCounters, PowerMode, PowerStats},
+    shadow::{ShadowDivergence, WakewordComparator},
+};
+
+#[cfg(feature = "rustpotter")]
+pub mod trainer;
+
+/// How many blocks of audio can wait for the wakeword engine. Blocks are dropped if it falls
+/// further behind.
+const WAKEWORD_QUEUED_BLOCKS: usize = 64;
+/// Blocks allocated up front for the audio thread to fill, so it doesn't allocate while the
+/// engine keeps up.
+const WAKEWORD_PREALLOCATED_BLOCKS: usize = 8;
+/// Interleaved samples per preallocated block, more than devices give at once.
+const WAKEWORD_BLOCK_CAPACITY: usize = 8192;
+
+/// A wakeword detector. [RustpotterEngine] is used by default, other engines can be set with
+/// [WakewordConfig::set_engine].
+pub trait WakewordEngine: Send {
+    /// Add a wakeword from a file in the format of the engine. The name is used to identify the
+    /// wakeword when it is detected.
+    fn add_wakeword_from_file(
+        &mut self,
+        name: &str,
+        path: &str,
+    ) -> Result<(), WakewordConfigAddError>;
+
+    /// Add a wakeword from the contents of a file in the format of the engine, e.g. embedded with
+    /// `include_bytes!` or downloaded.
+    fn add_wakeword_from_bytes(
+        &mut self,
+        _name: &str,
+        _bytes: &[u8],
+    ) -> Result<(), WakewordConfigAddError> {
+        Err(WakewordConfigAddError(
+            "The wakeword engine can't load wakewords from bytes".to_string(),
+        ))
+    }
+
+    /// Process a block of audio in the format of the [AudioSource] the listener is started on and
+    /// return the detected wakewords.
+    fn process(&mut self, samples: &[f32]) -> Vec<WakewordDetection>;
+
+    /// Apply new detector settings while listening, see
+    /// [WakewordListener::set_detector_settings]. Engines without such settings ignore them.
+    #[cfg(feature = "rustpotter")]
+    fn set_detector_settings(&mut self, _settings: &DetectorSettings) {}
+}
+
+/// A detected wakeword. Engines that don't score their detections report a score and gain of 1.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WakewordDetection {
+    pub name: String,
+    /// The score of the detection, compared with [DetectorSettings::set_threshold].
+    pub score: f32,
+    /// The score against the averaged template, compared with
+    /// [DetectorSettings::set_avg_threshold].
+    pub avg_score: f32,
+    /// The gain the gain normalizer applied to the audio, 1 if it is disabled.
+    pub gain: f32,
+    /// The end of the block of audio the wakeword was detected in, set by the
+    /// [WakewordListener].
+    pub timestamp: Option<AudioTimestamp>,
+}
+
+impl WakewordDetection {
+    /// A detection of an engine that doesn't score them.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            score: 1.,
+            avg_score: 1.,
+            gain: 1.,
+            timestamp: None,
+        }
+    }
+}
+
+/// How the score of a detection is computed from the scores against the templates of a
+/// wakeword, see [DetectorSettings::set_score_mode].
+#[cfg(feature = "rustpotter")]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScoreMode {
+    Average,
+    Max,
+    Median,
+    P25,
+    P50,
+    P75,
+    P80,
+    P90,
+    P95,
+}
+
+#[cfg(feature = "rustpotter")]
+impl From<ScoreMode> for rustpotter::ScoreMode {
+    fn from(mode: ScoreMode) -> Self {
+        match mode {
+            ScoreMode::Average => Self::Average,
+            ScoreMode::Max => Self::Max,
+            ScoreMode::Median => Self::Median,
+            ScoreMode::P25 => Self::P25,
+            ScoreMode::P50 => Self::P50,
+            ScoreMode::P75 => Self::P75,
+            ScoreMode::P80 => Self::P80,
+            ScoreMode::P90 => Self::P90,
+            ScoreMode::P95 => Self::P95,
+        }
+    }
+}
+
+/// A band-pass filter applied to the audio before detection, in Hz.
+#[cfg(feature = "rustpotter")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct BandPass {
+    pub low_cutoff: f32,
+    pub high_cutoff: f32,
+}
+
+#[cfg(feature = "rustpotter")]
+impl Default for BandPass {
+    fn default() -> Self {
+        Self {
+            low_cutoff: 80.,
+            high_cutoff: 400.,
+        }
+    }
+}
+
+/// Normalizes the loudness of the audio before detection, for microphones far from the speaker.
+#[cfg(feature = "rustpotter")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct GainNormalizer {
+    /// The loudness to normalize to, the one of the wakeword files if `None`.
+    pub gain_ref: Option<f32>,
+    pub min_gain: f32,
+    pub max_gain: f32,
+}
+
+#[cfg(feature = "rustpotter")]
+impl Default for GainNormalizer {
+    fn default() -> Self {
+        Self {
+            gain_ref: None,
+            min_gain: 0.1,
+            max_gain: 1.,
+        }
+    }
+}
+
+/// The settings of the Rustpotter detector, see [WakewordConfig::set_detector_settings]. The
+/// defaults are the ones of rustpotter-cli.
+#[cfg(feature = "rustpotter")]
+#[derive(Clone, Debug, PartialEq)]
+pub struct DetectorSettings {
+    threshold: f32,
+    avg_threshold: f32,
+    min_scores: usize,
+    score_ref: f32,
+    score_mode: ScoreMode,
+    band_pass: Option<BandPass>,
+    gain_normalizer: Option<GainNormalizer>,
+}
+
+#[cfg(feature = "rustpotter")]
+impl Default for DetectorSettings {
+    fn default() -> Self {
+        Self {
+            threshold: 0.5,
+            avg_threshold: 0.,
+            min_scores: 10,
+            score_ref: 0.22,
+            score_mode: ScoreMode::Max,
+            band_pass: None,
+            gain_normalizer: None,
+        }
+    }
+}
+
+#[cfg(feature = "rustpotter")]
+impl DetectorSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The lowest score for a detection, 0.5 by default. Higher values give fewer false
+    /// detections but miss more wakewords.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold;
+    }
+
+    /// The lowest score against the averaged template for the other templates to be scored.
+    /// 0, the default, scores all of them.
+    pub fn set_avg_threshold(&mut self, avg_threshold: f32) {
+        self.avg_threshold = avg_threshold;
+    }
+
+    /// How many frames in a row have to score above the threshold, 10 by default.
+    pub fn set_min_scores(&mut self, min_scores: usize) {
+        self.min_scores = min_scores;
+    }
+
+    /// How the distances to the templates are turned into scores, 0.22 by default. Higher
+    /// values give higher scores for the same audio.
+    pub fn set_score_ref(&mut self, score_ref: f32) {
+        self.score_ref = score_ref;
+    }
+
+    pub fn threshold(&self) -> f32 {
+        self.threshold
+    }
+
+    pub fn avg_threshold(&self) -> f32 {
+        self.avg_threshold
+    }
+
+    pub fn min_scores(&self) -> usize {
+        self.min_scores
+    }
+
+    pub fn score_ref(&self) -> f32 {
+        self.score_ref
+    }
+
+    /// [ScoreMode::Max] by default.
+    pub fn set_score_mode(&mut self, score_mode: ScoreMode) {
+        self.score_mode = score_mode;
+    }
+
+    /// Disabled by default.
+    pub fn set_band_pass(&mut self, band_pass: Option<BandPass>) {
+        self.band_pass = band_pass;
+    }
+
+    /// Disabled by default.
+    pub fn set_gain_normalizer(&mut self, gain_normalizer: Option<GainNormalizer>) {
+        self.gain_normalizer = gain_normalizer;
+    }
+}
 
 /// WakewordConfig can be used to configure the wakeword listener. It can be created by calling
 /// [WakewordConfig::build]. Wakewords can be added by calling [WakewordConfig::add_wakeword_from_file] and the
 /// listener can be started by calling [WakewordConfig::start].
 pub struct WakewordConfig {
-    rustpotter: Rustpotter,
-    input_device: cpal::Device,
-    input_config: cpal::SupportedStreamConfig,
-    stream_config: cpal::StreamConfig,
+    engine: Option<Box<dyn WakewordEngine>>,
+    shadow_engine: Option<Box<dyn WakewordEngine>>,
     wakeword_added: bool,
+    format: AudioFormat,
+    power_mode: PowerMode,
 }
 
 #[derive(Error, Debug)]
 pub enum WakewordConfigBuildError {
-    #[error("No input device available")]
-    NoInputDevice,
-    #[error("No default input config available")]
-    NoDefaultInputConfig(#[from] cpal::DefaultStreamConfigError),
-    #[error("Failed to list input configs")]
-    ListInputConfigs(#[from] cpal::SupportedStreamConfigsError),
-    #[error("Failed to get a supported input config")]
-    GetSupportedInputConfig,
-    #[error("Wrong sample format size")]
-    WrongSampleFormatSize,
+    #[cfg(feature = "rustpotter")]
     #[error("Failed to create Rustpotter")]
     CreateRustpotter(String),
 }
 
 #[derive(Error, Debug)]
 pub enum WakewordConfigStartError {
-    #[error("Failed to init input stream")]
-    InitInputStream(#[from] BuildStreamError),
-    #[error("Failed to play stream")]
-    PlayStream(#[from] cpal::PlayStreamError),
     #[error("No wakewords added")]
     NoWakewordsAdded,
 }
 
 #[derive(Error, Debug)]
 #[error("Failed to add wakeword: {0}")]
-pub struct WakewordConfigAddError(String);
+pub struct WakewordConfigAddError(pub String);
 
 impl WakewordConfig {
-    /// Create a new WakewordConfig. This function will try to find a compatible input device and
-    /// configuration. If no compatible configuration is found, it will return an error.
-    pub fn build() -> Result<Self, WakewordConfigBuildError> {
-        let host = cpal::default_host();
-        let input_device = host
-            .default_input_device()
-            .ok_or(WakewordConfigBuildError::NoInputDevice)?;
-
-        let default_input_config = input_device.default_input_config()?;
-
-        let input_config = if is_compatible_format(&default_input_config.sample_format()) {
-            default_input_config
-        } else {
-            // look for any compatible configuration
-            input_device
-                .supported_input_configs()?
-                .find(|sc| is_compatible_format(&sc.sample_format()))
-                .map(|sc| try_get_config_with_sample_rate(sc, 16000))
-                .ok_or(WakewordConfigBuildError::GetSupportedInputConfig)?
-        };
+    /// Create a new WakewordConfig for audio in the given format, usually the format of the
+    /// [AudioSource] the listener will be started on. Without the `rustpotter` feature there is no
+    /// default engine and one has to be set with [WakewordConfig::set_engine].
+    pub fn build(format: AudioFormat) -> Result<Self, WakewordConfigBuildError> {
+        #[cfg(feature = "rustpotter")]
+        let engine: Option<Box<dyn WakewordEngine>> =
+            Some(Box::new(RustpotterEngine::new(format)?));
+        #[cfg(not(feature = "rustpotter"))]
+        let engine = None;
 
-        let stream_config = cpal::StreamConfig {
-            channels: input_config.channels(),
-            sample_rate: input_config.sample_rate(),
-            buffer_size: cpal::BufferSize::Default,
-        };
+        Ok(WakewordConfig {
+            engine,
+            shadow_engine: None,
+            wakeword_added: false,
+            format,
+            power_mode: PowerMode::AlwaysOn,
+        })
+    }
 
-        let bits_per_sample = (input_config.sample_format().sample_size() * 8) as u16;
-        let mut config = RustpotterConfig::default();
+    /// Replace the wakeword engine. Wakewords added before are removed.
+    pub fn set_engine(&mut self, engine: impl WakewordEngine + 'static) {
+        self.engine = Some(Box::new(engine));
+        self.wakeword_added = false;
+    }
 
-        config.fmt.sample_rate = input_config.sample_rate().0 as usize;
-        config.fmt.channels = input_config.channels();
-        config.fmt.sample_format = if input_config.sample_format().is_float() {
-            SampleFormat::float_of_size(bits_per_sample)
-        } else {
-            SampleFormat::int_of_size(bits_per_sample)
-        }
-        .ok_or(WakewordConfigBuildError::WrongSampleFormatSize)?;
-
-        // Defaults from rustpotter-cli
-        config.detector.avg_threshold = 0.;
-        config.detector.threshold = 0.5;
-        config.detector.min_scores = 10;
-        config.detector.eager = true;
-        config.detector.score_mode = ScoreMode::Max;
-        config.detector.score_ref = 0.22;
-        config.detector.vad_mode = None;
-        // config.detector.record_path = None; // Requires `record` feature
-        config.filters.gain_normalizer.enabled = false;
-        config.filters.gain_normalizer.gain_ref = None;
-        config.filters.gain_normalizer.min_gain = 0.1;
-        config.filters.gain_normalizer.max_gain = 1.;
-        config.filters.band_pass.enabled = false;
-        config.filters.band_pass.low_cutoff = 80.;
-        config.filters.band_pass.high_cutoff = 400.;
+    /// Use a Rustpotter engine with these settings, to tune the sensitivity to the environment.
+    /// Wakewords added before are removed.
+    #[cfg(feature = "rustpotter")]
+    pub fn set_detector_settings(
+        &mut self,
+        settings: &DetectorSettings,
+    ) -> Result<(), WakewordConfigBuildError> {
+        self.set_engine(RustpotterEngine::with_settings(self.format, settings)?);
+        Ok(())
+    }
 
-        let rustpotter =
-            Rustpotter::new(&config).map_err(WakewordConfigBuildError::CreateRustpotter)?;
+    /// Run a second engine, with its wakewords already added, on the same audio without acting on
+    /// its detections. Detections that only one of the engines made are received with
+    /// [WakewordListener::shadow_divergences].
+    pub fn set_shadow_engine(&mut self, engine: impl WakewordEngine + 'static) {
+        self.shadow_engine = Some(Box::new(engine));
+    }
 
-        Ok(WakewordConfig {
-            rustpotter,
-            input_device,
-            input_config,
-            stream_config,
-            wakeword_added: false,
-        })
+    /// Select how the engines are run. With [PowerMode::LowPower] they only get the audio while
+    /// there is sound, see [WakewordListener::power_stats] for how much was processed.
+    pub fn set_power_mode(&mut self, mode: PowerMode) {
+        self.power_mode = mode;
     }
 
-    /// Add a wakeword from a file. The file should be in the Rustpotter Wakeword format.
-    /// The name is used to identify the wakeword when it is detected.
+    /// Add a wakeword from a file, in the format of the engine. The name is used to identify the
+    /// wakeword when it is detected.
     /// This function will return an error if the file could not be read or if the wakeword could
     /// not be added.
     pub fn add_wakeword_from_file(
@@ -125,135 +340,350 @@ impl WakewordConfig {
         name: &str,
         path: &str,
     ) -> Result<(), WakewordConfigAddError> {
-        self.rustpotter
-            .add_wakeword_from_file(name, path)
-            .map_err(WakewordConfigAddError)?;
+        self.engine_mut()?.add_wakeword_from_file(name, path)?;
+        self.wakeword_added = true;
+        Ok(())
+    }
+
+    /// Add a wakeword from the contents of a file in the format of the engine, so wakewords can
+    /// be embedded in the application with `include_bytes!` or downloaded.
+    pub fn add_wakeword_from_bytes(
+        &mut self,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<(), WakewordConfigAddError> {
+        self.engine_mut()?.add_wakeword_from_bytes(name, bytes)?;
         self.wakeword_added = true;
         Ok(())
     }
 
-    /// Start listening for wakewords. This function will return a WakewordListener that can be
-    /// used to listen for wakewords.
-    pub fn start(self) -> Result<WakewordListener, WakewordConfigStartError> {
-        if !self.wakeword_added {
+    fn engine_mut(&mut self) -> Result<&mut Box<dyn WakewordEngine>, WakewordConfigAddError> {
+        self.engine
+            .as_mut()
+            .ok_or_else(|| WakewordConfigAddError("No wakeword engine set".to_string()))
+    }
+
+    /// Start listening for wakewords on the given audio input. This function will return a
+    /// WakewordListener that can be used to listen for wakewords. The engines run on a thread of
+    /// their own until the audio input is dropped.
+    pub fn start(
+        self,
+        input: &dyn AudioSource,
+    ) -> Result<WakewordListener, WakewordConfigStartError> {
+        let Some(mut engine) = self.engine.filter(|_| self.wakeword_added) else {
             return Err(WakewordConfigStartError::NoWakewordsAdded);
-        }
+        };
 
         let (tx, rx) = mpsc::channel();
+        let (divergence_tx, divergences) = mpsc::channel();
+        #[cfg(feature = "rustpotter")]
+        let (settings, new_settings) = mpsc::channel::<DetectorSettings>();
+        let mut shadow_engine = self.shadow_engine;
+        let mut comparator = WakewordComparator::default();
+        let counters = Arc::new(PowerCounters::default());
+        let mut gate = EnergyGate::new(self.power_mode, self.format, counters.clone());
+        #[cfg(feature = "tokio")]
+        let notify = Arc::new(Notify::new());
+        #[cfg(feature = "tokio")]
+        let notify_detected = notify.clone();
+        let channels = usize::from(self.format.channels.max(1));
 
-        let stream = match self.input_config.sample_format() {
-            cpal::SampleFormat::I16 => init_input_stream(
-                &self.input_device,
-                self.stream_config,
-                self.rustpotter,
-                Vec::<i16>::new(),
-                tx,
-            )?,
-            cpal::SampleFormat::I32 => init_input_stream(
-                &self.input_device,
-                self.stream_config,
-                self.rustpotter,
-                Vec::<i32>::new(),
-                tx,
-            )?,
-            cpal::SampleFormat::F32 => init_input_stream(
-                &self.input_device,
-                self.stream_config,
-                self.rustpotter,
-                Vec::<f32>::new(),
-                tx,
-            )?,
-            _ => panic!("The only supported sample formats are i16, i32 and f32. This should never happen, because we already checked for this in WakewordConfig::build."),
-        };
+        // The engines process the audio on their own thread, where they can take their time and
+        // allocate without making the audio input miss its deadlines. Blocks are sent back once
+        // processed and reused, like for speech recognition.
+        let (blocks, received) =
+            mpsc::sync_channel::<(AudioTimestamp, Vec<f32>)>(WAKEWORD_QUEUED_BLOCKS);
+        let (free, free_rx) = mpsc::sync_channel(WAKEWORD_QUEUED_BLOCKS);
+        for _ in 0..WAKEWORD_PREALLOCATED_BLOCKS {
+            _ = free.try_send(Vec::with_capacity(WAKEWORD_BLOCK_CAPACITY));
+        }
+        input.subscribe(Box::new(move |data, mut end| {
+            end.frame += (data.len() / channels) as u64;
+            // Only allocates if all the blocks are queued
+            let mut block: Vec<f32> = free_rx.try_recv().unwrap_or_default();
+            block.clear();
+            block.extend_from_slice(data);
+            _ = blocks.try_send((end, block));
+        }));
 
-        stream.play()?;
+        thread::spawn(move || {
+            for (end, block) in received {
+                #[cfg(feature = "rustpotter")]
+                if let Some(settings) = new_settings.try_iter().last() {
+                    engine.set_detector_settings(&settings);
+                }
+                gate.process(&block, |data| {
+                    #[cfg(feature = "tracing")]
+                    let started = Instant::now();
+                    let detected = engine.process(data);
+                    #[cfg(feature = "tracing")]
+                    tracing::trace!(
+                        samples = data.len(),
+                        micros = started.elapsed().as_micros() as u64,
+                        "processed wakeword audio"
+                    );
+                    if let Some(shadow_engine) = &mut shadow_engine {
+                        let names = |detections: &[WakewordDetection]| {
+                            detections.iter().map(|d| d.name.clone()).collect()
+                        };
+                        let shadow = shadow_engine.process(data);
+                        let divergences =
+                            comparator.compare(names(&detected), names(&shadow), Instant::now());
+                        for divergence in divergences {
+                            _ = divergence_tx.send(divergence);
+                        }
+                    }
+                    for mut wakeword in detected {
+                        wakeword.timestamp = Some(end);
+                        _ = tx.send(wakeword);
+                        #[cfg(feature = "tokio")]
+                        notify_detected.notify_one();
+                    }
+                });
+                _ = free.try_send(block);
+            }
+        });
 
-        Ok(WakewordListener { rx, stream })
+        Ok(WakewordListener {
+            rx,
+            divergences,
+            counters,
+            #[cfg(feature = "rustpotter")]
+            settings,
+            #[cfg(feature = "tokio")]
+            notify,
+        })
     }
 }
 
 /// WakewordListener can be used to listen for wakewords and can only be created by
 /// calling [WakewordConfig::start].
 pub struct WakewordListener {
-    rx: mpsc::Receiver<String>,
-    #[allow(dead_code)]
-    stream: cpal::Stream,
+    rx: mpsc::Receiver<WakewordDetection>,
+    divergences: mpsc::Receiver<ShadowDivergence>,
+    counters: Arc<PowerCounters>,
+    #[cfg(feature = "rustpotter")]
+    settings: mpsc::Sender<DetectorSettings>,
+    #[cfg(feature = "tokio")]
+    notify: Arc<Notify>,
 }
 
 impl WakewordListener {
     /// Listen for wakewords. This function will block until a wakeword is detected.
-    pub fn listen(&self) -> Result<String, mpsc::RecvError> {
+    pub fn listen(&self) -> Result<WakewordDetection, mpsc::RecvError> {
         self.rx.recv()
     }
 
+    /// Like [WakewordListener::listen], but gives up after `timeout`.
+    pub fn listen_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<WakewordDetection, mpsc::RecvTimeoutError> {
+        self.rx.recv_timeout(timeout)
+    }
+
+    /// The divergences from the shadow engine since the last call, see
+    /// [WakewordConfig::set_shadow_engine].
+    pub fn shadow_divergences(&self) -> mpsc::TryIter<'_, ShadowDivergence> {
+        self.divergences.try_iter()
+    }
+
+    /// How much of the captured audio the engines processed, see
+    /// [WakewordConfig::set_power_mode].
+    pub fn power_stats(&self) -> PowerStats {
+        self.counters.stats()
+    }
+
+    /// Change the settings of the detector while listening, e.g. to tune it to the room. They
+    /// are applied from the next block of audio, and the wakewords are kept.
+    #[cfg(feature = "rustpotter")]
+    pub fn set_detector_settings(&self, settings: DetectorSettings) {
+        // The thread of the engine only goes away with the audio input
+        _ = self.settings.send(settings);
+    }
+
+    /// The wakewords detected since the last call, without waiting.
+    pub fn try_iter(&self) -> mpsc::TryIter<'_, WakewordDetection> {
+        self.rx.try_iter()
+    }
+
+    /// Drop the wakewords that were detected but not received yet.
+    pub fn drain(&self) {
+        self.rx.try_iter().for_each(drop);
+    }
+
     /// Returns an iterator over detected wakewords.
-    pub fn listen_iter(&self) -> mpsc::Iter<String> {
+    pub fn listen_iter(&self) -> mpsc::Iter<'_, WakewordDetection> {
         self.rx.iter()
     }
+
+    /// Like [WakewordListener::listen], but waits without blocking the thread. Enabled with the
+    /// `tokio` feature.
+    #[cfg(feature = "tokio")]
+    pub async fn listen_async(&self) -> Result<WakewordDetection, mpsc::RecvError> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(wakeword) => return Ok(wakeword),
+                Err(mpsc::TryRecvError::Empty) => self.notify.notified().await,
+                Err(mpsc::TryRecvError::Disconnected) => return Err(mpsc::RecvError),
+            }
+        }
+    }
 }
 
-fn is_compatible_format(format: &cpal::SampleFormat) -> bool {
-    matches!(
-        format,
-        cpal::SampleFormat::I16 | cpal::SampleFormat::I32 | cpal::SampleFormat::F32
-    )
+/// The default [WakewordEngine], detecting wakewords in the Rustpotter wakeword format.
+#[cfg(feature = "rustpotter")]
+pub struct RustpotterEngine {
+    rustpotter: Rustpotter,
+    buffer: RingBuffer<f32>,
+    // The frame given to Rustpotter, as samples and as the little endian bytes it reads
+    frame: Box<[f32]>,
+    frame_bytes: Box<[u8]>,
+    format: AudioFormat,
 }
 
-fn try_get_config_with_sample_rate(
-    sc: cpal::SupportedStreamConfigRange,
-    preferred_sample_rate: u32,
-) -> cpal::SupportedStreamConfig {
-    if sc.min_sample_rate().0 <= preferred_sample_rate
-        && preferred_sample_rate <= sc.max_sample_rate().0
-    {
-        sc.with_sample_rate(SampleRate(preferred_sample_rate))
-    } else {
-        sc.with_max_sample_rate()
+#[cfg(feature = "rustpotter")]
+impl RustpotterEngine {
+    pub fn new(format: AudioFormat) -> Result<Self, WakewordConfigBuildError> {
+        Self::with_settings(format, &DetectorSettings::default())
     }
-}
 
-fn init_input_stream<S: Sample + SizedSample>(
-    device: &cpal::Device,
-    config: cpal::StreamConfig,
-    mut rustpotter: Rustpotter,
-    mut buffer: Vec<S>,
-    mut tx: mpsc::Sender<String>,
-) -> Result<cpal::Stream, BuildStreamError> {
-    let error_callback = move |err| {
-        eprintln!("an error occurred on stream: {}", err);
-    };
-
-    let rustpotter_samples_per_frame = rustpotter.get_samples_per_frame();
-    let data_callback = move |data: &[S], _: &_| {
-        run_detection(
-            &mut rustpotter,
-            data,
-            &mut buffer,
-            rustpotter_samples_per_frame,
-            &mut tx,
-        )
-    };
-    device.build_input_stream(&config, data_callback, error_callback, None)
+    pub fn with_settings(
+        format: AudioFormat,
+        settings: &DetectorSettings,
+    ) -> Result<Self, WakewordConfigBuildError> {
+        let config = detector_config(
+            format.sample_rate as usize,
+            format.channels,
+            SampleFormat::F32,
+            settings,
+        );
+        let rustpotter =
+            Rustpotter::new(&config).map_err(WakewordConfigBuildError::CreateRustpotter)?;
+
+        // Room for a frame plus the block that completes it
+        let samples_per_frame = rustpotter.get_samples_per_frame();
+        Ok(Self {
+            buffer: RingBuffer::new(samples_per_frame * 4),
+            frame: vec![0.; samples_per_frame].into_boxed_slice(),
+            frame_bytes: vec![0; rustpotter.get_bytes_per_frame()].into_boxed_slice(),
+            rustpotter,
+            format,
+        })
+    }
 }
 
-fn run_detection<T: Sample>(
-    rustpotter: &mut Rustpotter,
-    data: &[T],
-    buffer: &mut Vec<T>,
-    rustpotter_samples_per_frame: usize,
-    tx: &mut mpsc::Sender<String>,
-) {
-    buffer.extend_from_slice(data);
-    while buffer.len() >= rustpotter_samples_per_frame {
-        let detection = rustpotter.process_samples(
-            buffer
-                .drain(0..rustpotter_samples_per_frame)
-                .as_slice()
-                .into(),
-        );
-        if let Some(detection) = detection {
-            // println!("Wakeword detection: {:?}", detection);
-            tx.send(detection.name).unwrap();
+#[cfg(feature = "rustpotter")]
+impl WakewordEngine for RustpotterEngine {
+    fn add_wakeword_from_file(
+        &mut self,
+        name: &str,
+        path: &str,
+    ) -> Result<(), WakewordConfigAddError> {
+        self.rustpotter
+            .add_wakeword_from_file(name, path)
+            .map_err(WakewordConfigAddError)
+    }
+
+    fn add_wakeword_from_bytes(
+        &mut self,
+        name: &str,
+        bytes: &[u8],
+    ) -> Result<(), WakewordConfigAddError> {
+        self.rustpotter
+            .add_wakeword_from_buffer(name, bytes)
+            .map_err(WakewordConfigAddError)
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Vec<WakewordDetection> {
+        let mut detections = Vec::new();
+        let mut samples = samples;
+        while !samples.is_empty() {
+            let pushed = self.buffer.push(samples);
+            samples = &samples[pushed..];
+            while self.buffer.pop_into(&mut self.frame) {
+                // Rustpotter only borrows frames given as bytes, samples would be moved
+                for (bytes, sample) in self.frame_bytes.chunks_exact_mut(4).zip(&self.frame[..]) {
+                    bytes.copy_from_slice(&sample.to_le_bytes());
+                }
+                if let Some(detection) = self.rustpotter.process_bytes(&self.frame_bytes) {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(
+                        name = %detection.name,
+                        score = detection.score,
+                        avg_score = detection.avg_score,
+                        gain = detection.gain,
+                        counter = detection.counter,
+                        "wakeword detected"
+                    );
+                    detections.push(WakewordDetection {
+                        name: detection.name,
+                        score: detection.score,
+                        avg_score: detection.avg_score,
+                        gain: detection.gain,
+                        timestamp: None,
+                    });
+                } else {
+                    // Scores of wakewords that may be detected with the next frames
+                    #[cfg(feature = "tracing")]
+                    if let Some(partial) = self.rustpotter.get_partial_detection() {
+                        tracing::trace!(
+                            name = %partial.name,
+                            score = partial.score,
+                            avg_score = partial.avg_score,
+                            counter = partial.counter,
+                            "partial wakeword detection"
+                        );
+                    }
+                }
+            }
         }
+        detections
     }
+
+    fn set_detector_settings(&mut self, settings: &DetectorSettings) {
+        self.rustpotter.update_config(&detector_config(
+            self.format.sample_rate as usize,
+            self.format.channels,
+            SampleFormat::F32,
+            settings,
+        ));
+    }
+}
+
+#[cfg(feature = "rustpotter")]
+/// Build the Rustpotter configuration used for detection on audio of the given format.
+pub(crate) fn detector_config(
+    sample_rate: usize,
+    channels: u16,
+    sample_format: SampleFormat,
+    settings: &DetectorSettings,
+) -> RustpotterConfig {
+    let mut config = RustpotterConfig::default();
+
+    config.fmt.sample_rate = sample_rate;
+    config.fmt.channels = channels;
+    config.fmt.sample_format = sample_format;
+
+    config.detector.avg_threshold = settings.avg_threshold;
+    config.detector.threshold = settings.threshold;
+    config.detector.min_scores = settings.min_scores;
+    config.detector.eager = true;
+    config.detector.score_mode = settings.score_mode.into();
+    config.detector.score_ref = settings.score_ref;
+    config.detector.vad_mode = None;
+    // config.detector.record_path = None; // Requires `record` feature
+    let gain_normalizer = settings.gain_normalizer.clone();
+    config.filters.gain_normalizer.enabled = gain_normalizer.is_some();
+    let gain_normalizer = gain_normalizer.unwrap_or_default();
+    config.filters.gain_normalizer.gain_ref = gain_normalizer.gain_ref;
+    config.filters.gain_normalizer.min_gain = gain_normalizer.min_gain;
+    config.filters.gain_normalizer.max_gain = gain_normalizer.max_gain;
+    let band_pass = settings.band_pass.clone();
+    config.filters.band_pass.enabled = band_pass.is_some();
+    let band_pass = band_pass.unwrap_or_default();
+    config.filters.band_pass.low_cutoff = band_pass.low_cutoff;
+    config.filters.band_pass.high_cutoff = band_pass.high_cutoff;
+
+    config
 }
@@ -0,0 +1,130 @@
+use hound::{SampleFormat as WavSampleFormat, WavSpec, WavWriter};
+use rustpotter::{WakewordLoad, WakewordRef, WakewordRefBuildFromBuffers, WakewordSave};
+use std::{collections::HashMap, io::Cursor};
+use thiserror::Error;
+
+use crate::stt::{rms, EndpointConfig, STT_SAMPLE_RATE};
+
+/// Fewer recordings than this give a wakeword that is detected unreliably.
+pub const MIN_SAMPLES: usize = 3;
+
+/// The number of MFCC coefficients of trained wakewords, the default of rustpotter-cli.
+const MFCC_SIZE: u16 = 16;
+
+/// Length of the blocks of audio checked for speech when trimming recordings, 10ms.
+const TRIM_BLOCK: usize = STT_SAMPLE_RATE as usize / 100;
+
+#[derive(Error, Debug)]
+pub enum WakewordTrainError {
+    #[error("At least {MIN_SAMPLES} recordings of the wakeword are needed, got {0}")]
+    NotEnoughSamples(usize),
+    #[error("No speech in the recording")]
+    NoSpeech,
+    #[error("Failed to build the wakeword: {0}")]
+    Build(String),
+    #[error("Failed to save the wakeword: {0}")]
+    Save(String),
+    #[error("Failed to load the wakeword: {0}")]
+    Load(String),
+}
+
+/// Builds a wakeword in the Rustpotter format from recordings of the user saying it, instead of
+/// training it with rustpotter-cli. See [crate::Assistant::train_wakeword] for a guided flow.
+pub struct WakewordTrainer {
+    name: String,
+    samples: Vec<Vec<i16>>,
+}
+
+impl WakewordTrainer {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            samples: Vec::new(),
+        }
+    }
+
+    /// Add a recording of the wakeword, mono at [STT_SAMPLE_RATE]. The silence around it is cut
+    /// off, so recordings from speech recognition can be used as they are.
+    pub fn add_sample(&mut self, audio: &[i16]) -> Result<(), WakewordTrainError> {
+        let threshold = EndpointConfig::default().speech_threshold;
+        let blocks: Vec<&[i16]> = audio.chunks(TRIM_BLOCK).collect();
+        let start = blocks
+            .iter()
+            .position(|block| rms(block) >= threshold)
+            .ok_or(WakewordTrainError::NoSpeech)?;
+        let end = blocks
+            .iter()
+            .rposition(|block| rms(block) >= threshold)
+            .unwrap_or(start);
+        let end = ((end + 1) * TRIM_BLOCK).min(audio.len());
+        self.samples.push(audio[start * TRIM_BLOCK..end].to_vec());
+        Ok(())
+    }
+
+    pub fn sample_count(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Build the wakeword from the recordings, each one being a template it is compared with.
+    pub fn build(&self) -> Result<TrainedWakeword, WakewordTrainError> {
+        if self.samples.len() < MIN_SAMPLES {
+            return Err(WakewordTrainError::NotEnoughSamples(self.samples.len()));
+        }
+        let mut buffers = HashMap::new();
+        for (i, sample) in self.samples.iter().enumerate() {
+            buffers.insert(format!("{}-{}.wav", self.name, i + 1), wav(sample)?);
+        }
+        let wakeword =
+            WakewordRef::new_from_sample_buffers(self.name.clone(), None, None, buffers, MFCC_SIZE)
+                .map_err(WakewordTrainError::Build)?;
+        Ok(TrainedWakeword(wakeword))
+    }
+}
+
+/// A wakeword built by a [WakewordTrainer].
+pub struct TrainedWakeword(WakewordRef);
+
+impl TrainedWakeword {
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Save the wakeword as a `.rpw` file, which can be added with
+    /// [super::WakewordConfig::add_wakeword_from_file].
+    pub fn save(&self, path: &str) -> Result<(), WakewordTrainError> {
+        self.0.save_to_file(path).map_err(WakewordTrainError::Save)
+    }
+
+    /// The contents of the `.rpw` file, see [super::WakewordConfig::add_wakeword_from_bytes].
+    pub fn to_bytes(&self) -> Result<Vec<u8>, WakewordTrainError> {
+        self.0.save_to_buffer().map_err(WakewordTrainError::Save)
+    }
+
+    pub fn load(path: &str) -> Result<Self, WakewordTrainError> {
+        WakewordRef::load_from_file(path)
+            .map(Self)
+            .map_err(WakewordTrainError::Load)
+    }
+}
+
+/// The audio as the bytes of a WAV file, which is what Rustpotter builds wakewords from.
+fn wav(samples: &[i16]) -> Result<Vec<u8>, WakewordTrainError> {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: STT_SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: WavSampleFormat::Int,
+    };
+    let mut bytes = Cursor::new(Vec::new());
+    let mut writer =
+        WavWriter::new(&mut bytes, spec).map_err(|e| WakewordTrainError::Build(e.to_string()))?;
+    for &sample in samples {
+        writer
+            .write_sample(sample)
+            .map_err(|e| WakewordTrainError::Build(e.to_string()))?;
+    }
+    writer
+        .finalize()
+        .map_err(|e| WakewordTrainError::Build(e.to_string()))?;
+    Ok(bytes.into_inner())
+}
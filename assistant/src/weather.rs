@@ -0,0 +1,571 @@
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, Weekday};
+use serde::Deserialize;
+use std::time::Duration;
+use thiserror::Error;
+
+use crate::{
+    http::{HttpClient, HttpError},
+    http_cache::HttpCache,
+    permissions::Capability,
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{Slot, SlotKind, SlotValue},
+    AssistantQuery,
+};
+
+/// A source of weather data. [OpenMeteo] is used by default, other services can implement
+/// this trait and be given to [WeatherSkill::with_provider].
+pub trait WeatherProvider {
+    fn current(&self, location: &WeatherLocation) -> Result<CurrentWeather, WeatherError>;
+
+    /// The forecast for the next days, starting today.
+    fn forecast(
+        &self,
+        location: &WeatherLocation,
+        days: u32,
+    ) -> Result<Vec<DailyForecast>, WeatherError>;
+}
+
+/// Where to get the weather for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WeatherLocation {
+    /// The name used in responses, like the city.
+    pub name: Option<String>,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Units {
+    /// Degrees Celsius and kilometers per hour.
+    #[default]
+    Metric,
+    /// Degrees Fahrenheit and miles per hour.
+    Imperial,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CurrentWeather {
+    pub temperature: f64,
+    pub wind_speed: f64,
+    pub conditions: Conditions,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DailyForecast {
+    pub date: NaiveDate,
+    pub min_temperature: f64,
+    pub max_temperature: f64,
+    /// From 0 to 100, if known.
+    pub precipitation_probability: Option<f64>,
+    pub conditions: Conditions,
+}
+
+/// The weather conditions, in the categories of the WMO weather codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Conditions {
+    Clear,
+    MainlyClear,
+    PartlyCloudy,
+    Overcast,
+    Fog,
+    Drizzle,
+    FreezingDrizzle,
+    Rain,
+    FreezingRain,
+    Snow,
+    RainShowers,
+    SnowShowers,
+    Thunderstorm,
+    Unknown,
+}
+
+impl Conditions {
+    /// Conditions for a WMO weather code, as returned by most weather services.
+    pub fn from_wmo_code(code: u32) -> Self {
+        match code {
+            0 => Conditions::Clear,
+            1 => Conditions::MainlyClear,
+            2 => Conditions::PartlyCloudy,
+            3 => Conditions::Overcast,
+            45 | 48 => Conditions::Fog,
+            51..=55 => Conditions::Drizzle,
+            56 | 57 => Conditions::FreezingDrizzle,
+            61..=65 => Conditions::Rain,
+            66 | 67 => Conditions::FreezingRain,
+            71..=77 => Conditions::Snow,
+            80..=82 => Conditions::RainShowers,
+            85 | 86 => Conditions::SnowShowers,
+            95..=99 => Conditions::Thunderstorm,
+            _ => Conditions::Unknown,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Conditions::Clear => "clear skies",
+            Conditions::MainlyClear => "mostly clear skies",
+            Conditions::PartlyCloudy => "partly cloudy",
+            Conditions::Overcast => "overcast",
+            Conditions::Fog => "foggy",
+            Conditions::Drizzle => "drizzle",
+            Conditions::FreezingDrizzle => "freezing drizzle",
+            Conditions::Rain => "rain",
+            Conditions::FreezingRain => "freezing rain",
+            Conditions::Snow => "snow",
+            Conditions::RainShowers => "rain showers",
+            Conditions::SnowShowers => "snow showers",
+            Conditions::Thunderstorm => "thunderstorms",
+            Conditions::Unknown => "unknown conditions",
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WeatherError {
+    #[error("Weather request failed")]
+    Request(#[from] HttpError),
+    #[error("Failed to read the weather response")]
+    Response(#[from] std::io::Error),
+    #[error("The weather response is missing data")]
+    MissingData,
+}
+
+/// The free Open-Meteo API, which needs no API key.
+pub struct OpenMeteo {
+    units: Units,
+    client: HttpClient,
+    cache: Option<(HttpCache, Duration)>,
+}
+
+impl OpenMeteo {
+    pub fn new(units: Units) -> Self {
+        Self {
+            units,
+            client: HttpClient::default(),
+            cache: None,
+        }
+    }
+
+    /// Make the requests with a shared client, see [crate::AssistantConfig::http_client].
+    pub fn set_client(&mut self, client: HttpClient) {
+        self.client = client;
+    }
+
+    /// Keep the responses in the cache for `ttl`, so that repeated questions are answered
+    /// without a request. Every request is made by default.
+    pub fn set_cache(&mut self, cache: HttpCache, ttl: Duration) {
+        self.cache = Some((cache, ttl));
+    }
+
+    fn request(
+        &self,
+        location: &WeatherLocation,
+        params: &[(&str, String)],
+    ) -> Result<OpenMeteoResponse, WeatherError> {
+        let mut request = self
+            .client
+            .get("https://api.open-meteo.com/v1/forecast")
+            .query("latitude", &location.latitude.to_string())
+            .query("longitude", &location.longitude.to_string())
+            .query("timezone", "auto");
+        if self.units == Units::Imperial {
+            request = request
+                .query("temperature_unit", "fahrenheit")
+                .query("wind_speed_unit", "mph");
+        }
+        for (name, value) in params {
+            request = request.query(name, value);
+        }
+        let fetch =
+            || -> Result<String, WeatherError> { Ok(request.clone().call()?.into_string()?) };
+        let body = match &self.cache {
+            Some((cache, ttl)) => cache.get_or_fetch(&request.url()?, *ttl, fetch)?,
+            None => fetch()?,
+        };
+        Ok(serde_json::from_str(&body).map_err(std::io::Error::from)?)
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoResponse {
+    current: Option<OpenMeteoCurrent>,
+    daily: Option<OpenMeteoDaily>,
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoCurrent {
+    temperature_2m: f64,
+    wind_speed_10m: f64,
+    weather_code: u32,
+}
+
+#[derive(Deserialize)]
+struct OpenMeteoDaily {
+    time: Vec<NaiveDate>,
+    weather_code: Vec<u32>,
+    temperature_2m_min: Vec<f64>,
+    temperature_2m_max: Vec<f64>,
+    precipitation_probability_max: Vec<Option<f64>>,
+}
+
+impl WeatherProvider for OpenMeteo {
+    fn current(&self, location: &WeatherLocation) -> Result<CurrentWeather, WeatherError> {
+        let current = self
+            .request(
+                location,
+                &[(
+                    "current",
+                    "temperature_2m,wind_speed_10m,weather_code".to_string(),
+                )],
+            )?
+            .current
+            .ok_or(WeatherError::MissingData)?;
+        Ok(CurrentWeather {
+            temperature: current.temperature_2m,
+            wind_speed: current.wind_speed_10m,
+            conditions: Conditions::from_wmo_code(current.weather_code),
+        })
+    }
+
+    fn forecast(
+        &self,
+        location: &WeatherLocation,
+        days: u32,
+    ) -> Result<Vec<DailyForecast>, WeatherError> {
+        let daily = self
+            .request(
+                location,
+                &[
+                    (
+                        "daily",
+                        "weather_code,temperature_2m_min,temperature_2m_max,\
+                         precipitation_probability_max"
+                            .to_string(),
+                    ),
+                    ("forecast_days", days.to_string()),
+                ],
+            )?
+            .daily
+            .ok_or(WeatherError::MissingData)?;
+        (0..daily.time.len())
+            .map(|i| {
+                Some(DailyForecast {
+                    date: daily.time[i],
+                    min_temperature: *daily.temperature_2m_min.get(i)?,
+                    max_temperature: *daily.temperature_2m_max.get(i)?,
+                    precipitation_probability: *daily.precipitation_probability_max.get(i)?,
+                    conditions: Conditions::from_wmo_code(*daily.weather_code.get(i)?),
+                })
+            })
+            .collect::<Option<_>>()
+            .ok_or(WeatherError::MissingData)
+    }
+}
+
+const CURRENT_INTENT: &str = "current weather";
+const FORECAST_INTENT: &str = "weather forecast";
+/// Days ahead that can be asked about, including today.
+const FORECAST_DAYS: u32 = 7;
+
+/// A [Skill] answering questions about the current weather and the forecast of the next days.
+/// Enabled with the `weather` feature.
+pub struct WeatherSkill {
+    provider: Box<dyn WeatherProvider>,
+    location: WeatherLocation,
+    units: Units,
+    // The last answers of the provider, used when it can't be reached
+    last_current: Option<Cached<CurrentWeather>>,
+    last_forecast: Option<Cached<Vec<DailyForecast>>>,
+}
+
+/// Weather returned by the provider for a location, and when.
+struct Cached<T> {
+    location: WeatherLocation,
+    weather: T,
+    at: DateTime<Local>,
+}
+
+impl<T> Cached<T> {
+    fn get<'a>(
+        cached: &'a Option<Self>,
+        location: &WeatherLocation,
+    ) -> Option<(&'a T, DateTime<Local>)> {
+        cached
+            .as_ref()
+            .filter(|cached| cached.location == *location)
+            .map(|cached| (&cached.weather, cached.at))
+    }
+}
+
+impl WeatherSkill {
+    /// Get the weather from [OpenMeteo].
+    pub fn new(location: WeatherLocation, units: Units) -> Self {
+        Self::with_provider(OpenMeteo::new(units), location, units)
+    }
+
+    /// Get the weather from another service. The provider has to return values in `units`.
+    pub fn with_provider(
+        provider: impl WeatherProvider + 'static,
+        location: WeatherLocation,
+        units: Units,
+    ) -> Self {
+        Self {
+            provider: Box::new(provider),
+            location,
+            units,
+            last_current: None,
+            last_forecast: None,
+        }
+    }
+
+    /// The answer to a query, from the provider if `online` and otherwise, or if the provider
+    /// fails, from the last weather it returned for the location.
+    fn answer(
+        &mut self,
+        ctx: &SkillContext,
+        query: &AssistantQuery<'_, str>,
+        online: bool,
+    ) -> Option<String> {
+        // The speaker's own city comes before the default one
+        let location = ctx
+            .speaker_preferences()
+            .and_then(|preferences| preferences.weather_location.as_ref())
+            .unwrap_or(&self.location)
+            .clone();
+        let now = ctx.clock().local_now();
+        let unavailable = if online {
+            "Sorry, I couldn't get the weather right now."
+        } else {
+            "I can't reach the internet right now."
+        };
+        match query.intent {
+            Some(CURRENT_INTENT) => {
+                if online {
+                    match self.provider.current(&location) {
+                        Ok(weather) => {
+                            let response = self.describe_current(&location, &weather, None);
+                            self.last_current = Some(Cached {
+                                location,
+                                weather,
+                                at: now,
+                            });
+                            return Some(response);
+                        }
+                        Err(e) => warn!("Failed to get the weather: {:?}", e),
+                    }
+                }
+                Some(match Cached::get(&self.last_current, &location) {
+                    Some((weather, at)) => format!(
+                        "{} {}",
+                        unavailable,
+                        self.describe_current(&location, weather, Some(at))
+                    ),
+                    None => unavailable.to_string(),
+                })
+            }
+            Some(FORECAST_INTENT) => {
+                let day = match query.slots.get("day") {
+                    Some(SlotValue::Entity(day)) => day.as_str(),
+                    _ => "today",
+                };
+                let Some(date) = resolve_day(day, now.date_naive()) else {
+                    return Some(format!("I don't know which day {} is.", day));
+                };
+                if online {
+                    match self.provider.forecast(&location, FORECAST_DAYS) {
+                        Ok(days) => {
+                            let response = self.forecast_for(&location, day, date, &days);
+                            self.last_forecast = Some(Cached {
+                                location,
+                                weather: days,
+                                at: now,
+                            });
+                            return Some(response);
+                        }
+                        Err(e) => warn!("Failed to get the weather: {:?}", e),
+                    }
+                }
+                Some(match Cached::get(&self.last_forecast, &location) {
+                    Some((days, at)) => format!(
+                        "{} The last forecast I got, at {}, said: {}",
+                        unavailable,
+                        at.format("%-H:%M"),
+                        self.forecast_for(&location, day, date, days)
+                    ),
+                    None => unavailable.to_string(),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn forecast_for(
+        &self,
+        location: &WeatherLocation,
+        day: &str,
+        date: NaiveDate,
+        days: &[DailyForecast],
+    ) -> String {
+        match days.iter().find(|f| f.date == date) {
+            Some(forecast) => self.describe_forecast(location, day, forecast),
+            None => format!("I don't have a forecast for {} yet.", day),
+        }
+    }
+
+    /// The weather at the time it was returned by the provider, if it isn't the current one.
+    fn describe_current(
+        &self,
+        location: &WeatherLocation,
+        weather: &CurrentWeather,
+        at: Option<DateTime<Local>>,
+    ) -> String {
+        let (degrees, speed) = self.unit_names();
+        let (start, wind) = match at {
+            Some(at) => (format!("At {} it was", at.format("%-H:%M")), "was"),
+            None => ("It's".to_string(), "is"),
+        };
+        format!(
+            "{} {:.0} {}{} with {}, and the wind {} at {:.0} {}.",
+            start,
+            weather.temperature,
+            degrees,
+            location_suffix(location),
+            weather.conditions.description(),
+            wind,
+            weather.wind_speed,
+            speed
+        )
+    }
+
+    fn describe_forecast(
+        &self,
+        location: &WeatherLocation,
+        day: &str,
+        forecast: &DailyForecast,
+    ) -> String {
+        let (degrees, _) = self.unit_names();
+        let precipitation = match forecast.precipitation_probability {
+            Some(probability) if probability >= 10. => {
+                format!(
+                    ", with a {:.0} percent chance of precipitation",
+                    probability
+                )
+            }
+            _ => String::new(),
+        };
+        format!(
+            "{}{}: {}, between {:.0} and {:.0} {}{}.",
+            capitalize(day),
+            location_suffix(location),
+            forecast.conditions.description(),
+            forecast.min_temperature,
+            forecast.max_temperature,
+            degrees,
+            precipitation
+        )
+    }
+
+    fn unit_names(&self) -> (&'static str, &'static str) {
+        match self.units {
+            Units::Metric => ("degrees", "kilometers per hour"),
+            Units::Imperial => ("degrees Fahrenheit", "miles per hour"),
+        }
+    }
+}
+
+impl Skill for WeatherSkill {
+    fn name(&self) -> &str {
+        "weather"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak, Capability::Network]
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        let days = ["today", "tomorrow"]
+            .into_iter()
+            .map(str::to_string)
+            .chain(WEEKDAYS.iter().map(|(name, _)| name.to_string()))
+            .collect();
+        vec![
+            IntentSpec::new(
+                CURRENT_INTENT,
+                examples(&[
+                    "what's the weather like",
+                    "what's the weather like outside",
+                    "how warm is it outside",
+                    "is it cold outside",
+                ]),
+            ),
+            IntentSpec::with_slots(
+                FORECAST_INTENT,
+                examples(&[
+                    "what's the forecast",
+                    "what's the forecast for {day}",
+                    "what's the weather {day}",
+                    "will it rain {day}",
+                ]),
+                vec![Slot::new("day", SlotKind::Entity(days))],
+            ),
+        ]
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        if let Some(response) = self.answer(ctx, query, true) {
+            _ = ctx.speak(response);
+        }
+    }
+
+    #[cfg(feature = "offline")]
+    fn needs_network(&self) -> bool {
+        true
+    }
+
+    /// The last known weather, if it's for the same place.
+    #[cfg(feature = "offline")]
+    fn handle_offline(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        if let Some(response) = self.answer(ctx, query, false) {
+            _ = ctx.speak(response);
+        }
+    }
+}
+
+const WEEKDAYS: [(&str, Weekday); 7] = [
+    ("monday", Weekday::Mon),
+    ("tuesday", Weekday::Tue),
+    ("wednesday", Weekday::Wed),
+    ("thursday", Weekday::Thu),
+    ("friday", Weekday::Fri),
+    ("saturday", Weekday::Sat),
+    ("sunday", Weekday::Sun),
+];
+
+fn location_suffix(location: &WeatherLocation) -> String {
+    match &location.name {
+        Some(name) => format!(" in {}", name),
+        None => String::new(),
+    }
+}
+
+/// The date of "today", "tomorrow" or the next day with the weekday of the name.
+fn resolve_day(day: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let days_ahead = match day {
+        "today" => 0,
+        "tomorrow" => 1,
+        name => {
+            let (_, weekday) = WEEKDAYS.iter().find(|(n, _)| *n == name)?;
+            (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7
+        }
+    };
+    today.checked_add_days(Days::new(days_ahead as u64))
+}
+
+pub(crate) fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -0,0 +1,109 @@
+use cpal::Sample;
+use thiserror::Error;
+use whisper_rs::{
+    FullParams, SamplingStrategy, WhisperContext, WhisperContextParameters, WhisperState,
+};
+
+use crate::stt::{
+    RecognitionError, RecognitionSession, Sentence, SessionOptions, SessionState, SpeechRecognizer,
+};
+
+/// A [SpeechRecognizer] using a whisper.cpp model, enabled with the `whisper` feature. Whisper
+/// can't decode while the user is speaking, so the sentence is recognized at once when the end of
+/// speech is detected and no partial results are available.
+pub struct WhisperRecognizer {
+    context: WhisperContext,
+    language: Option<String>,
+    threads: i32,
+}
+
+#[derive(Error, Debug)]
+pub enum WhisperBuildError {
+    #[error("Failed to load Whisper model")]
+    LoadModel(#[from] whisper_rs::WhisperError),
+}
+
+impl WhisperRecognizer {
+    /// Load a ggml Whisper model.
+    pub fn build(model_path: &str) -> Result<Self, WhisperBuildError> {
+        let context =
+            WhisperContext::new_with_params(model_path, WhisperContextParameters::default())?;
+
+        Ok(Self {
+            context,
+            language: Some("en".to_string()),
+            threads: 4,
+        })
+    }
+
+    /// Set the spoken language, e.g. "en", or `None` to detect it. English by default.
+    pub fn set_language(&mut self, language: Option<&str>) {
+        self.language = language.map(str::to_string);
+    }
+
+    /// Set the number of threads used for decoding, 4 by default.
+    pub fn set_threads(&mut self, threads: i32) {
+        self.threads = threads;
+    }
+}
+
+impl SpeechRecognizer for WhisperRecognizer {
+    fn start_session(
+        &self,
+        options: &SessionOptions,
+    ) -> Result<Box<dyn RecognitionSession + '_>, RecognitionError> {
+        let state = self
+            .context
+            .create_state()
+            .map_err(|_| RecognitionError::FailedCreateRecognizer)?;
+        Ok(Box::new(WhisperSession {
+            recognizer: self,
+            state,
+            // Whisper can't be restricted to a grammar, but mentioning the phrases in the prompt
+            // makes them more likely
+            prompt: options.grammar.as_ref().map(|grammar| grammar.join(", ")),
+            samples: Vec::new(),
+        }))
+    }
+}
+
+struct WhisperSession<'a> {
+    recognizer: &'a WhisperRecognizer,
+    state: WhisperState,
+    prompt: Option<String>,
+    samples: Vec<f32>,
+}
+
+impl RecognitionSession for WhisperSession<'_> {
+    fn accept_waveform(&mut self, samples: &[i16]) -> SessionState {
+        self.samples
+            .extend(samples.iter().map(|&s| f32::from_sample(s)));
+        SessionState::Running
+    }
+
+    fn final_result(&mut self) -> Option<Sentence> {
+        let mut params = FullParams::new(SamplingStrategy::Greedy { best_of: 1 });
+        params.set_language(self.recognizer.language.as_deref());
+        params.set_n_threads(self.recognizer.threads);
+        params.set_single_segment(true);
+        params.set_no_context(true);
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_special(false);
+        params.set_print_timestamps(false);
+        if let Some(prompt) = &self.prompt {
+            params.set_initial_prompt(prompt);
+        }
+
+        self.state.full(params, &self.samples).ok()?;
+        let segments = self.state.full_n_segments().ok()?;
+        let text = (0..segments)
+            .filter_map(|segment| self.state.full_get_segment_text(segment).ok())
+            .collect::<String>();
+
+        Some(Sentence {
+            text: text.trim().to_string(),
+            ..Sentence::default()
+        })
+    }
+}
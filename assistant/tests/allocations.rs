@@ -0,0 +1,141 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use assistant::{
+    audio::{AudioFormat, MemoryAudioSource},
+    stt::{
+        RecognitionError, RecognitionSession, RecognitionUpdate, STTSentenceRecognizer, Sentence,
+        SessionOptions, SessionState, SpeechRecognizer,
+    },
+    wakeword::{WakewordConfig, WakewordConfigAddError, WakewordDetection, WakewordEngine},
+};
+
+/// Counts the allocations made by the threads that enabled counting.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn count() {
+    if COUNTING.try_with(Cell::get).unwrap_or(false) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        count();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        count();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// How many allocations the calling thread made while running `f`.
+fn allocations_in(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    COUNTING.set(true);
+    f();
+    COUNTING.set(false);
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+/// Never detects anything, so only the buffering of the listener is measured.
+struct SilentEngine;
+
+impl WakewordEngine for SilentEngine {
+    fn add_wakeword_from_file(&mut self, _: &str, _: &str) -> Result<(), WakewordConfigAddError> {
+        Ok(())
+    }
+
+    fn process(&mut self, _: &[f32]) -> Vec<WakewordDetection> {
+        Vec::new()
+    }
+}
+
+/// Reports a new partial transcript for every block, so the stream yields one update per block.
+struct CountingRecognizer;
+
+struct CountingSession(usize);
+
+impl SpeechRecognizer for CountingRecognizer {
+    fn start_session(
+        &self,
+        _: &SessionOptions,
+    ) -> Result<Box<dyn RecognitionSession + '_>, RecognitionError> {
+        Ok(Box::new(CountingSession(0)))
+    }
+}
+
+impl RecognitionSession for CountingSession {
+    fn accept_waveform(&mut self, _: &[i16]) -> SessionState {
+        self.0 += 1;
+        SessionState::Running
+    }
+
+    fn partial_result(&mut self) -> String {
+        self.0.to_string()
+    }
+
+    fn final_result(&mut self) -> Option<Sentence> {
+        None
+    }
+}
+
+#[test]
+fn audio_callbacks_do_not_allocate() {
+    // Stereo at 48 kHz, so speech recognition mixes down and resamples
+    let format = AudioFormat {
+        sample_rate: 48000,
+        channels: 2,
+    };
+    let source = MemoryAudioSource::new(format);
+    let mut wakeword = WakewordConfig::build(format).unwrap();
+    wakeword.set_engine(SilentEngine);
+    wakeword.add_wakeword_from_file("silent", "").unwrap();
+    let listener = wakeword.start(&source).unwrap();
+    let recognizer = CountingRecognizer;
+    let mut stream = STTSentenceRecognizer::new(&recognizer, &source)
+        .recognize_streaming()
+        .unwrap();
+
+    // One block of the source, loud enough to be speech
+    let block: Vec<f32> = (0..1024 * 2)
+        .map(|i| (i as f32 * 0.05).sin() * 0.5)
+        .collect();
+    for pushed in 1..=200 {
+        let allocations = allocations_in(|| source.push(&block));
+        assert_eq!(allocations, 0, "block {} allocated", pushed);
+
+        // Let both threads give the block back before the next one
+        assert!(matches!(stream.next(), Some(RecognitionUpdate::Partial(_))));
+        let started = Instant::now();
+        while listener.power_stats().blocks < pushed {
+            assert!(started.elapsed() < Duration::from_secs(5));
+            thread::yield_now();
+        }
+    }
+}
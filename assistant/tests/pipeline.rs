@@ -0,0 +1,172 @@
+use std::{f32::consts::TAU, process, sync::Arc, time::Duration};
+
+use assistant::{
+    audio::{read_wav, MemoryAudioSource},
+    clock::ManualClock,
+    stt::{
+        RecognitionError, RecognitionResult, RecognitionSession, RecognitionUpdate,
+        STTSentenceRecognizer, Sentence, SessionOptions, SessionState, SpeechRecognizer,
+        STT_SAMPLE_RATE,
+    },
+    wakeword::{WakewordConfig, WakewordConfigAddError, WakewordDetection, WakewordEngine},
+};
+use chrono::Local;
+
+const SAMPLE_RATE: u32 = 48000;
+
+/// Where the beep that stands in for the wakeword starts and ends, in seconds.
+const BEEP: (f32, f32) = (0.5, 0.8);
+/// Where the tone that stands in for the command starts and ends, in seconds.
+const TONE: (f32, f32) = (1.3, 2.3);
+const LENGTH: f32 = 3.3;
+
+/// Write a stereo recording with a loud beep at 1 kHz, then a softer tone at 440 Hz.
+fn write_recording(path: &std::path::Path) {
+    let spec = hound::WavSpec {
+        channels: 2,
+        sample_rate: SAMPLE_RATE,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).unwrap();
+    for frame in 0..(LENGTH * SAMPLE_RATE as f32) as usize {
+        let time = frame as f32 / SAMPLE_RATE as f32;
+        let sample = if (BEEP.0..BEEP.1).contains(&time) {
+            (time * 1000. * TAU).sin() * 0.8
+        } else if (TONE.0..TONE.1).contains(&time) {
+            (time * 440. * TAU).sin() * 0.5
+        } else {
+            0.
+        };
+        let sample = (sample * i16::MAX as f32) as i16;
+        writer.write_sample(sample).unwrap();
+        writer.write_sample(sample).unwrap();
+    }
+    writer.finalize().unwrap();
+}
+
+fn rms(samples: impl ExactSizeIterator<Item = f32>) -> f32 {
+    let len = samples.len().max(1);
+    (samples.map(|s| s * s).sum::<f32>() / len as f32).sqrt()
+}
+
+/// Detects "beep" once at the start of every loud sound.
+#[derive(Default)]
+struct BeepEngine {
+    loud: bool,
+}
+
+impl WakewordEngine for BeepEngine {
+    fn add_wakeword_from_file(&mut self, _: &str, _: &str) -> Result<(), WakewordConfigAddError> {
+        Ok(())
+    }
+
+    fn process(&mut self, samples: &[f32]) -> Vec<WakewordDetection> {
+        let loud = rms(samples.iter().copied()) > 0.3;
+        let started = loud && !self.loud;
+        self.loud = loud;
+        if started {
+            vec![WakewordDetection::new("beep")]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Transcribes a tone as its frequency, counting zero crossings, and finalizes the sentence
+/// once it has been quiet for half a second after the tone.
+struct ToneRecognizer;
+
+#[derive(Default)]
+struct ToneSession {
+    crossings: usize,
+    tone_samples: usize,
+    quiet_samples: usize,
+    previous: i16,
+}
+
+impl SpeechRecognizer for ToneRecognizer {
+    fn start_session(
+        &self,
+        _: &SessionOptions,
+    ) -> Result<Box<dyn RecognitionSession + '_>, RecognitionError> {
+        Ok(Box::new(ToneSession::default()))
+    }
+}
+
+impl RecognitionSession for ToneSession {
+    fn accept_waveform(&mut self, samples: &[i16]) -> SessionState {
+        if rms(samples.iter().map(|&s| s as f32 / i16::MAX as f32)) < 0.1 {
+            self.quiet_samples += samples.len();
+        } else {
+            self.quiet_samples = 0;
+            self.tone_samples += samples.len();
+            for &sample in samples {
+                if (sample >= 0) != (self.previous >= 0) {
+                    self.crossings += 1;
+                }
+                self.previous = sample;
+            }
+        }
+        if self.tone_samples > 0 && self.quiet_samples >= STT_SAMPLE_RATE as usize / 2 {
+            return SessionState::Finalized(self.final_result().unwrap());
+        }
+        SessionState::Running
+    }
+
+    fn final_result(&mut self) -> Option<Sentence> {
+        let seconds = self.tone_samples as f32 / STT_SAMPLE_RATE as f32;
+        let hertz = (self.crossings as f32 / 2. / seconds / 10.).round() * 10.;
+        Some(Sentence {
+            text: format!("{} hertz", hertz),
+            ..Default::default()
+        })
+    }
+}
+
+#[test]
+fn recording_is_detected_and_transcribed() {
+    let path = std::env::temp_dir().join(format!("raspberry-pipeline-{}.wav", process::id()));
+    write_recording(&path);
+    let (format, samples) = read_wav(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(format.sample_rate, SAMPLE_RATE);
+    assert_eq!(format.channels, 2);
+
+    let source = MemoryAudioSource::new(format);
+    let mut wakeword = WakewordConfig::build(format).unwrap();
+    wakeword.set_engine(BeepEngine::default());
+    wakeword.add_wakeword_from_file("beep", "").unwrap();
+    let listener = wakeword.start(&source).unwrap();
+
+    // Everything up to the command, like the assistant waiting for the wakeword
+    let command = (TONE.0 * SAMPLE_RATE as f32) as usize * 2;
+    source.push(&samples[..command]);
+    let detection = listener.listen_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(detection.name, "beep");
+    let frame = detection.timestamp.unwrap().frame;
+    let beep_start = (BEEP.0 * SAMPLE_RATE as f32) as u64;
+    assert!(
+        (beep_start..beep_start + 1024).contains(&frame),
+        "detected at frame {}",
+        frame
+    );
+    assert!(listener.try_iter().next().is_none());
+
+    // The clock doesn't move, so it's the session that ends the sentence
+    let recognizer = ToneRecognizer;
+    let mut stt = STTSentenceRecognizer::new(&recognizer, &source);
+    stt.set_clock(Arc::new(ManualClock::new(Local::now())));
+    let stream = stt.recognize_streaming().unwrap();
+    source.push(&samples[command..]);
+    let result = stream
+        .filter_map(|update| match update {
+            RecognitionUpdate::Done(result) => Some(result),
+            RecognitionUpdate::Partial(_) => None,
+        })
+        .next();
+    let Some(RecognitionResult::Final(sentence)) = result else {
+        panic!("recognition didn't finish: {:?}", result);
+    };
+    assert_eq!(sentence.text, "440 hertz");
+}
@@ -0,0 +1,89 @@
+use std::{fs, process, sync::mpsc, time::SystemTime};
+
+use assistant::{
+    audio::{AudioFormat, AudioTimestamp, MemoryAudioSource},
+    events::AssistantEvent,
+    session::{read_session, record_session, RecordedEvent, SessionError, SessionEvent},
+    wakeword::WakewordDetection,
+};
+
+#[test]
+fn recorded_session_is_read_back() {
+    let path = std::env::temp_dir().join(format!("raspberry-session-{}.rsp", process::id()));
+    let format = AudioFormat {
+        sample_rate: 16000,
+        channels: 2,
+    };
+    let source = MemoryAudioSource::new(format);
+    let (events, rx) = mpsc::channel();
+    let writer = record_session(&path, &source, rx).unwrap();
+
+    let block: Vec<f32> = (0..2048).map(|i| (i as f32 / 2048.) - 0.5).collect();
+    source.push(&block);
+    // Received after more audio, like a detection in a busy assistant
+    source.push(&block);
+    let mut detection = WakewordDetection::new("pizza");
+    detection.timestamp = Some(AudioTimestamp {
+        frame: 1024,
+        time: SystemTime::now(),
+    });
+    events
+        .send(AssistantEvent::WakewordDetected(detection))
+        .unwrap();
+    // Not a result of the pipeline, so not kept
+    events.send(AssistantEvent::RecognitionStarted).unwrap();
+    events
+        .send(AssistantEvent::RecognitionFinished(Some(
+            "what time is it".to_string(),
+        )))
+        .unwrap();
+    events
+        .send(AssistantEvent::IntentMatched {
+            text: "what time is it".to_string(),
+            score: 0.75,
+        })
+        .unwrap();
+    drop(source);
+    drop(events);
+    writer.join().unwrap();
+
+    let session = read_session(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(session.format, format);
+    assert_eq!(session.samples.len(), block.len() * 2);
+    for (read, written) in session.samples.iter().zip(block.iter().chain(&block)) {
+        assert!((read - written).abs() < 1e-3, "{} != {}", read, written);
+    }
+    assert_eq!(
+        session.events,
+        [
+            RecordedEvent {
+                frame: 1024,
+                event: SessionEvent::WakewordDetected {
+                    name: "pizza".to_string(),
+                    score: WakewordDetection::new("pizza").score,
+                },
+            },
+            RecordedEvent {
+                frame: 2048,
+                event: SessionEvent::RecognitionFinished(Some("what time is it".to_string())),
+            },
+            RecordedEvent {
+                frame: 2048,
+                event: SessionEvent::IntentMatched {
+                    text: "what time is it".to_string(),
+                    score: 0.75,
+                },
+            },
+        ]
+    );
+}
+
+#[test]
+fn other_files_are_not_sessions() {
+    let path = std::env::temp_dir().join(format!("raspberry-not-session-{}.txt", process::id()));
+    fs::write(&path, "not a session").unwrap();
+    let result = read_session(&path);
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(result, Err(SessionError::NotAnArchive)));
+}
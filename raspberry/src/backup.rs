@@ -0,0 +1,92 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{config, dirs::get_config_path};
+
+const USAGE: &str = "Usage: raspberry <backup|restore> <file> [config_dir]";
+
+/// Archive the config directory into a gzipped tarball, with the config, wakewords, IR codes and
+/// the storage holding the schedule, history, learned voices and the rest of the state. The
+/// speech and intent models are left out, since they're large and can be downloaded again.
+pub fn backup(config_dir: &Path, file: &Path) -> io::Result<()> {
+    let mut command = Command::new("tar");
+    command.arg("--create").arg("--gzip");
+    if let Ok(declared) = config::load(config_dir) {
+        let mut models = vec![declared.stt_model.as_str(), declared.intent_model.as_str()];
+        models.extend(declared.other_stt_models());
+        models.extend(
+            declared
+                .languages
+                .iter()
+                .map(|language| language.stt_model.as_str()),
+        );
+        models.extend(
+            declared
+                .speakers
+                .as_ref()
+                .map(|speakers| speakers.model.as_str()),
+        );
+        for model in models {
+            command.arg(format!("--exclude=./{}", model.trim_end_matches('/')));
+        }
+    }
+    run_tar(
+        command
+            .arg("--file")
+            .arg(file)
+            .arg("--directory")
+            .arg(config_dir)
+            .arg("."),
+    )
+}
+
+/// Extract a backup into the config directory, replacing the files in it.
+pub fn restore(config_dir: &Path, file: &Path) -> io::Result<()> {
+    fs::create_dir_all(config_dir)?;
+    run_tar(
+        Command::new("tar")
+            .arg("--extract")
+            .arg("--gzip")
+            .arg("--file")
+            .arg(file)
+            .arg("--directory")
+            .arg(config_dir),
+    )
+}
+
+fn run_tar(command: &mut Command) -> io::Result<()> {
+    let status = command.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("tar exited with {}", status)))
+    }
+}
+
+/// `raspberry backup <file> [config_dir]`, while the assistant isn't running so that the storage
+/// is consistent.
+pub fn backup_command(mut args: impl Iterator<Item = String>) {
+    let (file, config_dir) = file_and_config_dir(&mut args);
+    backup(&config_dir, &file).expect("Failed to back up");
+    println!(
+        "Saved backup of {} to {}",
+        config_dir.display(),
+        file.display()
+    );
+}
+
+/// `raspberry restore <file> [config_dir]`
+pub fn restore_command(mut args: impl Iterator<Item = String>) {
+    let (file, config_dir) = file_and_config_dir(&mut args);
+    restore(&config_dir, &file).expect("Failed to restore");
+    println!("Restored {} from {}", config_dir.display(), file.display());
+}
+
+fn file_and_config_dir(args: &mut impl Iterator<Item = String>) -> (PathBuf, PathBuf) {
+    let file: PathBuf = args.next().expect(USAGE).into();
+    let config_dir = args.next().map(Into::into).unwrap_or_else(get_config_path);
+    (file, config_dir)
+}
@@ -0,0 +1,119 @@
+use std::{path::PathBuf, time::Duration};
+
+use assistant::stt::load_stt_model;
+
+use crate::dirs::{get_config_file, get_config_path};
+
+/// `raspberry bench-wakeword <recording.wav> [minutes] [config_dir]`
+pub fn bench_wakeword(mut args: impl Iterator<Item = String>) {
+    let recording = args
+        .next()
+        .expect("Usage: raspberry bench-wakeword <recording.wav> [minutes] [config_dir]");
+    let minutes: f64 = args
+        .next()
+        .map(|m| m.parse().expect("Minutes should be a number"))
+        .unwrap_or(1.);
+    let config_dir: PathBuf = args.next().map(Into::into).unwrap_or_else(get_config_path);
+
+    let declared = crate::config::load(&config_dir).expect("Failed to load config.toml");
+
+    let wakeword_files: Vec<(&str, String)> = declared
+        .wakewords
+        .iter()
+        .map(|wakeword| {
+            let file = get_config_file(&config_dir, &wakeword.file);
+            let file = file.to_str().expect("Failed to convert PathBuf to &str");
+            (wakeword.name.as_str(), file.to_string())
+        })
+        .collect();
+    let wakewords: Vec<(&str, &str)> = wakeword_files
+        .iter()
+        .map(|(name, file)| (*name, file.as_str()))
+        .collect();
+    let settings = declared
+        .wakeword_detector
+        .as_ref()
+        .map(|detector| detector.to_detector_settings())
+        .unwrap_or_default();
+    let report = assistant::bench::bench_wakeword(
+        &wakewords,
+        &settings,
+        &recording,
+        Duration::from_secs_f64(minutes * 60.),
+    )
+    .expect("Failed to run wakeword benchmark");
+
+    println!("Audio processed:   {:.1?}", report.audio_duration);
+    println!("Processing time:   {:.1?}", report.processing_time);
+    println!("Real-time factor:  {:.4}", report.real_time_factor());
+    println!("Frames:            {}", report.frames);
+    println!("CPU time / frame:  {:.1?}", report.cpu_time_per_frame());
+    println!("Slowest frame:     {:.1?}", report.max_frame_time);
+    match report.peak_memory_kib {
+        Some(kib) => println!("Peak memory:       {} KiB", kib),
+        None => println!("Peak memory:       unknown"),
+    }
+    println!("Detections:        {}", report.detections.len());
+    for (position, name) in report.detections {
+        println!("  {:>10.2?}  {}", position, name);
+    }
+}
+
+/// `raspberry bench-stt <recording.wav> <reference.txt> [config_dir]`
+pub fn bench_stt(mut args: impl Iterator<Item = String>) {
+    const USAGE: &str = "Usage: raspberry bench-stt <recording.wav> <reference.txt> [config_dir]";
+    let recording = args.next().expect(USAGE);
+    let reference =
+        std::fs::read_to_string(args.next().expect(USAGE)).expect("Failed to read reference text");
+    let config_dir: PathBuf = args.next().map(Into::into).unwrap_or_else(get_config_path);
+    let declared = crate::config::load(&config_dir).expect("Failed to load config.toml");
+
+    let model = load_stt_model(
+        get_config_file(&config_dir, &declared.stt_model)
+            .to_str()
+            .expect("Failed to convert PathBuf to &str"),
+    )
+    .expect("Failed to load STT model");
+    let report = assistant::bench::bench_stt(&model, &recording, &reference)
+        .expect("Failed to run STT benchmark");
+
+    println!("Transcript:        {}", report.transcript);
+    println!("Audio duration:    {:.1?}", report.audio_duration);
+    println!("Processing time:   {:.1?}", report.processing_time);
+    println!("Real-time factor:  {:.4}", report.real_time_factor());
+    println!(
+        "Word error rate:   {:.2}% ({} errors / {} words)",
+        report.word_error_rate() * 100.,
+        report.word_errors,
+        report.reference_words
+    );
+}
+
+/// `raspberry bench-tts [repeats]`
+pub fn bench_tts(mut args: impl Iterator<Item = String>) {
+    const PHRASES: &[&str] = &[
+        "Yes?",
+        "It's eleven thirty.",
+        "The weather today is sunny with a high of twenty degrees.",
+        "Here is the news. The city council approved the new budget on Monday, after a long \
+         debate about public transport, housing and the renovation of the old library.",
+    ];
+    let repeats: usize = args
+        .next()
+        .map(|r| r.parse().expect("Repeats should be a number"))
+        .unwrap_or(3);
+
+    let mut tts = assistant::tts::get_tts().expect("Failed to get TTS");
+    let results = assistant::bench::bench_tts(&mut tts, PHRASES, repeats)
+        .expect("Failed to run TTS benchmark");
+
+    println!("{:>6}  {:>12}  {:>12}", "Chars", "First audio", "Total");
+    for result in results {
+        println!(
+            "{:>6}  {:>12.1?}  {:>12.1?}",
+            result.phrase.chars().count(),
+            result.time_to_first_audio,
+            result.total_time
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use assistant::{
+    birthdays::{celebrated_today, ImportantDate},
+    schedule::Schedule,
+    tts_cache::Synthesizer,
+};
+use chrono::{DateTime, Local, Timelike};
+use std::{
+    io::{self, Write},
+    process::{Command, Stdio},
+};
+
+/// The daily briefing: the date, the birthdays and anniversaries of the day and what is
+/// scheduled.
+pub fn briefing_text(now: DateTime<Local>, schedule: &Schedule, dates: &[ImportantDate]) -> String {
+    let greeting = match now.hour() {
+        0..12 => "Good morning.",
+        12..18 => "Good afternoon.",
+        _ => "Good evening.",
+    };
+    let mut text = format!(
+        "{} It's {}, {}.",
+        greeting,
+        now.format("%A, %B %d"),
+        now.format("%I:%M %p")
+    );
+    if let Some(celebrated) = celebrated_today(dates, now.date_naive()) {
+        text.push(' ');
+        text.push_str(&celebrated);
+    }
+    let items = schedule.items();
+    if items.is_empty() {
+        text.push_str(" You have nothing scheduled.");
+    }
+    for item in items {
+        text.push_str(&format!(" {}.", item.describe()));
+    }
+    text
+}
+
+/// Render the text to a WAV file with espeak-ng, which the TTS backend uses on Linux too.
+pub fn render_wav(text: &str) -> io::Result<Vec<u8>> {
+    let mut child = Command::new("espeak-ng")
+        .arg("--stdout")
+        .arg("--stdin")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(text.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(io::Error::other(format!(
+            "espeak-ng exited with {}",
+            output.status
+        )))
+    }
+}
+
+/// Synthesizes the phrases of the TTS cache with [render_wav].
+pub struct EspeakSynthesizer;
+
+impl Synthesizer for EspeakSynthesizer {
+    fn synthesize(&self, text: &str) -> io::Result<Vec<u8>> {
+        render_wav(text)
+    }
+}
@@ -0,0 +1,179 @@
+use assistant::{
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{Slot, SlotKind, SlotValue},
+    AssistantQuery,
+};
+use std::{
+    io,
+    process::{Command, Output},
+};
+
+const ON_INTENT: &str = "tv on";
+const OFF_INTENT: &str = "tv off";
+const INPUT_INTENT: &str = "tv input";
+/// The logical address of the TV on the CEC bus.
+const TV: &str = "0";
+/// The logical address messages to every device are sent to.
+const BROADCAST: &str = "15";
+
+/// A device plugged into an HDMI input of the TV, switched to by its name, like "the Chromecast".
+#[derive(Clone, Debug, PartialEq)]
+pub struct CecInput {
+    pub name: String,
+    /// Its physical address, like "2.0.0.0" for the second HDMI input of the TV.
+    pub address: String,
+}
+
+/// Whether the text is a physical address of the CEC bus, four hexadecimal digits separated by
+/// dots like "2.0.0.0".
+pub fn is_physical_address(text: &str) -> bool {
+    let digits: Vec<&str> = text.split('.').collect();
+    digits.len() == 4
+        && digits
+            .iter()
+            .all(|digit| digit.len() == 1 && digit.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+/// A skill controlling the TV over HDMI-CEC with `cec-ctl` from v4l-utils: "turn on the TV",
+/// "turn the TV off" and "switch to the Chromecast", for the inputs of config.toml.
+pub struct CecSkill {
+    /// The CEC adapter, like `/dev/cec0`.
+    device: String,
+    /// What the TV shows as the name of the Pi.
+    name: String,
+    inputs: Vec<CecInput>,
+}
+
+impl CecSkill {
+    pub fn new(device: impl Into<String>, name: impl Into<String>, inputs: Vec<CecInput>) -> Self {
+        Self {
+            device: device.into(),
+            name: name.into(),
+            inputs,
+        }
+    }
+
+    /// Register the Pi as a playback device on the CEC bus, which it needs to send messages, and
+    /// list the devices on the bus to warn about the inputs that aren't there.
+    pub fn scan(&self) -> io::Result<()> {
+        self.cec_ctl(&["--playback", "--osd-name", &self.name])?;
+        let output = self.cec_ctl(&["--show-topology"])?;
+        let topology = String::from_utf8_lossy(&output.stdout);
+        for input in &self.inputs {
+            if !topology.contains(&input.address) {
+                eprintln!(
+                    "The HDMI-CEC input \"{}\" at {} isn't on the bus",
+                    input.name, input.address
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn cec_ctl(&self, args: &[&str]) -> io::Result<Output> {
+        let output = Command::new("cec-ctl")
+            .arg("--device")
+            .arg(&self.device)
+            .args(args)
+            .output()?;
+        if output.status.success() {
+            Ok(output)
+        } else {
+            Err(io::Error::other(format!(
+                "cec-ctl exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )))
+        }
+    }
+
+    fn power_on(&self) -> io::Result<()> {
+        self.cec_ctl(&["--to", TV, "--image-view-on"]).map(|_| ())
+    }
+
+    fn standby(&self) -> io::Result<()> {
+        self.cec_ctl(&["--to", TV, "--standby"]).map(|_| ())
+    }
+
+    /// Make the TV show the input, by announcing it as the active source.
+    fn switch_to(&self, input: &CecInput) -> io::Result<()> {
+        self.power_on()?;
+        let address = format!("phys-addr={}", input.address);
+        self.cec_ctl(&["--to", BROADCAST, "--active-source", &address])
+            .map(|_| ())
+    }
+}
+
+impl Skill for CecSkill {
+    fn name(&self) -> &str {
+        "cec"
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        let mut intents = vec![
+            IntentSpec::new(
+                ON_INTENT,
+                examples(&[
+                    "turn on the tv",
+                    "turn the tv on",
+                    "switch on the television",
+                ]),
+            ),
+            IntentSpec::new(
+                OFF_INTENT,
+                examples(&[
+                    "turn off the tv",
+                    "turn the tv off",
+                    "switch off the television",
+                ]),
+            ),
+        ];
+        if !self.inputs.is_empty() {
+            let names = self.inputs.iter().map(|input| input.name.clone()).collect();
+            intents.push(IntentSpec::with_slots(
+                INPUT_INTENT,
+                examples(&[
+                    "switch to {input}",
+                    "switch the tv to {input}",
+                    "change the input to {input}",
+                ]),
+                vec![Slot::new("input", SlotKind::Entity(names))],
+            ));
+        }
+        intents
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        let result = match query.intent {
+            Some(ON_INTENT) => self.power_on(),
+            Some(OFF_INTENT) => self.standby(),
+            Some(INPUT_INTENT) => {
+                let input = match query.slots.get("input") {
+                    Some(SlotValue::Entity(name)) => {
+                        self.inputs.iter().find(|input| input.name == *name)
+                    }
+                    _ => None,
+                };
+                match input {
+                    Some(input) => self.switch_to(input),
+                    None => {
+                        _ = ctx.speak("Which input?");
+                        return;
+                    }
+                }
+            }
+            _ => return,
+        };
+        let response = match result {
+            Ok(()) => "Done.",
+            Err(e) => {
+                eprintln!("Failed to control the TV: {}", e);
+                "I couldn't reach the TV."
+            }
+        };
+        if let Err(e) = ctx.speak(response) {
+            eprintln!("Failed to speak: {}", e);
+        }
+    }
+}
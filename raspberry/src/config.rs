@@ -0,0 +1,2081 @@
+use assistant::{
+    air_quality::{AirQualityAlert, AirQualityIndex, AirQualitySkill, OpenMeteoAirQuality},
+    automation::{self, Condition, Trigger},
+    birthdays::{load_dates, BirthdaySkill},
+    bridge::{
+        parse_group_address, parse_individual_address, BridgeDevice, BridgeSkill, KnxRouting,
+        ModbusTcp, KNX_ROUTING_ADDRESS,
+    },
+    chores,
+    connectivity::ConnectivityConfig,
+    guest::GuestModeConfig,
+    http::{HttpClient, HttpClientConfig, RateLimit},
+    http_cache::HttpCache,
+    hue::{HueBridge, HueSkill},
+    intents::{Fallback, Regex, Scoring},
+    knowledge::{Kiwix, KnowledgeSkill, KnowledgeSource, Wikipedia},
+    packages::{AfterShip, DeliveryAnnouncer, Package, PackageSkill},
+    permissions::{Capability, Permissions},
+    recording::RecordingConfig,
+    scheduling::{SchedulingConfig, ThreadScheduling},
+    sonos::{SonosSkill, SonosZone},
+    speakers::SpeakerPreferences,
+    sports::{MatchDayReminder, SportsSkill, Team, TheSportsDb},
+    storage::Storage,
+    sync::{FolderSync, HttpSync, SyncConfig, SyncKey},
+    transit::{TransitRoute, TransitSkill, TransportRest},
+    tts::VoiceSelection,
+    tts_cache::TtsCacheConfig,
+    wakeword::{BandPass, DetectorSettings, GainNormalizer, ScoreMode},
+    weather::{OpenMeteo, Units, WeatherLocation, WeatherSkill},
+};
+use chrono::{NaiveTime, Weekday};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    briefing::EspeakSynthesizer,
+    cec::{is_physical_address, CecInput, CecSkill},
+    dirs::get_config_file,
+    handoff::{HandoffPeer, HandoffSkill},
+    migrate::migrate,
+    scheduler,
+};
+
+/// The configuration written to the config directory on the first start.
+const DEFAULT_CONFIG: &str = include_str!("../config.toml");
+
+/// The declarative configuration of the assistant, see `config.toml` for the format.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// See [crate::migrate::CONFIG_VERSION].
+    #[serde(rename = "version")]
+    _version: i64,
+    pub stt_model: String,
+    pub intent_model: String,
+    #[serde(default)]
+    pub background_loading: bool,
+    pub reprompt: Option<String>,
+    pub not_understood: Option<String>,
+    #[serde(default)]
+    pub log_not_understood: bool,
+    pub greeting: Option<String>,
+    pub farewell: Option<String>,
+    pub threshold: Option<f32>,
+    #[serde(default)]
+    scoring: IntentScoring,
+    #[serde(default)]
+    pub grammar: bool,
+    #[serde(default)]
+    pub alternatives: u16,
+    #[serde(default)]
+    pub negative_examples: Vec<String>,
+    #[serde(default)]
+    pub scheduling: Scheduling,
+    pub wakeword_detector: Option<WakewordDetector>,
+    pub server: Option<Server>,
+    pub weather: Option<Weather>,
+    pub air_quality: Option<AirQuality>,
+    pub bridge: Option<Bridge>,
+    pub cec: Option<Cec>,
+    pub hue: Option<Hue>,
+    pub sonos: Option<Sonos>,
+    pub knowledge: Option<Knowledge>,
+    pub transit: Option<Transit>,
+    pub sports: Option<Sports>,
+    pub packages: Option<Packages>,
+    pub birthdays: Option<Birthdays>,
+    pub home_assistant: Option<HomeAssistant>,
+    pub mqtt: Option<Mqtt>,
+    pub presence: Option<Presence>,
+    pub guest_mode: Option<GuestMode>,
+    pub speakers: Option<Speakers>,
+    pub sync: Option<SyncService>,
+    #[serde(default)]
+    pub peers: Vec<Peer>,
+    pub connectivity: Option<Connectivity>,
+    pub http: Option<Http>,
+    pub recording: Option<Recording>,
+    pub tts_cache: Option<TtsCache>,
+    /// The capabilities denied to each skill, by its name
+    #[serde(default)]
+    permissions: HashMap<String, Vec<SkillCapability>>,
+    #[serde(default)]
+    pub languages: Vec<Language>,
+    #[serde(default)]
+    pub wakewords: Vec<Wakeword>,
+    #[serde(default)]
+    pub audio_cues: Vec<AudioCues>,
+    #[serde(default)]
+    pub intents: Vec<Intent>,
+    #[serde(default)]
+    pub automation: Vec<Rule>,
+    #[serde(default)]
+    pub announcements: Vec<Announcement>,
+    #[serde(default)]
+    pub chores: Vec<Chore>,
+    #[serde(default)]
+    pub acknowledgements: Vec<Acknowledgement>,
+}
+
+/// See [assistant::scheduling::SchedulingConfig].
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Scheduling {
+    pub audio_priority: Option<i32>,
+    #[serde(default)]
+    pub audio_cores: Vec<usize>,
+    #[serde(default)]
+    pub inference_cores: Vec<usize>,
+}
+
+/// See [assistant::wakeword::DetectorSettings], unset values keep their default.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WakewordDetector {
+    threshold: Option<f32>,
+    avg_threshold: Option<f32>,
+    min_scores: Option<usize>,
+    score_ref: Option<f32>,
+    score_mode: Option<DetectorScoreMode>,
+    /// The low and high cutoff in Hz
+    band_pass: Option<[f32; 2]>,
+    #[serde(default)]
+    gain_normalizer: bool,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+enum DetectorScoreMode {
+    Average,
+    Max,
+    Median,
+    P25,
+    P50,
+    P75,
+    P80,
+    P90,
+    P95,
+}
+
+/// See [assistant::permissions::Capability].
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+enum SkillCapability {
+    RecordAudio,
+    Network,
+    Speak,
+    SystemCommands,
+    Storage,
+}
+
+/// See `server.rs`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Server {
+    pub address: String,
+    /// Needed by the control endpoints, which are open to everyone on the network without it.
+    pub token: Option<String>,
+}
+
+/// See [assistant::air_quality::AirQualitySkill], at the location of the [Weather].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AirQuality {
+    #[serde(default)]
+    index: AirQualityScale,
+    /// Warn once a day when the index reaches this
+    alert_index: Option<f64>,
+    /// Warn once a day when a kind of pollen reaches this many grains/m³
+    alert_pollen: Option<f64>,
+    /// How often the air is checked for the warnings
+    #[serde(default = "default_air_quality_check_minutes")]
+    pub check_minutes: u64,
+    /// How long answers of the air quality service are reused, 0 to always ask it.
+    #[serde(default = "default_weather_cache_minutes")]
+    cache_minutes: u64,
+}
+
+fn default_air_quality_check_minutes() -> u64 {
+    60
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+enum AirQualityScale {
+    #[default]
+    European,
+    Us,
+}
+
+/// See [assistant::bridge::BridgeSkill].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Bridge {
+    protocol: BridgeProtocol,
+    /// Where KNX telegrams are sent, or the Modbus device
+    address: Option<String>,
+    /// The KNX address the telegrams are sent from
+    #[serde(default = "default_individual_address")]
+    individual_address: String,
+    /// The Modbus unit identifier
+    #[serde(default = "default_modbus_unit")]
+    unit: u8,
+    #[serde(default)]
+    devices: Vec<Device>,
+}
+
+fn default_individual_address() -> String {
+    "15.15.250".to_string()
+}
+
+fn default_modbus_unit() -> u8 {
+    1
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+enum BridgeProtocol {
+    Knx,
+    Modbus,
+}
+
+/// See [assistant::hue::HueSkill], with the key of `raspberry pair-hue`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Hue {
+    /// The host of the bridge, like "192.168.1.20"
+    address: String,
+    key: String,
+}
+
+/// See [SonosSkill] and [crate::sonos::SonosAnnouncer].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Sonos {
+    /// Say what the `[[announcements]]` say in the zones too, with the audio of the `[server]`
+    #[serde(default)]
+    announce: bool,
+    zones: Vec<Zone>,
+}
+
+/// A zone of the [Sonos], at the address of one of its players like "192.168.1.30".
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Zone {
+    name: String,
+    address: String,
+    /// From 0 to 100, the volume of the zone if not set
+    announcement_volume: Option<u8>,
+}
+
+/// See [CecSkill].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Cec {
+    #[serde(default = "default_cec_device")]
+    device: String,
+    /// What the TV shows as the name of the Pi
+    #[serde(default = "default_cec_name")]
+    name: String,
+    #[serde(default)]
+    inputs: Vec<TvInput>,
+}
+
+fn default_cec_device() -> String {
+    "/dev/cec0".to_string()
+}
+
+fn default_cec_name() -> String {
+    "Raspberry".to_string()
+}
+
+/// An input of the [Cec] TV, with its physical address like "2.0.0.0".
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct TvInput {
+    name: String,
+    address: String,
+}
+
+/// A device of the [Bridge], with a KNX group address like "1/0/1" or a Modbus coil or register
+/// like "12".
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Device {
+    name: String,
+    switch: String,
+    dim: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Weather {
+    name: Option<String>,
+    latitude: f64,
+    longitude: f64,
+    #[serde(default)]
+    units: WeatherUnits,
+    /// How long answers of the weather service are reused, 0 to always ask it.
+    #[serde(default = "default_weather_cache_minutes")]
+    cache_minutes: u64,
+}
+
+fn default_weather_cache_minutes() -> u64 {
+    10
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+enum WeatherUnits {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+/// See [assistant::knowledge::KnowledgeSkill].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Knowledge {
+    /// The language of the Wikipedia, like "en"
+    #[serde(default = "default_knowledge_language")]
+    language: String,
+    #[serde(default = "default_wikipedia")]
+    wikipedia: bool,
+    /// The address of a kiwix-serve server, asked before Wikipedia
+    kiwix: Option<String>,
+    /// The name of the archive on the Kiwix server
+    kiwix_book: Option<String>,
+    /// How many characters of an article are said at most
+    max_length: Option<usize>,
+    /// How long answers of Wikipedia are reused, 0 to always ask it.
+    #[serde(default = "default_knowledge_cache_minutes")]
+    cache_minutes: u64,
+}
+
+fn default_knowledge_language() -> String {
+    "en".to_string()
+}
+
+fn default_wikipedia() -> bool {
+    true
+}
+
+fn default_knowledge_cache_minutes() -> u64 {
+    24 * 60
+}
+
+/// See [assistant::transit::TransitSkill].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Transit {
+    /// The transport.rest API the stops are from
+    #[serde(default = "default_transit_url")]
+    url: String,
+    /// How long departures are reused, 0 to always ask the API.
+    #[serde(default = "default_transit_cache_seconds")]
+    cache_seconds: u64,
+    #[serde(default)]
+    routes: Vec<Route>,
+}
+
+fn default_transit_url() -> String {
+    "https://v6.db.transport.rest".to_string()
+}
+
+fn default_transit_cache_seconds() -> u64 {
+    30
+}
+
+/// A route of the [Transit], by the name it is called by.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Route {
+    name: String,
+    stop: String,
+    line: Option<String>,
+    direction: Option<String>,
+}
+
+/// See [assistant::sports::SportsSkill].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Sports {
+    /// The API key of TheSportsDB
+    #[serde(default = "default_sports_key")]
+    key: String,
+    /// When to remind of the matches of the day, like "09:00"
+    reminder: Option<String>,
+    /// How long answers of TheSportsDB are reused, 0 to always ask it.
+    #[serde(default = "default_weather_cache_minutes")]
+    cache_minutes: u64,
+    #[serde(default)]
+    teams: Vec<SportsTeam>,
+}
+
+fn default_sports_key() -> String {
+    "123".to_string()
+}
+
+/// A team of the [Sports], by the name it is called by and its id at TheSportsDB.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SportsTeam {
+    name: String,
+    id: String,
+}
+
+/// See [assistant::packages::PackageSkill]. More packages can be added through the server.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Packages {
+    /// The API key of AfterShip
+    api_key: String,
+    /// How often the packages are checked for the announcements
+    #[serde(default = "default_packages_check_minutes")]
+    pub check_minutes: u64,
+    /// Whether to announce when a package is out for delivery, expected or delivered
+    #[serde(default = "default_packages_announce")]
+    announce: bool,
+    #[serde(default)]
+    tracking: Vec<TrackedPackage>,
+}
+
+fn default_packages_check_minutes() -> u64 {
+    30
+}
+
+fn default_packages_announce() -> bool {
+    true
+}
+
+/// A package of the [Packages], with what is in it to say instead of the number.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct TrackedPackage {
+    number: String,
+    carrier: Option<String>,
+    name: Option<String>,
+}
+
+/// See [assistant::birthdays::BirthdaySkill].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Birthdays {
+    /// The dates, relative to the configuration directory
+    file: String,
+    /// How many days ahead upcoming dates are said
+    #[serde(default = "default_birthdays_days")]
+    days: u32,
+}
+
+fn default_birthdays_days() -> u32 {
+    30
+}
+
+/// See [assistant::sync::SyncConfig]. Snapshots go to either `url` or `folder`.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct SyncService {
+    passphrase: String,
+    url: Option<String>,
+    token: Option<String>,
+    folder: Option<String>,
+    /// Names the file of this assistant in the folder
+    instance: Option<String>,
+    #[serde(default = "default_sync_minutes")]
+    minutes: f64,
+}
+
+/// See [HandoffSkill].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Peer {
+    /// Where it is, like "bedroom" for "continue in the bedroom"
+    name: String,
+    /// The address of its [Server], like "192.168.1.41:8080"
+    address: String,
+}
+
+/// See [assistant::connectivity::ConnectivityConfig].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Connectivity {
+    hosts: Option<Vec<String>>,
+    seconds: Option<f64>,
+}
+
+impl Connectivity {
+    pub fn to_connectivity_config(&self) -> ConnectivityConfig {
+        let mut config = ConnectivityConfig::new();
+        if let Some(hosts) = &self.hosts {
+            config.set_hosts(hosts.clone());
+        }
+        if let Some(seconds) = self.seconds {
+            config.set_interval(std::time::Duration::from_secs_f64(seconds));
+        }
+        config
+    }
+}
+
+/// See [assistant::http::HttpClientConfig].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Http {
+    seconds: Option<f64>,
+    retries: Option<u32>,
+    /// Per host, 0 for no limit.
+    requests_per_minute: Option<usize>,
+}
+
+impl Http {
+    pub fn to_http_client(&self) -> HttpClient {
+        let mut config = HttpClientConfig::new();
+        if let Some(seconds) = self.seconds {
+            config.set_timeout(std::time::Duration::from_secs_f64(seconds));
+        }
+        if let Some(retries) = self.retries {
+            config.set_retries(
+                retries,
+                std::time::Duration::from_millis(500),
+                std::time::Duration::from_secs(4),
+            );
+        }
+        if let Some(requests) = self.requests_per_minute {
+            config.set_rate_limit((requests > 0).then(|| RateLimit::per_minute(requests)));
+        }
+        config.build()
+    }
+}
+
+/// See [assistant::recording::RecordingConfig].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Recording {
+    /// Relative to the config directory
+    #[serde(default = "default_recording_directory")]
+    directory: String,
+    /// Of audio before each wakeword detection
+    seconds: Option<f64>,
+    max_recordings: Option<usize>,
+    days: Option<f64>,
+    /// The archive of all the audio of a run, relative to the config directory
+    session: Option<String>,
+}
+
+fn default_recording_directory() -> String {
+    "recordings".to_string()
+}
+
+impl Recording {
+    pub fn to_recording_config(&self, config_dir: &Path) -> RecordingConfig {
+        let mut config = RecordingConfig::new(get_config_file(config_dir, &self.directory));
+        if let Some(seconds) = self.seconds {
+            config.set_wakeword_window(std::time::Duration::from_secs_f64(seconds));
+        }
+        if let Some(max_recordings) = self.max_recordings {
+            config.set_max_recordings(Some(max_recordings));
+        }
+        if let Some(days) = self.days {
+            config.set_max_age(Some(std::time::Duration::from_secs_f64(days * 86400.0)));
+        }
+        config
+    }
+
+    /// See [assistant::AssistantConfig::set_session_archive].
+    pub fn session_archive(&self, config_dir: &Path) -> Option<PathBuf> {
+        self.session
+            .as_ref()
+            .map(|session| get_config_file(config_dir, session))
+    }
+}
+
+/// See [assistant::tts_cache::TtsCacheConfig].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct TtsCache {
+    #[serde(default)]
+    phrases: Vec<String>,
+    capacity: Option<usize>,
+    max_length: Option<usize>,
+}
+
+impl TtsCache {
+    pub fn to_tts_cache_config(&self) -> TtsCacheConfig {
+        let mut config = TtsCacheConfig::new(EspeakSynthesizer);
+        for phrase in &self.phrases {
+            config.add_phrase(phrase.clone());
+        }
+        if let Some(capacity) = self.capacity {
+            config.set_capacity(capacity);
+        }
+        if let Some(max_length) = self.max_length {
+            config.set_max_length(max_length);
+        }
+        config
+    }
+}
+
+/// See [assistant::skills::Acknowledgement].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Acknowledgement {
+    /// The name of a skill or of one of its intents
+    pub name: String,
+    text: String,
+    /// How long the skill has to answer first
+    seconds: Option<f64>,
+}
+
+impl Acknowledgement {
+    pub fn to_acknowledgement(&self) -> assistant::skills::Acknowledgement {
+        let mut acknowledgement = assistant::skills::Acknowledgement::new(&self.text);
+        if let Some(seconds) = self.seconds {
+            acknowledgement.set_delay(std::time::Duration::from_secs_f64(seconds));
+        }
+        acknowledgement
+    }
+}
+
+/// See [assistant::speakers::VoskSpeakerIdentifier].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Speakers {
+    /// The directory of the Vosk speaker model
+    pub model: String,
+    pub threshold: Option<f32>,
+    /// By the name of the speaker profile
+    #[serde(default)]
+    preferences: HashMap<String, SpeakerPreference>,
+}
+
+/// See [assistant::speakers::SpeakerPreferences].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct SpeakerPreference {
+    voice: Option<String>,
+    language: Option<String>,
+    groups: Option<Vec<String>>,
+    weather: Option<Place>,
+    stt_model: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Place {
+    name: Option<String>,
+    latitude: f64,
+    longitude: f64,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct HomeAssistant {
+    pub url: String,
+    pub token: String,
+    pub language: Option<String>,
+    /// Give sentences no intent matched to the conversation agent of Home Assistant.
+    #[serde(default)]
+    pub conversation: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Mqtt {
+    pub address: String,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+/// A person detector publishing to MQTT, see [assistant::presence::PresenceSignal].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Presence {
+    pub topic: String,
+    /// How long someone counts as present after they were last detected
+    #[serde(default = "default_presence_seconds")]
+    pub seconds: f64,
+}
+
+fn default_presence_seconds() -> f64 {
+    60.0
+}
+
+/// See [assistant::guest::GuestModeConfig].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GuestMode {
+    wakeword: Option<String>,
+    #[serde(default)]
+    groups: Vec<String>,
+    /// How long guest mode stays on when it's turned on by voice
+    pub hours: f64,
+    start: Option<String>,
+    end: Option<String>,
+}
+
+/// See [assistant::language::Language].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Language {
+    pub name: String,
+    pub stt_model: String,
+    /// The name of the TTS voice
+    pub voice: Option<String>,
+}
+
+/// The `switch_language` of the language of `stt_model` and the default voice.
+pub const DEFAULT_LANGUAGE: &str = "default";
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Wakeword {
+    pub name: String,
+    pub file: String,
+    #[serde(default = "default_listen")]
+    pub listen: bool,
+    pub response: Option<String>,
+    /// The Vosk model of the queries after the wakeword, if not `stt_model`
+    pub stt_model: Option<String>,
+    /// Only the intents with this context are recognized after the wakeword
+    pub context: Option<String>,
+}
+
+/// See [assistant::cues::AudioCueSource].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AudioCues {
+    pub name: String,
+    /// The input device, as listed by `raspberry list-input-devices`
+    pub device: String,
+    pub cues: Vec<AudioCue>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AudioCue {
+    pub name: String,
+    /// In the Rustpotter format, like wakewords
+    pub file: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Intent {
+    pub name: String,
+    pub examples: Vec<String>,
+    pub group: Option<String>,
+    /// The name of the language the examples are in
+    pub language: Option<String>,
+    /// Recognized only after the wakewords with this context
+    pub context: Option<String>,
+    pub threshold: Option<f32>,
+    #[serde(default)]
+    keywords: Vec<String>,
+    #[serde(default)]
+    patterns: Vec<String>,
+    response: Option<Response>,
+    action: Option<Action>,
+    infrared: Option<String>,
+    mqtt: Option<String>,
+    home_assistant: Option<String>,
+    #[serde(default)]
+    home_assistant_data: HashMap<String, String>,
+    switch_language: Option<String>,
+}
+
+/// The canned response of an intent, or variants of it of which one is said at random.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Response {
+    One(String),
+    Variants(Vec<String>),
+}
+
+/// What the assistant does when an intent without a response matches.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Behavior {
+    Action(Action),
+    /// Send the IR code with the given name, learned with `raspberry learn-ir`
+    InfraredCode(String),
+    /// Publish the query to the MQTT topic
+    Mqtt {
+        topic: String,
+        intent: String,
+    },
+    /// Run the Home Assistant intent with the slots and the data
+    HomeAssistant {
+        intent: String,
+        data: HashMap<String, String>,
+    },
+    /// Switch to the language with the given name, or to the default one with `None`
+    SwitchLanguage(Option<String>),
+}
+
+/// See [assistant::intents::Scoring].
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+enum IntentScoring {
+    #[default]
+    Closest,
+    Centroid,
+    TopKMean(usize),
+}
+
+/// The built-in actions an intent can run.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Time,
+    Day,
+    Date,
+    AccessibilityOn,
+    AccessibilityOff,
+    SmartHomeOn,
+    SmartHomeOff,
+    GuestModeOn,
+    GuestModeOff,
+    /// Close the microphone for the duration in the query, with do not disturb on
+    StopListening,
+    LearnVoice,
+    ListVoices,
+    /// Forget the voice of the speaker
+    ForgetVoice,
+    /// Teach the assistant a new wakeword, used after a restart
+    LearnWakeword,
+}
+
+/// See [assistant::automation::Rule].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    name: String,
+    trigger: RuleTrigger,
+    #[serde(default)]
+    when: RuleConditions,
+    actions: Vec<RuleAction>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum RuleTrigger {
+    /// The name of an intent of the configuration or of a skill
+    Intent(String),
+    /// The label of a timer, alarm or reminder, or "*" for all of them
+    Schedule(String),
+    /// An MQTT topic filter
+    Mqtt(String),
+    /// A state key
+    State(String),
+    /// The name of an audio cue
+    AudioCue(String),
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct RuleConditions {
+    /// From and to, like `["22:00", "07:00"]`
+    between: Option<[String; 2]>,
+    do_not_disturb: Option<bool>,
+    #[serde(default)]
+    state: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+enum RuleAction {
+    Speak(String),
+    Query(String),
+    DoNotDisturb(bool),
+    State {
+        key: String,
+        value: String,
+    },
+    Mqtt {
+        topic: String,
+        payload: String,
+        #[serde(default)]
+        retain: bool,
+    },
+    Http {
+        url: String,
+        #[serde(default = "default_method")]
+        method: String,
+        body: Option<String>,
+    },
+}
+
+/// See [crate::scheduler::Announcement].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Announcement {
+    name: String,
+    /// Like `"07:30"`
+    at: String,
+    /// `"weekdays"`, `"weekends"` and days like `"mon"`, every day if empty
+    #[serde(default)]
+    days: Vec<String>,
+    actions: Vec<RuleAction>,
+}
+
+/// See [assistant::chores::Chore].
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Chore {
+    name: String,
+    done: String,
+    every_days: u32,
+    /// When to remind of it, like `"09:00"`
+    #[serde(default = "default_chore_at")]
+    at: String,
+    #[serde(default)]
+    people: Vec<String>,
+}
+
+fn default_chore_at() -> String {
+    "09:00".to_string()
+}
+
+fn default_method() -> String {
+    "POST".to_string()
+}
+
+fn default_listen() -> bool {
+    true
+}
+
+fn default_client_id() -> String {
+    "raspberry".to_string()
+}
+
+fn default_sync_minutes() -> f64 {
+    5.0
+}
+
+impl Scheduling {
+    pub fn to_scheduling_config(&self) -> SchedulingConfig {
+        SchedulingConfig {
+            audio: ThreadScheduling {
+                realtime_priority: self.audio_priority,
+                cores: self.audio_cores.clone(),
+            },
+            inference: ThreadScheduling {
+                realtime_priority: None,
+                cores: self.inference_cores.clone(),
+            },
+        }
+    }
+}
+
+impl Config {
+    pub fn scoring(&self) -> Scoring {
+        match self.scoring {
+            IntentScoring::Closest => Scoring::Closest,
+            IntentScoring::Centroid => Scoring::Centroid,
+            IntentScoring::TopKMean(k) => Scoring::TopKMean(k),
+        }
+    }
+
+    /// The Vosk models of wakewords and speakers other than `stt_model`, each once.
+    pub fn other_stt_models(&self) -> Vec<&str> {
+        let wakewords = self.wakewords.iter().map(|wakeword| &wakeword.stt_model);
+        let speakers = self
+            .speakers
+            .iter()
+            .flat_map(|speakers| speakers.preferences.values())
+            .map(|preference| &preference.stt_model);
+        let mut models: Vec<&str> = wakewords
+            .chain(speakers)
+            .flatten()
+            .map(String::as_str)
+            .filter(|model| *model != self.stt_model)
+            .collect();
+        models.sort();
+        models.dedup();
+        models
+    }
+
+    /// The skill handing the dialog off to the [Peer]s, `None` without them, or why they can't be
+    /// used.
+    pub fn handoff_skill(&self) -> Result<Option<HandoffSkill>, String> {
+        if self.peers.is_empty() {
+            return Ok(None);
+        }
+        let sync = self
+            .sync
+            .as_ref()
+            .ok_or("[[peers]] need the [sync] passphrase of the assistants")?;
+        let peers = self
+            .peers
+            .iter()
+            .map(|peer| HandoffPeer {
+                name: peer.name.clone(),
+                address: peer.address.clone(),
+            })
+            .collect();
+        Ok(Some(HandoffSkill::new(peers, sync.key())))
+    }
+
+    pub fn to_permissions(&self) -> Permissions {
+        let mut permissions = Permissions::new();
+        for (skill, denied) in &self.permissions {
+            for capability in denied {
+                permissions.deny(
+                    skill,
+                    match capability {
+                        SkillCapability::RecordAudio => Capability::RecordAudio,
+                        SkillCapability::Network => Capability::Network,
+                        SkillCapability::Speak => Capability::Speak,
+                        SkillCapability::SystemCommands => Capability::SystemCommands,
+                        SkillCapability::Storage => Capability::Storage,
+                    },
+                );
+            }
+        }
+        permissions
+    }
+}
+
+impl WakewordDetector {
+    pub fn to_detector_settings(&self) -> DetectorSettings {
+        let mut settings = DetectorSettings::new();
+        if let Some(threshold) = self.threshold {
+            settings.set_threshold(threshold);
+        }
+        if let Some(avg_threshold) = self.avg_threshold {
+            settings.set_avg_threshold(avg_threshold);
+        }
+        if let Some(min_scores) = self.min_scores {
+            settings.set_min_scores(min_scores);
+        }
+        if let Some(score_ref) = self.score_ref {
+            settings.set_score_ref(score_ref);
+        }
+        if let Some(score_mode) = self.score_mode {
+            settings.set_score_mode(match score_mode {
+                DetectorScoreMode::Average => ScoreMode::Average,
+                DetectorScoreMode::Max => ScoreMode::Max,
+                DetectorScoreMode::Median => ScoreMode::Median,
+                DetectorScoreMode::P25 => ScoreMode::P25,
+                DetectorScoreMode::P50 => ScoreMode::P50,
+                DetectorScoreMode::P75 => ScoreMode::P75,
+                DetectorScoreMode::P80 => ScoreMode::P80,
+                DetectorScoreMode::P90 => ScoreMode::P90,
+                DetectorScoreMode::P95 => ScoreMode::P95,
+            });
+        }
+        settings.set_band_pass(self.band_pass.map(|[low_cutoff, high_cutoff]| BandPass {
+            low_cutoff,
+            high_cutoff,
+        }));
+        settings.set_gain_normalizer(self.gain_normalizer.then(GainNormalizer::default));
+        settings
+    }
+}
+
+impl GuestMode {
+    pub fn to_guest_mode_config(&self) -> GuestModeConfig {
+        GuestModeConfig {
+            wakeword: self.wakeword.clone(),
+            allowed_groups: self.groups.clone(),
+            start_announcement: self.start.clone(),
+            end_announcement: self.end.clone(),
+        }
+    }
+}
+
+impl SyncService {
+    /// The key the snapshots, and the dialogs handed off to the [Peer]s, are sealed with.
+    pub fn key(&self) -> SyncKey {
+        SyncKey::from_passphrase(&self.passphrase)
+    }
+
+    pub fn to_sync_config(&self, config_dir: &Path) -> SyncConfig {
+        let key = self.key();
+        let mut config = match (&self.url, &self.folder, &self.instance) {
+            (Some(url), None, _) => SyncConfig::new(HttpSync::new(url, self.token.clone()), key),
+            (None, Some(folder), Some(instance)) => SyncConfig::new(
+                FolderSync::new(get_config_file(config_dir, folder), instance),
+                key,
+            ),
+            _ => unreachable!("Checked when loading the configuration"),
+        };
+        config.set_interval(std::time::Duration::from_secs_f64(self.minutes * 60.0));
+        config
+    }
+}
+
+impl Speakers {
+    /// The preferences of each speaker profile.
+    pub fn preferences(&self) -> impl Iterator<Item = (&str, SpeakerPreferences)> {
+        self.preferences.iter().map(|(name, preference)| {
+            let preferences = SpeakerPreferences {
+                language: preference.language.clone(),
+                voice: preference.voice.clone().map(VoiceSelection::Name),
+                weather_location: preference.weather.as_ref().map(|place| WeatherLocation {
+                    name: place.name.clone(),
+                    latitude: place.latitude,
+                    longitude: place.longitude,
+                }),
+                allowed_groups: preference.groups.clone(),
+                speech_recognizer: preference.stt_model.clone(),
+            };
+            (name.as_str(), preferences)
+        })
+    }
+}
+
+impl Weather {
+    pub fn to_skill(&self, client: HttpClient, cache: HttpCache) -> WeatherSkill {
+        let location = self.location();
+        let units = match self.units {
+            WeatherUnits::Metric => Units::Metric,
+            WeatherUnits::Imperial => Units::Imperial,
+        };
+        let mut provider = OpenMeteo::new(units);
+        provider.set_client(client);
+        provider.set_cache(
+            cache,
+            std::time::Duration::from_secs(self.cache_minutes * 60),
+        );
+        WeatherSkill::with_provider(provider, location, units)
+    }
+
+    fn location(&self) -> WeatherLocation {
+        WeatherLocation {
+            name: self.name.clone(),
+            latitude: self.latitude,
+            longitude: self.longitude,
+        }
+    }
+}
+
+impl AirQuality {
+    fn provider(&self, client: HttpClient, cache: HttpCache) -> OpenMeteoAirQuality {
+        let mut provider = OpenMeteoAirQuality::new(self.index());
+        provider.set_client(client);
+        provider.set_cache(
+            cache,
+            std::time::Duration::from_secs(self.cache_minutes * 60),
+        );
+        provider
+    }
+
+    fn index(&self) -> AirQualityIndex {
+        match self.index {
+            AirQualityScale::European => AirQualityIndex::European,
+            AirQualityScale::Us => AirQualityIndex::UnitedStates,
+        }
+    }
+
+    pub fn to_skill(
+        &self,
+        weather: &Weather,
+        client: HttpClient,
+        cache: HttpCache,
+    ) -> AirQualitySkill {
+        let provider = self.provider(client, cache);
+        AirQualitySkill::with_provider(provider, weather.location(), self.index())
+    }
+
+    /// The warnings, `None` without thresholds.
+    pub fn to_alert(
+        &self,
+        weather: &Weather,
+        client: HttpClient,
+        cache: HttpCache,
+    ) -> Option<AirQualityAlert> {
+        if self.alert_index.is_none() && self.alert_pollen.is_none() {
+            return None;
+        }
+        let provider = self.provider(client, cache);
+        let mut alert = AirQualityAlert::new(provider, weather.location(), self.index());
+        alert.set_index_threshold(self.alert_index);
+        alert.set_pollen_threshold(self.alert_pollen);
+        Some(alert)
+    }
+}
+
+impl Bridge {
+    /// The skill controlling the devices, or why the section is invalid.
+    pub fn to_skill(&self) -> Result<BridgeSkill, String> {
+        let parse = |address: &str| match self.protocol {
+            BridgeProtocol::Knx => parse_group_address(address).map_err(|e| e.to_string()),
+            BridgeProtocol::Modbus => address
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid Modbus address {}", address)),
+        };
+        let devices = self
+            .devices
+            .iter()
+            .map(|device| {
+                Ok(BridgeDevice {
+                    name: device.name.clone(),
+                    switch: parse(&device.switch)?,
+                    dim: device.dim.as_deref().map(parse).transpose()?,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(match self.protocol {
+            BridgeProtocol::Knx => {
+                let source = parse_individual_address(&self.individual_address)
+                    .map_err(|e| e.to_string())?;
+                let address = self.address.as_deref().unwrap_or(KNX_ROUTING_ADDRESS);
+                BridgeSkill::new(KnxRouting::new(address, source), devices)
+            }
+            BridgeProtocol::Modbus => {
+                let address = self
+                    .address
+                    .as_deref()
+                    .ok_or("The Modbus bridge needs the address of the device")?;
+                BridgeSkill::new(ModbusTcp::new(address, self.unit), devices)
+            }
+        })
+    }
+}
+
+impl Hue {
+    /// The skill controlling the lights, with the rooms and scenes of the bridge.
+    pub fn to_skill(&self, client: HttpClient) -> HueSkill {
+        let mut bridge = HueBridge::new(&self.address, &self.key);
+        bridge.set_client(client);
+        HueSkill::new(bridge)
+    }
+}
+
+impl Sonos {
+    /// The zones, or why the section is invalid.
+    pub fn zones(&self, client: HttpClient) -> Result<Vec<SonosZone>, String> {
+        if self.zones.is_empty() {
+            return Err("[sonos] needs at least one of [[sonos.zones]]".to_string());
+        }
+        self.zones
+            .iter()
+            .map(|declared| {
+                if declared
+                    .announcement_volume
+                    .is_some_and(|volume| volume > 100)
+                {
+                    return Err(format!(
+                        "The announcement volume of the Sonos zone \"{}\" is above 100",
+                        declared.name
+                    ));
+                }
+                let mut zone = SonosZone::new(&declared.name, &declared.address);
+                zone.set_client(client.clone());
+                zone.set_announcement_volume(declared.announcement_volume);
+                Ok(zone)
+            })
+            .collect()
+    }
+
+    /// The skill controlling the playback of the zones, or why the section is invalid.
+    pub fn to_skill(&self, client: HttpClient) -> Result<SonosSkill, String> {
+        Ok(SonosSkill::new(self.zones(client)?))
+    }
+
+    /// The port of the server the zones get the announcements from, `None` without `announce`,
+    /// or why it can't be used.
+    pub fn announcement_port(&self, server: Option<&Server>) -> Result<Option<u16>, String> {
+        if !self.announce {
+            return Ok(None);
+        }
+        let server = server.ok_or("Sonos announcements need the [server]")?;
+        server
+            .address
+            .rsplit_once(':')
+            .and_then(|(_, port)| port.parse().ok())
+            .map(Some)
+            .ok_or_else(|| format!("The server address {} has no port", server.address))
+    }
+}
+
+impl Cec {
+    /// The skill controlling the TV, or why the section is invalid.
+    pub fn to_skill(&self) -> Result<CecSkill, String> {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| {
+                if !is_physical_address(&input.address) {
+                    return Err(format!(
+                        "Invalid physical address {} of the TV input \"{}\"",
+                        input.address, input.name
+                    ));
+                }
+                Ok(CecInput {
+                    name: input.name.clone(),
+                    address: input.address.to_ascii_lowercase(),
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        // The CEC protocol limits names to 14 characters
+        if self.name.is_empty() || self.name.len() > 14 || !self.name.is_ascii() {
+            return Err("The CEC name needs 1 to 14 ASCII characters".to_string());
+        }
+        Ok(CecSkill::new(&self.device, &self.name, inputs))
+    }
+}
+
+impl Knowledge {
+    /// The skill looking subjects up, or why the section is invalid.
+    pub fn to_skill(&self, client: HttpClient, cache: HttpCache) -> Result<KnowledgeSkill, String> {
+        let mut sources: Vec<Box<dyn KnowledgeSource>> = Vec::new();
+        if let Some(url) = &self.kiwix {
+            let book = self
+                .kiwix_book
+                .as_deref()
+                .ok_or("Kiwix needs the name of the archive in kiwix_book")?;
+            let mut kiwix = Kiwix::new(url, book);
+            kiwix.set_client(client.clone());
+            sources.push(Box::new(kiwix));
+        }
+        if self.wikipedia {
+            let mut wikipedia = Wikipedia::new(&self.language);
+            wikipedia.set_client(client);
+            wikipedia.set_cache(
+                cache,
+                std::time::Duration::from_secs(self.cache_minutes * 60),
+            );
+            sources.push(Box::new(wikipedia));
+        }
+        if sources.is_empty() {
+            return Err("Knowledge needs Wikipedia or a Kiwix server".to_string());
+        }
+        let mut skill = KnowledgeSkill::new(sources);
+        if let Some(max_length) = self.max_length {
+            skill.set_max_length(max_length);
+        }
+        Ok(skill)
+    }
+}
+
+impl Transit {
+    pub fn to_skill(&self, client: HttpClient, cache: HttpCache) -> TransitSkill {
+        let mut provider = TransportRest::new(&self.url);
+        provider.set_client(client);
+        provider.set_cache(cache, std::time::Duration::from_secs(self.cache_seconds));
+        let routes = self
+            .routes
+            .iter()
+            .map(|route| TransitRoute {
+                name: route.name.clone(),
+                stop: route.stop.clone(),
+                line: route.line.clone(),
+                direction: route.direction.clone(),
+            })
+            .collect();
+        TransitSkill::new(provider, routes)
+    }
+}
+
+impl Sports {
+    fn provider(&self, client: HttpClient, cache: HttpCache) -> TheSportsDb {
+        let mut provider = TheSportsDb::new(&self.key);
+        provider.set_client(client);
+        provider.set_cache(
+            cache,
+            std::time::Duration::from_secs(self.cache_minutes * 60),
+        );
+        provider
+    }
+
+    fn teams(&self) -> Vec<Team> {
+        self.teams
+            .iter()
+            .map(|team| Team {
+                name: team.name.clone(),
+                id: team.id.clone(),
+            })
+            .collect()
+    }
+
+    pub fn to_skill(&self, client: HttpClient, cache: HttpCache) -> SportsSkill {
+        SportsSkill::new(self.provider(client, cache), self.teams())
+    }
+
+    /// The reminder of the matches of the day, `None` without a time for it, or why the time is
+    /// invalid.
+    pub fn to_reminder(
+        &self,
+        client: HttpClient,
+        cache: HttpCache,
+    ) -> Result<Option<MatchDayReminder>, String> {
+        let Some(reminder) = &self.reminder else {
+            return Ok(None);
+        };
+        let time = NaiveTime::parse_from_str(reminder, "%H:%M")
+            .map_err(|_| format!("The sports reminder has an invalid time {}", reminder))?;
+        let provider = self.provider(client, cache);
+        Ok(Some(MatchDayReminder::new(provider, self.teams(), time)))
+    }
+}
+
+impl Birthdays {
+    /// The file of the dates, read again whenever they are needed.
+    pub fn path(&self, config_dir: &Path) -> PathBuf {
+        get_config_file(config_dir, &self.file)
+    }
+
+    pub fn to_skill(&self, config_dir: &Path) -> BirthdaySkill {
+        let mut skill = BirthdaySkill::new(self.path(config_dir));
+        skill.set_days(self.days);
+        skill
+    }
+}
+
+impl Packages {
+    fn provider(&self, client: HttpClient) -> AfterShip {
+        let mut provider = AfterShip::new(&self.api_key);
+        provider.set_client(client);
+        provider
+    }
+
+    fn packages(&self) -> Vec<Package> {
+        self.tracking
+            .iter()
+            .map(|package| Package {
+                number: package.number.clone(),
+                carrier: package.carrier.clone(),
+                name: package.name.clone(),
+            })
+            .collect()
+    }
+
+    pub fn to_skill(&self, client: HttpClient) -> PackageSkill {
+        PackageSkill::new(self.provider(client), self.packages())
+    }
+
+    /// The announcements of deliveries, `None` if they are turned off.
+    pub fn to_announcer(
+        &self,
+        client: HttpClient,
+        storage: Arc<dyn Storage>,
+    ) -> Option<DeliveryAnnouncer> {
+        self.announce
+            .then(|| DeliveryAnnouncer::new(self.provider(client), self.packages(), Some(storage)))
+    }
+}
+
+impl Intent {
+    /// Matches the keywords and patterns when the examples don't, `None` without any.
+    pub fn fallback(&self) -> Result<Option<Fallback>, String> {
+        if self.keywords.is_empty() && self.patterns.is_empty() {
+            return Ok(None);
+        }
+        let patterns = self
+            .patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(&format!("(?i){}", pattern))
+                    .map_err(|e| format!("Invalid pattern of intent \"{}\": {}", self.name, e))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Some(Fallback {
+            keywords: self.keywords.clone(),
+            patterns,
+        }))
+    }
+
+    /// The variants of the canned response, for intents that only respond.
+    pub fn responses(&self) -> Option<&[String]> {
+        match self.response.as_ref()? {
+            Response::One(response) => Some(std::slice::from_ref(response)),
+            Response::Variants(variants) => Some(variants),
+        }
+    }
+
+    /// `None` for intents with a response.
+    pub fn behavior(&self) -> Option<Behavior> {
+        if let Some(action) = self.action {
+            return Some(Behavior::Action(action));
+        }
+        if let Some(code) = &self.infrared {
+            return Some(Behavior::InfraredCode(code.clone()));
+        }
+        if let Some(language) = &self.switch_language {
+            let language = (language != DEFAULT_LANGUAGE).then(|| language.clone());
+            return Some(Behavior::SwitchLanguage(language));
+        }
+        if let Some(topic) = &self.mqtt {
+            return Some(Behavior::Mqtt {
+                topic: topic.clone(),
+                intent: self.name.clone(),
+            });
+        }
+        self.home_assistant
+            .as_ref()
+            .map(|intent| Behavior::HomeAssistant {
+                intent: intent.clone(),
+                data: self.home_assistant_data.clone(),
+            })
+    }
+}
+
+impl Rule {
+    /// Intents of the configuration are matched by their behavior, other names are the intents
+    /// of skills.
+    pub fn to_rule(&self, intents: &[Intent]) -> Result<automation::Rule<Behavior>, String> {
+        let trigger = match &self.trigger {
+            RuleTrigger::Intent(name) => match intents.iter().find(|intent| intent.name == *name) {
+                Some(intent) => match intent.behavior() {
+                    Some(behavior) => Trigger::Intent(behavior),
+                    None => Trigger::SkillIntent(name.clone()),
+                },
+                None => Trigger::SkillIntent(name.clone()),
+            },
+            RuleTrigger::Schedule(label) if label == "*" => Trigger::ScheduledItemDue(None),
+            RuleTrigger::Schedule(label) => Trigger::ScheduledItemDue(Some(label.clone())),
+            RuleTrigger::Mqtt(topic) => Trigger::MqttMessage(topic.clone()),
+            RuleTrigger::State(key) => Trigger::StateChanged(key.clone()),
+            RuleTrigger::AudioCue(name) => Trigger::AudioCue(name.clone()),
+        };
+
+        let mut conditions = Vec::new();
+        if let Some([from, to]) = &self.when.between {
+            let parse = |time: &str| {
+                NaiveTime::parse_from_str(time, "%H:%M")
+                    .map_err(|_| format!("Rule \"{}\" has an invalid time {}", self.name, time))
+            };
+            conditions.push(Condition::TimeRange {
+                from: parse(from)?,
+                to: parse(to)?,
+            });
+        }
+        if let Some(on) = self.when.do_not_disturb {
+            conditions.push(Condition::DoNotDisturb(on));
+        }
+        for (key, value) in &self.when.state {
+            conditions.push(Condition::State {
+                key: key.clone(),
+                value: value.clone(),
+            });
+        }
+
+        Ok(automation::Rule {
+            name: self.name.clone(),
+            trigger,
+            conditions,
+            actions: self.actions.iter().map(RuleAction::to_action).collect(),
+        })
+    }
+
+    /// Whether the rule publishes or subscribes to MQTT topics.
+    fn uses_mqtt(&self) -> bool {
+        matches!(self.trigger, RuleTrigger::Mqtt(_))
+            || self
+                .actions
+                .iter()
+                .any(|action| matches!(action, RuleAction::Mqtt { .. }))
+    }
+
+    /// The topic filter of the trigger, for rules triggered by MQTT messages.
+    pub fn mqtt_topic(&self) -> Option<&str> {
+        match &self.trigger {
+            RuleTrigger::Mqtt(topic) => Some(topic),
+            _ => None,
+        }
+    }
+}
+
+impl RuleAction {
+    fn to_action(&self) -> automation::Action {
+        match self {
+            RuleAction::Speak(text) => automation::Action::Speak(text.clone()),
+            RuleAction::Query(text) => automation::Action::Query(text.clone()),
+            RuleAction::DoNotDisturb(on) => automation::Action::SetDoNotDisturb(*on),
+            RuleAction::State { key, value } => automation::Action::SetState {
+                key: key.clone(),
+                value: value.clone(),
+            },
+            RuleAction::Mqtt {
+                topic,
+                payload,
+                retain,
+            } => automation::Action::PublishMqtt {
+                topic: topic.clone(),
+                payload: payload.clone(),
+                retain: *retain,
+            },
+            RuleAction::Http { url, method, body } => automation::Action::Http {
+                method: method.clone(),
+                url: url.clone(),
+                body: body.clone(),
+            },
+        }
+    }
+}
+
+impl Chore {
+    pub fn to_chore(&self) -> Result<chores::Chore, String> {
+        let time = NaiveTime::parse_from_str(&self.at, "%H:%M")
+            .map_err(|_| format!("Chore \"{}\" has an invalid time {}", self.name, self.at))?;
+        if self.every_days == 0 {
+            return Err(format!("Chore \"{}\" needs at least 1 day", self.name));
+        }
+        Ok(chores::Chore {
+            name: self.name.clone(),
+            done: self.done.clone(),
+            days: self.every_days,
+            time,
+            people: self.people.clone(),
+        })
+    }
+}
+
+impl Announcement {
+    pub fn to_announcement(&self) -> Result<scheduler::Announcement, String> {
+        let time = NaiveTime::parse_from_str(&self.at, "%H:%M").map_err(|_| {
+            format!(
+                "Announcement \"{}\" has an invalid time {}",
+                self.name, self.at
+            )
+        })?;
+        let mut days = Vec::new();
+        for day in &self.days {
+            match day.as_str() {
+                "weekdays" => days.extend([
+                    Weekday::Mon,
+                    Weekday::Tue,
+                    Weekday::Wed,
+                    Weekday::Thu,
+                    Weekday::Fri,
+                ]),
+                "weekends" => days.extend([Weekday::Sat, Weekday::Sun]),
+                day => days.push(day.parse().map_err(|_| {
+                    format!("Announcement \"{}\" has an invalid day {}", self.name, day)
+                })?),
+            }
+        }
+        Ok(scheduler::Announcement {
+            name: self.name.clone(),
+            time,
+            days,
+            actions: self.actions.iter().map(RuleAction::to_action).collect(),
+        })
+    }
+
+    fn uses_mqtt(&self) -> bool {
+        self.actions
+            .iter()
+            .any(|action| matches!(action, RuleAction::Mqtt { .. }))
+    }
+}
+
+/// Load `config.toml` from the config directory, writing the default configuration there first
+/// if it doesn't exist.
+pub fn load(config_dir: &Path) -> io::Result<Config> {
+    let path = get_config_file(config_dir, "config.toml");
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            fs::write(&path, DEFAULT_CONFIG)?;
+            DEFAULT_CONFIG.to_string()
+        }
+        Err(e) => return Err(e),
+    };
+    let (text, changes) = migrate(&path, &text)?;
+    for change in changes {
+        println!("{}", change);
+    }
+    let config: Config = toml::from_str(&text)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, describe_error(&text, &e)))?;
+
+    for intent in &config.intents {
+        let behaviors = [
+            intent.response.is_some(),
+            intent.action.is_some(),
+            intent.infrared.is_some(),
+            intent.mqtt.is_some(),
+            intent.home_assistant.is_some(),
+            intent.switch_language.is_some(),
+        ];
+        if intent
+            .responses()
+            .is_some_and(|variants| variants.is_empty())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("The response of intent \"{}\" has no variants", intent.name),
+            ));
+        }
+        if behaviors.iter().filter(|b| **b).count() != 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Intent \"{}\" needs exactly one of response, action, infrared, mqtt, \
+                     home_assistant and switch_language",
+                    intent.name
+                ),
+            ));
+        }
+        let is_language = |name: &String| {
+            config
+                .languages
+                .iter()
+                .any(|language| language.name == *name)
+        };
+        let unknown_language = intent
+            .language
+            .iter()
+            .chain(
+                intent
+                    .switch_language
+                    .iter()
+                    .filter(|name| *name != DEFAULT_LANGUAGE),
+            )
+            .find(|name| !is_language(name));
+        let has_context = |context: &String| {
+            config
+                .wakewords
+                .iter()
+                .any(|wakeword| wakeword.context.as_ref() == Some(context))
+        };
+        if let Some(context) = intent.context.as_ref().filter(|c| !has_context(c)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Intent \"{}\" has the context \"{}\", which no wakeword has",
+                    intent.name, context
+                ),
+            ));
+        }
+        if let Some(name) = unknown_language {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Intent \"{}\" needs a [[languages]] entry named \"{}\"",
+                    intent.name, name
+                ),
+            ));
+        }
+        let guest_mode = matches!(
+            intent.action,
+            Some(Action::GuestModeOn | Action::GuestModeOff)
+        );
+        let speakers = matches!(
+            intent.action,
+            Some(Action::LearnVoice | Action::ListVoices | Action::ForgetVoice)
+        );
+        let missing = if intent.mqtt.is_some() && config.mqtt.is_none() {
+            Some("mqtt")
+        } else if guest_mode && config.guest_mode.is_none() {
+            Some("guest_mode")
+        } else if speakers && config.speakers.is_none() {
+            Some("speakers")
+        } else if intent.home_assistant.is_some() && config.home_assistant.is_none() {
+            Some("home_assistant")
+        } else {
+            None
+        };
+        if let Some(section) = missing {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Intent \"{}\" needs the [{}] section", intent.name, section),
+            ));
+        }
+        intent
+            .fallback()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    if let Some(bridge) = &config.bridge {
+        bridge
+            .to_skill()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    if let Some(cec) = &config.cec {
+        cec.to_skill()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    if let Some(sonos) = &config.sonos {
+        sonos
+            .zones(HttpClient::default())
+            .and_then(|_| sonos.announcement_port(config.server.as_ref()))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    if let Some(knowledge) = &config.knowledge {
+        knowledge
+            .to_skill(HttpClient::default(), HttpCache::new())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    if let Some(birthdays) = &config.birthdays {
+        let path = birthdays.path(config_dir);
+        load_dates(&path).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Failed to read the birthdays in {}: {}", path.display(), e),
+            )
+        })?;
+    }
+    if let Some(sports) = &config.sports {
+        sports
+            .to_reminder(HttpClient::default(), HttpCache::new())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    if config.air_quality.is_some() && config.weather.is_none() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Air quality needs the [weather] section",
+        ));
+    }
+    if let Some(presence) = &config.presence {
+        let error = if config.mqtt.is_none() {
+            Some("Presence needs the [mqtt] section")
+        } else if !(presence.seconds >= 0.0 && presence.seconds.is_finite()) {
+            Some("Presence needs a number of seconds that isn't negative")
+        } else {
+            None
+        };
+        if let Some(error) = error {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+        }
+    }
+    if let Some(guest_mode) = &config.guest_mode {
+        if !(guest_mode.hours > 0.0 && guest_mode.hours.is_finite()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Guest mode needs a positive number of hours",
+            ));
+        }
+        let wakeword = guest_mode.wakeword.as_ref();
+        if wakeword.is_some_and(|name| !config.wakewords.iter().any(|w| w.name == *name)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The wakeword of guest mode isn't in [[wakewords]]",
+            ));
+        }
+    }
+    if let Some(sync) = &config.sync {
+        let error = match (&sync.url, &sync.folder, &sync.instance) {
+            (Some(_), Some(_), _) | (None, None, _) => Some("Sync needs either a url or a folder"),
+            (None, Some(_), None) => Some("Syncing through a folder needs an instance name"),
+            _ if !(sync.minutes > 0.0 && sync.minutes.is_finite()) => {
+                Some("Sync needs a positive number of minutes")
+            }
+            _ => None,
+        };
+        if let Some(error) = error {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+        }
+    }
+    config
+        .handoff_skill()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let seconds = config.connectivity.as_ref().and_then(|c| c.seconds);
+    if seconds.is_some_and(|seconds| !(seconds > 0.0 && seconds.is_finite())) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The connectivity check needs a positive number of seconds",
+        ));
+    }
+    let seconds = config.http.as_ref().and_then(|h| h.seconds);
+    if seconds.is_some_and(|seconds| !(seconds > 0.0 && seconds.is_finite())) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The HTTP timeout needs a positive number of seconds",
+        ));
+    }
+    for acknowledgement in &config.acknowledgements {
+        if acknowledgement
+            .seconds
+            .is_some_and(|seconds| !(seconds >= 0.0 && seconds.is_finite()))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "The acknowledgement of \"{}\" needs a number of seconds that isn't negative",
+                    acknowledgement.name
+                ),
+            ));
+        }
+    }
+    for rule in &config.automation {
+        rule.to_rule(&config.intents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let RuleTrigger::AudioCue(name) = &rule.trigger {
+            let declared = config
+                .audio_cues
+                .iter()
+                .flat_map(|source| &source.cues)
+                .any(|cue| cue.name == *name);
+            if !declared {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Rule \"{}\" needs the audio cue \"{}\"", rule.name, name),
+                ));
+            }
+        }
+        if rule.uses_mqtt() && config.mqtt.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Rule \"{}\" needs the [mqtt] section", rule.name),
+            ));
+        }
+    }
+    for chore in &config.chores {
+        chore
+            .to_chore()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    }
+    for announcement in &config.announcements {
+        announcement
+            .to_announcement()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if announcement.uses_mqtt() && config.mqtt.is_none() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Announcement \"{}\" needs the [mqtt] section",
+                    announcement.name
+                ),
+            ));
+        }
+    }
+    Ok(config)
+}
+
+/// Add a wakeword to `config.toml`, keeping its comments.
+pub fn add_wakeword(config_dir: &Path, name: &str, file: &str) -> io::Result<()> {
+    let path = get_config_file(config_dir, "config.toml");
+    let mut config: toml_edit::DocumentMut = fs::read_to_string(&path)?
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}", e)))?;
+    let mut wakeword = toml_edit::Table::new();
+    wakeword["name"] = toml_edit::value(name);
+    wakeword["file"] = toml_edit::value(file);
+    wakeword["listen"] = toml_edit::value(true);
+    wakeword["response"] = toml_edit::value("Yes?");
+    config
+        .entry("wakewords")
+        .or_insert(toml_edit::Item::ArrayOfTables(
+            toml_edit::ArrayOfTables::new(),
+        ))
+        .as_array_of_tables_mut()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "`wakewords` isn't a list"))?
+        .push(wakeword);
+    fs::write(path, config.to_string())
+}
+
+/// Write the values tuned with `raspberry tune` to `config.toml`, keeping its comments.
+pub fn save_tuning(
+    config_dir: &Path,
+    detector: &DetectorSettings,
+    intent_threshold: f32,
+) -> io::Result<()> {
+    let path = get_config_file(config_dir, "config.toml");
+    let mut config: toml_edit::DocumentMut = fs::read_to_string(&path)?
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}", e)))?;
+    // Written as typed, 0.6 instead of the 0.6000000238418579 of the f32
+    let float = |value: f32| toml_edit::value(value.to_string().parse::<f64>().unwrap_or(0.));
+    config["threshold"] = float(intent_threshold);
+    let section = config
+        .entry("wakeword_detector")
+        .or_insert(toml_edit::table())
+        .as_table_mut()
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "`wakeword_detector` isn't a section",
+            )
+        })?;
+    section["threshold"] = float(detector.threshold());
+    section["avg_threshold"] = float(detector.avg_threshold());
+    section["min_scores"] = toml_edit::value(detector.min_scores() as i64);
+    section["score_ref"] = float(detector.score_ref());
+    fs::write(path, config.to_string())
+}
+
+/// Write the bridge and key of `raspberry pair-hue` to `config.toml`, keeping its comments.
+pub fn save_hue(config_dir: &Path, address: &str, key: &str) -> io::Result<()> {
+    let path = get_config_file(config_dir, "config.toml");
+    let mut config: toml_edit::DocumentMut = fs::read_to_string(&path)?
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}", e)))?;
+    let mut hue = toml_edit::Table::new();
+    hue["address"] = toml_edit::value(address);
+    hue["key"] = toml_edit::value(key);
+    config["hue"] = toml_edit::Item::Table(hue);
+    fs::write(path, config.to_string())
+}
+
+/// Where a TOML error is, with a suggestion for a misspelled key or value, e.g. "line 12 in
+/// [speakers]: unknown field `treshold`, expected one of `model`, `threshold`, `preferences`. Did
+/// you mean `threshold`?".
+fn describe_error(text: &str, error: &toml::de::Error) -> String {
+    let message = error.message().trim_end_matches('\n');
+    let mut description = match error.span() {
+        Some(span) => {
+            let before = &text[..span.start.min(text.len())];
+            let line = before.matches('\n').count() + 1;
+            // The last table header before the error
+            let section = before
+                .lines()
+                .rev()
+                .map(str::trim)
+                .find(|line| line.starts_with('['));
+            match section {
+                Some(section) => format!("line {} in {}: {}", line, section, message),
+                None => format!("line {}: {}", line, message),
+            }
+        }
+        None => message.to_string(),
+    };
+    if let Some(suggestion) = suggestion(message) {
+        description.push_str(&format!(". Did you mean `{}`?", suggestion));
+    }
+    description
+}
+
+/// The expected name closest to the unknown one of a serde error like "unknown field `x`,
+/// expected one of `a`, `b`", if it looks like a typo.
+fn suggestion(message: &str) -> Option<&str> {
+    if !message.starts_with("unknown") {
+        return None;
+    }
+    let mut names = message.split('`').skip(1).step_by(2);
+    let unknown = names.next()?;
+    names
+        .map(|name| (name, edit_distance(unknown, name)))
+        .filter(|(_, distance)| *distance <= (unknown.len() / 3).max(2))
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(name, _)| name)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// `raspberry check-config [config_dir]`, which prints the first error in `config.toml`.
+pub fn check_command(mut args: impl Iterator<Item = String>) {
+    let config_dir: PathBuf = args
+        .next()
+        .map(Into::into)
+        .unwrap_or_else(crate::dirs::get_config_path);
+    match load(&config_dir) {
+        Ok(_) => println!("config.toml is valid"),
+        Err(e) => {
+            eprintln!("Error in config.toml: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use assistant::metrics::Metric;
+use std::{path::PathBuf, time::Duration};
+
+use crate::{
+    config,
+    dirs::{get_config_file, get_config_path},
+    remote,
+};
+
+/// Above this, the TTS is slow to start answering.
+const SLOW_FIRST_AUDIO: Duration = Duration::from_secs(1);
+
+/// `raspberry doctor [config_dir]`, checking that config.toml is valid and that its models and
+/// wakewords are there, then showing the latencies of the assistant running with it, read from
+/// the `/metrics` endpoint of its `[server]`, like how long the TTS takes to start saying
+/// something. Exits with 1 if something is wrong.
+pub fn doctor_command(mut args: impl Iterator<Item = String>) {
+    let config_dir: PathBuf = args.next().map(Into::into).unwrap_or_else(get_config_path);
+    let declared = match config::load(&config_dir) {
+        Ok(declared) => declared,
+        Err(e) => {
+            eprintln!("Error in config.toml: {}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("config.toml is valid");
+
+    let mut healthy = true;
+    let files = std::iter::once(("STT model", &declared.stt_model))
+        .chain(
+            declared
+                .languages
+                .iter()
+                .map(|language| ("STT model", &language.stt_model)),
+        )
+        .chain(
+            declared
+                .wakewords
+                .iter()
+                .map(|wakeword| ("Wakeword", &wakeword.file)),
+        );
+    for (kind, file) in files {
+        if !get_config_file(&config_dir, file).exists() {
+            println!("{} {} is missing", kind, file);
+            healthy = false;
+        }
+    }
+
+    let Some(server) = &declared.server else {
+        println!("Add a [server] to config.toml to see the latencies of the assistant");
+        exit(healthy);
+    };
+    let token = server.token.as_deref();
+    let metrics = match remote::fetch(&server.address, "GET", "/metrics", token, "") {
+        Ok(metrics) => metrics,
+        Err(e) => {
+            println!("Failed to get the latencies from {}: {}", server.address, e);
+            println!("Is the assistant running?");
+            exit(false);
+        }
+    };
+    println!(
+        "{:<20}  {:>7}  {:>10}  {:>10}",
+        "Latency", "Samples", "p50", "p95"
+    );
+    for metric in Metric::ALL {
+        let name = format!("assistant_{}_seconds", metric.name());
+        let sample = |suffix: &str| {
+            metrics.lines().find_map(|line| {
+                let value = line.strip_prefix(&name)?.strip_prefix(suffix)?;
+                value.strip_prefix(' ')?.trim().parse::<f64>().ok()
+            })
+        };
+        let count = sample("_count").unwrap_or_default();
+        let quantile = |quantile: &str| {
+            let seconds = sample(&format!("{{quantile=\"{}\"}}", quantile)).unwrap_or_default();
+            Duration::from_secs_f64(seconds.max(0.))
+        };
+        if count == 0. {
+            println!("{:<20}  {:>7}", metric.name(), 0);
+            continue;
+        }
+        println!(
+            "{:<20}  {:>7}  {:>10.1?}  {:>10.1?}",
+            metric.name(),
+            count,
+            quantile("0.5"),
+            quantile("0.95")
+        );
+        if metric == Metric::TtsFirstAudio && quantile("0.95") > SLOW_FIRST_AUDIO {
+            println!(
+                "The TTS is slow to start speaking, try a faster voice or add the common \
+                 answers to the phrases of [tts_cache]"
+            );
+            healthy = false;
+        }
+    }
+    exit(healthy);
+}
+
+fn exit(healthy: bool) -> ! {
+    std::process::exit(if healthy { 0 } else { 1 })
+}
@@ -0,0 +1,79 @@
+use assistant::{
+    intents::{IntentRecognizer, IntentsConfig},
+    storage::SqliteStorage,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    config,
+    dirs::{get_config_file, get_config_path},
+    intent_model, intent_slots,
+};
+
+const USAGE: &str = "Usage: raspberry explain <text> [config_dir]";
+
+/// How many of the closest intents are shown.
+const CANDIDATES: usize = 5;
+
+/// `raspberry explain <text> [config_dir]`, showing which intents of config.toml the text is
+/// closest to, the example each of them matched best and the gap to the next one, to find out
+/// why a sentence is recognized as the wrong intent. The intents of skills aren't included.
+pub fn explain_command(mut args: impl Iterator<Item = String>) {
+    let text = args.next().expect(USAGE);
+    let config_dir: PathBuf = args.next().map(Into::into).unwrap_or_else(get_config_path);
+    let declared = config::load(&config_dir).expect("Failed to load config.toml");
+    let recognizer = build_recognizer(&config_dir, &declared);
+
+    let explanation = recognizer
+        .explain(&text, CANDIDATES)
+        .expect("Failed to embed the text");
+    for candidate in &explanation.candidates {
+        println!(
+            "{:<24} score {:.3} (threshold {:.3}), gap {}",
+            candidate.intent,
+            candidate.score,
+            candidate.threshold,
+            candidate
+                .gap
+                .map_or("none".to_string(), |gap| format!("{:.3}", gap)),
+        );
+        println!(
+            "    closest example {:.3}: {}",
+            candidate.example_score, candidate.example
+        );
+    }
+    if let Some(negative) = explanation.negative_score {
+        println!("Closest negative example: {:.3}", negative);
+    }
+    match explanation.candidates.first() {
+        Some(best) if explanation.recognized => println!("Recognized as {}", best.intent),
+        _ => println!("Not recognized, unless a fallback matches"),
+    }
+}
+
+/// The intent recognizer of the intents of config.toml, without those of skills.
+pub fn build_recognizer(config_dir: &Path, declared: &config::Config) -> IntentRecognizer<String> {
+    let mut intents = IntentsConfig::new(intent_model(config_dir, declared));
+    if let Ok(storage) = SqliteStorage::open(get_config_file(config_dir, "storage.sqlite")) {
+        intents.set_embedding_cache(Arc::new(storage));
+    }
+    if let Some(threshold) = declared.threshold {
+        intents.set_threshold(threshold);
+    }
+    intents.set_scoring(declared.scoring());
+    intents.add_negative_examples(declared.negative_examples.clone());
+    for intent in &declared.intents {
+        intents.set_group(intent.group.as_deref());
+        intents.set_intent_threshold(intent.threshold);
+        let slots = intent_slots(intent);
+        if slots.is_empty() {
+            intents.add_intent(intent.name.clone(), intent.examples.clone());
+        } else {
+            intents.add_intent_with_slots(intent.name.clone(), intent.examples.clone(), slots);
+        }
+    }
+    IntentRecognizer::build(intents).expect("Failed to build intent recognizer")
+}
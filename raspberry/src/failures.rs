@@ -0,0 +1,101 @@
+use assistant::analytics::cluster_transcripts;
+use chrono::Local;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config,
+    dirs::{get_config_file, get_config_path},
+    explain::build_recognizer,
+};
+
+/// The file in the config directory with the transcripts that weren't understood.
+const LOG_FILE: &str = "not-understood.log";
+
+/// How many of the biggest clusters are shown.
+const CLUSTERS: usize = 10;
+
+/// How many transcripts of a cluster are shown besides its representative.
+const EXAMPLES: usize = 3;
+
+/// How similar transcripts have to be to end up in the same cluster.
+const SIMILARITY: f32 = 0.75;
+
+/// How far below its threshold the closest intent can be for the transcripts to be suggested as
+/// its examples rather than as a new intent.
+const NEAR_THRESHOLD: f32 = 0.15;
+
+/// Append a transcript that no intent matched to `not-understood.log`, when `log_not_understood`
+/// is set in config.toml.
+pub fn log(config_dir: &Path, transcript: &str) {
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(get_config_file(config_dir, LOG_FILE))
+        .and_then(|mut file| writeln!(file, "{}\t{}", Local::now().to_rfc3339(), transcript));
+    if let Err(e) = result {
+        eprintln!("Failed to log \"{}\": {}", transcript, e);
+    }
+}
+
+/// `raspberry analyze-failures [config_dir]`, grouping the transcripts of `not-understood.log` by
+/// meaning and showing the biggest groups, with whether to add them as examples of an existing
+/// intent of config.toml or as a new intent.
+pub fn analyze_command(mut args: impl Iterator<Item = String>) {
+    let config_dir: PathBuf = args.next().map(Into::into).unwrap_or_else(get_config_path);
+    let declared = config::load(&config_dir).expect("Failed to load config.toml");
+    let log = fs::read_to_string(get_config_file(&config_dir, LOG_FILE)).expect(
+        "Failed to read not-understood.log, set log_not_understood in config.toml to create it",
+    );
+    let transcripts: Vec<String> = log
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .map(|(_, transcript)| transcript.trim().to_string())
+        .filter(|transcript| !transcript.is_empty())
+        .collect();
+    if transcripts.is_empty() {
+        println!("No transcripts in not-understood.log");
+        return;
+    }
+
+    let recognizer = build_recognizer(&config_dir, &declared);
+    let clusters = cluster_transcripts(&recognizer, &transcripts, SIMILARITY)
+        .expect("Failed to embed the transcripts");
+    println!(
+        "{} transcripts in {} clusters, the biggest first:",
+        transcripts.len(),
+        clusters.len()
+    );
+    for cluster in clusters.iter().take(CLUSTERS) {
+        let representative = cluster.representative();
+        println!("\n{} x \"{}\"", cluster.transcripts.len(), representative);
+        let mut shown = vec![representative];
+        for transcript in &cluster.transcripts {
+            if shown.len() > EXAMPLES {
+                break;
+            }
+            if !shown.contains(&transcript.as_str()) {
+                println!("    \"{}\"", transcript);
+                shown.push(transcript);
+            }
+        }
+
+        let explanation = recognizer
+            .explain(representative, 1)
+            .expect("Failed to embed the text");
+        match explanation.candidates.first() {
+            Some(best) if best.score >= best.threshold - NEAR_THRESHOLD => println!(
+                "    Add as examples of \"{}\" (score {:.3}, threshold {:.3})",
+                best.intent, best.score, best.threshold
+            ),
+            Some(best) => println!(
+                "    Add a new intent, the closest is \"{}\" (score {:.3})",
+                best.intent, best.score
+            ),
+            None => println!("    Add a new intent"),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use assistant::{
+    conversation::DialogContext,
+    permissions::Capability,
+    skills::{IntentSpec, Skill, SkillContext},
+    slots::{Slot, SlotKind, SlotValue},
+    sync::SyncKey,
+    AssistantQuery,
+};
+use std::{io, time::SystemTime};
+
+use crate::remote;
+
+const HANDOFF_INTENT: &str = "continue elsewhere";
+
+/// Another assistant, called by where it is, like "bedroom" for "continue in the bedroom".
+#[derive(Clone, Debug, PartialEq)]
+pub struct HandoffPeer {
+    pub name: String,
+    /// The address of its server, like "192.168.1.41:8080".
+    pub address: String,
+}
+
+/// A skill handing the dialog off to another assistant with "continue in the bedroom": the last
+/// query, its response and what its skill left pending go to the `/handoff` endpoint of the other
+/// assistant, which says the response again and listens for the next query. The dialog is sealed
+/// with the key of `[sync]`, which the other assistant has too.
+pub struct HandoffSkill {
+    peers: Vec<HandoffPeer>,
+    key: SyncKey,
+}
+
+impl HandoffSkill {
+    pub fn new(peers: Vec<HandoffPeer>, key: SyncKey) -> Self {
+        Self { peers, key }
+    }
+
+    fn hand_off(&self, dialog: &DialogContext, peer: &HandoffPeer) -> io::Result<()> {
+        let sealed = dialog
+            .seal(&self.key, SystemTime::now())
+            .map_err(|e| io::Error::other(e.to_string()))?;
+        remote::fetch(&peer.address, "POST", "/handoff", None, &sealed).map(|_| ())
+    }
+}
+
+impl Skill for HandoffSkill {
+    fn name(&self) -> &str {
+        "handoff"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak, Capability::Network]
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        let examples = |examples: &[&str]| examples.iter().map(|e| e.to_string()).collect();
+        let names = self.peers.iter().map(|peer| peer.name.clone()).collect();
+        vec![IntentSpec::with_slots(
+            HANDOFF_INTENT,
+            examples(&[
+                "continue in the {peer}",
+                "let's continue in the {peer}",
+                "move this to the {peer}",
+            ]),
+            vec![Slot::new("peer", SlotKind::Entity(names))],
+        )]
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        if query.intent != Some(HANDOFF_INTENT) {
+            return;
+        }
+        let peer = match query.slots.get("peer") {
+            Some(SlotValue::Entity(name)) => self.peers.iter().find(|peer| peer.name == *name),
+            _ => None,
+        };
+        let response = match peer {
+            None => "Where should I continue?".to_string(),
+            Some(_) if ctx.dialog().is_empty() => "There's nothing to continue.".to_string(),
+            Some(peer) => match self.hand_off(ctx.dialog(), peer) {
+                Ok(()) => format!("Continuing in the {}.", peer.name),
+                Err(e) => {
+                    eprintln!("Failed to hand the dialog off to {}: {}", peer.address, e);
+                    format!("I couldn't reach the {}.", peer.name)
+                }
+            },
+        };
+        if let Err(e) = ctx.speak(response) {
+            eprintln!("Failed to speak: {}", e);
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use assistant::{
+    http::HttpClient,
+    hue::{pair, HueError},
+};
+use std::{path::PathBuf, thread, time::Duration};
+
+use crate::{config, dirs::get_config_path};
+
+const USAGE: &str = "Usage: raspberry pair-hue <bridge address> [config_dir]";
+
+/// How long the link button of the bridge is waited for.
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// `raspberry pair-hue <bridge address> [config_dir]`, asking the Hue bridge for a key once its
+/// link button is pressed and writing it to the `[hue]` section of config.toml.
+pub fn pair_command(mut args: impl Iterator<Item = String>) {
+    let address = args.next().expect(USAGE);
+    let config_dir: PathBuf = args.next().map(Into::into).unwrap_or_else(get_config_path);
+
+    println!("Press the link button on the Hue bridge...");
+    let client = HttpClient::default();
+    let mut waited = Duration::ZERO;
+    let key = loop {
+        match pair(&client, &address, "raspberry#assistant") {
+            Ok(key) => break key,
+            Err(HueError::LinkButtonNotPressed) if waited < PAIRING_TIMEOUT => {
+                thread::sleep(Duration::from_secs(1));
+                waited += Duration::from_secs(1);
+            }
+            Err(e) => {
+                eprintln!("Failed to pair with the Hue bridge: {}", e);
+                std::process::exit(1);
+            }
+        }
+    };
+    config::save_hue(&config_dir, &address, &key).expect("Failed to write config.toml");
+    println!("Paired with the Hue bridge, restart the assistant to control the lights");
+}
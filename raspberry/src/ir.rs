@@ -0,0 +1,62 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::dirs::get_config_file;
+
+/// IR codes are stored in the config directory as `ir/<name>.ir` files in the pulse/space text
+/// format understood by `ir-ctl`.
+fn code_path(config_dir: &Path, name: &str) -> PathBuf {
+    get_config_file(config_dir, format!("ir/{}.ir", name))
+}
+
+pub fn has_code(config_dir: &Path, name: &str) -> bool {
+    code_path(config_dir, name).exists()
+}
+
+/// Send a previously learned IR code through the IR transmitter.
+pub fn send(config_dir: &Path, name: &str) -> io::Result<()> {
+    let path = code_path(config_dir, name);
+    run_ir_ctl(Command::new("ir-ctl").arg(format!("--send={}", path.display())))
+}
+
+/// Record a single IR code from the receiver and save it to the config directory.
+pub fn learn(config_dir: &Path, name: &str) -> io::Result<PathBuf> {
+    let path = code_path(config_dir, name);
+    fs::create_dir_all(path.parent().expect("IR code path always has a parent"))?;
+    run_ir_ctl(
+        Command::new("ir-ctl")
+            .arg("--one-shot")
+            .arg(format!("--receive={}", path.display())),
+    )?;
+    Ok(path)
+}
+
+fn run_ir_ctl(command: &mut Command) -> io::Result<()> {
+    let status = command.status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("ir-ctl exited with {}", status)))
+    }
+}
+
+/// `raspberry learn-ir <name> [config_dir]`
+pub fn learn_command(mut args: impl Iterator<Item = String>) {
+    let name = args
+        .next()
+        .expect("Usage: raspberry learn-ir <name> [config_dir]");
+    let config_dir: PathBuf = args
+        .next()
+        .map(Into::into)
+        .unwrap_or_else(crate::dirs::get_config_path);
+
+    println!(
+        "Point the remote at the receiver and press the button for \"{}\"...",
+        name
+    );
+    let path = learn(&config_dir, &name).expect("Failed to record IR code");
+    println!("Saved IR code to {}", path.display());
+}
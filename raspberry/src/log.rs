@@ -0,0 +1,58 @@
+use std::fmt::{self, Write};
+
+use tracing::{
+    field::{Field, Visit},
+    span, Event, Level, Metadata, Subscriber,
+};
+
+/// Prints the warnings of the assistant to stderr and what it is doing to stdout, like the
+/// messages of the binary itself. Finer levels aren't printed.
+struct PrintSubscriber;
+
+/// The message of an event, followed by its other fields.
+#[derive(Default)]
+struct Message(String);
+
+impl Visit for Message {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            _ = write!(self.0, "{:?}", value);
+        } else {
+            _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+impl Subscriber for PrintSubscriber {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        *metadata.level() <= Level::INFO
+    }
+
+    fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(1)
+    }
+
+    fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut message = Message::default();
+        event.record(&mut message);
+        if *event.metadata().level() == Level::INFO {
+            println!("{}", message.0);
+        } else {
+            eprintln!("{}", message.0);
+        }
+    }
+
+    fn enter(&self, _: &span::Id) {}
+
+    fn exit(&self, _: &span::Id) {}
+}
+
+/// Print what the assistant logs, see [PrintSubscriber].
+pub fn init() {
+    tracing::subscriber::set_global_default(PrintSubscriber)
+        .expect("Failed to set the subscriber of the logs");
+}
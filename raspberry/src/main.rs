@@ -1,15 +1,72 @@
 use assistant::{
+    audio::{input_device_names, InputDevice},
+    chores::{self, ChoreSkill},
+    cues::AudioCueSource,
+    dice::DiceSkill,
+    events::{AssistantEvent, StartupProgress},
+    home::{intent_data, query_json, HomeAssistant, MqttPublisher, MqttSubscriber},
     intents::{
         EmbeddingModelFilePaths, EmbeddingModelSource, InitOptionsUserDefined,
         IntentRecognizerError,
     },
-    AssistantConfig, AssistantListenError, AssistantListenSuccessfulWakewordError,
+    language::Language,
+    presence::PresenceSignal,
+    profile::SettingsProfile,
+    remote::{RemoteCommand, RemoteHandle},
+    schedule::ScheduleSkill,
+    skills::IntentSpec,
+    slots::{Slot, SlotKind, SlotValue},
+    sounds::Earcon,
+    speakers::{SpeakerProfiles, VoskSpeakerIdentifier},
+    storage::SqliteStorage,
+    stt::{load_stt_model, STTConfig, VoskRecognizer},
+    tts::{TtsConfig, VoiceSelection},
+    AskOptions, Assistant, AssistantConfig, AssistantListenError,
+    AssistantListenSuccessfulWakewordError, RecognitionFailure, RepromptPolicy, ROOM_SLOT,
 };
 use chrono::Local;
+use config::{Action, Behavior};
 use dirs::{get_config_file, get_config_path};
+use responses::CannedResponse;
+use sonos::SonosAnnouncer;
+use std::{
+    error::Error,
+    path::Path,
+    sync::{mpsc::Receiver, Arc},
+    time::Duration,
+};
 
+mod backup;
+mod bench;
+mod briefing;
+mod cec;
+mod config;
 mod dirs;
+mod doctor;
+mod explain;
+mod failures;
+mod handoff;
+mod hue;
+mod ir;
+mod log;
+mod migrate;
+mod remote;
+mod replay;
+mod responses;
+mod scaffold;
 mod scheduler;
+mod server;
+mod sonos;
+mod tune;
+mod voices;
+mod websocket;
+
+/// The intents controlling devices, which can be disabled by voice
+const SMART_HOME_GROUP: &str = "smart home";
+/// The slot with how long `stop-listening` lasts.
+const DURATION_SLOT: &str = "duration";
+/// How long `stop-listening` lasts without a duration.
+const DEFAULT_PAUSE: Duration = Duration::from_secs(2 * 60 * 60);
 
 macro_rules! speak {
     ($assistant:expr, $content:expr) => {
@@ -17,74 +74,575 @@ macro_rules! speak {
     };
 }
 
-enum Intents {
-    Greeting,
-    Weather,
-    Time,
-    Day,
-    Date,
+/// Tell the user that the assistant can't start, since there may be no screen to print to.
+fn speak_config_error() {
+    let Ok(mut tts) = assistant::tts::get_tts() else {
+        return;
+    };
+    let text = "There is an error in my configuration. Run raspberry check-config to see it.";
+    if tts.speak(text, false).is_ok() {
+        while tts.is_speaking().unwrap_or(false) {
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
+
+/// The model recognizing the intents, from the directory named by `intent_model`.
+fn intent_model(config_dir: &Path, declared: &config::Config) -> EmbeddingModelSource {
+    let model_file = |file: &str| {
+        get_config_file(config_dir, &declared.intent_model)
+            .join(file)
+            .to_str()
+            .expect("Failed to convert PathBuf to &str")
+            .to_string()
+    };
+    let (onnx, tokenizer, model_config, special_tokens_map, tokenizer_config) = (
+        model_file("model.onnx"),
+        model_file("tokenizer.json"),
+        model_file("config.json"),
+        model_file("special_tokens_map.json"),
+        model_file("tokenizer_config.json"),
+    );
+    EmbeddingModelSource::Local(
+        EmbeddingModelFilePaths {
+            onnx: &onnx,
+            tokenizer: &tokenizer,
+            config: &model_config,
+            special_tokens_map: &special_tokens_map,
+            tokenizer_config: &tokenizer_config,
+        }
+        .to_user_defined_embedding_model()
+        .expect("Couldn't find model files for intent recognition"),
+        InitOptionsUserDefined::new(),
+    )
+}
+
+/// The slots of the intent, the room and the duration if its examples mention them.
+fn intent_slots(intent: &config::Intent) -> Vec<Slot> {
+    [
+        (ROOM_SLOT, SlotKind::FreeText),
+        (DURATION_SLOT, SlotKind::Duration),
+    ]
+    .into_iter()
+    .filter(|(name, _)| {
+        let slot = format!("{{{}}}", name);
+        intent
+            .examples
+            .iter()
+            .any(|example| example.contains(&slot))
+    })
+    .map(|(name, kind)| Slot::new(name, kind))
+    .collect()
+}
+
+/// Print the progress of starting the assistant, which can take a while on slow devices.
+fn print_startup_progress(events: Receiver<AssistantEvent>) {
+    for event in events {
+        match event {
+            AssistantEvent::Startup(StartupProgress::IntentsEmbedded { embedded: 0, total }) => {
+                println!("Loaded the model, embedding {} intents...", total)
+            }
+            AssistantEvent::Startup(StartupProgress::IntentsEmbedded { embedded, total }) => {
+                println!("Embedded {}/{} intents", embedded, total)
+            }
+            AssistantEvent::Startup(StartupProgress::AudioReady) => {
+                println!("Started the microphone")
+            }
+            // The receiver is dropped, so the events of queries aren't queued for nobody
+            AssistantEvent::Startup(StartupProgress::Ready) => return,
+            _ => (),
+        }
+    }
 }
 
 fn main() {
+    log::init();
     let mut args_iter = std::env::args();
     _ = args_iter.next();
-    let config_dir: std::path::PathBuf = if let Some(config_dir) = args_iter.next() {
+    let first_arg = args_iter.next();
+    match first_arg.as_deref() {
+        Some("bench-wakeword") => return bench::bench_wakeword(args_iter),
+        Some("bench-stt") => return bench::bench_stt(args_iter),
+        Some("bench-tts") => return bench::bench_tts(args_iter),
+        Some("learn-ir") => return ir::learn_command(args_iter),
+        Some("pair-hue") => return hue::pair_command(args_iter),
+        Some("remote") => return remote::remote_command(args_iter),
+        Some("voices") => return voices::voices_command(args_iter),
+        Some("check-config") => return config::check_command(args_iter),
+        Some("doctor") => return doctor::doctor_command(args_iter),
+        Some("explain") => return explain::explain_command(args_iter),
+        Some("analyze-failures") => return failures::analyze_command(args_iter),
+        Some("replay") => return replay::replay_command(args_iter),
+        Some("backup") => return backup::backup_command(args_iter),
+        Some("restore") => return backup::restore_command(args_iter),
+        Some("new-skill") => return scaffold::new_skill_command(args_iter),
+        Some("list-input-devices") => {
+            for name in input_device_names().expect("Failed to list input devices") {
+                println!("{}", name);
+            }
+            return;
+        }
+        _ => (),
+    }
+    // `raspberry tune [config dir]` runs the assistant while reading tuning commands
+    let tune = first_arg.as_deref() == Some("tune");
+    let first_arg = if tune { args_iter.next() } else { first_arg };
+
+    let config_dir: std::path::PathBuf = if let Some(config_dir) = first_arg {
         config_dir.into()
     } else {
         get_config_path()
     };
 
-    let mut config = AssistantConfig::build(get_config_file(&config_dir, "vosk-model-small-en-us-0.15").to_str().expect("Failed to convert PathBuf to &str"), EmbeddingModelSource::Local(EmbeddingModelFilePaths {
-        onnx: get_config_file(&config_dir, "intents/model.onnx").to_str().expect("Failed to convert PathBuf to &str"),
-        tokenizer: get_config_file(&config_dir, "intents/tokenizer.json").to_str().expect("Failed to convert PathBuf to &str"),
-        config: get_config_file(&config_dir, "intents/config.json").to_str().expect("Failed to convert PathBuf to &str"),
-        special_tokens_map: get_config_file(&config_dir, "intents/special_tokens_map.json").to_str().expect("Failed to convert PathBuf to &str"),
-        tokenizer_config: get_config_file(&config_dir, "intents/tokenizer_config.json").to_str().expect("Failed to convert PathBuf to &str"),
-    }.to_user_defined_embedding_model().expect("Couldn't find model files for intent recognition"), InitOptionsUserDefined::new())).expect("Failed to build assistant config. Please ensure you have all required files setup in the correct location.");
-
-    config
-        .add_wakeword_from_file(
-            "pizza",
-            get_config_file(&config_dir, "pizza.rpw")
-                .to_str()
-                .expect("Failed to convert PathBuf to &str"),
-            true,
+    // The name of the microphone, from `raspberry list-input-devices`
+    let input_device = match std::fs::read_to_string(get_config_file(&config_dir, "input-device")) {
+        Ok(name) => InputDevice::Name(name.trim().to_string()),
+        Err(_) => InputDevice::Default,
+    };
+
+    let declared = match config::load(&config_dir) {
+        Ok(declared) => declared,
+        Err(e) => {
+            eprintln!("Error in config.toml: {}", e);
+            speak_config_error();
+            std::process::exit(1);
+        }
+    };
+
+    let stt_model = get_config_file(&config_dir, &declared.stt_model)
+        .to_str()
+        .expect("Failed to convert PathBuf to &str")
+        .to_string();
+    let build = if declared.background_loading {
+        AssistantConfig::build_in_background
+    } else {
+        AssistantConfig::build_with_input_device
+    };
+    let mut config = build(stt_model, intent_model(&config_dir, &declared), input_device).expect("Failed to build assistant config. Please ensure you have all required files setup in the correct location.");
+
+    if let Some(detector) = &declared.wakeword_detector {
+        config
+            .set_wakeword_detector(&detector.to_detector_settings())
+            .expect("Failed to set up the wakeword detector");
+    }
+    for wakeword in &declared.wakewords {
+        config
+            .add_wakeword_from_file(
+                &wakeword.name,
+                get_config_file(&config_dir, &wakeword.file)
+                    .to_str()
+                    .expect("Failed to convert PathBuf to &str"),
+                wakeword.listen,
+                wakeword.response.as_deref(),
+            )
+            .expect("Failed to add wakeword, are you sure it's valid?");
+        // Models are registered by their path in config.toml
+        let stt_model = wakeword
+            .stt_model
+            .as_deref()
+            .filter(|model| *model != declared.stt_model);
+        config.set_wakeword_speech_recognizer(&wakeword.name, stt_model);
+        config.set_wakeword_context(&wakeword.name, wakeword.context.as_deref());
+    }
+    for declared_language in &declared.languages {
+        let path = get_config_file(&config_dir, &declared_language.stt_model)
+            .to_str()
+            .expect("Failed to convert PathBuf to &str")
+            .to_string();
+        let stt_model = load_stt_model(path).expect("Failed to load the STT model of a language");
+        let mut language = Language::new(VoskRecognizer::new(stt_model, STTConfig::new()));
+        language.set_voice(declared_language.voice.clone().map(VoiceSelection::Name));
+        config.add_language(&declared_language.name, language);
+    }
+    for model in declared.other_stt_models() {
+        let path = get_config_file(&config_dir, model)
+            .to_str()
+            .expect("Failed to convert PathBuf to &str")
+            .to_string();
+        let stt_model = load_stt_model(path).expect("Failed to load the STT model of a wakeword");
+        config.add_speech_recognizer(model, VoskRecognizer::new(stt_model, STTConfig::new()));
+    }
+    for declared_source in &declared.audio_cues {
+        let mut source = AudioCueSource::new(
+            &declared_source.name,
+            InputDevice::Name(declared_source.device.clone()),
         )
-        .expect("Failed to add wakeword, are you sure it's valid?");
-    config.add_intent(
-        Intents::Greeting,
-        vec!["hello".to_string(), "hi".to_string(), "hey".to_string()],
+        .expect("Failed to set up the input device of the audio cues");
+        for cue in &declared_source.cues {
+            source
+                .wakeword_config_mut()
+                .add_wakeword_from_file(
+                    &cue.name,
+                    get_config_file(&config_dir, &cue.file)
+                        .to_str()
+                        .expect("Failed to convert PathBuf to &str"),
+                )
+                .expect("Failed to add audio cue, are you sure it's valid?");
+        }
+        config.add_audio_cue_source(source);
+    }
+    let storage = Arc::new(
+        SqliteStorage::open(get_config_file(&config_dir, "storage.sqlite"))
+            .expect("Failed to open storage"),
     );
-    config.add_intent(
-        Intents::Weather,
-        vec![
-            "what's the weather like today".to_string(),
-            "what's the forecast".to_string(),
-        ],
+    config.set_storage(storage.clone());
+    if let Some(sync) = &declared.sync {
+        config.set_sync(sync.to_sync_config(&config_dir));
+    }
+    config.set_connectivity_monitor(
+        declared
+            .connectivity
+            .as_ref()
+            .map(config::Connectivity::to_connectivity_config)
+            .unwrap_or_default(),
     );
-    config.add_intent(
-        Intents::Time,
-        vec![
-            "what time is it".to_string(),
-            "what's the current time".to_string(),
-        ],
+    if let Some(http) = &declared.http {
+        config.set_http_client(http.to_http_client());
+    }
+    config.set_permissions(declared.to_permissions());
+    if let (Some(presence), Some(declared_mqtt)) = (&declared.presence, &declared.mqtt) {
+        let signal = PresenceSignal::new(Duration::from_secs_f64(presence.seconds));
+        spawn_mqtt_presence(declared_mqtt, presence.topic.clone(), signal.clone());
+        config.set_presence_sensor(signal);
+    }
+    config.set_tts_cache(
+        declared
+            .tts_cache
+            .as_ref()
+            .map(config::TtsCache::to_tts_cache_config),
     );
-    config.add_intent(
-        Intents::Day,
-        vec![
-            "what day is it".to_string(),
-            "what's the current day".to_string(),
-        ],
+    for acknowledgement in &declared.acknowledgements {
+        config.set_acknowledgement(
+            &acknowledgement.name,
+            Some(acknowledgement.to_acknowledgement()),
+        );
+    }
+    config.set_recording(
+        declared
+            .recording
+            .as_ref()
+            .map(|recording| recording.to_recording_config(&config_dir)),
     );
-    config.add_intent(
-        Intents::Date,
-        vec![
-            "what's the date".to_string(),
-            "what's today's date".to_string(),
-        ],
+    config.set_session_archive(
+        declared
+            .recording
+            .as_ref()
+            .and_then(|recording| recording.session_archive(&config_dir)),
+    );
+    if let Some(speakers) = &declared.speakers {
+        let path = |file: &str| {
+            get_config_file(&config_dir, file)
+                .to_str()
+                .expect("Failed to convert PathBuf to &str")
+                .to_string()
+        };
+        let mut identifier = VoskSpeakerIdentifier::new(
+            load_stt_model(path(&declared.stt_model))
+                .expect("Failed to load the STT model for speaker identification"),
+            path(&speakers.model),
+            SpeakerProfiles::new(storage.clone()),
+        )
+        .expect("Failed to load the speaker model");
+        if let Some(threshold) = speakers.threshold {
+            identifier.set_threshold(threshold);
+        }
+        config.set_speaker_identifier(identifier);
+        for (name, preferences) in speakers.preferences() {
+            config.set_speaker_preferences(name, preferences);
+        }
+    }
+    // The name of the TTS voice, e.g. "english-us"
+    if let Ok(voice) = std::fs::read_to_string(get_config_file(&config_dir, "voice")) {
+        let mut tts_config = TtsConfig::new();
+        tts_config.set_voice(VoiceSelection::Name(voice.trim().to_string()));
+        config
+            .set_tts_config(&tts_config)
+            .expect("Failed to set the TTS voice, are you sure it exists?");
+    }
+    // The room the Raspberry Pi is in, e.g. "kitchen"
+    if let Ok(location) = std::fs::read_to_string(get_config_file(&config_dir, "location")) {
+        config.set_location(location.trim());
+    }
+    config.set_reprompt_policy(
+        RecognitionFailure::Failed,
+        declared.reprompt.as_ref().map(|prompt| RepromptPolicy {
+            prompt: prompt.clone(),
+            max_retries: 1,
+        }),
     );
+    let home_assistant = declared.home_assistant.as_ref().map(|declared| {
+        let mut home_assistant = HomeAssistant::new(&declared.url, &declared.token);
+        home_assistant.set_language(declared.language.as_deref());
+        home_assistant.set_client(config.http_client());
+        home_assistant
+    });
+    let mut mqtt = declared.mqtt.as_ref().map(|declared| {
+        let mut mqtt = MqttPublisher::new(&declared.address, &declared.client_id);
+        if let (Some(username), Some(password)) = (&declared.username, &declared.password) {
+            mqtt.set_credentials(username, password);
+        }
+        mqtt
+    });
+    // Sentences no intent matched are given to Home Assistant first, which says what it did
+    let converse = declared
+        .home_assistant
+        .as_ref()
+        .is_some_and(|declared| declared.conversation);
+    if !converse {
+        config.set_not_understood_response(declared.not_understood.as_deref());
+    }
+    config.set_greeting(declared.greeting.as_deref());
+    config.set_farewell(declared.farewell.as_deref());
+    config.set_barge_in(true);
+    config.set_query_alternatives(declared.alternatives);
+    if let Some(guest_mode) = &declared.guest_mode {
+        config.set_guest_mode(guest_mode.to_guest_mode_config());
+    }
+    config.set_scheduling(declared.scheduling.to_scheduling_config());
+    // Earcons are used if their sound file is in the config directory
+    for (earcon, file) in [
+        (Earcon::WakewordDetected, "wakeword.wav"),
+        (Earcon::Error, "error.wav"),
+        (Earcon::ScheduledItemDue, "alarm.wav"),
+        (Earcon::Ready, "ready.wav"),
+        (Earcon::Stopped, "shutdown.wav"),
+    ] {
+        let path = get_config_file(&config_dir, file);
+        if path.exists() {
+            config.set_earcon(earcon, Some(path));
+        }
+    }
+    if let Some(threshold) = declared.threshold {
+        config.set_intent_threshold(threshold);
+    }
+    config.set_intent_scoring(declared.scoring());
+    config.add_negative_examples(declared.negative_examples.clone());
+    for intent in &declared.intents {
+        config.set_intent_group(intent.group.as_deref());
+        config.set_intent_language(intent.language.as_deref());
+        config.set_intent_context(intent.context.as_deref());
+        config.set_threshold_of_next_intents(intent.threshold);
+        config.set_fallback_of_next_intents(
+            intent
+                .fallback()
+                .expect("Checked when loading the configuration"),
+        );
+        let slots = intent_slots(intent);
+        match (intent.responses(), intent.behavior()) {
+            (Some(responses), _) => config.add_skill(CannedResponse::new(
+                IntentSpec::with_slots(&intent.name, intent.examples.clone(), slots),
+                responses,
+            )),
+            (None, Some(behavior)) if slots.is_empty() => {
+                config.add_intent(behavior, intent.examples.clone())
+            }
+            (None, Some(behavior)) => {
+                config.add_intent_with_slots(behavior, intent.examples.clone(), slots)
+            }
+            (None, None) => unreachable!("Checked when loading the configuration"),
+        }
+    }
+    config.set_intent_group(None);
+    config.set_intent_language(None);
+    config.set_intent_context(None);
+    config.set_threshold_of_next_intents(None);
+    config.set_fallback_of_next_intents(None);
+    config.add_skill(ScheduleSkill);
+    config.add_skill(DiceSkill);
+    let chores: Vec<_> = declared
+        .chores
+        .iter()
+        .map(|chore| {
+            chore
+                .to_chore()
+                .expect("Checked when loading the configuration")
+        })
+        .collect();
+    if !chores.is_empty() {
+        config.add_skill(ChoreSkill::new(chores.clone()));
+    }
+    if let Some(birthdays) = &declared.birthdays {
+        config.add_skill(birthdays.to_skill(&config_dir));
+    }
+    if let Some(hue) = &declared.hue {
+        config.add_skill(hue.to_skill(config.http_client()));
+    }
+    let handoff = declared
+        .handoff_skill()
+        .expect("Checked when loading the configuration");
+    if let Some(handoff) = handoff {
+        config.add_skill(handoff);
+    }
+    if let Some(sonos) = &declared.sonos {
+        let skill = sonos
+            .to_skill(config.http_client())
+            .expect("Checked when loading the configuration");
+        config.add_skill(skill);
+    }
+    if let Some(cec) = &declared.cec {
+        let cec = cec
+            .to_skill()
+            .expect("Checked when loading the configuration");
+        if let Err(e) = cec.scan() {
+            eprintln!("Failed to set up HDMI-CEC: {}", e);
+        }
+        config.add_skill(cec);
+    }
+    if let Some(weather) = &declared.weather {
+        config.add_skill(weather.to_skill(config.http_client(), config.http_cache()));
+        if let Some(air_quality) = &declared.air_quality {
+            config.add_skill(air_quality.to_skill(
+                weather,
+                config.http_client(),
+                config.http_cache(),
+            ));
+        }
+    }
+    if let Some(bridge) = &declared.bridge {
+        config.add_skill(
+            bridge
+                .to_skill()
+                .expect("Checked when loading the configuration"),
+        );
+    }
+    if let Some(packages) = &declared.packages {
+        config.add_skill(packages.to_skill(config.http_client()));
+    }
+    if let Some(sports) = &declared.sports {
+        config.add_skill(sports.to_skill(config.http_client(), config.http_cache()));
+    }
+    if let Some(transit) = &declared.transit {
+        config.add_skill(transit.to_skill(config.http_client(), config.http_cache()));
+    }
+    if let Some(knowledge) = &declared.knowledge {
+        config.add_skill(
+            knowledge
+                .to_skill(config.http_client(), config.http_cache())
+                .expect("Checked when loading the configuration"),
+        );
+    }
 
+    for rule in &declared.automation {
+        config.add_rule(
+            rule.to_rule(&declared.intents)
+                .expect("Checked when loading the configuration"),
+        );
+    }
+    // Automation has its own connection, since a client id can only be connected once
+    if let Some(declared_mqtt) = declared
+        .mqtt
+        .as_ref()
+        .filter(|_| !declared.automation.is_empty() || !declared.announcements.is_empty())
+    {
+        let mut mqtt = MqttPublisher::new(
+            &declared_mqtt.address,
+            format!("{}-automation", declared_mqtt.client_id),
+        );
+        if let (Some(username), Some(password)) = (&declared_mqtt.username, &declared_mqtt.password)
+        {
+            mqtt.set_credentials(username, password);
+        }
+        config.set_automation_mqtt(mqtt);
+    }
+
+    let progress = config.events();
+    std::thread::spawn(move || print_startup_progress(progress));
+    if !declared.background_loading {
+        println!("Loading the intent recognition model...");
+    }
     let mut assistant = config.start().expect("Failed to start assistant");
+    let tuner = tune.then(|| tune::Tuner::new(assistant.remote(), config_dir.clone(), &declared));
+    if let Some(tuner) = &tuner {
+        tune::spawn_stdin(tuner.clone());
+    }
+    assistant.set_intent_grammar(declared.grammar);
+    let topics: Vec<String> = declared
+        .automation
+        .iter()
+        .filter_map(|rule| rule.mqtt_topic().map(str::to_string))
+        .collect();
+    if !chores.is_empty() {
+        if let Err(e) =
+            chores::schedule_reminders(&chores, assistant.schedule_mut(), &*storage, Local::now())
+        {
+            eprintln!("Failed to schedule the reminders of the chores: {}", e);
+        }
+    }
+    if !declared.announcements.is_empty() {
+        let announcements = declared
+            .announcements
+            .iter()
+            .map(|announcement| {
+                announcement
+                    .to_announcement()
+                    .expect("Checked when loading the configuration")
+            })
+            .collect();
+        let sonos = declared.sonos.as_ref().and_then(|sonos| {
+            let port = sonos
+                .announcement_port(declared.server.as_ref())
+                .expect("Checked when loading the configuration")?;
+            let zones = sonos
+                .zones(assistant.http_client())
+                .expect("Checked when loading the configuration");
+            Some(SonosAnnouncer::new(zones, port, &mut assistant))
+        });
+        scheduler::spawn(announcements, assistant.remote(), sonos);
+    }
+    if let (Some(air_quality), Some(weather)) = (&declared.air_quality, &declared.weather) {
+        let alert = air_quality.to_alert(weather, assistant.http_client(), assistant.http_cache());
+        if let Some(mut alert) = alert {
+            assistant.remote().spawn_check(
+                Duration::from_secs(air_quality.check_minutes * 60),
+                move || alert.check(Local::now()),
+            );
+        }
+    }
+    if let Some(sports) = &declared.sports {
+        let reminder = sports
+            .to_reminder(assistant.http_client(), assistant.http_cache())
+            .expect("Checked when loading the configuration");
+        if let Some(mut reminder) = reminder {
+            assistant
+                .remote()
+                .spawn_check(Duration::from_secs(15 * 60), move || {
+                    reminder.check(Local::now())
+                });
+        }
+    }
+    if let Some(packages) = &declared.packages {
+        if let Some(mut announcer) = packages.to_announcer(assistant.http_client(), storage.clone())
+        {
+            assistant.remote().spawn_check(
+                Duration::from_secs(packages.check_minutes * 60),
+                move || announcer.check(Local::now()),
+            );
+        }
+    }
+    if let Some(declared_mqtt) = declared.mqtt.as_ref().filter(|_| !topics.is_empty()) {
+        spawn_mqtt_triggers(declared_mqtt, topics, assistant.remote());
+    }
+    if let Some(server) = &declared.server {
+        let responses = declared
+            .intents
+            .iter()
+            .filter_map(|intent| Some((intent.name.clone(), intent.responses()?[0].clone())))
+            .collect();
+        server::spawn(
+            &server.address,
+            server.token.clone(),
+            storage,
+            responses,
+            declared
+                .birthdays
+                .as_ref()
+                .map(|birthdays| birthdays.path(&config_dir)),
+            tuner,
+            &mut assistant,
+        )
+        .expect("Failed to start HTTP server");
+    }
+    let location = assistant.location().map(str::to_string);
 
     println!("Listening for wakewords...");
     loop {
@@ -94,7 +652,24 @@ fn main() {
                 eprintln!("Stream shut down, failed to receive wakeword. Error: {}", e);
                 break;
             }
-            Err(AssistantListenError::ProcessError(_, e)) => {
+            Err(AssistantListenError::Stopped) => {
+                println!("Shutting down.");
+                // Let the shutdown earcon play before exiting
+                std::thread::sleep(Duration::from_secs(1));
+                break;
+            }
+            Err(AssistantListenError::ProcessError(failure, e)) => {
+                if let (true, Some(transcript)) = (declared.log_not_understood, &failure.transcript)
+                {
+                    if matches!(
+                        e,
+                        AssistantListenSuccessfulWakewordError::IntentRecognizerError(
+                            IntentRecognizerError::ScoreTooLow
+                        ) | AssistantListenSuccessfulWakewordError::NotUnderstood(_)
+                    ) {
+                        failures::log(&config_dir, transcript);
+                    }
+                }
                 match e {
                 AssistantListenSuccessfulWakewordError::SpeechRecognitionInitializationError(
                     e_in,
@@ -112,26 +687,316 @@ fn main() {
                     speak!(assistant, "There was a problem with the intent recognizer. Please try again.");
                 }
                 AssistantListenSuccessfulWakewordError::IntentRecognizerError(IntentRecognizerError::ScoreTooLow) => speak!(assistant, "I'm not sure I can do that, sorry."),
+                AssistantListenSuccessfulWakewordError::IntentRecognizerError(IntentRecognizerError::NoEmbeddingModel) => eprintln!("No embedding model to recognize the intent with."),
+                AssistantListenSuccessfulWakewordError::NotUnderstood(text) => {
+                    eprintln!("Didn't understand \"{}\"", text);
+                    for candidate in &failure.candidates {
+                        eprintln!("  closest: \"{}\" (score {:.2})", candidate.example, candidate.score);
+                    }
+                    // Otherwise the response was already spoken by the assistant
+                    if let (true, Some(home_assistant)) = (converse, &home_assistant) {
+                        match home_assistant.converse(&text) {
+                            Ok(answer) => speak!(assistant, answer.unwrap_or_else(|| "Done.".to_string())),
+                            Err(e) => {
+                                eprintln!("Home Assistant failed to handle \"{}\": {:?}", text, e);
+                                if let Some(response) = &declared.not_understood {
+                                    speak!(assistant, response.replace("{text}", &text));
+                                }
+                            }
+                        }
+                    }
+                }
             };
                 continue;
             }
         };
 
+        if let (Some(text), Some(score)) = (&query.text, query.score) {
+            match &query.context {
+                Some(context) => println!("Heard \"{}\" in {} (score {:.2})", text, context, score),
+                None => println!("Heard \"{}\" (score {:.2})", text, score),
+            }
+        }
+
         match query
             .intent
             .expect("Only added wakewords that listen, so should not happen")
+            .clone()
         {
-            Intents::Greeting => speak!(assistant, "Hello! How can I help you today?"),
-            Intents::Weather => speak!(assistant, "I'm sorry, but I can't fetch the weather yet."),
-            Intents::Time => speak!(
+            Behavior::Action(Action::Time) => speak!(
+                assistant,
+                format!(
+                    "It's {}.",
+                    assistant.clock().local_now().format("%I:%M:%S %p")
+                )
+            ),
+            Behavior::Action(Action::Day) => speak!(
+                assistant,
+                format!("It's {}.", assistant.clock().local_now().format("%A"))
+            ),
+            Behavior::Action(Action::Date) => speak!(
+                assistant,
+                format!(
+                    "It's {}.",
+                    assistant.clock().local_now().format("%B %d, %Y")
+                )
+            ),
+            Behavior::Action(Action::AccessibilityOn) => {
+                assistant
+                    .set_profile(SettingsProfile::accessibility())
+                    .expect("Failed to apply settings profile.");
+                speak!(assistant, "Accessibility mode is on.")
+            }
+            Behavior::Action(Action::AccessibilityOff) => {
+                assistant
+                    .set_profile(SettingsProfile::standard())
+                    .expect("Failed to apply settings profile.");
+                speak!(assistant, "Accessibility mode is off.")
+            }
+            Behavior::Action(Action::SmartHomeOn) => {
+                assistant
+                    .enable_intent_group(SMART_HOME_GROUP)
+                    .expect("Failed to save intent groups.");
+                speak!(assistant, "Smart home commands are on.")
+            }
+            Behavior::Action(Action::SmartHomeOff) => {
+                assistant
+                    .disable_intent_group(SMART_HOME_GROUP)
+                    .expect("Failed to save intent groups.");
+                speak!(assistant, "Smart home commands are off.")
+            }
+            Behavior::Action(Action::GuestModeOn) => {
+                let hours = declared
+                    .guest_mode
+                    .as_ref()
+                    .expect("Checked when loading the configuration")
+                    .hours;
+                assistant
+                    .start_guest_mode(Duration::from_secs_f64(hours * 3600.0))
+                    .expect("Failed to save guest mode.");
+            }
+            Behavior::Action(Action::GuestModeOff) => assistant
+                .end_guest_mode()
+                .expect("Failed to save guest mode."),
+            Behavior::Action(Action::LearnVoice) => {
+                if let Err(e) = assistant.enroll_speaker(None) {
+                    eprintln!("Failed to learn a voice: {}", e);
+                    speak!(assistant, "Sorry, I couldn't learn your voice.")
+                }
+            }
+            Behavior::Action(Action::LearnWakeword) => {
+                match learn_wakeword(&mut assistant, &config_dir) {
+                    Ok(name) => speak!(
+                        assistant,
+                        format!("I'll answer to {} after a restart.", name)
+                    ),
+                    Err(e) => {
+                        eprintln!("Failed to learn a wakeword: {}", e);
+                        speak!(assistant, "Sorry, I couldn't learn the wakeword.")
+                    }
+                }
+            }
+            Behavior::Action(Action::StopListening) => {
+                let duration = match query.slots.get(DURATION_SLOT) {
+                    Some(SlotValue::Duration(duration)) => *duration,
+                    _ => DEFAULT_PAUSE,
+                };
+                match assistant.pause_listening_for(duration) {
+                    Ok(until) => {
+                        speak!(
+                            assistant,
+                            format!(
+                                "OK, I'll stop listening until {}. Resume me from the remote to \
+                                 listen earlier.",
+                                until.format("%I:%M %p")
+                            )
+                        )
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to stop listening: {}", e);
+                        speak!(assistant, "Sorry, I couldn't stop listening.")
+                    }
+                }
+            }
+            Behavior::Action(Action::ListVoices) => {
+                let names = assistant
+                    .speaker_profiles()
+                    .expect("Failed to load the speaker profiles.");
+                match names.split_last() {
+                    None => speak!(assistant, "I don't know anyone's voice yet."),
+                    Some((last, [])) => {
+                        speak!(assistant, format!("I know the voice of {}.", last))
+                    }
+                    Some((last, others)) => speak!(
+                        assistant,
+                        format!("I know the voices of {} and {}.", others.join(", "), last)
+                    ),
+                }
+            }
+            Behavior::Action(Action::ForgetVoice) => {
+                match assistant.speaker().map(str::to_string) {
+                    Some(name) => {
+                        assistant
+                            .remove_speaker_profile(&name)
+                            .expect("Failed to remove the speaker profile.");
+                        speak!(assistant, format!("I forgot your voice, {}.", name))
+                    }
+                    None => speak!(assistant, "I don't recognize your voice."),
+                }
+            }
+            // The IR transmitter can only reach devices in the same room
+            Behavior::InfraredCode(_) if query.location != location => speak!(
                 assistant,
-                format!("It's {}.", Local::now().format("%I:%M:%S %p"))
+                format!(
+                    "I can only control devices in the {}.",
+                    location.as_deref().unwrap_or("room I'm in")
+                )
             ),
-            Intents::Day => speak!(assistant, format!("It's {}.", Local::now().format("%A"))),
-            Intents::Date => speak!(
+            Behavior::InfraredCode(name) if !ir::has_code(&config_dir, &name) => speak!(
                 assistant,
-                format!("It's {}.", Local::now().format("%B %d, %Y"))
+                "I haven't learned that remote control button yet."
             ),
+            Behavior::InfraredCode(name) => match ir::send(&config_dir, &name) {
+                Ok(()) => speak!(assistant, "Done."),
+                Err(e) => {
+                    eprintln!("Failed to send IR code {}: {}", name, e);
+                    speak!(assistant, "I couldn't send the remote control signal.")
+                }
+            },
+            Behavior::Mqtt { topic, intent } => {
+                let payload = query_json(&intent, &query).to_string();
+                let mqtt = mqtt
+                    .as_mut()
+                    .expect("Checked when loading the configuration");
+                match mqtt.publish(&topic, payload.as_bytes(), false) {
+                    Ok(()) => speak!(assistant, "Done."),
+                    Err(e) => {
+                        eprintln!("Failed to publish to {}: {:?}", topic, e);
+                        speak!(assistant, "I couldn't reach the smart home.")
+                    }
+                }
+            }
+            Behavior::HomeAssistant { intent, data } => {
+                let mut intent_data = intent_data(&query);
+                for (key, value) in data {
+                    intent_data.insert(key, value.into());
+                }
+                let home_assistant = home_assistant
+                    .as_ref()
+                    .expect("Checked when loading the configuration");
+                match home_assistant.handle_intent(&intent, intent_data) {
+                    Ok(answer) => speak!(assistant, answer.unwrap_or_else(|| "Done.".to_string())),
+                    Err(e) => {
+                        eprintln!("Home Assistant failed to run {}: {:?}", intent, e);
+                        speak!(assistant, "I couldn't reach Home Assistant.")
+                    }
+                }
+            }
+            // Said in the voice of the new language
+            Behavior::SwitchLanguage(language) => match assistant.set_language(language.as_deref())
+            {
+                Ok(()) => speak!(assistant, "OK."),
+                Err(e) => {
+                    eprintln!("Failed to switch the language: {}", e);
+                    speak!(assistant, "Sorry, I couldn't switch the language.")
+                }
+            },
+        }
+    }
+}
+
+/// Ask for the name of a new wakeword, learn it and add it to `config.toml`. Returns its name.
+fn learn_wakeword<T>(
+    assistant: &mut Assistant<T>,
+    config_dir: &Path,
+) -> Result<String, Box<dyn Error>> {
+    let name = assistant.ask(
+        "What should the new wakeword be?",
+        AskOptions::short_answer(),
+    )?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("No wakeword was said".into());
+    }
+    let file = format!("wakewords/{}.rpw", name.replace(' ', "-"));
+    let path = get_config_file(config_dir, &file);
+    std::fs::create_dir_all(path.parent().expect("Wakeword path always has a parent"))?;
+    assistant.train_wakeword(name, path.to_str().ok_or("Invalid wakeword path")?)?;
+    config::add_wakeword(config_dir, name, &file)?;
+    Ok(name.to_string())
+}
+
+/// Follow the person detector publishing to the topic, reconnecting when the connection to the
+/// broker is lost. Nobody is present while it is disconnected.
+fn spawn_mqtt_presence(declared: &config::Mqtt, topic: String, signal: PresenceSignal) {
+    let address = declared.address.clone();
+    let client_id = format!("{}-presence", declared.client_id);
+    let topics = vec![topic];
+    let credentials = declared.username.clone().zip(declared.password.clone());
+    std::thread::spawn(move || loop {
+        let credentials = credentials
+            .as_ref()
+            .map(|(username, password)| (username.as_str(), password.as_str()));
+        match MqttSubscriber::connect(&address, &client_id, credentials, &topics) {
+            Ok(mut subscriber) => loop {
+                match subscriber.next_message() {
+                    Ok((_, payload)) => signal.set_present(is_present_payload(&payload)),
+                    Err(e) => {
+                        eprintln!("Lost the MQTT connection: {:?}", e);
+                        signal.set_present(false);
+                        break;
+                    }
+                }
+            },
+            Err(e) => eprintln!("Failed to subscribe to the presence topic: {:?}", e),
         }
+        std::thread::sleep(Duration::from_secs(10));
+    });
+}
+
+/// Whether a payload of a person detector means someone is there, like "ON", "true", "1" or a
+/// count above zero.
+fn is_present_payload(payload: &[u8]) -> bool {
+    let payload = String::from_utf8_lossy(payload);
+    let payload = payload.trim();
+    match payload.parse::<f64>() {
+        Ok(count) => count > 0.0,
+        Err(_) => ["on", "true", "yes", "detected", "home"]
+            .iter()
+            .any(|value| payload.eq_ignore_ascii_case(value)),
     }
 }
+
+/// Forward the messages of the topics triggering automation rules to the assistant, reconnecting
+/// when the connection to the broker is lost.
+fn spawn_mqtt_triggers(declared: &config::Mqtt, topics: Vec<String>, remote: RemoteHandle) {
+    let address = declared.address.clone();
+    let client_id = format!("{}-triggers", declared.client_id);
+    let credentials = declared.username.clone().zip(declared.password.clone());
+    std::thread::spawn(move || loop {
+        let credentials = credentials
+            .as_ref()
+            .map(|(username, password)| (username.as_str(), password.as_str()));
+        match MqttSubscriber::connect(&address, &client_id, credentials, &topics) {
+            Ok(mut subscriber) => loop {
+                match subscriber.next_message() {
+                    Ok((topic, payload)) => {
+                        // The assistant was stopped
+                        if remote
+                            .send(RemoteCommand::MqttMessage { topic, payload })
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Lost the MQTT connection: {:?}", e);
+                        break;
+                    }
+                }
+            },
+            Err(e) => eprintln!("Failed to subscribe to the MQTT triggers: {:?}", e),
+        }
+        std::thread::sleep(Duration::from_secs(10));
+    });
+}
@@ -0,0 +1,64 @@
+use std::{fs, io, path::Path};
+use toml_edit::DocumentMut;
+
+/// The version of the format of `config.toml` written by this version of the assistant.
+pub const CONFIG_VERSION: i64 = 1;
+
+/// Migrates a config from the version before `MIGRATIONS[i]` to the next one, returning what it
+/// changed to report it.
+type Migration = fn(&mut DocumentMut) -> Vec<String>;
+
+/// By the version they migrate from. Configs without a version are version 0.
+const MIGRATIONS: [Migration; CONFIG_VERSION as usize] = [unversioned];
+
+/// Configs from before the format was versioned only lack the version.
+fn unversioned(_config: &mut DocumentMut) -> Vec<String> {
+    vec!["Added the version of the format".to_string()]
+}
+
+/// Migrate the config to [CONFIG_VERSION] if it is older, keeping its comments and a copy of the
+/// original next to it as `config.toml.v<version>.bak`. Returns what changed, nothing if it was
+/// up to date.
+pub fn migrate(path: &Path, text: &str) -> io::Result<(String, Vec<String>)> {
+    let mut config: DocumentMut = text
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}", e)))?;
+    let version = match config.get("version") {
+        None => 0,
+        Some(version) => version.as_integer().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "`version` has to be a number")
+        })?,
+    };
+    if version > CONFIG_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "config.toml is version {}, but this assistant only understands up to version {}",
+                version, CONFIG_VERSION
+            ),
+        ));
+    }
+    if version == CONFIG_VERSION {
+        return Ok((text.to_string(), Vec::new()));
+    }
+
+    let backup = path.with_extension(format!("toml.v{}.bak", version));
+    fs::copy(path, &backup)?;
+    let mut changes = Vec::new();
+    for migration in &MIGRATIONS[version.max(0) as usize..] {
+        changes.extend(migration(&mut config));
+    }
+    config["version"] = toml_edit::value(CONFIG_VERSION);
+    let migrated = config.to_string();
+    fs::write(path, &migrated)?;
+    changes.insert(
+        0,
+        format!(
+            "Migrated config.toml from version {} to {}, the original is in {}",
+            version,
+            CONFIG_VERSION,
+            backup.display()
+        ),
+    );
+    Ok((migrated, changes))
+}
@@ -0,0 +1,99 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+};
+
+const USAGE: &str = "Usage: raspberry remote <address> <query|speak|mute|unmute|pause|resume|shutdown|state|events|cache|tune> [text]";
+
+/// `raspberry remote <address> <command> [text]`, controlling an assistant through the HTTP
+/// server of another `raspberry`. The token of the server is read from `RASPBERRY_TOKEN`.
+pub fn remote_command(mut args: impl Iterator<Item = String>) {
+    let address = args.next().expect(USAGE);
+    let command = args.next().expect(USAGE);
+    let text = args.collect::<Vec<_>>().join(" ");
+    let token = std::env::var("RASPBERRY_TOKEN").ok();
+
+    let (method, path) = match command.as_str() {
+        "query" | "speak" | "tune" if text.is_empty() => panic!("{}", USAGE),
+        "query" => ("POST", "/query"),
+        "speak" => ("POST", "/speak"),
+        "mute" => ("POST", "/mute"),
+        "unmute" => ("POST", "/unmute"),
+        "pause" => ("POST", "/pause"),
+        "resume" => ("POST", "/resume"),
+        "shutdown" => ("POST", "/shutdown"),
+        "state" => ("GET", "/state"),
+        "events" => ("GET", "/events"),
+        "cache" => ("GET", "/cache"),
+        "tune" => ("POST", "/tune"),
+        _ => panic!("{}", USAGE),
+    };
+    let stream =
+        request(&address, method, path, token.as_deref(), &text).expect("Failed to send request");
+    let mut reader = BufReader::new(stream);
+
+    let mut status_line = String::new();
+    reader
+        .read_line(&mut status_line)
+        .expect("Failed to read response");
+    let mut line = String::new();
+    while reader
+        .read_line(&mut line)
+        .expect("Failed to read response")
+        > 2
+    {
+        line.clear();
+    }
+    if !status_line.contains(" 200 ") {
+        let mut body = String::new();
+        _ = reader.read_to_string(&mut body);
+        eprintln!("{}: {}", status_line.trim(), body);
+        std::process::exit(1);
+    }
+
+    // Events are printed as they arrive, until the server or the user closes the connection
+    for line in reader.lines() {
+        println!("{}", line.expect("Failed to read response"));
+    }
+}
+
+/// Send a request and read the whole response, failing with its status unless it is `200 OK`.
+pub(crate) fn fetch(
+    address: &str,
+    method: &str,
+    path: &str,
+    token: Option<&str>,
+    body: &str,
+) -> io::Result<String> {
+    let mut response = String::new();
+    request(address, method, path, token, body)?.read_to_string(&mut response)?;
+    let (head, body) = response.split_once("\r\n\r\n").unwrap_or((&response, ""));
+    let status = head.lines().next().unwrap_or_default();
+    if !status.contains(" 200 ") {
+        return Err(io::Error::other(status.to_string()));
+    }
+    Ok(body.to_string())
+}
+
+pub(crate) fn request(
+    address: &str,
+    method: &str,
+    path: &str,
+    token: Option<&str>,
+    body: &str,
+) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect(address)?;
+    write!(
+        stream,
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n",
+        method,
+        path,
+        address,
+        body.len()
+    )?;
+    if let Some(token) = token {
+        write!(stream, "Authorization: Bearer {}\r\n", token)?;
+    }
+    write!(stream, "\r\n{}", body)?;
+    Ok(stream)
+}
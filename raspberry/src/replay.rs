@@ -0,0 +1,203 @@
+use assistant::{
+    audio::MemoryAudioSource,
+    clock::ManualClock,
+    session::{read_session, RecordedEvent, SessionEvent},
+    stt::{
+        load_stt_model, RecognitionResult, RecognitionStream, RecognitionUpdate, STTConfig,
+        STTSentenceRecognizer, VoskRecognizer,
+    },
+    wakeword::WakewordConfig,
+};
+use chrono::Local;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    config,
+    dirs::{get_config_file, get_config_path},
+    explain::build_recognizer,
+};
+
+const USAGE: &str = "Usage: raspberry replay <archive> [config_dir]";
+
+/// How many frames are given to the pipeline at once, like a block of the microphone.
+const BLOCK_FRAMES: usize = 1024;
+
+/// How long the wakeword thread has to process a block before the replay gives up.
+const BLOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// `raspberry replay <archive> [config_dir]`, running the audio of a session archive, written
+/// when `session` is set in the `[recording]` of config.toml, through the wakewords, speech
+/// recognition and intents of the current config.toml, and comparing what they make of it with
+/// what the assistant did when it was recorded. Skills, contexts and languages aren't included.
+pub fn replay_command(mut args: impl Iterator<Item = String>) {
+    let archive = args.next().expect(USAGE);
+    let config_dir: PathBuf = args.next().map(Into::into).unwrap_or_else(get_config_path);
+    let declared = config::load(&config_dir).expect("Failed to load config.toml");
+    let session = read_session(&archive).expect("Failed to read the session archive");
+    let path = |file: &str| {
+        get_config_file(&config_dir, file)
+            .to_str()
+            .expect("Failed to convert PathBuf to &str")
+            .to_string()
+    };
+
+    let source = MemoryAudioSource::new(session.format);
+    let mut wakeword = WakewordConfig::build(session.format).expect("Failed to set up wakewords");
+    if let Some(detector) = &declared.wakeword_detector {
+        wakeword
+            .set_detector_settings(&detector.to_detector_settings())
+            .expect("Failed to set up the wakeword detector");
+    }
+    for declared_wakeword in &declared.wakewords {
+        wakeword
+            .add_wakeword_from_file(&declared_wakeword.name, &path(&declared_wakeword.file))
+            .expect("Failed to add wakeword, are you sure it's valid?");
+    }
+    let listener = wakeword.start(&source).expect("Failed to start wakewords");
+
+    // Models are loaded once, by their path in config.toml like in the assistant
+    let mut models = HashMap::new();
+    for model in std::iter::once(declared.stt_model.as_str()).chain(declared.other_stt_models()) {
+        let stt_model = load_stt_model(path(model)).expect("Failed to load STT model");
+        models.insert(model, VoskRecognizer::new(stt_model, STTConfig::new()));
+    }
+    let intents = build_recognizer(&config_dir, &declared);
+
+    let clock = Arc::new(ManualClock::new(Local::now()));
+    let channels = usize::from(session.format.channels.max(1));
+    let block_duration =
+        Duration::from_secs_f64(BLOCK_FRAMES as f64 / f64::from(session.format.sample_rate.max(1)));
+    let mut replayed = Vec::new();
+    let mut stream: Option<RecognitionStream> = None;
+    let mut frames = 0;
+    for (pushed, block) in session.samples.chunks(BLOCK_FRAMES * channels).enumerate() {
+        clock.advance(block_duration);
+        source.push(block);
+        frames += (block.len() / channels) as u64;
+        let mut event = |frame, event| replayed.push(RecordedEvent { frame, event });
+
+        let started = Instant::now();
+        while listener.power_stats().blocks <= pushed as u64 {
+            assert!(
+                started.elapsed() < BLOCK_TIMEOUT,
+                "The wakeword engine stopped processing audio"
+            );
+            thread::yield_now();
+        }
+        for detection in listener.try_iter() {
+            // The assistant doesn't listen for wakewords during a query
+            if stream.is_some() {
+                continue;
+            }
+            let declared_wakeword = declared
+                .wakewords
+                .iter()
+                .find(|declared_wakeword| declared_wakeword.name == detection.name);
+            // Placed where the wakeword was, like in archives
+            event(
+                detection
+                    .timestamp
+                    .map_or(frames, |timestamp| timestamp.frame),
+                SessionEvent::WakewordDetected {
+                    name: detection.name.clone(),
+                    score: detection.score,
+                },
+            );
+            let Some(declared_wakeword) = declared_wakeword.filter(|wakeword| wakeword.listen)
+            else {
+                continue;
+            };
+            let model = declared_wakeword
+                .stt_model
+                .as_deref()
+                .unwrap_or(&declared.stt_model);
+            let mut stt = STTSentenceRecognizer::new(&models[model], &source);
+            stt.set_clock(clock.clone());
+            match stt.recognize_streaming() {
+                Ok(started) => stream = Some(started),
+                Err(e) => event(frames, SessionEvent::Error(e.to_string())),
+            }
+        }
+
+        // Partial transcripts aren't kept in archives
+        let Some(result) =
+            std::iter::from_fn(|| stream.as_mut()?.try_next()).find_map(|update| match update {
+                RecognitionUpdate::Done(result) => Some(result),
+                RecognitionUpdate::Partial(_) => None,
+            })
+        else {
+            continue;
+        };
+        stream = None;
+        let RecognitionResult::Final(sentence) = result else {
+            event(frames, SessionEvent::RecognitionFinished(None));
+            continue;
+        };
+        event(
+            frames,
+            SessionEvent::RecognitionFinished(Some(sentence.text.clone())),
+        );
+        match intents.explain(&sentence.text, 1) {
+            Ok(explanation) if explanation.recognized => event(
+                frames,
+                SessionEvent::IntentMatched {
+                    text: sentence.text,
+                    score: explanation.candidates[0].score,
+                },
+            ),
+            Ok(_) => event(
+                frames,
+                SessionEvent::Error(format!("Not understood: {}", sentence.text)),
+            ),
+            Err(e) => event(frames, SessionEvent::Error(e.to_string())),
+        }
+    }
+
+    let seconds =
+        |event: &RecordedEvent| event.frame as f64 / f64::from(session.format.sample_rate);
+    println!(
+        "Replayed {:.1} s of audio",
+        frames as f64 / f64::from(session.format.sample_rate.max(1))
+    );
+    println!("\nRecorded:");
+    for event in &session.events {
+        println!("  {:>8.2} s  {}", seconds(event), event.event);
+    }
+    println!("\nReplayed:");
+    for event in &replayed {
+        println!("  {:>8.2} s  {}", seconds(event), event.event);
+    }
+    if outcomes(&session.events) == outcomes(&replayed) {
+        println!("\nSame wakewords, transcripts and intents as when it was recorded");
+    } else {
+        println!("\nThe replay differs from the recording");
+    }
+}
+
+/// The events of a timeline without their scores and errors, to compare what was heard and
+/// understood.
+fn outcomes(events: &[RecordedEvent]) -> Vec<SessionEvent> {
+    events
+        .iter()
+        .filter_map(|recorded| match &recorded.event {
+            SessionEvent::WakewordDetected { name, .. } => Some(SessionEvent::WakewordDetected {
+                name: name.clone(),
+                score: 0.,
+            }),
+            SessionEvent::IntentMatched { text, .. } => Some(SessionEvent::IntentMatched {
+                text: text.clone(),
+                score: 0.,
+            }),
+            SessionEvent::RecognitionFinished(text) => {
+                Some(SessionEvent::RecognitionFinished(text.clone()))
+            }
+            SessionEvent::Error(_) => None,
+        })
+        .collect()
+}
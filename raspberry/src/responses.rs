@@ -0,0 +1,39 @@
+use assistant::{
+    skills::{IntentSpec, Skill, SkillContext},
+    AssistantQuery,
+};
+
+/// A skill with a single intent that says one of the variants of its response, picked with the
+/// randomness of the assistant.
+pub struct CannedResponse {
+    intent: IntentSpec,
+    responses: Vec<String>,
+}
+
+impl CannedResponse {
+    pub fn new(intent: IntentSpec, responses: &[String]) -> Self {
+        Self {
+            intent,
+            responses: responses.to_vec(),
+        }
+    }
+}
+
+impl Skill for CannedResponse {
+    fn name(&self) -> &str {
+        "responses"
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        vec![self.intent.clone()]
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, _query: &AssistantQuery<'_, str>) {
+        let Some(response) = ctx.random().choose(&self.responses).cloned() else {
+            return;
+        };
+        if let Err(e) = ctx.speak(response) {
+            eprintln!("Failed to speak: {}", e);
+        }
+    }
+}
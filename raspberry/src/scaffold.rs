@@ -0,0 +1,217 @@
+use std::{fs, path::PathBuf};
+
+const USAGE: &str = "Usage: raspberry new-skill <name> [directory]";
+
+const CARGO_TOML: &str = r#"[package]
+name = "{crate}"
+version = "0.1.0"
+edition = "2021"
+
+[dependencies]
+# The assistant crate of the raspberry repository, as a path or a git dependency
+assistant = { path = "../assistant" }
+"#;
+
+const INTENTS: &str = r#"# The intents of the skill. A line in brackets starts an intent, the lines after it are its
+# example sentences. Slots like {day} can be added in src/lib.rs.
+[{name} hello]
+say hello
+greet me
+
+[{name} status]
+how is {name}
+what's the status of {name}
+"#;
+
+const LIB_RS: &str = r#"use assistant::{
+    permissions::Capability,
+    skills::{IntentSpec, Skill, SkillContext},
+    AssistantQuery,
+};
+
+/// The intents and their examples.
+const INTENTS: &str = include_str!("../intents.txt");
+
+/// The configuration of the skill, e.g. from a `[{name}]` section of config.toml.
+#[derive(Clone, Debug)]
+pub struct {type}Config {
+    pub greeting: String,
+}
+
+impl Default for {type}Config {
+    fn default() -> Self {
+        Self {
+            greeting: "Hello from {name}!".to_string(),
+        }
+    }
+}
+
+/// Registered with `AssistantConfig::add_skill`.
+pub struct {type}Skill {
+    config: {type}Config,
+}
+
+impl {type}Skill {
+    pub fn new(config: {type}Config) -> Self {
+        Self { config }
+    }
+
+    /// What to say for a query, kept apart from `handle` so it can be tested without speaking.
+    pub fn answer(&self, query: &AssistantQuery<'_, str>) -> Option<String> {
+        match query.intent? {
+            "{name} hello" => Some(self.config.greeting.clone()),
+            "{name} status" => Some("Everything is fine.".to_string()),
+            _ => None,
+        }
+    }
+}
+
+impl Skill for {type}Skill {
+    fn name(&self) -> &str {
+        "{name}"
+    }
+
+    fn capabilities(&self) -> Vec<Capability> {
+        vec![Capability::Speak]
+    }
+
+    fn intents(&self) -> Vec<IntentSpec> {
+        parse_intents(INTENTS)
+    }
+
+    fn handle(&mut self, ctx: &mut SkillContext, query: &AssistantQuery<'_, str>) {
+        if let Some(answer) = self.answer(query) {
+            if let Err(e) = ctx.speak(answer) {
+                eprintln!("Failed to speak: {}", e);
+            }
+        }
+    }
+}
+
+/// The intents of a file with `[intent]` lines followed by their examples.
+fn parse_intents(text: &str) -> Vec<IntentSpec> {
+    let mut intents: Vec<IntentSpec> = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+            Some(name) => intents.push(IntentSpec::new(name, Vec::new())),
+            None => {
+                if let Some(intent) = intents.last_mut() {
+                    intent.examples.push(line.to_string());
+                }
+            }
+        }
+    }
+    intents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assistant::slots::SlotValues;
+
+    fn query(intent: &str) -> AssistantQuery<'_, str> {
+        AssistantQuery {
+            wakeword: String::new(),
+            intent: Some(intent),
+            text: None,
+            score: None,
+            slots: SlotValues::new(),
+            location: None,
+            context: None,
+            wakeword_at: None,
+            speech_at: None,
+        }
+    }
+
+    #[test]
+    fn intents_have_examples() {
+        let intents = {type}Skill::new({type}Config::default()).intents();
+        assert_eq!(intents.len(), 2);
+        assert!(intents.iter().all(|intent| !intent.examples.is_empty()));
+    }
+
+    #[test]
+    fn answers_every_intent() {
+        let skill = {type}Skill::new({type}Config::default());
+        for intent in skill.intents() {
+            assert!(skill.answer(&query(&intent.name)).is_some(), "{}", intent.name);
+        }
+    }
+}
+"#;
+
+const README: &str = r#"# {crate}
+
+A skill for the raspberry assistant. The intents and their examples are in `intents.txt`, and
+what the skill says in `src/lib.rs`.
+
+Register it in `raspberry/src/main.rs` after adding `{crate}` to its dependencies:
+
+```rust
+config.add_skill({module}::{type}Skill::new({module}::{type}Config::default()));
+```
+
+Capabilities can be denied to it by its name in config.toml:
+
+```toml
+[permissions]
+{name} = ["network"]
+```
+
+`cargo test` checks that every intent has examples and an answer.
+"#;
+
+/// `raspberry new-skill <name> [directory]`, creating a crate with a skill for the assistant in
+/// `<directory>/<name>`, the current directory by default.
+pub fn new_skill_command(mut args: impl Iterator<Item = String>) {
+    let name = args.next().expect(USAGE);
+    let directory: PathBuf = args.next().unwrap_or_else(|| ".".to_string()).into();
+    assert!(
+        name.starts_with(|c: char| c.is_ascii_lowercase())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'),
+        "The name of a skill starts with a lowercase letter and can only have lowercase letters, \
+         digits and dashes"
+    );
+
+    let path = directory.join(&name);
+    assert!(!path.exists(), "{} already exists", path.display());
+    let module = name.replace('-', "_");
+    let type_name: String = name
+        .split('-')
+        .flat_map(|word| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase())
+                .into_iter()
+                .chain(chars)
+        })
+        .collect();
+    let fill = |template: &str| {
+        template
+            .replace("{crate}", &name)
+            .replace("{module}", &module)
+            .replace("{type}", &type_name)
+            .replace("{name}", &name)
+    };
+
+    fs::create_dir_all(path.join("src")).expect("Failed to create the skill directory");
+    for (file, template) in [
+        ("Cargo.toml", CARGO_TOML),
+        ("intents.txt", INTENTS),
+        ("src/lib.rs", LIB_RS),
+        ("README.md", README),
+    ] {
+        fs::write(path.join(file), fill(template)).expect("Failed to write the skill");
+    }
+    println!(
+        "Created the skill {} in {}. See its README.md to register it.",
+        name,
+        path.display()
+    );
+}
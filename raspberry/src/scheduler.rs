@@ -0,0 +1,84 @@
+use assistant::{
+    automation::Action,
+    remote::{RemoteCommand, RemoteHandle},
+};
+use chrono::{DateTime, Datelike, Local, NaiveTime, Timelike, Weekday};
+use std::{thread, time::Duration};
+
+use crate::sonos::SonosAnnouncer;
+
+/// Actions run at a time of the day, like "weekdays at 7:30 say the date and ask for the weather",
+/// from the `[[announcements]]` of `config.toml`.
+#[derive(Clone, Debug)]
+pub struct Announcement {
+    pub name: String,
+    pub time: NaiveTime,
+    /// Every day if empty
+    pub days: Vec<Weekday>,
+    pub actions: Vec<Action>,
+}
+
+impl Announcement {
+    fn is_due(&self, now: DateTime<Local>) -> bool {
+        (now.hour(), now.minute()) == (self.time.hour(), self.time.minute())
+            && (self.days.is_empty() || self.days.contains(&now.weekday()))
+    }
+
+    /// The actions, with `{date}` and `{time}` in what is said replaced by the current ones.
+    fn actions_at(&self, now: DateTime<Local>) -> Vec<Action> {
+        let date = now.format("%A, %B %d").to_string();
+        let time = now.format("%I:%M %p").to_string();
+        self.actions
+            .iter()
+            .map(|action| match action {
+                Action::Speak(text) => {
+                    Action::Speak(text.replace("{date}", &date).replace("{time}", &time))
+                }
+                action => action.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Send the announcements to the assistant when they are due, at the start of their minute. The
+/// assistant runs them once it is waiting for a wakeword and quiet, so they never talk over a
+/// query, see [RemoteCommand::Announce]. What they say is said in the Sonos zones too, right away.
+pub fn spawn(
+    announcements: Vec<Announcement>,
+    remote: RemoteHandle,
+    sonos: Option<SonosAnnouncer>,
+) {
+    thread::spawn(move || {
+        let mut last_minute = None;
+        loop {
+            let now = Local::now();
+            let minute = (now.date_naive(), now.hour(), now.minute());
+            if last_minute != Some(minute) {
+                last_minute = Some(minute);
+                for announcement in announcements.iter().filter(|a| a.is_due(now)) {
+                    println!("Announcing \"{}\"", announcement.name);
+                    let actions = announcement.actions_at(now);
+                    if let Some(sonos) = &sonos {
+                        let text: Vec<&str> = actions
+                            .iter()
+                            .filter_map(|action| match action {
+                                Action::Speak(text) => Some(text.as_str()),
+                                _ => None,
+                            })
+                            .collect();
+                        if !text.is_empty() {
+                            sonos.announce(&text.join(" "));
+                        }
+                    }
+                    let command = RemoteCommand::Announce(actions);
+                    if remote.send(command).is_err() {
+                        // The assistant was stopped
+                        return;
+                    }
+                }
+            }
+            let into_minute = Duration::new(now.second().into(), now.nanosecond() % 1_000_000_000);
+            thread::sleep(Duration::from_secs(60).saturating_sub(into_minute));
+        }
+    });
+}
@@ -0,0 +1,498 @@
+use assistant::{
+    birthdays::load_dates,
+    events::AssistantEvent,
+    http_cache::HttpCache,
+    metrics::Metrics,
+    packages::{add_package, remove_package, stored_packages, Package},
+    remote::{RemoteCommand, RemoteHandle},
+    schedule::Schedule,
+    storage::Storage,
+    Assistant,
+};
+use chrono::Local;
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    briefing::{briefing_text, render_wav},
+    tune::Tuner,
+    websocket,
+};
+
+/// Longer request bodies are cut off.
+const MAX_BODY_LENGTH: usize = 64 * 1024;
+
+/// The state shared by the connections.
+struct Server {
+    storage: Arc<dyn Storage>,
+    responses: HashMap<String, String>,
+    // The dates of the birthdays said in the briefing
+    birthdays: Option<PathBuf>,
+    token: Option<String>,
+    remote: RemoteHandle,
+    // Set with `raspberry tune`
+    tuner: Option<Tuner>,
+    http_cache: HttpCache,
+    metrics: Metrics,
+    status: Mutex<Status>,
+    // The connections following the events
+    event_streams: Mutex<Vec<Sender<String>>>,
+}
+
+/// What the assistant is doing, kept up to date by its events.
+struct Status {
+    muted: bool,
+    listening: bool,
+    do_not_disturb: bool,
+    guest_mode: bool,
+}
+
+impl Status {
+    fn update(&mut self, event: &AssistantEvent) {
+        match event {
+            AssistantEvent::MutedChanged(muted) => self.muted = *muted,
+            AssistantEvent::ListeningChanged(listening) => self.listening = *listening,
+            AssistantEvent::DoNotDisturbChanged(on) => self.do_not_disturb = *on,
+            AssistantEvent::GuestModeChanged(on) => self.guest_mode = *on,
+            _ => (),
+        }
+    }
+}
+
+/// Serve the assistant over HTTP, so other devices can play its content and control it. Content:
+/// - `GET /briefing.wav`: the daily briefing, with the birthdays of the day
+/// - `GET /responses/<intent>.wav`: the response of an intent of `config.toml`, its first variant
+/// - `GET /speech.wav?text=<text>`: the text said, e.g. for announcements on Sonos, see
+///   [speech_path]
+/// - `POST /handoff`: go on with the dialog of another assistant, sealed with the key of `[sync]`
+///   instead of the token, see [crate::handoff::HandoffSkill]
+/// - `GET /schedule.ics`: the timers, alarms and reminders, for calendars
+/// - `GET /metrics`: the latencies of the queries and of the TTS, in the Prometheus text format
+///
+/// Control, which needs the token as `Authorization: Bearer <token>` or `?token=<token>` if one is
+/// configured, see `raspberry remote`:
+/// - `POST /query`: match the text in the body as if it was said
+/// - `POST /speak`: say the text in the body
+/// - `POST /mute` and `POST /unmute`
+/// - `POST /pause` and `POST /resume`: turn the microphone off and on
+/// - `POST /shutdown`: stop the assistant
+/// - `GET /state`: whether the assistant is muted, listening, in do not disturb and guest mode
+/// - `GET /events`: the events of the assistant, one per line, until the connection is closed. A
+///   WebSocket with one event per message if the request is a WebSocket handshake
+/// - `GET /cache`: the hits and misses of the HTTP cache of the skills and what is in it
+/// - `POST /tune`: run the tuning command in the body, like `threshold 0.6`, and answer with the
+///   values. Only while the assistant was started with `raspberry tune`
+/// - `POST /packages`: track the package of the form in the body, with its `number` and
+///   optionally its `carrier` and `name`, like `number=1Z999AA10123456784&name=headphones`
+/// - `GET /packages`: the packages tracked that way, and `DELETE /packages/<number>` to stop
+pub fn spawn<T>(
+    address: &str,
+    token: Option<String>,
+    storage: Arc<dyn Storage>,
+    responses: HashMap<String, String>,
+    birthdays: Option<PathBuf>,
+    tuner: Option<Tuner>,
+    assistant: &mut Assistant<T>,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    println!("Serving on http://{}", listener.local_addr()?);
+    let events = assistant.events();
+    let server = Arc::new(Server {
+        storage,
+        responses,
+        birthdays,
+        token,
+        remote: assistant.remote(),
+        tuner,
+        http_cache: assistant.http_cache(),
+        metrics: assistant.metrics_handle(),
+        status: Mutex::new(Status {
+            muted: assistant.is_muted(),
+            listening: !assistant.is_listening_paused(),
+            do_not_disturb: assistant.is_do_not_disturb(),
+            guest_mode: assistant.is_guest_mode(),
+        }),
+        event_streams: Mutex::new(Vec::new()),
+    });
+
+    let events_server = server.clone();
+    thread::spawn(move || {
+        for event in events {
+            events_server.status.lock().unwrap().update(&event);
+            let line = format!("{:?}\n", event);
+            events_server
+                .event_streams
+                .lock()
+                .unwrap()
+                .retain(|tx| tx.send(line.clone()).is_ok());
+        }
+    });
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let server = server.clone();
+            // Event streams stay open, so every connection gets its own thread
+            thread::spawn(move || {
+                if let Err(e) = stream.and_then(|stream| server.handle(stream)) {
+                    eprintln!("Failed to handle HTTP request: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+impl Server {
+    fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+        let mut reader = BufReader::new(&stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        let mut content_length = 0;
+        let mut authorization = None;
+        let mut websocket_key = None;
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            if let Some((name, value)) = line.split_once(':') {
+                let value = value.trim();
+                match name.to_ascii_lowercase().as_str() {
+                    "content-length" => content_length = value.parse().unwrap_or(0),
+                    "authorization" => {
+                        authorization = value.strip_prefix("Bearer ").map(str::to_string)
+                    }
+                    "sec-websocket-key" => websocket_key = Some(value.to_string()),
+                    _ => (),
+                }
+            }
+            line.clear();
+        }
+        let mut body = vec![0; content_length.min(MAX_BODY_LENGTH)];
+        reader.read_exact(&mut body)?;
+        let body = String::from_utf8_lossy(&body).into_owned();
+
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return respond(&mut stream, "400 Bad Request", "text/plain", b"Bad request");
+        };
+        // Browsers can't set headers on WebSockets, so the token can be in the query instead
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        if let Some(token) = query
+            .split('&')
+            .find_map(|parameter| parameter.strip_prefix("token="))
+        {
+            authorization.get_or_insert_with(|| percent_decode(token));
+        }
+
+        // Answered by the tuner instead of the assistant
+        let mut tune_command = None;
+        let command = match (method, path) {
+            ("POST", "/query") => Some(RemoteCommand::Query(body.clone())),
+            ("POST", "/speak") => Some(RemoteCommand::Speak(body.clone())),
+            ("POST", "/mute") => Some(RemoteCommand::SetMuted(true)),
+            ("POST", "/unmute") => Some(RemoteCommand::SetMuted(false)),
+            ("POST", "/pause") => Some(RemoteCommand::SetListening(false)),
+            ("POST", "/resume") => Some(RemoteCommand::SetListening(true)),
+            ("POST", "/shutdown") => Some(RemoteCommand::Shutdown),
+            ("POST", "/handoff") => Some(RemoteCommand::ResumeDialog(body.clone())),
+            ("POST", "/tune") => {
+                tune_command = Some(body.clone());
+                None
+            }
+            _ => None,
+        };
+        // Handed off dialogs are sealed with the sync key instead of the token
+        let is_control = (command.is_some() && path != "/handoff")
+            || tune_command.is_some()
+            || matches!(path, "/events" | "/cache" | "/state")
+            || path == "/packages"
+            || path.starts_with("/packages/");
+        if is_control && self.token.is_some() && authorization != self.token {
+            return respond(
+                &mut stream,
+                "401 Unauthorized",
+                "text/plain",
+                b"Wrong token",
+            );
+        }
+        if let Some(command) = command {
+            return match self.remote.send(command) {
+                Ok(()) => respond(&mut stream, "200 OK", "text/plain", b"OK"),
+                Err(_) => respond(
+                    &mut stream,
+                    "503 Service Unavailable",
+                    "text/plain",
+                    b"The assistant was stopped",
+                ),
+            };
+        }
+
+        if let Some(line) = tune_command {
+            return match &self.tuner {
+                Some(tuner) => respond(
+                    &mut stream,
+                    "200 OK",
+                    "text/plain",
+                    tuner.run(&line).as_bytes(),
+                ),
+                None => respond(
+                    &mut stream,
+                    "404 Not Found",
+                    "text/plain",
+                    b"Not tuning, start the assistant with raspberry tune",
+                ),
+            };
+        }
+
+        if path == "/packages" || path.starts_with("/packages/") {
+            return self.update_packages(&mut stream, method, path, &body);
+        }
+
+        if method != "GET" {
+            return respond(
+                &mut stream,
+                "405 Method Not Allowed",
+                "text/plain",
+                b"Method not allowed",
+            );
+        }
+        match path {
+            "/events" => match websocket_key {
+                Some(key) => self.stream_events_websocket(stream, &key),
+                None => self.stream_events(stream),
+            },
+            "/state" => {
+                let status = self.status.lock().unwrap();
+                let text = format!(
+                    "muted: {}\nlistening: {}\ndo not disturb: {}\nguest mode: {}\n",
+                    status.muted, status.listening, status.do_not_disturb, status.guest_mode
+                );
+                respond(&mut stream, "200 OK", "text/plain", text.as_bytes())
+            }
+            "/cache" => {
+                let stats = self.http_cache.stats();
+                let mut text = format!("{} hits, {} misses\n", stats.hits, stats.misses);
+                for entry in stats.entries {
+                    text += &format!(
+                        "{} (fetched {}s ago, kept {}s)\n",
+                        entry.key,
+                        entry.age.as_secs(),
+                        entry.ttl.as_secs()
+                    );
+                }
+                respond(&mut stream, "200 OK", "text/plain", text.as_bytes())
+            }
+            "/schedule.ics" => {
+                let schedule = Schedule::load(Some(self.storage.clone()))
+                    .map_err(|e| io::Error::other(e.to_string()))?;
+                let ics = schedule.to_ics(Local::now());
+                respond(&mut stream, "200 OK", "text/calendar", ics.as_bytes())
+            }
+            "/metrics" => {
+                let text = self.metrics.snapshot().to_prometheus();
+                respond(
+                    &mut stream,
+                    "200 OK",
+                    "text/plain; version=0.0.4",
+                    text.as_bytes(),
+                )
+            }
+            path => self.serve_audio(stream, path, query),
+        }
+    }
+
+    /// See [assistant::packages::add_package].
+    fn update_packages(
+        &self,
+        stream: &mut TcpStream,
+        method: &str,
+        path: &str,
+        body: &str,
+    ) -> io::Result<()> {
+        let storage = self.storage.as_ref();
+        let result = match (method, path.strip_prefix("/packages/")) {
+            ("GET", None) => {
+                return match stored_packages(storage) {
+                    Ok(packages) => {
+                        let text: String = packages
+                            .iter()
+                            .map(|package| {
+                                format!(
+                                    "{} {} {}\n",
+                                    package.number,
+                                    package.carrier.as_deref().unwrap_or("-"),
+                                    package.name.as_deref().unwrap_or_default()
+                                )
+                            })
+                            .collect();
+                        respond(stream, "200 OK", "text/plain", text.as_bytes())
+                    }
+                    Err(e) => Err(io::Error::other(e.to_string())),
+                };
+            }
+            ("POST", None) => {
+                let field = |name: &str| {
+                    body.trim()
+                        .split('&')
+                        .filter_map(|field| field.split_once('='))
+                        .find(|(key, _)| *key == name)
+                        .map(|(_, value)| percent_decode(&value.replace('+', " ")))
+                        .filter(|value| !value.is_empty())
+                };
+                let Some(number) = field("number") else {
+                    return respond(
+                        stream,
+                        "400 Bad Request",
+                        "text/plain",
+                        b"The package needs a number",
+                    );
+                };
+                let package = Package {
+                    number,
+                    carrier: field("carrier"),
+                    name: field("name"),
+                };
+                add_package(storage, &package)
+            }
+            ("DELETE", Some(number)) => remove_package(storage, &percent_decode(number)),
+            _ => {
+                return respond(
+                    stream,
+                    "405 Method Not Allowed",
+                    "text/plain",
+                    b"Method not allowed",
+                )
+            }
+        };
+        match result {
+            Ok(()) => respond(stream, "200 OK", "text/plain", b"OK"),
+            Err(e) => Err(io::Error::other(e.to_string())),
+        }
+    }
+
+    fn stream_events(&self, mut stream: TcpStream) -> io::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        self.event_streams.lock().unwrap().push(tx);
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nConnection: close\r\n\r\n"
+        )?;
+        // Ends when the client disconnects and writing fails
+        for line in rx {
+            stream.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn stream_events_websocket(&self, mut stream: TcpStream, key: &str) -> io::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        self.event_streams.lock().unwrap().push(tx);
+        websocket::accept(&mut stream, key)?;
+        // Messages of the client aren't read, the connection ends when writing fails
+        for line in rx {
+            websocket::send_text(&mut stream, line.trim_end())?;
+        }
+        Ok(())
+    }
+
+    fn serve_audio(&self, mut stream: TcpStream, path: &str, query: &str) -> io::Result<()> {
+        let text = match path {
+            "/speech.wav" => query
+                .split('&')
+                .find_map(|parameter| parameter.strip_prefix("text="))
+                .map(|text| percent_decode(&text.replace('+', " "))),
+            "/briefing.wav" => {
+                let schedule = Schedule::load(Some(self.storage.clone()))
+                    .map_err(|e| io::Error::other(e.to_string()))?;
+                let dates = match &self.birthdays {
+                    Some(path) => load_dates(path).unwrap_or_else(|e| {
+                        eprintln!("Failed to read the birthdays: {}", e);
+                        Vec::new()
+                    }),
+                    None => Vec::new(),
+                };
+                Some(briefing_text(Local::now(), &schedule, &dates))
+            }
+            path => path
+                .strip_prefix("/responses/")
+                .and_then(|name| name.strip_suffix(".wav"))
+                .and_then(|name| self.responses.get(&percent_decode(name)).cloned()),
+        };
+        let Some(text) = text else {
+            return respond(&mut stream, "404 Not Found", "text/plain", b"Not found");
+        };
+        match render_wav(&text) {
+            Ok(wav) => respond(&mut stream, "200 OK", "audio/wav", &wav),
+            Err(e) => {
+                eprintln!("Failed to render \"{}\": {}", text, e);
+                respond(
+                    &mut stream,
+                    "500 Internal Server Error",
+                    "text/plain",
+                    b"Failed to render audio",
+                )
+            }
+        }
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+/// The path of [Server::serve_audio] saying the text.
+pub fn speech_path(text: &str) -> String {
+    let encoded: String = text
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                char::from(byte).to_string()
+            }
+            byte => format!("%{:02X}", byte),
+        })
+        .collect();
+    format!("/speech.wav?text={}", encoded)
+}
+
+/// Decode `%20` and the like in a URL path.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
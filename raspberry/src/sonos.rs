@@ -0,0 +1,70 @@
+use assistant::{events::AssistantEvent, sonos::SonosZone, Assistant};
+use std::{
+    io,
+    net::{IpAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use crate::server::speech_path;
+
+/// Says the announcements in Sonos zones too, with WAV files of the `/speech.wav` endpoint of
+/// the server on `port`. Like the assistant, it is quiet in do not disturb.
+pub struct SonosAnnouncer {
+    zones: Vec<SonosZone>,
+    port: u16,
+    do_not_disturb: Arc<AtomicBool>,
+}
+
+impl SonosAnnouncer {
+    pub fn new<T>(zones: Vec<SonosZone>, port: u16, assistant: &mut Assistant<T>) -> Self {
+        let do_not_disturb = Arc::new(AtomicBool::new(assistant.is_do_not_disturb()));
+        let events = assistant.events();
+        let followed = do_not_disturb.clone();
+        thread::spawn(move || {
+            for event in events {
+                if let AssistantEvent::DoNotDisturbChanged(on) = event {
+                    followed.store(on, Ordering::Relaxed);
+                }
+            }
+        });
+        Self {
+            zones,
+            port,
+            do_not_disturb,
+        }
+    }
+
+    /// Say the text in every zone at once, over what they are playing, without waiting.
+    pub fn announce(&self, text: &str) {
+        if self.do_not_disturb.load(Ordering::Relaxed) {
+            return;
+        }
+        for zone in &self.zones {
+            let url = match local_address(zone.address()) {
+                Ok(address) => format!("http://{}:{}{}", address, self.port, speech_path(text)),
+                Err(e) => {
+                    eprintln!("Failed to find a route to the {} Sonos: {}", zone.name(), e);
+                    continue;
+                }
+            };
+            let zone = zone.clone();
+            thread::spawn(move || {
+                if let Err(e) = zone.announce(&url) {
+                    eprintln!("Failed to announce on the {} Sonos: {}", zone.name(), e);
+                }
+            });
+        }
+    }
+}
+
+/// The address of the Pi on the network of the player, which the player fetches the audio from.
+/// Connecting a UDP socket sends nothing, it only picks the interface.
+fn local_address(player: &str) -> io::Result<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect((player, 1400))?;
+    Ok(socket.local_addr()?.ip())
+}
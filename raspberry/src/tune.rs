@@ -0,0 +1,128 @@
+use assistant::{
+    remote::{RemoteCommand, RemoteHandle},
+    wakeword::DetectorSettings,
+};
+use std::{
+    io::{self, BufRead},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::config;
+
+const HELP: &str = "Commands: threshold <score>, avg_threshold <score>, min_scores <frames>, \
+                    score_ref <value>, intent_threshold <score>, show, save";
+
+/// The default threshold of the intents, see [assistant::intents::IntentsConfig::set_threshold].
+const DEFAULT_INTENT_THRESHOLD: f32 = 0.5;
+
+/// The values tuned with `raspberry tune`, sent to the running assistant as soon as they change.
+/// Clones share the same values, so the terminal and the HTTP server can both change them.
+#[derive(Clone)]
+pub struct Tuner {
+    values: Arc<Mutex<Tuning>>,
+    remote: RemoteHandle,
+    config_dir: PathBuf,
+}
+
+struct Tuning {
+    detector: DetectorSettings,
+    intent_threshold: f32,
+}
+
+impl Tuner {
+    /// Start from the values of `config.toml`.
+    pub fn new(remote: RemoteHandle, config_dir: PathBuf, declared: &config::Config) -> Self {
+        let tuning = Tuning {
+            detector: declared
+                .wakeword_detector
+                .as_ref()
+                .map(config::WakewordDetector::to_detector_settings)
+                .unwrap_or_default(),
+            intent_threshold: declared.threshold.unwrap_or(DEFAULT_INTENT_THRESHOLD),
+        };
+        Self {
+            values: Arc::new(Mutex::new(tuning)),
+            remote,
+            config_dir,
+        }
+    }
+
+    /// Run a command like `threshold 0.6` and return what to show the user.
+    pub fn run(&self, line: &str) -> String {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return HELP.to_string();
+        };
+        let value = words.next();
+        let mut tuning = self.values.lock().unwrap();
+        let detector = &mut tuning.detector;
+        let result = match (command, value) {
+            ("show", None) => return tuning.describe(),
+            ("save", None) => {
+                return match config::save_tuning(
+                    &self.config_dir,
+                    &tuning.detector,
+                    tuning.intent_threshold,
+                ) {
+                    Ok(()) => "Saved to config.toml".to_string(),
+                    Err(e) => format!("Failed to save to config.toml: {}", e),
+                }
+            }
+            ("threshold", Some(value)) => parse(value).map(|v| detector.set_threshold(v)),
+            ("avg_threshold", Some(value)) => parse(value).map(|v| detector.set_avg_threshold(v)),
+            ("min_scores", Some(value)) => parse(value).map(|v| detector.set_min_scores(v)),
+            ("score_ref", Some(value)) => parse(value).map(|v| detector.set_score_ref(v)),
+            ("intent_threshold", Some(value)) => parse(value).map(|v| tuning.intent_threshold = v),
+            _ => return HELP.to_string(),
+        };
+        if let Err(e) = result {
+            return e;
+        }
+
+        let command = match command {
+            "intent_threshold" => RemoteCommand::SetIntentThreshold(tuning.intent_threshold),
+            _ => RemoteCommand::SetWakewordDetector(tuning.detector.clone()),
+        };
+        match self.remote.send(command) {
+            Ok(()) => tuning.describe(),
+            Err(e) => e.to_string(),
+        }
+    }
+}
+
+impl Tuning {
+    fn describe(&self) -> String {
+        format!(
+            "threshold = {}, avg_threshold = {}, min_scores = {}, score_ref = {}, intent_threshold = {}",
+            self.detector.threshold(),
+            self.detector.avg_threshold(),
+            self.detector.min_scores(),
+            self.detector.score_ref(),
+            self.intent_threshold
+        )
+    }
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("\"{}\" isn't a valid value", value))
+}
+
+/// Read tuning commands from the terminal while the assistant runs, see `raspberry tune`.
+pub fn spawn_stdin(tuner: Tuner) {
+    println!("Tuning. {}", HELP);
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            match line {
+                Ok(line) => println!("{}", tuner.run(&line)),
+                Err(e) => {
+                    eprintln!("Failed to read from the terminal: {}", e);
+                    return;
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,36 @@
+use assistant::{speakers::SpeakerProfiles, storage::SqliteStorage};
+use std::{path::PathBuf, sync::Arc};
+
+use crate::dirs::{get_config_file, get_config_path};
+
+const USAGE: &str = "Usage: raspberry voices <list|remove <name>> [config_dir]";
+
+/// `raspberry voices list [config_dir]` and `raspberry voices remove <name> [config_dir]`, for the
+/// voices learned by speaker identification.
+pub fn voices_command(mut args: impl Iterator<Item = String>) {
+    let command = args.next().expect(USAGE);
+    let name = match command.as_str() {
+        "list" => None,
+        "remove" => Some(args.next().expect(USAGE)),
+        _ => panic!("{}", USAGE),
+    };
+    let config_dir: PathBuf = args.next().map(Into::into).unwrap_or_else(get_config_path);
+    let storage = SqliteStorage::open(get_config_file(&config_dir, "storage.sqlite"))
+        .expect("Failed to open storage");
+    let profiles = SpeakerProfiles::new(Arc::new(storage));
+
+    match name {
+        None => {
+            for name in profiles.names().expect("Failed to list the voices") {
+                println!("{}", name);
+            }
+        }
+        Some(name) => {
+            if profiles.remove(&name).expect("Failed to remove the voice") {
+                println!("Forgot the voice of {}", name);
+            } else {
+                println!("No voice named {}", name);
+            }
+        }
+    }
+}